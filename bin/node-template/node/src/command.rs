@@ -132,8 +132,9 @@ pub fn run() -> sc_cli::Result<()> {
 							service::new_partial(&config)?;
 						let db = backend.expose_db();
 						let storage = backend.expose_storage();
+						let flat_state = backend.wait_for_flat_state();
 
-						cmd.run(config, client, db, storage)
+						cmd.run(config, client, db, storage, flat_state)
 					},
 					BenchmarkCmd::Overhead(cmd) => {
 						let PartialComponents { client, .. } = service::new_partial(&config)?;
@@ -78,7 +78,13 @@ pub fn new_partial(
 		config.default_heap_pages,
 		config.max_runtime_instances,
 		config.runtime_cache_size,
-	);
+	)
+	.with_runtime_artifacts(config.runtime_artifacts_path.clone())
+	.with_fuel_metering(config.execution_budgets.is_metered())
+	.with_host_function_profiling(config.wasm_host_function_profiling)
+	.with_heap_usage_warning(config.wasm_heap_usage_warning)
+	.with_sandbox_backend(config.wasm_sandbox_backend)
+	.with_prometheus_registry(config.prometheus_registry());
 
 	let (client, backend, keystore_container, task_manager) =
 		sc_service::new_full_parts::<Block, RuntimeApi, _>(
@@ -28,7 +28,7 @@ use sc_consensus::{
 };
 use sc_service::{
 	config::{
-		DatabaseSource, KeepBlocks, KeystoreConfig, NetworkConfiguration, OffchainWorkerConfig,
+		BlocksPruning, DatabaseSource, KeystoreConfig, NetworkConfiguration, OffchainWorkerConfig,
 		PruningMode, WasmExecutionMethod, WasmtimeInstantiationStrategy,
 	},
 	BasePath, Configuration, Role,
@@ -75,7 +75,10 @@ fn new_node(tokio_handle: Handle) -> node_cli::service::NewFullBase {
 		state_cache_size: 67108864,
 		state_cache_child_ratio: None,
 		state_pruning: Some(PruningMode::ArchiveAll),
-		keep_blocks: KeepBlocks::All,
+		blocks_pruning: BlocksPruning::keep_all(),
+		flat_state: false,
+		state_diff_index: false,
+		storage_item_stats: false,
 		chain_spec: spec,
 		wasm_method: WasmExecutionMethod::Compiled {
 			instantiation_strategy: WasmtimeInstantiationStrategy::PoolingCopyOnWrite,
@@ -87,6 +90,7 @@ fn new_node(tokio_handle: Handle) -> node_cli::service::NewFullBase {
 			offchain_worker: execution_strategy,
 			other: execution_strategy,
 		},
+		execution_budgets: Default::default(),
 		rpc_http: None,
 		rpc_ws: None,
 		rpc_ipc: None,
@@ -114,6 +118,10 @@ fn new_node(tokio_handle: Handle) -> node_cli::service::NewFullBase {
 		base_path: Some(base_path),
 		informant_output_format: Default::default(),
 		wasm_runtime_overrides: None,
+		runtime_artifacts_path: None,
+		wasm_host_function_profiling: false,
+		wasm_heap_usage_warning: None,
+		wasm_sandbox_backend: None,
 	};
 
 	node_cli::service::new_full_base(config, false, |_, _| ())
@@ -89,12 +89,22 @@ pub enum Subcommand {
 	/// Import blocks.
 	ImportBlocks(sc_cli::ImportBlocksCmd),
 
+	/// Initialise an empty database from a snapshot.
+	ImportSnapshot(sc_cli::ImportSnapshotCmd),
+
+	/// Precompile a runtime into an artifact loaded by the node.
+	PrecompileRuntime(sc_cli::PrecompileRuntimeCmd),
+
 	/// Remove the whole chain.
 	PurgeChain(sc_cli::PurgeChainCmd),
 
 	/// Revert the chain to a previous state.
 	Revert(sc_cli::RevertCmd),
 
+	/// Database management cli utilities
+	#[clap(subcommand)]
+	Db(sc_cli::DbSubcommand),
+
 	/// Db meta columns information.
 	ChainInfo(sc_cli::ChainInfoCmd),
 }
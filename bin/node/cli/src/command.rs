@@ -121,8 +121,9 @@ pub fn run() -> Result<()> {
 						let PartialComponents { client, backend, .. } = new_partial(&config)?;
 						let db = backend.expose_db();
 						let storage = backend.expose_storage();
+						let flat_state = backend.wait_for_flat_state();
 
-						cmd.run(config, client, db, storage)
+						cmd.run(config, client, db, storage, flat_state)
 					},
 					BenchmarkCmd::Overhead(cmd) => {
 						let PartialComponents { client, .. } = new_partial(&config)?;
@@ -173,10 +174,52 @@ pub fn run() -> Result<()> {
 				Ok((cmd.run(client, import_queue), task_manager))
 			})
 		},
+		Some(Subcommand::ImportSnapshot(cmd)) => {
+			let runner = cli.create_runner(cmd)?;
+			runner.async_run(|config| {
+				let PartialComponents { client, backend, task_manager, import_queue, .. } =
+					new_partial(&config)?;
+				Ok((cmd.run(client, backend, import_queue), task_manager))
+			})
+		},
+		Some(Subcommand::PrecompileRuntime(cmd)) => {
+			let runner = cli.create_runner(cmd)?;
+			if cmd.at.is_some() {
+				runner.async_run(|config| {
+					let PartialComponents { client, task_manager, .. } = new_partial(&config)?;
+					Ok((cmd.run_at(client, config), task_manager))
+				})
+			} else {
+				runner.sync_run(|config| cmd.run::<Block>(&config))
+			}
+		},
 		Some(Subcommand::PurgeChain(cmd)) => {
 			let runner = cli.create_runner(cmd)?;
 			runner.sync_run(|config| cmd.run(config.database))
 		},
+		Some(Subcommand::Db(sc_cli::DbSubcommand::Migrate(cmd))) => {
+			let runner = cli.create_runner(cmd)?;
+			if cmd.to != sc_cli::Database::RocksDb {
+				return runner.sync_run(|config| cmd.run::<Block>(config.database))
+			}
+			runner.async_run(|mut config| {
+				let (source, target, snapshot_path) = cmd.rocksdb_migration(&config.database)?;
+				config.database = source;
+				let source = new_partial(&config)?;
+				config.database = target;
+				let PartialComponents { client, backend, task_manager, import_queue, .. } =
+					new_partial(&config)?;
+				let migration = cmd.run_to_rocksdb(
+					source.client,
+					source.backend,
+					client,
+					backend,
+					import_queue,
+					snapshot_path,
+				);
+				Ok((migration, task_manager))
+			})
+		},
 		Some(Subcommand::Revert(cmd)) => {
 			let runner = cli.create_runner(cmd)?;
 			runner.async_run(|config| {
@@ -26,7 +26,7 @@ use futures::prelude::*;
 use node_executor::ExecutorDispatch;
 use node_primitives::Block;
 use node_runtime::RuntimeApi;
-use sc_client_api::{BlockBackend, ExecutorProvider};
+use sc_client_api::{Backend, BlockBackend, ExecutorProvider};
 use sc_consensus_babe::{self, SlotProportion};
 use sc_executor::NativeElseWasmExecutor;
 use sc_network::{Event, NetworkService};
@@ -162,7 +162,13 @@ pub fn new_partial(
 		config.default_heap_pages,
 		config.max_runtime_instances,
 		config.runtime_cache_size,
-	);
+	)
+	.with_runtime_artifacts(config.runtime_artifacts_path.clone())
+	.with_fuel_metering(config.execution_budgets.is_metered())
+	.with_host_function_profiling(config.wasm_host_function_profiling)
+	.with_heap_usage_warning(config.wasm_heap_usage_warning)
+	.with_sandbox_backend(config.wasm_sandbox_backend)
+	.with_prometheus_registry(config.prometheus_registry());
 
 	let (client, backend, keystore_container, task_manager) =
 		sc_service::new_full_parts::<Block, RuntimeApi, _>(
@@ -251,6 +257,10 @@ pub fn new_partial(
 		let select_chain = select_chain.clone();
 		let keystore = keystore_container.sync_keystore();
 		let chain_spec = config.chain_spec.cloned_box();
+		let snapshot_dir = config
+			.base_path
+			.as_ref()
+			.map(|base_path| base_path.config_dir(config.chain_spec.id()).join("snapshots"));
 
 		let rpc_backend = backend.clone();
 		let rpc_extensions_builder = move |deny_unsafe, subscription_executor| {
@@ -272,6 +282,7 @@ pub fn new_partial(
 					subscription_executor,
 					finality_provider: finality_proof_provider.clone(),
 				},
+				snapshot_dir: snapshot_dir.clone(),
 			};
 
 			node_rpc::create_full(deps, rpc_backend.clone()).map_err(Into::into)
@@ -346,6 +357,25 @@ pub fn new_full_base(
 		.network
 		.extra_sets
 		.push(grandpa::grandpa_peers_set_config(grandpa_protocol_name.clone()));
+
+	// Let authorities receive messages from other authorities and hand them to offchain workers.
+	let validator_messaging_protocol_name = sc_authority_discovery::messaging::protocol_name(
+		&client.block_hash(0).ok().flatten().expect("Genesis block exists; qed"),
+		config.chain_spec.fork_id(),
+	);
+	let validator_messaging = if config.role.is_authority() {
+		let (handler, protocol_config, messages) =
+			sc_authority_discovery::messaging::MessagingHandler::new(
+				validator_messaging_protocol_name.clone(),
+				client.clone(),
+				keystore_container.keystore(),
+				1024,
+			);
+		config.network.request_response_protocols.push(protocol_config);
+		Some((handler, messages, backend.offchain_storage()))
+	} else {
+		None
+	};
 	let warp_sync = Arc::new(grandpa::warp_proof::NetworkProvider::new(
 		backend.clone(),
 		import_setup.1.shared_authority_set().clone(),
@@ -485,7 +515,7 @@ pub fn new_full_base(
 					_ => None,
 				}
 			});
-		let (authority_discovery_worker, _service) =
+		let (authority_discovery_worker, authority_discovery_service) =
 			sc_authority_discovery::new_worker_and_service_with_config(
 				sc_authority_discovery::WorkerConfig {
 					publish_non_global_ips: auth_disc_publish_non_global_ips,
@@ -503,6 +533,56 @@ pub fn new_full_base(
 			Some("networking"),
 			authority_discovery_worker.run(),
 		);
+
+		if let Some((handler, messages, offchain_storage)) = validator_messaging {
+			let messaging = sc_authority_discovery::messaging::ValidatorMessaging::new(
+				validator_messaging_protocol_name,
+				client.clone(),
+				network.clone(),
+				authority_discovery_service.clone(),
+				keystore_container.keystore(),
+			);
+			let (offchain_extensions, offchain_sender) = messaging.offchain_extensions(1024);
+			client.execution_extensions().set_extensions_factory(Box::new(offchain_extensions));
+
+			task_manager.spawn_handle().spawn(
+				"validator-messaging-handler",
+				Some("networking"),
+				handler.run(),
+			);
+			task_manager.spawn_handle().spawn(
+				"validator-messaging-offchain",
+				Some("networking"),
+				offchain_sender,
+			);
+			if let Some(offchain_storage) = offchain_storage {
+				task_manager.spawn_handle().spawn(
+					"validator-messaging-inbox",
+					Some("networking"),
+					sc_authority_discovery::messaging::store_in_offchain_inbox(
+						offchain_storage,
+						messages,
+					),
+				);
+			}
+		}
+
+		// Keep GRANDPA connected to the current and next set of authorities.
+		let authority_connectivity_manager = sc_authority_discovery::ConnectivityManager::new(
+			sc_authority_discovery::ConnectivityConfig::new(grandpa_protocol_name.clone()),
+			client.clone(),
+			network.clone(),
+			authority_discovery_service,
+			Some(keystore_container.keystore()),
+			Box::pin(network.event_stream("authority-discovery-connectivity")),
+			prometheus_registry.clone(),
+		);
+
+		task_manager.spawn_handle().spawn(
+			"authority-discovery-connectivity",
+			Some("networking"),
+			authority_connectivity_manager.run(),
+		);
 	}
 
 	// if the node isn't actively participating in consensus then it doesn't
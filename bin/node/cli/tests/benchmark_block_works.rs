@@ -39,6 +39,7 @@ async fn benchmark_block_works() {
 		.arg(base_dir.path())
 		.args(["--from", "1", "--to", "1"])
 		.args(["--repeat", "1"])
+		.arg("--compare-ed25519-batching")
 		.args(["--execution", "wasm", "--wasm-execution", "compiled"])
 		.status()
 		.unwrap();
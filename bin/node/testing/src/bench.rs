@@ -391,7 +391,10 @@ impl BenchDb {
 			state_cache_child_ratio: Some((0, 100)),
 			state_pruning: Some(PruningMode::ArchiveAll),
 			source: database_type.into_settings(dir.into()),
-			keep_blocks: sc_client_db::KeepBlocks::All,
+			blocks_pruning: sc_client_db::BlocksPruning::keep_all(),
+			flat_state: false,
+			state_diff_index: false,
+			storage_item_stats: false,
 		};
 		let task_executor = TaskExecutor::new();
 
@@ -314,10 +314,54 @@ impl IndexMut<Order> for FreeLists {
 	}
 }
 
+/// Statistics of the heap usage of a [`FreeingBumpHeapAllocator`].
+///
+/// The sizes include the allocation headers and the rounding up to the orders.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AllocationStats {
+	/// The number of bytes currently allocated.
+	pub bytes_allocated: u32,
+	/// The highest number of bytes allocated at once.
+	pub bytes_allocated_peak: u32,
+	/// The number of bytes allocated in total, the freed allocations included.
+	pub bytes_allocated_sum: u64,
+	/// The number of bytes of the heap handed out by the bump allocator, i.e. its high-water mark.
+	///
+	/// An allocation fails once this reaches `heap_size`, whatever the bytes currently allocated.
+	pub address_space_used: u32,
+	/// The number of bytes of the linear memory after the heap base.
+	pub heap_size: u32,
+}
+
+impl AllocationStats {
+	/// Returns the share of the heap handed out by the bump allocator, between `0` and `1`.
+	pub fn heap_usage(&self) -> f64 {
+		ratio(self.address_space_used, self.heap_size)
+	}
+
+	/// Returns the share of the address space used that was never allocated at once, between `0`
+	/// and `1`.
+	///
+	/// The freed allocations can only be reused by allocations of the same order, the address space
+	/// they take is lost for the others.
+	pub fn fragmentation(&self) -> f64 {
+		1.0 - ratio(self.bytes_allocated_peak, self.address_space_used).min(1.0)
+	}
+}
+
+fn ratio(numerator: u32, denominator: u32) -> f64 {
+	if denominator == 0 {
+		0.0
+	} else {
+		numerator as f64 / denominator as f64
+	}
+}
+
 /// An implementation of freeing bump allocator.
 ///
 /// Refer to the module-level documentation for further details.
 pub struct FreeingBumpHeapAllocator {
+	heap_base: u32,
 	bumper: u32,
 	free_lists: FreeLists,
 	total_size: u32,
@@ -325,6 +369,7 @@ pub struct FreeingBumpHeapAllocator {
 	max_total_size: u32,
 	max_bumper: u32,
 	last_observed_memory_size: u32,
+	bytes_allocated_sum: u64,
 }
 
 impl Drop for FreeingBumpHeapAllocator {
@@ -348,6 +393,7 @@ impl FreeingBumpHeapAllocator {
 		let aligned_heap_base = (heap_base + ALIGNMENT - 1) / ALIGNMENT * ALIGNMENT;
 
 		FreeingBumpHeapAllocator {
+			heap_base: aligned_heap_base,
 			bumper: aligned_heap_base,
 			free_lists: FreeLists::new(),
 			total_size: 0,
@@ -355,6 +401,18 @@ impl FreeingBumpHeapAllocator {
 			max_total_size: 0,
 			max_bumper: aligned_heap_base,
 			last_observed_memory_size: 0,
+			bytes_allocated_sum: 0,
+		}
+	}
+
+	/// Returns the statistics of the heap usage so far.
+	pub fn stats(&self) -> AllocationStats {
+		AllocationStats {
+			bytes_allocated: self.total_size,
+			bytes_allocated_peak: self.max_total_size,
+			bytes_allocated_sum: self.bytes_allocated_sum,
+			address_space_used: self.bumper - self.heap_base,
+			heap_size: self.last_observed_memory_size.saturating_sub(self.heap_base),
 		}
 	}
 
@@ -413,6 +471,7 @@ impl FreeingBumpHeapAllocator {
 		Header::Occupied(order).write_into(mem, header_ptr)?;
 
 		self.total_size += order.size() + HEADER_SIZE;
+		self.bytes_allocated_sum += u64::from(order.size() + HEADER_SIZE);
 
 		log::trace!(
 			target: LOG_TARGET,
@@ -859,6 +918,34 @@ mod tests {
 		assert_eq!(heap.total_size, 0);
 	}
 
+	#[test]
+	fn should_report_stats() {
+		// given
+		let mut mem = [0u8; PAGE_SIZE as usize];
+		let mut heap = FreeingBumpHeapAllocator::new(16);
+
+		// when
+		let ptr = heap.allocate(&mut mem[..], 100).unwrap();
+		heap.deallocate(&mut mem[..], ptr).unwrap();
+		heap.allocate(&mut mem[..], 8).unwrap();
+
+		// then
+		// the first allocation takes 128 bytes and a header, its space can't be reused by the
+		// second allocation of 8 bytes and a header
+		assert_eq!(
+			heap.stats(),
+			AllocationStats {
+				bytes_allocated: 16,
+				bytes_allocated_peak: 136,
+				bytes_allocated_sum: 152,
+				address_space_used: 152,
+				heap_size: PAGE_SIZE - 16,
+			}
+		);
+		assert_eq!(heap.stats().heap_usage(), 152.0 / (PAGE_SIZE - 16) as f64);
+		assert_eq!(heap.stats().fragmentation(), 1.0 - 136.0 / 152.0);
+	}
+
 	#[test]
 	fn should_read_and_write_u64_correctly() {
 		// given
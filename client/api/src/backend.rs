@@ -20,7 +20,7 @@
 
 use crate::{
 	blockchain::{well_known_cache_keys, Backend as BlockchainBackend},
-	UsageInfo,
+	StorageItemUsage, UsageInfo,
 };
 use parking_lot::RwLock;
 use sp_blockchain;
@@ -190,6 +190,15 @@ pub trait BlockImportOperation<Block: BlockT> {
 		state_version: StateVersion,
 	) -> sp_blockchain::Result<Block::Hash>;
 
+	/// Use the state with the given root, already written to the database by other means, as the
+	/// storage of the block, replacing any existing data.
+	///
+	/// Fails if the database doesn't hold the root of that state, or if the backend doesn't
+	/// support it.
+	fn reset_to_existing_storage(&mut self, _root: Block::Hash) -> sp_blockchain::Result<()> {
+		Err(sp_blockchain::Error::Backend("Importing an existing state isn't supported".into()))
+	}
+
 	/// Set storage changes.
 	fn update_storage(
 		&mut self,
@@ -430,6 +439,50 @@ pub trait StorageProvider<Block: BlockT, B: Backend<Block>> {
 		child_info: &ChildInfo,
 		key: &StorageKey,
 	) -> sp_blockchain::Result<Option<Block::Hash>>;
+
+	/// Given a `BlockId`, return up to `count` default child tries of that block with their
+	/// roots, in order of their storage keys and following the child trie `start_key` if given.
+	fn default_child_tries(
+		&self,
+		id: &BlockId<Block>,
+		start_key: Option<&ChildInfo>,
+		count: u32,
+	) -> sp_blockchain::Result<Vec<(ChildInfo, Block::Hash)>>;
+
+	/// Given a block hash, return the top-level storage keys changed by that block, if the
+	/// backend indexes them.
+	///
+	/// Returns `None` if the keys changed by the block aren't known.
+	fn changed_keys(&self, hash: &Block::Hash) -> sp_blockchain::Result<Option<Vec<StorageKey>>>;
+
+	/// Given a key, return the blocks where its value has been changed, from the block number
+	/// `first` to the block `last`, on the chain of `last` and in ascending order.
+	///
+	/// Returns `None` if the backend doesn't index the changes of all the blocks in the range.
+	fn key_changes(
+		&self,
+		first: NumberFor<Block>,
+		last: &Block::Hash,
+		key: &StorageKey,
+	) -> sp_blockchain::Result<Option<Vec<(NumberFor<Block>, Block::Hash)>>>;
+}
+
+/// Index of the storage keys changed by the blocks.
+pub trait StateChangesIndex<Block: BlockT>: Send + Sync {
+	/// Returns the top-level storage keys changed by the block `hash`, or `None` if the block
+	/// isn't indexed.
+	fn changed_keys(&self, hash: &Block::Hash) -> sp_blockchain::Result<Option<Vec<StorageKey>>>;
+
+	/// Returns the blocks where `key` has been changed, from the block number `first` to the block
+	/// `last`, on the chain of `last` and in ascending order.
+	///
+	/// Returns `None` if some of the blocks in the range aren't indexed.
+	fn key_changes(
+		&self,
+		first: NumberFor<Block>,
+		last: &Block::Hash,
+		key: &StorageKey,
+	) -> sp_blockchain::Result<Option<Vec<(NumberFor<Block>, Block::Hash)>>>;
 }
 
 /// Client backend.
@@ -494,9 +547,22 @@ pub trait Backend<Block: BlockT>: AuxStore + Send + Sync {
 	/// Returns current usage statistics.
 	fn usage_info(&self) -> Option<UsageInfo>;
 
+	/// Returns the accesses of the individual storage items, since the start of the backend.
+	///
+	/// Returns `None` if the backend doesn't tally them. Unlike [`Self::usage_info`], this doesn't
+	/// reset the statistics.
+	fn storage_item_usage(&self) -> Option<Vec<StorageItemUsage>> {
+		None
+	}
+
 	/// Returns a handle to offchain storage.
 	fn offchain_storage(&self) -> Option<Self::OffchainStorage>;
 
+	/// Returns the index of the storage keys changed by the blocks, if the backend maintains one.
+	fn state_changes_index(&self) -> Option<&dyn StateChangesIndex<Block>> {
+		None
+	}
+
 	/// Returns true if state for given block is available.
 	fn have_state_at(&self, hash: &Block::Hash, _number: NumberFor<Block>) -> bool {
 		self.state_at(BlockId::Hash(*hash)).is_ok()
@@ -25,7 +25,7 @@ use sp_runtime::{
 	traits::{Block as BlockT, NumberFor},
 	Justifications,
 };
-use std::{collections::HashSet, fmt, sync::Arc};
+use std::{collections::HashSet, fmt, sync::Arc, time::Duration};
 
 use crate::{blockchain::Info, notifications::StorageEventStream, FinalizeSummary, ImportSummary};
 
@@ -149,6 +149,12 @@ pub trait BlockBackend<Block: BlockT> {
 
 	/// Tells whether the current client configuration requires full-sync mode.
 	fn requires_full_sync(&self) -> bool;
+
+	/// Returns the number of the oldest finalized block whose data of the given kind is kept, if
+	/// the data of older blocks is being pruned.
+	fn pruned_below(&self, _data: sp_blockchain::PrunableBlockData) -> Option<NumberFor<Block>> {
+		None
+	}
 }
 
 /// Provide a list of potential uncle headers for a given block.
@@ -209,6 +215,10 @@ pub struct StateDbMemoryInfo {
 	pub pruning: Option<MemorySize>,
 	/// Memory usage of the pinned blocks.
 	pub pinned: MemorySize,
+	/// Number of blocks in the pruning window.
+	pub pruning_window_blocks: Option<u64>,
+	/// Time span of the pruning window, according to the timestamps of its blocks.
+	pub pruning_window_age: Option<Duration>,
 }
 
 /// Memory statistics for client instance.
@@ -247,6 +257,32 @@ pub struct IoInfo {
 	pub state_writes_cache: u64,
 	/// State write (trie nodes) to backend db.
 	pub state_writes_nodes: u64,
+	/// Accesses of the individual storage items, since the start of the client.
+	pub storage_items: Vec<StorageItemUsage>,
+}
+
+/// Length of the key prefix that identifies a storage item.
+pub const STORAGE_ITEM_PREFIX_LEN: usize = 32;
+
+/// Access statistics of one storage item.
+///
+/// Storage items are identified by the first [`STORAGE_ITEM_PREFIX_LEN`] bytes of their keys,
+/// which for FRAME based runtimes is `twox128(pallet) ++ twox128(item)`. Only top trie
+/// accesses are accounted.
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+pub struct StorageItemUsage {
+	/// The key prefix of the storage item.
+	pub prefix: [u8; STORAGE_ITEM_PREFIX_LEN],
+	/// Key reads.
+	pub reads: u64,
+	/// Key reads served by the state cache.
+	pub cache_reads: u64,
+	/// Bytes read, keys included.
+	pub bytes_read: u64,
+	/// Key writes, removals included.
+	pub writes: u64,
+	/// Bytes written, keys included.
+	pub bytes_written: u64,
 }
 
 /// Usage statistics for running client instance.
@@ -267,13 +303,14 @@ impl fmt::Display for UsageInfo {
 		write!(
 			f,
 			"caches: ({} state, {} db overlay), \
-			 state db: ({} non-canonical, {} pruning, {} pinned), \
+			 state db: ({} non-canonical, {} pruning, {} pinned, {} blocks in window), \
 			 i/o: ({} tx, {} write, {} read, {} avg tx, {}/{} key cache reads/total, {} trie nodes writes)",
 			self.memory.state_cache,
 			self.memory.database_cache,
 			self.memory.state_db.non_canonical,
 			self.memory.state_db.pruning.unwrap_or_default(),
 			self.memory.state_db.pinned,
+			self.memory.state_db.pruning_window_blocks.unwrap_or_default(),
 			self.io.transactions,
 			self.io.bytes_written,
 			self.io.bytes_read,
@@ -27,6 +27,7 @@ use parking_lot::RwLock;
 use sc_transaction_pool_api::OffchainSubmitTransaction;
 use sp_core::{
 	offchain::{self, OffchainDbExt, OffchainWorkerExt, TransactionPoolExt},
+	traits::FuelBudgetExt,
 	ExecutionContext,
 };
 use sp_externalities::Extensions;
@@ -63,6 +64,47 @@ impl Default for ExecutionStrategies {
 	}
 }
 
+/// Fuel budgets of runtime calls, per execution context.
+///
+/// `None` leaves the calls of a context unmetered. Only wasm execution is metered, and only when
+/// the executor was built with fuel metering enabled.
+///
+/// Imported blocks are never metered: every node must execute them to the same result, which a
+/// budget chosen by each node can't guarantee.
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionBudgets {
+	/// Fuel budget of a call when constructing blocks.
+	pub block_construction: Option<u64>,
+	/// Fuel budget of a call when validating transactions.
+	pub transaction_validation: Option<u64>,
+	/// Fuel budget of a call for offchain workers.
+	pub offchain_worker: Option<u64>,
+	/// Fuel budget of a call in other cases, e.g. RPC calls.
+	pub other: Option<u64>,
+}
+
+impl ExecutionBudgets {
+	/// Returns `true` if the calls of any context are metered.
+	pub fn is_metered(&self) -> bool {
+		self.block_construction.is_some() ||
+			self.transaction_validation.is_some() ||
+			self.offchain_worker.is_some() ||
+			self.other.is_some()
+	}
+
+	/// Returns the fuel budget of a call in the given `context`.
+	pub fn budget(&self, context: &ExecutionContext) -> Option<u64> {
+		match context {
+			ExecutionContext::Importing | ExecutionContext::Syncing => None,
+			ExecutionContext::BlockConstruction => self.block_construction,
+			ExecutionContext::TransactionValidation => self.transaction_validation,
+			ExecutionContext::OffchainCall(Some((_, capabilities))) if capabilities.is_all() =>
+				self.offchain_worker,
+			ExecutionContext::OffchainCall(_) => self.other,
+		}
+	}
+}
+
 /// Generate the starting set of ExternalitiesExtensions based upon the given capabilities
 pub trait ExtensionsFactory: Send + Sync {
 	/// Make `Extensions` for given `Capabilities`.
@@ -94,6 +136,7 @@ impl<T: offchain::DbExternalities + Clone + Sync + Send + 'static> DbExternaliti
 /// for each call, based on required `Capabilities`.
 pub struct ExecutionExtensions<Block: traits::Block> {
 	strategies: ExecutionStrategies,
+	budgets: ExecutionBudgets,
 	keystore: Option<SyncCryptoStorePtr>,
 	offchain_db: Option<Box<dyn DbExternalitiesFactory>>,
 	// FIXME: these two are only RwLock because of https://github.com/paritytech/substrate/issues/4587
@@ -110,6 +153,7 @@ impl<Block: traits::Block> Default for ExecutionExtensions<Block> {
 	fn default() -> Self {
 		Self {
 			strategies: Default::default(),
+			budgets: Default::default(),
 			keystore: None,
 			offchain_db: None,
 			transaction_pool: RwLock::new(None),
@@ -129,6 +173,7 @@ impl<Block: traits::Block> ExecutionExtensions<Block> {
 		let extensions_factory = Box::new(());
 		Self {
 			strategies,
+			budgets: Default::default(),
 			keystore,
 			offchain_db,
 			extensions_factory: RwLock::new(extensions_factory),
@@ -141,6 +186,17 @@ impl<Block: traits::Block> ExecutionExtensions<Block> {
 		&self.strategies
 	}
 
+	/// Use the given fuel `budgets` for the runtime calls.
+	pub fn with_budgets(mut self, budgets: ExecutionBudgets) -> Self {
+		self.budgets = budgets;
+		self
+	}
+
+	/// Get a reference to the fuel budgets.
+	pub fn budgets(&self) -> &ExecutionBudgets {
+		&self.budgets
+	}
+
 	/// Set the new extensions_factory
 	pub fn set_extensions_factory(&self, maker: Box<dyn ExtensionsFactory>) {
 		*self.extensions_factory.write() = maker;
@@ -161,6 +217,10 @@ impl<Block: traits::Block> ExecutionExtensions<Block> {
 
 		let mut extensions = self.extensions_factory.read().extensions_for(capabilities);
 
+		if let Some(budget) = self.budgets.budget(&context) {
+			extensions.register(FuelBudgetExt(budget));
+		}
+
 		if capabilities.contains(offchain::Capabilities::KEYSTORE) {
 			if let Some(ref keystore) = self.keystore {
 				extensions.register(KeystoreExt(keystore.clone()));
@@ -212,7 +272,8 @@ impl<Block: traits::Block> ExecutionExtensions<Block> {
 			ExecutionContext::Importing => self.strategies.importing.get_manager(),
 			ExecutionContext::OffchainCall(Some((_, capabilities))) if capabilities.is_all() =>
 				self.strategies.offchain_worker.get_manager(),
-			ExecutionContext::OffchainCall(_) => self.strategies.other.get_manager(),
+			ExecutionContext::TransactionValidation | ExecutionContext::OffchainCall(_) =>
+				self.strategies.other.get_manager(),
 		};
 
 		(manager, self.extensions(at, context))
@@ -40,6 +40,45 @@ pub trait ProofProvider<Block: BlockT> {
 		keys: &mut dyn Iterator<Item = &[u8]>,
 	) -> sp_blockchain::Result<StorageProof>;
 
+	/// Reads the storage entries at a given block, from the child trie `child_info` if given,
+	/// with keys starting with `prefix` from `start_key` inclusively to `end_key` exclusively,
+	/// returning a proof that they are the whole content of this range.
+	///
+	/// The proof only covers the start of the range if it has more than `max_entries` entries,
+	/// see `sp_state_machine::read_complete_range_proof_check`.
+	fn read_complete_range_proof(
+		&self,
+		id: &BlockId<Block>,
+		child_info: Option<&ChildInfo>,
+		prefix: Option<&[u8]>,
+		start_key: Option<&[u8]>,
+		end_key: Option<&[u8]>,
+		max_entries: u32,
+	) -> sp_blockchain::Result<StorageProof>;
+
+	/// Reads storage values at several blocks + keys, returning a compact read proof per
+	/// block, in the order of `blocks`.
+	///
+	/// The proof of a block omits the trie nodes already in the proofs of the previous blocks,
+	/// see `sp_trie::encode_compact_multi`.
+	fn read_multi_block_proof(
+		&self,
+		blocks: &[Block::Hash],
+		keys: &mut dyn Iterator<Item = &[u8]>,
+	) -> sp_blockchain::Result<Vec<CompactProof>>;
+
+	/// Reads up to `count` storage entries at a given block, from the child trie `child_info` if
+	/// given, with keys starting with `prefix` and following `start_key`, returning the entries
+	/// along with their read proof.
+	fn read_range_proof(
+		&self,
+		id: &BlockId<Block>,
+		child_info: Option<&ChildInfo>,
+		prefix: Option<&[u8]>,
+		start_key: Option<&[u8]>,
+		count: u32,
+	) -> sp_blockchain::Result<(Vec<(Vec<u8>, Vec<u8>)>, StorageProof)>;
+
 	/// Execute a call to a contract on top of state in a block of given hash
 	/// AND returning execution proof.
 	///
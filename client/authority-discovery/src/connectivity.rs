@@ -0,0 +1,429 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Keeps the local node connected to the current and next set of authorities.
+//!
+//! The [`ConnectivityManager`] periodically retrieves the current and next authority set through
+//! the [`AuthorityDiscoveryApi`], resolves the addresses of those authorities through a
+//! [`Service`] and installs them as the reserved peers of a single notifications protocol, e.g.
+//! GRANDPA. Opened and closed substreams of that protocol are tracked in order to report how well
+//! the local node is connected to the other authorities. The local node's own authority keys are
+//! left out, it can neither connect to nor reserve itself.
+
+use crate::{
+	error::{Error, Result},
+	interval::ExpIncInterval,
+	Service,
+};
+
+use std::{
+	borrow::Cow,
+	collections::{HashMap, HashSet},
+	marker::PhantomData,
+	sync::Arc,
+	time::Duration,
+};
+
+use futures::{FutureExt, Stream, StreamExt};
+use libp2p::core::multiaddr;
+use log::{debug, error, trace};
+use prometheus_endpoint::{register, Gauge, Opts, F64, U64};
+use sc_client_api::blockchain::HeaderBackend;
+use sc_network::{Event, ExHashT, Multiaddr, PeerId};
+use sp_api::ProvideRuntimeApi;
+use sp_authority_discovery::{AuthorityDiscoveryApi, AuthorityId};
+use sp_core::crypto::key_types;
+use sp_keystore::CryptoStore;
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+
+#[cfg(test)]
+mod tests;
+
+const LOG_TARGET: &str = "sub-authority-discovery";
+
+/// Configuration of a [`ConnectivityManager`].
+#[derive(Clone, Debug)]
+pub struct ConnectivityConfig {
+	/// Name of the notifications protocol whose reserved set is kept in sync with the authority
+	/// set, e.g. the GRANDPA protocol name.
+	pub protocol: Cow<'static, str>,
+
+	/// The maximum interval in which the authority set is re-read from the runtime and the
+	/// addresses of its members are resolved again.
+	///
+	/// By default this is set to 1 minute.
+	pub max_refresh_interval: Duration,
+}
+
+impl ConnectivityConfig {
+	/// Create a new [`ConnectivityConfig`] for the given notifications protocol with the default
+	/// refresh interval.
+	pub fn new(protocol: impl Into<Cow<'static, str>>) -> Self {
+		Self { protocol: protocol.into(), max_refresh_interval: Duration::from_secs(60) }
+	}
+}
+
+/// Hooks into the underlying Substrate networking needed by a [`ConnectivityManager`].
+///
+/// Using this trait abstraction instead of [`sc_network::NetworkService`] directly is necessary
+/// to unit test [`ConnectivityManager`].
+pub trait NetworkReservedSets {
+	/// Sets the reserved set of a protocol to the given set of peers.
+	///
+	/// See [`sc_network::NetworkService::set_reserved_peers`].
+	fn set_reserved_peers(
+		&self,
+		protocol: Cow<'static, str>,
+		peers: HashSet<Multiaddr>,
+	) -> std::result::Result<(), String>;
+}
+
+impl<B, H> NetworkReservedSets for sc_network::NetworkService<B, H>
+where
+	B: BlockT + 'static,
+	H: ExHashT,
+{
+	fn set_reserved_peers(
+		&self,
+		protocol: Cow<'static, str>,
+		peers: HashSet<Multiaddr>,
+	) -> std::result::Result<(), String> {
+		self.set_reserved_peers(protocol, peers)
+	}
+}
+
+/// Keeps the reserved set of a notifications protocol in sync with the addresses of the current
+/// and next authority set.
+///
+/// A [`ConnectivityManager`] will
+///
+///    1. Retrieve the current and next set of authorities.
+///
+///    2. Resolve the addresses of each authority through the authority discovery [`Service`].
+///
+///    3. Set the resolved addresses as the reserved peers of the configured protocol.
+///
+///    4. Track the substreams opened for the configured protocol and report which share of the
+///    authorities the local node is connected to.
+pub struct ConnectivityManager<Client, Network, Block, EventStream> {
+	client: Arc<Client>,
+
+	network: Arc<Network>,
+
+	/// Used to resolve [`AuthorityId`]s to addresses.
+	service: Service,
+
+	/// Keystore holding the local authority keys, if the local node is an authority.
+	key_store: Option<Arc<dyn CryptoStore>>,
+
+	/// Channel we receive network events on.
+	event_stream: EventStream,
+
+	/// Same value as in the configuration.
+	protocol: Cow<'static, str>,
+
+	/// Interval at which the authority set is retrieved and resolved.
+	refresh_interval: ExpIncInterval,
+
+	/// Authority set retrieved at the latest refresh, without the local authority keys.
+	authorities: HashSet<AuthorityId>,
+	/// Peer ids each authority was resolved to at the latest refresh.
+	authority_peers: HashMap<AuthorityId, HashSet<PeerId>>,
+	/// Addresses installed as reserved peers at the latest refresh.
+	reserved: HashSet<Multiaddr>,
+	/// Peers we have an open substream with for `protocol`.
+	connected: HashSet<PeerId>,
+
+	metrics: Option<Metrics>,
+
+	phantom: PhantomData<Block>,
+}
+
+impl<Client, Network, Block, EventStream> ConnectivityManager<Client, Network, Block, EventStream>
+where
+	Block: BlockT + Unpin + 'static,
+	Network: NetworkReservedSets,
+	Client: ProvideRuntimeApi<Block> + Send + Sync + 'static + HeaderBackend<Block>,
+	<Client as ProvideRuntimeApi<Block>>::Api: AuthorityDiscoveryApi<Block>,
+	EventStream: Stream<Item = Event> + Unpin,
+{
+	/// Construct a [`ConnectivityManager`].
+	///
+	/// `event_stream` is expected to be a [`sc_network::NetworkService::event_stream`] of the
+	/// same network that `network` refers to. `key_store` holds the local authority keys, which
+	/// are excluded from the authority set.
+	pub fn new(
+		config: ConnectivityConfig,
+		client: Arc<Client>,
+		network: Arc<Network>,
+		service: Service,
+		key_store: Option<Arc<dyn CryptoStore>>,
+		event_stream: EventStream,
+		prometheus_registry: Option<prometheus_endpoint::Registry>,
+	) -> Self {
+		// Right after startup the authority discovery worker likely did not yet resolve any
+		// authority, thus retry soon and back off towards the configured maximum.
+		let refresh_interval =
+			ExpIncInterval::new(Duration::from_secs(2), config.max_refresh_interval);
+
+		let metrics = match prometheus_registry {
+			Some(registry) => match Metrics::register(&registry, &config.protocol) {
+				Ok(metrics) => Some(metrics),
+				Err(e) => {
+					error!(target: LOG_TARGET, "Failed to register connectivity metrics: {}", e);
+					None
+				},
+			},
+			None => None,
+		};
+
+		ConnectivityManager {
+			client,
+			network,
+			service,
+			key_store,
+			event_stream,
+			protocol: config.protocol,
+			refresh_interval,
+			authorities: HashSet::new(),
+			authority_peers: HashMap::new(),
+			reserved: HashSet::new(),
+			connected: HashSet::new(),
+			metrics,
+			phantom: PhantomData,
+		}
+	}
+
+	/// Start the connectivity manager.
+	pub async fn run(mut self) {
+		loop {
+			futures::select! {
+				// Process incoming events.
+				event = self.event_stream.next().fuse() => {
+					if let Some(event) = event {
+						self.handle_network_event(event);
+					} else {
+						// This point is reached if the network has shut down, at which point there
+						// is nothing left to keep connected.
+						return;
+					}
+				},
+				// Refresh the authority set and the reserved peers.
+				_ = self.refresh_interval.next().fuse() => {
+					if let Err(e) = self.refresh().await {
+						error!(
+							target: LOG_TARGET,
+							"Failed to refresh reserved authority peers: {}", e,
+						);
+					}
+				},
+			}
+		}
+	}
+
+	/// Retrieve the current and next authority set, resolve their addresses and update the
+	/// reserved set of the configured protocol if it changed.
+	async fn refresh(&mut self) -> Result<()> {
+		let id = BlockId::hash(self.client.info().best_hash);
+
+		let local_keys = match &self.key_store {
+			Some(key_store) => key_store
+				.sr25519_public_keys(key_types::AUTHORITY_DISCOVERY)
+				.await
+				.into_iter()
+				.map(AuthorityId::from)
+				.collect::<HashSet<_>>(),
+			None => HashSet::new(),
+		};
+
+		let authorities = self
+			.client
+			.runtime_api()
+			.authorities(&id)
+			.map_err(|e| Error::CallingRuntime(e.into()))?
+			.into_iter()
+			.filter(|authority| !local_keys.contains(authority))
+			.collect::<HashSet<_>>();
+
+		if authorities != self.authorities {
+			debug!(
+				target: LOG_TARGET,
+				"Authority set changed, {} other authorities in the current and next set.",
+				authorities.len(),
+			);
+			self.authorities = authorities;
+		}
+
+		let mut authority_peers = HashMap::with_capacity(self.authorities.len());
+		let mut reserved = HashSet::new();
+
+		for authority in self.authorities.iter() {
+			let addresses =
+				match self.service.get_addresses_by_authority_id(authority.clone()).await {
+					Some(addresses) => addresses,
+					None => {
+						trace!(
+							target: LOG_TARGET,
+							"No addresses known for authority {}.",
+							authority,
+						);
+						continue
+					},
+				};
+
+			let peers = addresses.iter().filter_map(peer_id_from_multiaddr).collect();
+			authority_peers.insert(authority.clone(), peers);
+			reserved.extend(addresses);
+		}
+
+		self.authority_peers = authority_peers;
+
+		if reserved != self.reserved {
+			debug!(
+				target: LOG_TARGET,
+				"Setting {} reserved peers for protocol {}.",
+				reserved.len(),
+				self.protocol,
+			);
+
+			self.network
+				.set_reserved_peers(self.protocol.clone(), reserved.clone())
+				.map_err(Error::SettingReservedPeers)?;
+			self.reserved = reserved;
+		}
+
+		self.update_metrics();
+
+		Ok(())
+	}
+
+	/// Track substreams of the configured protocol.
+	fn handle_network_event(&mut self, event: Event) {
+		match event {
+			Event::NotificationStreamOpened { remote, protocol, .. }
+				if protocol == self.protocol =>
+			{
+				self.connected.insert(remote);
+			},
+			Event::NotificationStreamClosed { remote, protocol } if protocol == self.protocol => {
+				self.connected.remove(&remote);
+			},
+			_ => return,
+		}
+
+		self.update_metrics();
+	}
+
+	/// Number of authorities the local node has an open substream with for the configured
+	/// protocol.
+	fn num_connected_authorities(&self) -> usize {
+		self.authority_peers
+			.values()
+			.filter(|peers| peers.iter().any(|peer| self.connected.contains(peer)))
+			.count()
+	}
+
+	fn update_metrics(&self) {
+		let metrics = match &self.metrics {
+			Some(metrics) => metrics,
+			None => return,
+		};
+
+		let authorities = self.authorities.len();
+		let connected = self.num_connected_authorities();
+
+		metrics.authorities.set(authorities.try_into().unwrap_or(std::u64::MAX));
+		metrics
+			.resolved_authorities
+			.set(self.authority_peers.len().try_into().unwrap_or(std::u64::MAX));
+		metrics.connected_authorities.set(connected.try_into().unwrap_or(std::u64::MAX));
+		metrics.connectivity_ratio.set(if authorities == 0 {
+			0.0
+		} else {
+			connected as f64 / authorities as f64
+		});
+	}
+}
+
+fn peer_id_from_multiaddr(address: &Multiaddr) -> Option<PeerId> {
+	match address.iter().last() {
+		Some(multiaddr::Protocol::P2p(key)) => PeerId::from_multihash(key).ok(),
+		_ => None,
+	}
+}
+
+/// Prometheus metrics for a [`ConnectivityManager`].
+#[derive(Clone)]
+pub(crate) struct Metrics {
+	authorities: Gauge<U64>,
+	resolved_authorities: Gauge<U64>,
+	connected_authorities: Gauge<U64>,
+	connectivity_ratio: Gauge<F64>,
+}
+
+impl Metrics {
+	pub(crate) fn register(
+		registry: &prometheus_endpoint::Registry,
+		protocol: &str,
+	) -> Result<Self> {
+		Ok(Self {
+			authorities: register(
+				Gauge::with_opts(
+					Opts::new(
+						"substrate_authority_discovery_connectivity_authorities",
+						"Number of authorities in the current and next authority set, without the \
+						 local node's.",
+					)
+					.const_label("protocol", protocol),
+				)?,
+				registry,
+			)?,
+			resolved_authorities: register(
+				Gauge::with_opts(
+					Opts::new(
+						"substrate_authority_discovery_connectivity_resolved_authorities",
+						"Number of authorities whose addresses are known and reserved.",
+					)
+					.const_label("protocol", protocol),
+				)?,
+				registry,
+			)?,
+			connected_authorities: register(
+				Gauge::with_opts(
+					Opts::new(
+						"substrate_authority_discovery_connectivity_connected_authorities",
+						"Number of authorities with an open substream for the protocol.",
+					)
+					.const_label("protocol", protocol),
+				)?,
+				registry,
+			)?,
+			connectivity_ratio: register(
+				Gauge::with_opts(
+					Opts::new(
+						"substrate_authority_discovery_connectivity_ratio",
+						"Share of the current and next authority set, without the local node, \
+						 with an open substream for the protocol.",
+					)
+					.const_label("protocol", protocol),
+				)?,
+				registry,
+			)?,
+		})
+	}
+}
@@ -0,0 +1,207 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use std::sync::Mutex;
+
+use futures::{
+	channel::mpsc::{self, channel},
+	executor::LocalPool,
+	task::LocalSpawn,
+};
+use sc_network::ObservedRole;
+use sp_authority_discovery::AuthorityPair;
+use sp_core::crypto::Pair;
+use sp_keystore::testing::KeyStore;
+
+use crate::{
+	new_worker_and_service,
+	worker::{
+		tests::{TestApi, TestNetwork},
+		Role,
+	},
+};
+
+use super::*;
+
+#[derive(Default)]
+struct TestReservedSets {
+	set_reserved_peers_call: Mutex<Vec<(Cow<'static, str>, HashSet<Multiaddr>)>>,
+}
+
+impl NetworkReservedSets for TestReservedSets {
+	fn set_reserved_peers(
+		&self,
+		protocol: Cow<'static, str>,
+		peers: HashSet<Multiaddr>,
+	) -> std::result::Result<(), String> {
+		self.set_reserved_peers_call.lock().unwrap().push((protocol, peers));
+		Ok(())
+	}
+}
+
+fn address_with_peer_id(peer_id: PeerId) -> Multiaddr {
+	"/ip6/2001:db8:0:0:0:0:0:2/tcp/30333"
+		.parse::<Multiaddr>()
+		.unwrap()
+		.with(multiaddr::Protocol::P2p(peer_id.into()))
+}
+
+#[test]
+fn reserves_resolved_authorities_and_tracks_connectivity() {
+	let (_dht_event_tx, dht_event_rx) = channel(0);
+	let (_event_tx, event_rx) = mpsc::unbounded();
+
+	let authority_1 = AuthorityPair::from_seed_slice(&[1; 32]).unwrap().public();
+	let authority_2 = AuthorityPair::from_seed_slice(&[2; 32]).unwrap().public();
+	let test_api =
+		Arc::new(TestApi { authorities: vec![authority_1.clone(), authority_2.clone()] });
+
+	let peer_1 = PeerId::random();
+	let address_1 = address_with_peer_id(peer_1);
+
+	let mut pool = LocalPool::new();
+
+	let (mut worker, service) = new_worker_and_service(
+		test_api.clone(),
+		Arc::new(TestNetwork::default()),
+		Box::pin(dht_event_rx),
+		Role::Discover,
+		None,
+	);
+	// Only the first authority has been discovered so far.
+	worker.inject_addresses(authority_1.clone(), vec![address_1.clone()]);
+	pool.spawner().spawn_local_obj(Box::pin(worker.run()).into()).unwrap();
+
+	let network = Arc::new(TestReservedSets::default());
+	let registry = prometheus_endpoint::Registry::new();
+	let mut manager = ConnectivityManager::new(
+		ConnectivityConfig::new("/test/1"),
+		test_api,
+		network.clone(),
+		service,
+		None,
+		event_rx,
+		Some(registry),
+	);
+
+	pool.run_until(manager.refresh()).unwrap();
+
+	assert_eq!(
+		vec![(Cow::Borrowed("/test/1"), HashSet::from([address_1.clone()]))],
+		*network.set_reserved_peers_call.lock().unwrap(),
+	);
+	assert_eq!(2, manager.authorities.len());
+	assert_eq!(1, manager.authority_peers.len());
+	assert_eq!(0, manager.num_connected_authorities());
+
+	// Refreshing without any change does not touch the reserved set again.
+	pool.run_until(manager.refresh()).unwrap();
+	assert_eq!(1, network.set_reserved_peers_call.lock().unwrap().len());
+
+	// Substreams on other protocols are ignored.
+	manager.handle_network_event(Event::NotificationStreamOpened {
+		remote: peer_1,
+		protocol: "/other/1".into(),
+		negotiated_fallback: None,
+		role: ObservedRole::Authority,
+	});
+	assert_eq!(0, manager.num_connected_authorities());
+
+	manager.handle_network_event(Event::NotificationStreamOpened {
+		remote: peer_1,
+		protocol: "/test/1".into(),
+		negotiated_fallback: None,
+		role: ObservedRole::Authority,
+	});
+	assert_eq!(1, manager.num_connected_authorities());
+
+	let metrics = manager.metrics.as_ref().unwrap();
+	assert_eq!(2, metrics.authorities.get());
+	assert_eq!(1, metrics.connected_authorities.get());
+	assert_eq!(0.5, metrics.connectivity_ratio.get());
+
+	manager.handle_network_event(Event::NotificationStreamClosed {
+		remote: peer_1,
+		protocol: "/test/1".into(),
+	});
+	assert_eq!(0, manager.num_connected_authorities());
+}
+
+#[test]
+fn local_authority_is_excluded() {
+	let (_dht_event_tx, dht_event_rx) = channel(0);
+	let (_event_tx, event_rx) = mpsc::unbounded();
+
+	let mut pool = LocalPool::new();
+
+	let key_store = Arc::new(KeyStore::new());
+	let local_authority: AuthorityId = pool
+		.run_until(key_store.sr25519_generate_new(key_types::AUTHORITY_DISCOVERY, None))
+		.unwrap()
+		.into();
+	let authority = AuthorityPair::from_seed_slice(&[1; 32]).unwrap().public();
+	let test_api =
+		Arc::new(TestApi { authorities: vec![local_authority.clone(), authority.clone()] });
+
+	let peer = PeerId::random();
+	let address = address_with_peer_id(peer);
+
+	let (mut worker, service) = new_worker_and_service(
+		test_api.clone(),
+		Arc::new(TestNetwork::default()),
+		Box::pin(dht_event_rx),
+		Role::Discover,
+		None,
+	);
+	worker.inject_addresses(local_authority.clone(), vec![address_with_peer_id(PeerId::random())]);
+	worker.inject_addresses(authority.clone(), vec![address.clone()]);
+	pool.spawner().spawn_local_obj(Box::pin(worker.run()).into()).unwrap();
+
+	let network = Arc::new(TestReservedSets::default());
+	let registry = prometheus_endpoint::Registry::new();
+	let mut manager = ConnectivityManager::new(
+		ConnectivityConfig::new("/test/1"),
+		test_api,
+		network.clone(),
+		service,
+		Some(key_store as Arc<dyn CryptoStore>),
+		event_rx,
+		Some(registry),
+	);
+
+	pool.run_until(manager.refresh()).unwrap();
+
+	// The local node neither reserves nor counts itself.
+	assert_eq!(
+		vec![(Cow::Borrowed("/test/1"), HashSet::from([address]))],
+		*network.set_reserved_peers_call.lock().unwrap(),
+	);
+	assert_eq!(HashSet::from([authority]), manager.authorities);
+
+	manager.handle_network_event(Event::NotificationStreamOpened {
+		remote: peer,
+		protocol: "/test/1".into(),
+		negotiated_fallback: None,
+		role: ObservedRole::Authority,
+	});
+
+	let metrics = manager.metrics.as_ref().unwrap();
+	assert_eq!(1, metrics.authorities.get());
+	assert_eq!(1, metrics.connected_authorities.get());
+	assert_eq!(1.0, metrics.connectivity_ratio.get());
+}
@@ -18,6 +18,7 @@
 
 //! Authority discovery errors.
 
+use sp_authority_discovery::AuthorityId;
 use sp_core::crypto::CryptoTypePublicPair;
 
 /// AuthorityDiscovery Result.
@@ -76,4 +77,36 @@ pub enum Error {
 
 	#[error("Received authority record without a valid signature for the remote peer id.")]
 	MissingPeerIdSignature,
+
+	#[error("Failed to set the reserved peers of a protocol: {0}")]
+	SettingReservedPeers(String),
+
+	#[error("No local authority discovery key is part of the current or next authority set.")]
+	NoLocalAuthorityKey,
+
+	#[error("No addresses known for authority {0}.")]
+	UnknownAuthorityAddresses(AuthorityId),
+
+	#[error("Message payload of {0} bytes exceeds the maximum payload size.")]
+	MessageTooLarge(usize),
+
+	#[error("Failed to send a message to an authority: {0}")]
+	SendingMessage(#[from] sc_network::RequestFailure),
+
+	#[error("Received a message for {0}, which is not a local authority discovery key.")]
+	InvalidMessageRecipient(AuthorityId),
+
+	#[error(
+		"Received a message from {0}, which is not part of the current or next authority set."
+	)]
+	InvalidMessageSender(AuthorityId),
+
+	#[error("Failed to verify a message with the given signature.")]
+	VerifyingMessage,
+
+	#[error("Received a message with a replayed or outdated nonce {0}.")]
+	ReplayedMessage(u64),
+
+	#[error("Message queue of the consumer is full.")]
+	MessageQueueFull,
 }
@@ -25,9 +25,16 @@
 //! other authorities. It is split into two components the [`Worker`] and the
 //! [`Service`].
 //!
-//! See [`Worker`] and [`Service`] for more documentation.
+//! On top of those, a [`ConnectivityManager`] can be used to keep the node connected to the
+//! current and next set of authorities on a given notifications protocol, and
+//! [`ValidatorMessaging`] to send messages to a given authority that are authenticated by its
+//! authority discovery key. See the [`messaging`] module for the latter.
+//!
+//! See [`Worker`], [`Service`] and [`ConnectivityManager`] for more documentation.
 
 pub use crate::{
+	connectivity::{ConnectivityConfig, ConnectivityManager, NetworkReservedSets},
+	messaging::{MessagingHandler, MessagingNetwork, ReceivedMessage, ValidatorMessaging},
 	service::Service,
 	worker::{NetworkProvider, Role, Worker},
 };
@@ -45,8 +52,10 @@ use sp_api::ProvideRuntimeApi;
 use sp_authority_discovery::{AuthorityDiscoveryApi, AuthorityId};
 use sp_runtime::traits::Block as BlockT;
 
+mod connectivity;
 mod error;
 mod interval;
+pub mod messaging;
 mod service;
 mod worker;
 
@@ -0,0 +1,615 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Direct messaging between authorities, authenticated by their authority discovery keys.
+//!
+//! Messages are sent over a dedicated request-response protocol. The receiving peer is looked up
+//! through the authority discovery [`Service`], thus the libp2p connection is established with the
+//! [`PeerId`] the target authority signed into its DHT record and is encrypted by the transport.
+//!
+//! On top of that each message is signed with the authority discovery key of the sender and
+//! names both the sending and the receiving [`AuthorityId`] as well as a nonce. A receiver only
+//! accepts a message if
+//!
+//!    1. the signature is valid for the sender's [`AuthorityId`],
+//!
+//!    2. it is addressed to one of the keys in its local keystore,
+//!
+//!    3. the sender is part of the current or next authority set and
+//!
+//!    4. the nonce has not been accepted from the sender before and is at most [`NONCE_WINDOW`]
+//!       below the highest nonce accepted from the sender.
+//!
+//! Thus a message is authenticated by the session key of the sender, independently of the libp2p
+//! identity of the node that relayed it, and can neither be replayed nor redirected to another
+//! authority. Only peers sending malformed or wrongly signed messages are penalized, as every
+//! other check depends on local state, e.g. the best block, that the sender might not share.
+//!
+//! Offchain workers can send messages through
+//! [`sp_authority_discovery::validator_messaging::send`] once the extensions returned by
+//! [`ValidatorMessaging::offchain_extensions`] are registered with the client, and read received
+//! messages from the inbox filled by [`store_in_offchain_inbox`].
+
+use crate::{
+	error::{Error, Result},
+	Service,
+};
+
+use std::{
+	borrow::Cow,
+	collections::{BTreeSet, HashMap},
+	marker::PhantomData,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use codec::{Decode, Encode};
+use futures::{
+	channel::{mpsc, oneshot},
+	Future, StreamExt,
+};
+use libp2p::core::multiaddr;
+use log::{debug, trace, warn};
+use parking_lot::Mutex;
+use sc_client_api::{blockchain::HeaderBackend, execution_extensions::ExtensionsFactory};
+use sc_network::{
+	config::{IncomingRequest, OutgoingResponse, RequestResponseConfig},
+	ExHashT, IfDisconnected, Multiaddr, PeerId, RequestFailure,
+};
+use sp_api::ProvideRuntimeApi;
+use sp_authority_discovery::{
+	AuthorityDiscoveryApi, AuthorityId, AuthorityPair, AuthoritySignature, MessageQueue,
+	ValidatorMessagingExt, OFFCHAIN_INBOX_KEY,
+};
+use sp_core::{
+	crypto::{key_types, ByteArray, Pair},
+	hexdisplay::HexDisplay,
+	offchain::{Capabilities, OffchainStorage, STORAGE_PREFIX},
+};
+use sp_externalities::Extensions;
+use sp_keystore::CryptoStore;
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+
+#[cfg(test)]
+mod tests;
+
+const LOG_TARGET: &str = "sub-authority-discovery";
+
+/// Context prepended to the signed payload, so that signatures of messages can not be confused
+/// with signatures of authority discovery records or anything else signed with the same key.
+const SIGNING_CONTEXT: &[u8] = b"substrate-validator-messaging";
+
+/// Maximum size of a single message payload.
+pub const MAX_PAYLOAD_SIZE: usize = 1024 * 1024;
+
+/// Distance below the highest nonce accepted from a sender within which nonces are still
+/// accepted.
+///
+/// Nonces follow the wall clock of the sender in milliseconds, thus messages sent concurrently
+/// may arrive out of order. The window matches the request timeout of the protocol.
+pub const NONCE_WINDOW: u64 = 10_000;
+
+/// Maximum number of messages kept in the offchain inbox, older messages are dropped first.
+pub const MAX_INBOX_MESSAGES: usize = 256;
+
+/// Maximum number of messages queued by offchain workers that are sent concurrently.
+const MAX_CONCURRENT_OFFCHAIN_SENDS: usize = 16;
+
+mod rep {
+	use sc_network::ReputationChange as Rep;
+
+	/// Reputation change when a peer sends a message that can not be decoded or carries an
+	/// invalid signature.
+	pub const BAD_MESSAGE: Rep = Rep::new(-(1 << 12), "Invalid validator message");
+}
+
+/// Generate the validator messaging protocol name from the genesis hash and fork id.
+pub fn protocol_name<Hash: AsRef<[u8]>>(genesis_hash: &Hash, fork_id: Option<&str>) -> String {
+	match fork_id {
+		Some(fork_id) => format!(
+			"/{}/{}/validator-messaging/1",
+			HexDisplay::from(&genesis_hash.as_ref()),
+			fork_id
+		),
+		None => format!("/{}/validator-messaging/1", HexDisplay::from(&genesis_hash.as_ref())),
+	}
+}
+
+/// Generates a [`RequestResponseConfig`] for the validator messaging protocol, refusing incoming
+/// requests.
+pub fn generate_protocol_config(
+	protocol_name: impl Into<Cow<'static, str>>,
+) -> RequestResponseConfig {
+	RequestResponseConfig {
+		name: protocol_name.into(),
+		// Leave some room for the envelope around the payload.
+		max_request_size: MAX_PAYLOAD_SIZE as u64 + 1024,
+		// Responses are empty acknowledgements.
+		max_response_size: 16,
+		request_timeout: Duration::from_secs(10),
+		inbound_queue: None,
+	}
+}
+
+/// A message as put on the wire.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub(crate) struct SignedMessage {
+	sender: AuthorityId,
+	recipient: AuthorityId,
+	nonce: u64,
+	payload: Vec<u8>,
+	signature: AuthoritySignature,
+}
+
+/// The bytes covered by the signature of a [`SignedMessage`].
+fn signing_payload(
+	sender: &AuthorityId,
+	recipient: &AuthorityId,
+	nonce: u64,
+	payload: &[u8],
+) -> Vec<u8> {
+	(SIGNING_CONTEXT, sender, recipient, nonce, payload).encode()
+}
+
+/// A verified message received from another authority.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceivedMessage {
+	/// Authority that signed the message.
+	pub sender: AuthorityId,
+	/// Local authority key the message was addressed to.
+	pub recipient: AuthorityId,
+	/// Node the message was received from.
+	pub peer: PeerId,
+	/// Application payload.
+	pub payload: Vec<u8>,
+}
+
+/// Hooks into the underlying Substrate networking needed by [`ValidatorMessaging`].
+///
+/// Using this trait abstraction instead of [`sc_network::NetworkService`] directly is necessary
+/// to unit test [`ValidatorMessaging`].
+#[async_trait]
+pub trait MessagingNetwork: Send + Sync {
+	/// Add an address known to a node.
+	fn add_known_address(&self, peer_id: PeerId, addr: Multiaddr);
+
+	/// Send a request to `target`, connecting to it first if necessary.
+	async fn request(
+		&self,
+		target: PeerId,
+		protocol: Cow<'static, str>,
+		request: Vec<u8>,
+	) -> std::result::Result<Vec<u8>, RequestFailure>;
+}
+
+#[async_trait]
+impl<B, H> MessagingNetwork for sc_network::NetworkService<B, H>
+where
+	B: BlockT + 'static,
+	H: ExHashT,
+{
+	fn add_known_address(&self, peer_id: PeerId, addr: Multiaddr) {
+		self.add_known_address(peer_id, addr)
+	}
+
+	async fn request(
+		&self,
+		target: PeerId,
+		protocol: Cow<'static, str>,
+		request: Vec<u8>,
+	) -> std::result::Result<Vec<u8>, RequestFailure> {
+		self.request(target, protocol, request, IfDisconnected::TryConnect).await
+	}
+}
+
+/// Handle to send messages to other authorities.
+///
+/// Messages are signed with the local authority discovery key that is part of the current or next
+/// authority set. The handle is cheap to clone and can be handed to any client task.
+pub struct ValidatorMessaging<Client, Network, Block> {
+	protocol: Cow<'static, str>,
+	client: Arc<Client>,
+	network: Arc<Network>,
+	service: Service,
+	key_store: Arc<dyn CryptoStore>,
+	/// Last used nonce, shared between all clones.
+	nonce: Arc<AtomicU64>,
+	phantom: PhantomData<Block>,
+}
+
+impl<Client, Network, Block> Clone for ValidatorMessaging<Client, Network, Block> {
+	fn clone(&self) -> Self {
+		Self {
+			protocol: self.protocol.clone(),
+			client: self.client.clone(),
+			network: self.network.clone(),
+			service: self.service.clone(),
+			key_store: self.key_store.clone(),
+			nonce: self.nonce.clone(),
+			phantom: PhantomData,
+		}
+	}
+}
+
+impl<Client, Network, Block> ValidatorMessaging<Client, Network, Block>
+where
+	Block: BlockT + 'static,
+	Network: MessagingNetwork,
+	Client: ProvideRuntimeApi<Block> + Send + Sync + 'static + HeaderBackend<Block>,
+	<Client as ProvideRuntimeApi<Block>>::Api: AuthorityDiscoveryApi<Block>,
+{
+	/// Create a new [`ValidatorMessaging`] handle.
+	///
+	/// `protocol` must have been registered with the network through
+	/// [`generate_protocol_config`] or [`MessagingHandler::new`].
+	pub fn new(
+		protocol: impl Into<Cow<'static, str>>,
+		client: Arc<Client>,
+		network: Arc<Network>,
+		service: Service,
+		key_store: Arc<dyn CryptoStore>,
+	) -> Self {
+		Self {
+			protocol: protocol.into(),
+			client,
+			network,
+			service,
+			key_store,
+			nonce: Arc::new(AtomicU64::new(0)),
+			phantom: PhantomData,
+		}
+	}
+
+	/// Send `payload` to the given authority.
+	///
+	/// Returns once the remote accepted the message.
+	pub async fn send(&self, recipient: AuthorityId, payload: Vec<u8>) -> Result<()> {
+		if payload.len() > MAX_PAYLOAD_SIZE {
+			return Err(Error::MessageTooLarge(payload.len()))
+		}
+
+		let mut service = self.service.clone();
+		let addresses = service
+			.get_addresses_by_authority_id(recipient.clone())
+			.await
+			.ok_or_else(|| Error::UnknownAuthorityAddresses(recipient.clone()))?;
+
+		let mut target = None;
+		for address in addresses {
+			let peer_id = match address.iter().last() {
+				Some(multiaddr::Protocol::P2p(key)) => PeerId::from_multihash(key).ok(),
+				_ => None,
+			};
+			let peer_id = match peer_id {
+				Some(peer_id) => peer_id,
+				None => continue,
+			};
+
+			let address: Multiaddr =
+				address.iter().filter(|p| !matches!(p, multiaddr::Protocol::P2p(_))).collect();
+			self.network.add_known_address(peer_id, address);
+			target = Some(peer_id);
+		}
+		let target = target.ok_or_else(|| Error::UnknownAuthorityAddresses(recipient.clone()))?;
+
+		let message = self.sign_message(recipient, payload).await?;
+
+		trace!(
+			target: LOG_TARGET,
+			"Sending message with nonce {} to authority {} at {}.",
+			message.nonce,
+			message.recipient,
+			target,
+		);
+
+		self.network
+			.request(target, self.protocol.clone(), message.encode())
+			.await
+			.map_err(Error::SendingMessage)?;
+
+		Ok(())
+	}
+
+	/// Sign `payload` for `recipient` with the local key within the current or next authority
+	/// set.
+	pub(crate) async fn sign_message(
+		&self,
+		recipient: AuthorityId,
+		payload: Vec<u8>,
+	) -> Result<SignedMessage> {
+		let id = BlockId::hash(self.client.info().best_hash);
+		let authorities = self
+			.client
+			.runtime_api()
+			.authorities(&id)
+			.map_err(|e| Error::CallingRuntime(e.into()))?;
+
+		let local_keys = self.key_store.sr25519_public_keys(key_types::AUTHORITY_DISCOVERY).await;
+		let sender = authorities
+			.into_iter()
+			.find(|authority| local_keys.contains(authority.as_ref()))
+			.ok_or(Error::NoLocalAuthorityKey)?;
+
+		let nonce = self.next_nonce();
+		let signature = self
+			.key_store
+			.sign_with(
+				key_types::AUTHORITY_DISCOVERY,
+				&sender.clone().into(),
+				&signing_payload(&sender, &recipient, nonce, &payload),
+			)
+			.await
+			.ok()
+			.flatten()
+			.ok_or_else(|| Error::MissingSignature(sender.clone().into()))?;
+		let signature = AuthoritySignature::decode(&mut &signature[..])
+			.map_err(Error::EncodingDecodingScale)?;
+
+		Ok(SignedMessage { sender, recipient, nonce, payload, signature })
+	}
+
+	/// Returns a nonce that is higher than all previously returned ones.
+	///
+	/// Nonces follow the wall clock in milliseconds so that they keep increasing across restarts
+	/// of the node.
+	fn next_nonce(&self) -> u64 {
+		let now = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|d| d.as_millis().try_into().unwrap_or(u64::MAX))
+			.unwrap_or_default();
+
+		let previous = self
+			.nonce
+			.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+				Some(std::cmp::max(last.saturating_add(1), now))
+			})
+			.expect("closure always returns `Some`; qed");
+
+		std::cmp::max(previous.saturating_add(1), now)
+	}
+
+	/// Returns the [`OffchainExtensions`] that let offchain workers send messages through
+	/// [`sp_authority_discovery::validator_messaging::send`] and the future sending the queued
+	/// messages.
+	///
+	/// At most `queue_size` messages are buffered, further messages are dropped until the future
+	/// catches up.
+	pub fn offchain_extensions(
+		&self,
+		queue_size: usize,
+	) -> (OffchainExtensions, impl Future<Output = ()> + Send + 'static)
+	where
+		Network: 'static,
+	{
+		let (tx, rx) = mpsc::channel::<(AuthorityId, Vec<u8>)>(queue_size);
+		let messaging = self.clone();
+		let sender =
+			rx.for_each_concurrent(MAX_CONCURRENT_OFFCHAIN_SENDS, move |(recipient, payload)| {
+				let messaging = messaging.clone();
+				async move {
+					if let Err(e) = messaging.send(recipient, payload).await {
+						debug!(target: LOG_TARGET, "Failed to send offchain worker message: {}", e);
+					}
+				}
+			});
+
+		(OffchainExtensions(Arc::new(OffchainQueue(Mutex::new(tx)))), sender)
+	}
+}
+
+/// [`MessageQueue`] handing the messages of offchain workers to [`ValidatorMessaging`].
+struct OffchainQueue(Mutex<mpsc::Sender<(AuthorityId, Vec<u8>)>>);
+
+impl MessageQueue for OffchainQueue {
+	fn queue(&self, recipient: AuthorityId, payload: Vec<u8>) -> bool {
+		self.0.lock().try_send((recipient, payload)).is_ok()
+	}
+}
+
+/// [`ExtensionsFactory`] providing offchain workers with a [`ValidatorMessagingExt`].
+///
+/// Created through [`ValidatorMessaging::offchain_extensions`].
+#[derive(Clone)]
+pub struct OffchainExtensions(Arc<dyn MessageQueue>);
+
+impl ExtensionsFactory for OffchainExtensions {
+	fn extensions_for(&self, capabilities: Capabilities) -> Extensions {
+		let mut extensions = Extensions::new();
+		// Messages are signed with the local keys and sent over the network.
+		if capabilities.contains(Capabilities::KEYSTORE | Capabilities::NETWORK_STATE) {
+			extensions.register(ValidatorMessagingExt(self.0.clone()));
+		}
+		extensions
+	}
+}
+
+/// Append the messages received on `messages` to the inbox in the persistent offchain storage.
+///
+/// See [`OFFCHAIN_INBOX_KEY`] for the format. At most [`MAX_INBOX_MESSAGES`] are kept, the oldest
+/// messages are dropped first.
+pub async fn store_in_offchain_inbox<Storage: OffchainStorage>(
+	mut storage: Storage,
+	mut messages: mpsc::Receiver<ReceivedMessage>,
+) {
+	while let Some(ReceivedMessage { sender, payload, .. }) = messages.next().await {
+		loop {
+			let old = storage.get(STORAGE_PREFIX, OFFCHAIN_INBOX_KEY);
+			let mut inbox = old
+				.as_ref()
+				.and_then(|inbox| Vec::<(AuthorityId, Vec<u8>)>::decode(&mut &inbox[..]).ok())
+				.unwrap_or_default();
+
+			inbox.push((sender.clone(), payload.clone()));
+			let excess = inbox.len().saturating_sub(MAX_INBOX_MESSAGES);
+			if excess > 0 {
+				warn!(
+					target: LOG_TARGET,
+					"Offchain inbox is full, dropping {} validator message(s).", excess,
+				);
+				inbox.drain(..excess);
+			}
+
+			if storage.compare_and_set(
+				STORAGE_PREFIX,
+				OFFCHAIN_INBOX_KEY,
+				old.as_deref(),
+				&inbox.encode(),
+			) {
+				break
+			}
+		}
+	}
+}
+
+/// Handler for incoming messages from other authorities.
+///
+/// Verified messages are forwarded on the channel returned by [`MessagingHandler::new`].
+pub struct MessagingHandler<Client, Block> {
+	client: Arc<Client>,
+	key_store: Arc<dyn CryptoStore>,
+	request_receiver: mpsc::Receiver<IncomingRequest>,
+	to_consumer: mpsc::Sender<ReceivedMessage>,
+	/// Nonces accepted per sender within [`NONCE_WINDOW`] of the highest one.
+	accepted_nonces: HashMap<AuthorityId, BTreeSet<u64>>,
+	phantom: PhantomData<Block>,
+}
+
+impl<Client, Block> MessagingHandler<Client, Block>
+where
+	Block: BlockT + 'static,
+	Client: ProvideRuntimeApi<Block> + Send + Sync + 'static + HeaderBackend<Block>,
+	<Client as ProvideRuntimeApi<Block>>::Api: AuthorityDiscoveryApi<Block>,
+{
+	/// Create a new [`MessagingHandler`].
+	///
+	/// Returns the handler, the [`RequestResponseConfig`] to register with the network and the
+	/// receiving side of the channel verified messages are delivered on. At most `queue_size`
+	/// messages are buffered, additional messages are refused until the consumer catches up.
+	pub fn new(
+		protocol: impl Into<Cow<'static, str>>,
+		client: Arc<Client>,
+		key_store: Arc<dyn CryptoStore>,
+		queue_size: usize,
+	) -> (Self, RequestResponseConfig, mpsc::Receiver<ReceivedMessage>) {
+		let (tx, request_receiver) = mpsc::channel(queue_size);
+		let (to_consumer, from_handler) = mpsc::channel(queue_size);
+
+		let mut protocol_config = generate_protocol_config(protocol);
+		protocol_config.inbound_queue = Some(tx);
+
+		let handler = Self {
+			client,
+			key_store,
+			request_receiver,
+			to_consumer,
+			accepted_nonces: HashMap::new(),
+			phantom: PhantomData,
+		};
+
+		(handler, protocol_config, from_handler)
+	}
+
+	/// Run [`MessagingHandler`].
+	pub async fn run(mut self) {
+		while let Some(request) = self.request_receiver.next().await {
+			let IncomingRequest { peer, payload, pending_response } = request;
+
+			match self.handle_request(peer, payload, pending_response).await {
+				Ok(()) => trace!(target: LOG_TARGET, "Handled validator message from {}.", peer),
+				Err(e) => debug!(
+					target: LOG_TARGET,
+					"Failed to handle validator message from {}: {}", peer, e,
+				),
+			}
+		}
+	}
+
+	async fn handle_request(
+		&mut self,
+		peer: PeerId,
+		payload: Vec<u8>,
+		pending_response: oneshot::Sender<OutgoingResponse>,
+	) -> Result<()> {
+		let result = match self.verify(peer, &payload).await {
+			Ok(message) => self.to_consumer.try_send(message).map_err(|_| Error::MessageQueueFull),
+			Err(e) => Err(e),
+		};
+
+		// Everything but the encoding and the signature is checked against local state.
+		let reputation_changes = match result {
+			Err(Error::EncodingDecodingScale(_) | Error::VerifyingMessage) =>
+				vec![rep::BAD_MESSAGE],
+			_ => Vec::new(),
+		};
+
+		let _ = pending_response.send(OutgoingResponse {
+			result: result.as_ref().map(|_| Vec::new()).map_err(|_| ()),
+			reputation_changes,
+			sent_feedback: None,
+		});
+
+		result
+	}
+
+	/// Decode and verify an incoming message.
+	pub(crate) async fn verify(&mut self, peer: PeerId, payload: &[u8]) -> Result<ReceivedMessage> {
+		let SignedMessage { sender, recipient, nonce, payload, signature } =
+			SignedMessage::decode(&mut &payload[..]).map_err(Error::EncodingDecodingScale)?;
+
+		if !AuthorityPair::verify(
+			&signature,
+			signing_payload(&sender, &recipient, nonce, &payload),
+			&sender,
+		) {
+			return Err(Error::VerifyingMessage)
+		}
+
+		if !self
+			.key_store
+			.has_keys(&[(recipient.to_raw_vec(), key_types::AUTHORITY_DISCOVERY)])
+			.await
+		{
+			return Err(Error::InvalidMessageRecipient(recipient))
+		}
+
+		let id = BlockId::hash(self.client.info().best_hash);
+		let is_authority = self
+			.client
+			.runtime_api()
+			.authorities(&id)
+			.map_err(|e| Error::CallingRuntime(e.into()))?
+			.contains(&sender);
+		if !is_authority {
+			return Err(Error::InvalidMessageSender(sender))
+		}
+
+		let accepted = self.accepted_nonces.entry(sender.clone()).or_default();
+		let highest = accepted.iter().next_back().copied().unwrap_or_default();
+		if nonce.saturating_add(NONCE_WINDOW) <= highest || !accepted.insert(nonce) {
+			return Err(Error::ReplayedMessage(nonce))
+		}
+		let highest = std::cmp::max(highest, nonce);
+		*accepted = accepted.split_off(&highest.saturating_sub(NONCE_WINDOW));
+
+		Ok(ReceivedMessage { sender, recipient, peer, payload })
+	}
+}
@@ -0,0 +1,245 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use futures::{channel::mpsc::channel, executor::block_on, SinkExt};
+use sp_core::offchain::testing::TestPersistentOffchainDB;
+use sp_keystore::testing::KeyStore;
+use std::any::TypeId;
+
+use crate::{
+	new_worker_and_service,
+	worker::{
+		tests::{TestApi, TestNetwork},
+		Role,
+	},
+};
+
+use super::*;
+
+struct NoNetwork;
+
+#[async_trait]
+impl MessagingNetwork for NoNetwork {
+	fn add_known_address(&self, _peer_id: PeerId, _addr: Multiaddr) {}
+
+	async fn request(
+		&self,
+		_target: PeerId,
+		_protocol: Cow<'static, str>,
+		_request: Vec<u8>,
+	) -> std::result::Result<Vec<u8>, RequestFailure> {
+		Err(RequestFailure::NotConnected)
+	}
+}
+
+struct Setup {
+	sender: AuthorityId,
+	recipient: AuthorityId,
+	messaging:
+		ValidatorMessaging<TestApi, NoNetwork, substrate_test_runtime_client::runtime::Block>,
+	handler: MessagingHandler<TestApi, substrate_test_runtime_client::runtime::Block>,
+}
+
+fn setup() -> Setup {
+	let sender_store = KeyStore::new();
+	let recipient_store = KeyStore::new();
+
+	let sender: AuthorityId =
+		block_on(sender_store.sr25519_generate_new(key_types::AUTHORITY_DISCOVERY, None))
+			.unwrap()
+			.into();
+	let recipient: AuthorityId =
+		block_on(recipient_store.sr25519_generate_new(key_types::AUTHORITY_DISCOVERY, None))
+			.unwrap()
+			.into();
+
+	let test_api = Arc::new(TestApi { authorities: vec![sender.clone(), recipient.clone()] });
+
+	let (_dht_event_tx, dht_event_rx) = channel(0);
+	let (_worker, service) = new_worker_and_service(
+		test_api.clone(),
+		Arc::new(TestNetwork::default()),
+		Box::pin(dht_event_rx),
+		Role::Discover,
+		None,
+	);
+
+	let messaging = ValidatorMessaging::new(
+		"/test/validator-messaging/1",
+		test_api.clone(),
+		Arc::new(NoNetwork),
+		service,
+		Arc::new(sender_store),
+	);
+	let (handler, _config, _messages) = MessagingHandler::new(
+		"/test/validator-messaging/1",
+		test_api,
+		Arc::new(recipient_store),
+		8,
+	);
+
+	Setup { sender, recipient, messaging, handler }
+}
+
+#[test]
+fn signed_message_is_accepted_once() {
+	let Setup { sender, recipient, messaging, mut handler } = setup();
+	let peer = PeerId::random();
+
+	let message = block_on(messaging.sign_message(recipient.clone(), b"hello".to_vec())).unwrap();
+	assert_eq!(sender, message.sender);
+
+	let received = block_on(handler.verify(peer, &message.encode())).unwrap();
+	assert_eq!(ReceivedMessage { sender, recipient, peer, payload: b"hello".to_vec() }, received);
+
+	assert!(matches!(
+		block_on(handler.verify(peer, &message.encode())),
+		Err(Error::ReplayedMessage(_)),
+	));
+}
+
+#[test]
+fn nonces_increase() {
+	let Setup { recipient, messaging, mut handler, .. } = setup();
+	let peer = PeerId::random();
+
+	let first = block_on(messaging.sign_message(recipient.clone(), vec![1])).unwrap();
+	let second = block_on(messaging.clone().sign_message(recipient, vec![2])).unwrap();
+	assert!(second.nonce > first.nonce);
+
+	// Messages delivered out of order are rejected as they could be replays.
+	assert!(block_on(handler.verify(peer, &second.encode())).is_ok());
+	assert!(block_on(handler.verify(peer, &first.encode())).is_err());
+}
+
+#[test]
+fn tampered_message_is_rejected() {
+	let Setup { recipient, messaging, mut handler, .. } = setup();
+
+	let mut message = block_on(messaging.sign_message(recipient, b"hello".to_vec())).unwrap();
+	message.payload = b"bye".to_vec();
+
+	assert!(matches!(
+		block_on(handler.verify(PeerId::random(), &message.encode())),
+		Err(Error::VerifyingMessage),
+	));
+}
+
+#[test]
+fn message_for_other_authority_is_rejected() {
+	let Setup { sender, messaging, mut handler, .. } = setup();
+
+	// The recipient's keystore does not hold the sender's key.
+	let message = block_on(messaging.sign_message(sender, b"hello".to_vec())).unwrap();
+
+	assert!(matches!(
+		block_on(handler.verify(PeerId::random(), &message.encode())),
+		Err(Error::InvalidMessageRecipient(_)),
+	));
+}
+
+#[test]
+fn only_verifiable_misbehaviour_is_penalized() {
+	let Setup { sender, recipient, messaging, mut handler } = setup();
+
+	let mut handle = |message: Vec<u8>| {
+		let (tx, rx) = oneshot::channel();
+		let _ = block_on(handler.handle_request(PeerId::random(), message, tx));
+		block_on(rx).unwrap().reputation_changes
+	};
+
+	// Undecodable.
+	assert_eq!(vec![rep::BAD_MESSAGE], handle(vec![1, 2, 3]));
+
+	// Invalid signature.
+	let mut tampered =
+		block_on(messaging.sign_message(recipient.clone(), b"hello".to_vec())).unwrap();
+	tampered.payload = b"bye".to_vec();
+	assert_eq!(vec![rep::BAD_MESSAGE], handle(tampered.encode()));
+
+	// Valid, but addressed to a key the recipient does not hold, e.g. after a key rotation.
+	let misaddressed = block_on(messaging.sign_message(sender, b"hello".to_vec())).unwrap();
+	assert!(handle(misaddressed.encode()).is_empty());
+
+	// Valid, but replayed.
+	let message = block_on(messaging.sign_message(recipient, b"hello".to_vec())).unwrap();
+	assert!(handle(message.encode()).is_empty());
+	assert!(handle(message.encode()).is_empty());
+}
+
+#[test]
+fn offchain_workers_can_queue_messages() {
+	let Setup { recipient, messaging, .. } = setup();
+	let (factory, _sender) = messaging.offchain_extensions(1);
+
+	assert!(factory
+		.extensions_for(Capabilities::empty())
+		.get_mut(TypeId::of::<ValidatorMessagingExt>())
+		.is_none());
+
+	let mut extensions = factory.extensions_for(Capabilities::all());
+	let ext = extensions
+		.get_mut(TypeId::of::<ValidatorMessagingExt>())
+		.and_then(|ext| ext.downcast_mut::<ValidatorMessagingExt>())
+		.unwrap();
+
+	// The channel holds one message per sender in addition to `queue_size`.
+	assert!(ext.0.queue(recipient.clone(), vec![1]));
+	assert!(ext.0.queue(recipient.clone(), vec![2]));
+	assert!(!ext.0.queue(recipient, vec![3]));
+}
+
+#[test]
+fn received_messages_are_stored_in_offchain_inbox() {
+	let Setup { sender, recipient, .. } = setup();
+	let storage = TestPersistentOffchainDB::new();
+	let (mut tx, rx) = channel(MAX_INBOX_MESSAGES + 2);
+
+	block_on(async {
+		for payload in 0..MAX_INBOX_MESSAGES as u32 + 2 {
+			tx.send(ReceivedMessage {
+				sender: sender.clone(),
+				recipient: recipient.clone(),
+				peer: PeerId::random(),
+				payload: payload.encode(),
+			})
+			.await
+			.unwrap();
+		}
+		drop(tx);
+		store_in_offchain_inbox(storage.clone(), rx).await;
+	});
+
+	let inbox = Vec::<(AuthorityId, Vec<u8>)>::decode(
+		&mut &OffchainStorage::get(&storage, STORAGE_PREFIX, OFFCHAIN_INBOX_KEY).unwrap()[..],
+	)
+	.unwrap();
+	// The oldest messages were dropped.
+	assert_eq!(MAX_INBOX_MESSAGES, inbox.len());
+	assert_eq!((sender, 2u32.encode()), inbox[0]);
+}
+
+#[test]
+fn send_fails_for_unknown_authority() {
+	let Setup { recipient, messaging, .. } = setup();
+
+	assert!(matches!(
+		block_on(messaging.send(recipient, b"hello".to_vec())),
+		Err(Error::UnknownAuthorityAddresses(_)),
+	));
+}
@@ -131,6 +131,75 @@ pub fn execution_method_from_cli(
 	}
 }
 
+/// The backend of the sandboxes created by the runtime, e.g. to execute contracts.
+#[derive(Debug, Clone, Copy)]
+pub enum WasmSandboxBackend {
+	/// Uses an interpreter.
+	Wasmi,
+	/// Uses wasmer.
+	Wasmer,
+	/// Uses wasmtime.
+	Wasmtime,
+}
+
+impl std::str::FromStr for WasmSandboxBackend {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, String> {
+		if s.eq_ignore_ascii_case("wasmi") {
+			Ok(Self::Wasmi)
+		} else if s.eq_ignore_ascii_case("wasmer") {
+			if cfg!(feature = "wasmer-sandbox") {
+				Ok(Self::Wasmer)
+			} else {
+				Err("`Wasmer` variant requires the `wasmer-sandbox` feature to be enabled".into())
+			}
+		} else if s.eq_ignore_ascii_case("wasmtime") {
+			if cfg!(feature = "wasmtime-sandbox") {
+				Ok(Self::Wasmtime)
+			} else {
+				Err("`Wasmtime` variant requires the `wasmtime-sandbox` feature to be enabled"
+					.into())
+			}
+		} else {
+			Err(format!("Unknown variant `{}`, known variants: {:?}", s, Self::variants()))
+		}
+	}
+}
+
+impl WasmSandboxBackend {
+	/// Returns all the variants of this enum to be shown in the cli.
+	pub fn variants() -> &'static [&'static str] {
+		match (cfg!(feature = "wasmer-sandbox"), cfg!(feature = "wasmtime-sandbox")) {
+			(true, true) => &["wasmi", "wasmer", "wasmtime"],
+			(true, false) => &["wasmi", "wasmer"],
+			(false, true) => &["wasmi", "wasmtime"],
+			(false, false) => &["wasmi"],
+		}
+	}
+}
+
+impl Into<sc_service::config::SandboxBackend> for WasmSandboxBackend {
+	fn into(self) -> sc_service::config::SandboxBackend {
+		match self {
+			WasmSandboxBackend::Wasmi => sc_service::config::SandboxBackend::Wasmi,
+			#[cfg(feature = "wasmer-sandbox")]
+			WasmSandboxBackend::Wasmer => sc_service::config::SandboxBackend::Wasmer,
+			#[cfg(not(feature = "wasmer-sandbox"))]
+			WasmSandboxBackend::Wasmer => panic!(
+				"Substrate must be compiled with \"wasmer-sandbox\" feature for the wasmer sandbox"
+			),
+			#[cfg(feature = "wasmtime-sandbox")]
+			WasmSandboxBackend::Wasmtime => sc_service::config::SandboxBackend::Wasmtime,
+			#[cfg(not(feature = "wasmtime-sandbox"))]
+			WasmSandboxBackend::Wasmtime => panic!(
+				"Substrate must be compiled with \"wasmtime-sandbox\" feature for the wasmtime \
+				 sandbox"
+			),
+		}
+	}
+}
+
 /// The default [`WasmExecutionMethod`].
 #[cfg(feature = "wasmtime")]
 pub const DEFAULT_WASM_EXECUTION_METHOD: &str = "compiled";
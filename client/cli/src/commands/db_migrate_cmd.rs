@@ -0,0 +1,159 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2020-2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{arg_enums::Database, error, params::SharedParams, CliConfiguration};
+use clap::Parser;
+use sc_client_api::HeaderBackend;
+use sc_service::{chain_ops::migrate_from_parity_db, DatabaseSource};
+use sp_api::{Core, ProvideRuntimeApi};
+use sp_runtime::traits::Block as BlockT;
+use std::{path::PathBuf, sync::Arc};
+
+/// The `db migrate` command used to migrate a database between RocksDB and ParityDb.
+#[derive(Debug, Clone, Parser)]
+pub struct DbMigrateCmd {
+	/// Database to migrate to, from the database of the other kind.
+	///
+	/// Migrating to RocksDB only keeps the state of the latest finalized block, like warp sync,
+	/// and neither keeps the blocks that aren't finalized nor the offchain storage.
+	#[clap(
+		long,
+		value_name = "DB",
+		ignore_case = true,
+		possible_values = &["rocksdb", "paritydb"],
+		default_value = "paritydb",
+	)]
+	pub to: Database,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub shared_params: SharedParams,
+}
+
+impl DbMigrateCmd {
+	/// Run the db migrate command, migrating a RocksDB database to ParityDb.
+	///
+	/// Migrating to RocksDB requires the clients of both databases, see
+	/// [`Self::run_to_rocksdb`].
+	pub fn run<B: BlockT>(&self, database_config: DatabaseSource) -> error::Result<()> {
+		if self.to == Database::RocksDb {
+			return Err(error::Error::Input("Use `run_to_rocksdb` to migrate to RocksDB".into()))
+		}
+		let (rocksdb_path, paritydb_path) = match &database_config {
+			DatabaseSource::Auto { rocksdb_path, paritydb_path, .. } =>
+				(rocksdb_path, paritydb_path),
+			_ => return Err(error::Error::Input("Cannot migrate this database".into())),
+		};
+		if !rocksdb_path.exists() {
+			return Err(error::Error::Input(format!(
+				"No RocksDB database found at {}",
+				rocksdb_path.display()
+			)))
+		}
+
+		println!("Migrating {} to {}", rocksdb_path.display(), paritydb_path.display());
+		let migrated =
+			sc_client_db::migration::migrate_to_parity_db::<B>(rocksdb_path, paritydb_path)?;
+		for column in migrated {
+			println!(
+				"Column {}: {} entries, {} references",
+				column.column, column.entries, column.references
+			);
+		}
+		println!(
+			"Migration complete, restart the node with `--database paritydb` to use {}. \
+			 {} can be removed afterwards.",
+			paritydb_path.display(),
+			rocksdb_path.display(),
+		);
+		Ok(())
+	}
+
+	/// Splits `database_config` into the ParityDb database to migrate to RocksDB and the RocksDB
+	/// database to create, and returns them along with the path of the snapshot the migration
+	/// goes through.
+	pub fn rocksdb_migration(
+		&self,
+		database_config: &DatabaseSource,
+	) -> error::Result<(DatabaseSource, DatabaseSource, PathBuf)> {
+		let (rocksdb_path, paritydb_path, cache_size) = match database_config {
+			DatabaseSource::Auto { rocksdb_path, paritydb_path, cache_size } =>
+				(rocksdb_path, paritydb_path, *cache_size),
+			_ => return Err(error::Error::Input("Cannot migrate this database".into())),
+		};
+		if !paritydb_path.join("db_version").exists() {
+			return Err(error::Error::Input(format!(
+				"No ParityDb database found at {}",
+				paritydb_path.display()
+			)))
+		}
+
+		let snapshot_path = paritydb_path.with_file_name("migration-snapshot");
+		Ok((
+			DatabaseSource::ParityDb { path: paritydb_path.clone() },
+			DatabaseSource::RocksDb { path: rocksdb_path.clone(), cache_size },
+			snapshot_path,
+		))
+	}
+
+	/// Run the db migrate command, migrating the ParityDb database of `source_client` to the
+	/// empty RocksDB database of `target_client`.
+	///
+	/// The databases and the snapshot path are given by [`Self::rocksdb_migration`].
+	pub async fn run_to_rocksdb<B, SC, TC, IQ>(
+		&self,
+		source_client: Arc<SC>,
+		source_backend: Arc<sc_client_db::Backend<B>>,
+		target_client: Arc<TC>,
+		target_backend: Arc<sc_client_db::Backend<B>>,
+		import_queue: IQ,
+		snapshot_path: PathBuf,
+	) -> error::Result<()>
+	where
+		B: BlockT,
+		SC: HeaderBackend<B> + ProvideRuntimeApi<B>,
+		SC::Api: Core<B>,
+		TC: HeaderBackend<B> + Send + Sync + 'static,
+		IQ: sc_service::ImportQueue<B> + 'static,
+	{
+		migrate_from_parity_db(
+			source_client,
+			source_backend,
+			target_client,
+			target_backend,
+			import_queue,
+			&snapshot_path,
+		)
+		.await?;
+		println!(
+			"Migration complete, restart the node with `--database rocksdb` to use the migrated \
+			 database. The ParityDb database can be removed afterwards."
+		);
+		Ok(())
+	}
+}
+
+impl CliConfiguration for DbMigrateCmd {
+	fn shared_params(&self) -> &SharedParams {
+		&self.shared_params
+	}
+
+	// Both the source and the target paths are needed.
+	fn database(&self) -> error::Result<Option<Database>> {
+		Ok(Some(Database::Auto))
+	}
+}
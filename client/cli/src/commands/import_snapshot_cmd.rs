@@ -0,0 +1,83 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{
+	error,
+	params::{ImportParams, SharedParams},
+	CliConfiguration,
+};
+use clap::Parser;
+use sc_client_api::HeaderBackend;
+use sc_service::chain_ops::import_snapshot;
+use sp_runtime::traits::Block as BlockT;
+use std::{fmt::Debug, path::PathBuf, str::FromStr, sync::Arc};
+
+/// The `import-snapshot` command used to initialise an empty database from a snapshot.
+#[derive(Debug, Parser)]
+pub struct ImportSnapshotCmd {
+	/// Directory of the snapshot, as created by the `dev_createSnapshot` RPC.
+	#[clap(parse(from_os_str))]
+	pub input: PathBuf,
+
+	/// Hash of the finalized block the snapshot is expected to have been taken at.
+	#[clap(long, value_name = "HASH")]
+	pub finalized_hash: String,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub shared_params: SharedParams,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub import_params: ImportParams,
+}
+
+impl ImportSnapshotCmd {
+	/// Run the import-snapshot command
+	pub async fn run<B, C, IQ>(
+		&self,
+		client: Arc<C>,
+		backend: Arc<sc_client_db::Backend<B>>,
+		import_queue: IQ,
+	) -> error::Result<()>
+	where
+		C: HeaderBackend<B> + Send + Sync + 'static,
+		B: BlockT,
+		B::Hash: FromStr,
+		<B::Hash as FromStr>::Err: Debug,
+		IQ: sc_service::ImportQueue<B> + 'static,
+	{
+		let hash = self.finalized_hash.strip_prefix("0x").unwrap_or(&self.finalized_hash);
+		let finalized_hash =
+			B::Hash::from_str(hash).map_err(|e| format!("Failed to parse block hash: {:?}", e))?;
+
+		import_snapshot(client, backend, import_queue, &self.input, finalized_hash)
+			.await
+			.map_err(Into::into)
+	}
+}
+
+impl CliConfiguration for ImportSnapshotCmd {
+	fn shared_params(&self) -> &SharedParams {
+		&self.shared_params
+	}
+
+	fn import_params(&self) -> Option<&ImportParams> {
+		Some(&self.import_params)
+	}
+}
@@ -18,15 +18,19 @@
 mod build_spec_cmd;
 mod chain_info_cmd;
 mod check_block_cmd;
+mod db;
+mod db_migrate_cmd;
 mod export_blocks_cmd;
 mod export_state_cmd;
 mod generate;
 mod generate_node_key;
 mod import_blocks_cmd;
+mod import_snapshot_cmd;
 mod insert_key;
 mod inspect_key;
 mod inspect_node_key;
 mod key;
+mod precompile_runtime_cmd;
 mod purge_chain_cmd;
 mod revert_cmd;
 mod run_cmd;
@@ -37,9 +41,11 @@ mod verify;
 
 pub use self::{
 	build_spec_cmd::BuildSpecCmd, chain_info_cmd::ChainInfoCmd, check_block_cmd::CheckBlockCmd,
-	export_blocks_cmd::ExportBlocksCmd, export_state_cmd::ExportStateCmd, generate::GenerateCmd,
-	generate_node_key::GenerateNodeKeyCmd, import_blocks_cmd::ImportBlocksCmd,
+	db::DbSubcommand, db_migrate_cmd::DbMigrateCmd, export_blocks_cmd::ExportBlocksCmd,
+	export_state_cmd::ExportStateCmd, generate::GenerateCmd, generate_node_key::GenerateNodeKeyCmd,
+	import_blocks_cmd::ImportBlocksCmd, import_snapshot_cmd::ImportSnapshotCmd,
 	insert_key::InsertKeyCmd, inspect_key::InspectKeyCmd, inspect_node_key::InspectNodeKeyCmd,
-	key::KeySubcommand, purge_chain_cmd::PurgeChainCmd, revert_cmd::RevertCmd, run_cmd::RunCmd,
-	sign::SignCmd, vanity::VanityCmd, verify::VerifyCmd,
+	key::KeySubcommand, precompile_runtime_cmd::PrecompileRuntimeCmd,
+	purge_chain_cmd::PurgeChainCmd, revert_cmd::RevertCmd, run_cmd::RunCmd, sign::SignCmd,
+	vanity::VanityCmd, verify::VerifyCmd,
 };
@@ -0,0 +1,88 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{
+	error,
+	params::{BlockNumberOrHash, ImportParams, SharedParams},
+	CliConfiguration,
+};
+use clap::Parser;
+use log::info;
+use sc_client_api::StorageProvider;
+use sc_service::config::Configuration;
+use sp_runtime::traits::{Block as BlockT, Header as HeaderT};
+use std::{fmt::Debug, str::FromStr, sync::Arc};
+
+/// The `precompile-runtime` command used to compile a runtime ahead of time into an artifact, that
+/// the node loads instead of compiling the runtime itself.
+///
+/// The artifact is stored in the runtime artifacts directory, and is only usable with the same
+/// execution method and instantiation strategy.
+#[derive(Debug, Clone, Parser)]
+pub struct PrecompileRuntimeCmd {
+	/// Block hash or number of which to precompile the runtime.
+	///
+	/// Defaults to the genesis runtime of the chain spec, which doesn't need a database.
+	#[clap(long, value_name = "HASH or NUMBER")]
+	pub at: Option<BlockNumberOrHash>,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub shared_params: SharedParams,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub import_params: ImportParams,
+}
+
+impl PrecompileRuntimeCmd {
+	/// Run the `precompile-runtime` command with the genesis runtime of the chain spec.
+	pub fn run<B: BlockT>(&self, config: &Configuration) -> error::Result<()> {
+		info!("Precompiling the genesis runtime...");
+		let path = sc_service::chain_ops::precompile_genesis_runtime::<B>(config)?;
+		info!("Runtime artifact written to {}", path.display());
+		Ok(())
+	}
+
+	/// Run the `precompile-runtime` command with the runtime at the block given by `--at`.
+	pub async fn run_at<B, BA, C>(&self, client: Arc<C>, config: Configuration) -> error::Result<()>
+	where
+		B: BlockT,
+		C: StorageProvider<B, BA>,
+		BA: sc_client_api::backend::Backend<B>,
+		B::Hash: FromStr,
+		<B::Hash as FromStr>::Err: Debug,
+		<<B::Header as HeaderT>::Number as FromStr>::Err: Debug,
+	{
+		let block = self.at.as_ref().ok_or("No block is given with `--at`")?.parse()?;
+		info!("Precompiling the runtime at block {}...", block);
+		let path = sc_service::chain_ops::precompile_runtime(client, block, &config)?;
+		info!("Runtime artifact written to {}", path.display());
+		Ok(())
+	}
+}
+
+impl CliConfiguration for PrecompileRuntimeCmd {
+	fn shared_params(&self) -> &SharedParams {
+		&self.shared_params
+	}
+
+	fn import_params(&self) -> Option<&ImportParams> {
+		Some(&self.import_params)
+	}
+}
@@ -24,14 +24,14 @@ use crate::{
 };
 use log::warn;
 use names::{Generator, Name};
-use sc_client_api::execution_extensions::ExecutionStrategies;
+use sc_client_api::execution_extensions::{ExecutionBudgets, ExecutionStrategies};
 use sc_service::{
 	config::{
 		BasePath, Configuration, DatabaseSource, KeystoreConfig, NetworkConfiguration,
 		NodeKeyConfig, OffchainWorkerConfig, PrometheusConfig, PruningMode, Role, RpcMethods,
-		TelemetryEndpoints, TransactionPoolOptions, WasmExecutionMethod,
+		SandboxBackend, TelemetryEndpoints, TransactionPoolOptions, WasmExecutionMethod,
 	},
-	ChainSpec, KeepBlocks, TracingReceiver,
+	BlocksPruning, ChainSpec, TracingReceiver,
 };
 use sc_tracing::logging::LoggerBuilder;
 use std::{net::SocketAddr, path::PathBuf};
@@ -42,6 +42,9 @@ pub(crate) const NODE_NAME_MAX_LENGTH: usize = 64;
 /// Default sub directory to store network config.
 pub(crate) const DEFAULT_NETWORK_CONFIG_PATH: &str = "network";
 
+/// Default sub directory to store the precompiled runtime artifacts.
+pub(crate) const DEFAULT_RUNTIME_ARTIFACTS_PATH: &str = "runtime-artifacts";
+
 /// The recommended open file descriptor limit to be configured for the process.
 const RECOMMENDED_OPEN_FILE_DESCRIPTOR_LIMIT: u64 = 10_000;
 
@@ -198,6 +201,30 @@ pub trait CliConfiguration<DCV: DefaultConfigurationValues = ()>: Sized {
 		Ok(self.database_params().map(|x| x.database_cache_size()).unwrap_or_default())
 	}
 
+	/// Whether to keep the latest finalized state in flat storage.
+	///
+	/// By default this is retrieved from `DatabaseParams` if it is available. Otherwise it is
+	/// `false`.
+	fn flat_state(&self) -> Result<bool> {
+		Ok(self.database_params().map(|x| x.flat_state()).unwrap_or_default())
+	}
+
+	/// Whether to index the storage keys changed by each block.
+	///
+	/// By default this is retrieved from `DatabaseParams` if it is available. Otherwise it is
+	/// `false`.
+	fn state_diff_index(&self) -> Result<bool> {
+		Ok(self.database_params().map(|x| x.state_diff_index()).unwrap_or_default())
+	}
+
+	/// Whether to tally the accesses per storage item.
+	///
+	/// By default this is retrieved from `DatabaseParams` if it is available. Otherwise it is
+	/// `false`.
+	fn storage_item_stats(&self) -> Result<bool> {
+		Ok(self.database_params().map(|x| x.storage_item_stats()).unwrap_or_default())
+	}
+
 	/// Get the database backend variant.
 	///
 	/// By default this is retrieved from `DatabaseParams` if it is available. Otherwise its `None`.
@@ -249,7 +276,7 @@ pub trait CliConfiguration<DCV: DefaultConfigurationValues = ()>: Sized {
 
 	/// Get the state pruning mode.
 	///
-	/// By default this is retrieved from `PruningMode` if it is available. Otherwise its
+	/// By default this is retrieved from `PruningMode` if it is available. Otherwise it is
 	/// `PruningMode::default()`.
 	fn state_pruning(&self) -> Result<Option<PruningMode>> {
 		self.pruning_params()
@@ -259,12 +286,12 @@ pub trait CliConfiguration<DCV: DefaultConfigurationValues = ()>: Sized {
 
 	/// Get the block pruning mode.
 	///
-	/// By default this is retrieved from `block_pruning` if it is available. Otherwise its
-	/// `KeepBlocks::All`.
-	fn keep_blocks(&self) -> Result<KeepBlocks> {
+	/// By default this is retrieved from `pruning_params` if it is available. Otherwise all the
+	/// data of all the blocks is kept.
+	fn blocks_pruning(&self) -> Result<BlocksPruning> {
 		self.pruning_params()
-			.map(|x| x.keep_blocks())
-			.unwrap_or_else(|| Ok(KeepBlocks::All))
+			.map(|x| x.blocks_pruning())
+			.unwrap_or_else(|| Ok(BlocksPruning::keep_all()))
 	}
 
 	/// Get the chain ID (string).
@@ -283,7 +310,7 @@ pub trait CliConfiguration<DCV: DefaultConfigurationValues = ()>: Sized {
 
 	/// Get the WASM execution method.
 	///
-	/// By default this is retrieved from `ImportParams` if it is available. Otherwise its
+	/// By default this is retrieved from `ImportParams` if it is available. Otherwise it is
 	/// `WasmExecutionMethod::default()`.
 	fn wasm_method(&self) -> Result<WasmExecutionMethod> {
 		Ok(self.import_params().map(|x| x.wasm_method()).unwrap_or_default())
@@ -296,9 +323,20 @@ pub trait CliConfiguration<DCV: DefaultConfigurationValues = ()>: Sized {
 		self.import_params().map(|x| x.wasm_runtime_overrides()).unwrap_or_default()
 	}
 
+	/// Get the path where the precompiled runtime artifacts live.
+	///
+	/// By default this is retrieved from `ImportParams` if it is available, falling back to the
+	/// `runtime-artifacts` sub directory of `config_dir`. Otherwise it is `None`.
+	fn runtime_artifacts_path(&self, config_dir: &PathBuf) -> Option<PathBuf> {
+		self.import_params().map(|x| {
+			x.runtime_artifacts_path()
+				.unwrap_or_else(|| config_dir.join(DEFAULT_RUNTIME_ARTIFACTS_PATH))
+		})
+	}
+
 	/// Get the execution strategies.
 	///
-	/// By default this is retrieved from `ImportParams` if it is available. Otherwise its
+	/// By default this is retrieved from `ImportParams` if it is available. Otherwise it is
 	/// `ExecutionStrategies::default()`.
 	fn execution_strategies(
 		&self,
@@ -311,6 +349,41 @@ pub trait CliConfiguration<DCV: DefaultConfigurationValues = ()>: Sized {
 			.unwrap_or_default())
 	}
 
+	/// Whether the host functions called by the runtime can be profiled.
+	///
+	/// By default this is retrieved from `ImportParams` if it is available. Otherwise it is
+	/// `false`.
+	fn wasm_host_function_profiling(&self) -> Result<bool> {
+		Ok(self
+			.import_params()
+			.map(|x| x.wasm_host_function_profiling())
+			.unwrap_or_default())
+	}
+
+	/// Get the percentage of the wasm heap above which a runtime call logs a warning.
+	///
+	/// By default this is retrieved from `ImportParams` if it is available. Otherwise there is
+	/// no warning.
+	fn wasm_heap_usage_warning(&self) -> Result<Option<u8>> {
+		Ok(self.import_params().and_then(|x| x.wasm_heap_usage_warning()))
+	}
+
+	/// Get the backend of the sandboxes created by the runtime.
+	///
+	/// By default this is retrieved from `ImportParams` if it is available. Otherwise the
+	/// execution method picks it.
+	fn wasm_sandbox_backend(&self) -> Result<Option<SandboxBackend>> {
+		Ok(self.import_params().and_then(|x| x.wasm_sandbox_backend()))
+	}
+
+	/// Get the fuel budgets of the runtime calls.
+	///
+	/// By default this is retrieved from `ImportParams` if it is available. Otherwise no call is
+	/// metered.
+	fn execution_budgets(&self) -> Result<ExecutionBudgets> {
+		Ok(self.import_params().map(|x| x.execution_budgets()).unwrap_or_default())
+	}
+
 	/// Get the RPC HTTP address (`None` if disabled).
 	///
 	/// By default this is `None`.
@@ -439,7 +512,7 @@ pub trait CliConfiguration<DCV: DefaultConfigurationValues = ()>: Sized {
 
 	/// Get the tracing targets from the current object (if any)
 	///
-	/// By default this is retrieved from [`SharedParams`] if it is available. Otherwise its
+	/// By default this is retrieved from [`SharedParams`] if it is available. Otherwise it is
 	/// `None`.
 	fn tracing_targets(&self) -> Result<Option<String>> {
 		Ok(self.shared_params().tracing_targets())
@@ -447,7 +520,7 @@ pub trait CliConfiguration<DCV: DefaultConfigurationValues = ()>: Sized {
 
 	/// Get the TracingReceiver value from the current object
 	///
-	/// By default this is retrieved from [`SharedParams`] if it is available. Otherwise its
+	/// By default this is retrieved from [`SharedParams`] if it is available. Otherwise it is
 	/// `TracingReceiver::default()`.
 	fn tracing_receiver(&self) -> Result<TracingReceiver> {
 		Ok(self.shared_params().tracing_receiver())
@@ -455,7 +528,7 @@ pub trait CliConfiguration<DCV: DefaultConfigurationValues = ()>: Sized {
 
 	/// Get the node key from the current object
 	///
-	/// By default this is retrieved from `NodeKeyParams` if it is available. Otherwise its
+	/// By default this is retrieved from `NodeKeyParams` if it is available. Otherwise it is
 	/// `NodeKeyConfig::default()`.
 	fn node_key(&self, net_config_dir: &PathBuf) -> Result<NodeKeyConfig> {
 		self.node_key_params()
@@ -530,10 +603,18 @@ pub trait CliConfiguration<DCV: DefaultConfigurationValues = ()>: Sized {
 			state_cache_size: self.state_cache_size()?,
 			state_cache_child_ratio: self.state_cache_child_ratio()?,
 			state_pruning: self.state_pruning()?,
-			keep_blocks: self.keep_blocks()?,
+			blocks_pruning: self.blocks_pruning()?,
+			flat_state: self.flat_state()?,
+			state_diff_index: self.state_diff_index()?,
+			storage_item_stats: self.storage_item_stats()?,
 			wasm_method: self.wasm_method()?,
 			wasm_runtime_overrides: self.wasm_runtime_overrides(),
+			runtime_artifacts_path: self.runtime_artifacts_path(&config_dir),
+			wasm_host_function_profiling: self.wasm_host_function_profiling()?,
+			wasm_heap_usage_warning: self.wasm_heap_usage_warning()?,
+			wasm_sandbox_backend: self.wasm_sandbox_backend()?,
 			execution_strategies: self.execution_strategies(is_dev, is_validator)?,
+			execution_budgets: self.execution_budgets()?,
 			rpc_http: self.rpc_http(DCV::rpc_http_listen_port())?,
 			rpc_ws: self.rpc_ws(DCV::rpc_ws_listen_port())?,
 			rpc_ipc: self.rpc_ipc()?,
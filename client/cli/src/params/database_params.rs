@@ -35,6 +35,29 @@ pub struct DatabaseParams {
 	/// Limit the memory the database cache can use.
 	#[clap(long = "db-cache", value_name = "MiB")]
 	pub database_cache_size: Option<usize>,
+
+	/// Keep the values of the latest finalized state in a flat key-value layout.
+	///
+	/// Storage reads of the recent blocks take a single database lookup instead of a lookup per
+	/// trie node. The flat state is built from the trie when first enabled, which can take a while
+	/// on large chains.
+	#[clap(long)]
+	pub flat_state: bool,
+
+	/// Index the storage keys changed by each block.
+	///
+	/// Historical storage queries, like `state_queryStorage` and `state_keyHistory`, then only
+	/// read the blocks where the requested keys have changed. Only the blocks imported while the
+	/// index is enabled are indexed.
+	#[clap(long)]
+	pub state_diff_index: bool,
+
+	/// Tally the accesses per storage item.
+	///
+	/// The accesses are exposed by the `dev_storageStats` RPC and the Prometheus metrics. Tallying
+	/// them has a cost on every storage access.
+	#[clap(long)]
+	pub storage_item_stats: bool,
 }
 
 impl DatabaseParams {
@@ -47,4 +70,19 @@ impl DatabaseParams {
 	pub fn database_cache_size(&self) -> Option<usize> {
 		self.database_cache_size
 	}
+
+	/// Whether to keep the latest finalized state in flat storage.
+	pub fn flat_state(&self) -> bool {
+		self.flat_state
+	}
+
+	/// Whether to index the storage keys changed by each block.
+	pub fn state_diff_index(&self) -> bool {
+		self.state_diff_index
+	}
+
+	/// Whether to tally the accesses per storage item.
+	pub fn storage_item_stats(&self) -> bool {
+		self.storage_item_stats
+	}
 }
@@ -18,7 +18,7 @@
 
 use crate::{
 	arg_enums::{
-		ExecutionStrategy, WasmExecutionMethod, WasmtimeInstantiationStrategy,
+		ExecutionStrategy, WasmExecutionMethod, WasmSandboxBackend, WasmtimeInstantiationStrategy,
 		DEFAULT_EXECUTION_BLOCK_CONSTRUCTION, DEFAULT_EXECUTION_IMPORT_BLOCK,
 		DEFAULT_EXECUTION_IMPORT_BLOCK_VALIDATOR, DEFAULT_EXECUTION_OFFCHAIN_WORKER,
 		DEFAULT_EXECUTION_OTHER, DEFAULT_EXECUTION_SYNCING,
@@ -27,7 +27,7 @@ use crate::{
 	params::{DatabaseParams, PruningParams},
 };
 use clap::Args;
-use sc_client_api::execution_extensions::ExecutionStrategies;
+use sc_client_api::execution_extensions::{ExecutionBudgets, ExecutionStrategies};
 use std::path::PathBuf;
 
 /// Parameters for block import.
@@ -90,10 +90,51 @@ pub struct ImportParams {
 	#[clap(long, value_name = "PATH", parse(from_os_str))]
 	pub wasm_runtime_overrides: Option<PathBuf>,
 
+	/// Specify the path where the precompiled runtime artifacts are stored.
+	///
+	/// With the `compiled` execution method, the runtimes are loaded from these artifacts, and
+	/// the artifacts of new runtimes are stored there. Defaults to the `runtime-artifacts`
+	/// directory in the chain's configuration directory.
+	///
+	/// The artifacts hold native code that the node executes, so the directory must only be
+	/// writable by the node and trusted operators.
+	#[clap(long, value_name = "PATH", parse(from_os_str))]
+	pub runtime_artifacts_path: Option<PathBuf>,
+
+	/// Allow profiling the host functions called by the runtime with the `state_profileBlock`
+	/// RPC.
+	///
+	/// With the `compiled` execution method this slows down every host function call.
+	#[clap(long)]
+	pub wasm_host_function_profiling: bool,
+
+	/// Log a warning when a block uses more than this percentage of the wasm heap.
+	///
+	/// A built block is checked with the highest usage of the calls building it. A call fails once
+	/// it used the whole heap. A value of 100 disables the warning.
+	#[clap(long, value_name = "PERCENT", default_value = "80")]
+	pub wasm_heap_usage_warning: u8,
+
+	/// The backend of the sandboxes created by the runtime, e.g. to execute contracts.
+	///
+	/// By default the `interpreted-i-know-what-i-do` execution method uses `wasmi` and the
+	/// `compiled` one `wasmer` if enabled at compile time, falling back to `wasmi`.
+	#[clap(
+		long,
+		value_name = "BACKEND",
+		possible_values = WasmSandboxBackend::variants(),
+		ignore_case = true,
+	)]
+	pub wasm_sandbox_backend: Option<WasmSandboxBackend>,
+
 	#[allow(missing_docs)]
 	#[clap(flatten)]
 	pub execution_strategies: ExecutionStrategiesParams,
 
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub execution_budgets: ExecutionBudgetsParams,
+
 	/// Specify the state cache size.
 	#[clap(long, value_name = "Bytes", default_value = "67108864")]
 	pub state_cache_size: usize,
@@ -116,6 +157,37 @@ impl ImportParams {
 		self.wasm_runtime_overrides.clone()
 	}
 
+	/// Get the path where the precompiled runtime artifacts are stored, if specified.
+	pub fn runtime_artifacts_path(&self) -> Option<PathBuf> {
+		self.runtime_artifacts_path.clone()
+	}
+
+	/// Whether the host functions called by the runtime can be profiled.
+	pub fn wasm_host_function_profiling(&self) -> bool {
+		self.wasm_host_function_profiling
+	}
+
+	/// Percentage of the wasm heap above which a runtime call logs a warning, if any.
+	pub fn wasm_heap_usage_warning(&self) -> Option<u8> {
+		(self.wasm_heap_usage_warning < 100).then(|| self.wasm_heap_usage_warning)
+	}
+
+	/// The backend of the sandboxes created by the runtime, if not the default.
+	pub fn wasm_sandbox_backend(&self) -> Option<sc_service::config::SandboxBackend> {
+		self.wasm_sandbox_backend.map(Into::into)
+	}
+
+	/// Get the fuel budgets of the runtime calls for the parameters.
+	pub fn execution_budgets(&self) -> ExecutionBudgets {
+		let budgets = &self.execution_budgets;
+		ExecutionBudgets {
+			block_construction: budgets.fuel_block_construction,
+			transaction_validation: budgets.fuel_transaction_validation,
+			offchain_worker: budgets.fuel_offchain_worker,
+			other: budgets.fuel_other,
+		}
+	}
+
 	/// Get execution strategies for the parameters
 	pub fn execution_strategies(&self, is_dev: bool, is_validator: bool) -> ExecutionStrategies {
 		let exec = &self.execution_strategies;
@@ -189,3 +261,28 @@ pub struct ExecutionStrategiesParams {
 	)]
 	pub execution: Option<ExecutionStrategy>,
 }
+
+/// Fuel budgets parameters.
+///
+/// Runtime calls executed in wasm are aborted once they consume their budget, which bounds the
+/// time a buggy runtime can hold on to a thread. Enabling any budget makes the executor meter all
+/// of its wasm execution, which slows it down. Imported blocks are never limited, as every node
+/// must execute them alike.
+#[derive(Debug, Clone, Args)]
+pub struct ExecutionBudgetsParams {
+	/// The fuel budget of a runtime call while constructing blocks.
+	#[clap(long, value_name = "FUEL")]
+	pub fuel_block_construction: Option<u64>,
+
+	/// The fuel budget of a runtime call while validating transactions for the pool.
+	#[clap(long, value_name = "FUEL")]
+	pub fuel_transaction_validation: Option<u64>,
+
+	/// The fuel budget of a runtime call while running an off-chain worker.
+	#[clap(long, value_name = "FUEL")]
+	pub fuel_offchain_worker: Option<u64>,
+
+	/// The fuel budget of a runtime call in other cases, e.g. the `state_call` RPC.
+	#[clap(long, value_name = "FUEL")]
+	pub fuel_other: Option<u64>,
+}
@@ -20,7 +20,8 @@ use crate::{arg_enums::SyncMode, params::node_key_params::NodeKeyParams};
 use clap::Args;
 use sc_network::{
 	config::{
-		NetworkConfiguration, NodeKeyConfig, NonReservedPeerMode, SetConfig, TransportConfig,
+		IpLimits, NetworkConfiguration, NodeKeyConfig, NonReservedPeerMode, SetConfig,
+		TransportConfig, WebSocketTlsConfig,
 	},
 	multiaddr::Protocol,
 };
@@ -69,6 +70,20 @@ pub struct NetworkParams {
 	#[clap(long, value_name = "PORT", conflicts_with_all = &[ "listen-addr" ])]
 	pub port: Option<u16>,
 
+	/// PEM file with the TLS certificate chain to accept connections on `/wss` listen addresses.
+	///
+	/// Browsers on HTTPS pages can only connect to `/wss` addresses with a certificate valid for
+	/// the domain name they dial, thus `--public-addr /dns/<domain>/tcp/<port>/wss` should be
+	/// passed as well. The file is reloaded when it changes.
+	#[clap(long, value_name = "PATH", requires = "wss-private-key")]
+	pub wss_certificate: Option<PathBuf>,
+
+	/// PEM file with the private key of the certificate passed with `--wss-certificate`.
+	///
+	/// The file is reloaded when it changes.
+	#[clap(long, value_name = "PATH", requires = "wss-certificate")]
+	pub wss_private_key: Option<PathBuf>,
+
 	/// Always forbid connecting to private IPv4 addresses (as specified in
 	/// [RFC1918](https://tools.ietf.org/html/rfc1918)), unless the address was passed with
 	/// `--reserved-nodes` or `--bootnodes`. Enabled by default for chains marked as "live" in
@@ -93,6 +108,30 @@ pub struct NetworkParams {
 	#[clap(long, value_name = "COUNT", default_value = "100")]
 	pub in_peers_light: u32,
 
+	/// Maximum number of inbound peers sharing an IP address.
+	///
+	/// Reserved nodes and peers connecting over a loopback or private address are not limited.
+	#[clap(long, value_name = "COUNT", default_value = "4")]
+	pub max_inbound_peers_per_ip: u32,
+
+	/// Maximum number of inbound peers sharing a subnet (/24 for IPv4, /48 for IPv6).
+	///
+	/// Reserved nodes and peers connecting over a loopback or private address are not limited.
+	#[clap(long, value_name = "COUNT", default_value = "8")]
+	pub max_inbound_peers_per_subnet: u32,
+
+	/// Maximum number of outbound peers sharing an IP address.
+	///
+	/// Reserved nodes and peers connected over a loopback or private address are not limited.
+	#[clap(long, value_name = "COUNT", default_value = "2")]
+	pub max_outbound_peers_per_ip: u32,
+
+	/// Maximum number of outbound peers sharing a subnet (/24 for IPv4, /48 for IPv6).
+	///
+	/// Reserved nodes and peers connected over a loopback or private address are not limited.
+	#[clap(long, value_name = "COUNT", default_value = "4")]
+	pub max_outbound_peers_per_subnet: u32,
+
 	/// Disable mDNS discovery.
 	///
 	/// By default, the network will use mDNS to discover other nodes on the
@@ -225,6 +264,12 @@ impl NetworkParams {
 			listen_addresses,
 			public_addresses,
 			extra_sets: Vec::new(),
+			ip_limits: IpLimits {
+				max_inbound_per_ip: self.max_inbound_peers_per_ip,
+				max_outbound_per_ip: self.max_outbound_peers_per_ip,
+				max_inbound_per_subnet: self.max_inbound_peers_per_subnet,
+				max_outbound_per_subnet: self.max_outbound_peers_per_subnet,
+			},
 			request_response_protocols: Vec::new(),
 			node_key,
 			node_name: node_name.to_string(),
@@ -232,6 +277,9 @@ impl NetworkParams {
 			transport: TransportConfig::Normal {
 				enable_mdns: !is_dev && !self.no_mdns,
 				allow_private_ipv4,
+				websocket_tls: self.wss_certificate.clone().zip(self.wss_private_key.clone()).map(
+					|(certificate, private_key)| WebSocketTlsConfig { certificate, private_key },
+				),
 			},
 			max_parallel_downloads: self.max_parallel_downloads,
 			enable_dht_random_walk: !self.reserved_only,
@@ -294,4 +342,21 @@ mod tests {
 
 		assert_eq!(SyncMode::Warp, params.network_params.sync);
 	}
+
+	#[test]
+	fn wss_certificate_requires_private_key() {
+		assert!(Cli::try_parse_from(["", "--wss-certificate", "cert.pem"]).is_err());
+
+		let params = Cli::try_parse_from([
+			"",
+			"--wss-certificate",
+			"cert.pem",
+			"--wss-private-key",
+			"key.pem",
+		])
+		.expect("Parses network params");
+
+		assert_eq!(Some(PathBuf::from("cert.pem")), params.network_params.wss_certificate);
+		assert_eq!(Some(PathBuf::from("key.pem")), params.network_params.wss_private_key);
+	}
 }
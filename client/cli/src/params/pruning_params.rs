@@ -18,7 +18,8 @@
 
 use crate::error;
 use clap::Args;
-use sc_service::{KeepBlocks, PruningMode};
+use sc_service::{BlocksPruning, KeepBlocks, KeepJustifications, PruningConstraints, PruningMode};
+use std::time::Duration;
 
 /// Parameters to define the pruning mode
 #[derive(Debug, Clone, PartialEq, Args)]
@@ -30,33 +31,123 @@ pub struct PruningParams {
 	/// or for all of the canonical blocks (i.e 'archive-canonical').
 	#[clap(long, value_name = "PRUNING_MODE")]
 	pub pruning: Option<String>,
-	/// Specify the number of finalized blocks to keep in the database.
+	/// Specify the maximum age of the state kept in the database, e.g. '7d', '12h' or '3600s'.
 	///
-	/// Default is to keep all blocks.
+	/// The age is measured using the block timestamps, read from the `Now` value of the timestamp
+	/// pallet. The node refuses to start if the finalized state doesn't have it. Combined with a
+	/// number of blocks given to `--pruning`, the state is pruned as soon as either limit is
+	/// exceeded.
+	#[clap(long, value_name = "DURATION", parse(try_from_str = parse_duration))]
+	pub pruning_max_age: Option<Duration>,
+	/// Specify the maximum memory in MiB used by the pruning journals.
+	///
+	/// Older state is pruned as needed to stay within the budget.
+	#[clap(long, value_name = "MiB")]
+	pub pruning_max_mem: Option<usize>,
+	/// Specify the number of finalized blocks to keep the bodies of in the database.
+	///
+	/// Default is to keep all blocks. The retention is a number of blocks rather than a duration:
+	/// to keep the bodies for 30 days on a chain producing a block every 6 seconds, specify
+	/// 432000. Also available as `--keep-blocks`.
+	#[clap(long, alias = "keep-blocks", value_name = "COUNT")]
+	pub keep_bodies: Option<u32>,
+	/// Specify the number of finalized blocks to keep the headers of in the database.
+	///
+	/// Default is to keep all headers. The genesis header is always kept.
+	#[clap(long, value_name = "COUNT")]
+	pub keep_headers: Option<u32>,
+	/// Specify the number of finalized blocks to keep the indexed transactions of in the
+	/// database.
+	///
+	/// Default is to keep them as long as the block bodies. They are never kept for longer.
 	#[clap(long, value_name = "COUNT")]
-	pub keep_blocks: Option<u32>,
+	pub keep_indexed_transactions: Option<u32>,
+	/// Specify the justifications to keep in the database, a number of finalized blocks to keep
+	/// the justifications of or 'authority-set-changes'.
+	///
+	/// Default is to keep all justifications. With 'authority-set-changes', only the
+	/// justifications of the last finalized block and of the blocks enacting a change of GRANDPA
+	/// authority set are kept.
+	#[clap(long, value_name = "JUSTIFICATIONS_PRUNING_MODE")]
+	pub keep_justifications: Option<String>,
 }
 
 impl PruningParams {
 	/// Get the pruning value from the parameters
 	pub fn state_pruning(&self) -> error::Result<Option<PruningMode>> {
-		self.pruning
+		let max_mem = self.pruning_max_mem.map(|mib| mib.saturating_mul(1024 * 1024));
+		let max_age = self.pruning_max_age;
+		let max_blocks = match self.pruning.as_ref().map(|s| s.as_str()) {
+			Some("archive") if max_mem.is_some() || max_age.is_some() =>
+				return Err(error::Error::Input(
+					"Pruning limits can't be specified with the archive pruning mode".into(),
+				)),
+			Some("archive") => return Ok(Some(PruningMode::ArchiveAll)),
+			Some(bc) =>
+				Some(bc.parse().map_err(|_| {
+					error::Error::Input("Invalid pruning mode specified".to_string())
+				})?),
+			None if max_mem.is_none() && max_age.is_none() => return Ok(None),
+			None => None,
+		};
+
+		Ok(Some(PruningMode::Constrained(PruningConstraints { max_blocks, max_mem, max_age })))
+	}
+
+	/// Get the block pruning value from the parameters
+	pub fn blocks_pruning(&self) -> error::Result<BlocksPruning> {
+		let keep = |count: Option<u32>| count.map_or(KeepBlocks::All, KeepBlocks::Some);
+		let bodies = keep(self.keep_bodies);
+		let justifications = self
+			.keep_justifications
 			.as_ref()
 			.map(|s| match s.as_str() {
-				"archive" => Ok(PruningMode::ArchiveAll),
-				bc => bc
+				"authority-set-changes" => Ok(KeepJustifications::AuthoritySetChanges),
+				count => count
 					.parse()
-					.map_err(|_| error::Error::Input("Invalid pruning mode specified".to_string()))
-					.map(PruningMode::keep_blocks),
+					.map_err(|_| {
+						error::Error::Input("Invalid justifications pruning mode specified".into())
+					})
+					.map(KeepJustifications::Some),
 			})
-			.transpose()
-	}
+			.transpose()?
+			.unwrap_or(KeepJustifications::All);
 
-	/// Get the block pruning value from the parameters
-	pub fn keep_blocks(&self) -> error::Result<KeepBlocks> {
-		Ok(match self.keep_blocks {
-			Some(n) => KeepBlocks::Some(n),
-			None => KeepBlocks::All,
+		Ok(BlocksPruning {
+			headers: keep(self.keep_headers),
+			bodies,
+			indexed_transactions: self.keep_indexed_transactions.map_or(bodies, KeepBlocks::Some),
+			justifications,
 		})
 	}
 }
+
+/// Parses a duration given in seconds, or with an `s`, `m`, `h` or `d` suffix.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+	let (value, unit) = match s.char_indices().last() {
+		Some((i, 's')) => (&s[..i], 1),
+		Some((i, 'm')) => (&s[..i], 60),
+		Some((i, 'h')) => (&s[..i], 60 * 60),
+		Some((i, 'd')) => (&s[..i], 24 * 60 * 60),
+		_ => (s, 1),
+	};
+	value
+		.parse::<u64>()
+		.map(|v| Duration::from_secs(v.saturating_mul(unit)))
+		.map_err(|_| format!("Invalid duration: {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_durations() {
+		assert_eq!(parse_duration("3600"), Ok(Duration::from_secs(3600)));
+		assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+		assert_eq!(parse_duration("12h"), Ok(Duration::from_secs(12 * 3600)));
+		assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(7 * 24 * 3600)));
+		assert!(parse_duration("d").is_err());
+		assert!(parse_duration("7w").is_err());
+	}
+}
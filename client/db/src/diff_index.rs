@@ -0,0 +1,322 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Index of the state changes.
+//!
+//! The top-level storage keys changed by each imported block are stored in the `STATE_DIFF`
+//! column, keyed by block hash. Blocks whose state is set from scratch, like the genesis block or
+//! the blocks imported by state sync, aren't indexed.
+//!
+//! Once finalized, the changes are also linked per key: for each key, the index stores the number
+//! of the last finalized block that changed it and, for each change, the number of the previous
+//! one. This allows listing the finalized blocks changing a key without going through the other
+//! blocks. The links are only complete from the block the index has been enabled at, or from the
+//! last block that wasn't indexed. Each such restart begins a new generation of links.
+
+use crate::{columns, utils::meta_keys, DbHash};
+use codec::{Decode, Encode};
+use log::debug;
+use parking_lot::RwLock;
+use sp_blockchain::{Error as ClientError, Result as ClientResult};
+use sp_core::hashing::blake2_256;
+use sp_database::{Database, Transaction};
+use sp_state_machine::StorageCollection;
+use std::{collections::HashMap, sync::Arc};
+
+/// Prefix of the keys changed by a block.
+const BLOCK_PREFIX: u8 = b'b';
+/// Prefix of the last finalized change of a key.
+const LAST_CHANGE_PREFIX: u8 = b'l';
+/// Prefix of the change of a key preceding a given one.
+const PREVIOUS_CHANGE_PREFIX: u8 = b'p';
+
+/// Maximum number of blocks linked in a single transaction.
+const LINK_BATCH_SIZE: u64 = 1_000;
+
+/// Last finalized block whose changes are linked.
+#[derive(Debug, Clone, Encode, Decode)]
+struct DiffHead<Hash> {
+	/// Generation of the links.
+	generation: u32,
+	/// First block whose changes are linked in this generation.
+	start: u64,
+	hash: Hash,
+	number: u64,
+}
+
+fn block_key<Hash: AsRef<[u8]>>(hash: &Hash) -> Vec<u8> {
+	let mut key = vec![BLOCK_PREFIX];
+	key.extend_from_slice(hash.as_ref());
+	key
+}
+
+fn last_change_key(generation: u32, key_hash: &[u8; 32]) -> Vec<u8> {
+	let mut key = Vec::with_capacity(1 + 4 + 32);
+	key.push(LAST_CHANGE_PREFIX);
+	key.extend_from_slice(&generation.to_be_bytes());
+	key.extend_from_slice(key_hash);
+	key
+}
+
+fn previous_change_key(generation: u32, key_hash: &[u8; 32], number: u64) -> Vec<u8> {
+	let mut key = Vec::with_capacity(1 + 4 + 32 + 8);
+	key.push(PREVIOUS_CHANGE_PREFIX);
+	key.extend_from_slice(&generation.to_be_bytes());
+	key.extend_from_slice(key_hash);
+	key.extend_from_slice(&number.to_be_bytes());
+	key
+}
+
+fn decode<T: Decode>(value: Option<Vec<u8>>) -> ClientResult<Option<T>> {
+	value
+		.map(|value| T::decode(&mut &value[..]))
+		.transpose()
+		.map_err(|e| ClientError::Backend(format!("Invalid state diff index entry: {}", e)))
+}
+
+/// Index of the keys changed by each block.
+pub(crate) struct DiffIndex<Hash> {
+	db: Arc<dyn Database<DbHash>>,
+	head: RwLock<Option<DiffHead<Hash>>>,
+}
+
+impl<Hash: Copy + Eq + AsRef<[u8]> + Encode + Decode + std::fmt::Debug> DiffIndex<Hash> {
+	/// Loads the index.
+	pub fn open(db: Arc<dyn Database<DbHash>>) -> ClientResult<Self> {
+		let head = decode(db.get(columns::META, meta_keys::STATE_DIFF))?;
+		Ok(DiffIndex { db, head: RwLock::new(head) })
+	}
+
+	/// Stores the keys changed by the block `hash`.
+	///
+	/// `reset` tells that the state of the block doesn't derive from the one of its parent, in
+	/// which case the block isn't indexed.
+	pub fn note_block(
+		&self,
+		transaction: &mut Transaction<DbHash>,
+		hash: &Hash,
+		reset: bool,
+		storage_updates: &StorageCollection,
+	) {
+		if reset {
+			return
+		}
+		let mut keys: Vec<&Vec<u8>> = storage_updates.iter().map(|(key, _)| key).collect();
+		keys.sort();
+		keys.dedup();
+		transaction.set_from_vec(columns::STATE_DIFF, &block_key(hash), keys.encode());
+	}
+
+	/// Forgets the keys changed by the block `hash`.
+	pub fn remove_block(&self, transaction: &mut Transaction<DbHash>, hash: &Hash) {
+		transaction.remove(columns::STATE_DIFF, &block_key(hash));
+	}
+
+	/// Returns the keys changed by the block `hash`, sorted, or `None` if it isn't indexed.
+	pub fn changed_keys(&self, hash: &Hash) -> ClientResult<Option<Vec<Vec<u8>>>> {
+		decode(self.db.get(columns::STATE_DIFF, &block_key(hash)))
+	}
+
+	/// Returns the last finalized block whose changes are linked, if any.
+	pub fn linked_head(&self) -> Option<(Hash, u64)> {
+		self.head.read().as_ref().map(|head| (head.hash, head.number))
+	}
+
+	/// Links the changes of the finalized blocks up to `number`.
+	///
+	/// `canonical_hash` returns the hash of the finalized block with the given number.
+	pub fn advance(
+		&self,
+		hash: Hash,
+		number: u64,
+		canonical_hash: impl Fn(u64) -> ClientResult<Option<Hash>>,
+	) -> ClientResult<()> {
+		let mut head = match self.head.read().clone() {
+			Some(head) if head.number == number && head.hash == hash => return Ok(()),
+			// The linked blocks must still be finalized, which isn't the case after a revert.
+			Some(head)
+				if head.number < number && canonical_hash(head.number)? == Some(head.hash) =>
+				head,
+			head => {
+				let generation = head.map_or(0, |head| head.generation + 1);
+				debug!(
+					target: "db",
+					"Starting generation {} of the state diff index at block #{}",
+					generation,
+					number + 1,
+				);
+				let head = DiffHead { generation, start: number + 1, hash, number };
+				let mut transaction = Transaction::new();
+				transaction.set_from_vec(columns::META, meta_keys::STATE_DIFF, head.encode());
+				return self.commit(transaction, head)
+			},
+		};
+
+		let mut transaction = Transaction::new();
+		let mut last_changes = HashMap::new();
+		while head.number < number {
+			let next = head.number + 1;
+			let next_hash = canonical_hash(next)?.ok_or_else(|| {
+				ClientError::Backend(format!("Missing hash of finalized block #{}", next))
+			})?;
+			match self.changed_keys(&next_hash)? {
+				Some(keys) =>
+					for key in keys {
+						let key_hash = blake2_256(&key);
+						let previous: Option<u64> = match last_changes.get(&key_hash) {
+							Some(previous) => Some(*previous),
+							None => decode(self.db.get(
+								columns::STATE_DIFF,
+								&last_change_key(head.generation, &key_hash),
+							))?,
+						};
+						transaction.set_from_vec(
+							columns::STATE_DIFF,
+							&previous_change_key(head.generation, &key_hash, next),
+							previous.encode(),
+						);
+						last_changes.insert(key_hash, next);
+					},
+				None => {
+					// The changes of the block are unknown, the links start over after it.
+					last_changes.clear();
+					transaction = Transaction::new();
+					head.generation += 1;
+					head.start = next + 1;
+				},
+			}
+			head.hash = next_hash;
+			head.number = next;
+
+			if head.number == number || head.number % LINK_BATCH_SIZE == 0 {
+				for (key_hash, last) in last_changes.drain() {
+					transaction.set_from_vec(
+						columns::STATE_DIFF,
+						&last_change_key(head.generation, &key_hash),
+						last.encode(),
+					);
+				}
+				transaction.set_from_vec(columns::META, meta_keys::STATE_DIFF, head.encode());
+				self.commit(std::mem::take(&mut transaction), head.clone())?;
+			}
+		}
+		Ok(())
+	}
+
+	fn commit(&self, transaction: Transaction<DbHash>, head: DiffHead<Hash>) -> ClientResult<()> {
+		let mut current = self.head.write();
+		self.db.commit(transaction)?;
+		*current = Some(head);
+		Ok(())
+	}
+
+	/// Returns the numbers of the linked finalized blocks from `first` to `last` that changed
+	/// `key`, in ascending order.
+	///
+	/// Returns `None` if the changes of some of these blocks aren't linked.
+	pub fn finalized_changes(
+		&self,
+		key: &[u8],
+		first: u64,
+		last: u64,
+	) -> ClientResult<Option<Vec<u64>>> {
+		// The head is locked so that the links aren't extended while they are followed.
+		let head = self.head.read();
+		let head = match head.as_ref() {
+			Some(head) if head.start <= first && last <= head.number => head,
+			_ => return Ok(None),
+		};
+		let key_hash = blake2_256(key);
+		let previous = |number: u64| -> ClientResult<Option<u64>> {
+			let key = previous_change_key(head.generation, &key_hash, number);
+			decode::<Option<u64>>(self.db.get(columns::STATE_DIFF, &key))
+				.map(|previous| previous.flatten())
+		};
+
+		let mut changes = Vec::new();
+		let mut current: Option<u64> =
+			decode(self.db.get(columns::STATE_DIFF, &last_change_key(head.generation, &key_hash)))?;
+		while let Some(number) = current {
+			if number < first {
+				break
+			}
+			if number <= last {
+				changes.push(number);
+			}
+			current = previous(number)?;
+		}
+		changes.reverse();
+		Ok(Some(changes))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sp_core::H256;
+
+	fn index_with_blocks(blocks: &[&[&[u8]]]) -> (DiffIndex<H256>, Vec<H256>) {
+		let db = Arc::new(sp_database::MemDb::default());
+		let index = DiffIndex::<H256>::open(db.clone()).unwrap();
+		let mut transaction = Transaction::new();
+		let hashes: Vec<_> = (0..blocks.len()).map(|n| H256::from_low_u64_be(n as u64)).collect();
+		for (n, keys) in blocks.iter().enumerate() {
+			let updates = keys.iter().map(|key| (key.to_vec(), Some(vec![n as u8]))).collect();
+			index.note_block(&mut transaction, &hashes[n], n == 0, &updates);
+		}
+		db.commit(transaction).unwrap();
+		(index, hashes)
+	}
+
+	#[test]
+	fn links_finalized_changes() {
+		let (index, hashes) =
+			index_with_blocks(&[&[b"a", b"b"], &[b"a"], &[b"b"], &[b"a", b"b"], &[], &[b"a"]]);
+		let canonical = |n: u64| Ok(hashes.get(n as usize).cloned());
+		assert_eq!(index.changed_keys(&hashes[0]).unwrap(), None);
+		assert_eq!(
+			index.changed_keys(&hashes[3]).unwrap(),
+			Some(vec![b"a".to_vec(), b"b".to_vec()])
+		);
+
+		index.advance(hashes[0], 0, canonical).unwrap();
+		assert_eq!(index.finalized_changes(b"a", 1, 1).unwrap(), None);
+		index.advance(hashes[3], 3, canonical).unwrap();
+		index.advance(hashes[5], 5, canonical).unwrap();
+		assert_eq!(index.linked_head(), Some((hashes[5], 5)));
+		assert_eq!(index.finalized_changes(b"a", 1, 5).unwrap(), Some(vec![1, 3, 5]));
+		assert_eq!(index.finalized_changes(b"a", 2, 4).unwrap(), Some(vec![3]));
+		assert_eq!(index.finalized_changes(b"b", 1, 5).unwrap(), Some(vec![2, 3]));
+		assert_eq!(index.finalized_changes(b"c", 1, 5).unwrap(), Some(vec![]));
+		assert_eq!(index.finalized_changes(b"a", 0, 5).unwrap(), None);
+	}
+
+	#[test]
+	fn unindexed_block_restarts_links() {
+		let (index, hashes) = index_with_blocks(&[&[], &[b"a"], &[b"a"], &[b"a"]]);
+		let mut transaction = Transaction::new();
+		index.remove_block(&mut transaction, &hashes[2]);
+		index.db.commit(transaction).unwrap();
+
+		let canonical = |n: u64| Ok(hashes.get(n as usize).cloned());
+		index.advance(hashes[0], 0, canonical).unwrap();
+		index.advance(hashes[3], 3, canonical).unwrap();
+		assert_eq!(index.finalized_changes(b"a", 1, 3).unwrap(), None);
+		assert_eq!(index.finalized_changes(b"a", 3, 3).unwrap(), Some(vec![3]));
+	}
+}
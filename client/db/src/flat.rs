@@ -0,0 +1,698 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Flat storage of the state.
+//!
+//! The values of the state of the last finalized block are stored in the `FLAT_STATE` column,
+//! keyed by their storage key, so that reading a value takes a single database lookup instead of
+//! a lookup per trie node. The changes made by the blocks that aren't finalized yet are kept as
+//! deltas, which are applied to the flat state when the blocks get finalized.
+//!
+//! The state of a block is read from the flat state when the block is the last finalized one, or
+//! when the deltas of all its ancestors up to the last finalized one are known. Other reads, along
+//! with the storage roots, the iterations and the proofs, go through the trie.
+//!
+//! The flat state is rebuilt from the trie in the background when the deltas are missing, e.g.
+//! when the state has been imported by state sync. The previous flat state is still read until
+//! the rebuild completes. Each rebuild starts a new generation of entries. As the database can't
+//! iterate over a column, the keys written to each generation are logged, so that the entries of
+//! the previous generations can be removed once the rebuild completes.
+
+use crate::{columns, utils::meta_keys, DbHash};
+use codec::{Decode, Encode};
+use log::{debug, info, warn};
+use parking_lot::{Condvar, Mutex, RwLock};
+use sp_blockchain::{Error as ClientError, Result as ClientResult};
+use sp_core::storage::{well_known_keys, ChildInfo};
+use sp_database::{Database, Transaction};
+use sp_runtime::traits::{Block as BlockT, HashFor, NumberFor};
+use sp_state_machine::{
+	backend::Backend as StateBackend, ChildStorageCollection, StorageCollection,
+};
+use std::{
+	collections::HashMap,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+};
+
+/// Maximum number of entries written at once when rebuilding the flat state.
+const REBUILD_BATCH_SIZE: usize = 10_000;
+
+/// Number of mismatching keys reported by [`FlatState::check`].
+const MAX_REPORTED_MISMATCHES: usize = 16;
+
+/// Block whose state is stored in the flat state.
+#[derive(Debug, Clone, Encode, Decode)]
+struct FlatHead<Hash, Number> {
+	/// Generation of the entries of the flat state.
+	generation: u32,
+	/// Number of chunks of the log of the keys of the generation.
+	key_chunks: u32,
+	hash: Hash,
+	number: Number,
+}
+
+/// Changes made to the state by a block that isn't finalized.
+#[derive(Encode, Decode)]
+struct StoredDelta<Hash, Number> {
+	parent: Hash,
+	number: Number,
+	/// Whether the state of the block has been set from scratch instead of being derived from
+	/// the state of its parent.
+	reset: bool,
+	changes: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+struct FlatDelta<Block: BlockT> {
+	parent: Block::Hash,
+	number: NumberFor<Block>,
+	reset: bool,
+	changes: HashMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl<Block: BlockT> From<StoredDelta<Block::Hash, NumberFor<Block>>> for FlatDelta<Block> {
+	fn from(delta: StoredDelta<Block::Hash, NumberFor<Block>>) -> Self {
+		FlatDelta {
+			parent: delta.parent,
+			number: delta.number,
+			reset: delta.reset,
+			changes: delta.changes.into_iter().collect(),
+		}
+	}
+}
+
+/// Result of the comparison of the flat state with the trie.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlatStateCheck<Hash> {
+	/// Block whose state is stored in the flat state.
+	pub block: Hash,
+	/// Number of values of the trie checked.
+	pub entries: u64,
+	/// Number of values missing from the flat state or different.
+	pub mismatches: u64,
+	/// Flat state keys of the first mismatching values.
+	pub mismatching_keys: Vec<Vec<u8>>,
+}
+
+/// Key of `key` in the flat state, without the generation.
+fn flat_key(child_info: Option<&ChildInfo>, key: &[u8]) -> Vec<u8> {
+	match child_info {
+		None => {
+			let mut flat_key = Vec::with_capacity(1 + key.len());
+			flat_key.push(0);
+			flat_key.extend_from_slice(key);
+			flat_key
+		},
+		Some(child_info) => {
+			let mut flat_key = vec![1];
+			child_info.storage_key().encode_to(&mut flat_key);
+			flat_key.extend_from_slice(key);
+			flat_key
+		},
+	}
+}
+
+fn column_key(generation: u32, flat_key: &[u8]) -> Vec<u8> {
+	let mut key = Vec::with_capacity(4 + flat_key.len());
+	key.extend_from_slice(&generation.to_be_bytes());
+	key.extend_from_slice(flat_key);
+	key
+}
+
+/// Key of a chunk of the log of the keys written to `generation`.
+fn key_log_key(generation: u32, chunk: u32) -> Vec<u8> {
+	let mut key = meta_keys::FLAT_STATE_KEYS_PREFIX.to_vec();
+	key.extend_from_slice(&generation.to_be_bytes());
+	key.extend_from_slice(&chunk.to_be_bytes());
+	key
+}
+
+fn delta_key<Hash: AsRef<[u8]>>(hash: &Hash) -> Vec<u8> {
+	let mut key = meta_keys::FLAT_STATE_DELTA_PREFIX.to_vec();
+	key.extend_from_slice(hash.as_ref());
+	key
+}
+
+fn decode_meta<T: Decode>(db: &dyn Database<DbHash>, key: &[u8]) -> ClientResult<Option<T>> {
+	db.get(columns::META, key)
+		.map(|value| T::decode(&mut &value[..]))
+		.transpose()
+		.map_err(|e| ClientError::Backend(format!("Invalid flat state metadata: {}", e)))
+}
+
+/// Flat state of a database.
+pub(crate) struct FlatState<Block: BlockT> {
+	db: Arc<dyn Database<DbHash>>,
+	head: RwLock<Option<FlatHead<Block::Hash, NumberFor<Block>>>>,
+	deltas: RwLock<HashMap<Block::Hash, Arc<FlatDelta<Block>>>>,
+	/// Whether the flat state is being rebuilt, in which case it isn't advanced.
+	rebuilding: AtomicBool,
+	/// Number of background jobs running.
+	jobs: Mutex<usize>,
+	jobs_done: Condvar,
+}
+
+impl<Block: BlockT> FlatState<Block> {
+	/// Loads the flat state along with the deltas of the ancestors of `leaves`.
+	pub fn open(
+		db: Arc<dyn Database<DbHash>>,
+		leaves: impl IntoIterator<Item = Block::Hash>,
+	) -> ClientResult<Self> {
+		let head: Option<FlatHead<Block::Hash, NumberFor<Block>>> =
+			decode_meta(&*db, meta_keys::FLAT_STATE)?;
+		let mut deltas = HashMap::new();
+		if let Some(head) = &head {
+			for leaf in leaves {
+				let mut hash = leaf;
+				while hash != head.hash && !deltas.contains_key(&hash) {
+					let delta: StoredDelta<Block::Hash, NumberFor<Block>> =
+						match decode_meta(&*db, &delta_key(&hash))? {
+							Some(delta) => delta,
+							None => break,
+						};
+					let parent = delta.parent;
+					deltas.insert(hash, Arc::new(FlatDelta::from(delta)));
+					hash = parent;
+				}
+			}
+			debug!(
+				target: "db",
+				"Loaded flat state of block #{} ({:?}) with {} deltas",
+				head.number,
+				head.hash,
+				deltas.len(),
+			);
+		}
+		Ok(FlatState {
+			db,
+			head: RwLock::new(head),
+			deltas: RwLock::new(deltas),
+			rebuilding: AtomicBool::new(false),
+			jobs: Mutex::new(0),
+			jobs_done: Condvar::new(),
+		})
+	}
+
+	/// Stores the changes made by the block `hash` to the state of its parent.
+	///
+	/// `reset` tells that the state of the block doesn't derive from the one of its parent.
+	pub fn note_block(
+		&self,
+		transaction: &mut Transaction<DbHash>,
+		hash: Block::Hash,
+		parent: Block::Hash,
+		number: NumberFor<Block>,
+		reset: bool,
+		storage_updates: &StorageCollection,
+		child_storage_updates: &ChildStorageCollection,
+	) {
+		let mut changes = Vec::new();
+		if !reset {
+			changes.extend(storage_updates.iter().map(|(k, v)| (flat_key(None, k), v.clone())));
+			for (storage_key, updates) in child_storage_updates {
+				let child_info = ChildInfo::new_default(storage_key);
+				changes.extend(
+					updates.iter().map(|(k, v)| (flat_key(Some(&child_info), k), v.clone())),
+				);
+			}
+		}
+		let delta = StoredDelta { parent, number, reset, changes };
+		transaction.set_from_vec(columns::META, &delta_key(&hash), delta.encode());
+		self.deltas.write().insert(hash, Arc::new(delta.into()));
+	}
+
+	/// Forgets the changes made by the block `hash`.
+	pub fn remove_block(&self, transaction: &mut Transaction<DbHash>, hash: &Block::Hash) {
+		if self.deltas.write().remove(hash).is_some() {
+			transaction.remove(columns::META, &delta_key(hash));
+		}
+	}
+
+	/// Returns the deltas to apply to the flat state to get the state of `hash`, latest first.
+	///
+	/// Returns `None` if the state of `hash` can't be derived from the flat state.
+	fn path(
+		&self,
+		head: &FlatHead<Block::Hash, NumberFor<Block>>,
+		hash: Block::Hash,
+	) -> Option<Vec<(Block::Hash, Arc<FlatDelta<Block>>)>> {
+		let deltas = self.deltas.read();
+		let mut path = Vec::new();
+		let mut hash = hash;
+		while hash != head.hash {
+			match deltas.get(&hash) {
+				Some(delta) if !delta.reset && delta.number > head.number => {
+					path.push((hash, delta.clone()));
+					hash = delta.parent;
+				},
+				_ => return None,
+			}
+		}
+		Some(path)
+	}
+
+	/// Returns a reader of the state of `hash`, if it can be derived from the flat state.
+	pub fn reader(self: &Arc<Self>, hash: Block::Hash) -> Option<FlatStateReader<Block>> {
+		let head = self.head.read().clone()?;
+		let path = self.path(&head, hash)?;
+		Some(FlatStateReader {
+			flat: self.clone(),
+			head: head.hash,
+			generation: head.generation,
+			deltas: path.into_iter().map(|(_, delta)| delta).collect(),
+		})
+	}
+
+	/// Brings the flat state to the state of the finalized block `hash`, `state`.
+	///
+	/// The deltas of the blocks between the current flat state and `hash` are applied if they are
+	/// all known. Otherwise the flat state is rebuilt from `state` in the background, and isn't
+	/// advanced until the rebuild completes.
+	pub fn advance<S: StateBackend<HashFor<Block>> + Send + 'static>(
+		self: &Arc<Self>,
+		hash: Block::Hash,
+		number: NumberFor<Block>,
+		state: impl FnOnce() -> ClientResult<S>,
+	) -> ClientResult<()> {
+		if self.rebuilding.load(Ordering::Acquire) {
+			return Ok(())
+		}
+		let head = self.head.read().clone();
+		if head.as_ref().map_or(false, |head| head.hash == hash) {
+			return Ok(())
+		}
+		match head.as_ref().and_then(|head| Some((head, self.path(head, hash)?))) {
+			Some((head, path)) => self.apply(head, hash, number, path),
+			None => {
+				// Holding the state pins it, it can't be pruned while being read.
+				let state = state()?;
+				if self.rebuilding.swap(true, Ordering::AcqRel) {
+					return Ok(())
+				}
+				self.spawn_job(move |flat| {
+					let rebuilt = flat.rebuild(hash, number, &state);
+					flat.rebuilding.store(false, Ordering::Release);
+					rebuilt?;
+					flat.remove_stale_generations()
+				});
+				Ok(())
+			},
+		}
+	}
+
+	/// Removes the entries of the stale generations in the background.
+	pub fn start_removing_stale_generations(self: &Arc<Self>) {
+		if !self.rebuilding.load(Ordering::Acquire) {
+			self.spawn_job(|flat| flat.remove_stale_generations());
+		}
+	}
+
+	/// Runs `job` on a thread of its own.
+	fn spawn_job(
+		self: &Arc<Self>,
+		job: impl FnOnce(&Arc<Self>) -> ClientResult<()> + Send + 'static,
+	) {
+		let flat = self.clone();
+		*self.jobs.lock() += 1;
+		let spawned = std::thread::Builder::new().name("flat-state".into()).spawn(move || {
+			if let Err(e) = job(&flat) {
+				warn!(target: "db", "Failed to update the flat state: {}", e);
+			}
+			flat.end_job();
+		});
+		if let Err(e) = spawned {
+			warn!(target: "db", "Failed to start updating the flat state: {}", e);
+			self.rebuilding.store(false, Ordering::Release);
+			self.end_job();
+		}
+	}
+
+	fn end_job(&self) {
+		let mut jobs = self.jobs.lock();
+		*jobs -= 1;
+		if *jobs == 0 {
+			self.jobs_done.notify_all();
+		}
+	}
+
+	/// Waits for the background rebuild and removal of the stale generations to complete, and
+	/// returns the block whose state is stored in the flat state.
+	pub fn wait_for_jobs(&self) -> Option<Block::Hash> {
+		let mut jobs = self.jobs.lock();
+		while *jobs > 0 {
+			self.jobs_done.wait(&mut jobs);
+		}
+		self.head.read().as_ref().map(|head| head.hash)
+	}
+
+	fn apply(
+		&self,
+		head: &FlatHead<Block::Hash, NumberFor<Block>>,
+		hash: Block::Hash,
+		number: NumberFor<Block>,
+		path: Vec<(Block::Hash, Arc<FlatDelta<Block>>)>,
+	) -> ClientResult<()> {
+		let generation = head.generation;
+		let mut transaction = Transaction::new();
+		let mut applied = HashMap::new();
+		// The latest changes take precedence.
+		for (_, delta) in &path {
+			for (key, value) in &delta.changes {
+				applied.entry(key).or_insert(value);
+			}
+		}
+		let mut new_keys = Vec::new();
+		for (flat_key, value) in applied {
+			let key = column_key(generation, flat_key);
+			match value {
+				Some(value) => {
+					if !self.db.contains(columns::FLAT_STATE, &key) {
+						new_keys.push(flat_key.clone());
+					}
+					transaction.set(columns::FLAT_STATE, &key, value)
+				},
+				None => transaction.remove(columns::FLAT_STATE, &key),
+			}
+		}
+		let mut key_chunks = head.key_chunks;
+		if !new_keys.is_empty() {
+			let chunk = key_log_key(generation, key_chunks);
+			transaction.set_from_vec(columns::META, &chunk, new_keys.encode());
+			key_chunks += 1;
+		}
+		debug!(target: "db", "Applying {} deltas to the flat state", path.len());
+		self.set_head(transaction, FlatHead { generation, key_chunks, hash, number })
+	}
+
+	fn rebuild<S: StateBackend<HashFor<Block>>>(
+		self: &Arc<Self>,
+		hash: Block::Hash,
+		number: NumberFor<Block>,
+		state: &S,
+	) -> ClientResult<()> {
+		// The generation is bumped before anything is written, so that entries left by an
+		// interrupted rebuild are never read.
+		let generation =
+			decode_meta::<u32>(&*self.db, meta_keys::FLAT_STATE_GENERATION)?.unwrap_or(0) + 1;
+		let mut transaction = Transaction::new();
+		transaction.set_from_vec(
+			columns::META,
+			meta_keys::FLAT_STATE_GENERATION,
+			generation.encode(),
+		);
+		self.db.commit(transaction)?;
+
+		info!(target: "db", "Building the flat state of block #{} ({:?})", number, hash);
+		let mut writer = RebuildWriter {
+			db: &*self.db,
+			generation,
+			transaction: Transaction::new(),
+			keys: Vec::new(),
+			key_chunks: 0,
+		};
+		// The rebuild is given up once the backend is closed, which holds the other reference.
+		let closed = || Arc::strong_count(self) == 1;
+		let mut entries = 0u64;
+		let mut child_tries = Vec::new();
+		let mut result = Ok(());
+		state
+			.apply_to_key_values_while(
+				None,
+				None,
+				None,
+				|key, value| {
+					if well_known_keys::is_child_storage_key(&key) {
+						if let Some(storage_key) =
+							key.strip_prefix(well_known_keys::DEFAULT_CHILD_STORAGE_KEY_PREFIX)
+						{
+							child_tries.push(ChildInfo::new_default(storage_key));
+						}
+						return true
+					}
+					entries += 1;
+					result = writer.set(flat_key(None, &key), value);
+					result.is_ok() && !closed()
+				},
+				false,
+			)
+			.map_err(|e| ClientError::Backend(format!("Failed to iterate the state: {}", e)))?;
+		std::mem::replace(&mut result, Ok(()))?;
+		for child_info in &child_tries {
+			state
+				.apply_to_key_values_while(
+					Some(child_info),
+					None,
+					None,
+					|key, value| {
+						entries += 1;
+						result = writer.set(flat_key(Some(child_info), &key), value);
+						result.is_ok() && !closed()
+					},
+					false,
+				)
+				.map_err(|e| ClientError::Backend(format!("Failed to iterate the state: {}", e)))?;
+			std::mem::replace(&mut result, Ok(()))?;
+		}
+
+		if closed() {
+			return Err(ClientError::Backend("Database closed during the rebuild".into()))
+		}
+
+		info!(target: "db", "Built the flat state of block #{}: {} entries", number, entries);
+		let (transaction, key_chunks) = writer.finish();
+		self.set_head(transaction, FlatHead { generation, key_chunks, hash, number })
+	}
+
+	/// Removes the entries of the generations older than the one of the flat state, along with
+	/// the logs of their keys.
+	///
+	/// The generations of the rebuilds that have been interrupted are newer, they are removed
+	/// once a rebuild completes. The generation being rebuilt is never removed.
+	fn remove_stale_generations(&self) -> ClientResult<()> {
+		let current = match self.head.read().as_ref() {
+			Some(head) => head.generation,
+			None => return Ok(()),
+		};
+		for generation in 1..current {
+			// The chunks are removed latest first, so that an interrupted removal leaves the first
+			// chunks of the log.
+			let mut chunks = 0;
+			while self.db.contains(columns::META, &key_log_key(generation, chunks)) {
+				chunks += 1;
+			}
+			if chunks > 0 {
+				debug!(target: "db", "Removing flat state generation {}", generation);
+			}
+			for chunk in (0..chunks).rev() {
+				let chunk_key = key_log_key(generation, chunk);
+				let keys: Vec<Vec<u8>> = match decode_meta(&*self.db, &chunk_key)? {
+					Some(keys) => keys,
+					None => continue,
+				};
+				let mut transaction = Transaction::new();
+				for key in keys {
+					transaction.remove(columns::FLAT_STATE, &column_key(generation, &key));
+				}
+				transaction.remove(columns::META, &chunk_key);
+				self.db.commit(transaction)?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Commits `transaction` along with the new flat state head, discarding the deltas that are
+	/// no longer needed.
+	fn set_head(
+		&self,
+		mut transaction: Transaction<DbHash>,
+		head: FlatHead<Block::Hash, NumberFor<Block>>,
+	) -> ClientResult<()> {
+		transaction.set_from_vec(columns::META, meta_keys::FLAT_STATE, head.encode());
+		let mut deltas = self.deltas.write();
+		let stale: Vec<_> = deltas
+			.iter()
+			.filter(|(_, delta)| delta.number <= head.number)
+			.map(|(hash, _)| *hash)
+			.collect();
+		for hash in &stale {
+			transaction.remove(columns::META, &delta_key(hash));
+		}
+
+		// The head is updated while the flat state is written, so that readers of the previous
+		// flat state never see the new values.
+		let mut current = self.head.write();
+		self.db.commit(transaction)?;
+		for hash in stale {
+			deltas.remove(&hash);
+		}
+		*current = Some(head);
+		Ok(())
+	}
+
+	/// Compares the values of the trie state of the flat state block, returned by `state_at`, with
+	/// the flat state.
+	///
+	/// Returns `None` if there is no flat state.
+	pub fn check<S: StateBackend<HashFor<Block>>>(
+		&self,
+		state_at: impl FnOnce(Block::Hash) -> ClientResult<S>,
+	) -> ClientResult<Option<FlatStateCheck<Block::Hash>>> {
+		let head = match self.head.read().clone() {
+			Some(head) => head,
+			None => return Ok(None),
+		};
+		let state = state_at(head.hash)?;
+		let mut check = FlatStateCheck {
+			block: head.hash,
+			entries: 0,
+			mismatches: 0,
+			mismatching_keys: Vec::new(),
+		};
+		let mut compare = |flat_key: Vec<u8>, value: Vec<u8>| {
+			check.entries += 1;
+			let key = column_key(head.generation, &flat_key);
+			if self.db.get(columns::FLAT_STATE, &key).as_ref() != Some(&value) {
+				check.mismatches += 1;
+				if check.mismatching_keys.len() < MAX_REPORTED_MISMATCHES {
+					check.mismatching_keys.push(flat_key);
+				}
+			}
+		};
+
+		let mut child_tries = Vec::new();
+		state
+			.apply_to_key_values_while(
+				None,
+				None,
+				None,
+				|key, value| {
+					match key.strip_prefix(well_known_keys::DEFAULT_CHILD_STORAGE_KEY_PREFIX) {
+						Some(storage_key) => child_tries.push(ChildInfo::new_default(storage_key)),
+						None if well_known_keys::is_child_storage_key(&key) => (),
+						None => compare(flat_key(None, &key), value),
+					}
+					true
+				},
+				false,
+			)
+			.map_err(|e| ClientError::Backend(format!("Failed to iterate the state: {}", e)))?;
+		for child_info in &child_tries {
+			state
+				.apply_to_key_values_while(
+					Some(child_info),
+					None,
+					None,
+					|key, value| {
+						compare(flat_key(Some(child_info), &key), value);
+						true
+					},
+					false,
+				)
+				.map_err(|e| ClientError::Backend(format!("Failed to iterate the state: {}", e)))?;
+		}
+		Ok(Some(check))
+	}
+}
+
+/// Writes the entries of a rebuilt flat state in batches, each along with the log of its keys.
+struct RebuildWriter<'a> {
+	db: &'a dyn Database<DbHash>,
+	generation: u32,
+	transaction: Transaction<DbHash>,
+	keys: Vec<Vec<u8>>,
+	key_chunks: u32,
+}
+
+impl<'a> RebuildWriter<'a> {
+	fn set(&mut self, flat_key: Vec<u8>, value: Vec<u8>) -> ClientResult<()> {
+		let key = column_key(self.generation, &flat_key);
+		self.transaction.set_from_vec(columns::FLAT_STATE, &key, value);
+		self.keys.push(flat_key);
+		if self.keys.len() == REBUILD_BATCH_SIZE {
+			self.log_keys();
+			self.db.commit(std::mem::take(&mut self.transaction))?;
+		}
+		Ok(())
+	}
+
+	fn log_keys(&mut self) {
+		let chunk = key_log_key(self.generation, self.key_chunks);
+		let keys = std::mem::take(&mut self.keys);
+		self.transaction.set_from_vec(columns::META, &chunk, keys.encode());
+		self.key_chunks += 1;
+	}
+
+	/// Returns the transaction writing the last entries, and the number of chunks of the log.
+	fn finish(mut self) -> (Transaction<DbHash>, u32) {
+		if !self.keys.is_empty() {
+			self.log_keys();
+		}
+		(self.transaction, self.key_chunks)
+	}
+}
+
+/// Reader of the state of a block from the flat state.
+pub struct FlatStateReader<Block: BlockT> {
+	flat: Arc<FlatState<Block>>,
+	head: Block::Hash,
+	generation: u32,
+	/// Deltas of the block and its ancestors down to the flat state block, latest first.
+	deltas: Vec<Arc<FlatDelta<Block>>>,
+}
+
+impl<Block: BlockT> FlatStateReader<Block> {
+	/// Returns the value of `key`, or `None` if it must be read from the trie.
+	pub fn storage(&self, child_info: Option<&ChildInfo>, key: &[u8]) -> Option<Option<Vec<u8>>> {
+		// The child trie roots are only updated in the trie.
+		if child_info.is_none() && well_known_keys::is_child_storage_key(key) {
+			return None
+		}
+		let flat_key = flat_key(child_info, key);
+		for delta in &self.deltas {
+			if let Some(value) = delta.changes.get(&flat_key) {
+				return Some(value.clone())
+			}
+		}
+
+		let head = self.flat.head.read();
+		if head.as_ref().map_or(true, |head| head.hash != self.head) {
+			return None
+		}
+		Some(self.flat.db.get(columns::FLAT_STATE, &column_key(self.generation, &flat_key)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn flat_keys_do_not_collide() {
+		let child_info = ChildInfo::new_default(b"child");
+		assert_eq!(flat_key(None, b"key"), b"\0key".to_vec());
+		assert_ne!(flat_key(Some(&child_info), b"key"), flat_key(None, b"key"));
+		assert_ne!(
+			flat_key(Some(&ChildInfo::new_default(b"chil")), b"dkey"),
+			flat_key(Some(&child_info), b"key"),
+		);
+		assert_eq!(column_key(1, b"\0key"), b"\0\0\0\x01\0key".to_vec());
+		assert_eq!(key_log_key(1, 2), b"fkeys\0\0\0\x01\0\0\0\x02".to_vec());
+	}
+}
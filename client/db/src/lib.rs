@@ -34,8 +34,12 @@ pub mod offchain;
 pub mod bench;
 
 mod children;
+mod diff_index;
+mod flat;
+pub mod migration;
 #[cfg(feature = "with-parity-db")]
 mod parity_db;
+pub mod snapshot;
 mod stats;
 mod storage_cache;
 #[cfg(any(feature = "with-kvdb-rocksdb", test))]
@@ -46,38 +50,41 @@ use linked_hash_map::LinkedHashMap;
 use log::{debug, trace, warn};
 use parking_lot::{Mutex, RwLock};
 use std::{
-	collections::{HashMap, HashSet},
+	collections::{BTreeSet, HashMap, HashSet},
 	io,
 	path::{Path, PathBuf},
 	sync::Arc,
 };
 
 use crate::{
+	diff_index::DiffIndex,
+	flat::{FlatState, FlatStateReader},
 	stats::StateUsageStats,
 	storage_cache::{new_shared_cache, CachingState, SharedCache, SyncingCachingState},
 	utils::{meta_keys, read_db, read_meta, DatabaseType, Meta},
 };
 use codec::{Decode, Encode};
-use hash_db::Prefix;
+use hash_db::{Prefix, EMPTY_PREFIX};
 use sc_client_api::{
 	backend::NewBlockState,
 	leaves::{FinalizationDisplaced, LeafSet},
 	utils::is_descendent_of,
-	IoInfo, MemoryInfo, MemorySize, UsageInfo,
+	IoInfo, MemoryInfo, MemorySize, StateChangesIndex, StorageItemUsage, UsageInfo,
 };
 use sc_state_db::StateDb;
 use sp_arithmetic::traits::Saturating;
 use sp_blockchain::{
 	well_known_cache_keys, Backend as _, CachedHeaderMetadata, Error as ClientError, HeaderBackend,
-	HeaderMetadata, HeaderMetadataCache, Result as ClientResult,
+	HeaderMetadata, HeaderMetadataCache, PrunableBlockData, Result as ClientResult,
 };
 use sp_core::{
 	offchain::OffchainOverlayedChange,
-	storage::{well_known_keys, ChildInfo},
+	storage::{well_known_keys, ChildInfo, StorageKey},
 };
 use sp_database::Transaction;
+use sp_finality_grandpa::{ConsensusLog as GrandpaConsensusLog, GRANDPA_ENGINE_ID};
 use sp_runtime::{
-	generic::BlockId,
+	generic::{BlockId, OpaqueDigestItemId},
 	traits::{
 		Block as BlockT, Hash, HashFor, Header as HeaderT, NumberFor, One, SaturatedConversion,
 		Zero,
@@ -90,8 +97,9 @@ use sp_state_machine::{
 };
 use sp_trie::{prefixed_key, MemoryDB, PrefixedMemoryDB};
 
+pub use flat::FlatStateCheck;
 // Re-export the Database trait so that one can pass an implementation of it.
-pub use sc_state_db::PruningMode;
+pub use sc_state_db::{Constraints as PruningConstraints, PruningMode};
 pub use sp_database::Database;
 
 #[cfg(any(feature = "with-kvdb-rocksdb", test))]
@@ -135,11 +143,18 @@ pub struct RefTrackingState<Block: BlockT> {
 	state: DbState<Block>,
 	storage: Arc<StorageDb<Block>>,
 	parent_hash: Option<Block::Hash>,
+	flat: Option<FlatStateReader<Block>>,
 }
 
 impl<B: BlockT> RefTrackingState<B> {
 	fn new(state: DbState<B>, storage: Arc<StorageDb<B>>, parent_hash: Option<B::Hash>) -> Self {
-		RefTrackingState { state, parent_hash, storage }
+		RefTrackingState { state, parent_hash, storage, flat: None }
+	}
+
+	/// Reads the values from `flat` when available instead of from the trie.
+	fn with_flat_state(mut self, flat: Option<FlatStateReader<B>>) -> Self {
+		self.flat = flat;
+		self
 	}
 }
 
@@ -163,6 +178,9 @@ impl<B: BlockT> StateBackend<HashFor<B>> for RefTrackingState<B> {
 	type TrieBackendStorage = <DbState<B> as StateBackend<HashFor<B>>>::TrieBackendStorage;
 
 	fn storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+		if let Some(value) = self.flat.as_ref().and_then(|flat| flat.storage(None, key)) {
+			return Ok(value)
+		}
 		self.state.storage(key)
 	}
 
@@ -175,10 +193,17 @@ impl<B: BlockT> StateBackend<HashFor<B>> for RefTrackingState<B> {
 		child_info: &ChildInfo,
 		key: &[u8],
 	) -> Result<Option<Vec<u8>>, Self::Error> {
+		if let Some(value) = self.flat.as_ref().and_then(|flat| flat.storage(Some(child_info), key))
+		{
+			return Ok(value)
+		}
 		self.state.child_storage(child_info, key)
 	}
 
 	fn exists_storage(&self, key: &[u8]) -> Result<bool, Self::Error> {
+		if let Some(value) = self.flat.as_ref().and_then(|flat| flat.storage(None, key)) {
+			return Ok(value.is_some())
+		}
 		self.state.exists_storage(key)
 	}
 
@@ -187,6 +212,10 @@ impl<B: BlockT> StateBackend<HashFor<B>> for RefTrackingState<B> {
 		child_info: &ChildInfo,
 		key: &[u8],
 	) -> Result<bool, Self::Error> {
+		if let Some(value) = self.flat.as_ref().and_then(|flat| flat.storage(Some(child_info), key))
+		{
+			return Ok(value.is_some())
+		}
 		self.state.exists_child_storage(child_info, key)
 	}
 
@@ -302,7 +331,15 @@ pub struct DatabaseSettings {
 	/// Where to find the database.
 	pub source: DatabaseSource,
 	/// Block pruning mode.
-	pub keep_blocks: KeepBlocks,
+	pub blocks_pruning: BlocksPruning,
+	/// Keep the values of the latest finalized state in a flat key-value layout, read instead of
+	/// the trie when possible.
+	pub flat_state: bool,
+	/// Index the top-level storage keys changed by each block, see [`StateChangesIndex`].
+	pub state_diff_index: bool,
+	/// Tally the accesses per storage item, see
+	/// [`sc_client_api::backend::Backend::storage_item_usage`].
+	pub storage_item_stats: bool,
 }
 
 /// Block pruning settings.
@@ -314,6 +351,92 @@ pub enum KeepBlocks {
 	Some(u32),
 }
 
+impl KeepBlocks {
+	/// Returns the number of recent finalized blocks to keep, if not all of them.
+	///
+	/// The last finalized block is always kept.
+	fn recent(&self) -> Option<u32> {
+		match self {
+			KeepBlocks::All => None,
+			KeepBlocks::Some(n) => Some(std::cmp::max(*n, 1)),
+		}
+	}
+}
+
+/// Justification pruning settings.
+#[derive(Debug, Clone, Copy)]
+pub enum KeepJustifications {
+	/// Keep the justifications of all finalized blocks.
+	All,
+	/// Keep the justifications of N recent finalized blocks.
+	Some(u32),
+	/// Keep the justifications of the last finalized block, and of the blocks enacting a change
+	/// of GRANDPA authority set, i.e. the blocks a scheduled change has been signaled `delay`
+	/// blocks before. These are the justifications needed to prove the authority set changes,
+	/// e.g. for warp sync.
+	AuthoritySetChanges,
+}
+
+impl KeepJustifications {
+	/// Returns the number of recent finalized blocks to keep all the justifications of, if not
+	/// all of them.
+	fn recent(&self) -> Option<u32> {
+		match self {
+			KeepJustifications::All => None,
+			KeepJustifications::Some(n) => KeepBlocks::Some(*n).recent(),
+			KeepJustifications::AuthoritySetChanges => Some(1),
+		}
+	}
+}
+
+/// Retention of the data of finalized blocks, per database column.
+///
+/// Each column is pruned independently as blocks get finalized, regardless of the state pruning
+/// mode. Changing the retention of the indexed transactions of an existing database isn't
+/// supported.
+#[derive(Debug, Clone, Copy)]
+pub struct BlocksPruning {
+	/// Retention of the headers, in the `HEADER` column. The genesis header is always kept.
+	pub headers: KeepBlocks,
+	/// Retention of the bodies, in the `BODY` and `BODY_INDEX` columns, holding the bodies of
+	/// blocks respectively imported without and with indexed transactions.
+	pub bodies: KeepBlocks,
+	/// Retention of the indexed transactions, in the `TRANSACTION` column. Indexed transactions
+	/// are never kept for longer than the bodies referencing them.
+	pub indexed_transactions: KeepBlocks,
+	/// Retention of the justifications, in the `JUSTIFICATIONS` column.
+	pub justifications: KeepJustifications,
+}
+
+impl BlocksPruning {
+	/// Keep all the data of all the blocks.
+	pub const fn keep_all() -> Self {
+		Self {
+			headers: KeepBlocks::All,
+			bodies: KeepBlocks::All,
+			indexed_transactions: KeepBlocks::All,
+			justifications: KeepJustifications::All,
+		}
+	}
+
+	/// Returns the number of recent finalized blocks to keep the indexed transactions of, if they
+	/// are pruned before the bodies referencing them.
+	fn separate_indexed_transactions(&self) -> Option<u32> {
+		match (self.indexed_transactions.recent(), self.bodies.recent()) {
+			(Some(transactions), Some(bodies)) if transactions < bodies => Some(transactions),
+			(Some(transactions), None) => Some(transactions),
+			_ => None,
+		}
+	}
+}
+
+impl From<KeepBlocks> for BlocksPruning {
+	/// Only prunes the bodies and the indexed transactions.
+	fn from(keep_blocks: KeepBlocks) -> Self {
+		Self { bodies: keep_blocks, indexed_transactions: keep_blocks, ..Self::keep_all() }
+	}
+}
+
 /// Where to find the database..
 #[derive(Debug, Clone)]
 pub enum DatabaseSource {
@@ -409,6 +532,10 @@ pub(crate) mod columns {
 	/// Transactions
 	pub const TRANSACTION: u32 = 11;
 	pub const BODY_INDEX: u32 = 12;
+	/// Values of the finalized state, see [`crate::flat`].
+	pub const FLAT_STATE: u32 = 13;
+	/// Keys changed by each block, see [`crate::diff_index`].
+	pub const STATE_DIFF: u32 = 14;
 }
 
 struct PendingBlock<Block: BlockT> {
@@ -456,21 +583,58 @@ pub struct BlockchainDb<Block: BlockT> {
 	leaves: RwLock<LeafSet<Block::Hash, NumberFor<Block>>>,
 	header_metadata_cache: Arc<HeaderMetadataCache<Block>>,
 	header_cache: Mutex<LinkedHashMap<Block::Hash, Option<Block::Header>>>,
+	blocks_pruning: BlocksPruning,
+	/// Numbers of the blocks whose justifications are kept by
+	/// [`KeepJustifications::AuthoritySetChanges`], once finalized.
+	justification_pins: Mutex<BTreeSet<NumberFor<Block>>>,
 }
 
 impl<Block: BlockT> BlockchainDb<Block> {
-	fn new(db: Arc<dyn Database<DbHash>>) -> ClientResult<Self> {
+	fn new(db: Arc<dyn Database<DbHash>>, blocks_pruning: BlocksPruning) -> ClientResult<Self> {
 		let meta = read_meta::<Block>(&*db, columns::HEADER)?;
 		let leaves = LeafSet::read_from_db(&*db, columns::META, meta_keys::LEAF_PREFIX)?;
+		let justification_pins = match db.get(columns::META, meta_keys::JUSTIFICATION_PINS) {
+			Some(pins) => Vec::<NumberFor<Block>>::decode(&mut &pins[..])
+				.map_err(|err| {
+					ClientError::Backend(format!("Error decoding justification pins: {}", err))
+				})?
+				.into_iter()
+				.collect(),
+			None => BTreeSet::new(),
+		};
 		Ok(BlockchainDb {
 			db,
 			leaves: RwLock::new(leaves),
 			meta: Arc::new(RwLock::new(meta)),
 			header_metadata_cache: Arc::new(HeaderMetadataCache::default()),
 			header_cache: Default::default(),
+			blocks_pruning,
+			justification_pins: Mutex::new(justification_pins),
 		})
 	}
 
+	/// Returns the lookup key of a block, read from the `KEY_LOOKUP` column so that it is
+	/// available even if the header has been pruned.
+	fn lookup_key(&self, id: BlockId<Block>) -> ClientResult<Option<Vec<u8>>> {
+		utils::block_id_to_lookup_key(&*self.db, columns::KEY_LOOKUP, id)
+	}
+
+	/// Returns the error to report when an indexed transaction of a block body is missing.
+	fn missing_indexed_transaction(&self, id: BlockId<Block>, hash: DbHash) -> ClientError {
+		let pruned_below = self.blocks_pruning.separate_indexed_transactions().and_then(|keep| {
+			prunable_height::<Block>(self.meta.read().finalized_number, keep)
+				.map(|n| n + One::one())
+		});
+		match (self.block_number_from_id(&id), pruned_below) {
+			(Ok(Some(number)), Some(pruned_below)) if number < pruned_below =>
+				ClientError::Backend(format!(
+					"Indexed transaction {:?} of block {} has been pruned",
+					hash, id
+				)),
+			_ => ClientError::Backend(format!("Missing indexed transaction {:?}", hash)),
+		}
+	}
+
 	fn update_meta(&self, update: MetaUpdate<Block>) {
 		let MetaUpdate { hash, number, is_best, is_finalized, with_state } = update;
 		let mut meta = self.meta.write();
@@ -542,12 +706,25 @@ impl<Block: BlockT> sc_client_api::blockchain::HeaderBackend<Block> for Blockcha
 	}
 
 	fn number(&self, hash: Block::Hash) -> ClientResult<Option<NumberFor<Block>>> {
-		Ok(self.header_metadata(hash).ok().map(|header_metadata| header_metadata.number))
+		if let Ok(header_metadata) = self.header_metadata(hash) {
+			return Ok(Some(header_metadata.number))
+		}
+
+		// The header might have been pruned.
+		self.lookup_key(BlockId::Hash(hash))?
+			.map(|key| utils::lookup_key_to_number(&key))
+			.transpose()
 	}
 
 	fn hash(&self, number: NumberFor<Block>) -> ClientResult<Option<Block::Hash>> {
-		self.header(BlockId::Number(number))
-			.map(|maybe_header| maybe_header.map(|header| header.hash()))
+		if let Some(header) = self.header(BlockId::Number(number))? {
+			return Ok(Some(header.hash()))
+		}
+
+		// The header might have been pruned.
+		self.lookup_key(BlockId::Number(number))?
+			.map(|key| utils::lookup_key_to_hash(&key))
+			.transpose()
 	}
 }
 
@@ -586,11 +763,7 @@ impl<Block: BlockT> sc_client_api::blockchain::Backend<Block> for BlockchainDb<B
 										)?;
 										body.push(ex);
 									},
-									None =>
-										return Err(sp_blockchain::Error::Backend(format!(
-											"Missing indexed transaction {:?}",
-											hash
-										))),
+									None => return Err(self.missing_indexed_transaction(id, hash)),
 								};
 							},
 							DbExtrinsic::Full(ex) => {
@@ -669,11 +842,7 @@ impl<Block: BlockT> sc_client_api::blockchain::Backend<Block> for BlockchainDb<B
 					if let DbExtrinsic::Indexed { hash, .. } = ex {
 						match self.db.get(columns::TRANSACTION, hash.as_ref()) {
 							Some(t) => transactions.push(t),
-							None =>
-								return Err(sp_blockchain::Error::Backend(format!(
-									"Missing indexed transaction {:?}",
-									hash
-								))),
+							None => return Err(self.missing_indexed_transaction(id, hash)),
 						}
 					}
 				}
@@ -683,6 +852,28 @@ impl<Block: BlockT> sc_client_api::blockchain::Backend<Block> for BlockchainDb<B
 				Err(sp_blockchain::Error::Backend(format!("Error decoding body list: {}", err))),
 		}
 	}
+
+	fn pruned_below(&self, data: PrunableBlockData) -> Option<NumberFor<Block>> {
+		let keep = match data {
+			PrunableBlockData::Header => self.blocks_pruning.headers,
+			PrunableBlockData::Body => self.blocks_pruning.bodies,
+		};
+		prunable_height::<Block>(self.meta.read().finalized_number, keep.recent()?)
+			.map(|n| n + One::one())
+	}
+}
+
+/// Returns the height of the finalized block whose data is pruned when keeping `keep` recent
+/// finalized blocks, if any.
+fn prunable_height<Block: BlockT>(
+	finalized: NumberFor<Block>,
+	keep: u32,
+) -> Option<NumberFor<Block>> {
+	if finalized >= keep.into() {
+		Some(finalized.saturating_sub(keep.into()))
+	} else {
+		None
+	}
 }
 
 impl<Block: BlockT> HeaderMetadata<Block> for BlockchainDb<Block> {
@@ -734,6 +925,8 @@ pub struct BlockImportOperation<Block: BlockT> {
 	finalized_blocks: Vec<(BlockId<Block>, Option<Justification>)>,
 	set_head: Option<BlockId<Block>>,
 	commit_state: bool,
+	/// Whether the state is set from scratch instead of being updated.
+	reset_state: bool,
 	index_ops: Vec<IndexOperation>,
 }
 
@@ -830,9 +1023,28 @@ impl<Block: BlockT> sc_client_api::backend::BlockImportOperation<Block>
 	) -> ClientResult<Block::Hash> {
 		let root = self.apply_new_state(storage, state_version)?;
 		self.commit_state = true;
+		self.reset_state = true;
 		Ok(root)
 	}
 
+	fn reset_to_existing_storage(&mut self, root: Block::Hash) -> ClientResult<()> {
+		let exists = self.old_state.as_trie_backend().map_or(false, |state| {
+			let node = sp_state_machine::Storage::get(
+				state.backend_storage().as_ref(),
+				&root,
+				EMPTY_PREFIX,
+			);
+			matches!(node, Ok(Some(_)))
+		});
+		if !exists {
+			return Err(sp_blockchain::Error::Backend(format!("Missing state with root {}", root)))
+		}
+		self.db_updates = PrefixedMemoryDB::default();
+		self.commit_state = true;
+		self.reset_state = true;
+		Ok(())
+	}
+
 	fn set_genesis_state(
 		&mut self,
 		storage: Storage,
@@ -841,6 +1053,7 @@ impl<Block: BlockT> sc_client_api::backend::BlockImportOperation<Block>
 	) -> ClientResult<Block::Hash> {
 		let root = self.apply_new_state(storage, state_version)?;
 		self.commit_state = commit;
+		self.reset_state = true;
 		Ok(root)
 	}
 
@@ -1006,10 +1219,12 @@ pub struct Backend<Block: BlockT> {
 	shared_cache: SharedCache<Block>,
 	import_lock: Arc<RwLock<()>>,
 	is_archive: bool,
-	keep_blocks: KeepBlocks,
+	timestamp_pruning: bool,
 	io_stats: FrozenForDuration<(kvdb::IoStats, StateUsageInfo)>,
 	state_usage: Arc<StateUsageStats>,
 	genesis_state: RwLock<Option<Arc<DbGenesisStorage<Block>>>>,
+	flat_state: Option<Arc<FlatState<Block>>>,
+	diff_index: Option<DiffIndex<Block::Hash>>,
 }
 
 impl<Block: BlockT> Backend<Block> {
@@ -1051,7 +1266,10 @@ impl<Block: BlockT> Backend<Block> {
 			state_cache_child_ratio: Some((50, 100)),
 			state_pruning: Some(PruningMode::keep_blocks(keep_blocks)),
 			source: DatabaseSource::Custom { db, require_create_flag: true },
-			keep_blocks: KeepBlocks::Some(keep_blocks),
+			blocks_pruning: KeepBlocks::Some(keep_blocks).into(),
+			flat_state: false,
+			state_diff_index: false,
+			storage_item_stats: true,
 		};
 
 		Self::new(db_setting, canonicalization_delay).expect("failed to create test-db")
@@ -1098,13 +1316,23 @@ impl<Block: BlockT> Backend<Block> {
 
 		let state_pruning_used = state_db.pruning_mode();
 		let is_archive_pruning = state_pruning_used.is_archive();
-		let blockchain = BlockchainDb::new(db.clone())?;
+		let blockchain = BlockchainDb::new(db.clone(), config.blocks_pruning)?;
 
 		let storage_db =
 			StorageDb { db: db.clone(), state_db, prefix_keys: !db.supports_ref_counting() };
 
 		let offchain_storage = offchain::LocalStorage::new(db.clone());
 
+		let flat_state = if config.flat_state {
+			let leaves = blockchain.leaves.read().hashes();
+			Some(Arc::new(FlatState::open(db.clone(), leaves)?))
+		} else {
+			None
+		};
+
+		let diff_index =
+			if config.state_diff_index { Some(DiffIndex::open(db.clone())?) } else { None };
+
 		let backend = Backend {
 			storage: Arc::new(storage_db),
 			offchain_storage,
@@ -1116,10 +1344,12 @@ impl<Block: BlockT> Backend<Block> {
 			),
 			import_lock: Default::default(),
 			is_archive: is_archive_pruning,
+			timestamp_pruning: state_pruning_used.uses_timestamps(),
 			io_stats: FrozenForDuration::new(std::time::Duration::from_secs(1)),
-			state_usage: Arc::new(StateUsageStats::new()),
-			keep_blocks: config.keep_blocks,
+			state_usage: Arc::new(StateUsageStats::with_storage_items(config.storage_item_stats)),
 			genesis_state: RwLock::new(None),
+			flat_state,
+			diff_index,
 		};
 
 		// Older DB versions have no last state key. Check if the state is available and set it.
@@ -1140,11 +1370,126 @@ impl<Block: BlockT> Backend<Block> {
 			});
 		}
 
+		if backend.timestamp_pruning {
+			backend.check_state_timestamps()?;
+		}
+
 		db.commit(db_init_transaction)?;
+		backend.sync_flat_state();
+		if let Some(flat) = &backend.flat_state {
+			flat.start_removing_stale_generations();
+		}
+		backend.sync_state_diff_index();
 
 		Ok(backend)
 	}
 
+	/// Brings the flat state, if enabled, to the last finalized state.
+	///
+	/// The flat state is rebuilt in the background when it can't be brought to the last
+	/// finalized state from the deltas. The flat state isn't required to be up to date, failures
+	/// are only logged.
+	fn sync_flat_state(&self) {
+		let flat = match &self.flat_state {
+			Some(flat) => flat,
+			None => return,
+		};
+		if let Some((hash, number)) = self.blockchain.info().finalized_state {
+			if let Err(e) = flat.advance(hash, number, || self.trie_state_at(hash)) {
+				warn!(target: "db", "Failed to update the flat state to block #{}: {}", number, e);
+			}
+		}
+	}
+
+	/// Links the changes of the finalized blocks in the state diff index, if enabled.
+	///
+	/// Failures are only logged, the affected blocks are then not linked.
+	fn sync_state_diff_index(&self) {
+		let index = match &self.diff_index {
+			Some(index) => index,
+			None => return,
+		};
+		let info = self.blockchain.info();
+		let number = info.finalized_number.saturated_into::<u64>();
+		if let Err(e) =
+			index.advance(info.finalized_hash, number, |n| self.blockchain.hash(n.saturated_into()))
+		{
+			warn!(
+				target: "db",
+				"Failed to update the state diff index to block #{}: {}",
+				number,
+				e,
+			);
+		}
+	}
+
+	/// Returns the state of `hash`, read from the trie only.
+	fn trie_state_at(&self, hash: Block::Hash) -> ClientResult<RefTrackingState<Block>> {
+		let header = self.blockchain.header_metadata(hash)?;
+		if !sc_client_api::Backend::have_state_at(self, &hash, header.number) {
+			return Err(sp_blockchain::Error::UnknownBlock(format!(
+				"State already discarded for {:?}",
+				hash
+			)))
+		}
+		self.storage.state_db.pin(&hash).map_err(|_| {
+			sp_blockchain::Error::UnknownBlock(format!("State already discarded for {:?}", hash))
+		})?;
+		let db_state = DbState::<Block>::new(self.storage.clone(), header.state_root);
+		Ok(RefTrackingState::new(db_state, self.storage.clone(), Some(hash)))
+	}
+
+	/// Returns the timestamp of `hash` in milliseconds, if the state pruning window is limited by
+	/// age.
+	///
+	/// The timestamp is read from the `Now` value of the timestamp pallet. Blocks that don't have
+	/// it are considered as old as the next block that does.
+	fn state_timestamp(&self, hash: Block::Hash) -> Option<u64> {
+		if !self.timestamp_pruning {
+			return None
+		}
+		let state = self.trie_state_at(hash).ok()?;
+		let value = state.storage(&timestamp_key()).ok()??;
+		u64::decode(&mut &value[..]).ok()
+	}
+
+	/// Checks that the timestamps used to prune the state by age can be read.
+	///
+	/// The timestamps are read from the `Now` value of the timestamp pallet. Pruning by age is
+	/// refused if the last finalized state, other than the genesis state, doesn't have it.
+	fn check_state_timestamps(&self) -> ClientResult<()> {
+		let (hash, number) = match self.blockchain.info().finalized_state {
+			Some((hash, number)) if !number.is_zero() => (hash, number),
+			_ => return Ok(()),
+		};
+		if self.state_timestamp(hash).is_none() {
+			return Err(sp_blockchain::Error::Backend(format!(
+				"Can't prune the state by age: no `Timestamp::Now` value in the state of block #{}",
+				number,
+			)))
+		}
+		Ok(())
+	}
+
+	/// Compares the flat state with the trie.
+	///
+	/// Every value of the trie state of the flat state block is looked up in the flat state.
+	/// Returns `None` if the flat state is disabled or hasn't been built yet.
+	pub fn check_flat_state(&self) -> ClientResult<Option<FlatStateCheck<Block::Hash>>> {
+		match &self.flat_state {
+			Some(flat) => flat.check(|hash| self.trie_state_at(hash)),
+			None => Ok(None),
+		}
+	}
+
+	/// Waits for the flat state to be rebuilt, if it is being rebuilt in the background, and
+	/// returns the block whose state it stores.
+	///
+	/// Returns `None` if the flat state is disabled or couldn't be built.
+	pub fn wait_for_flat_state(&self) -> Option<Block::Hash> {
+		self.flat_state.as_ref().and_then(|flat| flat.wait_for_jobs())
+	}
+
 	/// Handle setting head within a transaction. `route_to` should be the last
 	/// block that existed in the database. `best_to` should be the best block
 	/// to be set.
@@ -1264,6 +1609,17 @@ impl<Block: BlockT> Backend<Block> {
 		Ok(MetaUpdate { hash: *hash, number, is_best: false, is_finalized: true, with_state })
 	}
 
+	fn canonicalize_state(
+		&self,
+		hash: Block::Hash,
+	) -> ClientResult<sc_state_db::CommitSet<Vec<u8>>> {
+		let timestamp = self.state_timestamp(hash);
+		self.storage
+			.state_db
+			.canonicalize_block_with_timestamp(&hash, timestamp)
+			.map_err(sp_blockchain::Error::from_state_db::<sc_state_db::Error<io::Error>>)
+	}
+
 	// performs forced canonicalization with a delay after importing a non-finalized block.
 	fn force_delayed_canonicalize(
 		&self,
@@ -1297,10 +1653,7 @@ impl<Block: BlockT> Backend<Block> {
 			}
 
 			trace!(target: "db", "Canonicalize block #{} ({:?})", new_canonical, hash);
-			let commit =
-				self.storage.state_db.canonicalize_block(&hash).map_err(
-					sp_blockchain::Error::from_state_db::<sc_state_db::Error<io::Error>>,
-				)?;
+			let commit = self.canonicalize_state(hash)?;
 			apply_state_commit(transaction, commit);
 		}
 		Ok(())
@@ -1427,6 +1780,9 @@ impl<Block: BlockT> Backend<Block> {
 				self.state_usage.tally_writes_nodes(ops, bytes);
 				self.state_usage.tally_removed_nodes(removal, bytes_removal);
 
+				for (key, value) in operation.storage_updates.iter() {
+					self.state_usage.tally_key_write(key, value.as_deref());
+				}
 				let mut ops: u64 = 0;
 				let mut bytes: u64 = 0;
 				for (key, value) in operation
@@ -1441,6 +1797,25 @@ impl<Block: BlockT> Backend<Block> {
 					}
 				}
 				self.state_usage.tally_writes(ops, bytes);
+				if let Some(flat) = &self.flat_state {
+					flat.note_block(
+						&mut transaction,
+						hash,
+						parent_hash,
+						number,
+						operation.reset_state,
+						&operation.storage_updates,
+						&operation.child_storage_updates,
+					);
+				}
+				if let Some(index) = &self.diff_index {
+					index.note_block(
+						&mut transaction,
+						&hash,
+						operation.reset_state,
+						&operation.storage_updates,
+					);
+				}
 				let number_u64 = number.saturated_into::<u64>();
 				let commit = self
 					.storage
@@ -1452,9 +1827,25 @@ impl<Block: BlockT> Backend<Block> {
 				apply_state_commit(&mut transaction, commit);
 				if number <= last_finalized_num {
 					// Canonicalize in the db when re-importing existing blocks with state.
-					let commit = self.storage.state_db.canonicalize_block(&hash).map_err(
-						sp_blockchain::Error::from_state_db::<sc_state_db::Error<io::Error>>,
-					)?;
+					// The state isn't committed yet, so the timestamp is taken from the changes.
+					let timestamp = if self.timestamp_pruning {
+						let key = timestamp_key();
+						operation
+							.storage_updates
+							.iter()
+							.find(|(k, _)| *k == key)
+							.and_then(|(_, v)| v.as_ref())
+							.and_then(|v| u64::decode(&mut &v[..]).ok())
+					} else {
+						None
+					};
+					let commit = self
+						.storage
+						.state_db
+						.canonicalize_block_with_timestamp(&hash, timestamp)
+						.map_err(
+							sp_blockchain::Error::from_state_db::<sc_state_db::Error<io::Error>>,
+						)?;
 					apply_state_commit(&mut transaction, commit);
 					meta_updates.push(MetaUpdate {
 						hash,
@@ -1552,10 +1943,8 @@ impl<Block: BlockT> Backend<Block> {
 						);
 					}
 				} else if number > best_num + One::one() &&
-					number > One::one() && self
-					.blockchain
-					.header(BlockId::hash(parent_hash))?
-					.is_none()
+					number > One::one() &&
+					self.blockchain.header(BlockId::hash(parent_hash))?.is_none()
 				{
 					let gap = (best_num + One::one(), number - One::one());
 					transaction.set(columns::META, meta_keys::BLOCK_GAP, &gap.encode());
@@ -1662,13 +2051,16 @@ impl<Block: BlockT> Backend<Block> {
 				.map(|c| f_num.saturated_into::<u64>() > c)
 				.unwrap_or(true)
 		{
-			let commit =
-				self.storage.state_db.canonicalize_block(&f_hash).map_err(
-					sp_blockchain::Error::from_state_db::<sc_state_db::Error<io::Error>>,
-				)?;
+			let commit = self.canonicalize_state(f_hash)?;
 			apply_state_commit(transaction, commit);
 		}
 
+		if let KeepJustifications::AuthoritySetChanges =
+			self.blockchain.blocks_pruning.justifications
+		{
+			self.pin_authority_set_change(transaction, f_header);
+		}
+
 		let new_displaced = self.blockchain.leaves.write().finalize_height(f_num);
 		self.prune_blocks(transaction, f_num, &new_displaced)?;
 		match displaced {
@@ -1679,18 +2071,47 @@ impl<Block: BlockT> Backend<Block> {
 		Ok(())
 	}
 
+	/// Keeps the justification of the block enacting the GRANDPA authority set change signaled
+	/// by the given finalized header, if any.
+	///
+	/// The justification of the enacting block is only imported once that block is finalized,
+	/// `delay` blocks later, so its number is persisted until then.
+	fn pin_authority_set_change(
+		&self,
+		transaction: &mut Transaction<DbHash>,
+		header: &Block::Header,
+	) {
+		let id = OpaqueDigestItemId::Consensus(&GRANDPA_ENGINE_ID);
+		let change = header.digest().convert_first(|log| {
+			log.try_to::<GrandpaConsensusLog<NumberFor<Block>>>(id).and_then(|log| match log {
+				GrandpaConsensusLog::ScheduledChange(change) => Some(change),
+				_ => None,
+			})
+		});
+
+		if let Some(change) = change {
+			let mut pins = self.blockchain.justification_pins.lock();
+			pins.insert(*header.number() + change.delay);
+			write_justification_pins::<Block>(transaction, &pins);
+		}
+	}
+
 	fn prune_blocks(
 		&self,
 		transaction: &mut Transaction<DbHash>,
 		finalized: NumberFor<Block>,
 		displaced: &FinalizationDisplaced<Block::Hash, NumberFor<Block>>,
 	) -> ClientResult<()> {
-		if let KeepBlocks::Some(keep_blocks) = self.keep_blocks {
-			// Always keep the last finalized block
-			let keep = std::cmp::max(keep_blocks, 1);
-			if finalized >= keep.into() {
-				let number = finalized.saturating_sub(keep.into());
-				self.prune_block(transaction, BlockId::<Block>::number(number))?;
+		let pruning = self.blockchain.blocks_pruning;
+		let separate_indexed_transactions = pruning.separate_indexed_transactions();
+
+		if let Some(keep) = pruning.bodies.recent() {
+			if let Some(number) = prunable_height::<Block>(finalized, keep) {
+				self.prune_block(
+					transaction,
+					BlockId::<Block>::number(number),
+					separate_indexed_transactions.is_none(),
+				)?;
 			}
 
 			// Also discard all blocks from displaced branches
@@ -1705,7 +2126,12 @@ impl<Block: BlockT> Backend<Block> {
 					let id = BlockId::<Block>::hash(hash);
 					match self.blockchain.header(id)? {
 						Some(header) => {
-							self.prune_block(transaction, id)?;
+							// The indexed transactions of displaced blocks are only ever
+							// released along with their bodies.
+							self.prune_block(transaction, id, true)?;
+							if let Some(index) = &self.diff_index {
+								index.remove_block(transaction, &hash);
+							}
 							number = header.number().saturating_sub(One::one());
 							hash = *header.parent_hash();
 						},
@@ -1714,6 +2140,28 @@ impl<Block: BlockT> Backend<Block> {
 				}
 			}
 		}
+
+		if let Some(keep) = separate_indexed_transactions {
+			if let Some(number) = prunable_height::<Block>(finalized, keep) {
+				self.prune_indexed_transactions(transaction, BlockId::<Block>::number(number))?;
+			}
+		}
+
+		if let Some(keep) = pruning.justifications.recent() {
+			if let Some(number) = prunable_height::<Block>(finalized, keep) {
+				let authority_set_changes =
+					matches!(pruning.justifications, KeepJustifications::AuthoritySetChanges);
+				self.prune_justifications(transaction, number, authority_set_changes)?;
+			}
+		}
+
+		if let Some(keep) = pruning.headers.recent() {
+			match prunable_height::<Block>(finalized, keep) {
+				Some(number) if !number.is_zero() => self.prune_header(transaction, number)?,
+				_ => {},
+			}
+		}
+
 		Ok(())
 	}
 
@@ -1721,6 +2169,7 @@ impl<Block: BlockT> Backend<Block> {
 		&self,
 		transaction: &mut Transaction<DbHash>,
 		id: BlockId<Block>,
+		release_indexed_transactions: bool,
 	) -> ClientResult<()> {
 		debug!(target: "db", "Removing block #{}", id);
 		utils::remove_from_db(
@@ -1740,20 +2189,78 @@ impl<Block: BlockT> Backend<Block> {
 				columns::BODY_INDEX,
 				id,
 			)?;
-			match Vec::<DbExtrinsic<Block>>::decode(&mut &index[..]) {
-				Ok(index) =>
-					for ex in index {
-						if let DbExtrinsic::Indexed { hash, .. } = ex {
-							transaction.release(columns::TRANSACTION, hash);
-						}
-					},
-				Err(err) =>
-					return Err(sp_blockchain::Error::Backend(format!(
-						"Error decoding body list: {}",
-						err
-					))),
+			if release_indexed_transactions {
+				release_indexed_transactions::<Block>(transaction, &index)?;
+			}
+		}
+		Ok(())
+	}
+
+	fn prune_indexed_transactions(
+		&self,
+		transaction: &mut Transaction<DbHash>,
+		id: BlockId<Block>,
+	) -> ClientResult<()> {
+		if let Some(index) =
+			read_db(&*self.storage.db, columns::KEY_LOOKUP, columns::BODY_INDEX, id)?
+		{
+			debug!(target: "db", "Removing indexed transactions of block #{}", id);
+			release_indexed_transactions::<Block>(transaction, &index)?;
+		}
+		Ok(())
+	}
+
+	fn prune_justifications(
+		&self,
+		transaction: &mut Transaction<DbHash>,
+		number: NumberFor<Block>,
+		authority_set_changes: bool,
+	) -> ClientResult<()> {
+		if authority_set_changes {
+			let mut pins = self.blockchain.justification_pins.lock();
+			let enacting = pins.contains(&number);
+			// Pins of blocks finalized without a justification are released as well.
+			if pins.range(..=number).next().is_some() {
+				*pins = pins.split_off(&(number + One::one()));
+				write_justification_pins::<Block>(transaction, &pins);
+			}
+			if enacting {
+				return Ok(())
 			}
 		}
+
+		let id = BlockId::<Block>::number(number);
+		if self.blockchain.justifications(id)?.is_none() {
+			return Ok(())
+		}
+
+		debug!(target: "db", "Removing justifications of block #{}", number);
+		if let Some(key) = self.blockchain.lookup_key(id)? {
+			transaction.remove(columns::JUSTIFICATIONS, &key);
+		}
+		Ok(())
+	}
+
+	fn prune_header(
+		&self,
+		transaction: &mut Transaction<DbHash>,
+		number: NumberFor<Block>,
+	) -> ClientResult<()> {
+		let hash = match self.blockchain.hash(number)? {
+			Some(hash) => hash,
+			None => return Ok(()),
+		};
+
+		debug!(target: "db", "Removing header #{}", number);
+		utils::remove_from_db(
+			transaction,
+			&*self.storage.db,
+			columns::KEY_LOOKUP,
+			columns::HEADER,
+			BlockId::<Block>::number(number),
+		)?;
+		self.blockchain.header_metadata_cache.remove_header_metadata(hash);
+		self.blockchain.header_cache.lock().remove(&hash);
 		Ok(())
 	}
 
@@ -1771,6 +2278,40 @@ impl<Block: BlockT> Backend<Block> {
 	}
 }
 
+/// Releases the indexed transactions referenced by the given encoded body index.
+/// Persists the numbers of the blocks whose justifications are kept by
+/// [`KeepJustifications::AuthoritySetChanges`].
+fn write_justification_pins<Block: BlockT>(
+	transaction: &mut Transaction<DbHash>,
+	pins: &BTreeSet<NumberFor<Block>>,
+) {
+	let pins = pins.iter().copied().collect::<Vec<_>>();
+	transaction.set_from_vec(columns::META, meta_keys::JUSTIFICATION_PINS, pins.encode());
+}
+
+fn release_indexed_transactions<Block: BlockT>(
+	transaction: &mut Transaction<DbHash>,
+	index: &[u8],
+) -> ClientResult<()> {
+	match Vec::<DbExtrinsic<Block>>::decode(&mut &index[..]) {
+		Ok(index) => {
+			for ex in index {
+				if let DbExtrinsic::Indexed { hash, .. } = ex {
+					transaction.release(columns::TRANSACTION, hash);
+				}
+			}
+			Ok(())
+		},
+		Err(err) =>
+			Err(sp_blockchain::Error::Backend(format!("Error decoding body list: {}", err))),
+	}
+}
+
+/// Storage key of the timestamp pallet's `Now` value, the timestamp of the block in milliseconds.
+fn timestamp_key() -> Vec<u8> {
+	[sp_core::hashing::twox_128(b"Timestamp"), sp_core::hashing::twox_128(b"Now")].concat()
+}
+
 fn apply_state_commit(
 	transaction: &mut Transaction<DbHash>,
 	commit: sc_state_db::CommitSet<Vec<u8>>,
@@ -1886,6 +2427,67 @@ where
 	}
 }
 
+impl<Block: BlockT> StateChangesIndex<Block> for Backend<Block> {
+	fn changed_keys(&self, hash: &Block::Hash) -> ClientResult<Option<Vec<StorageKey>>> {
+		match &self.diff_index {
+			Some(index) => Ok(index
+				.changed_keys(hash)?
+				.map(|keys| keys.into_iter().map(StorageKey).collect())),
+			None => Ok(None),
+		}
+	}
+
+	fn key_changes(
+		&self,
+		first: NumberFor<Block>,
+		last: &Block::Hash,
+		key: &StorageKey,
+	) -> ClientResult<Option<Vec<(NumberFor<Block>, Block::Hash)>>> {
+		let index = match &self.diff_index {
+			Some(index) => index,
+			None => return Ok(None),
+		};
+		let linked = index.linked_head().map(|(_, number)| number);
+		let mut changes = Vec::new();
+		let mut current = self.blockchain.header_metadata(*last)?;
+		// The changes of the blocks that aren't linked yet are read block by block, down to the
+		// linked finalized blocks.
+		while current.number >= first {
+			let number = current.number.saturated_into::<u64>();
+			if linked.map_or(false, |linked| number <= linked) &&
+				self.blockchain.hash(current.number)? == Some(current.hash)
+			{
+				let finalized =
+					match index.finalized_changes(&key.0, first.saturated_into(), number)? {
+						Some(finalized) => finalized,
+						None => return Ok(None),
+					};
+				for number in finalized.into_iter().rev() {
+					let number = number.saturated_into::<NumberFor<Block>>();
+					let hash = self.blockchain.hash(number)?.ok_or_else(|| {
+						sp_blockchain::Error::UnknownBlock(format!("Missing block #{}", number))
+					})?;
+					changes.push((number, hash));
+				}
+				break
+			}
+			match index.changed_keys(&current.hash)? {
+				Some(keys) =>
+					if keys.binary_search(&key.0).is_ok() {
+						changes.push((current.number, current.hash));
+					},
+				None => return Ok(None),
+			}
+			if current.number.is_zero() {
+				break
+			}
+			current = self.blockchain.header_metadata(current.parent)?;
+		}
+		changes.reverse();
+		Ok(Some(changes))
+	}
+}
+
 impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 	type BlockImportOperation = BlockImportOperation<Block>;
 	type Blockchain = BlockchainDb<Block>;
@@ -1907,6 +2509,7 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 			finalized_blocks: Vec::new(),
 			set_head: None,
 			commit_state: false,
+			reset_state: false,
 			index_ops: Default::default(),
 		})
 	}
@@ -1934,6 +2537,8 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 		match self.try_commit_operation(operation) {
 			Ok(_) => {
 				self.storage.state_db.apply_pending();
+				self.sync_flat_state();
+				self.sync_state_diff_index();
 				Ok(())
 			},
 			e @ Err(_) => {
@@ -1963,6 +2568,8 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 		)?;
 		self.storage.db.commit(transaction)?;
 		self.blockchain.update_meta(m);
+		self.sync_flat_state();
+		self.sync_state_diff_index();
 		Ok(())
 	}
 
@@ -2013,6 +2620,10 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 		Some(self.offchain_storage.clone())
 	}
 
+	fn storage_item_usage(&self) -> Option<Vec<StorageItemUsage>> {
+		self.state_usage.items()
+	}
+
 	fn usage_info(&self) -> Option<UsageInfo> {
 		let (io_stats, state_stats) = self.io_stats.take_or_else(|| {
 			(
@@ -2040,6 +2651,7 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 				state_writes_cache: state_stats.overlay_writes.ops,
 				state_reads_cache: state_stats.cache_reads.ops,
 				state_writes_nodes: state_stats.nodes_writes.ops,
+				storage_items: self.state_usage.items().unwrap_or_default(),
 			},
 		})
 	}
@@ -2127,6 +2739,12 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 						}
 						transaction.set_from_vec(columns::META, meta_keys::BEST_BLOCK, key);
 						transaction.remove(columns::KEY_LOOKUP, removed.hash().as_ref());
+						if let Some(flat) = &self.flat_state {
+							flat.remove_block(&mut transaction, &removed_hash);
+						}
+						if let Some(index) = &self.diff_index {
+							index.remove_block(&mut transaction, &removed_hash);
+						}
 						children::remove_children(
 							&mut transaction,
 							columns::META,
@@ -2195,6 +2813,9 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 			apply_state_commit(&mut transaction, commit);
 		}
 		transaction.remove(columns::KEY_LOOKUP, hash.as_ref());
+		if let Some(index) = &self.diff_index {
+			index.remove_block(&mut transaction, hash);
+		}
 		leaves.revert(*hash, hdr.number);
 		leaves.prepare_transaction(&mut transaction, columns::META, meta_keys::LEAF_PREFIX);
 		self.storage.db.commit(transaction)?;
@@ -2206,6 +2827,10 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 		&self.blockchain
 	}
 
+	fn state_changes_index(&self) -> Option<&dyn StateChangesIndex<Block>> {
+		self.diff_index.as_ref().map(|_| self as &dyn StateChangesIndex<Block>)
+	}
+
 	fn state_at(&self, block: BlockId<Block>) -> ClientResult<Self::State> {
 		use sc_client_api::blockchain::HeaderBackend as BcHeaderBackend;
 
@@ -2248,7 +2873,10 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 				if let Ok(()) = self.storage.state_db.pin(&hash) {
 					let root = hdr.state_root;
 					let db_state = DbState::<Block>::new(self.storage.clone(), root);
-					let state = RefTrackingState::new(db_state, self.storage.clone(), Some(hash));
+					let state = RefTrackingState::new(db_state, self.storage.clone(), Some(hash))
+						.with_flat_state(
+							self.flat_state.as_ref().and_then(|flat| flat.reader(hash)),
+						);
 					let caching_state =
 						CachingState::new(state, self.shared_cache.clone(), Some(hash));
 					Ok(SyncingCachingState::new(
@@ -2436,7 +3064,10 @@ pub(crate) mod tests {
 				state_cache_child_ratio: Some((50, 100)),
 				state_pruning: Some(PruningMode::keep_blocks(1)),
 				source: DatabaseSource::Custom { db: backing, require_create_flag: false },
-				keep_blocks: KeepBlocks::All,
+				blocks_pruning: BlocksPruning::keep_all(),
+				flat_state: false,
+				state_diff_index: false,
+				storage_item_stats: false,
 			},
 			0,
 		)
@@ -2529,6 +3160,264 @@ pub(crate) mod tests {
 		}
 	}
 
+	#[test]
+	fn storage_item_usage_is_tallied() {
+		let db = Backend::<Block>::new_test(2, 0);
+		let item = [7u8; 32];
+		let key = |suffix: u8| {
+			let mut key = item.to_vec();
+			key.push(suffix);
+			key
+		};
+
+		let genesis = insert_header(&db, 0, Default::default(), None, Default::default());
+		{
+			let mut op = db.begin_operation().unwrap();
+			db.begin_state_operation(&mut op, BlockId::Hash(genesis)).unwrap();
+			let mut header = Header {
+				number: 1,
+				parent_hash: genesis,
+				state_root: Default::default(),
+				digest: Default::default(),
+				extrinsics_root: Default::default(),
+			};
+
+			let storage = vec![
+				(key(1), Some(vec![1, 2])),
+				(key(2), Some(vec![3])),
+				(vec![1, 2], Some(vec![4])),
+			];
+
+			let (root, overlay) = op.old_state.storage_root(
+				storage.iter().map(|(k, v)| (k.as_slice(), v.as_ref().map(|v| &v[..]))),
+				StateVersion::V1,
+			);
+			op.update_db_storage(overlay).unwrap();
+			header.state_root = root.into();
+
+			op.update_storage(storage, Vec::new()).unwrap();
+			op.set_block_data(header, Some(vec![]), None, None, NewBlockState::Best)
+				.unwrap();
+
+			db.commit_operation(op).unwrap();
+		}
+
+		{
+			let state = db.state_at(BlockId::Number(1)).unwrap();
+			assert_eq!(state.storage(&key(1)).unwrap(), Some(vec![1, 2]));
+			assert_eq!(state.storage(&key(1)).unwrap(), Some(vec![1, 2]));
+			assert_eq!(state.storage(&[1, 2]).unwrap(), Some(vec![4]));
+		}
+
+		let usage = db.storage_item_usage().unwrap();
+		assert_eq!(usage.len(), 1);
+		assert_eq!(usage[0].prefix, item);
+		assert_eq!((usage[0].reads, usage[0].bytes_read), (2, 70));
+		// The second read is served by the local cache at least.
+		assert!(usage[0].cache_reads >= 1);
+		assert_eq!((usage[0].writes, usage[0].bytes_written), (2, 69));
+	}
+
+	fn flat_state_backend(db: Arc<dyn Database<DbHash>>, flat_state: bool) -> Backend<Block> {
+		Backend::<Block>::new(
+			DatabaseSettings {
+				state_cache_size: 0,
+				state_cache_child_ratio: None,
+				state_pruning: Some(PruningMode::ArchiveAll),
+				source: DatabaseSource::Custom { db, require_create_flag: false },
+				blocks_pruning: BlocksPruning::keep_all(),
+				flat_state,
+				state_diff_index: false,
+				storage_item_stats: false,
+			},
+			0,
+		)
+		.unwrap()
+	}
+
+	pub fn insert_state_block(
+		backend: &Backend<Block>,
+		number: u64,
+		parent_hash: H256,
+		changes: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+	) -> H256 {
+		let state_version = StateVersion::default();
+		let mut op = backend.begin_operation().unwrap();
+		let mut header = Header {
+			number,
+			parent_hash,
+			state_root: Default::default(),
+			digest: Default::default(),
+			extrinsics_root: Default::default(),
+		};
+		if number == 0 {
+			backend
+				.begin_state_operation(&mut op, BlockId::Hash(Default::default()))
+				.unwrap();
+			let top = changes.into_iter().filter_map(|(k, v)| Some((k, v?))).collect();
+			header.state_root = op
+				.reset_storage(Storage { top, children_default: Default::default() }, state_version)
+				.unwrap();
+		} else {
+			backend.begin_state_operation(&mut op, BlockId::Hash(parent_hash)).unwrap();
+			let (root, overlay) = op.old_state.storage_root(
+				changes.iter().map(|(k, v)| (k.as_slice(), v.as_deref())),
+				state_version,
+			);
+			op.update_db_storage(overlay).unwrap();
+			op.update_storage(changes, Vec::new()).unwrap();
+			header.state_root = root;
+		}
+		let hash = header.hash();
+		op.set_block_data(header, Some(vec![]), None, None, NewBlockState::Best)
+			.unwrap();
+		backend.commit_operation(op).unwrap();
+		hash
+	}
+
+	#[test]
+	fn flat_state_follows_finalization() {
+		let db = sp_database::as_database(kvdb_memorydb::create(crate::utils::NUM_COLUMNS));
+		let backend = flat_state_backend(db, true);
+		let genesis = insert_state_block(
+			&backend,
+			0,
+			Default::default(),
+			vec![(vec![1], Some(vec![1])), (vec![2], Some(vec![2]))],
+		);
+		// The flat state is built in the background.
+		assert_eq!(backend.wait_for_flat_state(), Some(genesis));
+		let check = backend.check_flat_state().unwrap().unwrap();
+		assert_eq!((check.block, check.entries, check.mismatches), (genesis, 2, 0));
+
+		let block1 = insert_state_block(
+			&backend,
+			1,
+			genesis,
+			vec![(vec![1], None), (vec![3], Some(vec![3]))],
+		);
+		let block2 = insert_state_block(&backend, 2, block1, vec![(vec![2], Some(vec![4]))]);
+
+		// The unfinalized blocks are read through their deltas.
+		let flat = backend.flat_state.as_ref().unwrap();
+		assert!(flat.reader(block2).is_some());
+		let state = backend.state_at(BlockId::Hash(block2)).unwrap();
+		assert_eq!(state.storage(&[1]).unwrap(), None);
+		assert_eq!(state.storage(&[2]).unwrap(), Some(vec![4]));
+		assert_eq!(state.storage(&[3]).unwrap(), Some(vec![3]));
+		let state = backend.state_at(BlockId::Hash(genesis)).unwrap();
+		assert_eq!(state.storage(&[1]).unwrap(), Some(vec![1]));
+
+		backend.finalize_block(BlockId::Hash(block1), None).unwrap();
+		let check = backend.check_flat_state().unwrap().unwrap();
+		assert_eq!((check.block, check.entries, check.mismatches), (block1, 2, 0));
+
+		// The genesis state is no longer the flat state, it is read from the trie.
+		assert!(flat.reader(genesis).is_none());
+		let state = backend.state_at(BlockId::Hash(genesis)).unwrap();
+		assert_eq!(state.storage(&[1]).unwrap(), Some(vec![1]));
+		let state = backend.state_at(BlockId::Hash(block2)).unwrap();
+		assert_eq!(state.storage(&[2]).unwrap(), Some(vec![4]));
+		assert_eq!(state.storage(&[3]).unwrap(), Some(vec![3]));
+	}
+
+	#[test]
+	fn flat_state_is_built_when_enabled() {
+		let db = sp_database::as_database(kvdb_memorydb::create(crate::utils::NUM_COLUMNS));
+		let block1 = {
+			let backend = flat_state_backend(db.clone(), false);
+			let genesis =
+				insert_state_block(&backend, 0, Default::default(), vec![(vec![1], Some(vec![1]))]);
+			let block1 = insert_state_block(&backend, 1, genesis, vec![(vec![2], Some(vec![2]))]);
+			backend.finalize_block(BlockId::Hash(block1), None).unwrap();
+			assert_eq!(backend.check_flat_state().unwrap(), None);
+			block1
+		};
+
+		let backend = flat_state_backend(db, true);
+		assert_eq!(backend.wait_for_flat_state(), Some(block1));
+		let check = backend.check_flat_state().unwrap().unwrap();
+		assert_eq!((check.block, check.entries, check.mismatches), (block1, 2, 0));
+		let state = backend.state_at(BlockId::Hash(block1)).unwrap();
+		assert_eq!(state.storage(&[2]).unwrap(), Some(vec![2]));
+	}
+
+	#[test]
+	fn flat_state_rebuild_removes_stale_generation() {
+		// Value of the key `[1]` in the given generation of the flat state.
+		fn flat_value(db: &Arc<dyn Database<DbHash>>, generation: u8) -> Option<Vec<u8>> {
+			db.get(columns::FLAT_STATE, &[0, 0, 0, generation, 0, 1])
+		}
+
+		let db = sp_database::as_database(kvdb_memorydb::create(crate::utils::NUM_COLUMNS));
+		let genesis = {
+			let backend = flat_state_backend(db.clone(), true);
+			let genesis =
+				insert_state_block(&backend, 0, Default::default(), vec![(vec![1], Some(vec![1]))]);
+			assert_eq!(backend.wait_for_flat_state(), Some(genesis));
+			genesis
+		};
+		assert_eq!(flat_value(&db, 1), Some(vec![1]));
+
+		// The changes of the blocks finalized without the flat state are missing.
+		let block1 = {
+			let backend = flat_state_backend(db.clone(), false);
+			let block1 = insert_state_block(&backend, 1, genesis, vec![(vec![1], Some(vec![2]))]);
+			backend.finalize_block(BlockId::Hash(block1), None).unwrap();
+			block1
+		};
+
+		let backend = flat_state_backend(db.clone(), true);
+		assert_eq!(backend.wait_for_flat_state(), Some(block1));
+		assert_eq!(flat_value(&db, 1), None);
+		assert_eq!(flat_value(&db, 2), Some(vec![2]));
+		let key_log = [&meta_keys::FLAT_STATE_KEYS_PREFIX[..], &[0, 0, 0, 1, 0, 0, 0, 0]].concat();
+		assert!(!db.contains(columns::META, &key_log));
+		let check = backend.check_flat_state().unwrap().unwrap();
+		assert_eq!((check.block, check.entries, check.mismatches), (block1, 1, 0));
+	}
+
+	#[test]
+	fn state_diff_index_follows_finalization() {
+		let db = sp_database::as_database(kvdb_memorydb::create(crate::utils::NUM_COLUMNS));
+		let backend = Backend::<Block>::new(
+			DatabaseSettings {
+				state_cache_size: 0,
+				state_cache_child_ratio: None,
+				state_pruning: Some(PruningMode::ArchiveAll),
+				source: DatabaseSource::Custom { db, require_create_flag: false },
+				blocks_pruning: BlocksPruning::keep_all(),
+				flat_state: false,
+				state_diff_index: true,
+				storage_item_stats: false,
+			},
+			0,
+		)
+		.unwrap();
+		let genesis =
+			insert_state_block(&backend, 0, Default::default(), vec![(vec![1], Some(vec![0]))]);
+		let block1 = insert_state_block(&backend, 1, genesis, vec![(vec![1], Some(vec![1]))]);
+		let block2 = insert_state_block(&backend, 2, block1, vec![(vec![2], Some(vec![2]))]);
+		let block3 = insert_state_block(&backend, 3, block2, vec![(vec![1], None)]);
+		let index = backend.state_changes_index().unwrap();
+		assert_eq!(index.changed_keys(&genesis).unwrap(), None);
+		assert_eq!(index.changed_keys(&block1).unwrap(), Some(vec![StorageKey(vec![1])]));
+
+		let key = StorageKey(vec![1]);
+		let expected = Some(vec![(1, block1), (3, block3)]);
+		assert_eq!(index.key_changes(1, &block3, &key).unwrap(), expected);
+		assert_eq!(index.key_changes(0, &block3, &key).unwrap(), None);
+
+		backend.finalize_block(BlockId::Hash(block2), None).unwrap();
+		assert_eq!(backend.diff_index.as_ref().unwrap().linked_head(), Some((block2, 2)));
+		assert_eq!(index.key_changes(1, &block3, &key).unwrap(), expected);
+		assert_eq!(index.key_changes(2, &block3, &key).unwrap(), Some(vec![(3, block3)]));
+		assert_eq!(
+			index.key_changes(1, &block3, &StorageKey(vec![2])).unwrap(),
+			Some(vec![(2, block2)]),
+		);
+	}
+
 	#[test]
 	fn delete_only_when_negative_rc() {
 		sp_tracing::try_init_simple();
@@ -3140,6 +4029,160 @@ pub(crate) mod tests {
 		assert_eq!(Some(vec![4.into()]), bc.body(BlockId::hash(blocks[4])).unwrap());
 	}
 
+	#[test]
+	fn prune_columns_independently_on_finalize() {
+		let backend = Backend::<Block>::new(
+			DatabaseSettings {
+				state_cache_size: 16777216,
+				state_cache_child_ratio: Some((50, 100)),
+				state_pruning: Some(PruningMode::keep_blocks(1)),
+				source: DatabaseSource::Custom {
+					db: sp_database::as_database(kvdb_memorydb::create(crate::utils::NUM_COLUMNS)),
+					require_create_flag: true,
+				},
+				blocks_pruning: BlocksPruning {
+					headers: KeepBlocks::Some(3),
+					bodies: KeepBlocks::Some(2),
+					indexed_transactions: KeepBlocks::All,
+					justifications: KeepJustifications::Some(1),
+				},
+				flat_state: false,
+				state_diff_index: false,
+				storage_item_stats: false,
+			},
+			0,
+		)
+		.unwrap();
+		let mut blocks = Vec::new();
+		let mut prev_hash = Default::default();
+		for i in 0..5 {
+			let hash = insert_block(
+				&backend,
+				i,
+				prev_hash,
+				None,
+				Default::default(),
+				vec![i.into()],
+				None,
+			)
+			.unwrap();
+			blocks.push(hash);
+			prev_hash = hash;
+		}
+
+		for i in 1..5 {
+			let mut op = backend.begin_operation().unwrap();
+			backend.begin_state_operation(&mut op, BlockId::Hash(blocks[i])).unwrap();
+			op.mark_finalized(BlockId::Hash(blocks[i]), Some((CONS0_ENGINE_ID, vec![i as u8])))
+				.unwrap();
+			backend.commit_operation(op).unwrap();
+		}
+
+		let bc = backend.blockchain();
+		// The genesis header is always kept.
+		assert!(bc.header(BlockId::hash(blocks[0])).unwrap().is_some());
+		assert_eq!(None, bc.header(BlockId::hash(blocks[1])).unwrap());
+		assert!(bc.header(BlockId::hash(blocks[2])).unwrap().is_some());
+		assert_eq!(Some(blocks[1]), bc.hash(1).unwrap());
+		assert_eq!(Some(1), bc.number(blocks[1]).unwrap());
+
+		assert_eq!(None, bc.body(BlockId::hash(blocks[2])).unwrap());
+		assert_eq!(Some(vec![3.into()]), bc.body(BlockId::hash(blocks[3])).unwrap());
+
+		assert_eq!(None, bc.justifications(BlockId::hash(blocks[3])).unwrap());
+		assert_eq!(
+			Some(Justifications::from((CONS0_ENGINE_ID, vec![4]))),
+			bc.justifications(BlockId::hash(blocks[4])).unwrap()
+		);
+
+		assert_eq!(Some(2), bc.pruned_below(PrunableBlockData::Header));
+		assert_eq!(Some(3), bc.pruned_below(PrunableBlockData::Body));
+	}
+
+	#[test]
+	fn keep_justifications_of_delayed_authority_set_changes() {
+		use sp_finality_grandpa::ScheduledChange;
+		use sp_runtime::{testing::Digest, DigestItem};
+
+		let backend = Backend::<Block>::new(
+			DatabaseSettings {
+				state_cache_size: 16777216,
+				state_cache_child_ratio: Some((50, 100)),
+				state_pruning: Some(PruningMode::keep_blocks(1)),
+				source: DatabaseSource::Custom {
+					db: sp_database::as_database(kvdb_memorydb::create(crate::utils::NUM_COLUMNS)),
+					require_create_flag: true,
+				},
+				blocks_pruning: BlocksPruning {
+					justifications: KeepJustifications::AuthoritySetChanges,
+					..BlocksPruning::keep_all()
+				},
+				flat_state: false,
+				state_diff_index: false,
+				storage_item_stats: false,
+			},
+			0,
+		)
+		.unwrap();
+
+		// Block 1 signals a change of authority set, enacted two blocks later.
+		let change = GrandpaConsensusLog::ScheduledChange(ScheduledChange {
+			next_authorities: Vec::new(),
+			delay: 2u64,
+		});
+		let mut blocks = Vec::new();
+		let mut prev_hash = Default::default();
+		for i in 0..5 {
+			let logs = match i {
+				1 => vec![DigestItem::Consensus(GRANDPA_ENGINE_ID, change.encode())],
+				_ => Vec::new(),
+			};
+			let header = Header {
+				number: i,
+				parent_hash: prev_hash,
+				state_root: BlakeTwo256::trie_root(Vec::new(), StateVersion::V1),
+				digest: Digest { logs },
+				extrinsics_root: Default::default(),
+			};
+			prev_hash = header.hash();
+			blocks.push(prev_hash);
+
+			let block_id = if i == 0 {
+				BlockId::Hash(Default::default())
+			} else {
+				BlockId::Number(i - 1)
+			};
+			let mut op = backend.begin_operation().unwrap();
+			backend.begin_state_operation(&mut op, block_id).unwrap();
+			op.set_block_data(header, Some(Vec::new()), None, None, NewBlockState::Best).unwrap();
+			backend.commit_operation(op).unwrap();
+		}
+
+		for i in 1..5 {
+			let mut op = backend.begin_operation().unwrap();
+			backend.begin_state_operation(&mut op, BlockId::Hash(blocks[i])).unwrap();
+			op.mark_finalized(BlockId::Hash(blocks[i]), Some((GRANDPA_ENGINE_ID, vec![i as u8])))
+				.unwrap();
+			backend.commit_operation(op).unwrap();
+		}
+
+		let justifications = |i: usize| {
+			backend.blockchain().justifications(BlockId::hash(blocks[i])).unwrap()
+		};
+		assert_eq!(None, justifications(1));
+		assert_eq!(None, justifications(2));
+		assert_eq!(Some(Justifications::from((GRANDPA_ENGINE_ID, vec![3]))), justifications(3));
+		assert_eq!(Some(Justifications::from((GRANDPA_ENGINE_ID, vec![4]))), justifications(4));
+
+		// The pin is released, and persisted as such, once the enacting block is no longer the
+		// last finalized one.
+		assert!(backend.blockchain.justification_pins.lock().is_empty());
+		assert_eq!(
+			Some(Vec::<u64>::new().encode()),
+			backend.storage.db.get(columns::META, meta_keys::JUSTIFICATION_PINS),
+		);
+	}
+
 	#[test]
 	fn prune_blocks_on_finalize_with_fork() {
 		let backend = Backend::<Block>::new_test_with_tx_storage(2, 10);
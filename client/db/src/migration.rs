@@ -0,0 +1,442 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Migration of a RocksDB database to ParityDb.
+//!
+//! Every column is streamed from RocksDB to ParityDb. The keys of the reference counted columns
+//! are rewritten along the way, as ParityDb keys the state nodes by hash only and keeps the
+//! reference counts itself, where RocksDB stores them next to the values. The journals of the
+//! state database, of the blocks not canonicalized yet and of the blocks in the pruning window,
+//! are rewritten the same way, so that pruned databases keep pruning once migrated.
+//!
+//! The columns can't be copied back: the hash indexed columns of ParityDb only store hashes of
+//! the keys, which can't be enumerated. Migrating back to RocksDB goes through a
+//! [snapshot](crate::snapshot) of the latest finalized block instead, imported into a new
+//! database.
+//!
+//! The position of the migration is committed to the target database along with the migrated
+//! entries, so that an interrupted migration resumes where it stopped.
+
+use sp_blockchain::{Error as ClientError, Result as ClientResult};
+use sp_runtime::traits::Block as BlockT;
+use std::path::Path;
+
+/// Number of entries migrated from a column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigratedColumn {
+	/// Index of the column.
+	pub column: u32,
+	/// Number of distinct entries of the column.
+	pub entries: u64,
+	/// Number of references to these entries, the reference counts included.
+	pub references: u64,
+}
+
+/// Migrates the RocksDB database at `rocksdb_path` to a new ParityDb database at `paritydb_path`.
+///
+/// Resumes the previous migration to `paritydb_path` if it has been interrupted. Once all the
+/// columns have been migrated, checks that all the entries are found in the new database and
+/// compares a sample of their values.
+#[cfg(all(feature = "with-kvdb-rocksdb", feature = "with-parity-db"))]
+pub fn migrate_to_parity_db<Block: BlockT>(
+	rocksdb_path: &Path,
+	paritydb_path: &Path,
+) -> ClientResult<Vec<MigratedColumn>> {
+	imp::migrate_to_parity_db::<Block>(rocksdb_path, paritydb_path, None)
+}
+
+/// Migrates the RocksDB database at `rocksdb_path` to a new ParityDb database at `paritydb_path`.
+#[cfg(not(all(feature = "with-kvdb-rocksdb", feature = "with-parity-db")))]
+pub fn migrate_to_parity_db<Block: BlockT>(
+	_rocksdb_path: &Path,
+	_paritydb_path: &Path,
+) -> ClientResult<Vec<MigratedColumn>> {
+	Err(ClientError::Backend(
+		"Migrating databases requires the `with-kvdb-rocksdb` and `with-parity-db` features".into(),
+	))
+}
+
+#[cfg(all(feature = "with-kvdb-rocksdb", feature = "with-parity-db"))]
+mod imp {
+	use super::MigratedColumn;
+	use crate::{
+		columns,
+		utils::{DatabaseType, NUM_COLUMNS},
+		DbHash, DB_HASH_LEN,
+	};
+	use codec::{Decode, Encode};
+	use log::info;
+	use sp_blockchain::{Error as ClientError, Result as ClientResult};
+	use sp_database::{Database, Transaction};
+	use sp_runtime::traits::Block as BlockT;
+	use std::{
+		collections::{HashMap, HashSet},
+		path::Path,
+		sync::Arc,
+	};
+
+	/// Key of the `META` column of the target database holding the migration [`Progress`].
+	const PROGRESS_KEY: &[u8] = b"migration_progress";
+
+	/// Maximum number of changes committed at once.
+	const MAX_BATCH_CHANGES: usize = 10_000;
+
+	/// One entry out of `SAMPLE_INTERVAL` has its value compared once migrated.
+	const SAMPLE_INTERVAL: u64 = 1_000;
+
+	/// Position of the migration: the changes of the entries of the columns before `column`, of
+	/// the entries of `column` before `key`, and `changes` changes of the entry at `key` have
+	/// been committed.
+	#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+	struct Progress {
+		column: u32,
+		key: Vec<u8>,
+		changes: u32,
+	}
+
+	impl Progress {
+		/// Number of changes of the entry at (`column`, `key`) already committed.
+		fn committed(&self, column: u32, key: &[u8]) -> Option<u32> {
+			match (column.cmp(&self.column), key.cmp(&self.key)) {
+				(std::cmp::Ordering::Less, _) => Some(u32::MAX),
+				(std::cmp::Ordering::Equal, std::cmp::Ordering::Less) => Some(u32::MAX),
+				(std::cmp::Ordering::Equal, std::cmp::Ordering::Equal) => Some(self.changes),
+				_ => None,
+			}
+		}
+	}
+
+	fn rocksdb_err(err: impl std::fmt::Display) -> ClientError {
+		ClientError::Backend(format!("RocksDB error: {}", err))
+	}
+
+	struct RocksDbMeta<'a>(&'a kvdb_rocksdb::Database);
+
+	impl<'a> sc_state_db::MetaDb for RocksDbMeta<'a> {
+		type Error = std::io::Error;
+
+		fn get_meta(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+			self.0.get(columns::STATE_META, key)
+		}
+	}
+
+	/// Key of `key` in the target database, and number of references to it.
+	fn target_entry(
+		source: &kvdb_rocksdb::Database,
+		column: u32,
+		key: &[u8],
+	) -> ClientResult<Option<(Vec<u8>, u32)>> {
+		Ok(match column {
+			// The nodes are keyed by their hash, without the prefix of their position in the
+			// trie. Nodes found at different positions are referenced once per position.
+			columns::STATE => Some((key[key.len().saturating_sub(DB_HASH_LEN)..].to_vec(), 1)),
+			// The reference counts are stored in separate entries, keyed by the key of the value
+			// followed by a zero.
+			columns::TRANSACTION if key.len() == DB_HASH_LEN + 1 => None,
+			columns::TRANSACTION => {
+				let mut counter_key = key.to_vec();
+				counter_key.push(0);
+				let references = match source.get(column, &counter_key).map_err(rocksdb_err)? {
+					Some(counter) => u32::decode(&mut &counter[..]).map_err(|e| {
+						ClientError::Backend(format!("Invalid reference count: {}", e))
+					})?,
+					None => 1,
+				};
+				Some((key.to_vec(), references))
+			},
+			_ => Some((key.to_vec(), 1)),
+		})
+	}
+
+	/// Batches the changes made to the target database, committing them along with the progress.
+	struct Batch<'a> {
+		target: &'a dyn Database<DbHash>,
+		transaction: Transaction<DbHash>,
+		keys: HashSet<(u32, Vec<u8>)>,
+		progress: Progress,
+		/// Number of changes after which the migration is interrupted, for testing.
+		interrupt_after: Option<u64>,
+	}
+
+	impl<'a> Batch<'a> {
+		fn set(
+			&mut self,
+			column: u32,
+			key: Vec<u8>,
+			value: Vec<u8>,
+			progress: Progress,
+		) -> ClientResult<()> {
+			// The same key is only set once per commit, as setting it increments its reference
+			// count in the reference counted columns.
+			if self.keys.len() >= MAX_BATCH_CHANGES || self.keys.contains(&(column, key.clone())) {
+				self.commit()?;
+			}
+			self.transaction.set_from_vec(column, &key, value);
+			self.keys.insert((column, key));
+			self.progress = progress;
+			if let Some(changes) = self.interrupt_after.as_mut() {
+				*changes = changes.saturating_sub(1);
+				if *changes == 0 {
+					self.commit()?;
+					return Err(ClientError::Backend("Migration interrupted".into()))
+				}
+			}
+			Ok(())
+		}
+
+		fn commit(&mut self) -> ClientResult<()> {
+			if self.keys.is_empty() {
+				return Ok(())
+			}
+			let mut transaction = std::mem::take(&mut self.transaction);
+			transaction.set_from_vec(columns::META, PROGRESS_KEY, self.progress.encode());
+			self.target.commit(transaction)?;
+			self.keys.clear();
+			Ok(())
+		}
+	}
+
+	pub fn migrate_to_parity_db<Block: BlockT>(
+		rocksdb_path: &Path,
+		paritydb_path: &Path,
+		interrupt_after: Option<u64>,
+	) -> ClientResult<Vec<MigratedColumn>> {
+		if paritydb_path.join("db_version").exists() {
+			return Err(ClientError::Backend(format!(
+				"{} already contains a migrated database",
+				paritydb_path.display()
+			)))
+		}
+
+		crate::upgrade::upgrade_db::<Block>(rocksdb_path, DatabaseType::Full)
+			.map_err(|e| ClientError::Backend(e.to_string()))?;
+		let mut config = kvdb_rocksdb::DatabaseConfig::with_columns(NUM_COLUMNS);
+		config.create_if_missing = false;
+		let source = kvdb_rocksdb::Database::open(&config, rocksdb_path).map_err(rocksdb_err)?;
+
+		// The journals of the state database reference the nodes by their RocksDB keys, and the
+		// pruning journal accounts for the nodes re-inserted within the window, which ParityDb
+		// counts references to instead.
+		let journals: HashMap<_, _> =
+			sc_state_db::rewrite_journal_keys::<Block::Hash, _>(&RocksDbMeta(&source), |key| {
+				key[key.len().saturating_sub(DB_HASH_LEN)..].to_vec()
+			})
+			.map_err(ClientError::from_state_db)?
+			.into_iter()
+			.collect();
+
+		let target: Arc<dyn Database<DbHash>> =
+			crate::parity_db::open(paritydb_path, DatabaseType::Full, true, false)
+				.map_err(|e| ClientError::Backend(format!("ParityDb error: {}", e)))?;
+		let resumed: Option<Progress> = target
+			.get(columns::META, PROGRESS_KEY)
+			.map(|progress| Decode::decode(&mut &progress[..]))
+			.transpose()
+			.map_err(|e| ClientError::Backend(format!("Invalid migration progress: {}", e)))?;
+		if let Some(progress) = &resumed {
+			info!(target: "db", "Resuming the migration from column {}", progress.column);
+		}
+
+		let mut batch = Batch {
+			target: &*target,
+			transaction: Transaction::new(),
+			keys: HashSet::new(),
+			progress: Default::default(),
+			interrupt_after,
+		};
+		let mut migrated = Vec::new();
+		for column in 0..NUM_COLUMNS {
+			let mut summary = MigratedColumn { column, entries: 0, references: 0 };
+			for (key, value) in source.iter(column) {
+				let (target_key, references) = match target_entry(&source, column, &key)? {
+					Some(entry) => entry,
+					None => continue,
+				};
+				let value = match journals.get(&*key) {
+					Some(journal) if column == columns::STATE_META => journal.clone(),
+					_ => value.into_vec(),
+				};
+				summary.entries += 1;
+				summary.references += references as u64;
+
+				let committed = resumed.as_ref().and_then(|p| p.committed(column, &key));
+				for change in committed.unwrap_or(0)..references {
+					let progress = Progress { column, key: key.to_vec(), changes: change + 1 };
+					batch.set(column, target_key.clone(), value.clone(), progress)?;
+				}
+
+				if summary.entries % 1_000_000 == 0 {
+					info!(
+						target: "db",
+						"Migrated {} entries of column {}",
+						summary.entries,
+						column,
+					);
+				}
+			}
+			info!(
+				target: "db",
+				"Migrated column {}: {} entries, {} references",
+				column,
+				summary.entries,
+				summary.references,
+			);
+			migrated.push(summary);
+		}
+		batch.commit()?;
+
+		verify(&source, &*target, &journals, &migrated)?;
+
+		let mut transaction = Transaction::new();
+		transaction.remove(columns::META, PROGRESS_KEY);
+		target.commit(transaction)?;
+		crate::upgrade::update_version(paritydb_path)
+			.map_err(|e| ClientError::Backend(e.to_string()))?;
+		Ok(migrated)
+	}
+
+	/// Checks that all the entries of `source` are found in `target`, comparing the values of a
+	/// sample of them.
+	fn verify(
+		source: &kvdb_rocksdb::Database,
+		target: &dyn Database<DbHash>,
+		journals: &HashMap<Vec<u8>, Vec<u8>>,
+		migrated: &[MigratedColumn],
+	) -> ClientResult<()> {
+		let mut checked = 0u64;
+		for summary in migrated {
+			let column = summary.column;
+			let mut entries = 0;
+			for (key, value) in source.iter(column) {
+				let (target_key, _) = match target_entry(source, column, &key)? {
+					Some(entry) => entry,
+					None => continue,
+				};
+				entries += 1;
+				checked += 1;
+
+				let found = if checked % SAMPLE_INTERVAL == 0 {
+					let expected = match journals.get(&*key) {
+						Some(journal) if column == columns::STATE_META => &journal[..],
+						_ => &value[..],
+					};
+					target.get(column, &target_key).map_or(false, |v| v == expected)
+				} else {
+					target.contains(column, &target_key)
+				};
+				if !found {
+					return Err(ClientError::Backend(format!(
+						"Entry {:?} of column {} missing or different after migration",
+						key, column,
+					)))
+				}
+			}
+			if entries != summary.entries {
+				return Err(ClientError::Backend(format!(
+					"Column {} changed during the migration: {} entries, {} migrated",
+					column, entries, summary.entries,
+				)))
+			}
+		}
+		info!(target: "db", "Verified {} migrated entries", checked);
+		Ok(())
+	}
+
+	#[cfg(test)]
+	mod tests {
+		use super::*;
+
+		#[test]
+		fn progress_tells_committed_changes() {
+			let progress = Progress { column: 2, key: vec![5], changes: 1 };
+			assert_eq!(progress.committed(1, &[9]), Some(u32::MAX));
+			assert_eq!(progress.committed(2, &[4]), Some(u32::MAX));
+			assert_eq!(progress.committed(2, &[5]), Some(1));
+			assert_eq!(progress.committed(2, &[6]), None);
+			assert_eq!(progress.committed(3, &[0]), None);
+		}
+
+		#[test]
+		fn migrates_pruned_database_after_interruption() {
+			use crate::{
+				tests::{insert_state_block, Block},
+				Backend, BlocksPruning, DatabaseSettings, DatabaseSource, PruningMode,
+			};
+			use sc_client_api::backend::Backend as _;
+			use sp_blockchain::HeaderBackend;
+			use sp_runtime::generic::BlockId;
+			use sp_state_machine::Backend as _;
+
+			fn open(source: DatabaseSource) -> Backend<Block> {
+				let settings = DatabaseSettings {
+					state_cache_size: 0,
+					state_cache_child_ratio: None,
+					state_pruning: Some(PruningMode::keep_blocks(2)),
+					source,
+					blocks_pruning: BlocksPruning::keep_all(),
+					flat_state: false,
+					state_diff_index: false,
+					storage_item_stats: false,
+				};
+				Backend::new(settings, 0).unwrap()
+			}
+
+			let dir = tempfile::tempdir().unwrap();
+			let rocksdb_path = dir.path().join("db");
+			let paritydb_path = dir.path().join("paritydb");
+			let mut hash = Default::default();
+			{
+				let backend =
+					open(DatabaseSource::RocksDb { path: rocksdb_path.clone(), cache_size: 16 });
+				for number in 0..6u8 {
+					// Block 4 sets back the value of block 2, re-inserting nodes pending deletion.
+					let value = if number == 4 { 2 } else { number };
+					let changes =
+						vec![(vec![1], Some(vec![value])), (vec![10 + number], Some(vec![number]))];
+					hash = insert_state_block(&backend, number as u64, hash, changes);
+				}
+			}
+
+			assert!(migrate_to_parity_db::<Block>(&rocksdb_path, &paritydb_path, Some(5)).is_err());
+			assert!(!paritydb_path.join("db_version").exists());
+			let migrated =
+				migrate_to_parity_db::<Block>(&rocksdb_path, &paritydb_path, None).unwrap();
+			assert!(migrated.iter().any(|c| c.column == columns::STATE && c.entries > 0));
+
+			let backend = open(DatabaseSource::ParityDb { path: paritydb_path });
+			assert_eq!(backend.blockchain().info().best_hash, hash);
+			let state = backend.state_at(BlockId::Hash(hash)).unwrap();
+			assert_eq!(state.storage(&[1]).unwrap(), Some(vec![5]));
+
+			// The migrated pruning window keeps being pruned without losing live nodes.
+			for number in 6..10u8 {
+				hash = insert_state_block(
+					&backend,
+					number as u64,
+					hash,
+					vec![(vec![1], Some(vec![number]))],
+				);
+			}
+			let state = backend.state_at(BlockId::Hash(hash)).unwrap();
+			assert_eq!(state.storage(&[1]).unwrap(), Some(vec![9]));
+			for number in 0..6u8 {
+				assert_eq!(state.storage(&[10 + number]).unwrap(), Some(vec![number]));
+			}
+		}
+	}
+}
@@ -0,0 +1,776 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Consistent snapshots of the finalized chain data of a running node.
+//!
+//! A snapshot is a directory made of three files:
+//!
+//! - `metadata`: the SCALE-encoded [`SnapshotMetadata`], describing the snapshot block and holding
+//!   the checksums of the two other files.
+//! - `blocks`: the finalized blocks up to and including the snapshot block, in ascending order.
+//! - `state`: the key-values of the state of the snapshot block, child tries included.
+//!
+//! The state of the snapshot block is pinned while it is exported, so that a snapshot can be taken
+//! while the node keeps importing and finalizing blocks. The `metadata` file is written last, a
+//! snapshot is complete once it exists.
+//!
+//! The state is imported back one trie at a time, its nodes being written to the database as soon
+//! as they are built, so that states larger than the memory of the node can be imported.
+
+use crate::{columns, DbHash};
+use codec::{Decode, Encode};
+use hash_db::{Hasher, Prefix};
+use log::info;
+use sc_client_api::backend::Backend;
+use sp_blockchain::{
+	Backend as _, Error as ClientError, HeaderBackend as _, PrunableBlockData,
+	Result as ClientResult,
+};
+use sp_core::{
+	hashing::blake2_256,
+	storage::{well_known_keys, ChildInfo, ChildType, PrefixedStorageKey},
+};
+use sp_database::{error::DatabaseError, Database, Transaction};
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, HashFor, Header as HeaderT, NumberFor, One, Zero},
+	Justifications, StateVersion,
+};
+use sp_state_machine::Backend as StateBackend;
+use sp_trie::{prefixed_key, DBValue, KeySpacedDBMut, LayoutV0, LayoutV1};
+use std::{
+	collections::BTreeMap,
+	fs::{self, File},
+	io::{BufReader, BufWriter, Write},
+	iter::Peekable,
+	marker::PhantomData,
+	path::{Path, PathBuf},
+};
+
+/// Version of the snapshot format.
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+/// Number of trie nodes written to the database per transaction when importing a state.
+const IMPORT_BATCH_SIZE: usize = 10_000;
+
+const METADATA_FILE: &str = "metadata";
+const BLOCKS_FILE: &str = "blocks";
+const STATE_FILE: &str = "state";
+
+/// Description of a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct SnapshotMetadata<Hash, Number> {
+	/// Version of the snapshot format.
+	pub version: u32,
+	/// Hash of the genesis block of the chain.
+	pub genesis_hash: Hash,
+	/// Hash of the finalized block the snapshot has been taken at.
+	pub hash: Hash,
+	/// Number of the finalized block the snapshot has been taken at.
+	pub number: Number,
+	/// State root of the snapshot block.
+	pub state_root: Hash,
+	/// Version of the trie layout of the state, as encoded by `u8::from(StateVersion)`.
+	pub state_version: u8,
+	/// Number of the first block of the `blocks` file.
+	///
+	/// Equal to `number` when the ancestors of the snapshot block weren't all available.
+	pub first_block: Number,
+	/// Number of entries of the `state` file.
+	pub state_entries: u64,
+	/// Checksum of the `blocks` file.
+	pub blocks_checksum: [u8; 32],
+	/// Checksum of the `state` file.
+	pub state_checksum: [u8; 32],
+}
+
+/// Metadata of a snapshot of the given block type.
+pub type SnapshotMetadataFor<Block> = SnapshotMetadata<<Block as BlockT>::Hash, NumberFor<Block>>;
+
+/// A block stored in a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct SnapshotBlock<Block: BlockT> {
+	/// Header of the block.
+	pub header: Block::Header,
+	/// Body of the block, if it hadn't been pruned.
+	pub body: Option<Vec<Block::Extrinsic>>,
+	/// Indexed transactions of the block, if any.
+	pub indexed_body: Option<Vec<Vec<u8>>>,
+	/// Justifications of the block, if any.
+	pub justifications: Option<Justifications>,
+}
+
+/// An entry of the `state` file: the prefixed key of the child trie, empty for the top trie,
+/// followed by the key and the value.
+///
+/// The entries of the top trie come first, followed by those of each child trie in the order of
+/// their prefixed keys. The entries of a trie are ordered by key.
+pub type StateEntry = (Vec<u8>, Vec<u8>, Vec<u8>);
+
+fn io_err(path: &Path, err: impl std::fmt::Display) -> ClientError {
+	ClientError::Backend(format!("Snapshot file {}: {}", path.display(), err))
+}
+
+/// Running checksum of the entries of a snapshot file.
+#[derive(Default)]
+struct Checksum([u8; 32]);
+
+impl Checksum {
+	fn update(&mut self, entry: &[u8]) {
+		let mut input = Vec::with_capacity(self.0.len() + entry.len());
+		input.extend_from_slice(&self.0);
+		input.extend_from_slice(entry);
+		self.0 = blake2_256(&input);
+	}
+}
+
+/// Writes length-prefixed SCALE-encoded entries to a snapshot file.
+struct EntryWriter {
+	path: PathBuf,
+	file: BufWriter<File>,
+	checksum: Checksum,
+	entries: u64,
+}
+
+impl EntryWriter {
+	fn create(path: PathBuf) -> ClientResult<Self> {
+		let file = File::create(&path).map_err(|e| io_err(&path, e))?;
+		Ok(Self { path, file: BufWriter::new(file), checksum: Default::default(), entries: 0 })
+	}
+
+	fn write<T: Encode>(&mut self, entry: &T) -> ClientResult<()> {
+		let entry = entry.encode();
+		self.checksum.update(&entry);
+		self.entries += 1;
+		self.file.write_all(&entry.encode()).map_err(|e| io_err(&self.path, e))
+	}
+
+	/// Flushes the file, returning the number of entries and their checksum.
+	fn finish(mut self) -> ClientResult<(u64, [u8; 32])> {
+		self.file.flush().map_err(|e| io_err(&self.path, e))?;
+		self.file.get_ref().sync_all().map_err(|e| io_err(&self.path, e))?;
+		Ok((self.entries, self.checksum.0))
+	}
+}
+
+/// Reads the entries written by an [`EntryWriter`], checking their checksum once they have all
+/// been read.
+struct EntryReader {
+	path: PathBuf,
+	file: codec::IoReader<BufReader<File>>,
+	checksum: Checksum,
+	expected_checksum: [u8; 32],
+	remaining: u64,
+}
+
+impl EntryReader {
+	fn open(path: PathBuf, entries: u64, expected_checksum: [u8; 32]) -> ClientResult<Self> {
+		let file = File::open(&path).map_err(|e| io_err(&path, e))?;
+		Ok(Self {
+			path,
+			file: codec::IoReader(BufReader::new(file)),
+			checksum: Default::default(),
+			expected_checksum,
+			remaining: entries,
+		})
+	}
+
+	fn read<T: Decode>(&mut self) -> ClientResult<Option<T>> {
+		if self.remaining == 0 {
+			return Ok(None)
+		}
+		let entry = Vec::<u8>::decode(&mut self.file).map_err(|e| io_err(&self.path, e))?;
+		self.checksum.update(&entry);
+		self.remaining -= 1;
+		if self.remaining == 0 && self.checksum.0 != self.expected_checksum {
+			return Err(io_err(&self.path, "checksum mismatch"))
+		}
+		T::decode(&mut &entry[..]).map(Some).map_err(|e| io_err(&self.path, e))
+	}
+}
+
+/// Takes a snapshot of the finalized block `hash` of `backend` into the directory at `path`.
+///
+/// `state_version` is the version of the trie layout of the state of the block, as defined by its
+/// runtime.
+pub fn export<Block, BE>(
+	backend: &BE,
+	path: &Path,
+	hash: Block::Hash,
+	state_version: StateVersion,
+) -> ClientResult<SnapshotMetadataFor<Block>>
+where
+	Block: BlockT,
+	BE: Backend<Block>,
+{
+	let blockchain = backend.blockchain();
+	let chain_info = blockchain.info();
+	let number = blockchain
+		.number(hash)?
+		.ok_or_else(|| ClientError::UnknownBlock(format!("Unknown snapshot block {}", hash)))?;
+	if number > chain_info.finalized_number || blockchain.hash(number)? != Some(hash) {
+		return Err(ClientError::Backend(format!("Snapshot block {} isn't finalized", hash)))
+	}
+
+	// Holding the state pins it, it can't be pruned while being exported.
+	let state = backend.state_at(BlockId::Hash(hash))?;
+	let header = blockchain
+		.header(BlockId::Hash(hash))?
+		.ok_or_else(|| ClientError::UnknownBlock(format!("Missing header of {}", hash)))?;
+
+	fs::create_dir_all(path).map_err(|e| io_err(path, e))?;
+	info!(target: "db", "Exporting snapshot of block #{} ({}) to {}", number, hash, path.display());
+
+	// The ancestors are only exported if they can all be imported back, that is if none of
+	// them is missing.
+	let headers_pruned = blockchain
+		.pruned_below(PrunableBlockData::Header)
+		.map_or(false, |pruned_below| pruned_below > One::one());
+	let first_block = if number.is_zero() || headers_pruned || chain_info.block_gap.is_some() {
+		number
+	} else {
+		One::one()
+	};
+
+	let mut blocks = EntryWriter::create(path.join(BLOCKS_FILE))?;
+	let mut current = first_block;
+	while current <= number {
+		let id = if current == number { BlockId::Hash(hash) } else { BlockId::Number(current) };
+		let block = SnapshotBlock::<Block> {
+			header: blockchain.header(id)?.ok_or_else(|| {
+				ClientError::UnknownBlock(format!("Header of block {} pruned during export", id))
+			})?,
+			body: blockchain.body(id)?,
+			indexed_body: blockchain.block_indexed_body(id)?,
+			justifications: blockchain.justifications(id)?,
+		};
+		blocks.write(&block)?;
+		current += One::one();
+	}
+	let (_, blocks_checksum) = blocks.finish()?;
+
+	let mut entries = EntryWriter::create(path.join(STATE_FILE))?;
+	let mut child_roots = Vec::new();
+	export_trie(&state, None, &mut entries, |key| {
+		if key.starts_with(well_known_keys::DEFAULT_CHILD_STORAGE_KEY_PREFIX) {
+			child_roots.push(key.to_vec());
+		}
+	})?;
+	for prefixed_key in child_roots {
+		let storage_key = &prefixed_key[well_known_keys::DEFAULT_CHILD_STORAGE_KEY_PREFIX.len()..];
+		let child_info = ChildInfo::new_default(storage_key);
+		export_trie(&state, Some(&child_info), &mut entries, |_| ())?;
+	}
+	let (state_entries, state_checksum) = entries.finish()?;
+
+	let metadata = SnapshotMetadata {
+		version: SNAPSHOT_VERSION,
+		genesis_hash: chain_info.genesis_hash,
+		hash,
+		number,
+		state_root: *header.state_root(),
+		state_version: state_version.into(),
+		first_block,
+		state_entries,
+		blocks_checksum,
+		state_checksum,
+	};
+	// The metadata is written last and atomically, marking the snapshot as complete.
+	let metadata_path = path.join(METADATA_FILE);
+	let tmp_path = path.join(format!("{}.tmp", METADATA_FILE));
+	fs::write(&tmp_path, metadata.encode()).map_err(|e| io_err(&tmp_path, e))?;
+	fs::rename(&tmp_path, &metadata_path).map_err(|e| io_err(&metadata_path, e))?;
+
+	info!(
+		target: "db",
+		"Exported snapshot of block #{} ({}): {} blocks, {} state entries",
+		number,
+		hash,
+		number - first_block + One::one(),
+		state_entries,
+	);
+	Ok(metadata)
+}
+
+/// Writes all the key-values of the given trie of `state`, calling `on_key` on each of its keys.
+fn export_trie<S, H>(
+	state: &S,
+	child_info: Option<&ChildInfo>,
+	entries: &mut EntryWriter,
+	mut on_key: impl FnMut(&[u8]),
+) -> ClientResult<()>
+where
+	S: StateBackend<H>,
+	H: hash_db::Hasher,
+{
+	let prefixed_key =
+		child_info.map(|c| c.prefixed_storage_key().into_inner()).unwrap_or_default();
+	let mut result = Ok(());
+	state
+		.apply_to_key_values_while(
+			child_info,
+			None,
+			None,
+			|key, value| {
+				on_key(&key);
+				result = entries.write::<StateEntry>(&(prefixed_key.clone(), key, value));
+				result.is_ok()
+			},
+			false,
+		)
+		.map_err(|e| ClientError::Backend(format!("Failed to read snapshot state: {}", e)))?;
+	result
+}
+
+/// A snapshot stored on disk.
+pub struct Snapshot<Block: BlockT> {
+	path: PathBuf,
+	metadata: SnapshotMetadataFor<Block>,
+}
+
+impl<Block: BlockT> Snapshot<Block> {
+	/// Opens the snapshot stored in the directory at `path`.
+	pub fn open(path: &Path) -> ClientResult<Self> {
+		let metadata_path = path.join(METADATA_FILE);
+		let metadata = fs::read(&metadata_path).map_err(|e| io_err(&metadata_path, e))?;
+		let metadata = SnapshotMetadataFor::<Block>::decode(&mut &metadata[..])
+			.map_err(|e| io_err(&metadata_path, e))?;
+		if metadata.version != SNAPSHOT_VERSION {
+			return Err(io_err(
+				&metadata_path,
+				format!("unsupported snapshot version {}", metadata.version),
+			))
+		}
+
+		Ok(Self { path: path.to_owned(), metadata })
+	}
+
+	/// Metadata of the snapshot.
+	pub fn metadata(&self) -> &SnapshotMetadataFor<Block> {
+		&self.metadata
+	}
+
+	/// Checks the checksums of the snapshot and that its blocks form a chain ending with the
+	/// snapshot block, which is returned.
+	pub fn verify(&self) -> ClientResult<SnapshotBlock<Block>> {
+		let metadata = &self.metadata;
+		let mut last: Option<SnapshotBlock<Block>> = None;
+		for block in self.blocks()? {
+			let block = block?;
+			let expected_number = match &last {
+				Some(parent) => {
+					if block.header.parent_hash() != &parent.header.hash() {
+						return Err(ClientError::Backend(format!(
+							"Snapshot block #{} isn't a child of its predecessor",
+							block.header.number(),
+						)))
+					}
+					*parent.header.number() + One::one()
+				},
+				None => metadata.first_block,
+			};
+			if *block.header.number() != expected_number {
+				return Err(ClientError::Backend(format!(
+					"Unexpected snapshot block #{}, expected #{}",
+					block.header.number(),
+					expected_number,
+				)))
+			}
+			last = Some(block);
+		}
+
+		let block = last
+			.filter(|block| block.header.hash() == metadata.hash)
+			.ok_or_else(|| ClientError::Backend("Snapshot block missing from snapshot".into()))?;
+		if *block.header.state_root() != metadata.state_root {
+			return Err(ClientError::Backend("Snapshot state root mismatch".into()))
+		}
+
+		let mut state = EntryReader::open(
+			self.path.join(STATE_FILE),
+			metadata.state_entries,
+			metadata.state_checksum,
+		)?;
+		while state.read::<StateEntry>()?.is_some() {}
+
+		Ok(block)
+	}
+
+	/// Iterates over the blocks of the snapshot, in ascending order.
+	pub fn blocks(&self) -> ClientResult<impl Iterator<Item = ClientResult<SnapshotBlock<Block>>>> {
+		let metadata = &self.metadata;
+		let count = (metadata.number - metadata.first_block + One::one())
+			.try_into()
+			.map_err(|_| ClientError::Backend("Too many blocks in snapshot".into()))?;
+		let mut reader =
+			EntryReader::open(self.path.join(BLOCKS_FILE), count, metadata.blocks_checksum)?;
+		Ok(std::iter::from_fn(move || reader.read().transpose()))
+	}
+
+	/// Version of the trie layout of the state of the snapshot block.
+	pub fn state_version(&self) -> ClientResult<StateVersion> {
+		StateVersion::try_from(self.metadata.state_version).map_err(|_| {
+			ClientError::Backend(format!(
+				"Unknown snapshot state version {}",
+				self.metadata.state_version
+			))
+		})
+	}
+
+	/// Iterates over the entries of the state of the snapshot block, in the order they are
+	/// stored in.
+	pub fn state_entries(&self) -> ClientResult<impl Iterator<Item = ClientResult<StateEntry>>> {
+		let metadata = &self.metadata;
+		let mut reader = EntryReader::open(
+			self.path.join(STATE_FILE),
+			metadata.state_entries,
+			metadata.state_checksum,
+		)?;
+		Ok(std::iter::from_fn(move || reader.read().transpose()))
+	}
+
+	/// Writes the state of the snapshot block to the database of `backend` and checks its root.
+	///
+	/// The state is streamed from the snapshot one trie at a time, the trie nodes being written to
+	/// the database in batches as soon as they are built. The snapshot block must then be
+	/// committed with `BlockImportOperation::reset_to_existing_storage` for the state to be used.
+	pub fn import_state(&self, backend: &crate::Backend<Block>) -> ClientResult<()> {
+		let state_version = self.state_version()?;
+		let mut writer = NodeWriter::<HashFor<Block>>::new(&*backend.storage.db);
+		let mut entries = self.state_entries()?.peekable();
+
+		// Roots of the child tries, as found in the top trie.
+		let mut child_roots = BTreeMap::new();
+		let root = import_trie::<Block, _>(
+			&mut entries,
+			&[],
+			&mut writer,
+			state_version,
+			&mut child_roots,
+		)?;
+		if root != self.metadata.state_root {
+			return Err(ClientError::Backend("Snapshot state root mismatch".into()))
+		}
+
+		while let Some(entry) = entries.peek() {
+			let prefixed_key = match entry {
+				Ok((prefixed_key, _, _)) => prefixed_key.clone(),
+				Err(_) => return entries.next().expect("Entry was peeked; qed").map(|_| ()),
+			};
+			let child_info =
+				match ChildType::from_prefixed_key(PrefixedStorageKey::new_ref(&prefixed_key)) {
+					Some((ChildType::ParentKeyId, storage_key)) =>
+						ChildInfo::new_default(storage_key),
+					None =>
+						return Err(ClientError::Backend(
+							"Invalid child storage key in snapshot".into(),
+						)),
+				};
+			// Each child trie must be listed in the top trie, and come only once.
+			let expected_root = child_roots.remove(&prefixed_key).ok_or_else(|| {
+				ClientError::Backend("Missing child trie root in snapshot".into())
+			})?;
+			let mut child_writer = KeySpacedDBMut::new(&mut writer, child_info.keyspace());
+			let root = import_trie::<Block, _>(
+				&mut entries,
+				&prefixed_key,
+				&mut child_writer,
+				state_version,
+				&mut BTreeMap::new(),
+			)?;
+			if root.as_ref() != &expected_root[..] {
+				return Err(ClientError::Backend("Snapshot child trie root mismatch".into()))
+			}
+		}
+		if !child_roots.is_empty() {
+			return Err(ClientError::Backend("Missing child trie in snapshot".into()))
+		}
+
+		let nodes = writer.finish()?;
+		info!(target: "db", "Imported {} state trie nodes of the snapshot", nodes);
+		Ok(())
+	}
+}
+
+/// Builds the trie whose entries come next in `entries`, writing its nodes to `db`, and returns
+/// its root.
+///
+/// The roots of the child tries found in the trie are added to `child_roots`.
+fn import_trie<Block, DB>(
+	entries: &mut Peekable<impl Iterator<Item = ClientResult<StateEntry>>>,
+	prefixed_key: &[u8],
+	db: &mut DB,
+	state_version: StateVersion,
+	child_roots: &mut BTreeMap<Vec<u8>, Vec<u8>>,
+) -> ClientResult<Block::Hash>
+where
+	Block: BlockT,
+	DB: hash_db::HashDB<HashFor<Block>, DBValue>,
+{
+	// The trie builder can't fail, the first error stops the iteration and is returned instead.
+	let mut error = None;
+	let mut last_key: Option<Vec<u8>> = None;
+	let key_values = std::iter::from_fn(|| {
+		let entry = entries.next_if(|entry| {
+			entry.as_ref().map_or(true, |(child_key, _, _)| child_key == prefixed_key)
+		})?;
+		let (_, key, value) = match entry {
+			Ok(entry) => entry,
+			Err(e) => {
+				error = Some(e);
+				return None
+			},
+		};
+		if last_key.as_ref().map_or(false, |last_key| *last_key >= key) {
+			error = Some(ClientError::Backend("Unordered snapshot state entries".into()));
+			return None
+		}
+		if prefixed_key.is_empty() && well_known_keys::is_child_storage_key(&key) {
+			child_roots.insert(key.clone(), value.clone());
+		}
+		last_key = Some(key.clone());
+		Some((key, value))
+	});
+
+	let root = match state_version {
+		StateVersion::V0 =>
+			sp_trie::build_trie::<LayoutV0<HashFor<Block>>, _, _, _, _>(key_values, db),
+		StateVersion::V1 =>
+			sp_trie::build_trie::<LayoutV1<HashFor<Block>>, _, _, _, _>(key_values, db),
+	};
+	match error {
+		Some(e) => Err(e),
+		None => Ok(root),
+	}
+}
+
+/// Writes trie nodes straight to the state column of the database, in batches.
+///
+/// Nodes are keyed like the state database does, so that they can be read back once the block
+/// they belong to is committed.
+struct NodeWriter<'a, H> {
+	db: &'a dyn Database<DbHash>,
+	transaction: Transaction<DbHash>,
+	len: usize,
+	nodes: u64,
+	error: Option<DatabaseError>,
+	_phantom: PhantomData<H>,
+}
+
+impl<'a, H: Hasher> NodeWriter<'a, H> {
+	fn new(db: &'a dyn Database<DbHash>) -> Self {
+		Self {
+			db,
+			transaction: Default::default(),
+			len: 0,
+			nodes: 0,
+			error: None,
+			_phantom: PhantomData,
+		}
+	}
+
+	fn commit(&mut self) {
+		if self.error.is_none() {
+			self.error = self.db.commit(std::mem::take(&mut self.transaction)).err();
+		}
+		self.len = 0;
+	}
+
+	/// Writes the pending nodes, returning the number of nodes written in total.
+	fn finish(mut self) -> ClientResult<u64> {
+		self.commit();
+		match self.error {
+			Some(e) => Err(e.into()),
+			None => Ok(self.nodes),
+		}
+	}
+}
+
+impl<'a, H: Hasher> hash_db::HashDB<H, DBValue> for NodeWriter<'a, H> {
+	fn get(&self, _key: &H::Out, _prefix: Prefix) -> Option<DBValue> {
+		None
+	}
+
+	fn contains(&self, _key: &H::Out, _prefix: Prefix) -> bool {
+		false
+	}
+
+	fn insert(&mut self, prefix: Prefix, value: &[u8]) -> H::Out {
+		let key = H::hash(value);
+		self.emplace(key, prefix, value.to_vec());
+		key
+	}
+
+	fn emplace(&mut self, key: H::Out, prefix: Prefix, value: DBValue) {
+		let mut key = prefixed_key::<H>(&key, prefix);
+		self.db.sanitize_key(&mut key);
+		self.transaction.set_from_vec(columns::STATE, &key, value);
+		self.nodes += 1;
+		self.len += 1;
+		if self.len == IMPORT_BATCH_SIZE {
+			self.commit();
+		}
+	}
+
+	fn remove(&mut self, _key: &H::Out, _prefix: Prefix) {}
+}
+
+impl<'a, H: Hasher> hash_db::AsHashDB<H, DBValue> for NodeWriter<'a, H> {
+	fn as_hash_db(&self) -> &dyn hash_db::HashDB<H, DBValue> {
+		self
+	}
+
+	fn as_hash_db_mut<'b>(&'b mut self) -> &'b mut (dyn hash_db::HashDB<H, DBValue> + 'b) {
+		self
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::tests::Block;
+	use sc_client_api::backend::{BlockImportOperation, NewBlockState};
+	use sp_core::storage::{Storage, StorageChild};
+	use sp_runtime::testing::Header;
+
+	/// Inserts a chain of three finalized blocks, returning their headers.
+	fn insert_chain(backend: &crate::Backend<Block>) -> Vec<Header> {
+		let child_info = ChildInfo::new_default(b"child");
+		let mut headers: Vec<Header> = Vec::new();
+		let mut state_root = Default::default();
+		for number in 0..3 {
+			let mut op = backend.begin_operation().unwrap();
+			let parent_hash = headers.last().map(|header| header.hash()).unwrap_or_default();
+			backend.begin_state_operation(&mut op, BlockId::Hash(parent_hash)).unwrap();
+			if number == 0 {
+				let storage = Storage {
+					top: vec![
+						(b"key".to_vec(), b"value".to_vec()),
+						// Stored in a value node of its own.
+						(b"large".to_vec(), vec![1; 64]),
+					]
+					.into_iter()
+					.collect(),
+					children_default: vec![(
+						child_info.storage_key().to_vec(),
+						StorageChild {
+							data: vec![(b"child_key".to_vec(), b"child_value".to_vec())]
+								.into_iter()
+								.collect(),
+							child_info: child_info.clone(),
+						},
+					)]
+					.into_iter()
+					.collect(),
+				};
+				state_root = op.reset_storage(storage, StateVersion::V1).unwrap();
+			}
+			let header = Header {
+				number,
+				parent_hash,
+				state_root,
+				digest: Default::default(),
+				extrinsics_root: Default::default(),
+			};
+			op.set_block_data(header.clone(), Some(vec![]), None, None, NewBlockState::Final)
+				.unwrap();
+			backend.commit_operation(op).unwrap();
+			headers.push(header);
+		}
+		headers
+	}
+
+	#[test]
+	fn export_and_open_snapshot() {
+		let backend = crate::Backend::<Block>::new_test(10, 10);
+		let hashes = insert_chain(&backend).iter().map(|h| h.hash()).collect::<Vec<_>>();
+
+		let dir = tempfile::tempdir().unwrap();
+		let metadata = export(&backend, dir.path(), hashes[2], StateVersion::V1).unwrap();
+		assert_eq!(metadata.hash, hashes[2]);
+		assert_eq!(metadata.number, 2);
+		assert_eq!(metadata.first_block, 1);
+		// Three top trie entries, the child trie root included, and one child trie entry.
+		assert_eq!(metadata.state_entries, 4);
+
+		let snapshot = Snapshot::<Block>::open(dir.path()).unwrap();
+		assert_eq!(snapshot.metadata(), &metadata);
+		assert_eq!(snapshot.state_version().unwrap(), StateVersion::V1);
+		assert_eq!(snapshot.verify().unwrap().header.hash(), hashes[2]);
+		let blocks =
+			snapshot.blocks().unwrap().map(|b| b.unwrap().header.hash()).collect::<Vec<_>>();
+		assert_eq!(blocks, hashes[1..]);
+		let entries = snapshot.state_entries().unwrap().map(Result::unwrap).collect::<Vec<_>>();
+		// The child trie root comes first, `:` sorting before letters.
+		assert_eq!(entries[1], (vec![], b"key".to_vec(), b"value".to_vec()));
+		assert_eq!(
+			entries[3],
+			(
+				ChildInfo::new_default(b"child").prefixed_storage_key().into_inner(),
+				b"child_key".to_vec(),
+				b"child_value".to_vec(),
+			)
+		);
+
+		// Corrupting the state makes the verification fail.
+		let state_path = dir.path().join(STATE_FILE);
+		let mut state = fs::read(&state_path).unwrap();
+		*state.last_mut().unwrap() ^= 1;
+		fs::write(&state_path, state).unwrap();
+		assert!(Snapshot::<Block>::open(dir.path()).unwrap().verify().is_err());
+	}
+
+	#[test]
+	fn export_rejects_unknown_blocks() {
+		let backend = crate::Backend::<Block>::new_test(10, 10);
+		insert_chain(&backend);
+
+		let dir = tempfile::tempdir().unwrap();
+		assert!(export(&backend, dir.path(), Default::default(), StateVersion::V1).is_err());
+	}
+
+	#[test]
+	fn import_snapshot_state() {
+		let backend = crate::Backend::<Block>::new_test(10, 10);
+		let genesis = insert_chain(&backend).remove(0);
+
+		let dir = tempfile::tempdir().unwrap();
+		export(&backend, dir.path(), genesis.hash(), StateVersion::V1).unwrap();
+		let snapshot = Snapshot::<Block>::open(dir.path()).unwrap();
+
+		// The state can't be used before it has been imported.
+		let imported = crate::Backend::<Block>::new_test(10, 10);
+		let mut op = imported.begin_operation().unwrap();
+		assert!(op.reset_to_existing_storage(genesis.state_root).is_err());
+
+		snapshot.import_state(&imported).unwrap();
+		op.set_block_data(genesis.clone(), Some(vec![]), None, None, NewBlockState::Final)
+			.unwrap();
+		op.reset_to_existing_storage(genesis.state_root).unwrap();
+		imported.commit_operation(op).unwrap();
+
+		let state = imported.state_at(BlockId::Hash(genesis.hash())).unwrap();
+		assert_eq!(state.storage(b"key").unwrap(), Some(b"value".to_vec()));
+		assert_eq!(state.storage(b"large").unwrap(), Some(vec![1; 64]));
+		assert_eq!(
+			state.child_storage(&ChildInfo::new_default(b"child"), b"child_key").unwrap(),
+			Some(b"child_value".to_vec())
+		);
+		assert_eq!(state.storage_root(std::iter::empty(), StateVersion::V1).0, genesis.state_root);
+	}
+}
@@ -18,7 +18,12 @@
 
 //! Database usage statistics
 
-use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use parking_lot::Mutex;
+use sc_client_api::{StorageItemUsage, STORAGE_ITEM_PREFIX_LEN};
+use std::{
+	collections::HashMap,
+	sync::atomic::{AtomicU64, Ordering as AtomicOrdering},
+};
 
 /// Accumulated usage statistics for state queries.
 pub struct StateUsageStats {
@@ -33,11 +38,18 @@ pub struct StateUsageStats {
 	bytes_removed_nodes: AtomicU64,
 	reads_cache: AtomicU64,
 	bytes_read_cache: AtomicU64,
+	/// Accesses per storage item, only tallied when enabled.
+	items: Option<Mutex<HashMap<[u8; STORAGE_ITEM_PREFIX_LEN], StorageItemUsage>>>,
 }
 
 impl StateUsageStats {
 	/// New empty usage stats.
 	pub fn new() -> Self {
+		Self::with_storage_items(false)
+	}
+
+	/// New empty usage stats, tallying the accesses per storage item if `storage_items` is set.
+	pub fn with_storage_items(storage_items: bool) -> Self {
 		Self {
 			started: std::time::Instant::now(),
 			reads: 0.into(),
@@ -50,9 +62,28 @@ impl StateUsageStats {
 			bytes_removed_nodes: 0.into(),
 			reads_cache: 0.into(),
 			bytes_read_cache: 0.into(),
+			items: storage_items.then(Default::default),
 		}
 	}
 
+	/// Whether the accesses per storage item are tallied.
+	pub fn tallies_storage_items(&self) -> bool {
+		self.items.is_some()
+	}
+
+	fn tally_item(&self, key: &[u8], f: impl FnOnce(&mut StorageItemUsage)) {
+		let items = match &self.items {
+			Some(items) if key.len() >= STORAGE_ITEM_PREFIX_LEN => items,
+			_ => return,
+		};
+		let mut prefix = [0u8; STORAGE_ITEM_PREFIX_LEN];
+		prefix.copy_from_slice(&key[..STORAGE_ITEM_PREFIX_LEN]);
+		f(items
+			.lock()
+			.entry(prefix)
+			.or_insert_with(|| StorageItemUsage { prefix, ..Default::default() }));
+	}
+
 	/// Tally one read operation, of some length.
 	pub fn tally_read(&self, data_bytes: u64, cache: bool) {
 		self.reads.fetch_add(1, AtomicOrdering::Relaxed);
@@ -65,10 +96,23 @@ impl StateUsageStats {
 
 	/// Tally one key read.
 	pub fn tally_key_read(&self, key: &[u8], val: Option<&Vec<u8>>, cache: bool) {
-		self.tally_read(
-			key.len() as u64 + val.as_ref().map(|x| x.len() as u64).unwrap_or(0),
-			cache,
-		);
+		let bytes = key.len() as u64 + val.as_ref().map(|x| x.len() as u64).unwrap_or(0);
+		self.tally_read(bytes, cache);
+		self.tally_item(key, |usage| {
+			usage.reads += 1;
+			usage.bytes_read += bytes;
+			if cache {
+				usage.cache_reads += 1;
+			}
+		});
+	}
+
+	/// Tally one key write of the top trie, or its removal.
+	pub fn tally_key_write(&self, key: &[u8], val: Option<&[u8]>) {
+		self.tally_item(key, |usage| {
+			usage.writes += 1;
+			usage.bytes_written += key.len() as u64 + val.map(|x| x.len() as u64).unwrap_or(0);
+		});
 	}
 
 	/// Tally one child key read.
@@ -115,6 +159,31 @@ impl StateUsageStats {
 		self.bytes_read_cache.fetch_add(info.cache_reads.bytes, AtomicOrdering::Relaxed);
 	}
 
+	/// Move the storage item accesses tallied by `other` into these stats.
+	pub fn merge_items(&self, other: &StateUsageStats) {
+		let (items, other) = match (&self.items, &other.items) {
+			(Some(items), Some(other)) => (items, std::mem::take(&mut *other.lock())),
+			_ => return,
+		};
+		let mut items = items.lock();
+		for (prefix, usage) in other {
+			let item = items
+				.entry(prefix)
+				.or_insert_with(|| StorageItemUsage { prefix, ..Default::default() });
+			item.reads += usage.reads;
+			item.cache_reads += usage.cache_reads;
+			item.bytes_read += usage.bytes_read;
+			item.writes += usage.writes;
+			item.bytes_written += usage.bytes_written;
+		}
+	}
+
+	/// Returns the accesses tallied per storage item, if enabled. Unlike [`Self::take`], this
+	/// doesn't reset them.
+	pub fn items(&self) -> Option<Vec<StorageItemUsage>> {
+		self.items.as_ref().map(|items| items.lock().values().cloned().collect())
+	}
+
 	/// Returns the collected `UsageInfo` and resets the internal state.
 	pub fn take(&self) -> sp_state_machine::UsageInfo {
 		use sp_state_machine::UsageUnit;
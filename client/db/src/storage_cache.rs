@@ -749,11 +749,16 @@ pub struct SyncingCachingState<S, Block: BlockT> {
 impl<S, B: BlockT> SyncingCachingState<S, B> {
 	/// Create new automatic syncing state.
 	pub fn new(
-		caching_state: CachingState<S, B>,
+		mut caching_state: CachingState<S, B>,
 		state_usage: Arc<StateUsageStats>,
 		meta: Arc<RwLock<Meta<NumberFor<B>, B::Hash>>>,
 		lock: Arc<RwLock<()>>,
 	) -> Self {
+		// The accesses per storage item are tallied locally, like the others, if the backend
+		// tallies them.
+		if state_usage.tallies_storage_items() {
+			caching_state.usage = StateUsageStats::with_storage_items(true);
+		}
 		Self { caching_state: Some(caching_state), state_usage, meta, lock, disable_syncing: false }
 	}
 
@@ -936,6 +941,7 @@ impl<S, B: BlockT> Drop for SyncingCachingState<S, B> {
 			let _lock = self.lock.read();
 
 			self.state_usage.merge_sm(caching_state.usage.take());
+			self.state_usage.merge_items(&caching_state.usage);
 			if let Some(hash) = caching_state.cache.parent_hash {
 				let is_best = self.meta.read().best_hash == hash;
 				caching_state.cache.sync_cache(&[], &[], vec![], vec![], None, None, is_best);
@@ -40,7 +40,7 @@ use sp_trie::DBValue;
 	feature = "test-helpers",
 	test
 ))]
-pub const NUM_COLUMNS: u32 = 13;
+pub const NUM_COLUMNS: u32 = 15;
 /// Meta column. The set of keys in the column is shared by full && light storages.
 pub const COLUMN_META: u32 = 0;
 
@@ -62,6 +62,18 @@ pub mod meta_keys {
 	pub const LEAF_PREFIX: &[u8; 4] = b"leaf";
 	/// Children prefix list key.
 	pub const CHILDREN_PREFIX: &[u8; 8] = b"children";
+	/// Block whose state is stored in the flat state.
+	pub const FLAT_STATE: &[u8; 5] = b"fhead";
+	/// Last generation of the flat state.
+	pub const FLAT_STATE_GENERATION: &[u8; 4] = b"fgen";
+	/// Flat state deltas prefix key.
+	pub const FLAT_STATE_DELTA_PREFIX: &[u8; 6] = b"fdelta";
+	/// Prefix of the logs of the keys of each flat state generation.
+	pub const FLAT_STATE_KEYS_PREFIX: &[u8; 5] = b"fkeys";
+	/// Last finalized block whose changes are linked in the state diff index.
+	pub const STATE_DIFF: &[u8; 5] = b"dhead";
+	/// Numbers of the blocks whose justifications are kept as they enact authority set changes.
+	pub const JUSTIFICATION_PINS: &[u8; 5] = b"jpins";
 }
 
 /// Database metadata.
@@ -118,6 +130,26 @@ where
 	Ok(lookup_key)
 }
 
+/// Extract the block number from a lookup key.
+pub fn lookup_key_to_number<N>(key: &[u8]) -> sp_blockchain::Result<N>
+where
+	N: From<u32>,
+{
+	if key.len() < 4 {
+		return Err(sp_blockchain::Error::Backend("Invalid block key".into()))
+	}
+	let number =
+		(key[0] as u32) << 24 | (key[1] as u32) << 16 | (key[2] as u32) << 8 | (key[3] as u32);
+	Ok(number.into())
+}
+
+/// Extract the block hash from a lookup key.
+pub fn lookup_key_to_hash<H: Decode>(key: &[u8]) -> sp_blockchain::Result<H> {
+	key.get(4..)
+		.and_then(|mut hash| H::decode(&mut hash).ok())
+		.ok_or_else(|| sp_blockchain::Error::Backend("Invalid block key".into()))
+}
+
 /// Delete number to hash mapping in DB transaction.
 pub fn remove_number_to_key_mapping<N: TryInto<u32>>(
 	transaction: &mut Transaction<DbHash>,
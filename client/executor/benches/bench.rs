@@ -65,6 +65,7 @@ fn initialize(
 			heap_pages,
 			host_functions,
 			allow_missing_func_imports,
+			false,
 		)
 		.map(|runtime| -> Arc<dyn WasmModule> { Arc::new(runtime) }),
 		#[cfg(feature = "wasmtime")]
@@ -79,7 +80,9 @@ fn initialize(
 					canonicalize_nans: false,
 					parallel_compilation: true,
 					max_memory_size: None,
+					fuel_metering: false,
 				},
+				host_function_profiling: false,
 			};
 
 			if precompile {
@@ -111,6 +111,9 @@ pub enum Error {
 
 	#[error("Execution aborted due to trap: {0}")]
 	AbortedDueToTrap(MessageWithBacktrace),
+
+	#[error("Execution aborted after consuming its budget of {0} fuel")]
+	OutOfFuel(u64),
 }
 
 impl wasmi::HostError for Error {}
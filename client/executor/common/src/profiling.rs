@@ -0,0 +1,125 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Profiling of the host function calls made by a runtime.
+
+use sp_wasm_interface::{Function, FunctionContext, Pointer, Sandbox, Value, WordSize};
+use std::{
+	cell::Cell,
+	collections::HashMap,
+	time::{Duration, Instant},
+};
+
+/// Statistics of the calls to a host function.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HostFunctionStats {
+	/// Number of calls.
+	pub calls: u64,
+	/// Time spent in the host function.
+	pub time: Duration,
+	/// Number of bytes the host function read from the wasm memory.
+	pub bytes_read: u64,
+	/// Number of bytes the host function wrote to the wasm memory.
+	pub bytes_written: u64,
+}
+
+impl HostFunctionStats {
+	fn add(&mut self, other: &HostFunctionStats) {
+		self.calls += other.calls;
+		self.time += other.time;
+		self.bytes_read += other.bytes_read;
+		self.bytes_written += other.bytes_written;
+	}
+}
+
+/// The host function calls made by a runtime instance, by host function name.
+#[derive(Debug, Default, Clone)]
+pub struct HostFunctionProfile {
+	/// Statistics of the called host functions.
+	pub host_functions: HashMap<&'static str, HostFunctionStats>,
+}
+
+impl HostFunctionProfile {
+	/// Record the statistics of calls to the host function `name`.
+	pub fn record(&mut self, name: &'static str, stats: &HostFunctionStats) {
+		self.host_functions.entry(name).or_default().add(stats);
+	}
+
+	/// Returns the total time spent in host functions.
+	pub fn host_time(&self) -> Duration {
+		self.host_functions.values().map(|stats| stats.time).sum()
+	}
+}
+
+/// Execute the host `function`, measuring the call.
+pub fn execute_profiled(
+	function: &dyn Function,
+	context: &mut dyn FunctionContext,
+	args: &mut dyn Iterator<Item = Value>,
+) -> (sp_wasm_interface::Result<Option<Value>>, HostFunctionStats) {
+	let mut context =
+		CountingContext { inner: context, bytes_read: Cell::new(0), bytes_written: 0 };
+	let start = Instant::now();
+	let result = function.execute(&mut context, args);
+	let stats = HostFunctionStats {
+		calls: 1,
+		time: start.elapsed(),
+		bytes_read: context.bytes_read.get(),
+		bytes_written: context.bytes_written,
+	};
+	(result, stats)
+}
+
+/// A [`FunctionContext`] counting the bytes moved between the host and the wasm memory.
+struct CountingContext<'a> {
+	inner: &'a mut dyn FunctionContext,
+	bytes_read: Cell<u64>,
+	bytes_written: u64,
+}
+
+impl<'a> FunctionContext for CountingContext<'a> {
+	fn read_memory_into(
+		&self,
+		address: Pointer<u8>,
+		dest: &mut [u8],
+	) -> sp_wasm_interface::Result<()> {
+		self.bytes_read.set(self.bytes_read.get() + dest.len() as u64);
+		self.inner.read_memory_into(address, dest)
+	}
+
+	fn write_memory(&mut self, address: Pointer<u8>, data: &[u8]) -> sp_wasm_interface::Result<()> {
+		self.bytes_written += data.len() as u64;
+		self.inner.write_memory(address, data)
+	}
+
+	fn allocate_memory(&mut self, size: WordSize) -> sp_wasm_interface::Result<Pointer<u8>> {
+		self.inner.allocate_memory(size)
+	}
+
+	fn deallocate_memory(&mut self, ptr: Pointer<u8>) -> sp_wasm_interface::Result<()> {
+		self.inner.deallocate_memory(ptr)
+	}
+
+	fn sandbox(&mut self) -> &mut dyn Sandbox {
+		self.inner.sandbox()
+	}
+
+	fn register_panic_error_message(&mut self, message: &str) {
+		self.inner.register_panic_error_message(message)
+	}
+}
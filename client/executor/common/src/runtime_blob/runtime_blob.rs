@@ -35,7 +35,8 @@ impl RuntimeBlob {
 	/// Create `RuntimeBlob` from the given wasm code. Will attempt to decompress the code before
 	/// deserializing it.
 	///
-	/// See [`sp_maybe_compressed_blob`] for details about decompression.
+	/// See [`sp_maybe_compressed_blob`] for details about decompression. Deltas are refused, since
+	/// they must be decoded against their base runtime first.
 	pub fn uncompress_if_needed(wasm_code: &[u8]) -> Result<Self, WasmError> {
 		use sp_maybe_compressed_blob::CODE_BLOB_BOMB_LIMIT;
 		let wasm_code = sp_maybe_compressed_blob::decompress(wasm_code, CODE_BLOB_BOMB_LIMIT)
@@ -94,6 +95,21 @@ impl RuntimeBlob {
 		Ok(Self { raw_module: injected_module })
 	}
 
+	/// Injects calls to the imported function `gas_module_name::gas` that report the number of
+	/// instructions about to be executed, one per instruction.
+	///
+	/// This allows an engine without native fuel metering to meter the execution.
+	pub fn inject_gas_metering(self, gas_module_name: &str) -> Result<Self, WasmError> {
+		let injected_module = wasm_instrument::gas_metering::inject(
+			self.raw_module,
+			&wasm_instrument::gas_metering::ConstantCostRules::default(),
+			gas_module_name,
+		)
+		.map_err(|_| WasmError::Other("cannot inject the gas metering".into()))?;
+
+		Ok(Self { raw_module: injected_module })
+	}
+
 	/// Perform an instrumentation that makes sure that a specific function `entry_point` is
 	/// exported
 	pub fn entry_point_exists(&self, entry_point: &str) -> bool {
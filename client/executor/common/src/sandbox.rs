@@ -18,11 +18,13 @@
 
 //! This module implements sandboxing support in the runtime.
 //!
-//! Sandboxing is backed by wasmi and wasmer, depending on the configuration.
+//! Sandboxing is backed by wasmi, wasmer or wasmtime, depending on the configuration.
 
 #[cfg(feature = "wasmer-sandbox")]
 mod wasmer_backend;
 mod wasmi_backend;
+#[cfg(feature = "wasmtime-sandbox")]
+mod wasmtime_backend;
 
 use std::{collections::HashMap, rc::Rc};
 
@@ -45,6 +47,12 @@ use self::wasmi_backend::{
 	get_global as wasmi_get_global, instantiate as wasmi_instantiate, invoke as wasmi_invoke,
 	new_memory as wasmi_new_memory, MemoryWrapper as WasmiMemoryWrapper,
 };
+#[cfg(feature = "wasmtime-sandbox")]
+use self::wasmtime_backend::{
+	get_global as wasmtime_get_global, instantiate as wasmtime_instantiate,
+	invoke as wasmtime_invoke, new_memory as wasmtime_new_memory, Backend as WasmtimeBackend,
+	InstanceWrapper as WasmtimeInstanceWrapper, MemoryWrapper as WasmtimeMemoryWrapper,
+};
 
 /// Index of a function inside the supervisor.
 ///
@@ -163,6 +171,10 @@ enum BackendInstance {
 	/// Wasmer module instance
 	#[cfg(feature = "wasmer-sandbox")]
 	Wasmer(wasmer::Instance),
+
+	/// Wasmtime module instance
+	#[cfg(feature = "wasmtime-sandbox")]
+	Wasmtime(WasmtimeInstanceWrapper),
 }
 
 /// Sandboxed instance of a wasm module.
@@ -206,6 +218,10 @@ impl SandboxInstance {
 			#[cfg(feature = "wasmer-sandbox")]
 			BackendInstance::Wasmer(wasmer_instance) =>
 				wasmer_invoke(wasmer_instance, export_name, args, state, sandbox_context),
+
+			#[cfg(feature = "wasmtime-sandbox")]
+			BackendInstance::Wasmtime(wasmtime_instance) =>
+				wasmtime_invoke(wasmtime_instance, export_name, args, state, sandbox_context),
 		}
 	}
 
@@ -218,6 +234,10 @@ impl SandboxInstance {
 
 			#[cfg(feature = "wasmer-sandbox")]
 			BackendInstance::Wasmer(wasmer_instance) => wasmer_get_global(wasmer_instance, name),
+
+			#[cfg(feature = "wasmtime-sandbox")]
+			BackendInstance::Wasmtime(wasmtime_instance) =>
+				wasmtime_get_global(wasmtime_instance, name),
 		}
 	}
 }
@@ -314,6 +334,7 @@ impl UnregisteredInstance {
 }
 
 /// Sandbox backend to use
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SandboxBackend {
 	/// Wasm interpreter
 	Wasmi,
@@ -324,6 +345,10 @@ pub enum SandboxBackend {
 
 	/// Use wasmer backend if available. Fall back to wasmi otherwise.
 	TryWasmer,
+
+	/// Wasmtime environment
+	#[cfg(feature = "wasmtime-sandbox")]
+	Wasmtime,
 }
 
 /// Memory reference in terms of a selected backend
@@ -335,6 +360,10 @@ pub enum Memory {
 	/// Wasmer memory refernce
 	#[cfg(feature = "wasmer-sandbox")]
 	Wasmer(WasmerMemoryWrapper),
+
+	/// Wasmtime memory reference
+	#[cfg(feature = "wasmtime-sandbox")]
+	Wasmtime(WasmtimeMemoryWrapper),
 }
 
 impl Memory {
@@ -345,6 +374,9 @@ impl Memory {
 
 			#[cfg(feature = "wasmer-sandbox")]
 			Memory::Wasmer(_) => None,
+
+			#[cfg(feature = "wasmtime-sandbox")]
+			Memory::Wasmtime(_) => None,
 		}
 	}
 
@@ -354,6 +386,21 @@ impl Memory {
 		match self {
 			Memory::Wasmer(memory) => Some(memory.clone()),
 			Memory::Wasmi(_) => None,
+
+			#[cfg(feature = "wasmtime-sandbox")]
+			Memory::Wasmtime(_) => None,
+		}
+	}
+
+	/// View as wasmtime memory
+	#[cfg(feature = "wasmtime-sandbox")]
+	pub fn as_wasmtime(&self) -> Option<WasmtimeMemoryWrapper> {
+		match self {
+			Memory::Wasmtime(memory) => Some(memory.clone()),
+			Memory::Wasmi(_) => None,
+
+			#[cfg(feature = "wasmer-sandbox")]
+			Memory::Wasmer(_) => None,
 		}
 	}
 }
@@ -365,6 +412,9 @@ impl util::MemoryTransfer for Memory {
 
 			#[cfg(feature = "wasmer-sandbox")]
 			Memory::Wasmer(sandboxed_memory) => sandboxed_memory.read(source_addr, size),
+
+			#[cfg(feature = "wasmtime-sandbox")]
+			Memory::Wasmtime(sandboxed_memory) => sandboxed_memory.read(source_addr, size),
 		}
 	}
 
@@ -374,6 +424,10 @@ impl util::MemoryTransfer for Memory {
 
 			#[cfg(feature = "wasmer-sandbox")]
 			Memory::Wasmer(sandboxed_memory) => sandboxed_memory.read_into(source_addr, destination),
+
+			#[cfg(feature = "wasmtime-sandbox")]
+			Memory::Wasmtime(sandboxed_memory) =>
+				sandboxed_memory.read_into(source_addr, destination),
 		}
 	}
 
@@ -383,6 +437,9 @@ impl util::MemoryTransfer for Memory {
 
 			#[cfg(feature = "wasmer-sandbox")]
 			Memory::Wasmer(sandboxed_memory) => sandboxed_memory.write_from(dest_addr, source),
+
+			#[cfg(feature = "wasmtime-sandbox")]
+			Memory::Wasmtime(sandboxed_memory) => sandboxed_memory.write_from(dest_addr, source),
 		}
 	}
 }
@@ -395,6 +452,10 @@ enum BackendContext {
 	/// Wasmer specific context
 	#[cfg(feature = "wasmer-sandbox")]
 	Wasmer(WasmerBackend),
+
+	/// Wasmtime specific context
+	#[cfg(feature = "wasmtime-sandbox")]
+	Wasmtime(WasmtimeBackend),
 }
 
 impl BackendContext {
@@ -408,6 +469,9 @@ impl BackendContext {
 			#[cfg(feature = "wasmer-sandbox")]
 			SandboxBackend::Wasmer | SandboxBackend::TryWasmer =>
 				BackendContext::Wasmer(WasmerBackend::new()),
+
+			#[cfg(feature = "wasmtime-sandbox")]
+			SandboxBackend::Wasmtime => BackendContext::Wasmtime(WasmtimeBackend::new()),
 		}
 	}
 }
@@ -455,6 +519,9 @@ impl<DT: Clone> Store<DT> {
 
 			#[cfg(feature = "wasmer-sandbox")]
 			BackendContext::Wasmer(context) => wasmer_new_memory(context, initial, maximum)?,
+
+			#[cfg(feature = "wasmtime-sandbox")]
+			BackendContext::Wasmtime(context) => wasmtime_new_memory(context, initial, maximum)?,
 		};
 
 		let mem_idx = memories.len();
@@ -565,6 +632,10 @@ impl<DT: Clone> Store<DT> {
 			#[cfg(feature = "wasmer-sandbox")]
 			BackendContext::Wasmer(ref context) =>
 				wasmer_instantiate(context, wasm, guest_env, state, sandbox_context)?,
+
+			#[cfg(feature = "wasmtime-sandbox")]
+			BackendContext::Wasmtime(ref context) =>
+				wasmtime_instantiate(context, wasm, guest_env, state, sandbox_context)?,
 		};
 
 		Ok(UnregisteredInstance { sandbox_instance })
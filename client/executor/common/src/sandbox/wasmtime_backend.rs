@@ -0,0 +1,605 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Wasmtime specific impls for sandbox
+
+use std::{
+	cell::RefCell,
+	mem,
+	panic::{self, AssertUnwindSafe},
+	rc::Rc,
+	sync::mpsc,
+	thread,
+};
+
+use wasmtime::{AsContextMut, Caller, StoreContextMut, Trap};
+
+use codec::{Decode, Encode};
+use sp_sandbox::HostError;
+use sp_wasm_interface::{FunctionContext, Pointer, ReturnValue, Value, WordSize};
+
+use crate::{
+	error::{Error, Result},
+	runtime_blob::RuntimeBlob,
+	sandbox::{
+		BackendInstance, GuestEnvironment, InstantiationError, Memory, SandboxContext,
+		SandboxInstance, SupervisorFuncIndex,
+	},
+	util::{checked_range, MemoryTransfer},
+};
+
+/// The number of logical stack values a guest module is allowed to use.
+///
+/// The guest code is instrumented to count its stack usage, so that running out of stack
+/// is deterministic and doesn't depend on how much native stack the compiled code consumes.
+const GUEST_STACK_LIMIT: u32 = 64 * 1024;
+
+/// The maximum number of bytes of native stack that the compiled guest code can use.
+///
+/// This must be large enough for the [`GUEST_STACK_LIMIT`] to always trigger first.
+const GUEST_NATIVE_STACK_MAX: usize = 256 * 1024 * 1024;
+
+/// The native stack of the [`GuestThread`].
+///
+/// Next to the [`GUEST_NATIVE_STACK_MAX`] of the guest code it fits the frames of wasmtime and of
+/// the host functions the guests call, which the limit doesn't account for.
+const GUEST_THREAD_STACK_SIZE: usize = GUEST_NATIVE_STACK_MAX + 16 * 1024 * 1024;
+
+environmental::environmental!(SandboxContextStore: trait SandboxContext);
+
+/// Access to the store while it is borrowed by a running guest.
+pub trait GuestStore {
+	/// Returns the store the guest is executing in.
+	fn store(&mut self) -> StoreContextMut<'_, ()>;
+}
+
+impl GuestStore for Caller<'_, ()> {
+	fn store(&mut self) -> StoreContextMut<'_, ()> {
+		self.as_context_mut()
+	}
+}
+
+environmental::environmental!(CallerStore: trait GuestStore);
+
+/// A job sent to the other thread of a [`GuestThread`], with the lifetimes of its borrows erased.
+type Job = Box<dyn FnOnce() + Send>;
+
+enum Message {
+	/// Run the job, then answer with [`Message::Done`].
+	Run(Job),
+	/// The last job sent to the other thread is done.
+	Done,
+}
+
+/// The end of a [`GuestThread`] on one of its two threads.
+///
+/// A thread only runs the jobs of the other one while it waits for its own job to be done, so the
+/// jobs nest like calls and only one of the threads runs at a time.
+struct Endpoint {
+	sender: mpsc::Sender<Message>,
+	receiver: mpsc::Receiver<Message>,
+}
+
+impl Endpoint {
+	/// Runs `f` on the other thread, running the jobs it sends back meanwhile.
+	///
+	/// A panic of `f` is resumed on this thread.
+	fn call<R>(&self, f: impl FnOnce() -> R) -> R {
+		let mut result = None;
+		let job: Box<dyn FnOnce() + '_> = Box::new(|| {
+			result = Some(panic::catch_unwind(AssertUnwindSafe(f)));
+		});
+		// SAFETY: The job only borrows from this frame, which waits below until the other thread
+		// is done with it. Until then this thread only runs the jobs nested in it, so `f` and its
+		// result are never accessed from both threads at once.
+		let job = unsafe { mem::transmute::<Box<dyn FnOnce() + '_>, Job>(job) };
+		self.sender
+			.send(Message::Run(job))
+			.expect("the other thread runs as long as this endpoint exists; qed");
+		self.wait();
+
+		match result.expect("the other thread is done once it ran the job; qed") {
+			Ok(result) => result,
+			Err(payload) => panic::resume_unwind(payload),
+		}
+	}
+
+	/// Runs the jobs sent by the other thread until it is done with the job sent to it.
+	fn wait(&self) {
+		loop {
+			match self
+				.receiver
+				.recv()
+				.expect("the other thread runs as long as this endpoint exists; qed")
+			{
+				Message::Run(job) => self.run(job),
+				Message::Done => return,
+			}
+		}
+	}
+
+	fn run(&self, job: Job) {
+		job();
+		// The other thread waits for the answer, unless it was ended.
+		let _ = self.sender.send(Message::Done);
+	}
+}
+
+/// A thread with a known native stack the guests run on.
+///
+/// The thread calling the sandbox can't run them itself as the native stack it has left is
+/// unknown, and wasmtime doesn't check that the [`GUEST_NATIVE_STACK_MAX`] fits into it. The host
+/// functions imported by the guests run back on the calling thread, where the externalities and
+/// the state of the supervisor live.
+///
+/// The thread ends once this is dropped.
+struct GuestThread(Endpoint);
+
+thread_local! {
+	/// The endpoint of the [`GuestThread`] on the thread of the supervisor, set on the guest
+	/// thread.
+	static SUPERVISOR: RefCell<Option<Rc<Endpoint>>> = RefCell::new(None);
+}
+
+impl GuestThread {
+	fn spawn() -> Self {
+		let (guest_sender, guest_receiver) = mpsc::channel();
+		let (supervisor_sender, supervisor_receiver) = mpsc::channel();
+
+		thread::Builder::new()
+			.name("sandbox-guest".into())
+			.stack_size(GUEST_THREAD_STACK_SIZE)
+			.spawn(move || {
+				let supervisor =
+					Rc::new(Endpoint { sender: supervisor_sender, receiver: guest_receiver });
+				SUPERVISOR.with(|endpoint| *endpoint.borrow_mut() = Some(supervisor.clone()));
+
+				// Ends once the endpoint of the supervisor is dropped.
+				while let Ok(Message::Run(job)) = supervisor.receiver.recv() {
+					supervisor.run(job);
+				}
+			})
+			.expect("failed to spawn the thread of the sandbox guests");
+
+		GuestThread(Endpoint { sender: guest_sender, receiver: supervisor_receiver })
+	}
+}
+
+/// Runs `f` on the thread of the supervisor, from a host function called by a guest through
+/// `caller`.
+fn on_supervisor<R>(caller: &mut Caller<'_, ()>, f: impl FnOnce() -> R) -> R {
+	let supervisor = SUPERVISOR
+		.with(|endpoint| endpoint.borrow().clone())
+		.expect("guests only run on the guest thread, which sets `SUPERVISOR`; qed");
+	supervisor.call(|| CallerStore::using(caller, f))
+}
+
+/// The store all guest instances and memories live in, with the thread the guests run on.
+struct SharedStore {
+	store: RefCell<wasmtime::Store<()>>,
+	/// Spawned when a guest runs for the first time.
+	guest_thread: RefCell<Option<Rc<GuestThread>>>,
+}
+
+impl SharedStore {
+	/// Runs `f`, which calls into the guests, on the [`GuestThread`].
+	fn run_guest<R>(&self, f: impl FnOnce() -> R) -> R {
+		let guest_thread = self
+			.guest_thread
+			.borrow_mut()
+			.get_or_insert_with(|| Rc::new(GuestThread::spawn()))
+			.clone();
+		guest_thread.0.call(f)
+	}
+}
+
+type StoreHandle = Rc<SharedStore>;
+
+/// Runs `f` with the store all guest instances and memories live in.
+///
+/// While a guest is running the store is exclusively borrowed by the call, so the supervisor
+/// can only reach it through the caller of the host function it was called from.
+fn with_store<R>(store: &StoreHandle, f: impl FnOnce(StoreContextMut<'_, ()>) -> R) -> R {
+	match store.store.try_borrow_mut() {
+		Ok(mut store) => f(store.as_context_mut()),
+		Err(_) => CallerStore::with(|caller| f(caller.store())).expect(
+			"the store is only borrowed while a guest is running; \
+			host functions called by the guest set `CallerStore`; qed",
+		),
+	}
+}
+
+/// Wasmtime specific context
+pub struct Backend {
+	store: StoreHandle,
+}
+
+impl Backend {
+	pub fn new() -> Self {
+		let mut config = wasmtime::Config::new();
+
+		// Make the execution of guests deterministic.
+		config.cranelift_nan_canonicalization(true);
+		config.wasm_threads(false);
+		config.wasm_simd(false);
+		config.wasm_bulk_memory(false);
+		config.wasm_reference_types(false);
+		config.wasm_multi_value(false);
+		config.wasm_multi_memory(false);
+		config.wasm_module_linking(false);
+		config.wasm_memory64(false);
+		config
+			.max_wasm_stack(GUEST_NATIVE_STACK_MAX)
+			.expect("the native stack limit is a non-zero constant; qed");
+
+		let engine = wasmtime::Engine::new(&config)
+			.expect("the configuration only consists of supported settings; qed");
+
+		Backend {
+			store: Rc::new(SharedStore {
+				store: RefCell::new(wasmtime::Store::new(&engine, ())),
+				guest_thread: RefCell::new(None),
+			}),
+		}
+	}
+}
+
+/// Wasmtime module instance together with the store it lives in
+pub struct InstanceWrapper {
+	instance: wasmtime::Instance,
+	store: StoreHandle,
+}
+
+/// Invoke a function within a sandboxed module
+pub fn invoke(
+	instance: &InstanceWrapper,
+	export_name: &str,
+	args: &[Value],
+	_state: u32,
+	sandbox_context: &mut dyn SandboxContext,
+) -> std::result::Result<Option<Value>, Error> {
+	let args: Vec<wasmtime::Val> = args
+		.iter()
+		.map(|v| match *v {
+			Value::I32(val) => wasmtime::Val::I32(val),
+			Value::I64(val) => wasmtime::Val::I64(val),
+			Value::F32(val) => wasmtime::Val::F32(val),
+			Value::F64(val) => wasmtime::Val::F64(val),
+		})
+		.collect();
+
+	let wasmtime_result = with_store(&instance.store, |mut store| {
+		let function = instance
+			.instance
+			.get_func(&mut store, export_name)
+			.ok_or_else(|| Error::Sandbox(format!("function {} not found", export_name)))?;
+
+		let mut results = vec![wasmtime::Val::I32(0); function.ty(&store).results().len()];
+
+		SandboxContextStore::using(sandbox_context, || {
+			instance
+				.store
+				.run_guest(|| function.call(&mut store, &args, &mut results))
+				.map_err(|error| Error::Sandbox(error.to_string()))
+		})?;
+
+		Ok(results)
+	})?;
+
+	match wasmtime_result.as_slice() {
+		[] => Ok(None),
+
+		[wasm_value] => {
+			let wasmtime_value = match *wasm_value {
+				wasmtime::Val::I32(val) => Value::I32(val),
+				wasmtime::Val::I64(val) => Value::I64(val),
+				wasmtime::Val::F32(val) => Value::F32(val),
+				wasmtime::Val::F64(val) => Value::F64(val),
+				_ =>
+					return Err(Error::Sandbox(format!(
+						"Unsupported return value: {:?}",
+						wasm_value,
+					))),
+			};
+
+			Ok(Some(wasmtime_value))
+		},
+
+		_ => Err(Error::Sandbox("multiple return types are not supported yet".into())),
+	}
+}
+
+/// Instantiate a module within a sandbox context
+pub fn instantiate(
+	context: &Backend,
+	wasm: &[u8],
+	guest_env: GuestEnvironment,
+	state: u32,
+	sandbox_context: &mut dyn SandboxContext,
+) -> std::result::Result<Rc<SandboxInstance>, InstantiationError> {
+	let wasm = RuntimeBlob::new(wasm)
+		.and_then(|blob| blob.inject_stack_depth_metering(GUEST_STACK_LIMIT))
+		.map_err(|_| InstantiationError::ModuleDecoding)?
+		.serialize();
+
+	let instance = with_store(&context.store, |mut store| {
+		let module = wasmtime::Module::new(store.engine(), &wasm)
+			.map_err(|_| InstantiationError::ModuleDecoding)?;
+
+		let mut linker = wasmtime::Linker::new(store.engine());
+
+		for import in module.imports() {
+			let name = import.name().ok_or(InstantiationError::ModuleDecoding)?;
+
+			match import.ty() {
+				wasmtime::ExternType::Memory(_) => {
+					let memory = guest_env
+						.imports
+						.memory_by_name(import.module(), name)
+						.ok_or(InstantiationError::ModuleDecoding)?;
+
+					let wasmtime_memory = memory.as_wasmtime().expect(
+						"memory is created by wasmtime; \
+						exported by the same module and backend; \
+						thus the operation can't fail; \
+						qed",
+					);
+
+					linker
+						.define(import.module(), name, wasmtime_memory.memory)
+						.map_err(|_| InstantiationError::EnvironmentDefinitionCorrupted)?;
+				},
+
+				wasmtime::ExternType::Func(func_ty) => {
+					let guest_func_index = if let Some(index) =
+						guest_env.imports.func_by_name(import.module(), name)
+					{
+						index
+					} else {
+						// Missing import, the instantiation below will fail.
+						continue
+					};
+
+					let supervisor_func_index = guest_env
+						.guest_to_supervisor_mapping
+						.func_by_guest_index(guest_func_index)
+						.ok_or(InstantiationError::ModuleDecoding)?;
+
+					let function =
+						dispatch_function(supervisor_func_index, &mut store, func_ty, state);
+
+					linker
+						.define(import.module(), name, function)
+						.map_err(|_| InstantiationError::EnvironmentDefinitionCorrupted)?;
+				},
+
+				// Nothing to do here, these imports can't be satisfied.
+				_ => (),
+			}
+		}
+
+		SandboxContextStore::using(sandbox_context, || {
+			let instance = context.store.run_guest(|| linker.instantiate(&mut store, &module));
+			instance.map_err(|error| {
+				if error.downcast_ref::<Trap>().is_some() {
+					InstantiationError::StartTrapped
+				} else {
+					InstantiationError::Instantiation
+				}
+			})
+		})
+	})?;
+
+	Ok(Rc::new(SandboxInstance {
+		backend_instance: BackendInstance::Wasmtime(InstanceWrapper {
+			instance,
+			store: context.store.clone(),
+		}),
+		guest_to_supervisor_mapping: guest_env.guest_to_supervisor_mapping,
+	}))
+}
+
+fn dispatch_function(
+	supervisor_func_index: SupervisorFuncIndex,
+	store: impl AsContextMut<Data = ()>,
+	func_ty: wasmtime::FuncType,
+	state: u32,
+) -> wasmtime::Func {
+	wasmtime::Func::new(store, func_ty, move |mut caller, params, results| {
+		on_supervisor(&mut caller, || {
+			SandboxContextStore::with(|sandbox_context| {
+				// Serialize arguments into a byte vector.
+				let invoke_args_data = params
+					.iter()
+					.map(|val| match val {
+						wasmtime::Val::I32(val) => Ok(Value::I32(*val)),
+						wasmtime::Val::I64(val) => Ok(Value::I64(*val)),
+						wasmtime::Val::F32(val) => Ok(Value::F32(*val)),
+						wasmtime::Val::F64(val) => Ok(Value::F64(*val)),
+						_ => Err(Trap::new(format!("Unsupported function argument: {:?}", val))),
+					})
+					.collect::<std::result::Result<Vec<_>, _>>()?
+					.encode();
+
+				// Move serialized arguments inside the memory, invoke dispatch thunk and
+				// then free allocated memory.
+				let invoke_args_len = invoke_args_data.len() as WordSize;
+				let invoke_args_ptr =
+					sandbox_context.supervisor_context().allocate_memory(invoke_args_len).map_err(
+						|_| Trap::new("Can't allocate memory in supervisor for the arguments"),
+					)?;
+
+				let deallocate = |fe: &mut dyn FunctionContext, ptr, fail_msg| {
+					fe.deallocate_memory(ptr).map_err(|_| Trap::new(fail_msg))
+				};
+
+				if sandbox_context
+					.supervisor_context()
+					.write_memory(invoke_args_ptr, &invoke_args_data)
+					.is_err()
+				{
+					deallocate(
+						sandbox_context.supervisor_context(),
+						invoke_args_ptr,
+						"Failed dealloction after failed write of invoke arguments",
+					)?;
+
+					return Err(Trap::new("Can't write invoke args into memory"))
+				}
+
+				// Perform the actual call
+				let serialized_result = sandbox_context
+					.invoke(invoke_args_ptr, invoke_args_len, state, supervisor_func_index)
+					.map_err(|e| Trap::new(e.to_string()));
+
+				deallocate(
+					sandbox_context.supervisor_context(),
+					invoke_args_ptr,
+					"Failed dealloction after invoke",
+				)?;
+
+				let serialized_result = serialized_result?;
+
+				// dispatch_thunk returns pointer to serialized arguments.
+				// Unpack pointer and len of the serialized result data.
+				let (serialized_result_val_ptr, serialized_result_val_len) = {
+					// Cast to u64 to use zero-extension.
+					let v = serialized_result as u64;
+					let ptr = (v as u64 >> 32) as u32;
+					let len = (v & 0xFFFFFFFF) as u32;
+					(Pointer::new(ptr), len)
+				};
+
+				let serialized_result_val = sandbox_context
+					.supervisor_context()
+					.read_memory(serialized_result_val_ptr, serialized_result_val_len)
+					.map_err(|_| Trap::new("Can't read the serialized result from dispatch thunk"));
+
+				deallocate(
+					sandbox_context.supervisor_context(),
+					serialized_result_val_ptr,
+					"Can't deallocate memory for dispatch thunk's result",
+				)?;
+
+				let serialized_result_val = serialized_result_val?;
+
+				let deserialized_result = std::result::Result::<ReturnValue, HostError>::decode(
+					&mut serialized_result_val.as_slice(),
+				)
+				.map_err(|_| Trap::new("Decoding Result<ReturnValue, HostError> failed!"))?
+				.map_err(|_| Trap::new("Supervisor function returned sandbox::HostError"))?;
+
+				let result = match deserialized_result {
+					ReturnValue::Value(Value::I32(val)) => Some(wasmtime::Val::I32(val)),
+					ReturnValue::Value(Value::I64(val)) => Some(wasmtime::Val::I64(val)),
+					ReturnValue::Value(Value::F32(val)) => Some(wasmtime::Val::F32(val)),
+					ReturnValue::Value(Value::F64(val)) => Some(wasmtime::Val::F64(val)),
+
+					ReturnValue::Unit => None,
+				};
+
+				match (result, &mut *results) {
+					(None, []) => Ok(()),
+					(Some(value), [result]) => {
+						*result = value;
+						Ok(())
+					},
+					_ => Err(Trap::new("Supervisor function returned an unexpected result")),
+				}
+			})
+			.expect("SandboxContextStore is set when invoking sandboxed functions; qed")
+		})
+	})
+}
+
+/// Allocate new memory region
+pub fn new_memory(context: &Backend, initial: u32, maximum: Option<u32>) -> Result<Memory> {
+	let ty = wasmtime::MemoryType::new(initial, maximum);
+	let memory = with_store(&context.store, |store| wasmtime::Memory::new(store, ty))
+		.map_err(|_| Error::InvalidMemoryReference)?;
+
+	Ok(Memory::Wasmtime(MemoryWrapper { memory, store: context.store.clone() }))
+}
+
+/// Wasmtime memory together with the store it lives in.
+///
+/// All memory operations go through the store, which enforces the memory access protocol.
+#[derive(Clone)]
+pub struct MemoryWrapper {
+	memory: wasmtime::Memory,
+	store: StoreHandle,
+}
+
+impl std::fmt::Debug for MemoryWrapper {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("MemoryWrapper").field("memory", &self.memory).finish()
+	}
+}
+
+impl MemoryTransfer for MemoryWrapper {
+	fn read(&self, source_addr: Pointer<u8>, size: usize) -> Result<Vec<u8>> {
+		with_store(&self.store, |store| {
+			let source = self.memory.data(&store);
+
+			let range = checked_range(source_addr.into(), size, source.len())
+				.ok_or_else(|| Error::Other("memory read is out of bounds".into()))?;
+
+			Ok(source[range].to_vec())
+		})
+	}
+
+	fn read_into(&self, source_addr: Pointer<u8>, destination: &mut [u8]) -> Result<()> {
+		with_store(&self.store, |store| {
+			let source = self.memory.data(&store);
+
+			let range = checked_range(source_addr.into(), destination.len(), source.len())
+				.ok_or_else(|| Error::Other("memory read is out of bounds".into()))?;
+
+			destination.copy_from_slice(&source[range]);
+			Ok(())
+		})
+	}
+
+	fn write_from(&self, dest_addr: Pointer<u8>, source: &[u8]) -> Result<()> {
+		with_store(&self.store, |store| {
+			let destination = self.memory.data_mut(store);
+
+			let range = checked_range(dest_addr.into(), source.len(), destination.len())
+				.ok_or_else(|| Error::Other("memory write is out of bounds".into()))?;
+
+			destination[range].copy_from_slice(source);
+			Ok(())
+		})
+	}
+}
+
+/// Get global value by name
+pub fn get_global(instance: &InstanceWrapper, name: &str) -> Option<Value> {
+	with_store(&instance.store, |mut store| {
+		let global = instance.instance.get_global(&mut store, name)?;
+		let wasmtime_value = match global.get(&mut store) {
+			wasmtime::Val::I32(val) => Value::I32(val),
+			wasmtime::Val::I64(val) => Value::I64(val),
+			wasmtime::Val::F32(val) => Value::F32(val),
+			wasmtime::Val::F64(val) => Value::F64(val),
+			_ => None?,
+		};
+
+		Some(wasmtime_value)
+	})
+}
@@ -18,7 +18,8 @@
 
 //! Definitions for a wasm runtime.
 
-use crate::error::Error;
+use crate::{error::Error, profiling::HostFunctionProfile, sandbox::SandboxBackend};
+use sc_allocator::AllocationStats;
 use sp_wasm_interface::Value;
 
 /// A method to be used to find the entrypoint when calling into the runtime
@@ -94,6 +95,36 @@ pub trait WasmInstance: Send {
 	/// This method is only suitable for getting immutable globals.
 	fn get_global_const(&mut self, name: &str) -> Result<Option<Value>, Error>;
 
+	/// Limit the fuel the following calls may consume to `fuel`, or lift the limit if `None`.
+	///
+	/// A call running out of fuel fails with [`Error::OutOfFuel`]. This has no effect unless the
+	/// runtime was created with fuel metering.
+	fn set_fuel(&mut self, _fuel: Option<u64>) -> Result<(), Error> {
+		Ok(())
+	}
+
+	/// Create the sandboxes of the following calls with `backend`.
+	///
+	/// This has no effect if the runtime doesn't support sandboxing.
+	fn set_sandbox_backend(&mut self, _backend: SandboxBackend) {}
+
+	/// Start or stop recording the host function calls of the following calls.
+	///
+	/// This has no effect unless the runtime was created with host function profiling.
+	fn set_host_function_profiling(&mut self, _enabled: bool) {}
+
+	/// Take the host function calls recorded since the profiling started, if it did.
+	fn take_host_function_profile(&mut self) -> Option<HostFunctionProfile> {
+		None
+	}
+
+	/// Take the heap usage of the last call, if it is not taken yet.
+	///
+	/// This is `None` if the call failed before allocating its input.
+	fn take_allocation_stats(&mut self) -> Option<AllocationStats> {
+		None
+	}
+
 	/// **Testing Only**. This function returns the base address of the linear memory.
 	///
 	/// This is meant to be the starting address of the memory mapped area for the linear memory.
@@ -0,0 +1,259 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Precompiled runtime artifacts.
+//!
+//! Compiling a runtime with wasmtime takes long enough to stall the node on startup or after a
+//! runtime upgrade. Runtimes can instead be compiled ahead of time into artifacts, which are kept
+//! in a directory and loaded from there.
+//!
+//! An artifact is identified by the hash of the runtime code, the version of wasmtime and the
+//! [`Semantics`] it was compiled with, and consists of two files named after these:
+//!
+//! - `<id>.cwasm` holds the compiled code.
+//! - `<id>.meta` holds the SCALE encoded `ArtifactMetadata`, which repeats what identifies the
+//!   artifact along with a checksum of the compiled code.
+//!
+//! The metadata is written last, an artifact without one is incomplete and ignored. The compiled
+//! code is read into memory and checked once, the checked copy is what gets loaded.
+//!
+//! # Trust
+//!
+//! The artifacts hold native code which is loaded without being validated, so they must be as
+//! trusted as the node binary itself. The checksum only detects corrupted artifacts: anyone able
+//! to write to the artifacts directory can replace the code along with its metadata and execute
+//! arbitrary code in the node. The directory must only be writable by the node and by the
+//! operators preparing the artifacts.
+
+use codec::{Decode, Encode};
+use sc_executor_common::{error::WasmError, runtime_blob::RuntimeBlob};
+use sc_executor_wasmtime::Semantics;
+use sp_core::{hashing::blake2_256, hexdisplay::HexDisplay};
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+/// Version of the layout of the artifacts. Bump it when changing `ArtifactMetadata`.
+const ARTIFACT_FORMAT_VERSION: u32 = 1;
+
+const CODE_EXTENSION: &str = "cwasm";
+const METADATA_EXTENSION: &str = "meta";
+
+/// What identifies an artifact, stored next to its compiled code.
+#[derive(Encode, Decode, PartialEq, Eq, Debug)]
+struct ArtifactMetadata {
+	format_version: u32,
+	code_hash: Vec<u8>,
+	wasmtime_version: String,
+	semantics: Vec<u8>,
+	/// `blake2_256` of the compiled code.
+	checksum: [u8; 32],
+}
+
+/// A directory of precompiled runtime artifacts.
+///
+/// See the [module documentation](self) for the trust the directory requires.
+#[derive(Clone, Debug)]
+pub struct RuntimeArtifacts {
+	dir: PathBuf,
+}
+
+impl RuntimeArtifacts {
+	/// Use the artifacts in `dir`. The directory is created when the first artifact is prepared.
+	pub fn new(dir: PathBuf) -> Self {
+		Self { dir }
+	}
+
+	fn path(
+		&self,
+		code_hash: &[u8],
+		semantics: &Semantics,
+		extension: &str,
+	) -> Result<PathBuf, WasmError> {
+		let id = blake2_256(
+			&(ARTIFACT_FORMAT_VERSION, code_hash, wasmtime_version()?, semantics.fingerprint())
+				.encode(),
+		);
+		Ok(self.dir.join(format!("{}.{}", HexDisplay::from(&id), extension)))
+	}
+
+	/// Returns the path of the compiled code of the runtime with the given `code_hash`.
+	pub fn code_path(&self, code_hash: &[u8], semantics: &Semantics) -> Result<PathBuf, WasmError> {
+		self.path(code_hash, semantics, CODE_EXTENSION)
+	}
+
+	/// Returns the compiled code of the runtime with the given `code_hash`, if a valid artifact
+	/// for it is present.
+	///
+	/// The code is returned as it was checked, it isn't read again from the disk. Artifacts
+	/// failing the integrity checks are removed.
+	pub fn load(&self, code_hash: &[u8], semantics: &Semantics) -> Option<Vec<u8>> {
+		let metadata_path = self.path(code_hash, semantics, METADATA_EXTENSION).ok()?;
+		let code_path = self.code_path(code_hash, semantics).ok()?;
+		let metadata = fs::read(&metadata_path).ok()?;
+
+		let checked = ArtifactMetadata::decode(&mut &metadata[..])
+			.map_err(|e| format!("invalid metadata: {}", e))
+			.and_then(|metadata| {
+				let expected = ArtifactMetadata {
+					format_version: ARTIFACT_FORMAT_VERSION,
+					code_hash: code_hash.to_vec(),
+					wasmtime_version: wasmtime_version().map_err(|e| e.to_string())?.into(),
+					semantics: semantics.fingerprint(),
+					checksum: metadata.checksum,
+				};
+				if metadata != expected {
+					return Err("metadata of another artifact".into())
+				}
+				let code = fs::read(&code_path)
+					.map_err(|e| format!("cannot read the compiled code: {}", e))?;
+				if blake2_256(&code) != metadata.checksum {
+					return Err("checksum mismatch".into())
+				}
+				Ok(code)
+			});
+
+		match checked {
+			Ok(code) => Some(code),
+			Err(reason) => {
+				tracing::warn!(
+					target: "wasm-runtime",
+					"Removing the corrupted runtime artifact {}: {}",
+					code_path.display(),
+					reason,
+				);
+				let _ = fs::remove_file(&metadata_path);
+				let _ = fs::remove_file(&code_path);
+				None
+			},
+		}
+	}
+
+	/// Compile the runtime `blob` of hash `code_hash` into an artifact, unless a valid one is
+	/// present already. Returns the compiled code, as checked or as compiled.
+	pub fn prepare(
+		&self,
+		blob: &RuntimeBlob,
+		code_hash: &[u8],
+		semantics: &Semantics,
+	) -> Result<Vec<u8>, WasmError> {
+		if let Some(code) = self.load(code_hash, semantics) {
+			return Ok(code)
+		}
+
+		let code = sc_executor_wasmtime::prepare_runtime_artifact(blob.clone(), semantics)?;
+		let metadata = ArtifactMetadata {
+			format_version: ARTIFACT_FORMAT_VERSION,
+			code_hash: code_hash.to_vec(),
+			wasmtime_version: wasmtime_version()?.into(),
+			semantics: semantics.fingerprint(),
+			checksum: blake2_256(&code),
+		};
+
+		fs::create_dir_all(&self.dir).map_err(|e| {
+			WasmError::Other(format!("cannot create the runtime artifacts directory: {}", e))
+		})?;
+		let code_path = self.code_path(code_hash, semantics)?;
+		write_atomically(&code_path, &code)?;
+		let metadata_path = self.path(code_hash, semantics, METADATA_EXTENSION)?;
+		write_atomically(&metadata_path, &metadata.encode())?;
+
+		tracing::debug!(
+			target: "wasm-runtime",
+			"Prepared the runtime artifact {}",
+			code_path.display(),
+		);
+		Ok(code)
+	}
+}
+
+/// Returns the version of wasmtime, which the artifacts are only loadable by.
+fn wasmtime_version() -> Result<&'static str, WasmError> {
+	sc_executor_wasmtime::wasmtime_version().ok_or_else(|| {
+		WasmError::Other("cannot determine the version of wasmtime preparing the artifacts".into())
+	})
+}
+
+/// Write `data` to a temporary file and move it to `path`, so that `path` never holds partial
+/// data.
+fn write_atomically(path: &Path, data: &[u8]) -> Result<(), WasmError> {
+	let mut tmp_path = path.as_os_str().to_owned();
+	tmp_path.push(format!(".{}.tmp", std::process::id()));
+	let tmp_path = PathBuf::from(tmp_path);
+	fs::write(&tmp_path, data)
+		.and_then(|_| fs::rename(&tmp_path, path))
+		.map_err(|e| {
+			let _ = fs::remove_file(&tmp_path);
+			WasmError::Other(format!("cannot write {}: {}", path.display(), e))
+		})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sc_executor_wasmtime::InstantiationStrategy;
+
+	fn semantics(extra_heap_pages: u64) -> Semantics {
+		Semantics {
+			extra_heap_pages,
+			instantiation_strategy: InstantiationStrategy::PoolingCopyOnWrite,
+			deterministic_stack_limit: None,
+			canonicalize_nans: false,
+			parallel_compilation: true,
+			max_memory_size: None,
+			fuel_metering: false,
+		}
+	}
+
+	#[test]
+	fn artifacts_are_prepared_and_checked() {
+		let dir = tempfile::tempdir().unwrap();
+		let artifacts = RuntimeArtifacts::new(dir.path().join("artifacts"));
+		let blob =
+			RuntimeBlob::uncompress_if_needed(sc_runtime_test::wasm_binary_unwrap()).unwrap();
+		let code_hash = [1u8; 32];
+
+		assert_eq!(artifacts.load(&code_hash, &semantics(2048)), None);
+		let code = artifacts.prepare(&blob, &code_hash, &semantics(2048)).unwrap();
+		assert_eq!(artifacts.load(&code_hash, &semantics(2048)), Some(code.clone()));
+		let path = artifacts.code_path(&code_hash, &semantics(2048)).unwrap();
+		assert_eq!(fs::read(&path).unwrap(), code);
+
+		// Other semantics or code need their own artifact.
+		assert_eq!(artifacts.load(&code_hash, &semantics(1024)), None);
+		assert_eq!(artifacts.load(&[2u8; 32], &semantics(2048)), None);
+
+		// A corrupted artifact is removed.
+		let mut corrupted = code.clone();
+		corrupted[0] ^= 1;
+		fs::write(&path, corrupted).unwrap();
+		assert_eq!(artifacts.load(&code_hash, &semantics(2048)), None);
+		assert!(!path.exists());
+
+		let code = artifacts.prepare(&blob, &code_hash, &semantics(2048)).unwrap();
+		assert_eq!(artifacts.load(&code_hash, &semantics(2048)), Some(code));
+	}
+
+	#[test]
+	fn wasmtime_version_is_read_from_the_precompiled_modules() {
+		let version = wasmtime_version().unwrap();
+		assert_eq!(version.split('.').count(), 3, "{}", version);
+		assert!(version.split('.').all(|n| n.parse::<u32>().is_ok()), "{}", version);
+	}
+}
@@ -22,7 +22,9 @@ mod sandbox;
 
 use codec::{Decode, Encode};
 use hex_literal::hex;
-use sc_executor_common::{error::Error, runtime_blob::RuntimeBlob, wasm_runtime::WasmModule};
+use sc_executor_common::{
+	error::Error, runtime_blob::RuntimeBlob, sandbox::SandboxBackend, wasm_runtime::WasmModule,
+};
 use sc_runtime_test::wasm_binary_unwrap;
 use sp_core::{
 	blake2_128, blake2_256, ed25519, map,
@@ -112,12 +114,12 @@ macro_rules! test_wasm_execution_sandbox {
 		paste::item! {
 			#[test]
 			fn [<$method_name _interpreted_host_executor>]() {
-				$method_name(WasmExecutionMethod::Interpreted, "_host");
+				$method_name(WasmExecutionMethod::Interpreted, "_host", None);
 			}
 
 			#[test]
 			fn [<$method_name _interpreted_embedded_executor>]() {
-				$method_name(WasmExecutionMethod::Interpreted, "_embedded");
+				$method_name(WasmExecutionMethod::Interpreted, "_embedded", None);
 			}
 
 			#[test]
@@ -125,7 +127,7 @@ macro_rules! test_wasm_execution_sandbox {
 			fn [<$method_name _compiled_pooling_cow_host_executor>]() {
 				$method_name(WasmExecutionMethod::Compiled {
 					instantiation_strategy: sc_executor_wasmtime::InstantiationStrategy::PoolingCopyOnWrite
-				}, "_host");
+				}, "_host", None);
 			}
 
 			#[test]
@@ -133,7 +135,7 @@ macro_rules! test_wasm_execution_sandbox {
 			fn [<$method_name _compiled_pooling_cow_embedded_executor>]() {
 				$method_name(WasmExecutionMethod::Compiled {
 					instantiation_strategy: sc_executor_wasmtime::InstantiationStrategy::PoolingCopyOnWrite
-				}, "_embedded");
+				}, "_embedded", None);
 			}
 
 			#[test]
@@ -141,7 +143,7 @@ macro_rules! test_wasm_execution_sandbox {
 			fn [<$method_name _compiled_pooling_vanilla_host_executor>]() {
 				$method_name(WasmExecutionMethod::Compiled {
 					instantiation_strategy: sc_executor_wasmtime::InstantiationStrategy::Pooling
-				}, "_host");
+				}, "_host", None);
 			}
 
 			#[test]
@@ -149,7 +151,7 @@ macro_rules! test_wasm_execution_sandbox {
 			fn [<$method_name _compiled_pooling_vanilla_embedded_executor>]() {
 				$method_name(WasmExecutionMethod::Compiled {
 					instantiation_strategy: sc_executor_wasmtime::InstantiationStrategy::Pooling
-				}, "_embedded");
+				}, "_embedded", None);
 			}
 
 			#[test]
@@ -157,7 +159,7 @@ macro_rules! test_wasm_execution_sandbox {
 			fn [<$method_name _compiled_recreate_instance_cow_host_executor>]() {
 				$method_name(WasmExecutionMethod::Compiled {
 					instantiation_strategy: sc_executor_wasmtime::InstantiationStrategy::RecreateInstanceCopyOnWrite
-				}, "_host");
+				}, "_host", None);
 			}
 
 			#[test]
@@ -165,7 +167,7 @@ macro_rules! test_wasm_execution_sandbox {
 			fn [<$method_name _compiled_recreate_instance_cow_embedded_executor>]() {
 				$method_name(WasmExecutionMethod::Compiled {
 					instantiation_strategy: sc_executor_wasmtime::InstantiationStrategy::RecreateInstanceCopyOnWrite
-				}, "_embedded");
+				}, "_embedded", None);
 			}
 
 			#[test]
@@ -173,7 +175,7 @@ macro_rules! test_wasm_execution_sandbox {
 			fn [<$method_name _compiled_recreate_instance_vanilla_host_executor>]() {
 				$method_name(WasmExecutionMethod::Compiled {
 					instantiation_strategy: sc_executor_wasmtime::InstantiationStrategy::RecreateInstance
-				}, "_host");
+				}, "_host", None);
 			}
 
 			#[test]
@@ -181,7 +183,7 @@ macro_rules! test_wasm_execution_sandbox {
 			fn [<$method_name _compiled_recreate_instance_vanilla_embedded_executor>]() {
 				$method_name(WasmExecutionMethod::Compiled {
 					instantiation_strategy: sc_executor_wasmtime::InstantiationStrategy::RecreateInstance
-				}, "_embedded");
+				}, "_embedded", None);
 			}
 
 			#[test]
@@ -189,7 +191,7 @@ macro_rules! test_wasm_execution_sandbox {
 			fn [<$method_name _compiled_legacy_instance_reuse_host_executor>]() {
 				$method_name(WasmExecutionMethod::Compiled {
 					instantiation_strategy: sc_executor_wasmtime::InstantiationStrategy::LegacyInstanceReuse
-				}, "_host");
+				}, "_host", None);
 			}
 
 			#[test]
@@ -197,7 +199,25 @@ macro_rules! test_wasm_execution_sandbox {
 			fn [<$method_name _compiled_legacy_instance_reuse_embedded_executor>]() {
 				$method_name(WasmExecutionMethod::Compiled {
 					instantiation_strategy: sc_executor_wasmtime::InstantiationStrategy::LegacyInstanceReuse
-				}, "_embedded");
+				}, "_embedded", None);
+			}
+
+			#[test]
+			#[cfg(feature = "wasmtime-sandbox")]
+			fn [<$method_name _interpreted_wasmtime_sandbox_host_executor>]() {
+				$method_name(
+					WasmExecutionMethod::Interpreted,
+					"_host",
+					Some(crate::sandbox::SandboxBackend::Wasmtime),
+				);
+			}
+
+			#[test]
+			#[cfg(all(feature = "wasmtime", feature = "wasmtime-sandbox"))]
+			fn [<$method_name _compiled_recreate_instance_wasmtime_sandbox_host_executor>]() {
+				$method_name(WasmExecutionMethod::Compiled {
+					instantiation_strategy: sc_executor_wasmtime::InstantiationStrategy::RecreateInstance
+				}, "_host", Some(crate::sandbox::SandboxBackend::Wasmtime));
 			}
 		}
 	};
@@ -206,14 +226,26 @@ macro_rules! test_wasm_execution_sandbox {
 		paste::item! {
 			#[test]
 			fn [<$method_name _interpreted_host_executor>]() {
-				$method_name(WasmExecutionMethod::Interpreted, "_host");
+				$method_name(WasmExecutionMethod::Interpreted, "_host", None);
 			}
 		}
 
 		paste::item! {
 			#[test]
 			fn [<$method_name _interpreted_embedded_executor>]() {
-				$method_name(WasmExecutionMethod::Interpreted, "_embedded");
+				$method_name(WasmExecutionMethod::Interpreted, "_embedded", None);
+			}
+		}
+
+		paste::item! {
+			#[test]
+			#[cfg(feature = "wasmtime-sandbox")]
+			fn [<$method_name _interpreted_wasmtime_sandbox_host_executor>]() {
+				$method_name(
+					WasmExecutionMethod::Interpreted,
+					"_host",
+					Some(crate::sandbox::SandboxBackend::Wasmtime),
+				);
 			}
 		}
 	};
@@ -224,9 +256,20 @@ fn call_in_wasm<E: Externalities>(
 	call_data: &[u8],
 	execution_method: WasmExecutionMethod,
 	ext: &mut E,
+) -> Result<Vec<u8>, Error> {
+	call_in_wasm_with_sandbox(function, call_data, execution_method, None, ext)
+}
+
+fn call_in_wasm_with_sandbox<E: Externalities>(
+	function: &str,
+	call_data: &[u8],
+	execution_method: WasmExecutionMethod,
+	sandbox_backend: Option<SandboxBackend>,
+	ext: &mut E,
 ) -> Result<Vec<u8>, Error> {
 	let executor =
-		crate::WasmExecutor::<HostFunctions>::new(execution_method, Some(1024), 8, None, 2);
+		crate::WasmExecutor::<HostFunctions>::new(execution_method, Some(1024), 8, None, 2)
+			.with_sandbox_backend(sandbox_backend);
 	executor.uncached_call(
 		RuntimeBlob::uncompress_if_needed(wasm_binary_unwrap()).unwrap(),
 		ext,
@@ -500,6 +543,139 @@ fn ordered_trie_root_should_work(wasm_method: WasmExecutionMethod) {
 	);
 }
 
+test_wasm_execution!(fuel_budget_is_enforced);
+fn fuel_budget_is_enforced(wasm_method: WasmExecutionMethod) {
+	let executor = crate::WasmExecutor::<HostFunctions>::new(wasm_method, Some(1024), 8, None, 2)
+		.with_fuel_metering(true);
+	let call = |ext: &mut TestExternalities| {
+		executor.uncached_call(
+			RuntimeBlob::uncompress_if_needed(wasm_binary_unwrap()).unwrap(),
+			&mut ext.ext(),
+			true,
+			"test_ordered_trie_root",
+			&[0],
+		)
+	};
+
+	let mut ext = TestExternalities::default();
+	call(&mut ext).unwrap();
+
+	ext.register_extension(sp_core::traits::FuelBudgetExt(100));
+	match call(&mut ext).unwrap_err() {
+		Error::OutOfFuel(budget) => assert_eq!(budget, 100),
+		error => panic!("unexpected error: {:?}", error),
+	}
+}
+
+test_wasm_execution!(host_function_calls_are_profiled);
+fn host_function_calls_are_profiled(wasm_method: WasmExecutionMethod) {
+	use parking_lot::Mutex;
+	use sp_core::traits::{
+		CodeExecutor, HostFunctionProfiler, HostFunctionProfilerExt, RuntimeCode,
+		WrappedRuntimeCode,
+	};
+	use std::{collections::HashMap, time::Duration};
+
+	#[derive(Default)]
+	struct Profiler {
+		runtime_calls: Mutex<Vec<bool>>,
+		host_functions: Mutex<HashMap<String, u64>>,
+	}
+
+	impl HostFunctionProfiler for Profiler {
+		fn record_runtime_call(&self, _time: Duration, profiled: bool) {
+			self.runtime_calls.lock().push(profiled);
+		}
+
+		fn record_host_function(
+			&self,
+			name: &str,
+			calls: u64,
+			_time: Duration,
+			_bytes_read: u64,
+			_bytes_written: u64,
+		) {
+			*self.host_functions.lock().entry(name.into()).or_default() += calls;
+		}
+	}
+
+	let code_fetcher = WrappedRuntimeCode(wasm_binary_unwrap().into());
+	let runtime_code =
+		RuntimeCode { code_fetcher: &code_fetcher, hash: vec![1, 2, 3], heap_pages: None };
+	let call = |executor: &crate::WasmExecutor<HostFunctions>, profiler: &Arc<Profiler>| {
+		let mut ext = TestExternalities::default();
+		ext.register_extension(HostFunctionProfilerExt(profiler.clone()));
+		executor
+			.call::<Vec<u8>, fn() -> _>(
+				&mut ext.ext(),
+				&runtime_code,
+				"test_ordered_trie_root",
+				&[0],
+				false,
+				None,
+			)
+			.0
+			.unwrap();
+	};
+
+	let executor = crate::WasmExecutor::<HostFunctions>::new(wasm_method, Some(1024), 8, None, 2)
+		.with_host_function_profiling(true);
+	let profiler = Arc::new(Profiler::default());
+	call(&executor, &profiler);
+	assert_eq!(*profiler.runtime_calls.lock(), vec![true]);
+	assert_eq!(
+		profiler.host_functions.lock().get("ext_trie_blake2_256_ordered_root_version_2"),
+		Some(&1),
+	);
+
+	// Without host function profiling only the runtime calls are recorded.
+	let executor = crate::WasmExecutor::<HostFunctions>::new(wasm_method, Some(1024), 8, None, 2);
+	let profiler = Arc::new(Profiler::default());
+	call(&executor, &profiler);
+	assert_eq!(*profiler.runtime_calls.lock(), vec![false]);
+	assert!(profiler.host_functions.lock().is_empty());
+}
+
+test_wasm_execution!(runtime_cache_tracks_instances_and_heap_usage);
+fn runtime_cache_tracks_instances_and_heap_usage(wasm_method: WasmExecutionMethod) {
+	use crate::PrepareRuntime;
+	use sp_core::traits::{CodeExecutor, RuntimeCode, WrappedRuntimeCode};
+
+	let code_fetcher = WrappedRuntimeCode(wasm_binary_unwrap().into());
+	let runtime_code =
+		RuntimeCode { code_fetcher: &code_fetcher, hash: vec![1, 2, 3], heap_pages: None };
+	let executor = crate::WasmExecutor::<HostFunctions>::new(wasm_method, Some(1024), 8, None, 2)
+		.with_heap_usage_warning(Some(80));
+
+	for _ in 0..2 {
+		let mut ext = TestExternalities::default();
+		executor
+			.call::<Vec<u8>, fn() -> _>(
+				&mut ext.ext(),
+				&runtime_code,
+				"test_ordered_trie_root",
+				&[0],
+				false,
+				None,
+			)
+			.0
+			.unwrap();
+	}
+
+	let stats = executor.runtime_cache_stats();
+	assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 1, 0));
+	assert_eq!(
+		(
+			stats.instance_hits,
+			stats.instance_misses,
+			stats.instance_waits,
+			stats.instance_evictions
+		),
+		(1, 1, 0, 0),
+	);
+	assert!(stats.heap_used_peak > 0);
+}
+
 test_wasm_execution!(offchain_index);
 fn offchain_index(wasm_method: WasmExecutionMethod) {
 	let mut ext = TestExternalities::default();
@@ -599,6 +775,8 @@ fn mk_test_runtime(wasm_method: WasmExecutionMethod, pages: u64) -> Arc<dyn Wasm
 		pages,
 		blob,
 		true,
+		false,
+		false,
 		None,
 	)
 	.expect("failed to instantiate wasm runtime")
@@ -859,6 +1037,8 @@ fn memory_is_cleared_between_invocations(wasm_method: WasmExecutionMethod) {
 		1024,
 		RuntimeBlob::uncompress_if_needed(&binary[..]).unwrap(),
 		true,
+		false,
+		false,
 		None,
 	)
 	.unwrap();
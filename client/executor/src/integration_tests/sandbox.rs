@@ -16,13 +16,17 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
-use super::{call_in_wasm, TestExternalities};
-use crate::{test_wasm_execution_sandbox, WasmExecutionMethod};
+use super::{call_in_wasm_with_sandbox, TestExternalities};
+use crate::{sandbox::SandboxBackend, test_wasm_execution_sandbox, WasmExecutionMethod};
 
 use codec::Encode;
 
 test_wasm_execution_sandbox!(sandbox_should_work);
-fn sandbox_should_work(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
+fn sandbox_should_work(
+	wasm_method: WasmExecutionMethod,
+	fn_suffix: &str,
+	sandbox_backend: Option<SandboxBackend>,
+) {
 	let mut ext = TestExternalities::default();
 	let mut ext = ext.ext();
 
@@ -52,13 +56,24 @@ fn sandbox_should_work(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
 	.encode();
 
 	assert_eq!(
-		call_in_wasm(&format!("test_sandbox{}", fn_suffix), &code, wasm_method, &mut ext).unwrap(),
+		call_in_wasm_with_sandbox(
+			&format!("test_sandbox{}", fn_suffix),
+			&code,
+			wasm_method,
+			sandbox_backend,
+			&mut ext
+		)
+		.unwrap(),
 		true.encode()
 	);
 }
 
 test_wasm_execution_sandbox!(sandbox_trap);
-fn sandbox_trap(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
+fn sandbox_trap(
+	wasm_method: WasmExecutionMethod,
+	fn_suffix: &str,
+	sandbox_backend: Option<SandboxBackend>,
+) {
 	let mut ext = TestExternalities::default();
 	let mut ext = ext.ext();
 
@@ -76,13 +91,24 @@ fn sandbox_trap(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
 	.unwrap();
 
 	assert_eq!(
-		call_in_wasm(&format!("test_sandbox{}", fn_suffix), &code, wasm_method, &mut ext).unwrap(),
+		call_in_wasm_with_sandbox(
+			&format!("test_sandbox{}", fn_suffix),
+			&code,
+			wasm_method,
+			sandbox_backend,
+			&mut ext
+		)
+		.unwrap(),
 		vec![0]
 	);
 }
 
 test_wasm_execution_sandbox!(start_called);
-fn start_called(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
+fn start_called(
+	wasm_method: WasmExecutionMethod,
+	fn_suffix: &str,
+	sandbox_backend: Option<SandboxBackend>,
+) {
 	let mut ext = TestExternalities::default();
 	let mut ext = ext.ext();
 
@@ -118,13 +144,24 @@ fn start_called(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
 	.encode();
 
 	assert_eq!(
-		call_in_wasm(&format!("test_sandbox{}", fn_suffix), &code, wasm_method, &mut ext).unwrap(),
+		call_in_wasm_with_sandbox(
+			&format!("test_sandbox{}", fn_suffix),
+			&code,
+			wasm_method,
+			sandbox_backend,
+			&mut ext
+		)
+		.unwrap(),
 		true.encode()
 	);
 }
 
 test_wasm_execution_sandbox!(invoke_args);
-fn invoke_args(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
+fn invoke_args(
+	wasm_method: WasmExecutionMethod,
+	fn_suffix: &str,
+	sandbox_backend: Option<SandboxBackend>,
+) {
 	let mut ext = TestExternalities::default();
 	let mut ext = ext.ext();
 
@@ -156,14 +193,24 @@ fn invoke_args(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
 	.encode();
 
 	assert_eq!(
-		call_in_wasm(&format!("test_sandbox_args{}", fn_suffix), &code, wasm_method, &mut ext,)
-			.unwrap(),
+		call_in_wasm_with_sandbox(
+			&format!("test_sandbox_args{}", fn_suffix),
+			&code,
+			wasm_method,
+			sandbox_backend,
+			&mut ext,
+		)
+		.unwrap(),
 		true.encode(),
 	);
 }
 
 test_wasm_execution_sandbox!(return_val);
-fn return_val(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
+fn return_val(
+	wasm_method: WasmExecutionMethod,
+	fn_suffix: &str,
+	sandbox_backend: Option<SandboxBackend>,
+) {
 	let mut ext = TestExternalities::default();
 	let mut ext = ext.ext();
 
@@ -183,10 +230,11 @@ fn return_val(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
 	.encode();
 
 	assert_eq!(
-		call_in_wasm(
+		call_in_wasm_with_sandbox(
 			&format!("test_sandbox_return_val{}", fn_suffix),
 			&code,
 			wasm_method,
+			sandbox_backend,
 			&mut ext,
 		)
 		.unwrap(),
@@ -195,7 +243,11 @@ fn return_val(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
 }
 
 test_wasm_execution_sandbox!(unlinkable_module);
-fn unlinkable_module(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
+fn unlinkable_module(
+	wasm_method: WasmExecutionMethod,
+	fn_suffix: &str,
+	sandbox_backend: Option<SandboxBackend>,
+) {
 	let mut ext = TestExternalities::default();
 	let mut ext = ext.ext();
 
@@ -213,10 +265,11 @@ fn unlinkable_module(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
 	.encode();
 
 	assert_eq!(
-		call_in_wasm(
+		call_in_wasm_with_sandbox(
 			&format!("test_sandbox_instantiate{}", fn_suffix),
 			&code,
 			wasm_method,
+			sandbox_backend,
 			&mut ext,
 		)
 		.unwrap(),
@@ -225,7 +278,11 @@ fn unlinkable_module(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
 }
 
 test_wasm_execution_sandbox!(corrupted_module);
-fn corrupted_module(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
+fn corrupted_module(
+	wasm_method: WasmExecutionMethod,
+	fn_suffix: &str,
+	sandbox_backend: Option<SandboxBackend>,
+) {
 	let mut ext = TestExternalities::default();
 	let mut ext = ext.ext();
 
@@ -233,10 +290,11 @@ fn corrupted_module(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
 	let code = vec![0u8, 0, 0, 0, 1, 0, 0, 0].encode();
 
 	assert_eq!(
-		call_in_wasm(
+		call_in_wasm_with_sandbox(
 			&format!("test_sandbox_instantiate{}", fn_suffix),
 			&code,
 			wasm_method,
+			sandbox_backend,
 			&mut ext,
 		)
 		.unwrap(),
@@ -245,7 +303,11 @@ fn corrupted_module(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
 }
 
 test_wasm_execution_sandbox!(start_fn_ok);
-fn start_fn_ok(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
+fn start_fn_ok(
+	wasm_method: WasmExecutionMethod,
+	fn_suffix: &str,
+	sandbox_backend: Option<SandboxBackend>,
+) {
 	let mut ext = TestExternalities::default();
 	let mut ext = ext.ext();
 
@@ -266,10 +328,11 @@ fn start_fn_ok(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
 	.encode();
 
 	assert_eq!(
-		call_in_wasm(
+		call_in_wasm_with_sandbox(
 			&format!("test_sandbox_instantiate{}", fn_suffix),
 			&code,
 			wasm_method,
+			sandbox_backend,
 			&mut ext,
 		)
 		.unwrap(),
@@ -278,7 +341,11 @@ fn start_fn_ok(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
 }
 
 test_wasm_execution_sandbox!(start_fn_traps);
-fn start_fn_traps(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
+fn start_fn_traps(
+	wasm_method: WasmExecutionMethod,
+	fn_suffix: &str,
+	sandbox_backend: Option<SandboxBackend>,
+) {
 	let mut ext = TestExternalities::default();
 	let mut ext = ext.ext();
 
@@ -300,10 +367,11 @@ fn start_fn_traps(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
 	.encode();
 
 	assert_eq!(
-		call_in_wasm(
+		call_in_wasm_with_sandbox(
 			&format!("test_sandbox_instantiate{}", fn_suffix),
 			&code,
 			wasm_method,
+			sandbox_backend,
 			&mut ext,
 		)
 		.unwrap(),
@@ -312,7 +380,11 @@ fn start_fn_traps(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
 }
 
 test_wasm_execution_sandbox!(get_global_val_works);
-fn get_global_val_works(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
+fn get_global_val_works(
+	wasm_method: WasmExecutionMethod,
+	fn_suffix: &str,
+	sandbox_backend: Option<SandboxBackend>,
+) {
 	let mut ext = TestExternalities::default();
 	let mut ext = ext.ext();
 
@@ -327,13 +399,73 @@ fn get_global_val_works(wasm_method: WasmExecutionMethod, fn_suffix: &str) {
 	.encode();
 
 	assert_eq!(
-		call_in_wasm(
+		call_in_wasm_with_sandbox(
 			&format!("test_sandbox_get_global_val{}", fn_suffix),
 			&code,
 			wasm_method,
+			sandbox_backend,
 			&mut ext,
 		)
 		.unwrap(),
 		500i64.encode(),
 	);
 }
+
+test_wasm_execution_sandbox!(deep_recursion);
+fn deep_recursion(
+	wasm_method: WasmExecutionMethod,
+	fn_suffix: &str,
+	sandbox_backend: Option<SandboxBackend>,
+) {
+	let mut ext = TestExternalities::default();
+	let mut ext = ext.ext();
+
+	let recursion = |depth: &str| {
+		wat::parse_str(format!(
+			r#"
+			(module
+				(func $recurse (param $depth i64)
+					(if (i64.ne (local.get $depth) (i64.const 0))
+						(then
+							(call $recurse (i64.sub (local.get $depth) (i64.const 1)))
+						)
+					)
+				)
+
+				(func (export "call")
+					(call $recurse (i64.const {}))
+				)
+			)
+			"#,
+			depth,
+		))
+		.unwrap()
+		.encode()
+	};
+
+	// Within the stack limit of the guests.
+	assert_eq!(
+		call_in_wasm_with_sandbox(
+			&format!("test_sandbox{}", fn_suffix),
+			&recursion("5000"),
+			wasm_method,
+			sandbox_backend,
+			&mut ext,
+		)
+		.unwrap(),
+		true.encode(),
+	);
+
+	// Traps once out of stack, instead of overflowing the native stack of the node.
+	assert_eq!(
+		call_in_wasm_with_sandbox(
+			&format!("test_sandbox{}", fn_suffix),
+			&recursion("-1"),
+			wasm_method,
+			sandbox_backend,
+			&mut ext,
+		)
+		.unwrap(),
+		false.encode(),
+	);
+}
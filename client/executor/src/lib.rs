@@ -31,10 +31,13 @@
 #![warn(missing_docs)]
 #![recursion_limit = "128"]
 
+#[cfg(feature = "wasmtime")]
+mod artifacts;
 #[macro_use]
 mod native_executor;
 #[cfg(test)]
 mod integration_tests;
+mod runtime_tasks;
 mod wasm_runtime;
 
 pub use codec::Codec;
@@ -46,11 +49,16 @@ pub use sp_core::traits::Externalities;
 pub use sp_version::{NativeVersion, RuntimeVersion};
 #[doc(hidden)]
 pub use sp_wasm_interface;
-pub use wasm_runtime::{read_embedded_version, WasmExecutionMethod};
+pub use wasm_runtime::{
+	precompile_runtime, read_embedded_version, RuntimeCacheStats, WasmExecutionMethod,
+};
 pub use wasmi;
 
+pub use sc_allocator::AllocationStats;
 pub use sc_executor_common::{error, sandbox};
 
+#[cfg(feature = "wasmtime")]
+pub use artifacts::RuntimeArtifacts;
 #[cfg(feature = "wasmtime")]
 pub use sc_executor_wasmtime::InstantiationStrategy as WasmtimeInstantiationStrategy;
 
@@ -64,6 +72,21 @@ pub trait RuntimeVersionOf {
 	) -> error::Result<RuntimeVersion>;
 }
 
+/// Prepares runtimes ahead of their first use.
+pub trait PrepareRuntime {
+	/// Compile the given `runtime_code` into the runtime cache, unless it is cached already.
+	///
+	/// Returns how long the compilation took, or `None` if the runtime was cached.
+	fn prepare_runtime(
+		&self,
+		ext: &mut dyn Externalities,
+		runtime_code: &sp_core::traits::RuntimeCode,
+	) -> error::Result<Option<std::time::Duration>>;
+
+	/// Returns the statistics of the runtime cache.
+	fn runtime_cache_stats(&self) -> RuntimeCacheStats;
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -94,4 +117,35 @@ mod tests {
 			.unwrap();
 		assert_eq!(res, vec![0u8; 0]);
 	}
+
+	#[test]
+	fn prepared_runtime_is_cached() {
+		let mut ext = TestExternalities::default();
+		let mut ext = ext.ext();
+
+		let executor = WasmExecutor::<sp_io::SubstrateHostFunctions>::new(
+			WasmExecutionMethod::Interpreted,
+			Some(8),
+			8,
+			None,
+			2,
+		);
+		let code_fetcher = sp_core::traits::WrappedRuntimeCode(
+			substrate_test_runtime::wasm_binary_unwrap().into(),
+		);
+		let runtime_code = sp_core::traits::RuntimeCode {
+			code_fetcher: &code_fetcher,
+			hash: vec![1, 2, 3],
+			heap_pages: None,
+		};
+
+		assert!(executor.prepare_runtime(&mut ext, &runtime_code).unwrap().is_some());
+		assert_eq!(executor.prepare_runtime(&mut ext, &runtime_code).unwrap(), None);
+
+		executor.runtime_version(&mut ext, &runtime_code).unwrap();
+		assert_eq!(
+			executor.runtime_cache_stats(),
+			RuntimeCacheStats { hits: 1, instance_misses: 1, ..Default::default() },
+		);
+	}
 }
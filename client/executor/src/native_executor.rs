@@ -18,8 +18,9 @@
 
 use crate::{
 	error::{Error, Result},
-	wasm_runtime::{RuntimeCache, WasmExecutionMethod},
-	RuntimeVersionOf,
+	runtime_tasks::{ParallelRuntimeSpawn, TaskPool},
+	wasm_runtime::{RuntimeCache, RuntimeCacheStats, WasmExecutionMethod},
+	PrepareRuntime, RuntimeVersionOf,
 };
 
 use std::{
@@ -32,15 +33,23 @@ use std::{
 		atomic::{AtomicU64, Ordering},
 		mpsc, Arc,
 	},
+	time::{Duration, Instant},
 };
 
 use codec::{Decode, Encode};
+use parking_lot::Mutex;
+use prometheus_endpoint::Registry;
+use sc_allocator::AllocationStats;
 use sc_executor_common::{
 	runtime_blob::RuntimeBlob,
+	sandbox::SandboxBackend,
 	wasm_runtime::{InvokeMethod, WasmInstance, WasmModule},
 };
 use sp_core::{
-	traits::{CodeExecutor, Externalities, RuntimeCode, RuntimeSpawn, RuntimeSpawnExt},
+	traits::{
+		CodeExecutor, Externalities, FuelBudgetExt, HostFunctionProfilerExt, RuntimeCode,
+		RuntimeSpawn, RuntimeSpawnExt,
+	},
 	NativeOrEncoded,
 };
 use sp_externalities::ExternalitiesExt as _;
@@ -49,7 +58,7 @@ use sp_version::{GetNativeVersion, NativeVersion, RuntimeVersion};
 use sp_wasm_interface::{ExtendedHostFunctions, HostFunctions};
 
 /// Default num of pages for the heap
-const DEFAULT_HEAP_PAGES: u64 = 2048;
+pub(crate) const DEFAULT_HEAP_PAGES: u64 = 2048;
 
 /// Set up the externalities and safe calling environment to execute runtime calls.
 ///
@@ -101,6 +110,20 @@ pub struct WasmExecutor<H> {
 	/// The path to a directory which the executor can leverage for a file cache, e.g. put there
 	/// compiled artifacts.
 	cache_path: Option<PathBuf>,
+	/// The directory of the precompiled runtime artifacts.
+	runtime_artifacts: Option<PathBuf>,
+	/// Whether to meter the fuel consumed by the runtime.
+	fuel_metering: bool,
+	/// Whether the runtime calls can record their host function calls.
+	host_function_profiling: bool,
+	/// Worker threads of the tasks spawned by the runtimes implementing `ParallelTasks`.
+	task_pool: Arc<TaskPool>,
+	/// The share of the heap, in percent, above which the blocks using it log a warning.
+	heap_usage_warning: Option<u8>,
+	/// The highest heap usage of the calls building the current block.
+	built_block_heap_usage: Arc<Mutex<Option<AllocationStats>>>,
+	/// The backend of the sandboxes created by the runtime, if not the default of the engine.
+	sandbox_backend: Option<SandboxBackend>,
 
 	phantom: PhantomData<H>,
 }
@@ -112,6 +135,13 @@ impl<H> Clone for WasmExecutor<H> {
 			default_heap_pages: self.default_heap_pages,
 			cache: self.cache.clone(),
 			cache_path: self.cache_path.clone(),
+			runtime_artifacts: self.runtime_artifacts.clone(),
+			fuel_metering: self.fuel_metering,
+			host_function_profiling: self.host_function_profiling,
+			task_pool: self.task_pool.clone(),
+			heap_usage_warning: self.heap_usage_warning,
+			built_block_heap_usage: self.built_block_heap_usage.clone(),
+			sandbox_backend: self.sandbox_backend,
 			phantom: self.phantom,
 		}
 	}
@@ -154,10 +184,80 @@ where
 				runtime_cache_size,
 			)),
 			cache_path,
+			runtime_artifacts: None,
+			fuel_metering: false,
+			host_function_profiling: false,
+			task_pool: Arc::new(TaskPool::new()),
+			heap_usage_warning: None,
+			built_block_heap_usage: Default::default(),
+			sandbox_backend: None,
 			phantom: PhantomData,
 		}
 	}
 
+	/// Load the runtimes from the precompiled artifacts in the directory `runtime_artifacts`, and
+	/// prepare there the artifacts of the runtimes that have none yet.
+	///
+	/// Only the compiled execution method uses the artifacts. `None` disables them.
+	pub fn with_runtime_artifacts(mut self, runtime_artifacts: Option<PathBuf>) -> Self {
+		self.runtime_artifacts = runtime_artifacts;
+		self
+	}
+
+	/// Meter the fuel consumed by the runtime, so that the calls given a [`FuelBudgetExt`] fail
+	/// with [`Error::OutOfFuel`] once they consume it.
+	///
+	/// Metering slows the execution down, without it the calls are not limited.
+	pub fn with_fuel_metering(mut self, fuel_metering: bool) -> Self {
+		self.fuel_metering = fuel_metering;
+		self
+	}
+
+	/// Allow the runtime calls given a [`HostFunctionProfilerExt`] to record their host function
+	/// calls into it.
+	///
+	/// With the compiled execution method this slows the host function calls down, even when they
+	/// are not recorded.
+	pub fn with_host_function_profiling(mut self, host_function_profiling: bool) -> Self {
+		self.host_function_profiling = host_function_profiling;
+		self
+	}
+
+	/// Log a warning for the blocks using more than `heap_usage_warning` percent of the heap.
+	///
+	/// An imported block is checked once executed, a built block once finalized with the highest
+	/// usage of the calls building it. A call fails once it used the whole heap, even if it freed
+	/// most of it since. `None` disables the warning.
+	pub fn with_heap_usage_warning(mut self, heap_usage_warning: Option<u8>) -> Self {
+		self.heap_usage_warning = heap_usage_warning;
+		self
+	}
+
+	/// Create the sandboxes of the runtime, e.g. the contracts executed by it, with the
+	/// `sandbox_backend`.
+	///
+	/// `None` leaves it to the execution method, the interpreted one uses wasmi and the compiled
+	/// one [`SandboxBackend::TryWasmer`].
+	pub fn with_sandbox_backend(mut self, sandbox_backend: Option<SandboxBackend>) -> Self {
+		self.sandbox_backend = sandbox_backend;
+		self
+	}
+
+	/// Report the metrics of the runtime cache and of the instance pools of its runtimes to
+	/// `registry`, if any.
+	pub fn with_prometheus_registry(self, registry: Option<&Registry>) -> Self {
+		if let Some(registry) = registry {
+			if let Err(error) = self.cache.register_metrics(registry) {
+				tracing::warn!(
+					target: "executor",
+					%error,
+					"Failed to register the runtime cache metrics",
+				);
+			}
+		}
+		self
+	}
+
 	/// Execute the given closure `f` with the latest runtime (based on `runtime_code`).
 	///
 	/// The closure `f` is expected to return `Err(_)` when there happened a `panic!` in native code
@@ -175,6 +275,7 @@ where
 		&self,
 		runtime_code: &RuntimeCode,
 		ext: &mut dyn Externalities,
+		method: &str,
 		allow_missing_host_functions: bool,
 		f: F,
 	) -> Result<R>
@@ -192,11 +293,48 @@ where
 			self.method,
 			self.default_heap_pages,
 			allow_missing_host_functions,
+			self.fuel_metering,
+			self.host_function_profiling,
+			self.runtime_artifacts.as_deref(),
 			|module, instance, version, ext| {
-				let module = AssertUnwindSafe(module);
-				let instance = AssertUnwindSafe(instance);
-				let ext = AssertUnwindSafe(ext);
-				f(module, instance, version, ext)
+				instance.set_fuel(ext.extension::<FuelBudgetExt>().map(|budget| budget.0))?;
+				if let Some(sandbox_backend) = self.sandbox_backend {
+					instance.set_sandbox_backend(sandbox_backend);
+				}
+				let profiler = ext.extension::<HostFunctionProfilerExt>().map(|ext| ext.0.clone());
+				instance.set_host_function_profiling(
+					self.host_function_profiling && profiler.is_some(),
+				);
+
+				let start = Instant::now();
+				let result = f(
+					AssertUnwindSafe(module),
+					AssertUnwindSafe(&mut *instance),
+					version,
+					AssertUnwindSafe(ext),
+				);
+
+				if let Some(stats) = instance.take_allocation_stats() {
+					self.cache.record_allocation_stats(&stats);
+					self.check_heap_usage(method, &stats);
+				}
+
+				if let Some(profiler) = profiler {
+					let profile = instance.take_host_function_profile();
+					profiler.record_runtime_call(start.elapsed(), profile.is_some());
+					for (name, stats) in profile.iter().flat_map(|profile| &profile.host_functions)
+					{
+						profiler.record_host_function(
+							name,
+							stats.calls,
+							stats.time,
+							stats.bytes_read,
+							stats.bytes_written,
+						);
+					}
+				}
+
+				result
 			},
 		)? {
 			Ok(r) => r,
@@ -204,6 +342,53 @@ where
 		}
 	}
 
+	/// Log the heap usage of a call, warning if the block it executes or finalizes used more than
+	/// the `heap_usage_warning`.
+	fn check_heap_usage(&self, method: &str, stats: &AllocationStats) {
+		tracing::debug!(
+			target: "wasm-heap",
+			?stats,
+			method,
+			"Runtime call used {:.0}% of the heap",
+			stats.heap_usage() * 100.0,
+		);
+
+		let block_stats = match method {
+			"Core_execute_block" => *stats,
+			"Core_initialize_block" => {
+				*self.built_block_heap_usage.lock() = Some(*stats);
+				return
+			},
+			"BlockBuilder_inherent_extrinsics" | "BlockBuilder_apply_extrinsic" => {
+				let mut built_block = self.built_block_heap_usage.lock();
+				if let Some(block_stats) = built_block.as_mut() {
+					if stats.heap_usage() > block_stats.heap_usage() {
+						*block_stats = *stats;
+					}
+				}
+				return
+			},
+			"BlockBuilder_finalize_block" => match self.built_block_heap_usage.lock().take() {
+				Some(block_stats) if block_stats.heap_usage() > stats.heap_usage() => block_stats,
+				_ => *stats,
+			},
+			_ => return,
+		};
+
+		let usage = block_stats.heap_usage() * 100.0;
+		if self.heap_usage_warning.map_or(false, |warning| usage > f64::from(warning)) {
+			tracing::warn!(
+				target: "wasm-heap",
+				"Block used {:.0}% of the heap: {} of {} bytes, of which {:.0}% fragmented. \
+				 Consider raising the heap pages of the runtime.",
+				usage,
+				block_stats.address_space_used,
+				block_stats.heap_size,
+				block_stats.fragmentation() * 100.0,
+			);
+		}
+	}
+
 	/// Perform a call into the given runtime.
 	///
 	/// The runtime is passed as a [`RuntimeBlob`]. The runtime will be instantiated with the
@@ -225,19 +410,25 @@ where
 			self.default_heap_pages,
 			runtime_blob,
 			allow_missing_host_functions,
+			self.fuel_metering,
+			false,
 			self.cache_path.as_deref(),
 		)
 		.map_err(|e| format!("Failed to create module: {}", e))?;
 
-		let instance =
+		let mut instance =
 			module.new_instance().map_err(|e| format!("Failed to create instance: {}", e))?;
+		instance.set_fuel(ext.extension::<FuelBudgetExt>().map(|budget| budget.0))?;
+		if let Some(sandbox_backend) = self.sandbox_backend {
+			instance.set_sandbox_backend(sandbox_backend);
+		}
 
 		let mut instance = AssertUnwindSafe(instance);
 		let mut ext = AssertUnwindSafe(ext);
 		let module = AssertUnwindSafe(module);
 
 		with_externalities_safe(&mut **ext, move || {
-			preregister_builtin_ext(module.clone());
+			preregister_builtin_ext(module.clone(), None);
 			instance.call_export(export_name, call_data)
 		})
 		.and_then(|r| r)
@@ -309,10 +500,12 @@ where
 		let result = self.with_instance(
 			runtime_code,
 			ext,
+			method,
 			false,
-			|module, mut instance, _onchain_version, mut ext| {
+			|module, mut instance, onchain_version, mut ext| {
+				let task_pool = parallel_task_pool(&self.task_pool, onchain_version);
 				with_externalities_safe(&mut **ext, move || {
-					preregister_builtin_ext(module.clone());
+					preregister_builtin_ext(module.clone(), task_pool);
 					instance.call_export(method, data).map(NativeOrEncoded::Encoded)
 				})
 			},
@@ -330,9 +523,41 @@ where
 		ext: &mut dyn Externalities,
 		runtime_code: &RuntimeCode,
 	) -> Result<RuntimeVersion> {
-		self.with_instance(runtime_code, ext, false, |_module, _instance, version, _ext| {
-			Ok(version.cloned().ok_or_else(|| Error::ApiError("Unknown version".into())))
-		})
+		self.with_instance(
+			runtime_code,
+			ext,
+			"Core_version",
+			false,
+			|_module, _instance, version, _ext| {
+				Ok(version.cloned().ok_or_else(|| Error::ApiError("Unknown version".into())))
+			},
+		)
+	}
+}
+
+impl<H> PrepareRuntime for WasmExecutor<H>
+where
+	H: HostFunctions,
+{
+	fn prepare_runtime(
+		&self,
+		ext: &mut dyn Externalities,
+		runtime_code: &RuntimeCode,
+	) -> Result<Option<Duration>> {
+		self.cache.prepare::<H>(
+			runtime_code,
+			ext,
+			self.method,
+			self.default_heap_pages,
+			false,
+			self.fuel_metering,
+			self.host_function_profiling,
+			self.runtime_artifacts.as_deref(),
+		)
+	}
+
+	fn runtime_cache_stats(&self) -> RuntimeCacheStats {
+		self.cache.stats()
 	}
 }
 
@@ -380,6 +605,55 @@ impl<D: NativeExecutionDispatch> NativeElseWasmExecutor<D> {
 			wasm: wasm_executor,
 		}
 	}
+
+	/// Load the runtimes from the precompiled artifacts in the directory `runtime_artifacts`.
+	///
+	/// See [`WasmExecutor::with_runtime_artifacts`].
+	pub fn with_runtime_artifacts(mut self, runtime_artifacts: Option<PathBuf>) -> Self {
+		self.wasm = self.wasm.with_runtime_artifacts(runtime_artifacts);
+		self
+	}
+	/// Meter the fuel consumed by the runtime when it is executed in wasm. The native runtime is
+	/// never limited.
+	///
+	/// See [`WasmExecutor::with_fuel_metering`].
+	pub fn with_fuel_metering(mut self, fuel_metering: bool) -> Self {
+		self.wasm = self.wasm.with_fuel_metering(fuel_metering);
+		self
+	}
+
+	/// Allow the runtime calls executed in wasm to record their host function calls.
+	///
+	/// See [`WasmExecutor::with_host_function_profiling`].
+	pub fn with_host_function_profiling(mut self, host_function_profiling: bool) -> Self {
+		self.wasm = self.wasm.with_host_function_profiling(host_function_profiling);
+		self
+	}
+
+	/// Log a warning for the calls executed in wasm using more than `heap_usage_warning` percent
+	/// of the heap.
+	///
+	/// See [`WasmExecutor::with_heap_usage_warning`].
+	pub fn with_heap_usage_warning(mut self, heap_usage_warning: Option<u8>) -> Self {
+		self.wasm = self.wasm.with_heap_usage_warning(heap_usage_warning);
+		self
+	}
+
+	/// Create the sandboxes of the runtime executed in wasm with the `sandbox_backend`.
+	///
+	/// See [`WasmExecutor::with_sandbox_backend`].
+	pub fn with_sandbox_backend(mut self, sandbox_backend: Option<SandboxBackend>) -> Self {
+		self.wasm = self.wasm.with_sandbox_backend(sandbox_backend);
+		self
+	}
+
+	/// Report the metrics of the runtime cache to `registry`, if any.
+	///
+	/// See [`WasmExecutor::with_prometheus_registry`].
+	pub fn with_prometheus_registry(mut self, registry: Option<&Registry>) -> Self {
+		self.wasm = self.wasm.with_prometheus_registry(registry);
+		self
+	}
 }
 
 impl<D: NativeExecutionDispatch> RuntimeVersionOf for NativeElseWasmExecutor<D> {
@@ -388,10 +662,29 @@ impl<D: NativeExecutionDispatch> RuntimeVersionOf for NativeElseWasmExecutor<D>
 		ext: &mut dyn Externalities,
 		runtime_code: &RuntimeCode,
 	) -> Result<RuntimeVersion> {
-		self.wasm
-			.with_instance(runtime_code, ext, false, |_module, _instance, version, _ext| {
+		self.wasm.with_instance(
+			runtime_code,
+			ext,
+			"Core_version",
+			false,
+			|_module, _instance, version, _ext| {
 				Ok(version.cloned().ok_or_else(|| Error::ApiError("Unknown version".into())))
-			})
+			},
+		)
+	}
+}
+
+impl<D: NativeExecutionDispatch> PrepareRuntime for NativeElseWasmExecutor<D> {
+	fn prepare_runtime(
+		&self,
+		ext: &mut dyn Externalities,
+		runtime_code: &RuntimeCode,
+	) -> Result<Option<Duration>> {
+		self.wasm.prepare_runtime(ext, runtime_code)
+	}
+
+	fn runtime_cache_stats(&self) -> RuntimeCacheStats {
+		self.wasm.runtime_cache_stats()
 	}
 }
 
@@ -404,7 +697,7 @@ impl<D: NativeExecutionDispatch> GetNativeVersion for NativeElseWasmExecutor<D>
 /// Helper inner struct to implement `RuntimeSpawn` extension.
 pub struct RuntimeInstanceSpawn {
 	module: Arc<dyn WasmModule>,
-	tasks: parking_lot::Mutex<HashMap<u64, mpsc::Receiver<Vec<u8>>>>,
+	tasks: Mutex<HashMap<u64, mpsc::Receiver<Vec<u8>>>>,
 	counter: AtomicU64,
 	scheduler: Box<dyn sp_core::traits::SpawnNamed>,
 }
@@ -491,7 +784,7 @@ impl RuntimeSpawn for RuntimeInstanceSpawn {
 		new_handle
 	}
 
-	fn join(&self, handle: u64) -> Vec<u8> {
+	fn join(&self, handle: u64, _ext: &dyn Externalities) -> Vec<u8> {
 		let receiver = self.tasks.lock().remove(&handle).expect("No task for the handle");
 		receiver.recv().expect("Spawned task panicked for the handle")
 	}
@@ -514,15 +807,36 @@ impl RuntimeInstanceSpawn {
 	}
 }
 
+/// Returns the task pool if the runtime with the given version runs its tasks in parallel.
+fn parallel_task_pool(
+	task_pool: &Arc<TaskPool>,
+	onchain_version: Option<&RuntimeVersion>,
+) -> Option<Arc<TaskPool>> {
+	onchain_version
+		.filter(|version| sp_tasks::runs_tasks_in_parallel(version))
+		.map(|_| task_pool.clone())
+}
+
 /// Pre-registers the built-in extensions to the currently effective externalities.
 ///
+/// The tasks spawned by the runtime are executed on `task_pool` if one is given.
+///
 /// Meant to be called each time before calling into the runtime.
-fn preregister_builtin_ext(module: Arc<dyn WasmModule>) {
+fn preregister_builtin_ext(module: Arc<dyn WasmModule>, task_pool: Option<Arc<TaskPool>>) {
 	sp_externalities::with_externalities(move |mut ext| {
-		if let Some(runtime_spawn) =
-			RuntimeInstanceSpawn::with_externalities_and_module(module, ext)
-		{
-			if let Err(e) = ext.register_extension(RuntimeSpawnExt(Box::new(runtime_spawn))) {
+		let runtime_spawn: Option<Arc<dyn RuntimeSpawn>> = match task_pool {
+			Some(task_pool) => ext
+				.extension::<sp_core::traits::TaskExecutorExt>()
+				.map(|task_ext| task_ext.clone())
+				.map(|scheduler| {
+					Arc::new(ParallelRuntimeSpawn::new(module, task_pool, scheduler)) as Arc<_>
+				}),
+			None => RuntimeInstanceSpawn::with_externalities_and_module(module, ext)
+				.map(|runtime_spawn| Arc::new(runtime_spawn) as Arc<_>),
+		};
+
+		if let Some(runtime_spawn) = runtime_spawn {
+			if let Err(e) = ext.register_extension(RuntimeSpawnExt(runtime_spawn)) {
 				tracing::trace!(
 					target: "executor",
 					error = ?e,
@@ -558,6 +872,7 @@ impl<D: NativeExecutionDispatch + 'static> CodeExecutor for NativeElseWasmExecut
 		let result = self.wasm.with_instance(
 			runtime_code,
 			ext,
+			method,
 			false,
 			|module, mut instance, onchain_version, mut ext| {
 				let onchain_version =
@@ -577,8 +892,10 @@ impl<D: NativeExecutionDispatch + 'static> CodeExecutor for NativeElseWasmExecut
 							);
 						}
 
+						let task_pool =
+							parallel_task_pool(&self.wasm.task_pool, Some(onchain_version));
 						with_externalities_safe(&mut **ext, move || {
-							preregister_builtin_ext(module.clone());
+							preregister_builtin_ext(module.clone(), task_pool);
 							instance.call_export(method, data).map(NativeOrEncoded::Encoded)
 						})
 					},
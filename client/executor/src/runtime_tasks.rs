@@ -0,0 +1,334 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Parallel execution of the tasks spawned by the runtimes implementing
+//! [`sp_tasks::ParallelTasks`].
+
+use crate::native_executor::with_externalities_safe;
+
+use parking_lot::Mutex;
+use sc_executor_common::wasm_runtime::{InvokeMethod, WasmModule};
+use sp_core::traits::{Externalities, RuntimeSpawn, SpawnNamed};
+use sp_tasks::{new_async_externalities, wait_for_task, TaskMessage, MAX_TASKS_PER_CALL};
+use std::{
+	collections::HashMap,
+	panic::AssertUnwindSafe,
+	sync::{
+		atomic::{AtomicU32, AtomicU64, Ordering},
+		mpsc, Arc,
+	},
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A task which runs either on a worker thread or, if it is joined first, on a thread of its own.
+struct Task {
+	job: Mutex<Option<Job>>,
+}
+
+impl Task {
+	fn new(job: Job) -> Self {
+		Self { job: Mutex::new(Some(job)) }
+	}
+
+	fn run(&self) {
+		let job = self.job.lock().take();
+		if let Some(job) = job {
+			job()
+		}
+	}
+
+	/// Starts the task on a thread of its own if no worker started it yet.
+	///
+	/// The joining thread can't run the task itself, it answers the storage reads of the task.
+	fn start_joined(&self) {
+		let job = self.job.lock().take();
+		if let Some(job) = job {
+			let spawned = std::thread::Builder::new().name("runtime-task-joined".into()).spawn(job);
+			if let Err(error) = spawned {
+				// The job is dropped without sending a result, the join panics.
+				tracing::warn!(target: "executor", %error, "Failed to start a joined runtime task");
+			}
+		}
+	}
+}
+
+/// Worker threads executing the tasks spawned by the runtimes.
+///
+/// The threads are started with the first task. A task which did not start yet when it is joined
+/// gets a thread of its own, so that nested tasks can't exhaust the workers.
+pub(crate) struct TaskPool {
+	sender: Mutex<Option<mpsc::Sender<Arc<Task>>>>,
+}
+
+impl TaskPool {
+	pub(crate) fn new() -> Self {
+		Self { sender: Mutex::new(None) }
+	}
+
+	fn spawn(&self, task: Arc<Task>) {
+		let mut sender = self.sender.lock();
+		let sender = sender.get_or_insert_with(Self::start_workers);
+		if sender.send(task).is_err() {
+			// The task still runs when it is joined.
+			tracing::warn!(target: "executor", "Runtime task workers stopped");
+		}
+	}
+
+	fn start_workers() -> mpsc::Sender<Arc<Task>> {
+		let (sender, receiver) = mpsc::channel::<Arc<Task>>();
+		let receiver = Arc::new(Mutex::new(receiver));
+		let workers = std::thread::available_parallelism()
+			.map(|threads| threads.get())
+			.unwrap_or(1)
+			.min(MAX_TASKS_PER_CALL as usize);
+
+		for index in 0..workers {
+			let receiver = receiver.clone();
+			let spawned = std::thread::Builder::new()
+				.name(format!("runtime-task-{}", index))
+				.spawn(move || loop {
+					let task = receiver.lock().recv();
+					match task {
+						Ok(task) => task.run(),
+						Err(_) => break,
+					}
+				});
+
+			if let Err(error) = spawned {
+				tracing::warn!(
+					target: "executor",
+					%error,
+					"Failed to start a runtime task worker",
+				);
+			}
+		}
+
+		sender
+	}
+}
+
+/// Implements the `RuntimeSpawn` extension for the runtimes implementing
+/// [`sp_tasks::ParallelTasks`].
+///
+/// Every task is executed in a fresh instance of `module`. Its storage reads are answered from the
+/// backend of the externalities joining it.
+pub(crate) struct ParallelRuntimeSpawn {
+	module: Arc<dyn WasmModule>,
+	pool: Arc<TaskPool>,
+	scheduler: Box<dyn SpawnNamed>,
+	/// Number of tasks spawned by the runtime call, shared with the spawners of its tasks.
+	spawned: Arc<AtomicU32>,
+	counter: AtomicU64,
+	tasks: Mutex<HashMap<u64, (Arc<Task>, mpsc::Receiver<TaskMessage>)>>,
+}
+
+impl ParallelRuntimeSpawn {
+	pub(crate) fn new(
+		module: Arc<dyn WasmModule>,
+		pool: Arc<TaskPool>,
+		scheduler: Box<dyn SpawnNamed>,
+	) -> Self {
+		Self::with_spawned(module, pool, scheduler, Default::default())
+	}
+
+	fn with_spawned(
+		module: Arc<dyn WasmModule>,
+		pool: Arc<TaskPool>,
+		scheduler: Box<dyn SpawnNamed>,
+		spawned: Arc<AtomicU32>,
+	) -> Self {
+		Self { module, pool, scheduler, spawned, counter: 0.into(), tasks: Default::default() }
+	}
+}
+
+impl RuntimeSpawn for ParallelRuntimeSpawn {
+	fn spawn_call(&self, dispatcher_ref: u32, func: u32, data: Vec<u8>) -> u64 {
+		if self.spawned.fetch_add(1, Ordering::Relaxed) >= MAX_TASKS_PER_CALL {
+			panic!("Runtime call spawned more than {} tasks", MAX_TASKS_PER_CALL)
+		}
+
+		let new_handle = self.counter.fetch_add(1, Ordering::Relaxed);
+		let (sender, receiver) = mpsc::channel();
+
+		let module = self.module.clone();
+		let runtime_spawn = Self::with_spawned(
+			self.module.clone(),
+			self.pool.clone(),
+			self.scheduler.clone(),
+			self.spawned.clone(),
+		);
+		let scheduler = self.scheduler.clone();
+		let job = move || {
+			let async_ext = new_async_externalities(scheduler)
+				.and_then(|ext| ext.with_runtime_spawn(Box::new(runtime_spawn)))
+				.map(|ext| ext.with_joiner(sender.clone()));
+			let mut async_ext = match async_ext {
+				Ok(val) => val,
+				Err(e) => {
+					tracing::error!(
+						target: "executor",
+						error = %e,
+						"Failed to setup externalities for runtime task.",
+					);
+
+					// This will drop sender and the join will panic
+					return
+				},
+			};
+
+			let module = AssertUnwindSafe(module);
+			let result = with_externalities_safe(&mut async_ext, move || {
+				let mut instance = match module.new_instance() {
+					Ok(instance) => instance,
+					Err(error) => panic!("failed to create new instance from module: {}", error),
+				};
+
+				match instance
+					.call(InvokeMethod::TableWithWrapper { dispatcher_ref, func }, &data[..])
+				{
+					Ok(result) => result,
+					Err(error) => panic!("failed to invoke instance: {}", error),
+				}
+			});
+
+			match result {
+				Ok(output) => {
+					let _ = sender.send(TaskMessage::Result(output));
+				},
+				Err(error) => {
+					// If execution is panicked, the `join` in the original runtime code will
+					// panic as well, since the sender is dropped without sending anything.
+					tracing::error!(error = %error, "Call error in spawned task");
+				},
+			}
+		};
+
+		let task = Arc::new(Task::new(Box::new(job)));
+		self.tasks.lock().insert(new_handle, (task.clone(), receiver));
+		self.pool.spawn(task);
+
+		new_handle
+	}
+
+	fn join(&self, handle: u64, ext: &dyn Externalities) -> Vec<u8> {
+		let (task, receiver) = self.tasks.lock().remove(&handle).expect("No task for the handle");
+		task.start_joined();
+		wait_for_task(&receiver, ext).expect("Spawned task panicked for the handle")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sc_executor_common::{error::Error, wasm_runtime::WasmInstance};
+	use sp_core::testing::TaskExecutor;
+	use sp_io::TestExternalities;
+	use sp_wasm_interface::Value;
+
+	/// A module whose instances return the storage value of the key they are called with.
+	struct ReadingModule;
+
+	impl WasmModule for ReadingModule {
+		fn new_instance(&self) -> Result<Box<dyn WasmInstance>, Error> {
+			Ok(Box::new(ReadingInstance))
+		}
+	}
+
+	struct ReadingInstance;
+
+	impl WasmInstance for ReadingInstance {
+		fn call(&mut self, _method: InvokeMethod, data: &[u8]) -> Result<Vec<u8>, Error> {
+			Ok(sp_io::storage::get(data).unwrap_or_default())
+		}
+
+		fn get_global_const(&mut self, _name: &str) -> Result<Option<Value>, Error> {
+			Ok(None)
+		}
+	}
+
+	fn parallel_runtime_spawn(spawned: Arc<AtomicU32>) -> ParallelRuntimeSpawn {
+		ParallelRuntimeSpawn::with_spawned(
+			Arc::new(ReadingModule),
+			Arc::new(TaskPool::new()),
+			Box::new(TaskExecutor::new()),
+			spawned,
+		)
+	}
+
+	#[test]
+	fn task_pool_runs_the_tasks() {
+		let pool = TaskPool::new();
+		let (sender, receiver) = mpsc::channel();
+		for index in 0..32 {
+			let sender = sender.clone();
+			pool.spawn(Arc::new(Task::new(Box::new(move || sender.send(index).unwrap()))));
+		}
+
+		let mut done = receiver.iter().take(32).collect::<Vec<_>>();
+		done.sort();
+		assert_eq!(done, (0..32).collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn joined_task_which_did_not_start_runs_on_its_own_thread() {
+		let (sender, receiver) = mpsc::channel();
+		let task = Task::new(Box::new(move || {
+			sender.send(std::thread::current().name().map(ToOwned::to_owned)).unwrap()
+		}));
+
+		task.start_joined();
+		assert_eq!(receiver.recv().unwrap().as_deref(), Some("runtime-task-joined"));
+
+		// The task runs once.
+		task.run();
+		task.start_joined();
+		assert!(receiver.recv().is_err());
+	}
+
+	#[test]
+	fn tasks_read_the_backend_of_the_joining_externalities() {
+		let mut ext = TestExternalities::default();
+		ext.insert(b"key".to_vec(), b"value".to_vec());
+		let mut ext = ext.ext();
+		ext.set_storage(b"key".to_vec(), b"changed".to_vec());
+
+		let runtime_spawn = parallel_runtime_spawn(Default::default());
+		let handles = (0..4).map(|_| runtime_spawn.spawn_call(0, 0, b"key".to_vec()));
+		for handle in handles.collect::<Vec<_>>() {
+			assert_eq!(runtime_spawn.join(handle, &ext), b"value".to_vec());
+		}
+		assert_eq!(ext.storage(b"key"), Some(b"changed".to_vec()));
+	}
+
+	#[test]
+	fn runtime_call_spawns_at_most_max_tasks_per_call() {
+		let spawned = Arc::new(AtomicU32::new(0));
+		let runtime_spawn = parallel_runtime_spawn(spawned.clone());
+		let task_spawn = parallel_runtime_spawn(spawned);
+
+		for _ in 1..MAX_TASKS_PER_CALL {
+			runtime_spawn.spawn_call(0, 0, Vec::new());
+		}
+		// The tasks spawned by the tasks count as well.
+		task_spawn.spawn_call(0, 0, Vec::new());
+
+		let spawn_more = || runtime_spawn.spawn_call(0, 0, Vec::new());
+		assert!(std::panic::catch_unwind(AssertUnwindSafe(spawn_more)).is_err());
+	}
+}
@@ -25,6 +25,10 @@ use crate::error::{Error, WasmError};
 use codec::Decode;
 use lru::LruCache;
 use parking_lot::Mutex;
+use prometheus_endpoint::{
+	register, MetricSource, Opts, PrometheusError, Registry, SourcedCounter, SourcedGauge,
+};
+use sc_allocator::AllocationStats;
 use sc_executor_common::{
 	runtime_blob::RuntimeBlob,
 	wasm_runtime::{WasmInstance, WasmModule},
@@ -34,7 +38,11 @@ use sp_version::RuntimeVersion;
 use std::{
 	panic::AssertUnwindSafe,
 	path::{Path, PathBuf},
-	sync::Arc,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+	time::{Duration, Instant},
 };
 
 use sp_wasm_interface::HostFunctions;
@@ -81,9 +89,23 @@ struct VersionedRuntime {
 	instances: Arc<Vec<Mutex<Option<Box<dyn WasmInstance>>>>>,
 }
 
+/// Counters of the use of the instance pools.
+#[derive(Default)]
+struct InstancePoolCounters {
+	hits: AtomicU64,
+	misses: AtomicU64,
+	waits: AtomicU64,
+	evictions: AtomicU64,
+}
+
 impl VersionedRuntime {
 	/// Run the given closure `f` with an instance of this runtime.
-	fn with_instance<R, F>(&self, ext: &mut dyn Externalities, f: F) -> Result<R, Error>
+	fn with_instance<R, F>(
+		&self,
+		ext: &mut dyn Externalities,
+		counters: &InstancePoolCounters,
+		f: F,
+	) -> Result<R, Error>
 	where
 		F: FnOnce(
 			&Arc<dyn WasmModule>,
@@ -105,6 +127,11 @@ impl VersionedRuntime {
 					.take()
 					.map(|r| Ok((r, false)))
 					.unwrap_or_else(|| self.module.new_instance().map(|i| (i, true)))?;
+				if new_inst {
+					counters.misses.fetch_add(1, Ordering::Relaxed);
+				} else {
+					counters.hits.fetch_add(1, Ordering::Relaxed);
+				}
 
 				let result = f(&self.module, &mut *instance, self.version.as_ref(), ext);
 				if let Err(e) = &result {
@@ -115,6 +142,7 @@ impl VersionedRuntime {
 							"Fresh runtime instance failed",
 						)
 					} else {
+						counters.evictions.fetch_add(1, Ordering::Relaxed);
 						tracing::warn!(
 							target: "wasm-runtime",
 							error = %e,
@@ -137,6 +165,7 @@ impl VersionedRuntime {
 				result
 			},
 			None => {
+				counters.waits.fetch_add(1, Ordering::Relaxed);
 				tracing::warn!(target: "wasm-runtime", "Ran out of free WASM instances");
 
 				// Allocate a new instance
@@ -167,6 +196,67 @@ pub struct RuntimeCache {
 	/// The size of the instances cache for each runtime.
 	max_runtime_instances: usize,
 	cache_path: Option<PathBuf>,
+	/// Number of requests served by a cached runtime.
+	hits: AtomicU64,
+	/// Number of requests that had to create the runtime.
+	misses: AtomicU64,
+	/// Number of runtimes evicted to make room for another.
+	evictions: AtomicU64,
+	/// Use of the instance pools of the runtimes.
+	instance_pool: InstancePoolCounters,
+	/// The largest part of the heap used by a call.
+	heap_used_peak: AtomicU64,
+}
+
+/// Statistics of a [`RuntimeCache`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RuntimeCacheStats {
+	/// Number of runtime requests served by a cached runtime.
+	pub hits: u64,
+	/// Number of runtime requests that had to create the runtime.
+	pub misses: u64,
+	/// Number of runtimes evicted from the cache to make room for another.
+	pub evictions: u64,
+	/// Number of calls executed by an instance reused from the instance pool.
+	pub instance_hits: u64,
+	/// Number of calls that had to create an instance for the instance pool.
+	pub instance_misses: u64,
+	/// Number of calls that found every instance of the pool busy, and had to create an instance
+	/// that is not kept after the call.
+	pub instance_waits: u64,
+	/// Number of instances evicted from the instance pool after a failed call.
+	pub instance_evictions: u64,
+	/// The largest number of heap bytes used by a call, i.e. the highest high-water mark of the
+	/// allocator.
+	pub heap_used_peak: u64,
+}
+
+/// A metric of a [`RuntimeCache`], read from its statistics.
+#[derive(Clone)]
+struct CacheMetric(Arc<RuntimeCache>, fn(&RuntimeCacheStats) -> u64);
+
+impl MetricSource for CacheMetric {
+	type N = u64;
+
+	fn collect(&self, mut set: impl FnMut(&[&str], Self::N)) {
+		set(&[], (self.1)(&self.0.stats()));
+	}
+}
+
+/// The use of the instance pools of a [`RuntimeCache`], by event.
+#[derive(Clone)]
+struct InstancePoolMetric(Arc<RuntimeCache>);
+
+impl MetricSource for InstancePoolMetric {
+	type N = u64;
+
+	fn collect(&self, mut set: impl FnMut(&[&str], Self::N)) {
+		let stats = self.0.stats();
+		set(&["hit"], stats.instance_hits);
+		set(&["miss"], stats.instance_misses);
+		set(&["wait"], stats.instance_waits);
+		set(&["eviction"], stats.instance_evictions);
+	}
 }
 
 impl RuntimeCache {
@@ -189,9 +279,95 @@ impl RuntimeCache {
 			runtimes: Mutex::new(LruCache::new(runtime_cache_size.into())),
 			max_runtime_instances,
 			cache_path,
+			hits: AtomicU64::new(0),
+			misses: AtomicU64::new(0),
+			evictions: AtomicU64::new(0),
+			instance_pool: Default::default(),
+			heap_used_peak: AtomicU64::new(0),
 		}
 	}
 
+	/// Returns the statistics of this cache.
+	pub fn stats(&self) -> RuntimeCacheStats {
+		RuntimeCacheStats {
+			hits: self.hits.load(Ordering::Relaxed),
+			misses: self.misses.load(Ordering::Relaxed),
+			evictions: self.evictions.load(Ordering::Relaxed),
+			instance_hits: self.instance_pool.hits.load(Ordering::Relaxed),
+			instance_misses: self.instance_pool.misses.load(Ordering::Relaxed),
+			instance_waits: self.instance_pool.waits.load(Ordering::Relaxed),
+			instance_evictions: self.instance_pool.evictions.load(Ordering::Relaxed),
+			heap_used_peak: self.heap_used_peak.load(Ordering::Relaxed),
+		}
+	}
+
+	/// Record the heap usage of a call.
+	pub fn record_allocation_stats(&self, stats: &AllocationStats) {
+		self.heap_used_peak
+			.fetch_max(stats.address_space_used.into(), Ordering::Relaxed);
+	}
+
+	/// Register the metrics of this cache and of the instance pools of its runtimes in `registry`.
+	///
+	/// The metrics are read from the [`RuntimeCache::stats`] when they are scraped.
+	pub fn register_metrics(self: &Arc<Self>, registry: &Registry) -> Result<(), PrometheusError> {
+		register(
+			SourcedCounter::new(
+				&Opts::new(
+					"substrate_runtime_cache_hits_total",
+					"Number of runtime calls served by a cached runtime",
+				),
+				CacheMetric(self.clone(), |stats| stats.hits),
+			)?,
+			registry,
+		)?;
+		register(
+			SourcedCounter::new(
+				&Opts::new(
+					"substrate_runtime_cache_misses_total",
+					"Number of runtime calls that had to compile their runtime",
+				),
+				CacheMetric(self.clone(), |stats| stats.misses),
+			)?,
+			registry,
+		)?;
+		register(
+			SourcedCounter::new(
+				&Opts::new(
+					"substrate_runtime_cache_evictions_total",
+					"Number of runtimes evicted from the runtime cache",
+				),
+				CacheMetric(self.clone(), |stats| stats.evictions),
+			)?,
+			registry,
+		)?;
+		register(
+			SourcedCounter::new(
+				&Opts::new(
+					"substrate_runtime_instance_pool_total",
+					"Number of runtime calls by their use of the instance pool: reusing an \
+					 instance (hit), creating one (miss), finding them all busy (wait), or \
+					 evicting a failed one (eviction)",
+				)
+				.variable_label("event"),
+				InstancePoolMetric(self.clone()),
+			)?,
+			registry,
+		)?;
+		register(
+			SourcedGauge::new(
+				&Opts::new(
+					"substrate_runtime_heap_used_peak_bytes",
+					"Largest number of heap bytes used by a runtime call",
+				),
+				CacheMetric(self.clone(), |stats| stats.heap_used_peak),
+			)?,
+			registry,
+		)?;
+
+		Ok(())
+	}
+
 	/// Prepares a WASM module instance and executes given function for it.
 	///
 	/// This uses internal cache to find available instance or create a new one.
@@ -205,6 +381,12 @@ impl RuntimeCache {
 	///
 	/// `allow_missing_func_imports` - Ignore missing function imports.
 	///
+	/// `fuel_metering` - Meter the fuel consumed by the runtime.
+	///
+	/// `host_function_profiling` - Allow the instances to record their host function calls.
+	///
+	/// `runtime_artifacts` - Directory of the precompiled runtime artifacts, if any.
+	///
 	/// `f` - Function to execute.
 	///
 	/// `H` - A compile-time list of host functions to expose to the runtime.
@@ -223,6 +405,9 @@ impl RuntimeCache {
 		wasm_method: WasmExecutionMethod,
 		default_heap_pages: u64,
 		allow_missing_func_imports: bool,
+		fuel_metering: bool,
+		host_function_profiling: bool,
+		runtime_artifacts: Option<&Path>,
 		f: F,
 	) -> Result<Result<R, Error>, Error>
 	where
@@ -243,20 +428,26 @@ impl RuntimeCache {
 		let mut runtimes = self.runtimes.lock(); // this must be released prior to calling f
 		let versioned_runtime = if let Some(versioned_runtime) = runtimes.get(&versioned_runtime_id)
 		{
+			self.hits.fetch_add(1, Ordering::Relaxed);
 			versioned_runtime.clone()
 		} else {
+			self.misses.fetch_add(1, Ordering::Relaxed);
 			let code = runtime_code.fetch_runtime_code().ok_or(WasmError::CodeNotFound)?;
 
-			let time = std::time::Instant::now();
+			let time = Instant::now();
 
 			let result = create_versioned_wasm_runtime::<H>(
 				&code,
+				code_hash,
 				ext,
 				wasm_method,
 				heap_pages,
 				allow_missing_func_imports,
+				fuel_metering,
+				host_function_profiling,
 				self.max_runtime_instances,
 				self.cache_path.as_deref(),
+				runtime_artifacts,
 			);
 
 			match result {
@@ -276,7 +467,7 @@ impl RuntimeCache {
 			let versioned_runtime = Arc::new(result?);
 
 			// Save new versioned wasm runtime in cache
-			runtimes.put(versioned_runtime_id, versioned_runtime.clone());
+			self.put(&mut runtimes, versioned_runtime_id, versioned_runtime.clone());
 
 			versioned_runtime
 		};
@@ -284,16 +475,113 @@ impl RuntimeCache {
 		// Lock must be released prior to calling f
 		drop(runtimes);
 
-		Ok(versioned_runtime.with_instance(ext, f))
+		Ok(versioned_runtime.with_instance(ext, &self.instance_pool, f))
+	}
+
+	/// Add a runtime to `runtimes`, counting the runtime it evicts if any.
+	fn put(
+		&self,
+		runtimes: &mut LruCache<VersionedRuntimeId, Arc<VersionedRuntime>>,
+		versioned_runtime_id: VersionedRuntimeId,
+		versioned_runtime: Arc<VersionedRuntime>,
+	) {
+		if runtimes.len() == runtimes.cap() && !runtimes.contains(&versioned_runtime_id) {
+			self.evictions.fetch_add(1, Ordering::Relaxed);
+		}
+		runtimes.put(versioned_runtime_id, versioned_runtime);
+	}
+
+	/// Create the runtime of `runtime_code` and add it to the cache, so that its first use
+	/// doesn't wait for its compilation.
+	///
+	/// Unlike [`Self::with_instance`], the cache isn't locked while the runtime is created, which
+	/// makes this suitable to prepare upcoming runtimes in the background.
+	///
+	/// Returns how long it took to create the runtime, or `None` if it was cached already.
+	pub fn prepare<'c, H>(
+		&self,
+		runtime_code: &'c RuntimeCode<'c>,
+		ext: &mut dyn Externalities,
+		wasm_method: WasmExecutionMethod,
+		default_heap_pages: u64,
+		allow_missing_func_imports: bool,
+		fuel_metering: bool,
+		host_function_profiling: bool,
+		runtime_artifacts: Option<&Path>,
+	) -> Result<Option<Duration>, Error>
+	where
+		H: HostFunctions,
+	{
+		let code_hash = &runtime_code.hash;
+		let heap_pages = runtime_code.heap_pages.unwrap_or(default_heap_pages);
+
+		let versioned_runtime_id =
+			VersionedRuntimeId { code_hash: code_hash.clone(), heap_pages, wasm_method };
+
+		{
+			let runtimes = self.runtimes.lock();
+			if runtimes.contains(&versioned_runtime_id) {
+				return Ok(None)
+			}
+			// Adding a runtime to a cache of one would evict the runtime in use.
+			if runtimes.cap() < 2 {
+				return Err(Error::Other(
+					"the runtime cache is too small to prepare runtimes ahead of time".into(),
+				))
+			}
+		}
+
+		let code = runtime_code.fetch_runtime_code().ok_or(WasmError::CodeNotFound)?;
+
+		let time = Instant::now();
+		let versioned_runtime = create_versioned_wasm_runtime::<H>(
+			&code,
+			code_hash,
+			ext,
+			wasm_method,
+			heap_pages,
+			allow_missing_func_imports,
+			fuel_metering,
+			host_function_profiling,
+			self.max_runtime_instances,
+			self.cache_path.as_deref(),
+			runtime_artifacts,
+		)?;
+		let elapsed = time.elapsed();
+
+		tracing::debug!(
+			target: "wasm-runtime",
+			"Prepared upcoming runtime version {:?} in {} ms.",
+			versioned_runtime.version,
+			elapsed.as_millis(),
+		);
+
+		let mut runtimes = self.runtimes.lock();
+		if !runtimes.contains(&versioned_runtime_id) {
+			// Keep the most recently used runtime in front, it is the one in use until the upgrade
+			// is applied.
+			let in_use = runtimes.iter().next().map(|(id, _)| id.clone());
+			self.put(&mut runtimes, versioned_runtime_id, Arc::new(versioned_runtime));
+			if let Some(in_use) = in_use {
+				runtimes.get(&in_use);
+			}
+		}
+
+		Ok(Some(elapsed))
 	}
 }
 
 /// Create a wasm runtime with the given `code`.
+///
+/// With `fuel_metering` the calls of the runtime can be given a fuel budget, and with
+/// `host_function_profiling` its instances can record their host function calls.
 pub fn create_wasm_runtime_with_code<H>(
 	wasm_method: WasmExecutionMethod,
 	heap_pages: u64,
 	blob: RuntimeBlob,
 	allow_missing_func_imports: bool,
+	fuel_metering: bool,
+	host_function_profiling: bool,
 	cache_path: Option<&Path>,
 ) -> Result<Arc<dyn WasmModule>, WasmError>
 where
@@ -304,14 +592,16 @@ where
 			// Wasmi doesn't have any need in a cache directory.
 			//
 			// We drop the cache_path here to silence warnings that cache_path is not used if
-			// compiling without the `wasmtime` flag.
-			let _ = cache_path;
+			// compiling without the `wasmtime` flag. Wasmi instances can always record their host
+			// function calls.
+			let _ = (cache_path, host_function_profiling);
 
 			sc_executor_wasmi::create_runtime(
 				blob,
 				heap_pages,
 				H::host_functions(),
 				allow_missing_func_imports,
+				fuel_metering,
 			)
 			.map(|runtime| -> Arc<dyn WasmModule> { Arc::new(runtime) })
 		},
@@ -322,20 +612,149 @@ where
 				sc_executor_wasmtime::Config {
 					allow_missing_func_imports,
 					cache_path: cache_path.map(ToOwned::to_owned),
-					semantics: sc_executor_wasmtime::Semantics {
-						extra_heap_pages: heap_pages,
+					semantics: wasmtime_semantics(
+						heap_pages,
 						instantiation_strategy,
-						deterministic_stack_limit: None,
-						canonicalize_nans: false,
-						parallel_compilation: true,
-						max_memory_size: None,
-					},
+						fuel_metering,
+					),
+					host_function_profiling,
 				},
 			)
 			.map(|runtime| -> Arc<dyn WasmModule> { Arc::new(runtime) }),
 	}
 }
 
+#[cfg(feature = "wasmtime")]
+fn wasmtime_semantics(
+	heap_pages: u64,
+	instantiation_strategy: sc_executor_wasmtime::InstantiationStrategy,
+	fuel_metering: bool,
+) -> sc_executor_wasmtime::Semantics {
+	sc_executor_wasmtime::Semantics {
+		extra_heap_pages: heap_pages,
+		instantiation_strategy,
+		deterministic_stack_limit: None,
+		canonicalize_nans: false,
+		parallel_compilation: true,
+		max_memory_size: None,
+		fuel_metering,
+	}
+}
+
+/// Returns the precompiled artifacts of the runtimes executed with `wasm_method`, if that method
+/// supports them.
+#[cfg(feature = "wasmtime")]
+fn runtime_artifacts_for(
+	wasm_method: WasmExecutionMethod,
+	heap_pages: u64,
+	fuel_metering: bool,
+	runtime_artifacts: &Path,
+) -> Option<(crate::artifacts::RuntimeArtifacts, sc_executor_wasmtime::Semantics)> {
+	use sc_executor_wasmtime::InstantiationStrategy;
+
+	match wasm_method {
+		WasmExecutionMethod::Compiled { instantiation_strategy }
+			if instantiation_strategy != InstantiationStrategy::LegacyInstanceReuse =>
+			Some((
+				crate::artifacts::RuntimeArtifacts::new(runtime_artifacts.to_owned()),
+				wasmtime_semantics(heap_pages, instantiation_strategy, fuel_metering),
+			)),
+		_ => None,
+	}
+}
+
+/// Create a wasm runtime with the given `code`, loading it from the precompiled artifacts in
+/// `runtime_artifacts` if `wasm_method` supports them. A missing artifact is prepared first.
+fn create_wasm_runtime_with_artifacts<H>(
+	wasm_method: WasmExecutionMethod,
+	heap_pages: u64,
+	blob: RuntimeBlob,
+	code_hash: &[u8],
+	allow_missing_func_imports: bool,
+	fuel_metering: bool,
+	host_function_profiling: bool,
+	cache_path: Option<&Path>,
+	runtime_artifacts: &Path,
+) -> Result<Arc<dyn WasmModule>, WasmError>
+where
+	H: HostFunctions,
+{
+	#[cfg(feature = "wasmtime")]
+	if let Some((artifacts, semantics)) =
+		runtime_artifacts_for(wasm_method, heap_pages, fuel_metering, runtime_artifacts)
+	{
+		let config = sc_executor_wasmtime::Config {
+			allow_missing_func_imports,
+			cache_path: None,
+			semantics,
+			host_function_profiling,
+		};
+		let result = artifacts.prepare(&blob, code_hash, &config.semantics).and_then(|code| {
+			// SAFETY: The artifact was produced by `prepare_runtime_artifact`, either just now or
+			//         ahead of time by a trusted operator, and passed the integrity checks. The
+			//         checked code is loaded from memory, so it can't change in between.
+			unsafe {
+				sc_executor_wasmtime::create_runtime_from_artifact_bytes::<H>(&code, config)
+			}
+		});
+		match result {
+			Ok(runtime) => return Ok(Arc::new(runtime)),
+			Err(error) => tracing::warn!(
+				target: "wasm-runtime",
+				?error,
+				"Cannot use a precompiled runtime artifact, compiling the runtime instead",
+			),
+		}
+	}
+
+	// We drop these here to silence warnings that they are not used if compiling without the
+	// `wasmtime` flag.
+	let _ = (code_hash, runtime_artifacts);
+
+	create_wasm_runtime_with_code::<H>(
+		wasm_method,
+		heap_pages,
+		blob,
+		allow_missing_func_imports,
+		fuel_metering,
+		host_function_profiling,
+		cache_path,
+	)
+}
+
+/// Precompile the runtime `code` of hash `code_hash` into an artifact in the directory
+/// `runtime_artifacts`, for executors using `wasm_method` and `heap_pages`. Returns the path of
+/// the artifact.
+///
+/// `heap_pages` defaults to the default of the executors. Artifacts are only supported by the
+/// compiled execution method, with any instantiation strategy but the legacy instance reuse.
+/// Executors metering the fuel need artifacts prepared with `fuel_metering`.
+pub fn precompile_runtime(
+	runtime_artifacts: &Path,
+	code: &[u8],
+	code_hash: &[u8],
+	wasm_method: WasmExecutionMethod,
+	heap_pages: Option<u64>,
+	fuel_metering: bool,
+) -> Result<PathBuf, WasmError> {
+	let heap_pages = heap_pages.unwrap_or(crate::native_executor::DEFAULT_HEAP_PAGES);
+
+	#[cfg(feature = "wasmtime")]
+	if let Some((artifacts, semantics)) =
+		runtime_artifacts_for(wasm_method, heap_pages, fuel_metering, runtime_artifacts)
+	{
+		let blob = RuntimeBlob::uncompress_if_needed(code)?;
+		artifacts.prepare(&blob, code_hash, &semantics)?;
+		return artifacts.code_path(code_hash, &semantics)
+	}
+
+	let _ = (runtime_artifacts, code, code_hash, heap_pages, fuel_metering);
+	Err(WasmError::Other(format!(
+		"the {:?} execution method doesn't support precompiled runtime artifacts",
+		wasm_method,
+	)))
+}
+
 fn decode_version(mut version: &[u8]) -> Result<RuntimeVersion, WasmError> {
 	Decode::decode(&mut version).map_err(|_| {
 		WasmError::Instantiation(
@@ -394,12 +813,16 @@ pub fn read_embedded_version(blob: &RuntimeBlob) -> Result<Option<RuntimeVersion
 
 fn create_versioned_wasm_runtime<H>(
 	code: &[u8],
+	code_hash: &[u8],
 	ext: &mut dyn Externalities,
 	wasm_method: WasmExecutionMethod,
 	heap_pages: u64,
 	allow_missing_func_imports: bool,
+	fuel_metering: bool,
+	host_function_profiling: bool,
 	max_instances: usize,
 	cache_path: Option<&Path>,
+	runtime_artifacts: Option<&Path>,
 ) -> Result<VersionedRuntime, WasmError>
 where
 	H: HostFunctions,
@@ -413,13 +836,28 @@ where
 	// runtime.
 	let mut version: Option<_> = read_embedded_version(&blob)?;
 
-	let runtime = create_wasm_runtime_with_code::<H>(
-		wasm_method,
-		heap_pages,
-		blob,
-		allow_missing_func_imports,
-		cache_path,
-	)?;
+	let runtime = match runtime_artifacts {
+		Some(runtime_artifacts) => create_wasm_runtime_with_artifacts::<H>(
+			wasm_method,
+			heap_pages,
+			blob,
+			code_hash,
+			allow_missing_func_imports,
+			fuel_metering,
+			host_function_profiling,
+			cache_path,
+			runtime_artifacts,
+		),
+		None => create_wasm_runtime_with_code::<H>(
+			wasm_method,
+			heap_pages,
+			blob,
+			allow_missing_func_imports,
+			fuel_metering,
+			host_function_profiling,
+			cache_path,
+		),
+	}?;
 
 	// If the runtime blob doesn't embed the runtime version then use the legacy version query
 	// mechanism: call the runtime.
@@ -29,8 +29,10 @@ use wasmi::{
 };
 
 use codec::{Decode, Encode};
+use sc_allocator::AllocationStats;
 use sc_executor_common::{
 	error::{Error, MessageWithBacktrace, WasmError},
+	profiling::{execute_profiled, HostFunctionProfile},
 	runtime_blob::{DataSegmentsSnapshot, RuntimeBlob},
 	sandbox,
 	util::MemoryTransfer,
@@ -42,6 +44,22 @@ use sp_wasm_interface::{
 	Function, FunctionContext, MemoryId, Pointer, Result as WResult, Sandbox, WordSize,
 };
 
+/// Name of the function, imported from `env`, that the gas metering instrumentation calls to
+/// consume fuel.
+const GAS_FUNCTION_NAME: &str = "gas";
+
+/// Host function index of the gas function, out of the range of the other host functions.
+const GAS_FUNCTION_INDEX: usize = usize::MAX;
+
+/// The fuel of a call.
+#[derive(Clone, Copy)]
+struct Fuel {
+	/// The fuel the call started with.
+	budget: u64,
+	/// The fuel left.
+	left: u64,
+}
+
 struct FunctionExecutor {
 	sandbox_store: Rc<RefCell<sandbox::Store<wasmi::FuncRef>>>,
 	heap: RefCell<sc_allocator::FreeingBumpHeapAllocator>,
@@ -51,6 +69,11 @@ struct FunctionExecutor {
 	allow_missing_func_imports: bool,
 	missing_functions: Arc<Vec<String>>,
 	panic_message: Option<String>,
+	/// The fuel of the call, if it is metered.
+	fuel: Option<Fuel>,
+	out_of_fuel: bool,
+	/// The host function calls of the call, if they are profiled.
+	host_function_profile: Option<HostFunctionProfile>,
 }
 
 impl FunctionExecutor {
@@ -61,11 +84,12 @@ impl FunctionExecutor {
 		host_functions: Arc<Vec<&'static dyn Function>>,
 		allow_missing_func_imports: bool,
 		missing_functions: Arc<Vec<String>>,
+		fuel: Option<u64>,
+		host_function_profile: Option<HostFunctionProfile>,
+		sandbox_backend: sandbox::SandboxBackend,
 	) -> Result<Self, Error> {
 		Ok(FunctionExecutor {
-			sandbox_store: Rc::new(RefCell::new(sandbox::Store::new(
-				sandbox::SandboxBackend::Wasmi,
-			))),
+			sandbox_store: Rc::new(RefCell::new(sandbox::Store::new(sandbox_backend))),
 			heap: RefCell::new(sc_allocator::FreeingBumpHeapAllocator::new(heap_base)),
 			memory: m,
 			table: t,
@@ -73,8 +97,25 @@ impl FunctionExecutor {
 			allow_missing_func_imports,
 			missing_functions,
 			panic_message: None,
+			fuel: fuel.map(|budget| Fuel { budget, left: budget }),
+			out_of_fuel: false,
+			host_function_profile,
 		})
 	}
+
+	/// Consume `amount` of the fuel of the call, if it is metered.
+	fn consume_fuel(&mut self, amount: u64) -> Result<(), Error> {
+		if let Some(fuel) = &mut self.fuel {
+			match fuel.left.checked_sub(amount) {
+				Some(left) => fuel.left = left,
+				None => {
+					self.out_of_fuel = true;
+					return Err(Error::OutOfFuel(fuel.budget))
+				},
+			}
+		}
+		Ok(())
+	}
 }
 
 struct SandboxContext<'a> {
@@ -332,6 +373,8 @@ struct Resolver<'a> {
 	/// resolving. However, to be backwards compatible, we also support memory
 	/// exported by the WASM blob (this will be `None` after resolving).
 	import_memory: RefCell<Option<MemoryRef>>,
+	/// Whether the module is instrumented with gas metering, which imports the gas function.
+	fuel_metering: bool,
 }
 
 impl<'a> Resolver<'a> {
@@ -339,6 +382,7 @@ impl<'a> Resolver<'a> {
 		host_functions: &'a [&'static dyn Function],
 		allow_missing_func_imports: bool,
 		heap_pages: usize,
+		fuel_metering: bool,
 	) -> Resolver<'a> {
 		Resolver {
 			host_functions,
@@ -346,6 +390,7 @@ impl<'a> Resolver<'a> {
 			missing_functions: RefCell::new(Vec::new()),
 			heap_pages,
 			import_memory: Default::default(),
+			fuel_metering,
 		}
 	}
 }
@@ -356,6 +401,10 @@ impl<'a> wasmi::ModuleImportResolver for Resolver<'a> {
 		name: &str,
 		signature: &wasmi::Signature,
 	) -> std::result::Result<wasmi::FuncRef, wasmi::Error> {
+		if self.fuel_metering && name == GAS_FUNCTION_NAME {
+			return Ok(wasmi::FuncInstance::alloc_host(signature.clone(), GAS_FUNCTION_INDEX))
+		}
+
 		let signature = sp_wasm_interface::Signature::from(signature);
 		for (function_index, function) in self.host_functions.iter().enumerate() {
 			if name == function.name() {
@@ -432,11 +481,24 @@ impl wasmi::Externals for FunctionExecutor {
 		index: usize,
 		args: wasmi::RuntimeArgs,
 	) -> Result<Option<wasmi::RuntimeValue>, wasmi::Trap> {
+		if index == GAS_FUNCTION_INDEX {
+			let amount: u32 = args.nth_checked(0)?;
+			return self.consume_fuel(amount.into()).map(|_| None).map_err(wasmi::Trap::from)
+		}
+
 		let mut args = args.as_ref().iter().copied().map(Into::into);
 
-		if let Some(function) = self.host_functions.clone().get(index) {
-			function
-				.execute(self, &mut args)
+		if let Some(&function) = self.host_functions.clone().get(index) {
+			let result = if self.host_function_profile.is_some() {
+				let (result, stats) = execute_profiled(function, self, &mut args);
+				if let Some(profile) = &mut self.host_function_profile {
+					profile.record(function.name(), &stats);
+				}
+				result
+			} else {
+				function.execute(self, &mut args)
+			};
+			result
 				.map_err(|msg| Error::FunctionExecution(function.name().to_string(), msg))
 				.map_err(wasmi::Trap::from)
 				.map(|v| v.map(Into::into))
@@ -489,6 +551,10 @@ fn call_in_wasm_module(
 	host_functions: Arc<Vec<&'static dyn Function>>,
 	allow_missing_func_imports: bool,
 	missing_functions: Arc<Vec<String>>,
+	fuel: Option<u64>,
+	host_function_profile: &mut Option<HostFunctionProfile>,
+	allocation_stats: &mut Option<AllocationStats>,
+	sandbox_backend: sandbox::SandboxBackend,
 ) -> Result<Vec<u8>, Error> {
 	// Initialize FunctionExecutor.
 	let table: Option<TableRef> = module_instance
@@ -503,6 +569,9 @@ fn call_in_wasm_module(
 		host_functions,
 		allow_missing_func_imports,
 		missing_functions,
+		fuel,
+		host_function_profile.take(),
+		sandbox_backend,
 	)?;
 
 	// Write the call data
@@ -510,7 +579,9 @@ fn call_in_wasm_module(
 	function_executor.write_memory(offset, data)?;
 
 	fn convert_trap(executor: &mut FunctionExecutor, trap: wasmi::Trap) -> Error {
-		if let Some(message) = executor.panic_message.take() {
+		if let Some(fuel) = executor.fuel.filter(|_| executor.out_of_fuel) {
+			Error::OutOfFuel(fuel.budget)
+		} else if let Some(message) = executor.panic_message.take() {
 			Error::AbortedDueToPanic(MessageWithBacktrace { message, backtrace: None })
 		} else {
 			Error::AbortedDueToTrap(MessageWithBacktrace {
@@ -560,6 +631,8 @@ fn call_in_wasm_module(
 			.map_err(|trap| convert_trap(&mut function_executor, trap))
 		},
 	};
+	*host_function_profile = function_executor.host_function_profile.take();
+	*allocation_stats = Some(function_executor.heap.borrow().stats());
 
 	match result {
 		Ok(Some(I64(r))) => {
@@ -584,8 +657,10 @@ fn instantiate_module(
 	module: &Module,
 	host_functions: &[&'static dyn Function],
 	allow_missing_func_imports: bool,
+	fuel_metering: bool,
 ) -> Result<(ModuleRef, Vec<String>, MemoryRef), Error> {
-	let resolver = Resolver::new(host_functions, allow_missing_func_imports, heap_pages);
+	let resolver =
+		Resolver::new(host_functions, allow_missing_func_imports, heap_pages, fuel_metering);
 	// start module instantiation. Don't run 'start' function yet.
 	let intermediate_instance =
 		ModuleInstance::new(module, &ImportsBuilder::new().with_resolver("env", &resolver))?;
@@ -675,6 +750,8 @@ pub struct WasmiRuntime {
 	allow_missing_func_imports: bool,
 	/// Numer of heap pages this runtime uses.
 	heap_pages: u64,
+	/// Whether the module is instrumented with gas metering.
+	fuel_metering: bool,
 
 	global_vals_snapshot: GlobalValsSnapshot,
 	data_segments_snapshot: DataSegmentsSnapshot,
@@ -688,6 +765,7 @@ impl WasmModule for WasmiRuntime {
 			&self.module,
 			&self.host_functions,
 			self.allow_missing_func_imports,
+			self.fuel_metering,
 		)
 		.map_err(|e| WasmError::Instantiation(e.to_string()))?;
 
@@ -699,18 +777,27 @@ impl WasmModule for WasmiRuntime {
 			host_functions: self.host_functions.clone(),
 			allow_missing_func_imports: self.allow_missing_func_imports,
 			missing_functions: Arc::new(missing_functions),
+			fuel: None,
+			host_function_profile: None,
+			allocation_stats: None,
+			sandbox_backend: sandbox::SandboxBackend::Wasmi,
 		}))
 	}
 }
 
 /// Create a new `WasmiRuntime` given the code. This function loads the module and
 /// stores it in the instance.
+///
+/// With `fuel_metering` the code is instrumented to count the executed instructions, so that its
+/// instances can be given a fuel budget.
 pub fn create_runtime(
 	blob: RuntimeBlob,
 	heap_pages: u64,
 	host_functions: Vec<&'static dyn Function>,
 	allow_missing_func_imports: bool,
+	fuel_metering: bool,
 ) -> Result<WasmiRuntime, WasmError> {
+	let blob = if fuel_metering { blob.inject_gas_metering("env")? } else { blob };
 	let data_segments_snapshot =
 		DataSegmentsSnapshot::take(&blob).map_err(|e| WasmError::Other(e.to_string()))?;
 
@@ -723,6 +810,7 @@ pub fn create_runtime(
 			&module,
 			&host_functions,
 			allow_missing_func_imports,
+			fuel_metering,
 		)
 		.map_err(|e| WasmError::Instantiation(e.to_string()))?;
 		GlobalValsSnapshot::take(&instance)
@@ -735,6 +823,7 @@ pub fn create_runtime(
 		host_functions: Arc::new(host_functions),
 		allow_missing_func_imports,
 		heap_pages,
+		fuel_metering,
 	})
 }
 
@@ -755,6 +844,14 @@ pub struct WasmiInstance {
 	allow_missing_func_imports: bool,
 	/// List of missing functions detected during function resolution
 	missing_functions: Arc<Vec<String>>,
+	/// The fuel budget of the calls.
+	fuel: Option<u64>,
+	/// The host function calls recorded, if they are profiled.
+	host_function_profile: Option<HostFunctionProfile>,
+	/// The heap usage of the last call.
+	allocation_stats: Option<AllocationStats>,
+	/// The backend of the sandboxes created by the calls.
+	sandbox_backend: sandbox::SandboxBackend,
 }
 
 // This is safe because `WasmiInstance` does not leak any references to `self.memory` and
@@ -766,6 +863,8 @@ impl WasmInstance for WasmiInstance {
 		// We reuse a single wasm instance for multiple calls and a previous call (if any)
 		// altered the state. Therefore, we need to restore the instance to original state.
 
+		self.allocation_stats = None;
+
 		// First, zero initialize the linear memory.
 		self.memory.erase().map_err(|e| {
 			// Snapshot restoration failed. This is pretty unexpected since this can happen
@@ -790,9 +889,34 @@ impl WasmInstance for WasmiInstance {
 			self.host_functions.clone(),
 			self.allow_missing_func_imports,
 			self.missing_functions.clone(),
+			self.fuel,
+			&mut self.host_function_profile,
+			&mut self.allocation_stats,
+			self.sandbox_backend,
 		)
 	}
 
+	fn set_fuel(&mut self, fuel: Option<u64>) -> Result<(), Error> {
+		self.fuel = fuel;
+		Ok(())
+	}
+
+	fn set_sandbox_backend(&mut self, backend: sandbox::SandboxBackend) {
+		self.sandbox_backend = backend;
+	}
+
+	fn set_host_function_profiling(&mut self, enabled: bool) {
+		self.host_function_profile = enabled.then(Default::default);
+	}
+
+	fn take_host_function_profile(&mut self) -> Option<HostFunctionProfile> {
+		self.host_function_profile.take()
+	}
+
+	fn take_allocation_stats(&mut self) -> Option<AllocationStats> {
+		self.allocation_stats.take()
+	}
+
 	fn get_global_const(&mut self, name: &str) -> Result<Option<sp_wasm_interface::Value>, Error> {
 		match self.instance.export_by_name(name) {
 			Some(global) => Ok(Some(
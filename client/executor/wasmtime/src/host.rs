@@ -23,9 +23,10 @@ use log::trace;
 use wasmtime::{Caller, Func, Val};
 
 use codec::{Decode, Encode};
-use sc_allocator::FreeingBumpHeapAllocator;
+use sc_allocator::{AllocationStats, FreeingBumpHeapAllocator};
 use sc_executor_common::{
 	error::Result,
+	profiling::HostFunctionProfile,
 	sandbox::{self, SupervisorFuncIndex},
 	util::MemoryTransfer,
 };
@@ -48,17 +49,22 @@ pub struct HostState {
 	sandbox_store: SandboxStore,
 	allocator: FreeingBumpHeapAllocator,
 	panic_message: Option<String>,
+	/// The host function calls of the runtime call, if they are profiled.
+	pub(crate) host_function_profile: Option<HostFunctionProfile>,
 }
 
 impl HostState {
 	/// Constructs a new `HostState`.
-	pub fn new(allocator: FreeingBumpHeapAllocator) -> Self {
+	pub fn new(
+		allocator: FreeingBumpHeapAllocator,
+		host_function_profile: Option<HostFunctionProfile>,
+		sandbox_backend: sandbox::SandboxBackend,
+	) -> Self {
 		HostState {
-			sandbox_store: SandboxStore(Some(Box::new(sandbox::Store::new(
-				sandbox::SandboxBackend::TryWasmer,
-			)))),
+			sandbox_store: SandboxStore(Some(Box::new(sandbox::Store::new(sandbox_backend)))),
 			allocator,
 			panic_message: None,
+			host_function_profile,
 		}
 	}
 
@@ -66,6 +72,11 @@ impl HostState {
 	pub fn take_panic_message(&mut self) -> Option<String> {
 		self.panic_message.take()
 	}
+
+	/// Returns the heap usage of the runtime call so far.
+	pub(crate) fn allocation_stats(&self) -> AllocationStats {
+		self.allocator.stats()
+	}
 }
 
 /// A `HostContext` implements `FunctionContext` for making host calls from a Wasmtime
@@ -16,18 +16,22 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
-use crate::{host::HostContext, runtime::StoreData};
-use sc_executor_common::error::WasmError;
-use sp_wasm_interface::{FunctionContext, HostFunctions};
+use crate::{host::HostContext, runtime::StoreData, util};
+use sc_executor_common::{error::WasmError, profiling::execute_profiled};
+use sp_wasm_interface::{Function, FunctionContext, HostFunctions, ValueType};
 use std::collections::HashMap;
-use wasmtime::{ExternType, FuncType, ImportType, Linker, Module, Trap};
+use wasmtime::{ExternType, FuncType, ImportType, Linker, Module, Trap, ValType};
 
 /// Goes over all imports of a module and prepares the given linker for instantiation of the module.
 /// Returns an error if there are imports that cannot be satisfied.
+///
+/// With `host_function_profiling` the host functions are dispatched dynamically, so that their
+/// calls can be recorded.
 pub(crate) fn prepare_imports<H>(
 	linker: &mut Linker<StoreData>,
 	module: &Module,
 	allow_missing_func_imports: bool,
+	host_function_profiling: bool,
 ) -> Result<(), WasmError>
 where
 	H: HostFunctions,
@@ -58,7 +62,11 @@ where
 	}
 
 	let mut registry = Registry { linker, pending_func_imports };
-	H::register_static(&mut registry)?;
+	if host_function_profiling {
+		registry.register_profiled(H::host_functions())?;
+	} else {
+		H::register_static(&mut registry)?;
+	}
 
 	if !registry.pending_func_imports.is_empty() {
 		if allow_missing_func_imports {
@@ -92,6 +100,73 @@ struct Registry<'a, 'b> {
 	pending_func_imports: HashMap<String, (ImportType<'b>, FuncType)>,
 }
 
+impl<'a, 'b> Registry<'a, 'b> {
+	/// Register the imported `host_functions` with a dynamic dispatch recording their calls in the
+	/// host function profile of the running call, if any.
+	fn register_profiled(
+		&mut self,
+		host_functions: Vec<&'static dyn Function>,
+	) -> Result<(), WasmError> {
+		for function in host_functions {
+			let func_ty = match self.pending_func_imports.remove(function.name()) {
+				Some((_, func_ty)) => func_ty,
+				None => continue,
+			};
+			if !signature_matches(function, &func_ty) {
+				return Err(WasmError::Other(format!(
+					"host function '{}' doesn't match the signature of its import: {:?}",
+					function.name(),
+					func_ty,
+				)))
+			}
+
+			self.linker
+				.func_new("env", function.name(), func_ty, move |caller, params, results| {
+					let mut args = params.iter().cloned().map(util::from_wasmtime_val);
+					let mut context = HostContext { caller };
+					let (result, stats) = execute_profiled(function, &mut context, &mut args);
+					if let Some(profile) = context
+						.caller
+						.data_mut()
+						.host_state_mut()
+						.and_then(|host_state| host_state.host_function_profile.as_mut())
+					{
+						profile.record(function.name(), &stats);
+					}
+
+					if let Some(value) = result.map_err(Trap::new)? {
+						results[0] = util::into_wasmtime_val(value);
+					}
+					Ok(())
+				})
+				.map_err(|error| {
+					WasmError::Other(format!(
+						"failed to register host function '{}' with the WASM linker: {}",
+						function.name(),
+						error
+					))
+				})?;
+		}
+
+		Ok(())
+	}
+}
+
+fn signature_matches(function: &dyn Function, func_ty: &FuncType) -> bool {
+	fn val_type(value_type: &ValueType) -> ValType {
+		match value_type {
+			ValueType::I32 => ValType::I32,
+			ValueType::I64 => ValType::I64,
+			ValueType::F32 => ValType::F32,
+			ValueType::F64 => ValType::F64,
+		}
+	}
+
+	let signature = function.signature();
+	func_ty.params().eq(signature.args.iter().map(val_type)) &&
+		func_ty.results().eq(signature.return_value.iter().map(val_type))
+}
+
 impl<'a, 'b> sp_wasm_interface::HostFunctionRegistry for Registry<'a, 'b> {
 	type State = StoreData;
 	type Error = WasmError;
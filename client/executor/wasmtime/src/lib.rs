@@ -37,6 +37,7 @@ mod util;
 mod tests;
 
 pub use runtime::{
-	create_runtime, create_runtime_from_artifact, prepare_runtime_artifact, Config,
-	DeterministicStackLimit, InstantiationStrategy, Semantics,
+	create_runtime, create_runtime_from_artifact, create_runtime_from_artifact_bytes,
+	prepare_runtime_artifact, wasmtime_version, Config, DeterministicStackLimit,
+	InstantiationStrategy, Semantics,
 };
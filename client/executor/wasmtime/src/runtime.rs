@@ -24,12 +24,16 @@ use crate::{
 	util,
 };
 
-use sc_allocator::FreeingBumpHeapAllocator;
+use codec::Encode;
+use once_cell::sync::OnceCell;
+use sc_allocator::{AllocationStats, FreeingBumpHeapAllocator};
 use sc_executor_common::{
-	error::{Result, WasmError},
+	error::{Error, Result, WasmError},
+	profiling::HostFunctionProfile,
 	runtime_blob::{
 		self, DataSegmentsSnapshot, ExposedMutableGlobalsSet, GlobalsSnapshot, RuntimeBlob,
 	},
+	sandbox::SandboxBackend,
 	wasm_runtime::{InvokeMethod, WasmInstance, WasmModule},
 };
 use sp_runtime_interface::unpack_ptr_and_len;
@@ -174,7 +178,15 @@ impl WasmModule for WasmtimeRuntime {
 			}),
 		};
 
-		Ok(Box::new(WasmtimeInstance { strategy }))
+		Ok(Box::new(WasmtimeInstance {
+			strategy,
+			fuel_metering: self.config.semantics.fuel_metering,
+			fuel: None,
+			host_function_profiling: self.config.host_function_profiling,
+			host_function_profile: None,
+			allocation_stats: None,
+			sandbox_backend: SandboxBackend::TryWasmer,
+		}))
 	}
 }
 
@@ -182,10 +194,24 @@ impl WasmModule for WasmtimeRuntime {
 /// to execute the compiled code.
 pub struct WasmtimeInstance {
 	strategy: Strategy,
+	/// Whether the engine meters the fuel.
+	fuel_metering: bool,
+	/// The fuel budget of the calls.
+	fuel: Option<u64>,
+	/// Whether the host functions are linked to record their calls.
+	host_function_profiling: bool,
+	/// The host function calls recorded, if they are profiled.
+	host_function_profile: Option<HostFunctionProfile>,
+	/// The heap usage of the last call.
+	allocation_stats: Option<AllocationStats>,
+	/// The backend of the sandboxes created by the calls.
+	sandbox_backend: SandboxBackend,
 }
 
 impl WasmInstance for WasmtimeInstance {
 	fn call(&mut self, method: InvokeMethod, data: &[u8]) -> Result<Vec<u8>> {
+		self.allocation_stats = None;
+
 		match &mut self.strategy {
 			Strategy::LegacyInstanceReuse {
 				ref mut instance_wrapper,
@@ -205,7 +231,18 @@ impl WasmInstance for WasmtimeInstance {
 				globals_snapshot.apply(&mut InstanceGlobals { instance: instance_wrapper });
 				let allocator = FreeingBumpHeapAllocator::new(*heap_base);
 
-				let result = perform_call(data, instance_wrapper, entrypoint, allocator);
+				let result =
+					with_fuel(instance_wrapper, self.fuel_metering, self.fuel, |instance| {
+						perform_call(
+							data,
+							instance,
+							entrypoint,
+							allocator,
+							&mut self.host_function_profile,
+							&mut self.allocation_stats,
+							self.sandbox_backend,
+						)
+					});
 
 				// Signal to the OS that we are done with the linear memory and that it can be
 				// reclaimed.
@@ -219,11 +256,43 @@ impl WasmInstance for WasmtimeInstance {
 				let entrypoint = instance_wrapper.resolve_entrypoint(method)?;
 
 				let allocator = FreeingBumpHeapAllocator::new(heap_base);
-				perform_call(data, &mut instance_wrapper, entrypoint, allocator)
+				with_fuel(&mut instance_wrapper, self.fuel_metering, self.fuel, |instance| {
+					perform_call(
+						data,
+						instance,
+						entrypoint,
+						allocator,
+						&mut self.host_function_profile,
+						&mut self.allocation_stats,
+						self.sandbox_backend,
+					)
+				})
 			},
 		}
 	}
 
+	fn set_fuel(&mut self, fuel: Option<u64>) -> Result<()> {
+		self.fuel = fuel;
+		Ok(())
+	}
+
+	fn set_sandbox_backend(&mut self, backend: SandboxBackend) {
+		self.sandbox_backend = backend;
+	}
+
+	fn set_host_function_profiling(&mut self, enabled: bool) {
+		self.host_function_profile =
+			(self.host_function_profiling && enabled).then(Default::default);
+	}
+
+	fn take_host_function_profile(&mut self) -> Option<HostFunctionProfile> {
+		self.host_function_profile.take()
+	}
+
+	fn take_allocation_stats(&mut self) -> Option<AllocationStats> {
+		self.allocation_stats.take()
+	}
+
 	fn get_global_const(&mut self, name: &str) -> Result<Option<Value>> {
 		match &mut self.strategy {
 			Strategy::LegacyInstanceReuse { instance_wrapper, .. } =>
@@ -315,6 +384,7 @@ fn common_config(semantics: &Semantics) -> std::result::Result<wasmtime::Config,
 	}
 
 	config.parallel_compilation(semantics.parallel_compilation);
+	config.consume_fuel(semantics.fuel_metering);
 
 	// Be clear and specific about the extensions we support. If an update brings new features
 	// they should be introduced here as well.
@@ -521,6 +591,44 @@ pub struct Semantics {
 	///
 	/// The default is `None`.
 	pub max_memory_size: Option<usize>,
+
+	/// Configures wasmtime to meter the fuel consumed by the execution, which allows to give each
+	/// call a budget with [`WasmInstance::set_fuel`].
+	///
+	/// Metering slows the execution down, the calls of a runtime compiled without it are not
+	/// limited.
+	pub fuel_metering: bool,
+}
+
+impl Semantics {
+	/// Returns an encoding of the semantics which the code compiled by
+	/// [`prepare_runtime_artifact`] depends on.
+	///
+	/// An artifact must only be loaded with semantics of the same fingerprint as the ones it was
+	/// prepared with.
+	pub fn fingerprint(&self) -> Vec<u8> {
+		let instantiation_strategy: u8 = match self.instantiation_strategy {
+			InstantiationStrategy::PoolingCopyOnWrite => 0,
+			InstantiationStrategy::RecreateInstanceCopyOnWrite => 1,
+			InstantiationStrategy::Pooling => 2,
+			InstantiationStrategy::RecreateInstance => 3,
+			InstantiationStrategy::LegacyInstanceReuse => 4,
+		};
+		let deterministic_stack_limit = self
+			.deterministic_stack_limit
+			.as_ref()
+			.map(|limit| (limit.logical_max, limit.native_stack_max));
+
+		(
+			instantiation_strategy,
+			deterministic_stack_limit,
+			self.canonicalize_nans,
+			self.extra_heap_pages,
+			self.max_memory_size.map(|size| size as u64),
+			self.fuel_metering,
+		)
+			.encode()
+	}
 }
 
 pub struct Config {
@@ -535,6 +643,10 @@ pub struct Config {
 
 	/// Tuning of various semantics of the wasmtime executor.
 	pub semantics: Semantics,
+
+	/// Link the host functions so that the instances can record the host function calls of their
+	/// runtime calls. This makes the host function calls slower.
+	pub host_function_profiling: bool,
 }
 
 enum CodeSupplyMode<'a> {
@@ -549,6 +661,11 @@ enum CodeSupplyMode<'a> {
 	/// We use a `Path` here instead of simply passing a byte slice to allow `wasmtime` to
 	/// map the runtime's linear memory on supported platforms in a copy-on-write fashion.
 	Precompiled(&'a Path),
+
+	/// The runtime is instantiated using a precompiled module held in memory.
+	///
+	/// The same assumptions as for [`CodeSupplyMode::Precompiled`] apply.
+	PrecompiledBytes(&'a [u8]),
 }
 
 /// Create a new `WasmtimeRuntime` given the code. This function performs translation from Wasm to
@@ -593,6 +710,27 @@ where
 	do_create_runtime::<H>(CodeSupplyMode::Precompiled(compiled_artifact_path), config)
 }
 
+/// The same as [`create_runtime_from_artifact`] but takes the precompiled artifact as bytes.
+///
+/// Unlike the file, the bytes can't change once they are checked, at the cost of copying the
+/// module into memory.
+///
+/// # Safety
+///
+/// The caller must ensure that the compiled artifact passed here was produced by
+/// [`prepare_runtime_artifact`] and was not modified.
+///
+/// Failure to adhere to these requirements might lead to crashes and arbitrary code execution.
+pub unsafe fn create_runtime_from_artifact_bytes<H>(
+	compiled_artifact: &[u8],
+	config: Config,
+) -> std::result::Result<WasmtimeRuntime, WasmError>
+where
+	H: HostFunctions,
+{
+	do_create_runtime::<H>(CodeSupplyMode::PrecompiledBytes(compiled_artifact), config)
+}
+
 /// # Safety
 ///
 /// This is only unsafe if called with [`CodeSupplyMode::Artifact`]. See
@@ -649,13 +787,14 @@ where
 					(module, InternalInstantiationStrategy::Builtin),
 			}
 		},
+		CodeSupplyMode::Precompiled(_) | CodeSupplyMode::PrecompiledBytes(_)
+			if config.semantics.instantiation_strategy ==
+				InstantiationStrategy::LegacyInstanceReuse =>
+			return Err(WasmError::Other(
+				"the legacy instance reuse instantiation strategy is incompatible with precompiled modules"
+					.into(),
+			)),
 		CodeSupplyMode::Precompiled(compiled_artifact_path) => {
-			if let InstantiationStrategy::LegacyInstanceReuse =
-				config.semantics.instantiation_strategy
-			{
-				return Err(WasmError::Other("the legacy instance reuse instantiation strategy is incompatible with precompiled modules".into()));
-			}
-
 			// SAFETY: The unsafety of `deserialize_file` is covered by this function. The
 			//         responsibilities to maintain the invariants are passed to the caller.
 			//
@@ -665,10 +804,25 @@ where
 
 			(module, InternalInstantiationStrategy::Builtin)
 		},
+		CodeSupplyMode::PrecompiledBytes(compiled_artifact) => {
+			// SAFETY: The unsafety of `deserialize` is covered by this function. The
+			//         responsibilities to maintain the invariants are passed to the caller.
+			//
+			//         See [`create_runtime_from_artifact_bytes`] for more details.
+			let module = wasmtime::Module::deserialize(&engine, compiled_artifact)
+				.map_err(|e| WasmError::Other(format!("cannot deserialize module: {}", e)))?;
+
+			(module, InternalInstantiationStrategy::Builtin)
+		},
 	};
 
 	let mut linker = wasmtime::Linker::new(&engine);
-	crate::imports::prepare_imports::<H>(&mut linker, &module, config.allow_missing_func_imports)?;
+	crate::imports::prepare_imports::<H>(
+		&mut linker,
+		&module,
+		config.allow_missing_func_imports,
+		config.host_function_profiling,
+	)?;
 
 	let mut store =
 		crate::instance_wrapper::create_store(module.engine(), config.semantics.max_memory_size);
@@ -714,6 +868,30 @@ fn prepare_blob_for_compilation(
 	Ok(blob)
 }
 
+/// Returns the version of wasmtime compiling the runtime artifacts, as written by wasmtime in the
+/// header of the modules it precompiles, or `None` if it can't be read from there.
+///
+/// Artifacts are only loadable by the version of wasmtime that prepared them.
+pub fn wasmtime_version() -> Option<&'static str> {
+	static VERSION: OnceCell<Option<String>> = OnceCell::new();
+
+	VERSION
+		.get_or_init(|| {
+			// The smallest valid module: the magic number and the version of the binary format.
+			const EMPTY_MODULE: &[u8] = b"\0asm\x01\0\0\0";
+			// Precompiled modules hold this header after the compiled code, followed by the length
+			// of the version of wasmtime and the version itself.
+			const HEADER: &[u8] = b"\0wasmtime-aot";
+
+			let module = Engine::default().precompile_module(EMPTY_MODULE).ok()?;
+			let start = module.windows(HEADER.len()).rposition(|window| window == HEADER)?;
+			let (len, rest) = module[start + HEADER.len()..].split_first()?;
+			let version = rest.get(..*len as usize)?;
+			String::from_utf8(version.to_vec()).ok()
+		})
+		.as_deref()
+}
+
 /// Takes a [`RuntimeBlob`] and precompiles it returning the serialized result of compilation. It
 /// can then be used for calling [`create_runtime`] avoiding long compilation times.
 pub fn prepare_runtime_artifact(
@@ -730,15 +908,55 @@ pub fn prepare_runtime_artifact(
 		.map_err(|e| WasmError::Other(format!("cannot precompile module: {}", e)))
 }
 
+/// The fuel given to the calls without a budget when the engine meters the fuel.
+const UNLIMITED_FUEL: u64 = 1 << 62;
+
+/// Perform `call` with the store of `instance_wrapper` refueled to the budget `fuel`.
+///
+/// A failed call that consumed all of its budget is reported as [`Error::OutOfFuel`].
+fn with_fuel<R>(
+	instance_wrapper: &mut InstanceWrapper,
+	fuel_metering: bool,
+	fuel: Option<u64>,
+	call: impl FnOnce(&mut InstanceWrapper) -> Result<R>,
+) -> Result<R> {
+	if !fuel_metering {
+		return call(instance_wrapper)
+	}
+
+	fn fuel_error(error: impl std::fmt::Display) -> Error {
+		Error::Other(format!("cannot set the fuel: {}", error))
+	}
+
+	let store = instance_wrapper.store_mut();
+	let budget = fuel.unwrap_or(UNLIMITED_FUEL);
+	let left = store.consume_fuel(0).map_err(fuel_error)?;
+	if left < budget {
+		store.add_fuel(budget - left).map_err(fuel_error)?;
+	} else {
+		store.consume_fuel(left - budget).map_err(fuel_error)?;
+	}
+
+	call(instance_wrapper).map_err(|error| match fuel {
+		Some(budget)
+			if instance_wrapper.store_mut().consume_fuel(0).map_or(false, |left| left == 0) =>
+			Error::OutOfFuel(budget),
+		_ => error,
+	})
+}
+
 fn perform_call(
 	data: &[u8],
 	instance_wrapper: &mut InstanceWrapper,
 	entrypoint: EntryPoint,
 	mut allocator: FreeingBumpHeapAllocator,
+	host_function_profile: &mut Option<HostFunctionProfile>,
+	allocation_stats: &mut Option<AllocationStats>,
+	sandbox_backend: SandboxBackend,
 ) -> Result<Vec<u8>> {
 	let (data_ptr, data_len) = inject_input_data(instance_wrapper, &mut allocator, data)?;
 
-	let host_state = HostState::new(allocator);
+	let host_state = HostState::new(allocator, host_function_profile.take(), sandbox_backend);
 
 	// Set the host state before calling into wasm.
 	instance_wrapper.store_mut().data_mut().host_state = Some(host_state);
@@ -748,7 +966,9 @@ fn perform_call(
 		.map(unpack_ptr_and_len);
 
 	// Reset the host state
-	instance_wrapper.store_mut().data_mut().host_state = None;
+	let host_state = instance_wrapper.store_mut().data_mut().host_state.take();
+	*allocation_stats = host_state.as_ref().map(|host_state| host_state.allocation_stats());
+	*host_function_profile = host_state.and_then(|host_state| host_state.host_function_profile);
 
 	let (output_ptr, output_len) = ret?;
 	let output = extract_output_data(instance_wrapper, output_ptr, output_len)?;
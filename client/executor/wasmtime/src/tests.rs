@@ -80,6 +80,8 @@ struct RuntimeBuilder {
 	extra_heap_pages: u64,
 	max_memory_size: Option<usize>,
 	precompile_runtime: bool,
+	fuel_metering: bool,
+	host_function_profiling: bool,
 	tmpdir: Option<tempfile::TempDir>,
 }
 
@@ -93,6 +95,8 @@ impl RuntimeBuilder {
 			extra_heap_pages: 1024,
 			max_memory_size: None,
 			precompile_runtime: false,
+			fuel_metering: false,
+			host_function_profiling: false,
 			tmpdir: None,
 		}
 	}
@@ -122,6 +126,16 @@ impl RuntimeBuilder {
 		self
 	}
 
+	fn fuel_metering(mut self, fuel_metering: bool) -> Self {
+		self.fuel_metering = fuel_metering;
+		self
+	}
+
+	fn host_function_profiling(mut self, host_function_profiling: bool) -> Self {
+		self.host_function_profiling = host_function_profiling;
+		self
+	}
+
 	fn build<'a>(&'a mut self) -> impl WasmModule + 'a {
 		let blob = {
 			let wasm: Vec<u8>;
@@ -154,7 +168,9 @@ impl RuntimeBuilder {
 				parallel_compilation: true,
 				extra_heap_pages: self.extra_heap_pages,
 				max_memory_size: self.max_memory_size,
+				fuel_metering: self.fuel_metering,
 			},
+			host_function_profiling: self.host_function_profiling,
 		};
 
 		if self.precompile_runtime {
@@ -232,6 +248,85 @@ fn test_stack_depth_reaching(instantiation_strategy: InstantiationStrategy) {
 	}
 }
 
+test_wasm_execution!(test_fuel_metering);
+fn test_fuel_metering(instantiation_strategy: InstantiationStrategy) {
+	let mut builder = RuntimeBuilder::new(instantiation_strategy)
+		.use_wat(
+			r#"
+			(module
+				(import "env" "memory" (memory $0 1))
+				(global (export "__heap_base") i32 (i32.const 0))
+				(func (export "main")
+					(param i32 i32) (result i64)
+					(loop $loop (br $loop))
+					(i64.const 0)
+				)
+				(func (export "noop")
+					(param i32 i32) (result i64)
+					(i64.const 0)
+				)
+			)
+			"#
+			.to_string(),
+		)
+		.fuel_metering(true);
+
+	let runtime = builder.build();
+	let mut instance = runtime.new_instance().expect("failed to instantiate a runtime");
+
+	instance.set_fuel(Some(10_000)).unwrap();
+	match instance.call_export("main", &[]).unwrap_err() {
+		Error::OutOfFuel(budget) => assert_eq!(budget, 10_000),
+		error => panic!("unexpected error: {:?}", error),
+	}
+
+	// The fuel is refilled for every call.
+	instance.call_export("noop", &[]).unwrap();
+	instance.call_export("noop", &[]).unwrap();
+}
+
+test_wasm_execution!(test_host_function_profiling);
+fn test_host_function_profiling(instantiation_strategy: InstantiationStrategy) {
+	let wat = r#"
+		(module
+			(import "env" "memory" (memory $0 1))
+			(import "env" "ext_allocator_malloc_version_1" (func $malloc (param i32) (result i32)))
+			(import "env" "ext_allocator_free_version_1" (func $free (param i32)))
+			(global (export "__heap_base") i32 (i32.const 0))
+			(func (export "main")
+				(param i32 i32) (result i64)
+				(call $free (call $malloc (i32.const 16)))
+				(call $free (call $malloc (i32.const 16)))
+				(i64.const 0)
+			)
+		)
+		"#;
+
+	let mut builder = RuntimeBuilder::new(instantiation_strategy)
+		.use_wat(wat.to_string())
+		.host_function_profiling(true);
+	let runtime = builder.build();
+	let mut instance = runtime.new_instance().expect("failed to instantiate a runtime");
+
+	instance.call_export("main", &[]).unwrap();
+	assert!(instance.take_host_function_profile().is_none());
+
+	instance.set_host_function_profiling(true);
+	instance.call_export("main", &[]).unwrap();
+	let profile = instance.take_host_function_profile().unwrap();
+	assert_eq!(profile.host_functions.len(), 2);
+	assert_eq!(profile.host_functions["ext_allocator_malloc_version_1"].calls, 2);
+	assert_eq!(profile.host_functions["ext_allocator_free_version_1"].calls, 2);
+
+	// The runtime calls are not recorded unless the host functions are linked for it.
+	let mut builder = RuntimeBuilder::new(instantiation_strategy).use_wat(wat.to_string());
+	let runtime = builder.build();
+	let mut instance = runtime.new_instance().expect("failed to instantiate a runtime");
+	instance.set_host_function_profiling(true);
+	instance.call_export("main", &[]).unwrap();
+	assert!(instance.take_host_function_profile().is_none());
+}
+
 test_wasm_execution!(test_max_memory_pages_imported_memory_without_precompilation);
 fn test_max_memory_pages_imported_memory_without_precompilation(
 	instantiation_strategy: InstantiationStrategy,
@@ -461,7 +556,9 @@ fn test_instances_without_reuse_are_not_leaked() {
 				parallel_compilation: true,
 				extra_heap_pages: 2048,
 				max_memory_size: None,
+				fuel_metering: false,
 			},
+			host_function_profiling: false,
 		},
 	)
 	.unwrap();
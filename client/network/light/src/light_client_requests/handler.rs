@@ -43,6 +43,9 @@ use std::{marker::PhantomData, sync::Arc};
 
 const LOG_TARGET: &str = "light-client-request-handler";
 
+/// Maximum number of storage entries proven in response to a range request.
+const MAX_RANGE_ENTRIES: u32 = 1000;
+
 /// Handler for incoming light client requests from a remote peer.
 pub struct LightClientRequestHandler<B, Client> {
 	request_receiver: mpsc::Receiver<IncomingRequest>,
@@ -145,6 +148,8 @@ where
 				self.on_remote_read_child_request(&peer, r)?,
 			Some(schema::v1::light::request::Request::RemoteChangesRequest(_r)) =>
 				return Err(HandleRequestError::BadRequest("Not supported.")),
+			Some(schema::v1::light::request::Request::RemoteReadRangeRequest(r)) =>
+				self.on_remote_read_range_request(&peer, r)?,
 			None =>
 				return Err(HandleRequestError::BadRequest("Remote request without request data.")),
 		};
@@ -287,6 +292,64 @@ where
 
 		Ok(schema::v1::light::Response { response: Some(response) })
 	}
+
+	fn on_remote_read_range_request(
+		&mut self,
+		peer: &PeerId,
+		request: &schema::v1::light::RemoteReadRangeRequest,
+	) -> Result<schema::v1::light::Response, HandleRequestError> {
+		trace!(
+			"Remote read range request from {} ({} {} at {:?}).",
+			peer,
+			HexDisplay::from(&request.storage_key),
+			fmt_keys(Some(&request.start), Some(&request.end)),
+			request.block,
+		);
+
+		let block = Decode::decode(&mut request.block.as_ref())?;
+
+		let child_info = if request.storage_key.is_empty() {
+			Ok(None)
+		} else {
+			let prefixed_key = PrefixedStorageKey::new_ref(&request.storage_key);
+			match ChildType::from_prefixed_key(prefixed_key) {
+				Some((ChildType::ParentKeyId, storage_key)) =>
+					Ok(Some(ChildInfo::new_default(storage_key))),
+				None => Err(sp_blockchain::Error::InvalidChildStorageKey),
+			}
+		};
+		let non_empty = |key: &Vec<u8>| (!key.is_empty()).then(|| key.as_slice());
+		let proof = match child_info.and_then(|child_info| {
+			self.client.read_complete_range_proof(
+				&BlockId::Hash(block),
+				child_info.as_ref(),
+				non_empty(&request.prefix),
+				non_empty(&request.start),
+				non_empty(&request.end),
+				MAX_RANGE_ENTRIES,
+			)
+		}) {
+			Ok(proof) => proof,
+			Err(error) => {
+				trace!(
+					"remote read range request from {} ({} {} at {:?}) failed with: {}",
+					peer,
+					HexDisplay::from(&request.storage_key),
+					fmt_keys(Some(&request.start), Some(&request.end)),
+					request.block,
+					error,
+				);
+				StorageProof::empty()
+			},
+		};
+
+		let response = {
+			let r = schema::v1::light::RemoteReadResponse { proof: proof.encode() };
+			schema::v1::light::response::Response::RemoteReadResponse(r)
+		};
+
+		Ok(schema::v1::light::Response { response: Some(response) })
+	}
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -42,7 +42,7 @@ use prost::Message;
 use sc_client_api::{BlockBackend, ProofProvider};
 use sc_consensus::import_queue::{IncomingBlock, Origin};
 use sc_network_common::{config::ProtocolId, request_responses::ProtocolConfig};
-use sc_peerset::PeersetHandle;
+use sc_peerset::{DiscoverySource, PeersetHandle};
 use sp_blockchain::{HeaderBackend, HeaderMetadata};
 use sp_consensus::BlockOrigin;
 use sp_runtime::{
@@ -52,7 +52,6 @@ use sp_runtime::{
 use std::{
 	borrow::Cow,
 	collections::{HashSet, VecDeque},
-	iter,
 	task::{Context, Poll},
 	time::Duration,
 };
@@ -534,10 +533,15 @@ where
 			listen_addrs.truncate(30);
 		}
 
+		let first_addr = listen_addrs.first().cloned();
 		for addr in listen_addrs {
 			self.discovery.add_self_reported_address(&peer_id, protocols.iter(), addr);
 		}
-		self.substrate.add_default_set_discovered_nodes(iter::once(peer_id));
+		self.substrate.add_default_set_discovered_node(
+			peer_id,
+			DiscoverySource::Connection,
+			first_addr,
+		);
 	}
 }
 
@@ -560,8 +564,8 @@ where
 				// `PeerInfoBehaviour`. See the `NetworkBehaviourEventProcess`
 				// implementation for `PeerInfoEvent`.
 			},
-			DiscoveryOut::Discovered(peer_id) => {
-				self.substrate.add_default_set_discovered_nodes(iter::once(peer_id));
+			DiscoveryOut::Discovered(peer_id, source, address) => {
+				self.substrate.add_default_set_discovered_node(peer_id, source, address);
 			},
 			DiscoveryOut::ValueFound(results, duration) => {
 				self.events
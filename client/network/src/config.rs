@@ -28,6 +28,7 @@ pub use sc_network_common::{
 	},
 };
 pub use sc_network_sync::warp_request_handler::WarpSyncProvider;
+pub use sc_peerset::IpLimits;
 
 pub use libp2p::{build_multiaddr, core::PublicKey, identity};
 
@@ -416,6 +417,9 @@ pub struct NetworkConfiguration {
 	pub default_peers_set_num_full: u32,
 	/// Configuration for extra sets of nodes.
 	pub extra_sets: Vec<NonDefaultSetConfig>,
+	/// Limits on the number of peers sharing an IP address or a subnet, across all sets.
+	/// Reserved nodes aren't subject to these limits.
+	pub ip_limits: IpLimits,
 	/// Client identifier. Sent over the wire for debugging purposes.
 	pub client_version: String,
 	/// Name of the node. Sent over the wire for debugging purposes.
@@ -482,9 +486,14 @@ impl NetworkConfiguration {
 			default_peers_set_num_full: default_peers_set.in_peers + default_peers_set.out_peers,
 			default_peers_set,
 			extra_sets: Vec::new(),
+			ip_limits: IpLimits::default(),
 			client_version: client_version.into(),
 			node_name: node_name.into(),
-			transport: TransportConfig::Normal { enable_mdns: false, allow_private_ipv4: true },
+			transport: TransportConfig::Normal {
+				enable_mdns: false,
+				allow_private_ipv4: true,
+				websocket_tls: None,
+			},
 			max_parallel_downloads: 5,
 			sync_mode: SyncMode::Full,
 			enable_dht_random_walk: true,
@@ -625,6 +634,12 @@ pub enum TransportConfig {
 		/// [RFC1918](https://tools.ietf.org/html/rfc1918)). Irrelevant for addresses that have
 		/// been passed in [`NetworkConfiguration::boot_nodes`].
 		allow_private_ipv4: bool,
+
+		/// TLS certificate and key to accept connections on `/wss` listen addresses with.
+		///
+		/// Without it, listening on `/wss` addresses is not possible. Dialing `/wss` addresses
+		/// is always supported.
+		websocket_tls: Option<WebSocketTlsConfig>,
 	},
 
 	/// Only allow connections within the same process.
@@ -632,6 +647,18 @@ pub enum TransportConfig {
 	MemoryOnly,
 }
 
+/// TLS configuration of secure WebSocket (`/wss`) listeners.
+///
+/// Both files are watched for modifications. When either of them changes, the `/wss` listeners
+/// are restarted with the new certificate, without affecting established connections.
+#[derive(Clone, Debug)]
+pub struct WebSocketTlsConfig {
+	/// PEM file containing the certificate chain, leaf certificate first.
+	pub certificate: PathBuf,
+	/// PEM file containing the private key of the leaf certificate, in PKCS#8 or RSA format.
+	pub private_key: PathBuf,
+}
+
 /// The policy for connections to non-reserved peers.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum NonReservedPeerMode {
@@ -73,6 +73,7 @@ use libp2p::{
 };
 use log::{debug, error, info, trace, warn};
 use sc_network_common::config::ProtocolId;
+use sc_peerset::DiscoverySource;
 use sp_core::hexdisplay::HexDisplay;
 use std::{
 	cmp,
@@ -308,7 +309,11 @@ impl DiscoveryBehaviour {
 				k.add_address(&peer_id, addr.clone());
 			}
 
-			self.pending_events.push_back(DiscoveryOut::Discovered(peer_id));
+			self.pending_events.push_back(DiscoveryOut::Discovered(
+				peer_id,
+				DiscoverySource::Other,
+				Some(addr.clone()),
+			));
 			addrs_list.push(addr);
 		}
 	}
@@ -462,7 +467,9 @@ pub enum DiscoveryOut {
 	/// configured. If the peer is to be included in the routing table, it must
 	/// be explicitly added via
 	/// [`DiscoveryBehaviour::add_self_reported_address`].
-	Discovered(PeerId),
+	///
+	/// Also carries how the peer has been discovered and, if known, one of its addresses.
+	Discovered(PeerId, DiscoverySource, Option<Multiaddr>),
 
 	/// A peer connected to this node for whom no listen address is known.
 	///
@@ -760,16 +767,21 @@ impl NetworkBehaviour for DiscoveryBehaviour {
 			while let Poll::Ready(ev) = kademlia.poll(cx, params) {
 				match ev {
 					NetworkBehaviourAction::GenerateEvent(ev) => match ev {
-						KademliaEvent::RoutingUpdated { peer, .. } => {
-							let ev = DiscoveryOut::Discovered(peer);
+						KademliaEvent::RoutingUpdated { peer, addresses, .. } => {
+							let ev = DiscoveryOut::Discovered(
+								peer,
+								DiscoverySource::Dht,
+								Some(addresses.first().clone()),
+							);
 							return Poll::Ready(NetworkBehaviourAction::GenerateEvent(ev))
 						},
 						KademliaEvent::UnroutablePeer { peer, .. } => {
 							let ev = DiscoveryOut::UnroutablePeer(peer);
 							return Poll::Ready(NetworkBehaviourAction::GenerateEvent(ev))
 						},
-						KademliaEvent::RoutablePeer { peer, .. } => {
-							let ev = DiscoveryOut::Discovered(peer);
+						KademliaEvent::RoutablePeer { peer, address } => {
+							let ev =
+								DiscoveryOut::Discovered(peer, DiscoverySource::Dht, Some(address));
 							return Poll::Ready(NetworkBehaviourAction::GenerateEvent(ev))
 						},
 						KademliaEvent::PendingRoutablePeer { .. } |
@@ -922,8 +934,9 @@ impl NetworkBehaviour for DiscoveryBehaviour {
 							continue
 						}
 
-						self.pending_events
-							.extend(list.map(|(peer_id, _)| DiscoveryOut::Discovered(peer_id)));
+						self.pending_events.extend(list.map(|(peer_id, addr)| {
+							DiscoveryOut::Discovered(peer_id, DiscoverySource::Mdns, Some(addr))
+						}));
 						if let Some(ev) = self.pending_events.pop_front() {
 							return Poll::Ready(NetworkBehaviourAction::GenerateEvent(ev))
 						}
@@ -1083,7 +1096,7 @@ mod tests {
 								SwarmEvent::Behaviour(behavior) => {
 									match behavior {
 										DiscoveryOut::UnroutablePeer(other) |
-										DiscoveryOut::Discovered(other) => {
+										DiscoveryOut::Discovered(other, ..) => {
 											// Call `add_self_reported_address` to simulate identify
 											// happening.
 											let addr = swarms
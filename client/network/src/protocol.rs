@@ -311,6 +311,7 @@ where
 		block_announce_validator: Box<dyn BlockAnnounceValidator<B> + Send>,
 		metrics_registry: Option<&Registry>,
 		warp_sync_provider: Option<Arc<dyn WarpSyncProvider<B>>>,
+		anchors: &[config::MultiaddrWithPeerId],
 	) -> error::Result<(Protocol<B, Client>, sc_peerset::PeersetHandle, Vec<(PeerId, Multiaddr)>)> {
 		let info = chain.info();
 		let sync = ChainSync::new(
@@ -362,6 +363,10 @@ where
 				}
 			}
 
+			for anchor in anchors {
+				known_addresses.push((anchor.peer_id, anchor.multiaddr.clone()));
+			}
+
 			let mut bootnodes = Vec::with_capacity(network_config.boot_nodes.len());
 			for bootnode in network_config.boot_nodes.iter() {
 				bootnodes.push(bootnode.peer_id);
@@ -375,6 +380,7 @@ where
 				reserved_nodes: default_sets_reserved.clone(),
 				reserved_only: network_config.default_peers_set.non_reserved_mode ==
 					config::NonReservedPeerMode::Deny,
+				anchors: anchors.iter().map(|anchor| anchor.peer_id).collect(),
 			});
 
 			for set_cfg in &network_config.extra_sets {
@@ -393,10 +399,14 @@ where
 					bootnodes: Vec::new(),
 					reserved_nodes,
 					reserved_only,
+					anchors: Vec::new(),
 				});
 			}
 
-			sc_peerset::Peerset::from_config(sc_peerset::PeersetConfig { sets })
+			sc_peerset::Peerset::from_config(sc_peerset::PeersetConfig {
+				sets,
+				ip_limits: network_config.ip_limits,
+			})
 		};
 
 		let block_announces_protocol: Cow<'static, str> =
@@ -483,6 +493,17 @@ where
 		self.behaviour.num_discovered_peers()
 	}
 
+	/// Returns the number of peers refused because of the peerset IP limits since the last call.
+	pub fn take_ip_limit_rejections(&mut self) -> sc_peerset::IpLimitRejections {
+		self.behaviour.take_ip_limit_rejections()
+	}
+
+	/// Returns the longest-lived outgoing peers of the default set, to be connected to first after
+	/// a restart.
+	pub fn default_set_anchors(&mut self) -> Vec<PeerId> {
+		self.behaviour.anchors(HARDCODED_PEERSETS_SYNC)
+	}
+
 	/// Disconnects the given peer if we are connected to it.
 	pub fn disconnect_peer(&mut self, peer_id: &PeerId, protocol_name: &str) {
 		if let Some(position) = self.notification_protocols.iter().position(|p| *p == protocol_name)
@@ -1192,13 +1213,22 @@ where
 		}
 	}
 
-	/// Notify the protocol that we have learned about the existence of nodes on the default set.
+	/// Notify the protocol that we have learned about the existence of a node on the default set,
+	/// along with how it has been discovered and, if known, one of its addresses.
 	///
-	/// Can be called multiple times with the same `PeerId`s.
-	pub fn add_default_set_discovered_nodes(&mut self, peer_ids: impl Iterator<Item = PeerId>) {
-		for peer_id in peer_ids {
-			self.peerset_handle.add_to_peers_set(HARDCODED_PEERSETS_SYNC, peer_id);
-		}
+	/// Can be called multiple times with the same `PeerId`.
+	pub fn add_default_set_discovered_node(
+		&mut self,
+		peer_id: PeerId,
+		source: sc_peerset::DiscoverySource,
+		address: Option<Multiaddr>,
+	) {
+		self.peerset_handle.add_discovered_to_peers_set(
+			HARDCODED_PEERSETS_SYNC,
+			peer_id,
+			source,
+			address,
+		);
 	}
 
 	/// Add a peer to a peers set.
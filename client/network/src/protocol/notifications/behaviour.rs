@@ -401,6 +401,17 @@ impl Notifications {
 		self.peerset.num_discovered_peers()
 	}
 
+	/// Returns the number of peers refused because of the peerset IP limits since the last call.
+	pub fn take_ip_limit_rejections(&mut self) -> sc_peerset::IpLimitRejections {
+		self.peerset.take_ip_limit_rejections()
+	}
+
+	/// Returns the longest-lived outgoing peers of the given set, see
+	/// [`sc_peerset::Peerset::anchors`].
+	pub fn anchors(&mut self, set_id: sc_peerset::SetId) -> Vec<PeerId> {
+		self.peerset.anchors(set_id)
+	}
+
 	/// Returns the list of all the peers we have an open channel to.
 	pub fn open_peers(&self) -> impl Iterator<Item = &PeerId> {
 		self.peers.iter().filter(|(_, state)| state.is_open()).map(|((id, _), _)| id)
@@ -1067,8 +1078,13 @@ impl NetworkBehaviour for Notifications {
 		conn: &ConnectionId,
 		endpoint: &ConnectedPoint,
 		_failed_addresses: Option<&Vec<Multiaddr>>,
-		_other_established: usize,
+		other_established: usize,
 	) {
+		// The peerset needs to know the address of the peer in order to enforce its IP limits.
+		if other_established == 0 {
+			self.peerset.connection_established(*peer_id, endpoint.get_remote_address());
+		}
+
 		for set_id in (0..self.notif_protocols.len()).map(sc_peerset::SetId::from) {
 			match self.peers.entry((*peer_id, set_id)).or_insert(PeerState::Poisoned) {
 				// Requested | PendingRequest => Enabled
@@ -1127,8 +1143,12 @@ impl NetworkBehaviour for Notifications {
 		conn: &ConnectionId,
 		_endpoint: &ConnectedPoint,
 		_handler: <Self::ConnectionHandler as IntoConnectionHandler>::Handler,
-		_remaining_established: usize,
+		remaining_established: usize,
 	) {
+		if remaining_established == 0 {
+			self.peerset.connection_closed(peer_id);
+		}
+
 		for set_id in (0..self.notif_protocols.len()).map(sc_peerset::SetId::from) {
 			let mut entry = if let Entry::Occupied(entry) = self.peers.entry((*peer_id, set_id)) {
 				entry
@@ -74,7 +74,9 @@ fn build_nodes() -> (Swarm<CustomProtoWithAddr>, Swarm<CustomProtoWithAddr>) {
 				},
 				reserved_nodes: Default::default(),
 				reserved_only: false,
+				anchors: Vec::new(),
 			}],
+			ip_limits: sc_peerset::IpLimits::default(),
 		});
 
 		let behaviour = CustomProtoWithAddr {
@@ -957,7 +957,7 @@ mod tests {
 		swarm::{Swarm, SwarmEvent},
 		Multiaddr,
 	};
-	use sc_peerset::{Peerset, PeersetConfig, SetConfig};
+	use sc_peerset::{IpLimits, Peerset, PeersetConfig, SetConfig};
 	use std::{iter, time::Duration};
 
 	fn build_swarm(
@@ -981,7 +981,9 @@ mod tests {
 				bootnodes: vec![],
 				reserved_nodes: Default::default(),
 				reserved_only: false,
+				anchors: Vec::new(),
 			}],
+			ip_limits: IpLimits::default(),
 		};
 
 		let (peerset, handle) = Peerset::from_config(config);
@@ -30,7 +30,7 @@
 use crate::{
 	behaviour::{self, Behaviour, BehaviourOut},
 	bitswap::Bitswap,
-	config::{parse_str_addr, Params, TransportConfig},
+	config::{parse_str_addr, MultiaddrWithPeerId, Params, TransportConfig},
 	discovery::DiscoveryConfig,
 	error::Error,
 	network_state::{
@@ -45,8 +45,9 @@ use crate::{
 
 use codec::Encode as _;
 use futures::{channel::oneshot, prelude::*};
+use futures_timer::Delay;
 use libp2p::{
-	core::{either::EitherError, upgrade, ConnectedPoint, Executor},
+	core::{connection::ListenerId, either::EitherError, upgrade, ConnectedPoint, Executor},
 	multiaddr,
 	ping::Failure as PingFailure,
 	swarm::{
@@ -72,6 +73,7 @@ use std::{
 	fs, iter,
 	marker::PhantomData,
 	num::NonZeroUsize,
+	path::PathBuf,
 	pin::Pin,
 	str,
 	sync::{
@@ -85,6 +87,7 @@ pub use behaviour::{
 	IfDisconnected, InboundFailure, OutboundFailure, RequestFailure, ResponseFailure,
 };
 
+mod anchors;
 mod metrics;
 mod out_events;
 mod signature;
@@ -202,6 +205,14 @@ where
 
 		let default_notif_handshake_message = Roles::from(&params.role).encode();
 
+		// Nodes we had a long-lived outgoing connection with before restarting.
+		let anchors_path = params
+			.network_config
+			.net_config_path
+			.as_ref()
+			.map(|path| path.join(anchors::ANCHORS_FILE));
+		let saved_anchors = anchors_path.as_deref().map(anchors::load).unwrap_or_default();
+
 		let (warp_sync_provider, warp_sync_protocol_config) = match params.warp_sync {
 			Some((p, c)) => (Some(p), Some(c)),
 			None => (None, None),
@@ -225,6 +236,7 @@ where
 			params.block_announce_validator,
 			params.metrics_registry.as_ref(),
 			warp_sync_provider,
+			&saved_anchors,
 		)?;
 
 		// List of multiaddresses that we know in the network.
@@ -262,6 +274,12 @@ where
 
 		// Build the swarm.
 		let client = params.chain.clone();
+		let websocket_tls = match &params.network_config.transport {
+			TransportConfig::Normal { websocket_tls: Some(config), .. } =>
+				Some(transport::WebSocketTls::new(config.clone())?),
+			_ => None,
+		};
+
 		let (mut swarm, bandwidth): (Swarm<Behaviour<B, Client>>, _) = {
 			let user_agent = format!(
 				"{} ({})",
@@ -343,6 +361,7 @@ where
 					config_mem,
 					params.network_config.yamux_window_size,
 					yamux_maximum_buffer_size,
+					websocket_tls.as_ref(),
 				)
 			};
 
@@ -406,9 +425,15 @@ where
 		};
 
 		// Listen on multiaddresses.
+		let mut secure_websocket_listeners = Vec::new();
 		for addr in &params.network_config.listen_addresses {
-			if let Err(err) = Swarm::<Behaviour<B, Client>>::listen_on(&mut swarm, addr.clone()) {
-				warn!(target: "sub-libp2p", "Can't listen on {} because: {:?}", addr, err)
+			match Swarm::<Behaviour<B, Client>>::listen_on(&mut swarm, addr.clone()) {
+				Ok(id) =>
+					if addr.iter().any(|p| matches!(p, multiaddr::Protocol::Wss(_))) {
+						secure_websocket_listeners.push((id, addr.clone()));
+					},
+				Err(err) =>
+					warn!(target: "sub-libp2p", "Can't listen on {} because: {:?}", addr, err),
 			}
 		}
 
@@ -461,6 +486,11 @@ where
 			tx_handler_controller,
 			metrics,
 			boot_node_ids,
+			websocket_tls,
+			secure_websocket_listeners,
+			anchors_path,
+			next_anchors_save: Delay::new(anchors::SAVE_INTERVAL),
+			saved_anchors: saved_anchors.into_iter().map(|anchor| anchor.peer_id).collect(),
 		})
 	}
 
@@ -1499,6 +1529,16 @@ where
 	peers_notifications_sinks: Arc<Mutex<HashMap<(PeerId, Cow<'static, str>), NotificationsSink>>>,
 	/// Controller for the handler of incoming and outgoing transactions.
 	tx_handler_controller: transactions::TransactionsHandlerController<H>,
+	/// TLS configuration of the `/wss` listeners, if any.
+	websocket_tls: Option<transport::WebSocketTls>,
+	/// Listeners on `/wss` addresses, restarted whenever the TLS configuration is reloaded.
+	secure_websocket_listeners: Vec<(ListenerId, Multiaddr)>,
+	/// Where the anchors of the default peer set are persisted, if anywhere.
+	anchors_path: Option<PathBuf>,
+	/// When to check whether the anchors have changed and need to be persisted.
+	next_anchors_save: Delay,
+	/// Anchors persisted the last time.
+	saved_anchors: Vec<PeerId>,
 }
 
 impl<B, H, Client> Future for NetworkWorker<B, H, Client>
@@ -1956,11 +1996,19 @@ where
 						}
 					}
 				},
-				Poll::Ready(SwarmEvent::NewListenAddr { address, .. }) => {
+				Poll::Ready(SwarmEvent::NewListenAddr { listener_id, address }) => {
 					trace!(target: "sub-libp2p", "Libp2p => NewListenAddr({})", address);
 					if let Some(metrics) = this.metrics.as_ref() {
 						metrics.listeners_local_addresses.inc();
 					}
+					// Restart `/wss` listeners on the port they were assigned, not on a new one.
+					if let Some((_, addr)) = this
+						.secure_websocket_listeners
+						.iter_mut()
+						.find(|(id, _)| *id == listener_id)
+					{
+						*addr = with_assigned_port(addr, &address);
+					}
 				},
 				Poll::Ready(SwarmEvent::ExpiredListenAddr { address, .. }) => {
 					info!(target: "sub-libp2p", "📪 No longer listening on {}", address);
@@ -2087,6 +2135,62 @@ where
 			};
 		}
 
+		// Restart the `/wss` listeners so that they pick up a reloaded TLS certificate.
+		if let Some(websocket_tls) = this.websocket_tls.as_mut() {
+			while websocket_tls.poll_reload(cx).is_ready() {
+				for (id, addr) in this.secure_websocket_listeners.iter_mut() {
+					let _ = Swarm::<Behaviour<B, Client>>::remove_listener(
+						&mut this.network_service,
+						*id,
+					);
+					match Swarm::<Behaviour<B, Client>>::listen_on(
+						&mut this.network_service,
+						addr.clone(),
+					) {
+						Ok(new_id) => *id = new_id,
+						Err(err) => warn!(
+							target: "sub-libp2p",
+							"Can't listen on {} after reloading the TLS certificate because: {:?}",
+							addr,
+							err,
+						),
+					}
+				}
+			}
+		}
+
+		// Persist the anchors whenever they change, so that they are connected to first after a
+		// restart.
+		if let Some(path) = this.anchors_path.as_ref() {
+			while this.next_anchors_save.poll_unpin(cx).is_ready() {
+				this.next_anchors_save = Delay::new(anchors::SAVE_INTERVAL);
+
+				let anchors =
+					this.network_service.behaviour_mut().user_protocol_mut().default_set_anchors();
+				if anchors == this.saved_anchors {
+					continue
+				}
+
+				let behaviour = this.network_service.behaviour();
+				let addresses = anchors
+					.iter()
+					.filter_map(|peer_id| {
+						let mut multiaddr = match behaviour.node(peer_id)?.endpoint()? {
+							ConnectedPoint::Dialer { address, .. } => address.clone(),
+							ConnectedPoint::Listener { .. } => return None,
+						};
+						if matches!(multiaddr.iter().last(), Some(multiaddr::Protocol::P2p(_))) {
+							multiaddr.pop();
+						}
+						Some(MultiaddrWithPeerId { multiaddr, peer_id: *peer_id })
+					})
+					.collect::<Vec<_>>();
+
+				anchors::save(path, &addresses);
+				this.saved_anchors = anchors;
+			}
+		}
+
 		let num_connected_peers =
 			this.network_service.behaviour_mut().user_protocol_mut().num_connected_peers();
 
@@ -2139,6 +2243,19 @@ where
 				.peerset_num_discovered
 				.set(this.network_service.behaviour_mut().user_protocol().num_discovered_peers()
 					as u64);
+			let ip_limit_rejections = this
+				.network_service
+				.behaviour_mut()
+				.user_protocol_mut()
+				.take_ip_limit_rejections();
+			metrics
+				.peerset_ip_limit_rejections_total
+				.with_label_values(&["in"])
+				.inc_by(ip_limit_rejections.inbound);
+			metrics
+				.peerset_ip_limit_rejections_total
+				.with_label_values(&["out"])
+				.inc_by(ip_limit_rejections.outbound);
 			metrics.pending_connections.set(
 				Swarm::network_info(&this.network_service).connection_counters().num_pending()
 					as u64,
@@ -2254,3 +2371,19 @@ fn ensure_addresses_consistent_with_transport<'a>(
 
 	Ok(())
 }
+
+/// Replaces a `/tcp/0` in `addr` by the TCP port of `assigned`, an address reported by the
+/// listener started on `addr`.
+fn with_assigned_port(addr: &Multiaddr, assigned: &Multiaddr) -> Multiaddr {
+	let port = assigned.iter().find_map(|p| match p {
+		multiaddr::Protocol::Tcp(port) => Some(port),
+		_ => None,
+	});
+
+	addr.iter()
+		.map(|p| match (p, port) {
+			(multiaddr::Protocol::Tcp(0), Some(port)) => multiaddr::Protocol::Tcp(port),
+			(p, _) => p,
+		})
+		.collect()
+}
@@ -0,0 +1,91 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Persistence of the anchors of the default peer set across restarts.
+//!
+//! Anchors are the nodes we have had an outgoing connection with for the longest time. Connecting
+//! to them first after a restart prevents an attacker from taking advantage of the restart to
+//! occupy all our outgoing slots.
+
+use crate::config::MultiaddrWithPeerId;
+use log::{debug, warn};
+use std::{fs, path::Path, time::Duration};
+
+/// Name of the file, in the network configuration directory, the anchors are stored in.
+pub const ANCHORS_FILE: &str = "anchors.json";
+
+/// Interval at which the anchors are checked for changes and persisted.
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Loads the anchors stored at `path`.
+///
+/// Returns an empty list if the file doesn't exist or can't be parsed.
+pub fn load(path: &Path) -> Vec<MultiaddrWithPeerId> {
+	let contents = match fs::read(path) {
+		Ok(contents) => contents,
+		Err(err) => {
+			debug!(target: "sub-libp2p", "No anchors loaded from {}: {}", path.display(), err);
+			return Vec::new()
+		},
+	};
+
+	serde_json::from_slice(&contents).unwrap_or_else(|err| {
+		warn!(target: "sub-libp2p", "Failed to parse anchors from {}: {}", path.display(), err);
+		Vec::new()
+	})
+}
+
+/// Stores `anchors` at `path`, replacing the previous ones.
+///
+/// The anchors are written to a temporary file first and then moved to `path`, so that the
+/// previous anchors are kept intact if the node stops in the middle of writing.
+pub fn save(path: &Path, anchors: &[MultiaddrWithPeerId]) {
+	let tmp_path = path.with_extension("json.tmp");
+	let result = serde_json::to_vec(anchors).map_err(|err| err.to_string()).and_then(|contents| {
+		fs::write(&tmp_path, contents)
+			.and_then(|()| fs::rename(&tmp_path, path))
+			.map_err(|err| err.to_string())
+	});
+
+	if let Err(err) = result {
+		warn!(target: "sub-libp2p", "Failed to save anchors to {}: {}", path.display(), err);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use libp2p::PeerId;
+
+	#[test]
+	fn saved_anchors_are_loaded() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join(ANCHORS_FILE);
+		let anchors = vec![MultiaddrWithPeerId {
+			multiaddr: "/ip4/192.0.2.1/tcp/30333".parse().unwrap(),
+			peer_id: PeerId::random(),
+		}];
+
+		assert!(load(&path).is_empty());
+		save(&path, &anchors);
+		save(&path, &anchors);
+
+		assert_eq!(load(&path), anchors);
+		assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+	}
+}
@@ -659,3 +659,18 @@ fn ensure_public_addresses_consistent_with_transport_not_memory() {
 		..config::NetworkConfiguration::new("test-node", "test-client", Default::default(), None)
 	});
 }
+
+#[test]
+fn restarted_listeners_keep_assigned_port() {
+	let listen_addr = config::build_multiaddr![Ip4([0, 0, 0, 0]), Tcp(0_u16), Wss("/")];
+	let reported = config::build_multiaddr![Ip4([127, 0, 0, 1]), Tcp(30333_u16), Wss("/")];
+
+	assert_eq!(
+		config::build_multiaddr![Ip4([0, 0, 0, 0]), Tcp(30333_u16), Wss("/")],
+		super::with_assigned_port(&listen_addr, &reported),
+	);
+
+	// Explicitly configured ports are kept.
+	let listen_addr = config::build_multiaddr![Ip4([0, 0, 0, 0]), Tcp(443_u16), Wss("/")];
+	assert_eq!(listen_addr, super::with_assigned_port(&listen_addr, &reported));
+}
@@ -0,0 +1,96 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use super::*;
+use futures::executor::block_on;
+use sc_network::config::Secret;
+use std::fs;
+
+const HONEST: usize = 0;
+const NUM_ATTACKERS: usize = 8;
+
+/// Anchors are persisted every 5 seconds, leave plenty of margin for slow machines.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Polls `net` until `condition` holds, panicking after [`TIMEOUT`].
+fn wait_until(net: &mut TestNet, what: &str, mut condition: impl FnMut() -> bool) {
+	block_on(timeout(
+		TIMEOUT,
+		futures::future::poll_fn::<(), _>(|cx| {
+			net.poll(cx);
+			if condition() {
+				Poll::Ready(())
+			} else {
+				Poll::Pending
+			}
+		}),
+	))
+	.unwrap_or_else(|_| panic!("{} didn't happen within {:?}", what, TIMEOUT));
+}
+
+#[test]
+fn anchors_survive_restart_among_attackers() {
+	sp_tracing::try_init_simple();
+	let net_config_path = tempfile::tempdir().unwrap();
+	let anchors_file = net_config_path.path().join("anchors.json");
+	// The victim keeps its identity across restarts, as a real node would.
+	let victim_config = |bootnodes| FullPeerConfig {
+		in_peers: Some(0),
+		out_peers: Some(2),
+		bootnodes,
+		net_config_path: Some(net_config_path.path().to_owned()),
+		node_key: NodeKeyConfig::Ed25519(Secret::File(
+			net_config_path.path().join("secret_ed25519"),
+		)),
+		..Default::default()
+	};
+
+	let mut net = TestNet::new(1);
+	let honest = net.peer(HONEST).id();
+
+	// A first run of the victim, only knowing about the honest node.
+	net.add_full_peer_with_config(victim_config(vec![HONEST]));
+	let victim = net.peer(1).id();
+	wait_until(&mut net, "Persisting the honest node as an anchor", || {
+		fs::read_to_string(&anchors_file)
+			.map_or(false, |anchors| anchors.contains(&honest.to_base58()))
+	});
+
+	// Stop the victim.
+	net.mut_peers(|peers| drop(peers.pop()));
+
+	// While the victim is offline, the attackers join the network. They are many more than the
+	// victim has outgoing slots, and they all know each other.
+	for _ in 0..NUM_ATTACKERS {
+		net.add_full_peer();
+	}
+	let attackers = (1..=NUM_ATTACKERS).collect::<Vec<_>>();
+
+	// The victim restarts with only the attackers as bootnodes, which would occupy all its
+	// outgoing slots if the honest node hadn't been persisted as an anchor.
+	net.add_full_peer_with_config(victim_config(attackers));
+	let restarted = 1 + NUM_ATTACKERS;
+	assert_eq!(net.peer(restarted).id(), victim);
+
+	// The anchors have been loaded, the next ones persisted reflect the slots after the restart.
+	fs::remove_file(&anchors_file).unwrap();
+	wait_until(&mut net, "Persisting the anchors after the restart", || anchors_file.exists());
+
+	let anchors = fs::read_to_string(&anchors_file).unwrap();
+	assert!(anchors.contains(&honest.to_base58()), "Eclipsed after a restart: {}", anchors);
+}
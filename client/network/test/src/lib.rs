@@ -20,11 +20,14 @@
 #[cfg(test)]
 mod block_import;
 #[cfg(test)]
+mod eclipse;
+#[cfg(test)]
 mod sync;
 
 use std::{
 	borrow::Cow,
 	collections::HashMap,
+	path::PathBuf,
 	pin::Pin,
 	sync::Arc,
 	task::{Context as FutureContext, Poll},
@@ -50,8 +53,8 @@ pub use sc_network::config::EmptyTransactionPool;
 use sc_network::{
 	block_request_handler::BlockRequestHandler,
 	config::{
-		MultiaddrWithPeerId, NetworkConfiguration, NonDefaultSetConfig, NonReservedPeerMode,
-		ProtocolConfig, Role, SyncMode, TransportConfig,
+		MultiaddrWithPeerId, NetworkConfiguration, NodeKeyConfig, NonDefaultSetConfig,
+		NonReservedPeerMode, ProtocolConfig, Role, SyncMode, TransportConfig,
 	},
 	light_client_requests::handler::LightClientRequestHandler,
 	state_request_handler::StateRequestHandler,
@@ -684,6 +687,16 @@ pub struct FullPeerConfig {
 	pub extra_storage: Option<sp_core::storage::Storage>,
 	/// Enable transaction indexing.
 	pub storage_chain: bool,
+	/// Number of ingoing slots of the default peer set, if different from the default.
+	pub in_peers: Option<u32>,
+	/// Number of outgoing slots of the default peer set, if different from the default.
+	pub out_peers: Option<u32>,
+	/// The indices of the peers to use as bootnodes.
+	pub bootnodes: Vec<usize>,
+	/// Directory the network configuration is persisted in, if any.
+	pub net_config_path: Option<PathBuf>,
+	/// The key identifying the peer on the network.
+	pub node_key: NodeKeyConfig,
 }
 
 pub trait TestNetFactory: Sized
@@ -782,8 +795,12 @@ where
 
 		let listen_addr = build_multiaddr![Memory(rand::random::<u64>())];
 
-		let mut network_config =
-			NetworkConfiguration::new("test-node", "test-client", Default::default(), None);
+		let mut network_config = NetworkConfiguration::new(
+			"test-node",
+			"test-client",
+			config.node_key,
+			config.net_config_path,
+		);
 		network_config.sync_mode = config.sync_mode;
 		network_config.transport = TransportConfig::MemoryOnly;
 		network_config.listen_addresses = vec![listen_addr.clone()];
@@ -798,6 +815,21 @@ where
 				set_config: Default::default(),
 			})
 			.collect();
+		if let Some(in_peers) = config.in_peers {
+			network_config.default_peers_set.in_peers = in_peers;
+		}
+		if let Some(out_peers) = config.out_peers {
+			network_config.default_peers_set.out_peers = out_peers;
+		}
+		network_config.boot_nodes = config
+			.bootnodes
+			.iter()
+			.map(|v| {
+				let peer_id = *self.peer(*v).network_service().local_peer_id();
+				let multiaddr = self.peer(*v).listen_addr.clone();
+				MultiaddrWithPeerId { peer_id, multiaddr }
+			})
+			.collect();
 		if let Some(connect_to) = config.connect_to_peers {
 			let addrs = connect_to
 				.iter()
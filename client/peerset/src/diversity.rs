@@ -0,0 +1,232 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Diversity of the nodes occupying outgoing slots.
+//!
+//! An attacker able to flood us with the identities of the nodes it controls could otherwise end
+//! up occupying all our outgoing slots, isolating us from the rest of the network. Outgoing slots
+//! are therefore spread across network groups, i.e. subnets, and across the sources through
+//! which the nodes were discovered.
+
+use crate::ip_limits;
+use libp2p::{Multiaddr, PeerId};
+use std::{
+	collections::{HashMap, HashSet},
+	net::IpAddr,
+};
+
+/// How a node has been discovered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiscoverySource {
+	/// The node is one of the configured bootnodes.
+	Bootnode,
+	/// We had a long-lived outgoing connection with the node before restarting.
+	Anchor,
+	/// The node has been found through the DHT.
+	Dht,
+	/// The node has been found on the local network.
+	Mdns,
+	/// The node connected to us and reported its own addresses.
+	Connection,
+	/// Any other source, for example an explicit request through the API.
+	Other,
+}
+
+/// Discovery source and address of a node.
+#[derive(Debug, Clone, Copy)]
+struct Origin {
+	source: DiscoverySource,
+	/// IP address the node has been discovered with or, once connected, the one we have
+	/// observed, if subject to the IP limits.
+	ip: Option<IpAddr>,
+	/// Whether `ip` has been observed on a connection rather than reported by a third party.
+	observed: bool,
+}
+
+/// How the nodes we know about have been discovered.
+#[derive(Debug, Default)]
+pub(crate) struct Origins {
+	origins: HashMap<PeerId, Origin>,
+}
+
+impl Origins {
+	/// Records how a node has been discovered.
+	///
+	/// The first source a node has been discovered through is kept, as later sources are under
+	/// the control of whoever got us to know the node first.
+	pub fn insert(
+		&mut self,
+		peer_id: PeerId,
+		source: DiscoverySource,
+		address: Option<&Multiaddr>,
+	) {
+		let ip = address.and_then(ip_limits::limited_ip);
+		let origin =
+			self.origins.entry(peer_id).or_insert(Origin { source, ip, observed: false });
+		if origin.ip.is_none() && !origin.observed {
+			origin.ip = ip;
+		}
+	}
+
+	/// Records the address a connection with a node has been established at.
+	///
+	/// Unlike the addresses nodes are discovered with, which anyone can make up, this one can't
+	/// be chosen freely by an attacker and takes precedence.
+	pub fn observe(&mut self, peer_id: PeerId, address: &Multiaddr) {
+		let ip = ip_limits::limited_ip(address);
+		let origin = self.origins.entry(peer_id).or_insert(Origin {
+			source: DiscoverySource::Connection,
+			ip,
+			observed: true,
+		});
+		origin.ip = ip;
+		origin.observed = true;
+	}
+
+	/// Returns how a node has been discovered.
+	pub fn source(&self, peer_id: &PeerId) -> DiscoverySource {
+		self.origins.get(peer_id).map_or(DiscoverySource::Other, |origin| origin.source)
+	}
+
+	/// Returns the IP address a node has been observed or discovered with, if any.
+	pub fn ip(&self, peer_id: &PeerId) -> Option<IpAddr> {
+		self.origins.get(peer_id).and_then(|origin| origin.ip)
+	}
+
+	/// Returns the network group of the address a node has been observed or discovered with, if
+	/// any.
+	pub fn group(&self, peer_id: &PeerId) -> Option<IpAddr> {
+		self.ip(peer_id).map(ip_limits::subnet)
+	}
+
+	/// Only keeps the nodes for which `f` returns `true`.
+	pub fn retain(&mut self, mut f: impl FnMut(&PeerId) -> bool) {
+		self.origins.retain(|peer_id, _| f(peer_id));
+	}
+}
+
+/// Network groups and discovery sources of the nodes occupying the outgoing slots of a set.
+#[derive(Debug)]
+pub(crate) struct OutgoingDiversity {
+	groups: HashSet<IpAddr>,
+	sources: HashMap<DiscoverySource, u32>,
+	max_per_source: u32,
+}
+
+impl OutgoingDiversity {
+	/// Builds an empty [`OutgoingDiversity`] for a set with `out_peers` outgoing slots.
+	///
+	/// Nodes discovered through the same source can occupy at most half of the slots, rounded
+	/// up.
+	pub fn new(out_peers: u32) -> Self {
+		Self {
+			groups: HashSet::new(),
+			sources: HashMap::new(),
+			max_per_source: ((out_peers + 1) / 2).max(1),
+		}
+	}
+
+	/// Registers a node occupying an outgoing slot.
+	pub fn insert(&mut self, group: Option<IpAddr>, source: DiscoverySource) {
+		if let Some(group) = group {
+			self.groups.insert(group);
+		}
+		*self.sources.entry(source).or_default() += 1;
+	}
+
+	/// Returns `true` if a node from the given network group and discovery source would add to
+	/// the diversity of the outgoing slots.
+	pub fn allows(&self, group: Option<IpAddr>, source: DiscoverySource) -> bool {
+		group.map_or(true, |group| !self.groups.contains(&group)) &&
+			self.sources.get(&source).map_or(true, |n| *n < self.max_per_source)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn sources_share_slots() {
+		let mut diversity = OutgoingDiversity::new(3);
+		assert!(diversity.allows(None, DiscoverySource::Dht));
+
+		diversity.insert(None, DiscoverySource::Dht);
+		assert!(diversity.allows(None, DiscoverySource::Dht));
+
+		diversity.insert(None, DiscoverySource::Dht);
+		assert!(!diversity.allows(None, DiscoverySource::Dht));
+		assert!(diversity.allows(None, DiscoverySource::Bootnode));
+	}
+
+	#[test]
+	fn one_node_per_group() {
+		let group = Some("192.0.2.0".parse().unwrap());
+		let mut diversity = OutgoingDiversity::new(25);
+
+		diversity.insert(group, DiscoverySource::Dht);
+		assert!(!diversity.allows(group, DiscoverySource::Bootnode));
+		assert!(diversity.allows(Some("198.51.100.0".parse().unwrap()), DiscoverySource::Dht));
+	}
+
+	#[test]
+	fn first_source_is_kept() {
+		let peer_id = PeerId::random();
+		let mut origins = Origins::default();
+
+		origins.insert(peer_id, DiscoverySource::Bootnode, None);
+		origins.insert(
+			peer_id,
+			DiscoverySource::Dht,
+			Some(&"/ip4/192.0.2.1/tcp/30333".parse().unwrap()),
+		);
+
+		assert_eq!(origins.source(&peer_id), DiscoverySource::Bootnode);
+		assert_eq!(origins.group(&peer_id), Some("192.0.2.0".parse().unwrap()));
+		assert_eq!(origins.source(&PeerId::random()), DiscoverySource::Other);
+	}
+	#[test]
+	fn observed_address_takes_precedence() {
+		let peer_id = PeerId::random();
+		let mut origins = Origins::default();
+
+		origins.insert(
+			peer_id,
+			DiscoverySource::Dht,
+			Some(&"/ip4/192.0.2.1/tcp/30333".parse().unwrap()),
+		);
+		origins.observe(peer_id, &"/ip4/198.51.100.1/tcp/30333".parse().unwrap());
+		origins.insert(
+			peer_id,
+			DiscoverySource::Dht,
+			Some(&"/ip4/203.0.113.1/tcp/30333".parse().unwrap()),
+		);
+
+		assert_eq!(origins.source(&peer_id), DiscoverySource::Dht);
+		assert_eq!(origins.group(&peer_id), Some("198.51.100.0".parse().unwrap()));
+
+		// Nodes connected from a private network aren't grouped, whatever they claim.
+		origins.observe(peer_id, &"/ip4/10.0.0.1/tcp/30333".parse().unwrap());
+		origins.insert(
+			peer_id,
+			DiscoverySource::Dht,
+			Some(&"/ip4/203.0.113.1/tcp/30333".parse().unwrap()),
+		);
+		assert_eq!(origins.group(&peer_id), None);
+	}
+}
@@ -0,0 +1,261 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Limits on the number of slot-occupying nodes that share an IP address or a subnet.
+//!
+//! Without these limits, a single operator controlling a small range of addresses could occupy
+//! all the slots of a set. Limits are enforced across all sets together: a node counts once
+//! towards the limits of its address regardless of how many sets it occupies a slot in.
+
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+use std::{
+	collections::HashMap,
+	net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+/// Length of the prefix shared by the IPv4 addresses of a subnet.
+const IPV4_SUBNET_PREFIX_LEN: u32 = 24;
+/// Length of the prefix shared by the IPv6 addresses of a subnet.
+const IPV6_SUBNET_PREFIX_LEN: u32 = 48;
+
+/// Maximum number of slot-occupying nodes that can share an IP address or a subnet (`/24` for
+/// IPv4, `/48` for IPv6).
+///
+/// Reserved nodes are neither subject to nor counted towards these limits. Nodes reached through
+/// a loopback, private or link-local address or through a relay aren't limited either, so that
+/// for example validators and their sentries can share a private subnet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpLimits {
+	/// Maximum number of nodes connected through an ingoing connection per IP address.
+	pub max_inbound_per_ip: u32,
+	/// Maximum number of nodes connected through an outgoing connection per IP address.
+	pub max_outbound_per_ip: u32,
+	/// Maximum number of nodes connected through an ingoing connection per subnet.
+	pub max_inbound_per_subnet: u32,
+	/// Maximum number of nodes connected through an outgoing connection per subnet.
+	pub max_outbound_per_subnet: u32,
+}
+
+impl IpLimits {
+	/// Limits that never refuse a node.
+	pub const fn unlimited() -> Self {
+		Self {
+			max_inbound_per_ip: u32::MAX,
+			max_outbound_per_ip: u32::MAX,
+			max_inbound_per_subnet: u32::MAX,
+			max_outbound_per_subnet: u32::MAX,
+		}
+	}
+}
+
+impl Default for IpLimits {
+	fn default() -> Self {
+		Self {
+			max_inbound_per_ip: 4,
+			max_outbound_per_ip: 2,
+			max_inbound_per_subnet: 8,
+			max_outbound_per_subnet: 4,
+		}
+	}
+}
+
+/// Number of times a node has been refused a slot because of the [`IpLimits`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IpLimitRejections {
+	/// Number of refused incoming connections.
+	pub inbound: u64,
+	/// Number of dropped outgoing connections.
+	pub outbound: u64,
+}
+
+/// Direction of the slot occupied by a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Direction {
+	Inbound,
+	Outbound,
+}
+
+/// Returns the IP address that counts towards the limits for a node reached at `address`, or
+/// `None` if the node isn't subject to limits.
+pub(crate) fn limited_ip(address: &Multiaddr) -> Option<IpAddr> {
+	let mut iter = address.iter();
+	let ip = match iter.next()? {
+		Protocol::Ip4(ip) => IpAddr::V4(ip),
+		Protocol::Ip6(ip) => IpAddr::V6(ip),
+		_ => return None,
+	};
+
+	// Nodes in non-global ranges are operated by ourselves or by someone we share a network with,
+	// e.g. validators and their sentries or local test networks. The address of a relayed
+	// connection is the one of the relay rather than the one of the node.
+	if !is_global(ip) || iter.any(|p| matches!(p, Protocol::P2pCircuit)) {
+		return None
+	}
+
+	Some(ip)
+}
+
+/// Returns `false` if `ip` belongs to a loopback, private, link-local or otherwise not globally
+/// routable range.
+fn is_global(ip: IpAddr) -> bool {
+	match ip {
+		IpAddr::V4(ip) => {
+			let [a, b, ..] = ip.octets();
+			// Shared address space of carrier-grade NATs, `100.64.0.0/10`.
+			let is_shared = a == 100 && (b & 0b1100_0000) == 0b0100_0000;
+			!(ip.is_loopback() ||
+				ip.is_private() ||
+				ip.is_link_local() ||
+				ip.is_unspecified() ||
+				ip.is_broadcast() ||
+				is_shared)
+		},
+		IpAddr::V6(ip) => {
+			let segments = ip.segments();
+			// IPv4-mapped addresses, `::ffff:0:0/96`.
+			if let [0, 0, 0, 0, 0, 0xffff, high, low] = segments {
+				let ip = (u32::from(high) << 16) | u32::from(low);
+				return is_global(IpAddr::V4(Ipv4Addr::from(ip)))
+			}
+			let first = segments[0];
+			// Unique local addresses, `fc00::/7`.
+			let is_unique_local = (first & 0xfe00) == 0xfc00;
+			// Link-local addresses, `fe80::/10`.
+			let is_link_local = (first & 0xffc0) == 0xfe80;
+			!(ip.is_loopback() || ip.is_unspecified() || is_unique_local || is_link_local)
+		},
+	}
+}
+
+/// Returns the subnet `ip` belongs to, in the form of its first address.
+pub(crate) fn subnet(ip: IpAddr) -> IpAddr {
+	match ip {
+		IpAddr::V4(ip) =>
+			IpAddr::V4(Ipv4Addr::from(u32::from(ip) & (u32::MAX << (32 - IPV4_SUBNET_PREFIX_LEN)))),
+		IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from(
+			u128::from(ip) & (u128::MAX << (128 - IPV6_SUBNET_PREFIX_LEN)),
+		)),
+	}
+}
+
+/// IP addresses of the nodes we have a connection with.
+#[derive(Debug)]
+pub(crate) struct IpConnections {
+	/// Configured limits.
+	limits: IpLimits,
+	/// IP address of each connected node subject to limits.
+	addresses: HashMap<PeerId, IpAddr>,
+}
+
+impl IpConnections {
+	pub fn new(limits: IpLimits) -> Self {
+		Self { limits, addresses: HashMap::new() }
+	}
+
+	/// Records the IP address of a connected node.
+	pub fn insert(&mut self, peer_id: PeerId, ip: IpAddr) {
+		self.addresses.insert(peer_id, ip);
+	}
+
+	/// Forgets the IP address of a node we are no longer connected to.
+	pub fn remove(&mut self, peer_id: &PeerId) {
+		self.addresses.remove(peer_id);
+	}
+
+	/// Returns the IP address of a connected node, if it is subject to limits.
+	pub fn ip(&self, peer_id: &PeerId) -> Option<IpAddr> {
+		self.addresses.get(peer_id).copied()
+	}
+
+	/// Returns `true` if a node at `ip` can occupy a slot in the given direction, given the
+	/// addresses of the other nodes occupying a slot in that direction.
+	pub fn allows(
+		&self,
+		ip: IpAddr,
+		direction: Direction,
+		others: impl IntoIterator<Item = IpAddr>,
+	) -> bool {
+		let (max_per_ip, max_per_subnet) = match direction {
+			Direction::Inbound =>
+				(self.limits.max_inbound_per_ip, self.limits.max_inbound_per_subnet),
+			Direction::Outbound =>
+				(self.limits.max_outbound_per_ip, self.limits.max_outbound_per_subnet),
+		};
+
+		let subnet = subnet(ip);
+		let mut num_same_ip = 0;
+		let mut num_same_subnet = 0;
+		for other_ip in others {
+			if self::subnet(other_ip) != subnet {
+				continue
+			}
+
+			num_same_subnet += 1;
+			if other_ip == ip {
+				num_same_ip += 1;
+			}
+		}
+
+		num_same_ip < max_per_ip && num_same_subnet < max_per_subnet
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn subnets() {
+		assert_eq!(subnet("192.0.2.42".parse().unwrap()), "192.0.2.0".parse::<IpAddr>().unwrap());
+		assert_eq!(
+			subnet("2001:db8:1:2::1".parse().unwrap()),
+			"2001:db8:1::".parse::<IpAddr>().unwrap()
+		);
+	}
+
+	#[test]
+	fn limited_addresses() {
+		assert_eq!(
+			limited_ip(&"/ip4/192.0.2.1/tcp/30333".parse().unwrap()),
+			Some("192.0.2.1".parse().unwrap())
+		);
+		assert_eq!(
+			limited_ip(&"/ip6/2001:db8::1/tcp/30333".parse().unwrap()),
+			Some("2001:db8::1".parse().unwrap())
+		);
+		assert_eq!(limited_ip(&"/ip4/127.0.0.1/tcp/30333".parse().unwrap()), None);
+		assert_eq!(limited_ip(&"/ip6/::1/tcp/30333".parse().unwrap()), None);
+		assert_eq!(limited_ip(&"/ip4/10.0.0.1/tcp/30333".parse().unwrap()), None);
+		assert_eq!(limited_ip(&"/ip4/172.16.0.1/tcp/30333".parse().unwrap()), None);
+		assert_eq!(limited_ip(&"/ip4/192.168.1.1/tcp/30333".parse().unwrap()), None);
+		assert_eq!(limited_ip(&"/ip4/169.254.0.1/tcp/30333".parse().unwrap()), None);
+		assert_eq!(limited_ip(&"/ip4/100.64.0.1/tcp/30333".parse().unwrap()), None);
+		assert_eq!(limited_ip(&"/ip6/fd00::1/tcp/30333".parse().unwrap()), None);
+		assert_eq!(limited_ip(&"/ip6/fe80::1/tcp/30333".parse().unwrap()), None);
+		assert_eq!(limited_ip(&"/ip6/::ffff:10.0.0.1/tcp/30333".parse().unwrap()), None);
+		assert_eq!(limited_ip(&"/dns/example.com/tcp/30333".parse().unwrap()), None);
+		assert_eq!(
+			limited_ip(
+				&"/ip4/192.0.2.1/tcp/30333/p2p/12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN/p2p-circuit"
+					.parse()
+					.unwrap()
+			),
+			None
+		);
+	}
+}
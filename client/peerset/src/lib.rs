@@ -31,7 +31,18 @@
 //!
 //! In addition, for each, set, the peerset also holds a list of reserved nodes towards which it
 //! will at all time try to maintain a connection with.
-
+//!
+//! Finally, the number of slot-occupying nodes sharing an IP address or a subnet is bounded
+//! across all sets by the [`IpLimits`].
+//!
+//! In order to make it harder for an attacker to isolate us from the rest of the network,
+//! outgoing slots are spread across network groups and across the [`DiscoverySource`]s of the
+//! nodes. The longest-lived outgoing connections are exposed as [`Peerset::anchors`], meant to
+//! be passed back through [`SetConfig::anchors`] after a restart, and one outgoing slot of each
+//! set is periodically freed to give other nodes a chance.
+
+mod diversity;
+mod ip_limits;
 mod peersstate;
 
 use futures::{channel::oneshot, prelude::*};
@@ -40,13 +51,19 @@ use sc_utils::mpsc::{tracing_unbounded, TracingUnboundedReceiver, TracingUnbound
 use serde_json::json;
 use std::{
 	collections::{HashMap, HashSet, VecDeque},
+	iter, mem,
 	pin::Pin,
 	task::{Context, Poll},
 	time::{Duration, Instant},
 };
 use wasm_timer::Delay;
 
-pub use libp2p::PeerId;
+pub use diversity::DiscoverySource;
+pub use ip_limits::{IpLimitRejections, IpLimits};
+pub use libp2p::{Multiaddr, PeerId};
+
+use diversity::{Origins, OutgoingDiversity};
+use ip_limits::{Direction, IpConnections};
 
 /// We don't accept nodes whose reputation is under this value.
 pub const BANNED_THRESHOLD: i32 = 82 * (i32::MIN / 100);
@@ -55,6 +72,11 @@ const DISCONNECT_REPUTATION_CHANGE: i32 = -256;
 /// Amount of time between the moment we disconnect from a node and the moment we remove it from
 /// the list.
 const FORGET_AFTER: Duration = Duration::from_secs(3600);
+/// Interval at which one outgoing slot of each set is freed.
+const SLOT_ROTATION_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// Maximum number of anchors per set. The outgoing connections to the anchors are never freed by
+/// the slot rotation.
+const MAX_ANCHORS: usize = 2;
 
 #[derive(Debug)]
 enum Action {
@@ -64,6 +86,7 @@ enum Action {
 	SetReservedOnly(SetId, bool),
 	ReportPeer(PeerId, ReputationChange),
 	AddToPeersSet(SetId, PeerId),
+	AddDiscoveredToPeersSet(SetId, PeerId, DiscoverySource, Option<Multiaddr>),
 	RemoveFromPeersSet(SetId, PeerId),
 	PeerReputation(PeerId, oneshot::Sender<i32>),
 }
@@ -162,6 +185,20 @@ impl PeersetHandle {
 		let _ = self.tx.unbounded_send(Action::AddToPeersSet(set_id, peer_id));
 	}
 
+	/// Add a peer to a set, along with how it has been discovered and, if any, the address it has
+	/// been discovered with.
+	pub fn add_discovered_to_peers_set(
+		&self,
+		set_id: SetId,
+		peer_id: PeerId,
+		source: DiscoverySource,
+		address: Option<Multiaddr>,
+	) {
+		let _ = self
+			.tx
+			.unbounded_send(Action::AddDiscoveredToPeersSet(set_id, peer_id, source, address));
+	}
+
 	/// Remove a peer from a set.
 	pub fn remove_from_peers_set(&self, set_id: SetId, peer_id: PeerId) {
 		let _ = self.tx.unbounded_send(Action::RemoveFromPeersSet(set_id, peer_id));
@@ -218,6 +255,9 @@ impl From<u64> for IncomingIndex {
 pub struct PeersetConfig {
 	/// List of sets of nodes the peerset manages.
 	pub sets: Vec<SetConfig>,
+
+	/// Limits on the number of nodes sharing an IP address or a subnet, across all sets.
+	pub ip_limits: IpLimits,
 }
 
 /// Configuration for a single set of nodes.
@@ -243,6 +283,12 @@ pub struct SetConfig {
 
 	/// If true, we only accept nodes in [`SetConfig::reserved_nodes`].
 	pub reserved_only: bool,
+
+	/// Nodes to connect to in priority, typically the [`Peerset::anchors`] of a previous run.
+	///
+	/// > **Note**: Keep in mind that the networking has to know an address for these nodes,
+	/// >			otherwise it will not be able to connect to them.
+	pub anchors: Vec<PeerId>,
 }
 
 /// Side of the peer set manager owned by the network. In other words, the "receiving" side.
@@ -270,6 +316,22 @@ pub struct Peerset {
 	/// Next time to do a periodic call to `alloc_slots` with all sets. This is done once per
 	/// second, to match the period of the reputation updates.
 	next_periodic_alloc_slots: Delay,
+	/// IP addresses of the nodes we are connected to, checked against the [`IpLimits`].
+	ip_connections: IpConnections,
+	/// Number of nodes refused because of the [`IpLimits`] since the last call to
+	/// [`Peerset::take_ip_limit_rejections`].
+	ip_limit_rejections: IpLimitRejections,
+	/// For each set, the number of outgoing slots.
+	out_peers: Vec<u32>,
+	/// How the nodes we know about have been discovered.
+	origins: Origins,
+	/// For each set, anchors we haven't tried to connect to yet.
+	anchors: Vec<Vec<PeerId>>,
+	/// When we last allocated an outgoing slot to a node. Might contain obsolete entries, which
+	/// are periodically removed.
+	outgoing_since: HashMap<(SetId, PeerId), Instant>,
+	/// Next time to free one outgoing slot of each set.
+	next_slot_rotation: Delay,
 }
 
 impl Peerset {
@@ -297,6 +359,18 @@ impl Peerset {
 				created: now,
 				latest_time_update: now,
 				next_periodic_alloc_slots: Delay::new(Duration::new(0, 0)),
+				ip_connections: IpConnections::new(config.ip_limits),
+				ip_limit_rejections: IpLimitRejections::default(),
+				out_peers: config.sets.iter().map(|set| set.out_peers).collect(),
+				origins: Origins::default(),
+				// Reversed so that the oldest anchors are popped first.
+				anchors: config
+					.sets
+					.iter()
+					.map(|set| set.anchors.iter().rev().cloned().collect())
+					.collect(),
+				outgoing_since: HashMap::new(),
+				next_slot_rotation: Delay::new(SLOT_ROTATION_INTERVAL),
 			}
 		};
 
@@ -306,12 +380,20 @@ impl Peerset {
 			}
 
 			for peer_id in set_config.bootnodes {
+				peerset.origins.insert(peer_id, DiscoverySource::Bootnode, None);
 				if let peersstate::Peer::Unknown(entry) = peerset.data.peer(set, &peer_id) {
 					entry.discover();
 				} else {
 					debug!(target: "peerset", "Duplicate bootnode in config: {:?}", peer_id);
 				}
 			}
+
+			for peer_id in set_config.anchors {
+				peerset.origins.insert(peer_id, DiscoverySource::Anchor, None);
+				if let peersstate::Peer::Unknown(entry) = peerset.data.peer(set, &peer_id) {
+					entry.discover();
+				}
+			}
 		}
 
 		for set_index in 0..peerset.data.num_sets() {
@@ -414,6 +496,17 @@ impl Peerset {
 		}
 	}
 
+	fn on_add_discovered_to_peers_set(
+		&mut self,
+		set_id: SetId,
+		peer_id: PeerId,
+		source: DiscoverySource,
+		address: Option<Multiaddr>,
+	) {
+		self.origins.insert(peer_id, source, address.as_ref());
+		self.add_to_peers_set(set_id, peer_id);
+	}
+
 	fn on_remove_from_peers_set(&mut self, set_id: SetId, peer_id: PeerId) {
 		// Don't do anything if node is reserved.
 		if self.reserved_nodes[set_id.0].0.contains(&peer_id) {
@@ -535,6 +628,12 @@ impl Peerset {
 
 	/// Try to fill available out slots with nodes for the given set.
 	fn alloc_slots(&mut self, set_id: SetId) {
+		self.alloc_slots_except(set_id, HashSet::new())
+	}
+
+	/// Try to fill available out slots with nodes for the given set, other than the ones in
+	/// `excluded`.
+	fn alloc_slots_except(&mut self, set_id: SetId, mut excluded: HashSet<PeerId>) {
 		self.update_time();
 
 		// Try to connect to all the reserved nodes that we are not connected to.
@@ -577,24 +676,84 @@ impl Peerset {
 			return
 		}
 
+		// Anchors are tried first, once.
+		while self.data.has_free_outgoing_slot(set_id.0) {
+			let anchor = match self.anchors[set_id.0].pop() {
+				Some(anchor) => anchor,
+				None => break,
+			};
+
+			if !self.is_allowed_by_ip_limits(&anchor, Direction::Outbound) {
+				continue
+			}
+
+			if let peersstate::Peer::NotConnected(entry) = self.data.peer(set_id.0, &anchor) {
+				if entry.reputation() < BANNED_THRESHOLD {
+					continue
+				}
+
+				if let Ok(conn) = entry.try_outgoing() {
+					trace!(target: "peerset", "Connecting to anchor {}", anchor);
+					self.outgoing_since.insert((set_id, anchor), Instant::now());
+					self.message_queue
+						.push_back(Message::Connect { set_id, peer_id: conn.into_peer_id() });
+				}
+			}
+		}
+
 		// Try to grab the next node to attempt to connect to.
 		// Since `highest_not_connected_peer` is rather expensive to call, check beforehand
 		// whether we have an available slot.
 		while self.data.has_free_outgoing_slot(set_id.0) {
-			let next = match self.data.highest_not_connected_peer(set_id.0) {
-				Some(n) => n,
+			// Nodes adding to the diversity of the outgoing slots are preferred, but any node is
+			// better than a free slot.
+			// A banned preferred node doesn't mean that all the other nodes are banned.
+			let diversity = self.outgoing_diversity(set_id);
+			let Self { data, origins, .. } = self;
+			let next = data
+				.highest_not_connected_peer_filtered(set_id.0, |peer_id| {
+					!excluded.contains(peer_id) &&
+						diversity.allows(origins.group(peer_id), origins.source(peer_id))
+				})
+				.filter(|entry| entry.reputation() >= BANNED_THRESHOLD)
+				.map(|entry| entry.into_peer_id())
+				.or_else(|| {
+					data.highest_not_connected_peer_filtered(set_id.0, |peer_id| {
+						!excluded.contains(peer_id)
+					})
+					.map(|entry| entry.into_peer_id())
+				});
+			let peer_id = match next {
+				Some(peer_id) => peer_id,
 				None => break,
 			};
 
+			// Nodes whose address is already known and that would exceed the IP limits are
+			// skipped.
+			if !self.is_allowed_by_ip_limits(&peer_id, Direction::Outbound) {
+				excluded.insert(peer_id);
+				continue
+			}
+
+			let next = match self.data.peer(set_id.0, &peer_id).into_not_connected() {
+				Some(n) => n,
+				None => {
+					debug_assert!(false, "Peer returned as not connected just above; qed");
+					break
+				},
+			};
+
 			// Don't connect to nodes with an abysmal reputation.
 			if next.reputation() < BANNED_THRESHOLD {
 				break
 			}
 
 			match next.try_outgoing() {
-				Ok(conn) => self
-					.message_queue
-					.push_back(Message::Connect { set_id, peer_id: conn.into_peer_id() }),
+				Ok(conn) => {
+					self.outgoing_since.insert((set_id, peer_id), Instant::now());
+					self.message_queue
+						.push_back(Message::Connect { set_id, peer_id: conn.into_peer_id() })
+				},
 				Err(_) => {
 					// This branch can only be entered if there is no free slot, which is
 					// checked above.
@@ -605,6 +764,81 @@ impl Peerset {
 		}
 	}
 
+	/// Returns the nodes occupying an outgoing slot of the given set, ordered from the longest
+	/// connected.
+	fn outgoing_peers(&mut self, set_id: SetId) -> Vec<PeerId> {
+		let connected = self.data.connected_peers(set_id.0).cloned().collect::<Vec<_>>();
+		let mut outgoing = connected
+			.into_iter()
+			.filter(|peer_id| !self.reserved_nodes[set_id.0].0.contains(peer_id))
+			.filter(|peer_id| {
+				self.data
+					.peer(set_id.0, peer_id)
+					.into_connected()
+					.map_or(false, |entry| entry.is_outgoing())
+			})
+			.collect::<Vec<_>>();
+
+		let now = Instant::now();
+		outgoing.sort_by_key(|peer_id| {
+			self.outgoing_since.get(&(set_id, *peer_id)).copied().unwrap_or(now)
+		});
+		outgoing
+	}
+
+	/// Returns the network groups and discovery sources of the nodes occupying an outgoing slot
+	/// of the given set.
+	fn outgoing_diversity(&mut self, set_id: SetId) -> OutgoingDiversity {
+		let mut diversity = OutgoingDiversity::new(self.out_peers[set_id.0]);
+		for peer_id in self.outgoing_peers(set_id) {
+			diversity.insert(self.origins.group(&peer_id), self.origins.source(&peer_id));
+		}
+		diversity
+	}
+
+	/// Returns the nodes we have had an outgoing connection with for the longest time in the
+	/// context of the given set, at most [`MAX_ANCHORS`] of them.
+	///
+	/// Meant to be persisted and passed back through [`SetConfig::anchors`] after a restart.
+	pub fn anchors(&mut self, set_id: SetId) -> Vec<PeerId> {
+		let mut anchors = self.outgoing_peers(set_id);
+		anchors.truncate(MAX_ANCHORS);
+		anchors
+	}
+
+	/// Frees one outgoing slot of the given set, if all of them are occupied, and tries to
+	/// allocate it to another node.
+	///
+	/// The anchors are never disconnected. Among the other nodes, the one with the lowest
+	/// reputation is disconnected.
+	fn rotate_slot(&mut self, set_id: SetId) {
+		if self.reserved_nodes[set_id.0].1 || self.data.has_free_outgoing_slot(set_id.0) {
+			return
+		}
+
+		let candidates = self.outgoing_peers(set_id).into_iter().skip(MAX_ANCHORS);
+		let mut lowest = None::<(PeerId, i32)>;
+		for peer_id in candidates {
+			let reputation = self.data.peer_reputation(peer_id).reputation();
+			if lowest.map_or(true, |(_, lowest)| reputation <= lowest) {
+				lowest = Some((peer_id, reputation));
+			}
+		}
+
+		let peer_id = match lowest {
+			Some((peer_id, _)) => peer_id,
+			None => return,
+		};
+
+		if let Some(entry) = self.data.peer(set_id.0, &peer_id).into_connected() {
+			trace!(target: "peerset", "Rotating outgoing slot of {}", peer_id);
+			entry.disconnect();
+			self.outgoing_since.remove(&(set_id, peer_id));
+			self.message_queue.push_back(Message::Drop { set_id, peer_id });
+			self.alloc_slots_except(set_id, iter::once(peer_id).collect());
+		}
+	}
+
 	/// Indicate that we received an incoming connection. Must be answered either with
 	/// a corresponding `Accept` or `Reject`, except if we were already connected to this peer.
 	///
@@ -624,8 +858,23 @@ impl Peerset {
 			return
 		}
 
+		// If we're already connected, don't answer, as the docs mention.
+		if let peersstate::Peer::Connected(_) = self.data.peer(set_id.0, &peer_id) {
+			return
+		}
+
+		if !self.is_allowed_by_ip_limits(&peer_id, Direction::Inbound) {
+			debug!(
+				target: "peerset",
+				"Rejecting {}: too many nodes with the same address",
+				peer_id,
+			);
+			self.ip_limit_rejections.inbound += 1;
+			self.message_queue.push_back(Message::Reject(index));
+			return
+		}
+
 		let not_connected = match self.data.peer(set_id.0, &peer_id) {
-			// If we're already connected, don't answer, as the docs mention.
 			peersstate::Peer::Connected(_) => return,
 			peersstate::Peer::NotConnected(mut entry) => {
 				entry.bump_last_connected_or_discovered();
@@ -673,6 +922,101 @@ impl Peerset {
 		self.alloc_slots(set_id);
 	}
 
+	/// Indicate that a connection with the given peer has been established at the given address.
+	///
+	/// Outgoing slots are allocated based on the address a node has been discovered with, if
+	/// any, which might not be the one we end up connected to. From now on, the network group
+	/// of the node is derived from the address of the connection instead. If that address
+	/// exceeds the [`IpLimits`], the node is dropped from the sets it has an outgoing slot in.
+	pub fn connection_established(&mut self, peer_id: PeerId, address: &Multiaddr) {
+		self.origins.observe(peer_id, address);
+
+		let ip = match ip_limits::limited_ip(address) {
+			Some(ip) => ip,
+			None => return,
+		};
+
+		self.ip_connections.insert(peer_id, ip);
+
+		if self.is_allowed_by_ip_limits(&peer_id, Direction::Outbound) {
+			return
+		}
+
+		self.update_time();
+
+		let mut dropped_sets = Vec::new();
+		for set_index in 0..self.data.num_sets() {
+			if let peersstate::Peer::Connected(mut entry) = self.data.peer(set_index, &peer_id) {
+				if entry.is_outgoing() {
+					// Lower the reputation so that other nodes are tried first.
+					entry.add_reputation(DISCONNECT_REPUTATION_CHANGE);
+					entry.disconnect();
+					self.message_queue
+						.push_back(Message::Drop { set_id: SetId(set_index), peer_id });
+					dropped_sets.push(SetId(set_index));
+				}
+			}
+		}
+
+		if !dropped_sets.is_empty() {
+			debug!(target: "peerset", "Dropping {}: too many nodes with the same address", peer_id);
+			self.ip_limit_rejections.outbound += 1;
+		}
+
+		for set_id in dropped_sets {
+			self.alloc_slots(set_id);
+		}
+	}
+
+	/// Indicate that we no longer have any connection with the given peer.
+	pub fn connection_closed(&mut self, peer_id: &PeerId) {
+		self.ip_connections.remove(peer_id);
+	}
+
+	/// Returns the number of nodes refused because of the [`IpLimits`] since the last call.
+	pub fn take_ip_limit_rejections(&mut self) -> IpLimitRejections {
+		mem::take(&mut self.ip_limit_rejections)
+	}
+
+	/// Returns `true` if the given node can occupy a slot in the given direction without
+	/// exceeding the [`IpLimits`]. Reserved nodes are always allowed and don't count towards
+	/// the limits.
+	///
+	/// Nodes we aren't connected to yet are accounted for with the address they have been
+	/// discovered with, so that the limits are enforced before dialing them.
+	fn is_allowed_by_ip_limits(&mut self, peer_id: &PeerId, direction: Direction) -> bool {
+		let Self { ip_connections, data, reserved_nodes, origins, .. } = self;
+		let is_reserved =
+			|peer_id: &PeerId| reserved_nodes.iter().any(|(nodes, _)| nodes.contains(peer_id));
+		let ip_of = |peer_id: &PeerId| ip_connections.ip(peer_id).or_else(|| origins.ip(peer_id));
+
+		if is_reserved(peer_id) {
+			return true
+		}
+
+		let ip = match ip_of(peer_id) {
+			Some(ip) => ip,
+			None => return true,
+		};
+
+		let mut others = HashSet::new();
+		for set_index in 0..data.num_sets() {
+			let connected = data.connected_peers(set_index).cloned().collect::<Vec<_>>();
+			for other in connected {
+				let occupies_slot = match data.peer(set_index, &other) {
+					peersstate::Peer::Connected(entry) =>
+						entry.is_outgoing() == (direction == Direction::Outbound),
+					_ => false,
+				};
+				if occupies_slot && other != *peer_id && !is_reserved(&other) {
+					others.insert(other);
+				}
+			}
+		}
+
+		ip_connections.allows(ip, direction, others.iter().filter_map(ip_of))
+	}
+
 	/// Reports an adjustment to the reputation of the given peer.
 	pub fn report_peer(&mut self, peer_id: PeerId, score_diff: ReputationChange) {
 		// We don't immediately perform the adjustments in order to have state consistency. We
@@ -731,11 +1075,31 @@ impl Stream for Peerset {
 			if Future::poll(Pin::new(&mut self.next_periodic_alloc_slots), cx).is_ready() {
 				self.next_periodic_alloc_slots = Delay::new(Duration::new(1, 0));
 
+				let connected = (0..self.data.num_sets())
+					.map(|set| self.data.connected_peers(set).cloned().collect::<HashSet<_>>())
+					.collect::<Vec<_>>();
+				self.outgoing_since
+					.retain(|(set_id, peer_id), _| connected[set_id.0].contains(peer_id));
+
 				for set_index in 0..self.data.num_sets() {
 					self.alloc_slots(SetId(set_index));
 				}
 			}
 
+			if Future::poll(Pin::new(&mut self.next_slot_rotation), cx).is_ready() {
+				self.next_slot_rotation = Delay::new(SLOT_ROTATION_INTERVAL);
+
+				let known = self.data.peers().cloned().collect::<HashSet<_>>();
+				self.origins.retain(|peer_id| known.contains(peer_id));
+
+				for set_index in 0..self.data.num_sets() {
+					self.rotate_slot(SetId(set_index));
+				}
+
+				// Rotating slots might have queued messages.
+				continue
+			}
+
 			let action = match Stream::poll_next(Pin::new(&mut self.rx), cx) {
 				Poll::Pending => return Poll::Pending,
 				Poll::Ready(Some(event)) => event,
@@ -754,6 +1118,8 @@ impl Stream for Peerset {
 				Action::ReportPeer(peer_id, score_diff) => self.on_report_peer(peer_id, score_diff),
 				Action::AddToPeersSet(sets_name, peer_id) =>
 					self.add_to_peers_set(sets_name, peer_id),
+				Action::AddDiscoveredToPeersSet(set_id, peer_id, source, address) =>
+					self.on_add_discovered_to_peers_set(set_id, peer_id, source, address),
 				Action::RemoveFromPeersSet(sets_name, peer_id) =>
 					self.on_remove_from_peers_set(sets_name, peer_id),
 				Action::PeerReputation(peer_id, pending_response) =>
@@ -777,12 +1143,12 @@ pub enum DropReason {
 #[cfg(test)]
 mod tests {
 	use super::{
-		IncomingIndex, Message, Peerset, PeersetConfig, ReputationChange, SetConfig, SetId,
-		BANNED_THRESHOLD,
+		DiscoverySource, IncomingIndex, IpLimits, Message, Peerset, PeersetConfig,
+		ReputationChange, SetConfig, SetId, BANNED_THRESHOLD,
 	};
 	use futures::prelude::*;
 	use libp2p::PeerId;
-	use std::{pin::Pin, task::Poll, thread, time::Duration};
+	use std::{collections::HashSet, pin::Pin, task::Poll, thread, time::Duration};
 
 	fn assert_messages(mut peerset: Peerset, messages: Vec<Message>) -> Peerset {
 		for expected_message in messages {
@@ -811,7 +1177,9 @@ mod tests {
 				bootnodes: vec![bootnode],
 				reserved_nodes: Default::default(),
 				reserved_only: true,
+				anchors: Vec::new(),
 			}],
+			ip_limits: IpLimits::default(),
 		};
 
 		let (peerset, handle) = Peerset::from_config(config);
@@ -844,7 +1212,9 @@ mod tests {
 				bootnodes: vec![bootnode],
 				reserved_nodes: Default::default(),
 				reserved_only: false,
+				anchors: Vec::new(),
 			}],
+			ip_limits: IpLimits::default(),
 		};
 
 		let (mut peerset, _handle) = Peerset::from_config(config);
@@ -875,7 +1245,9 @@ mod tests {
 				bootnodes: vec![],
 				reserved_nodes: Default::default(),
 				reserved_only: true,
+				anchors: Vec::new(),
 			}],
+			ip_limits: IpLimits::default(),
 		};
 
 		let (mut peerset, _) = Peerset::from_config(config);
@@ -884,6 +1256,237 @@ mod tests {
 		assert_messages(peerset, vec![Message::Reject(ii)]);
 	}
 
+	#[test]
+	fn test_peerset_ip_limits_incoming() {
+		let same_ip = PeerId::random();
+		let same_ip2 = PeerId::random();
+		let same_subnet = PeerId::random();
+		let same_subnet2 = PeerId::random();
+		let reserved = PeerId::random();
+		let config = PeersetConfig {
+			sets: vec![
+				SetConfig {
+					in_peers: 50,
+					out_peers: 50,
+					bootnodes: vec![],
+					reserved_nodes: Default::default(),
+					reserved_only: false,
+					anchors: Vec::new(),
+				},
+				SetConfig {
+					in_peers: 0,
+					out_peers: 0,
+					bootnodes: vec![],
+					reserved_nodes: vec![reserved].into_iter().collect(),
+					reserved_only: true,
+					anchors: Vec::new(),
+				},
+			],
+			ip_limits: IpLimits {
+				max_inbound_per_ip: 1,
+				max_inbound_per_subnet: 2,
+				..IpLimits::unlimited()
+			},
+		};
+
+		let (mut peerset, _handle) = Peerset::from_config(config);
+		for (peer_id, address) in [
+			(same_ip, "/ip4/192.0.2.1/tcp/30333"),
+			(same_ip2, "/ip4/192.0.2.1/tcp/30334"),
+			(same_subnet, "/ip4/192.0.2.2/tcp/30333"),
+			(same_subnet2, "/ip4/192.0.2.3/tcp/30333"),
+			(reserved, "/ip4/192.0.2.1/tcp/30335"),
+		] {
+			peerset.connection_established(peer_id, &address.parse().unwrap());
+		}
+
+		peerset.incoming(SetId::from(0), same_ip, IncomingIndex(1));
+		peerset.incoming(SetId::from(0), same_ip2, IncomingIndex(2));
+		peerset.incoming(SetId::from(0), same_subnet, IncomingIndex(3));
+		peerset.incoming(SetId::from(0), same_subnet2, IncomingIndex(4));
+		// Being reserved in another set is enough to bypass the limits.
+		peerset.incoming(SetId::from(0), reserved, IncomingIndex(5));
+
+		assert_eq!(peerset.take_ip_limit_rejections().inbound, 2);
+		assert_eq!(peerset.take_ip_limit_rejections().inbound, 0);
+
+		assert_messages(
+			peerset,
+			vec![
+				Message::Connect { set_id: SetId::from(1), peer_id: reserved },
+				Message::Accept(IncomingIndex(1)),
+				Message::Reject(IncomingIndex(2)),
+				Message::Accept(IncomingIndex(3)),
+				Message::Reject(IncomingIndex(4)),
+				Message::Accept(IncomingIndex(5)),
+			],
+		);
+	}
+
+	#[test]
+	fn test_peerset_ip_limits_outgoing() {
+		let bootnode = PeerId::random();
+		let bootnode2 = PeerId::random();
+		let config = PeersetConfig {
+			sets: vec![SetConfig {
+				in_peers: 0,
+				out_peers: 2,
+				bootnodes: vec![bootnode, bootnode2],
+				reserved_nodes: Default::default(),
+				reserved_only: false,
+				anchors: Vec::new(),
+			}],
+			ip_limits: IpLimits { max_outbound_per_ip: 1, ..IpLimits::unlimited() },
+		};
+
+		let (peerset, _handle) = Peerset::from_config(config);
+		let (first, peerset) = next_message(peerset).unwrap();
+		let (second, mut peerset) = next_message(peerset).unwrap();
+		let mut connected = [first, second].into_iter().map(|message| match message {
+			Message::Connect { peer_id, .. } => peer_id,
+			message => panic!("Unexpected message: {:?}", message),
+		});
+		let (first, second) = (connected.next().unwrap(), connected.next().unwrap());
+
+		// The addresses of the bootnodes are only known once connected.
+		peerset.connection_established(first, &"/ip4/192.0.2.1/tcp/30333".parse().unwrap());
+		peerset.connection_established(second, &"/ip4/192.0.2.1/tcp/30334".parse().unwrap());
+
+		assert_eq!(peerset.take_ip_limit_rejections().outbound, 1);
+
+		let (message, peerset) = next_message(peerset).unwrap();
+		assert_eq!(message, Message::Drop { set_id: SetId::from(0), peer_id: second });
+
+		// The dropped node isn't tried again while its address is over the limit.
+		assert!(peerset.message_queue.is_empty());
+	}
+
+	#[test]
+	fn test_peerset_ip_limits_outgoing_before_dialing() {
+		let config = PeersetConfig {
+			sets: vec![SetConfig {
+				in_peers: 0,
+				out_peers: 3,
+				bootnodes: vec![],
+				reserved_nodes: Default::default(),
+				reserved_only: true,
+				anchors: Vec::new(),
+			}],
+			ip_limits: IpLimits { max_outbound_per_ip: 1, ..IpLimits::unlimited() },
+		};
+
+		let (mut peerset, _handle) = Peerset::from_config(config);
+		let set_id = SetId::from(0);
+
+		let same_ip = PeerId::random();
+		let same_ip2 = PeerId::random();
+		let other_ip = PeerId::random();
+		for (peer_id, address) in [
+			(same_ip, "/ip4/192.0.2.1/tcp/30333"),
+			(same_ip2, "/ip4/192.0.2.1/tcp/30334"),
+			(other_ip, "/ip4/198.51.100.1/tcp/30333"),
+		] {
+			peerset.on_add_discovered_to_peers_set(
+				set_id,
+				peer_id,
+				DiscoverySource::Dht,
+				Some(address.parse().unwrap()),
+			);
+		}
+
+		// Slots are only allocated once all the nodes are known.
+		peerset.on_set_reserved_only(set_id, false);
+
+		// Only one of the nodes sharing an address is dialed, even though a slot is left free.
+		let connected = peerset
+			.message_queue
+			.drain(..)
+			.map(|message| match message {
+				Message::Connect { peer_id, .. } => peer_id,
+				message => panic!("Unexpected message: {:?}", message),
+			})
+			.collect::<HashSet<_>>();
+		assert_eq!(connected.len(), 2);
+		assert!(connected.contains(&other_ip));
+		assert!(connected.contains(&same_ip) != connected.contains(&same_ip2));
+	}
+
+	#[test]
+	fn test_peerset_private_addresses_are_not_limited() {
+		let first = PeerId::random();
+		let second = PeerId::random();
+		let config = PeersetConfig {
+			sets: vec![SetConfig {
+				in_peers: 2,
+				out_peers: 0,
+				bootnodes: vec![],
+				reserved_nodes: Default::default(),
+				reserved_only: false,
+				anchors: Vec::new(),
+			}],
+			ip_limits: IpLimits { max_inbound_per_ip: 1, ..IpLimits::unlimited() },
+		};
+
+		let (mut peerset, _handle) = Peerset::from_config(config);
+		// For example a validator and its sentries.
+		peerset.connection_established(first, &"/ip4/10.0.0.1/tcp/30333".parse().unwrap());
+		peerset.connection_established(second, &"/ip4/10.0.0.1/tcp/30334".parse().unwrap());
+
+		peerset.incoming(SetId::from(0), first, IncomingIndex(1));
+		peerset.incoming(SetId::from(0), second, IncomingIndex(2));
+
+		assert_eq!(peerset.take_ip_limit_rejections().inbound, 0);
+		assert_messages(
+			peerset,
+			vec![Message::Accept(IncomingIndex(1)), Message::Accept(IncomingIndex(2))],
+		);
+	}
+
+	#[test]
+	fn test_peerset_banned_preferred_node_is_skipped() {
+		let config = PeersetConfig {
+			sets: vec![SetConfig {
+				in_peers: 0,
+				out_peers: 2,
+				bootnodes: vec![],
+				reserved_nodes: Default::default(),
+				reserved_only: true,
+				anchors: Vec::new(),
+			}],
+			ip_limits: IpLimits::unlimited(),
+		};
+
+		let (mut peerset, _handle) = Peerset::from_config(config);
+		let set_id = SetId::from(0);
+
+		let dht = PeerId::random();
+		let dht2 = PeerId::random();
+		let banned = PeerId::random();
+		peerset.on_add_discovered_to_peers_set(set_id, dht, DiscoverySource::Dht, None);
+		peerset.on_add_discovered_to_peers_set(set_id, dht2, DiscoverySource::Dht, None);
+		peerset.on_add_discovered_to_peers_set(set_id, banned, DiscoverySource::Mdns, None);
+		peerset
+			.data
+			.peer(0, &banned)
+			.into_not_connected()
+			.unwrap()
+			.set_reputation(BANNED_THRESHOLD - 1);
+
+		// Once a node discovered through the DHT is connected, the banned node is the only one
+		// adding to the diversity of the outgoing slots. The other node is connected instead.
+		peerset.on_set_reserved_only(set_id, false);
+
+		let connected = peerset
+			.message_queue
+			.drain(..)
+			.map(|message| match message {
+				Message::Connect { peer_id, .. } => peer_id,
+				message => panic!("Unexpected message: {:?}", message),
+			})
+			.collect::<HashSet<_>>();
+		assert_eq!(connected, [dht, dht2].into_iter().collect::<HashSet<_>>());
+	}
+
 	#[test]
 	fn test_peerset_discovered() {
 		let bootnode = PeerId::random();
@@ -896,7 +1499,9 @@ mod tests {
 				bootnodes: vec![bootnode],
 				reserved_nodes: Default::default(),
 				reserved_only: false,
+				anchors: Vec::new(),
 			}],
+			ip_limits: IpLimits::default(),
 		};
 
 		let (mut peerset, _handle) = Peerset::from_config(config);
@@ -913,6 +1518,114 @@ mod tests {
 		);
 	}
 
+	#[test]
+	fn test_peerset_outgoing_diversity() {
+		let config = PeersetConfig {
+			sets: vec![SetConfig {
+				in_peers: 0,
+				out_peers: 4,
+				bootnodes: vec![],
+				reserved_nodes: Default::default(),
+				reserved_only: true,
+				anchors: Vec::new(),
+			}],
+			ip_limits: IpLimits::default(),
+		};
+
+		let (mut peerset, _handle) = Peerset::from_config(config);
+		let set_id = SetId::from(0);
+
+		// Nodes sharing a subnet, all found through the DHT.
+		for n in 1..=3 {
+			let address = format!("/ip4/192.0.2.{}/tcp/30333", n).parse().unwrap();
+			peerset.on_add_discovered_to_peers_set(
+				set_id,
+				PeerId::random(),
+				DiscoverySource::Dht,
+				Some(address),
+			);
+		}
+
+		let honest = PeerId::random();
+		let honest2 = PeerId::random();
+		peerset.on_add_discovered_to_peers_set(
+			set_id,
+			honest,
+			DiscoverySource::Dht,
+			Some("/ip4/198.51.100.1/tcp/30333".parse().unwrap()),
+		);
+		peerset.on_add_discovered_to_peers_set(
+			set_id,
+			honest2,
+			DiscoverySource::Mdns,
+			Some("/ip4/203.0.113.1/tcp/30333".parse().unwrap()),
+		);
+
+		// Slots are only allocated once all the nodes are known.
+		peerset.on_set_reserved_only(set_id, false);
+
+		let connected = peerset
+			.message_queue
+			.drain(..)
+			.map(|message| match message {
+				Message::Connect { peer_id, .. } => peer_id,
+				message => panic!("Unexpected message: {:?}", message),
+			})
+			.collect::<HashSet<_>>();
+		assert_eq!(connected.len(), 4);
+		assert!(connected.contains(&honest));
+		assert!(connected.contains(&honest2));
+	}
+
+	#[test]
+	fn test_peerset_anchors_and_rotation() {
+		let anchor = PeerId::random();
+		let anchor2 = PeerId::random();
+		let config = PeersetConfig {
+			sets: vec![SetConfig {
+				in_peers: 0,
+				out_peers: 3,
+				bootnodes: vec![],
+				reserved_nodes: Default::default(),
+				reserved_only: false,
+				anchors: vec![anchor, anchor2],
+			}],
+			ip_limits: IpLimits::default(),
+		};
+
+		let (mut peerset, _handle) = Peerset::from_config(config);
+		let set_id = SetId::from(0);
+
+		// Make sure the anchors have been connected for longer than the other nodes.
+		for since in peerset.outgoing_since.values_mut() {
+			*since -= Duration::from_millis(10);
+		}
+
+		let discovered = PeerId::random();
+		let discovered2 = PeerId::random();
+		peerset.add_to_peers_set(set_id, discovered);
+		peerset.add_to_peers_set(set_id, discovered2);
+
+		// Only the nodes that aren't anchors are ever rotated out.
+		peerset.rotate_slot(set_id);
+
+		assert_eq!(
+			peerset.anchors(set_id).into_iter().collect::<HashSet<_>>(),
+			[anchor, anchor2].into_iter().collect::<HashSet<_>>(),
+		);
+
+		assert_messages(
+			peerset,
+			vec![
+				Message::Connect { set_id, peer_id: anchor },
+				Message::Connect { set_id, peer_id: anchor2 },
+				Message::Connect { set_id, peer_id: discovered },
+				Message::Drop { set_id, peer_id: discovered },
+				Message::Connect { set_id, peer_id: discovered2 },
+			],
+		);
+	}
+
 	#[test]
 	fn test_peerset_banned() {
 		let (mut peerset, handle) = Peerset::from_config(PeersetConfig {
@@ -922,7 +1635,9 @@ mod tests {
 				bootnodes: vec![],
 				reserved_nodes: Default::default(),
 				reserved_only: false,
+				anchors: Vec::new(),
 			}],
+			ip_limits: IpLimits::default(),
 		});
 
 		// We ban a node by setting its reputation under the threshold.
@@ -965,7 +1680,9 @@ mod tests {
 				bootnodes: vec![],
 				reserved_nodes: Default::default(),
 				reserved_only: false,
+				anchors: Vec::new(),
 			}],
+			ip_limits: IpLimits::default(),
 		});
 
 		// We ban a node by setting its reputation under the threshold.
@@ -236,6 +236,22 @@ impl PeersState {
 	///
 	/// `set` must be within range of the sets passed to [`PeersState::new`].
 	pub fn highest_not_connected_peer(&mut self, set: usize) -> Option<NotConnectedPeer> {
+		self.highest_not_connected_peer_filtered(set, |_| true)
+	}
+
+	/// Returns the peer with the highest reputation among the ones that we are not connected to
+	/// and for which `filter` returns `true`.
+	///
+	/// If multiple nodes have the same reputation, which one is returned is unspecified.
+	///
+	/// # Panic
+	///
+	/// `set` must be within range of the sets passed to [`PeersState::new`].
+	pub fn highest_not_connected_peer_filtered(
+		&mut self,
+		set: usize,
+		mut filter: impl FnMut(&PeerId) -> bool,
+	) -> Option<NotConnectedPeer> {
 		// The code below will panic anyway if this happens to be false, but this earlier assert
 		// makes it explicit what is wrong.
 		assert!(set < self.sets.len());
@@ -244,6 +260,7 @@ impl PeersState {
 			.nodes
 			.iter_mut()
 			.filter(|(_, Node { sets, .. })| sets[set].is_not_connected())
+			.filter(|(peer_id, _)| filter(peer_id))
 			.fold(None::<(&PeerId, &mut Node)>, |mut cur_node, to_try| {
 				if let Some(cur_node) = cur_node.take() {
 					if cur_node.1.reputation >= to_try.1.reputation {
@@ -362,6 +379,14 @@ impl<'a> ConnectedPeer<'a> {
 		self.peer_id.into_owned()
 	}
 
+	/// Returns `true` if we are connected to the peer through an outgoing connection.
+	pub fn is_outgoing(&self) -> bool {
+		self.state
+			.nodes
+			.get(&*self.peer_id)
+			.map_or(false, |node| node.sets[self.set] == MembershipState::Out)
+	}
+
 	/// Switches the peer to "not connected".
 	pub fn disconnect(self) -> NotConnectedPeer<'a> {
 		let is_no_slot_occupy = self.state.sets[self.set].no_slot_nodes.contains(&*self.peer_id);
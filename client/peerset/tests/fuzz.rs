@@ -23,7 +23,8 @@ use rand::{
 	seq::IteratorRandom,
 };
 use sc_peerset::{
-	DropReason, IncomingIndex, Message, Peerset, PeersetConfig, ReputationChange, SetConfig, SetId,
+	DropReason, IncomingIndex, IpLimits, Message, Peerset, PeersetConfig, ReputationChange,
+	SetConfig, SetId,
 };
 use std::{
 	collections::{HashMap, HashSet},
@@ -69,7 +70,9 @@ fn test_once() {
 			in_peers: Uniform::new_inclusive(0, 25).sample(&mut rng),
 			out_peers: Uniform::new_inclusive(0, 25).sample(&mut rng),
 			reserved_only: Uniform::new_inclusive(0, 10).sample(&mut rng) == 0,
+			anchors: Vec::new(),
 		}],
+		ip_limits: IpLimits::default(),
 	});
 
 	futures::executor::block_on(futures::future::poll_fn(move |cx| {
@@ -34,6 +34,9 @@ pub enum Error {
 	/// Other error type.
 	#[error("{0}")]
 	Other(String),
+	/// The requested data has been pruned from the database.
+	#[error("{0}")]
+	Pruned(String),
 }
 
 /// Base error code for all chain errors.
@@ -44,6 +47,8 @@ impl From<Error> for JsonRpseeError {
 		match e {
 			Error::Other(message) =>
 				CallError::Custom(ErrorObject::owned(BASE_ERROR + 1, message, None::<()>)).into(),
+			Error::Pruned(message) =>
+				CallError::Custom(ErrorObject::owned(BASE_ERROR + 2, message, None::<()>)).into(),
 			e => e.into(),
 		}
 	}
@@ -19,8 +19,19 @@
 //! Substrate child state API
 use crate::state::ReadProof;
 use jsonrpsee::{core::RpcResult, proc_macros::rpc};
+use serde::{Deserialize, Serialize};
 use sp_core::storage::{PrefixedStorageKey, StorageData, StorageKey};
 
+/// A default child trie of the state, as returned by `childstate_getChildTries`.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChildTrie<Hash> {
+	/// Prefixed storage key of the child trie.
+	pub storage_key: PrefixedStorageKey,
+	/// Root of the child trie.
+	pub root: Hash,
+}
+
 /// Substrate child state API
 ///
 /// Note that all `PrefixedStorageKey` are deserialized
@@ -50,6 +61,43 @@ pub trait ChildStateApi<Hash> {
 		hash: Option<Hash>,
 	) -> RpcResult<Vec<StorageKey>>;
 
+	/// Returns the keys with prefix from a child storage along with their values, with
+	/// pagination support.
+	/// Up to `count` pairs will be returned.
+	/// If `start_key` is passed, return next pairs in storage in lexicographic order.
+	#[method(name = "childstate_getPairsPaged", blocking)]
+	fn storage_pairs_paged(
+		&self,
+		child_storage_key: PrefixedStorageKey,
+		prefix: Option<StorageKey>,
+		count: u32,
+		start_key: Option<StorageKey>,
+		hash: Option<Hash>,
+	) -> RpcResult<Vec<(StorageKey, StorageData)>>;
+
+	/// Returns the default child tries at a block's state with their roots, with pagination
+	/// support.
+	/// Up to `count` child tries will be returned.
+	/// If `start_key` is passed, return next child tries in lexicographic order.
+	#[method(name = "childstate_getChildTries", blocking)]
+	fn child_tries(
+		&self,
+		start_key: Option<PrefixedStorageKey>,
+		count: u32,
+		hash: Option<Hash>,
+	) -> RpcResult<Vec<ChildTrie<Hash>>>;
+
+	/// Returns the root of a child storage at a block's state.
+	///
+	/// The root is stored in the parent storage under the child storage key, so
+	/// `state_getReadProof` proves it.
+	#[method(name = "childstate_getRoot", blocking)]
+	fn root(
+		&self,
+		child_storage_key: PrefixedStorageKey,
+		hash: Option<Hash>,
+	) -> RpcResult<Option<Hash>>;
+
 	/// Returns a child storage entry at a specific block's state.
 	#[method(name = "childstate_getStorage", blocking)]
 	fn storage(
@@ -94,4 +142,26 @@ pub trait ChildStateApi<Hash> {
 		keys: Vec<StorageKey>,
 		hash: Option<Hash>,
 	) -> RpcResult<ReadProof<Hash>>;
+
+	/// Returns proof of the child storage pairs returned by `childstate_getPairsPaged` for the
+	/// same parameters.
+	#[method(name = "childstate_getPairsProof", blocking)]
+	fn read_child_pairs_proof(
+		&self,
+		child_storage_key: PrefixedStorageKey,
+		prefix: Option<StorageKey>,
+		count: u32,
+		start_key: Option<StorageKey>,
+		hash: Option<Hash>,
+	) -> RpcResult<ReadProof<Hash>>;
+
+	/// Returns proof of the child tries returned by `childstate_getChildTries` for the same
+	/// parameters.
+	#[method(name = "childstate_getChildTriesProof", blocking)]
+	fn read_child_tries_proof(
+		&self,
+		start_key: Option<PrefixedStorageKey>,
+		count: u32,
+		hash: Option<Hash>,
+	) -> RpcResult<ReadProof<Hash>>;
 }
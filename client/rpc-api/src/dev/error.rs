@@ -35,6 +35,21 @@ pub enum Error {
 	/// The witness compaction failed.
 	#[error("Failed to create to compact the witness")]
 	WitnessCompactionFailed,
+	/// Taking a snapshot of the database failed.
+	#[error("Failed to create the snapshot: {0}")]
+	SnapshotFailed(Box<dyn std::error::Error + Send>),
+	/// The node has no snapshot directory.
+	#[error("Snapshots are disabled on this node")]
+	SnapshotsDisabled,
+	/// The snapshot name isn't a plain directory name.
+	#[error("Invalid snapshot name {0:?}, expected a directory name")]
+	InvalidSnapshotName(String),
+	/// Another snapshot is being taken.
+	#[error("A snapshot is already being taken")]
+	SnapshotInProgress,
+	/// The node doesn't tally the accesses per storage item.
+	#[error("Storage item statistics are disabled on this node")]
+	StorageStatsDisabled,
 	/// The method is marked as unsafe but unsafe flag wasn't supplied on the CLI.
 	#[error(transparent)]
 	UnsafeRpcCalled(#[from] crate::policy::UnsafeRpcError),
@@ -54,6 +69,16 @@ impl From<Error> for JsonRpseeError {
 				CallError::Custom(ErrorObject::owned(BASE_ERROR + 3, msg, None::<()>)),
 			Error::WitnessCompactionFailed =>
 				CallError::Custom(ErrorObject::owned(BASE_ERROR + 4, msg, None::<()>)),
+			Error::SnapshotFailed(_) =>
+				CallError::Custom(ErrorObject::owned(BASE_ERROR + 5, msg, None::<()>)),
+			Error::SnapshotsDisabled =>
+				CallError::Custom(ErrorObject::owned(BASE_ERROR + 6, msg, None::<()>)),
+			Error::InvalidSnapshotName(_) =>
+				CallError::Custom(ErrorObject::owned(BASE_ERROR + 7, msg, None::<()>)),
+			Error::SnapshotInProgress =>
+				CallError::Custom(ErrorObject::owned(BASE_ERROR + 8, msg, None::<()>)),
+			Error::StorageStatsDisabled =>
+				CallError::Custom(ErrorObject::owned(BASE_ERROR + 9, msg, None::<()>)),
 			Error::UnsafeRpcCalled(e) => e.into(),
 		}
 		.into()
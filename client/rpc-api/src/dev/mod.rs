@@ -26,6 +26,7 @@ use codec::{Decode, Encode};
 use jsonrpsee::{core::RpcResult, proc_macros::rpc};
 use scale_info::TypeInfo;
 use serde::{Deserialize, Serialize};
+use sp_core::Bytes;
 
 /// Statistics of a block returned by the `dev_getBlockStats` RPC.
 #[derive(Eq, PartialEq, Clone, Copy, Encode, Decode, Debug, TypeInfo, Serialize, Deserialize)]
@@ -47,6 +48,28 @@ pub struct BlockStats {
 	pub num_extrinsics: u64,
 }
 
+/// Access statistics of a storage item returned by the `dev_storageStats` RPC.
+#[derive(Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageItemStats {
+	/// The key prefix of the storage item, `twox128(pallet) ++ twox128(item)`.
+	pub prefix: Bytes,
+	/// Name of the pallet, if the runtime metadata declares the prefix.
+	pub pallet: Option<String>,
+	/// Name of the storage item, if the runtime metadata declares the prefix.
+	pub item: Option<String>,
+	/// Number of key reads.
+	pub reads: u64,
+	/// Number of key reads served by the state cache.
+	pub cache_reads: u64,
+	/// Bytes read, keys included.
+	pub bytes_read: u64,
+	/// Number of key writes, removals included.
+	pub writes: u64,
+	/// Bytes written, keys included.
+	pub bytes_written: u64,
+}
+
 /// Substrate dev API.
 ///
 /// This API contains unstable and unsafe methods only meant for development nodes. They
@@ -60,4 +83,23 @@ pub trait DevApi<Hash> {
 	/// this function will return `None`.
 	#[method(name = "dev_getBlockStats")]
 	fn block_stats(&self, block_hash: Hash) -> RpcResult<Option<BlockStats>>;
+
+	/// Start taking a consistent snapshot of the last finalized block, named `name`, in the
+	/// snapshot directory of the node.
+	///
+	/// The snapshot contains the state of the block and the finalized blocks up to it, and can
+	/// initialise the database of another node with the `import-snapshot` command. It is taken in
+	/// the background, one at a time, and is complete once its `metadata` file exists. Returns
+	/// the hash of the snapshot block, which that command expects.
+	#[method(name = "dev_createSnapshot", blocking)]
+	fn create_snapshot(&self, name: String) -> RpcResult<Hash>;
+
+	/// Returns the accesses of the storage items during block import and RPC queries, since the
+	/// start of the node.
+	///
+	/// Items are keyed by the first 32 bytes of their keys and named after the metadata of the
+	/// runtime at the best block. The most accessed items come first. Fails unless the node
+	/// tallies them, see `--storage-item-stats`.
+	#[method(name = "dev_storageStats", blocking)]
+	fn storage_stats(&self) -> RpcResult<Vec<StorageItemStats>>;
 }
@@ -30,3 +30,13 @@ pub struct ReadProof<Hash> {
 	/// A proof used to prove that storage entries are included in the storage trie
 	pub proof: Vec<Bytes>,
 }
+
+/// Compact read proofs of several blocks returned by the RPC
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiBlockReadProof<Hash> {
+	/// Block hashes used to generate the proofs, in order
+	pub at: Vec<Hash>,
+	/// Compact encoded trie nodes of each block, omitting those of the previous blocks
+	pub proofs: Vec<Vec<Bytes>>,
+}
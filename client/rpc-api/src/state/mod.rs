@@ -28,7 +28,7 @@ use sp_version::RuntimeVersion;
 pub mod error;
 pub mod helpers;
 
-pub use self::helpers::ReadProof;
+pub use self::helpers::{MultiBlockReadProof, ReadProof};
 
 /// Substrate state API
 #[rpc(client, server)]
@@ -95,6 +95,20 @@ pub trait StateApi<Hash> {
 		hash: Option<Hash>,
 	) -> RpcResult<Vec<StorageChangeSet<Hash>>>;
 
+	/// Query the history of a storage entry (by key) starting from a block given as the second
+	/// parameter.
+	///
+	/// NOTE The first returned result contains the initial value of the entry. Subsequent values
+	/// are the new values at the blocks where the entry has changed, up to the block given as the
+	/// third parameter or the best block. Nodes indexing the state changes only read these blocks.
+	#[method(name = "state_keyHistory", blocking)]
+	fn key_history(
+		&self,
+		key: StorageKey,
+		block: Hash,
+		hash: Option<Hash>,
+	) -> RpcResult<Vec<StorageChangeSet<Hash>>>;
+
 	/// Query storage entries (by key) starting at block hash given as the second parameter.
 	#[method(name = "state_queryStorageAt", blocking)]
 	fn query_storage_at(
@@ -107,6 +121,31 @@ pub trait StateApi<Hash> {
 	#[method(name = "state_getReadProof", blocking)]
 	fn read_proof(&self, keys: Vec<StorageKey>, hash: Option<Hash>) -> RpcResult<ReadProof<Hash>>;
 
+	/// Returns proof of all the storage entries with keys starting with `prefix`, from
+	/// `start_key` inclusively to `end_key` exclusively, at a specific block's state.
+	///
+	/// Checking the proof shows that the range contains exactly the proven entries. Large
+	/// ranges are only proven up to some key, from which the next part can be requested.
+	#[method(name = "state_getRangeProof", blocking)]
+	fn read_range_proof(
+		&self,
+		prefix: Option<StorageKey>,
+		start_key: Option<StorageKey>,
+		end_key: Option<StorageKey>,
+		hash: Option<Hash>,
+	) -> RpcResult<ReadProof<Hash>>;
+
+	/// Returns compact proofs of storage entries at several blocks' states.
+	///
+	/// The proof of each block omits the trie nodes contained in the proofs of the previous
+	/// blocks, so proving the same keys over consecutive blocks mostly shares the nodes.
+	#[method(name = "state_getMultiBlockReadProof", blocking)]
+	fn read_multi_block_proof(
+		&self,
+		keys: Vec<StorageKey>,
+		blocks: Vec<Hash>,
+	) -> RpcResult<MultiBlockReadProof<Hash>>;
+
 	/// New runtime version subscription
 	#[subscription(
 		name = "state_subscribeRuntimeVersion" => "state_runtimeVersion",
@@ -285,4 +324,24 @@ pub trait StateApi<Hash> {
 		storage_keys: Option<String>,
 		methods: Option<String>,
 	) -> RpcResult<sp_rpc::tracing::TraceBlockResponse>;
+
+	/// The `profileBlock` RPC re-executes a single block in wasm and returns how many times
+	/// the runtime called each host function, the time spent in it and the bytes it moved
+	/// between the host and the wasm memory, together with the time spent executing wasm code.
+	///
+	/// The response also contains the profile in the folded stacks format, which can be saved
+	/// to a file and rendered by flamegraph tools, e.g.:
+	///
+	/// ```text
+	/// curl \
+	/// 	-H "Content-Type: application/json" \
+	/// 	-d '{"id":1, "jsonrpc":"2.0", "method": "state_profileBlock", \
+	/// 		"params": ["0xb246acf1adea1f801ce15c77a5fa7d8f2eb8fed466978bcee172cc02cf64e264"]}' \
+	/// 	http://localhost:9933/ | jq -r .result.foldedStacks | inferno-flamegraph > block.svg
+	/// ```
+	///
+	/// Note: requires the node to run with `--rpc-methods=Unsafe` and
+	/// `--wasm-host-function-profiling`.
+	#[method(name = "state_profileBlock", blocking)]
+	fn profile_block(&self, block: Hash) -> RpcResult<sp_rpc::profiling::ProfileBlockResponse>;
 }
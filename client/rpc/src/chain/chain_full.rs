@@ -28,7 +28,7 @@ use futures::{
 };
 use jsonrpsee::PendingSubscription;
 use sc_client_api::{BlockBackend, BlockchainEvents};
-use sp_blockchain::HeaderBackend;
+use sp_blockchain::{HeaderBackend, PrunableBlockData};
 use sp_runtime::{
 	generic::{BlockId, SignedBlock},
 	traits::Block as BlockT,
@@ -51,6 +51,28 @@ impl<Block: BlockT, Client> FullChain<Block, Client> {
 	}
 }
 
+impl<Block, Client> FullChain<Block, Client>
+where
+	Block: BlockT,
+	Client: BlockBackend<Block> + HeaderBackend<Block>,
+{
+	/// Returns an error if the given data of the block has been pruned from the database.
+	fn ensure_not_pruned(&self, hash: Block::Hash, data: PrunableBlockData) -> Result<(), Error> {
+		let pruned_below = match self.client.pruned_below(data) {
+			Some(pruned_below) => pruned_below,
+			None => return Ok(()),
+		};
+
+		match self.client.number(hash).map_err(client_err)? {
+			Some(number) if number < pruned_below => Err(Error::Pruned(format!(
+				"The {} of block {} has been pruned, only the ones of blocks from #{} are kept",
+				data, hash, pruned_below
+			))),
+			_ => Ok(()),
+		}
+	}
+}
+
 impl<Block, Client> ChainBackend<Client, Block> for FullChain<Block, Client>
 where
 	Block: BlockT + 'static,
@@ -62,11 +84,22 @@ where
 	}
 
 	fn header(&self, hash: Option<Block::Hash>) -> Result<Option<Block::Header>, Error> {
-		self.client.header(BlockId::Hash(self.unwrap_or_best(hash))).map_err(client_err)
+		let hash = self.unwrap_or_best(hash);
+		let header = self.client.header(BlockId::Hash(hash)).map_err(client_err)?;
+		if header.is_none() {
+			self.ensure_not_pruned(hash, PrunableBlockData::Header)?;
+		}
+		Ok(header)
 	}
 
 	fn block(&self, hash: Option<Block::Hash>) -> Result<Option<SignedBlock<Block>>, Error> {
-		self.client.block(&BlockId::Hash(self.unwrap_or_best(hash))).map_err(client_err)
+		let hash = self.unwrap_or_best(hash);
+		let block = self.client.block(&BlockId::Hash(hash)).map_err(client_err)?;
+		if block.is_none() {
+			self.ensure_not_pruned(hash, PrunableBlockData::Header)?;
+			self.ensure_not_pruned(hash, PrunableBlockData::Body)?;
+		}
+		Ok(block)
 	}
 
 	fn subscribe_all_heads(&self, sink: PendingSubscription) {
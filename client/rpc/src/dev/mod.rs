@@ -22,48 +22,81 @@
 #[cfg(test)]
 mod tests;
 
+use crate::SubscriptionTaskExecutor;
+use codec::Decode;
+use frame_metadata::{RuntimeMetadata, RuntimeMetadataPrefixed};
+use futures::FutureExt;
 use jsonrpsee::core::RpcResult;
-use sc_client_api::{BlockBackend, HeaderBackend};
+use log::{info, warn};
+use sc_client_api::{BlockBackend, HeaderBackend, STORAGE_ITEM_PREFIX_LEN};
+use sc_client_db::snapshot;
 use sc_rpc_api::{dev::error::Error, DenyUnsafe};
-use sp_api::{ApiExt, Core, ProvideRuntimeApi};
-use sp_core::Encode;
+use sp_api::{ApiExt, Core, Metadata, ProvideRuntimeApi};
+use sp_core::{hashing::twox_128, Bytes, Encode};
 use sp_runtime::{
 	generic::{BlockId, DigestItem},
 	traits::{Block as BlockT, Header},
 };
 use std::{
+	collections::HashMap,
+	io,
 	marker::{PhantomData, Send, Sync},
-	sync::Arc,
+	path::{Component, Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
 };
 
-pub use sc_rpc_api::dev::{BlockStats, DevApiServer};
+pub use sc_rpc_api::dev::{BlockStats, DevApiServer, StorageItemStats};
 
 type HasherOf<Block> = <<Block as BlockT>::Header as Header>::Hashing;
 
 /// The Dev API. All methods are unsafe.
-pub struct Dev<Block: BlockT, Client> {
+pub struct Dev<Block: BlockT, Client, Backend> {
 	client: Arc<Client>,
+	backend: Arc<Backend>,
+	/// Directory the snapshots are taken in, snapshots are disabled without one.
+	snapshot_dir: Option<PathBuf>,
+	/// Whether a snapshot is being taken.
+	snapshot_running: Arc<AtomicBool>,
+	executor: SubscriptionTaskExecutor,
 	deny_unsafe: DenyUnsafe,
 	_phantom: PhantomData<Block>,
 }
 
-impl<Block: BlockT, Client> Dev<Block, Client> {
+impl<Block: BlockT, Client, Backend> Dev<Block, Client, Backend> {
 	/// Create a new Dev API.
-	pub fn new(client: Arc<Client>, deny_unsafe: DenyUnsafe) -> Self {
-		Self { client, deny_unsafe, _phantom: PhantomData::default() }
+	pub fn new(
+		client: Arc<Client>,
+		backend: Arc<Backend>,
+		snapshot_dir: Option<PathBuf>,
+		executor: SubscriptionTaskExecutor,
+		deny_unsafe: DenyUnsafe,
+	) -> Self {
+		Self {
+			client,
+			backend,
+			snapshot_dir,
+			snapshot_running: Default::default(),
+			executor,
+			deny_unsafe,
+			_phantom: PhantomData::default(),
+		}
 	}
 }
 
-impl<Block, Client> DevApiServer<Block::Hash> for Dev<Block, Client>
+impl<Block, Client, Backend> DevApiServer<Block::Hash> for Dev<Block, Client, Backend>
 where
 	Block: BlockT + 'static,
+	Backend: sc_client_api::Backend<Block> + Send + Sync + 'static,
 	Client: BlockBackend<Block>
 		+ HeaderBackend<Block>
 		+ ProvideRuntimeApi<Block>
 		+ Send
 		+ Sync
 		+ 'static,
-	Client::Api: Core<Block>,
+	Client::Api: Core<Block> + Metadata<Block>,
 {
 	fn block_stats(&self, hash: Block::Hash) -> RpcResult<Option<BlockStats>> {
 		self.deny_unsafe.check_if_safe()?;
@@ -113,4 +146,102 @@ where
 			.encoded_size() as u64;
 		Ok(Some(BlockStats { witness_len, witness_compact_len, block_len, num_extrinsics }))
 	}
+
+	fn create_snapshot(&self, name: String) -> RpcResult<Block::Hash> {
+		self.deny_unsafe.check_if_safe()?;
+
+		let snapshot_dir = self.snapshot_dir.as_ref().ok_or(Error::SnapshotsDisabled)?;
+		// Snapshots can't be written outside of the snapshot directory.
+		let mut components = Path::new(&name).components();
+		if !matches!((components.next(), components.next()), (Some(Component::Normal(_)), None)) {
+			return Err(Error::InvalidSnapshotName(name).into())
+		}
+		let path = snapshot_dir.join(&name);
+		if path.exists() {
+			let err = io::Error::new(io::ErrorKind::AlreadyExists, "snapshot already exists");
+			return Err(Error::SnapshotFailed(Box::new(err)).into())
+		}
+
+		let hash = self.client.info().finalized_hash;
+		let state_version = self
+			.client
+			.runtime_api()
+			.version(&BlockId::Hash(hash))
+			.map_err(|e| Error::BlockQueryError(Box::new(e)))?
+			.state_version();
+
+		if self.snapshot_running.swap(true, Ordering::SeqCst) {
+			return Err(Error::SnapshotInProgress.into())
+		}
+		let running = self.snapshot_running.clone();
+		let backend = self.backend.clone();
+		let export = async move {
+			match snapshot::export(&*backend, &path, hash, state_version) {
+				Ok(_) => info!("Snapshot {} complete", path.display()),
+				Err(e) => warn!("Failed to take snapshot {}: {}", path.display(), e),
+			}
+			running.store(false, Ordering::SeqCst);
+		};
+		self.executor.spawn_blocking("dev-snapshot", Some("rpc"), export.boxed());
+		Ok(hash)
+	}
+
+	fn storage_stats(&self) -> RpcResult<Vec<StorageItemStats>> {
+		self.deny_unsafe.check_if_safe()?;
+
+		let usage = self.backend.storage_item_usage().ok_or(Error::StorageStatsDisabled)?;
+		// Items stay unnamed if the runtime doesn't provide its metadata.
+		let names = self
+			.client
+			.runtime_api()
+			.metadata(&BlockId::Hash(self.client.info().best_hash))
+			.map(|metadata| storage_item_names(&metadata))
+			.unwrap_or_default();
+		let mut stats = usage
+			.into_iter()
+			.map(|usage| {
+				let (pallet, item) = match names.get(&usage.prefix) {
+					Some((pallet, item)) => (Some(pallet.clone()), Some(item.clone())),
+					None => (None, None),
+				};
+				StorageItemStats {
+					prefix: Bytes(usage.prefix.to_vec()),
+					pallet,
+					item,
+					reads: usage.reads,
+					cache_reads: usage.cache_reads,
+					bytes_read: usage.bytes_read,
+					writes: usage.writes,
+					bytes_written: usage.bytes_written,
+				}
+			})
+			.collect::<Vec<_>>();
+		stats.sort_by(|a, b| (b.reads + b.writes).cmp(&(a.reads + a.writes)));
+		Ok(stats)
+	}
+}
+
+/// Resolve the storage item prefixes declared by the given runtime metadata to the names
+/// of their pallet and item.
+///
+/// `metadata` is the SCALE encoded metadata returned by the `Metadata` runtime API. Only
+/// metadata V14 is understood; an empty map is returned for anything else.
+fn storage_item_names(
+	metadata: &[u8],
+) -> HashMap<[u8; STORAGE_ITEM_PREFIX_LEN], (String, String)> {
+	let mut names = HashMap::new();
+	let metadata = match RuntimeMetadataPrefixed::decode(&mut &metadata[..]) {
+		Ok(RuntimeMetadataPrefixed(_, RuntimeMetadata::V14(metadata))) => metadata,
+		_ => return names,
+	};
+	for storage in metadata.pallets.into_iter().filter_map(|pallet| pallet.storage) {
+		let pallet_hash = twox_128(storage.prefix.as_bytes());
+		for entry in storage.entries {
+			let mut prefix = [0u8; STORAGE_ITEM_PREFIX_LEN];
+			prefix[..16].copy_from_slice(&pallet_hash);
+			prefix[16..].copy_from_slice(&twox_128(entry.name.as_bytes()));
+			names.insert(prefix, (storage.prefix.clone(), entry.name));
+		}
+	}
+	names
 }
@@ -17,17 +17,25 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
 use super::*;
+use crate::testing::{test_executor, timeout_secs};
 use assert_matches::assert_matches;
-use jsonrpsee::{core::Error as JsonRpseeError, types::error::CallError};
+use jsonrpsee::{
+	core::Error as JsonRpseeError,
+	types::{error::CallError, EmptyParams},
+};
 use sc_block_builder::BlockBuilderProvider;
 use sp_blockchain::HeaderBackend;
 use sp_consensus::BlockOrigin;
+use sp_core::{hashing::twox_128, H256};
 use substrate_test_runtime_client::{prelude::*, runtime::Block};
 
 #[tokio::test]
 async fn block_stats_work() {
-	let mut client = Arc::new(substrate_test_runtime_client::new());
-	let api = <Dev<Block, _>>::new(client.clone(), DenyUnsafe::No).into_rpc();
+	let (client, backend) = TestClientBuilder::new().build_with_backend();
+	let mut client = Arc::new(client);
+	let api =
+		<Dev<Block, _, _>>::new(client.clone(), backend, None, test_executor(), DenyUnsafe::No)
+			.into_rpc();
 
 	let block = client.new_block(Default::default()).unwrap().build().unwrap().block;
 	client.import(BlockOrigin::Own, block).await.unwrap();
@@ -55,8 +63,11 @@ async fn block_stats_work() {
 
 #[tokio::test]
 async fn deny_unsafe_works() {
-	let mut client = Arc::new(substrate_test_runtime_client::new());
-	let api = <Dev<Block, _>>::new(client.clone(), DenyUnsafe::Yes).into_rpc();
+	let (client, backend) = TestClientBuilder::new().build_with_backend();
+	let mut client = Arc::new(client);
+	let api =
+		<Dev<Block, _, _>>::new(client.clone(), backend, None, test_executor(), DenyUnsafe::Yes)
+			.into_rpc();
 
 	let block = client.new_block(Default::default()).unwrap().build().unwrap().block;
 	client.import(BlockOrigin::Own, block).await.unwrap();
@@ -67,3 +78,94 @@ async fn deny_unsafe_works() {
 		Err(JsonRpseeError::Call(CallError::Custom(err))) if err.message().contains("RPC call is unsafe to be called externally")
 	);
 }
+
+#[tokio::test]
+async fn create_snapshot_works() {
+	let (client, backend) = TestClientBuilder::new().build_with_backend();
+	let mut client = Arc::new(client);
+	let dir = tempfile::tempdir().unwrap();
+	let api = <Dev<Block, _, _>>::new(
+		client.clone(),
+		backend,
+		Some(dir.path().to_owned()),
+		test_executor(),
+		DenyUnsafe::No,
+	)
+	.into_rpc();
+
+	let block = client.new_block(Default::default()).unwrap().build().unwrap().block;
+	let hash = block.header.hash();
+	client.import_as_final(BlockOrigin::Own, block).await.unwrap();
+
+	assert_eq!(api.call::<_, H256>("dev_createSnapshot", ["snapshot"]).await.unwrap(), hash);
+
+	// The snapshot is taken in the background.
+	let path = dir.path().join("snapshot");
+	timeout_secs(10, async {
+		while sc_client_db::snapshot::Snapshot::<Block>::open(&path).is_err() {
+			tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+		}
+	})
+	.await
+	.unwrap();
+	let snapshot = sc_client_db::snapshot::Snapshot::<Block>::open(&path).unwrap();
+	assert_eq!(snapshot.verify().unwrap().header.hash(), hash);
+
+	// Snapshots aren't overwritten.
+	assert_matches!(
+		api.call::<_, H256>("dev_createSnapshot", ["snapshot"]).await,
+		Err(JsonRpseeError::Call(CallError::Custom(err))) if err.message().contains("already exists")
+	);
+}
+
+#[tokio::test]
+async fn create_snapshot_stays_in_snapshot_dir() {
+	let (client, backend) = TestClientBuilder::new().build_with_backend();
+	let client = Arc::new(client);
+	let dir = tempfile::tempdir().unwrap();
+	let api = <Dev<Block, _, _>>::new(
+		client.clone(),
+		backend.clone(),
+		Some(dir.path().join("snapshots")),
+		test_executor(),
+		DenyUnsafe::No,
+	)
+	.into_rpc();
+
+	for name in ["../snapshot", "/tmp/snapshot", "nested/snapshot", ".", ""] {
+		assert_matches!(
+			api.call::<_, H256>("dev_createSnapshot", [name]).await,
+			Err(JsonRpseeError::Call(CallError::Custom(err))) if err.message().contains("Invalid snapshot name")
+		);
+	}
+
+	let api = <Dev<Block, _, _>>::new(client, backend, None, test_executor(), DenyUnsafe::No)
+		.into_rpc();
+	assert_matches!(
+		api.call::<_, H256>("dev_createSnapshot", ["snapshot"]).await,
+		Err(JsonRpseeError::Call(CallError::Custom(err))) if err.message().contains("disabled")
+	);
+}
+
+#[tokio::test]
+async fn storage_stats_work() {
+	let (client, backend) = TestClientBuilder::new().build_with_backend();
+	let mut client = Arc::new(client);
+	let api =
+		<Dev<Block, _, _>>::new(client.clone(), backend, None, test_executor(), DenyUnsafe::No)
+			.into_rpc();
+
+	let block = client.new_block(Default::default()).unwrap().build().unwrap().block;
+	client.import(BlockOrigin::Own, block).await.unwrap();
+
+	let number = [twox_128(b"TestRuntime"), twox_128(b"Number")].concat();
+	let stats = api
+		.call::<_, Vec<StorageItemStats>>("dev_storageStats", EmptyParams::new())
+		.await
+		.unwrap();
+	let stats = stats.iter().find(|stats| stats.prefix.0 == number).unwrap();
+	assert!(stats.writes > 0);
+	assert!(stats.bytes_written > 0);
+	// The test runtime doesn't provide its metadata.
+	assert_eq!(stats.pallet, None);
+}
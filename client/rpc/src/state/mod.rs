@@ -51,6 +51,7 @@ pub use sc_rpc_api::{child_state::*, state::*};
 use sp_blockchain::{HeaderBackend, HeaderMetadata};
 
 const STORAGE_KEYS_PAGED_MAX_COUNT: u32 = 1000;
+const MULTI_BLOCK_PROOF_MAX_BLOCKS: u32 = 256;
 
 /// State backend API.
 pub trait StateBackend<Block: BlockT, Client>: Send + Sync + 'static
@@ -138,6 +139,15 @@ where
 		at: Option<Block::Hash>,
 	) -> Result<Vec<StorageChangeSet<Block::Hash>>, Error>;
 
+	/// Query the history of a storage entry (by key) starting from a block given as the first
+	/// parameter.
+	fn key_history(
+		&self,
+		from: Block::Hash,
+		to: Option<Block::Hash>,
+		key: StorageKey,
+	) -> Result<Vec<StorageChangeSet<Block::Hash>>, Error>;
+
 	/// Returns proof of storage entries at a specific block's state.
 	fn read_proof(
 		&self,
@@ -145,6 +155,22 @@ where
 		keys: Vec<StorageKey>,
 	) -> Result<ReadProof<Block::Hash>, Error>;
 
+	/// Returns proof of all the storage entries of a range at a specific block's state.
+	fn read_range_proof(
+		&self,
+		block: Option<Block::Hash>,
+		prefix: Option<StorageKey>,
+		start_key: Option<StorageKey>,
+		end_key: Option<StorageKey>,
+	) -> Result<ReadProof<Block::Hash>, Error>;
+
+	/// Returns compact proofs of storage entries at several blocks' states.
+	fn read_multi_block_proof(
+		&self,
+		blocks: Vec<Block::Hash>,
+		keys: Vec<StorageKey>,
+	) -> Result<MultiBlockReadProof<Block::Hash>, Error>;
+
 	/// Trace storage changes for block
 	fn trace_block(
 		&self,
@@ -154,6 +180,12 @@ where
 		methods: Option<String>,
 	) -> Result<sp_rpc::tracing::TraceBlockResponse, Error>;
 
+	/// Profile the host function calls of block execution
+	fn profile_block(
+		&self,
+		block: Block::Hash,
+	) -> Result<sp_rpc::profiling::ProfileBlockResponse, Error>;
+
 	/// New runtime version subscription
 	fn subscribe_runtime_version(&self, sink: PendingSubscription);
 
@@ -292,6 +324,16 @@ where
 		self.backend.query_storage_at(keys, at).map_err(Into::into)
 	}
 
+	fn key_history(
+		&self,
+		key: StorageKey,
+		from: Block::Hash,
+		to: Option<Block::Hash>,
+	) -> RpcResult<Vec<StorageChangeSet<Block::Hash>>> {
+		self.deny_unsafe.check_if_safe()?;
+		self.backend.key_history(from, to, key).map_err(Into::into)
+	}
+
 	fn read_proof(
 		&self,
 		keys: Vec<StorageKey>,
@@ -300,6 +342,32 @@ where
 		self.backend.read_proof(block, keys).map_err(Into::into)
 	}
 
+	fn read_range_proof(
+		&self,
+		prefix: Option<StorageKey>,
+		start_key: Option<StorageKey>,
+		end_key: Option<StorageKey>,
+		block: Option<Block::Hash>,
+	) -> RpcResult<ReadProof<Block::Hash>> {
+		self.backend
+			.read_range_proof(block, prefix, start_key, end_key)
+			.map_err(Into::into)
+	}
+
+	fn read_multi_block_proof(
+		&self,
+		keys: Vec<StorageKey>,
+		blocks: Vec<Block::Hash>,
+	) -> RpcResult<MultiBlockReadProof<Block::Hash>> {
+		if blocks.len() > MULTI_BLOCK_PROOF_MAX_BLOCKS as usize {
+			return Err(JsonRpseeError::from(Error::InvalidCount {
+				value: blocks.len() as u32,
+				max: MULTI_BLOCK_PROOF_MAX_BLOCKS,
+			}))
+		}
+		self.backend.read_multi_block_proof(blocks, keys).map_err(Into::into)
+	}
+
 	/// Re-execute the given block with the tracing targets given in `targets`
 	/// and capture all state changes.
 	///
@@ -318,6 +386,18 @@ where
 			.map_err(Into::into)
 	}
 
+	/// Re-execute the given block in wasm and profile the host functions it calls.
+	///
+	/// Note: requires the node to run with `--rpc-methods=Unsafe`.
+	/// Note: requires the node to run with `--wasm-host-function-profiling`.
+	fn profile_block(
+		&self,
+		block: Block::Hash,
+	) -> RpcResult<sp_rpc::profiling::ProfileBlockResponse> {
+		self.deny_unsafe.check_if_safe()?;
+		self.backend.profile_block(block).map_err(Into::into)
+	}
+
 	fn subscribe_runtime_version(&self, sink: PendingSubscription) {
 		self.backend.subscribe_runtime_version(sink)
 	}
@@ -367,6 +447,51 @@ where
 		start_key: Option<StorageKey>,
 	) -> Result<Vec<StorageKey>, Error>;
 
+	/// Returns the keys with prefix from a child storage along with their values, with
+	/// pagination support.
+	fn storage_pairs_paged(
+		&self,
+		block: Option<Block::Hash>,
+		storage_key: PrefixedStorageKey,
+		prefix: Option<StorageKey>,
+		count: u32,
+		start_key: Option<StorageKey>,
+	) -> Result<Vec<(StorageKey, StorageData)>, Error>;
+
+	/// Returns the default child tries at a block's state with their roots, with pagination
+	/// support.
+	fn child_tries(
+		&self,
+		block: Option<Block::Hash>,
+		start_key: Option<PrefixedStorageKey>,
+		count: u32,
+	) -> Result<Vec<ChildTrie<Block::Hash>>, Error>;
+
+	/// Returns the root of a child storage at a block's state.
+	fn root(
+		&self,
+		block: Option<Block::Hash>,
+		storage_key: PrefixedStorageKey,
+	) -> Result<Option<Block::Hash>, Error>;
+
+	/// Returns proof of the child storage pairs returned by `storage_pairs_paged`.
+	fn read_child_pairs_proof(
+		&self,
+		block: Option<Block::Hash>,
+		storage_key: PrefixedStorageKey,
+		prefix: Option<StorageKey>,
+		count: u32,
+		start_key: Option<StorageKey>,
+	) -> Result<ReadProof<Block::Hash>, Error>;
+
+	/// Returns proof of the child tries returned by `child_tries`.
+	fn read_child_tries_proof(
+		&self,
+		block: Option<Block::Hash>,
+		start_key: Option<PrefixedStorageKey>,
+		count: u32,
+	) -> Result<ReadProof<Block::Hash>, Error>;
+
 	/// Returns a child storage entry at a specific block's state.
 	fn storage(
 		&self,
@@ -480,6 +605,69 @@ where
 			.read_child_proof(block, child_storage_key, keys)
 			.map_err(Into::into)
 	}
+
+	fn storage_pairs_paged(
+		&self,
+		storage_key: PrefixedStorageKey,
+		prefix: Option<StorageKey>,
+		count: u32,
+		start_key: Option<StorageKey>,
+		block: Option<Block::Hash>,
+	) -> RpcResult<Vec<(StorageKey, StorageData)>> {
+		check_paged_count(count)?;
+		self.backend
+			.storage_pairs_paged(block, storage_key, prefix, count, start_key)
+			.map_err(Into::into)
+	}
+
+	fn child_tries(
+		&self,
+		start_key: Option<PrefixedStorageKey>,
+		count: u32,
+		block: Option<Block::Hash>,
+	) -> RpcResult<Vec<ChildTrie<Block::Hash>>> {
+		check_paged_count(count)?;
+		self.backend.child_tries(block, start_key, count).map_err(Into::into)
+	}
+
+	fn root(
+		&self,
+		storage_key: PrefixedStorageKey,
+		block: Option<Block::Hash>,
+	) -> RpcResult<Option<Block::Hash>> {
+		self.backend.root(block, storage_key).map_err(Into::into)
+	}
+
+	fn read_child_pairs_proof(
+		&self,
+		storage_key: PrefixedStorageKey,
+		prefix: Option<StorageKey>,
+		count: u32,
+		start_key: Option<StorageKey>,
+		block: Option<Block::Hash>,
+	) -> RpcResult<ReadProof<Block::Hash>> {
+		check_paged_count(count)?;
+		self.backend
+			.read_child_pairs_proof(block, storage_key, prefix, count, start_key)
+			.map_err(Into::into)
+	}
+
+	fn read_child_tries_proof(
+		&self,
+		start_key: Option<PrefixedStorageKey>,
+		count: u32,
+		block: Option<Block::Hash>,
+	) -> RpcResult<ReadProof<Block::Hash>> {
+		check_paged_count(count)?;
+		self.backend.read_child_tries_proof(block, start_key, count).map_err(Into::into)
+	}
+}
+
+fn check_paged_count(count: u32) -> Result<(), Error> {
+	if count > STORAGE_KEYS_PAGED_MAX_COUNT {
+		return Err(Error::InvalidCount { value: count, max: STORAGE_KEYS_PAGED_MAX_COUNT })
+	}
+	Ok(())
 }
 
 fn client_err(err: sp_blockchain::Error) -> Error {
@@ -23,17 +23,18 @@ use std::{collections::HashMap, marker::PhantomData, sync::Arc};
 use super::{
 	client_err,
 	error::{Error, Result},
-	ChildStateBackend, StateBackend,
+	ChildStateBackend, ChildTrie, StateBackend,
 };
 use crate::SubscriptionTaskExecutor;
 
+use codec::Decode;
 use futures::{future, stream, FutureExt, StreamExt};
 use jsonrpsee::{core::Error as JsonRpseeError, PendingSubscription};
 use sc_client_api::{
 	Backend, BlockBackend, BlockchainEvents, CallExecutor, ExecutorProvider, ProofProvider,
 	StorageProvider,
 };
-use sc_rpc_api::state::ReadProof;
+use sc_rpc_api::state::{MultiBlockReadProof, ReadProof};
 use sp_api::{CallApiAt, Metadata, ProvideRuntimeApi};
 use sp_blockchain::{
 	CachedHeaderMetadata, Error as ClientError, HeaderBackend, HeaderMetadata,
@@ -41,13 +42,22 @@ use sp_blockchain::{
 };
 use sp_core::{
 	storage::{
-		ChildInfo, ChildType, PrefixedStorageKey, StorageChangeSet, StorageData, StorageKey,
+		well_known_keys::DEFAULT_CHILD_STORAGE_KEY_PREFIX, ChildInfo, ChildType,
+		PrefixedStorageKey, StorageChangeSet, StorageData, StorageKey,
 	},
+	traits::FuelBudgetExt,
 	Bytes,
 };
-use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+use sp_externalities::Extensions;
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, One},
+};
 use sp_version::RuntimeVersion;
 
+/// Maximum number of entries proven by `state_getRangeProof`.
+const RANGE_PROOF_MAX_ENTRIES: u32 = 1000;
+
 /// Ranges to query in state_queryStorage.
 struct QueryStorageRange<Block: BlockT> {
 	/// Hashes of all the blocks in the range.
@@ -136,6 +146,8 @@ where
 	}
 
 	/// Iterates through range.unfiltered_range and check each block for changes of keys' values.
+	///
+	/// Keys that the state changes index reports as untouched by a block aren't read again.
 	fn query_storage_unfiltered(
 		&self,
 		range: &QueryStorageRange<Block>,
@@ -146,7 +158,14 @@ where
 		for block_hash in &range.hashes {
 			let mut block_changes = StorageChangeSet { block: *block_hash, changes: Vec::new() };
 			let id = BlockId::hash(*block_hash);
+			let changed_keys = self.client.changed_keys(block_hash).map_err(client_err)?;
 			for key in keys {
+				let unchanged = changed_keys
+					.as_ref()
+					.map_or(false, |changed| changed.binary_search(key).is_err());
+				if unchanged && last_values.contains_key(key) {
+					continue
+				}
 				let (has_changed, data) = {
 					let curr_data = self.client.storage(&id, key).map_err(client_err)?;
 					match last_values.get(key) {
@@ -194,14 +213,20 @@ where
 	) -> std::result::Result<Bytes, Error> {
 		self.block_or_best(block)
 			.and_then(|block| {
+				let execution_extensions = self.client.execution_extensions();
+				let extensions = execution_extensions.budgets().other.map(|budget| {
+					let mut extensions = Extensions::new();
+					extensions.register(FuelBudgetExt(budget));
+					extensions
+				});
 				self.client
 					.executor()
 					.call(
 						&BlockId::Hash(block),
 						&method,
 						&*call_data,
-						self.client.execution_extensions().strategies().other,
-						None,
+						execution_extensions.strategies().other,
+						extensions,
 					)
 					.map(Into::into)
 			})
@@ -342,6 +367,68 @@ where
 		self.query_storage(at, Some(at), keys)
 	}
 
+	fn key_history(
+		&self,
+		from: Block::Hash,
+		to: Option<Block::Hash>,
+		key: StorageKey,
+	) -> std::result::Result<Vec<StorageChangeSet<Block::Hash>>, Error> {
+		let to = self
+			.block_or_best(to)
+			.map_err(|e| invalid_block::<Block>(from, to, e.to_string()))?;
+
+		let invalid_block_err =
+			|e: ClientError| invalid_block::<Block>(from, Some(to), e.to_string());
+		let from_meta = self.client.header_metadata(from).map_err(invalid_block_err)?;
+		let to_meta = self.client.header_metadata(to).map_err(invalid_block_err)?;
+
+		if from_meta.number > to_meta.number {
+			return Err(invalid_block_range(
+				&from_meta,
+				&to_meta,
+				"from number > to number".to_owned(),
+			))
+		}
+
+		let ancestor = sp_blockchain::lowest_common_ancestor(&*self.client, from, to)
+			.map_err(|e| invalid_block_range::<Block>(&from_meta, &to_meta, e.to_string()))?;
+		if ancestor.hash != from {
+			return Err(invalid_block_range(
+				&from_meta,
+				&to_meta,
+				"from and to are on different forks".to_owned(),
+			))
+		}
+
+		let changed_at = self
+			.client
+			.key_changes(from_meta.number + One::one(), &to, &key)
+			.map_err(client_err)?;
+		let changed_at = match changed_at {
+			Some(changed_at) => changed_at,
+			// Without an index covering the range, every block has to be read.
+			None => return self.query_storage(from, Some(to), vec![key]),
+		};
+
+		let mut last_value = self.client.storage(&BlockId::Hash(from), &key).map_err(client_err)?;
+		let mut changes = vec![StorageChangeSet {
+			block: from,
+			changes: vec![(key.clone(), last_value.clone())],
+		}];
+		for (_, hash) in changed_at {
+			let value = self.client.storage(&BlockId::Hash(hash), &key).map_err(client_err)?;
+			// The index records writes, which may leave the value as it was.
+			if value != last_value {
+				changes.push(StorageChangeSet {
+					block: hash,
+					changes: vec![(key.clone(), value.clone())],
+				});
+				last_value = value;
+			}
+		}
+		Ok(changes)
+	}
+
 	fn read_proof(
 		&self,
 		block: Option<Block::Hash>,
@@ -357,6 +444,47 @@ where
 			.map_err(client_err)
 	}
 
+	fn read_range_proof(
+		&self,
+		block: Option<Block::Hash>,
+		prefix: Option<StorageKey>,
+		start_key: Option<StorageKey>,
+		end_key: Option<StorageKey>,
+	) -> std::result::Result<ReadProof<Block::Hash>, Error> {
+		self.block_or_best(block)
+			.and_then(|block| {
+				self.client
+					.read_complete_range_proof(
+						&BlockId::Hash(block),
+						None,
+						prefix.as_ref().map(|prefix| prefix.0.as_slice()),
+						start_key.as_ref().map(|key| key.0.as_slice()),
+						end_key.as_ref().map(|key| key.0.as_slice()),
+						RANGE_PROOF_MAX_ENTRIES,
+					)
+					.map(|proof| proof.iter_nodes().map(|node| node.into()).collect())
+					.map(|proof| ReadProof { at: block, proof })
+			})
+			.map_err(client_err)
+	}
+
+	fn read_multi_block_proof(
+		&self,
+		blocks: Vec<Block::Hash>,
+		keys: Vec<StorageKey>,
+	) -> std::result::Result<MultiBlockReadProof<Block::Hash>, Error> {
+		self.client
+			.read_multi_block_proof(&blocks, &mut keys.iter().map(|key| key.0.as_ref()))
+			.map(|proofs| MultiBlockReadProof {
+				at: blocks,
+				proofs: proofs
+					.into_iter()
+					.map(|proof| proof.encoded_nodes.into_iter().map(Into::into).collect())
+					.collect(),
+			})
+			.map_err(client_err)
+	}
+
 	fn subscribe_runtime_version(&self, pending: PendingSubscription) {
 		let client = self.client.clone();
 
@@ -468,6 +596,16 @@ where
 		.trace_block()
 		.map_err(|e| invalid_block::<Block>(block, None, e.to_string()))
 	}
+
+	fn profile_block(
+		&self,
+		block: Block::Hash,
+	) -> std::result::Result<sp_rpc::profiling::ProfileBlockResponse, Error> {
+		sc_tracing::block::BlockProfiler::new(self.client.clone(), block)
+			.profile_block()
+			.map(Into::into)
+			.map_err(|e| invalid_block::<Block>(block, None, e.to_string()))
+	}
 }
 
 impl<BE, Block, Client> ChildStateBackend<Block, Client> for FullState<BE, Block, Client>
@@ -618,6 +756,142 @@ where
 			})
 			.map_err(client_err)
 	}
+
+	fn storage_pairs_paged(
+		&self,
+		block: Option<Block::Hash>,
+		storage_key: PrefixedStorageKey,
+		prefix: Option<StorageKey>,
+		count: u32,
+		start_key: Option<StorageKey>,
+	) -> std::result::Result<Vec<(StorageKey, StorageData)>, Error> {
+		self.block_or_best(block)
+			.and_then(|block| {
+				let child_info = match ChildType::from_prefixed_key(&storage_key) {
+					Some((ChildType::ParentKeyId, storage_key)) =>
+						ChildInfo::new_default(storage_key),
+					None => return Err(sp_blockchain::Error::InvalidChildStorageKey),
+				};
+				let id = BlockId::Hash(block);
+				self.client
+					.child_storage_keys_iter(
+						&id,
+						child_info.clone(),
+						prefix.as_ref(),
+						start_key.as_ref(),
+					)?
+					.take(count as usize)
+					.map(|key| {
+						let value = self.client.child_storage(&id, &child_info, &key)?;
+						Ok((key, value.unwrap_or_default()))
+					})
+					.collect()
+			})
+			.map_err(client_err)
+	}
+
+	fn child_tries(
+		&self,
+		block: Option<Block::Hash>,
+		start_key: Option<PrefixedStorageKey>,
+		count: u32,
+	) -> std::result::Result<Vec<ChildTrie<Block::Hash>>, Error> {
+		self.block_or_best(block)
+			.and_then(|block| {
+				let start_key = match start_key.as_ref().map(ChildType::from_prefixed_key) {
+					Some(Some((ChildType::ParentKeyId, storage_key))) =>
+						Some(ChildInfo::new_default(storage_key)),
+					Some(None) => return Err(sp_blockchain::Error::InvalidChildStorageKey),
+					None => None,
+				};
+				self.client
+					.default_child_tries(&BlockId::Hash(block), start_key.as_ref(), count)
+			})
+			.map(|child_tries| {
+				child_tries
+					.into_iter()
+					.map(|(child_info, root)| ChildTrie {
+						storage_key: child_info.prefixed_storage_key(),
+						root,
+					})
+					.collect()
+			})
+			.map_err(client_err)
+	}
+
+	fn root(
+		&self,
+		block: Option<Block::Hash>,
+		storage_key: PrefixedStorageKey,
+	) -> std::result::Result<Option<Block::Hash>, Error> {
+		self.block_or_best(block)
+			.and_then(|block| {
+				if ChildType::from_prefixed_key(&storage_key).is_none() {
+					return Err(sp_blockchain::Error::InvalidChildStorageKey)
+				}
+				let root = self
+					.client
+					.storage(&BlockId::Hash(block), &StorageKey(storage_key.into_inner()))?;
+				root.map(|root| {
+					Decode::decode(&mut &root.0[..]).map_err(|e| {
+						sp_blockchain::Error::Storage(format!("Invalid child trie root: {}", e))
+					})
+				})
+				.transpose()
+			})
+			.map_err(client_err)
+	}
+
+	fn read_child_pairs_proof(
+		&self,
+		block: Option<Block::Hash>,
+		storage_key: PrefixedStorageKey,
+		prefix: Option<StorageKey>,
+		count: u32,
+		start_key: Option<StorageKey>,
+	) -> std::result::Result<ReadProof<Block::Hash>, Error> {
+		self.block_or_best(block)
+			.and_then(|block| {
+				let child_info = match ChildType::from_prefixed_key(&storage_key) {
+					Some((ChildType::ParentKeyId, storage_key)) =>
+						ChildInfo::new_default(storage_key),
+					None => return Err(sp_blockchain::Error::InvalidChildStorageKey),
+				};
+				self.client
+					.read_range_proof(
+						&BlockId::Hash(block),
+						Some(&child_info),
+						prefix.as_ref().map(|prefix| prefix.0.as_slice()),
+						start_key.as_ref().map(|key| key.0.as_slice()),
+						count,
+					)
+					.map(|(_, proof)| proof.iter_nodes().map(|node| node.into()).collect())
+					.map(|proof| ReadProof { at: block, proof })
+			})
+			.map_err(client_err)
+	}
+
+	fn read_child_tries_proof(
+		&self,
+		block: Option<Block::Hash>,
+		start_key: Option<PrefixedStorageKey>,
+		count: u32,
+	) -> std::result::Result<ReadProof<Block::Hash>, Error> {
+		self.block_or_best(block)
+			.and_then(|block| {
+				self.client
+					.read_range_proof(
+						&BlockId::Hash(block),
+						None,
+						Some(DEFAULT_CHILD_STORAGE_KEY_PREFIX),
+						start_key.as_ref().map(|key| key.as_slice()),
+						count,
+					)
+					.map(|(_, proof)| proof.iter_nodes().map(|node| node.into()).collect())
+					.map(|proof| ReadProof { at: block, proof })
+			})
+			.map_err(client_err)
+	}
 }
 
 fn invalid_block_range<B: BlockT>(
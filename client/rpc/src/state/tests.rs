@@ -189,6 +189,73 @@ async fn should_return_child_storage_entries() {
 	assert_matches!(child.storage_size(child_key.clone(), keys[0].clone(), None), Ok(Some(1)));
 }
 
+#[tokio::test]
+async fn should_enumerate_child_tries() {
+	let child_info = ChildInfo::new_default(STORAGE_KEY);
+	let other_child_info = ChildInfo::new_default(b"other");
+	let client = Arc::new(
+		substrate_test_runtime_client::TestClientBuilder::new()
+			.add_child_storage(&child_info, "key1", vec![42_u8])
+			.add_child_storage(&child_info, "key2", vec![43_u8, 44])
+			.add_child_storage(&other_child_info, "key", vec![1_u8])
+			.build(),
+	);
+	let genesis_hash = client.genesis_hash();
+	let (_client, child) = new_full(client, test_executor(), DenyUnsafe::No, None);
+
+	let child_tries = child.child_tries(None, 10, Some(genesis_hash)).unwrap();
+	assert_eq!(
+		child_tries.iter().map(|trie| trie.storage_key.clone()).collect::<Vec<_>>(),
+		vec![prefixed_storage_key(), other_child_info.prefixed_storage_key()],
+	);
+	assert_eq!(
+		child.root(prefixed_storage_key(), Some(genesis_hash)).unwrap(),
+		Some(child_tries[0].root),
+	);
+	let next = child.child_tries(Some(prefixed_storage_key()), 10, None).unwrap();
+	assert_eq!(next, child_tries[1..]);
+	assert!(child.read_child_tries_proof(None, 10, None).unwrap().proof.len() > 0);
+
+	assert_eq!(
+		child
+			.storage_pairs_paged(
+				prefixed_storage_key(),
+				None,
+				1,
+				Some(StorageKey(b"key1".to_vec())),
+				None
+			)
+			.unwrap(),
+		vec![(StorageKey(b"key2".to_vec()), StorageData(vec![43, 44]))],
+	);
+	assert!(
+		child
+			.read_child_pairs_proof(prefixed_storage_key(), None, 2, None, None)
+			.unwrap()
+			.proof
+			.len() > 0
+	);
+	assert_matches!(child.child_tries(None, 1001, None), Err(_));
+}
+
+#[tokio::test]
+async fn should_return_multi_block_read_proof() {
+	let client = Arc::new(substrate_test_runtime_client::new());
+	let genesis_hash = client.genesis_hash();
+	let (api, _child) = new_full(client, test_executor(), DenyUnsafe::No, None);
+	let keys = vec![StorageKey(b":code".to_vec())];
+
+	let proof = api
+		.read_multi_block_proof(keys.clone(), vec![genesis_hash, genesis_hash])
+		.unwrap();
+	assert_eq!(proof.at, vec![genesis_hash, genesis_hash]);
+	assert!(!proof.proofs[0].is_empty());
+	// The state was already proven by the first proof.
+	assert!(proof.proofs[1].is_empty());
+
+	assert_matches!(api.read_multi_block_proof(keys, vec![genesis_hash; 257]), Err(_));
+}
+
 #[tokio::test]
 async fn should_call_contract() {
 	let client = Arc::new(substrate_test_runtime_client::new());
@@ -347,6 +414,27 @@ async fn should_query_storage() {
 
 		assert_eq!(result.unwrap(), expected);
 
+		// History of a single key.
+		let result = api.key_history(StorageKey(vec![5]), genesis_hash, None);
+
+		assert_eq!(
+			result.unwrap(),
+			vec![
+				StorageChangeSet {
+					block: genesis_hash,
+					changes: vec![(StorageKey(vec![5]), None)]
+				},
+				StorageChangeSet {
+					block: block1_hash,
+					changes: vec![(StorageKey(vec![5]), Some(StorageData(vec![0])))],
+				},
+				StorageChangeSet {
+					block: block2_hash,
+					changes: vec![(StorageKey(vec![5]), Some(StorageData(vec![1])))],
+				},
+			]
+		);
+
 		// Inverted range.
 		let result = api.query_storage(keys.clone(), block1_hash, Some(genesis_hash));
 
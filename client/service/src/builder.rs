@@ -208,7 +208,10 @@ where
 			state_cache_child_ratio: config.state_cache_child_ratio.map(|v| (v, 100)),
 			state_pruning: config.state_pruning.clone(),
 			source: config.database.clone(),
-			keep_blocks: config.keep_blocks,
+			blocks_pruning: config.blocks_pruning,
+			flat_state: config.flat_state,
+			state_diff_index: config.state_diff_index,
+			storage_item_stats: config.storage_item_stats,
 		};
 
 		let backend = new_db_backend(db_config)?;
@@ -217,7 +220,8 @@ where
 			config.execution_strategies.clone(),
 			Some(keystore_container.sync_keystore()),
 			sc_offchain::OffchainDb::factory_from_backend(&*backend),
-		);
+		)
+		.with_budgets(config.execution_budgets.clone());
 
 		let wasm_runtime_substitutes = config
 			.chain_spec
@@ -401,6 +405,7 @@ where
 		+ sp_transaction_pool::runtime_api::TaggedTransactionQueue<TBl>
 		+ sp_session::SessionKeys<TBl>
 		+ sp_api::ApiExt<TBl, StateBackend = TBackend::State>,
+	<TCl as ExecutorProvider<TBl>>::Executor: sc_executor::PrepareRuntime,
 	TBl: BlockT,
 	TBl::Hash: Unpin,
 	TBl::Header: Unpin,
@@ -457,6 +462,20 @@ where
 		transaction_notifications(transaction_pool.clone(), network.clone(), telemetry.clone()),
 	);
 
+	// Compile the upcoming runtimes before their first use. Preparing a runtime needs room for it
+	// next to the runtime in use.
+	if config.runtime_cache_size >= 2 {
+		spawn_handle.spawn_blocking(
+			"runtime-precompiler",
+			None,
+			crate::runtime_precompiler::run(
+				client.clone(),
+				backend.clone(),
+				config.prometheus_registry().cloned(),
+			),
+		);
+	}
+
 	// Prometheus metrics.
 	let metrics_service =
 		if let Some(PrometheusConfig { port, registry }) = config.prometheus_config.clone() {
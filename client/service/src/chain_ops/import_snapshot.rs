@@ -0,0 +1,203 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::error::Error;
+use futures::future;
+use log::{info, warn};
+use sc_client_api::HeaderBackend;
+use sc_client_db::snapshot::{Snapshot, SnapshotBlock};
+use sc_consensus::{
+	import_queue::{BlockImportError, BlockImportStatus, ImportQueue, IncomingBlock, Link},
+	ImportedState,
+};
+use sp_consensus::BlockOrigin;
+use sp_runtime::traits::{Block as BlockT, Header, NumberFor, One, Zero};
+use sp_state_machine::KeyValueStates;
+use std::{path::Path, sync::Arc, task::Poll};
+
+/// Number of blocks of the snapshot we add to the queue before waiting for it to catch up.
+const MAX_PENDING_BLOCKS: usize = 10_000;
+
+/// Counts the blocks imported by the queue.
+#[derive(Default)]
+struct WaitLink {
+	imported_blocks: usize,
+	has_error: bool,
+}
+
+impl<B: BlockT> Link<B> for WaitLink {
+	fn blocks_processed(
+		&mut self,
+		imported: usize,
+		_num_expected_blocks: usize,
+		results: Vec<(Result<BlockImportStatus<NumberFor<B>>, BlockImportError>, B::Hash)>,
+	) {
+		self.imported_blocks += imported;
+
+		for result in results {
+			if let (Err(err), hash) = result {
+				warn!("There was an error importing block with hash {:?}: {}", hash, err);
+				self.has_error = true;
+				break
+			}
+		}
+	}
+}
+
+/// Waits for the queue to have imported `expected` blocks in total.
+async fn wait_for_queue<B: BlockT, IQ: ImportQueue<B>>(
+	queue: &mut IQ,
+	link: &mut WaitLink,
+	expected: usize,
+) -> Result<(), Error> {
+	future::poll_fn(|cx| {
+		queue.poll_actions(cx, link);
+		if link.has_error {
+			Poll::Ready(Err(Error::Other(format!(
+				"Stopping after #{} blocks because of an error",
+				link.imported_blocks
+			))))
+		} else if link.imported_blocks >= expected {
+			Poll::Ready(Ok(()))
+		} else {
+			Poll::Pending
+		}
+	})
+	.await
+}
+
+fn incoming_block<B: BlockT>(
+	block: SnapshotBlock<B>,
+	state: Option<ImportedState<B>>,
+) -> IncomingBlock<B> {
+	IncomingBlock {
+		hash: block.header.hash(),
+		header: Some(block.header),
+		body: block.body,
+		indexed_body: block.indexed_body,
+		justifications: block.justifications,
+		origin: None,
+		allow_missing_state: true,
+		import_existing: state.is_some(),
+		skip_execution: true,
+		state,
+	}
+}
+
+/// Initialises the empty database of `client` from the snapshot stored at `path`, which must have
+/// been taken at the block `finalized_hash`.
+///
+/// The state of the snapshot block is streamed to the database of `backend` first. Like with state
+/// sync, the block is then imported without being executed, so that consensus can initialise its
+/// data from that state. Its ancestors contained in the snapshot are imported afterwards, without
+/// state.
+///
+/// An import interrupted while importing the ancestors is resumed from the first missing one.
+pub async fn import_snapshot<B, IQ, C>(
+	client: Arc<C>,
+	backend: Arc<sc_client_db::Backend<B>>,
+	mut import_queue: IQ,
+	path: &Path,
+	finalized_hash: B::Hash,
+) -> Result<(), Error>
+where
+	C: HeaderBackend<B> + Send + Sync + 'static,
+	B: BlockT,
+	IQ: ImportQueue<B> + 'static,
+{
+	let snapshot = Snapshot::<B>::open(path)?;
+	let metadata = snapshot.metadata().clone();
+	let info = client.info();
+	if metadata.hash != finalized_hash {
+		return Err(Error::Other(format!(
+			"Snapshot taken at block {:?} instead of the expected block {:?}",
+			metadata.hash, finalized_hash,
+		)))
+	}
+	if metadata.genesis_hash != info.genesis_hash {
+		return Err(Error::Other(format!(
+			"Snapshot of the chain with genesis {:?} instead of {:?}",
+			metadata.genesis_hash, info.genesis_hash,
+		)))
+	}
+	if metadata.number.is_zero() {
+		return Err(Error::Other("Snapshot of the genesis block, nothing to import".into()))
+	}
+
+	let mut link = WaitLink::default();
+	let mut queued = 0;
+	// The ancestors of the snapshot block below `first_missing` have been imported already.
+	let first_missing = if info.finalized_hash == metadata.hash {
+		match info.block_gap {
+			Some((start, _)) => start,
+			None => {
+				info!("The snapshot of block #{} is already imported", metadata.number);
+				return Ok(())
+			},
+		}
+	} else if info.best_number.is_zero() {
+		info!("🔎 Verifying snapshot of block #{} ({:?})", metadata.number, metadata.hash);
+		let block = snapshot.verify()?;
+
+		info!("📦 Importing state of block #{}", metadata.number);
+		snapshot.import_state(&backend)?;
+		// An empty state lets the client know that the state is already in the database.
+		let state = ImportedState { block: metadata.hash, state: KeyValueStates(Vec::new()) };
+		queued += 1;
+		import_queue.import_blocks(
+			BlockOrigin::NetworkInitialSync,
+			vec![incoming_block(block, Some(state))],
+		);
+		wait_for_queue::<B, _>(&mut import_queue, &mut link, queued).await?;
+		One::one()
+	} else {
+		return Err(Error::Other("Snapshots can only be imported into an empty database".into()))
+	};
+
+	// Importing the snapshot block left a gap between it and the genesis, which its ancestors
+	// fill when they are part of the snapshot.
+	if metadata.first_block < metadata.number {
+		let mut pending = Vec::new();
+		for block in snapshot.blocks()? {
+			let block = block?;
+			if *block.header.number() == metadata.number {
+				break
+			}
+			if *block.header.number() < first_missing {
+				continue
+			}
+			pending.push(incoming_block(block, None));
+
+			if pending.len() == MAX_PENDING_BLOCKS {
+				queued += pending.len();
+				import_queue
+					.import_blocks(BlockOrigin::NetworkInitialSync, std::mem::take(&mut pending));
+				wait_for_queue::<B, _>(&mut import_queue, &mut link, queued).await?;
+				info!("📦 Imported {} blocks of the snapshot", queued);
+			}
+		}
+		queued += pending.len();
+		import_queue.import_blocks(BlockOrigin::NetworkInitialSync, pending);
+		wait_for_queue::<B, _>(&mut import_queue, &mut link, queued).await?;
+	} else if metadata.number > One::one() {
+		info!("The ancestors of the snapshot block are missing, they will be downloaded by sync");
+	}
+
+	info!("🎉 Imported snapshot of block #{} ({:?})", metadata.number, metadata.hash);
+	Ok(())
+}
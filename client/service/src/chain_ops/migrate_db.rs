@@ -0,0 +1,86 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{chain_ops::import_snapshot, error::Error};
+use log::info;
+use sc_client_api::HeaderBackend;
+use sc_client_db::snapshot::{self, Snapshot};
+use sc_consensus::import_queue::ImportQueue;
+use sp_api::{Core, ProvideRuntimeApi};
+use sp_runtime::{generic::BlockId, traits::{Block as BlockT, One}};
+use std::{fs, path::Path, sync::Arc};
+
+/// Migrates the ParityDb database of `source_client` to the empty RocksDB database of
+/// `target_client`, through a snapshot of the latest finalized block stored at `snapshot_path`.
+///
+/// The columns of ParityDb can't be enumerated, see [`sc_client_db::migration`]. Like after warp
+/// sync, the migrated database only holds the state of the latest finalized block, and neither
+/// holds the blocks that aren't finalized nor the offchain storage.
+///
+/// An interrupted migration resumes from the snapshot found at `snapshot_path`, which is removed
+/// once the migrated blocks have been checked.
+pub async fn migrate_from_parity_db<B, SC, TC, IQ>(
+	source_client: Arc<SC>,
+	source_backend: Arc<sc_client_db::Backend<B>>,
+	target_client: Arc<TC>,
+	target_backend: Arc<sc_client_db::Backend<B>>,
+	import_queue: IQ,
+	snapshot_path: &Path,
+) -> Result<(), Error>
+where
+	B: BlockT,
+	SC: HeaderBackend<B> + ProvideRuntimeApi<B>,
+	SC::Api: Core<B>,
+	TC: HeaderBackend<B> + Send + Sync + 'static,
+	IQ: ImportQueue<B> + 'static,
+{
+	// The metadata is written last, a snapshot without it is incomplete.
+	let metadata = match Snapshot::<B>::open(snapshot_path) {
+		Ok(snapshot) => {
+			info!("Resuming the migration from the snapshot at {}", snapshot_path.display());
+			snapshot.metadata().clone()
+		},
+		Err(_) => {
+			if snapshot_path.exists() {
+				fs::remove_dir_all(snapshot_path)?;
+			}
+			let hash = source_client.info().finalized_hash;
+			let state_version = source_client
+				.runtime_api()
+				.version(&BlockId::Hash(hash))
+				.map_err(|e| format!("Failed to get the runtime version: {}", e))?
+				.state_version();
+			snapshot::export(&*source_backend, snapshot_path, hash, state_version)?
+		},
+	};
+
+	let (client, hash) = (target_client.clone(), metadata.hash);
+	import_snapshot(client, target_backend, import_queue, snapshot_path, hash).await?;
+
+	let mut number = metadata.first_block;
+	while number <= metadata.number {
+		let source_hash = source_client.hash(number)?;
+		if source_hash.is_none() || target_client.hash(number)? != source_hash {
+			return Err(format!("Block #{} differs after the migration", number).into())
+		}
+		number += One::one();
+	}
+	fs::remove_dir_all(snapshot_path)?;
+	info!("Migrated blocks #{} to #{}", metadata.first_block, metadata.number);
+	Ok(())
+}
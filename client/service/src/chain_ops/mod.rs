@@ -22,10 +22,16 @@ mod check_block;
 mod export_blocks;
 mod export_raw_state;
 mod import_blocks;
+mod import_snapshot;
+mod migrate_db;
+mod precompile_runtime;
 mod revert_chain;
 
 pub use check_block::*;
 pub use export_blocks::*;
 pub use export_raw_state::*;
 pub use import_blocks::*;
+pub use import_snapshot::*;
+pub use migrate_db::*;
+pub use precompile_runtime::*;
 pub use revert_chain::*;
@@ -0,0 +1,97 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{config::Configuration, error::Error};
+use codec::Decode;
+use sc_client_api::StorageProvider;
+use sp_core::storage::{well_known_keys, StorageKey};
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, Hash, HashFor},
+};
+use std::{path::PathBuf, sync::Arc};
+
+/// Precompile the runtime of the genesis storage of the chain spec of `config` into the runtime
+/// artifacts directory of `config`. Returns the path of the artifact.
+pub fn precompile_genesis_runtime<B: BlockT>(config: &Configuration) -> Result<PathBuf, Error> {
+	let storage = config.chain_spec.as_storage_builder().build_storage()?;
+	let code = storage
+		.top
+		.get(well_known_keys::CODE)
+		.ok_or("The genesis storage has no runtime code")?;
+	let heap_pages = storage
+		.top
+		.get(well_known_keys::HEAP_PAGES)
+		.map(|heap_pages| decode_heap_pages(heap_pages))
+		.transpose()?;
+
+	precompile(config, code, HashFor::<B>::hash(code).as_ref(), heap_pages)
+}
+
+/// Precompile the runtime stored at `block` into the runtime artifacts directory of `config`.
+/// Returns the path of the artifact.
+pub fn precompile_runtime<B, BA, C>(
+	client: Arc<C>,
+	block: BlockId<B>,
+	config: &Configuration,
+) -> Result<PathBuf, Error>
+where
+	C: StorageProvider<B, BA>,
+	B: BlockT,
+	BA: sc_client_api::backend::Backend<B>,
+{
+	let code_key = StorageKey(well_known_keys::CODE.to_vec());
+	let code = client
+		.storage(&block, &code_key)?
+		.ok_or_else(|| format!("No runtime code at block {}", block))?;
+	let code_hash = client
+		.storage_hash(&block, &code_key)?
+		.ok_or_else(|| format!("No runtime code at block {}", block))?;
+	let heap_pages = client
+		.storage(&block, &StorageKey(well_known_keys::HEAP_PAGES.to_vec()))?
+		.map(|heap_pages| decode_heap_pages(&heap_pages.0))
+		.transpose()?;
+
+	precompile(config, &code.0, code_hash.as_ref(), heap_pages)
+}
+
+fn decode_heap_pages(mut heap_pages: &[u8]) -> Result<u64, Error> {
+	u64::decode(&mut heap_pages).map_err(|e| format!("Invalid heap pages: {}", e).into())
+}
+
+fn precompile(
+	config: &Configuration,
+	code: &[u8],
+	code_hash: &[u8],
+	heap_pages: Option<u64>,
+) -> Result<PathBuf, Error> {
+	let runtime_artifacts = config
+		.runtime_artifacts_path
+		.as_ref()
+		.ok_or("No runtime artifacts directory is configured")?;
+
+	sc_executor::precompile_runtime(
+		runtime_artifacts,
+		code,
+		code_hash,
+		config.wasm_method,
+		heap_pages.or(config.default_heap_pages),
+		config.execution_budgets.is_metered(),
+	)
+	.map_err(|e| Error::Other(format!("Cannot precompile the runtime: {}", e)))
+}
@@ -19,7 +19,7 @@
 use super::{client::ClientConfig, wasm_override::WasmOverride, wasm_substitutes::WasmSubstitutes};
 use codec::{Decode, Encode};
 use sc_client_api::{backend, call_executor::CallExecutor, HeaderBackend};
-use sc_executor::{RuntimeVersion, RuntimeVersionOf};
+use sc_executor::{PrepareRuntime, RuntimeCacheStats, RuntimeVersion, RuntimeVersionOf};
 use sp_api::{ProofRecorder, StorageTransactionCache};
 use sp_core::{
 	traits::{CodeExecutor, RuntimeCode, SpawnNamed},
@@ -331,6 +331,24 @@ where
 	}
 }
 
+impl<B, E, Block> PrepareRuntime for LocalCallExecutor<Block, B, E>
+where
+	E: PrepareRuntime,
+	Block: BlockT,
+{
+	fn prepare_runtime(
+		&self,
+		ext: &mut dyn sp_externalities::Externalities,
+		runtime_code: &sp_core::traits::RuntimeCode,
+	) -> Result<Option<std::time::Duration>, sc_executor::error::Error> {
+		self.executor.prepare_runtime(ext, runtime_code)
+	}
+
+	fn runtime_cache_stats(&self) -> RuntimeCacheStats {
+		self.executor.runtime_cache_stats()
+	}
+}
+
 impl<Block, B, E> sp_version::GetRuntimeVersionAt<Block> for LocalCallExecutor<Block, B, E>
 where
 	B: backend::Backend<Block>,
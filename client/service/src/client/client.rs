@@ -76,10 +76,10 @@ use sp_runtime::{
 	BuildStorage, Digest, Justification, Justifications, StateVersion,
 };
 use sp_state_machine::{
-	prove_child_read, prove_range_read_with_child_with_size, prove_read,
-	read_range_proof_check_with_child_on_proving_backend, Backend as StateBackend,
-	ChildStorageCollection, KeyValueStates, KeyValueStorageLevel, StorageCollection,
-	MAX_NESTED_TRIE_DEPTH,
+	prove_child_read, prove_complete_range_read, prove_range_read_with_child_with_size,
+	prove_range_read_with_count, prove_read, read_range_proof_check_with_child_on_proving_backend,
+	Backend as StateBackend, ChildStorageCollection, KeyValueStates, KeyValueStorageLevel,
+	StorageCollection, MAX_NESTED_TRIE_DEPTH,
 };
 use sp_trie::{CompactProof, StorageProof};
 use std::{
@@ -606,6 +606,14 @@ where
 
 						Some((main_sc, child_sc))
 					},
+					// The state has been written to the database beforehand, e.g. by a snapshot
+					// import, its root must already be there.
+					sc_consensus::StorageChanges::Import(changes) if changes.state.0.is_empty() => {
+						operation
+							.op
+							.reset_to_existing_storage(*import_headers.post().state_root())?;
+						None
+					},
 					sc_consensus::StorageChanges::Import(changes) => {
 						let mut storage = sp_storage::Storage::default();
 						for state in changes.state.0.into_iter() {
@@ -1173,6 +1181,60 @@ where
 			.and_then(|state| prove_child_read(state, child_info, keys).map_err(Into::into))
 	}
 
+	fn read_complete_range_proof(
+		&self,
+		id: &BlockId<Block>,
+		child_info: Option<&ChildInfo>,
+		prefix: Option<&[u8]>,
+		start_key: Option<&[u8]>,
+		end_key: Option<&[u8]>,
+		max_entries: u32,
+	) -> sp_blockchain::Result<StorageProof> {
+		self.state_at(id).and_then(|state| {
+			prove_complete_range_read(state, child_info, prefix, start_key, end_key, max_entries)
+				.map_err(Into::into)
+		})
+	}
+
+	fn read_multi_block_proof(
+		&self,
+		blocks: &[Block::Hash],
+		keys: &mut dyn Iterator<Item = &[u8]>,
+	) -> sp_blockchain::Result<Vec<CompactProof>> {
+		let keys = keys.collect::<Vec<_>>();
+		let proofs = blocks
+			.iter()
+			.map(|hash| {
+				let id = BlockId::Hash(*hash);
+				let root = *self
+					.backend
+					.blockchain()
+					.header(id)?
+					.ok_or_else(|| Error::UnknownBlock(format!("{:?}", hash)))?
+					.state_root();
+				let proof = self.read_proof(&id, &mut keys.iter().copied())?;
+				Ok((proof, root))
+			})
+			.collect::<sp_blockchain::Result<Vec<_>>>()?;
+		// This is read proof only, we can use either LayoutV0 or LayoutV1.
+		sp_trie::encode_compact_multi::<sp_trie::LayoutV0<HashFor<Block>>>(proofs)
+			.map_err(|e| sp_blockchain::Error::from_state(Box::new(e)))
+	}
+
+	fn read_range_proof(
+		&self,
+		id: &BlockId<Block>,
+		child_info: Option<&ChildInfo>,
+		prefix: Option<&[u8]>,
+		start_key: Option<&[u8]>,
+		count: u32,
+	) -> sp_blockchain::Result<(Vec<(Vec<u8>, Vec<u8>)>, StorageProof)> {
+		self.state_at(id).and_then(|state| {
+			prove_range_read_with_count(state, child_info, prefix, count, start_key)
+				.map_err(Into::into)
+		})
+	}
+
 	fn execution_proof(
 		&self,
 		id: &BlockId<Block>,
@@ -1510,6 +1572,54 @@ where
 			.child_storage_hash(child_info, &key.0)
 			.map_err(|e| sp_blockchain::Error::from_state(Box::new(e)))
 	}
+
+	fn default_child_tries(
+		&self,
+		id: &BlockId<Block>,
+		start_key: Option<&ChildInfo>,
+		count: u32,
+	) -> sp_blockchain::Result<Vec<(ChildInfo, Block::Hash)>> {
+		let start_key = start_key.map(|child_info| child_info.storage_key());
+		let mut child_tries = Vec::new();
+		if count > 0 {
+			self.state_at(id)?
+				.apply_to_default_child_tries_while(start_key, |child_info, root| {
+					if Some(child_info.storage_key()) != start_key {
+						child_tries.push((child_info, root));
+					}
+					(child_tries.len() as u32) < count
+				})
+				.map_err(|e| sp_blockchain::Error::from_state(Box::new(e)))?;
+		}
+		child_tries
+			.into_iter()
+			.map(|(child_info, root)| {
+				let root = Block::Hash::decode(&mut &root[..]).map_err(|e| {
+					sp_blockchain::Error::Storage(format!("Invalid child trie root: {}", e))
+				})?;
+				Ok((child_info, root))
+			})
+			.collect()
+	}
+
+	fn changed_keys(&self, hash: &Block::Hash) -> sp_blockchain::Result<Option<Vec<StorageKey>>> {
+		match self.backend.state_changes_index() {
+			Some(index) => index.changed_keys(hash),
+			None => Ok(None),
+		}
+	}
+
+	fn key_changes(
+		&self,
+		first: NumberFor<Block>,
+		last: &Block::Hash,
+		key: &StorageKey,
+	) -> sp_blockchain::Result<Option<Vec<(NumberFor<Block>, Block::Hash)>>> {
+		match self.backend.state_changes_index() {
+			Some(index) => index.key_changes(first, last, key),
+			None => Ok(None),
+		}
+	}
 }
 
 impl<B, E, Block, RA> HeaderMetadata<Block> for Client<B, E, Block, RA>
@@ -1993,6 +2103,10 @@ where
 	fn requires_full_sync(&self) -> bool {
 		self.backend.requires_full_sync()
 	}
+
+	fn pruned_below(&self, data: sp_blockchain::PrunableBlockData) -> Option<NumberFor<Block>> {
+		self.backend.blockchain().pruned_below(data)
+	}
 }
 
 impl<B, E, Block, RA> backend::AuxStore for Client<B, E, Block, RA>
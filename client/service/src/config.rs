@@ -18,11 +18,16 @@
 
 //! Service configuration.
 
-pub use sc_client_api::execution_extensions::{ExecutionStrategies, ExecutionStrategy};
-pub use sc_client_db::{Database, DatabaseSource, KeepBlocks, PruningMode};
-pub use sc_executor::WasmExecutionMethod;
+pub use sc_client_api::execution_extensions::{
+	ExecutionBudgets, ExecutionStrategies, ExecutionStrategy,
+};
+pub use sc_client_db::{
+	BlocksPruning, Database, DatabaseSource, KeepBlocks, KeepJustifications, PruningConstraints,
+	PruningMode,
+};
 #[cfg(feature = "wasmtime")]
 pub use sc_executor::WasmtimeInstantiationStrategy;
+pub use sc_executor::{sandbox::SandboxBackend, WasmExecutionMethod};
 pub use sc_network::{
 	config::{
 		MultiaddrWithPeerId, NetworkConfiguration, NodeKeyConfig, NonDefaultSetConfig, Role,
@@ -76,8 +81,14 @@ pub struct Configuration {
 	pub state_cache_child_ratio: Option<usize>,
 	/// State pruning settings.
 	pub state_pruning: Option<PruningMode>,
-	/// Number of blocks to keep in the db.
-	pub keep_blocks: KeepBlocks,
+	/// Retention of the data of finalized blocks in the db.
+	pub blocks_pruning: BlocksPruning,
+	/// Keep the latest finalized state in flat storage, next to the trie.
+	pub flat_state: bool,
+	/// Index the storage keys changed by each block.
+	pub state_diff_index: bool,
+	/// Tally the accesses per storage item.
+	pub storage_item_stats: bool,
 	/// Chain configuration.
 	pub chain_spec: Box<dyn ChainSpec>,
 	/// Wasm execution method.
@@ -86,8 +97,20 @@ pub struct Configuration {
 	/// over on-chain runtimes when the spec version matches. Set to `None` to
 	/// disable overrides (default).
 	pub wasm_runtime_overrides: Option<PathBuf>,
+	/// Directory of the precompiled runtime artifacts, which are loaded instead of compiling the
+	/// runtimes with the compiled execution method. `None` disables them.
+	pub runtime_artifacts_path: Option<PathBuf>,
+	/// Whether the host functions called by the runtime can be profiled.
+	pub wasm_host_function_profiling: bool,
+	/// Percentage of the wasm heap above which a block logs a warning, if any.
+	pub wasm_heap_usage_warning: Option<u8>,
+	/// The backend of the sandboxes created by the runtime, if not the default of the execution
+	/// method.
+	pub wasm_sandbox_backend: Option<SandboxBackend>,
 	/// Execution strategies.
 	pub execution_strategies: ExecutionStrategies,
+	/// Fuel budgets of the runtime calls. Setting any of them meters the wasm execution.
+	pub execution_budgets: ExecutionBudgets,
 	/// RPC over HTTP binding address. `None` if disabled.
 	pub rpc_http: Option<SocketAddr>,
 	/// RPC over Websockets binding address. `None` if disabled.
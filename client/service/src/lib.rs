@@ -32,6 +32,7 @@ pub mod client;
 #[cfg(not(feature = "test-helpers"))]
 mod client;
 mod metrics;
+mod runtime_precompiler;
 mod task_manager;
 
 use std::{collections::HashMap, net::SocketAddr};
@@ -60,7 +61,8 @@ pub use self::{
 	error::Error,
 };
 pub use config::{
-	BasePath, Configuration, DatabaseSource, KeepBlocks, PruningMode, Role, RpcMethods, TaskType,
+	BasePath, BlocksPruning, Configuration, DatabaseSource, KeepBlocks, KeepJustifications,
+	PruningConstraints, PruningMode, Role, RpcMethods, TaskType,
 };
 pub use sc_chain_spec::{
 	ChainSpec, ChainType, Extension as ChainSpecExtension, GenericChainSpec, NoExtension,
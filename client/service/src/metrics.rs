@@ -27,6 +27,7 @@ use sc_telemetry::{telemetry, TelemetryHandle, SUBSTRATE_INFO};
 use sc_transaction_pool_api::{MaintainedTransactionPool, PoolStatus};
 use sc_utils::metrics::register_globals;
 use sp_api::ProvideRuntimeApi;
+use sp_core::hexdisplay::HexDisplay;
 use sp_runtime::traits::{Block, NumberFor, SaturatedConversion, UniqueSaturatedInto};
 use std::{
 	sync::Arc,
@@ -43,6 +44,10 @@ struct PrometheusMetrics {
 	database_cache: Gauge<U64>,
 	state_cache: Gauge<U64>,
 	state_db: GaugeVec<U64>,
+	state_db_pruning_window_blocks: Gauge<U64>,
+	state_db_pruning_window_age: Gauge<U64>,
+	storage_item_ops: GaugeVec<U64>,
+	storage_item_bytes: GaugeVec<U64>,
 }
 
 impl PrometheusMetrics {
@@ -123,6 +128,40 @@ impl PrometheusMetrics {
 				)?,
 				registry,
 			)?,
+			state_db_pruning_window_blocks: register(
+				Gauge::new(
+					"substrate_state_db_pruning_window_blocks",
+					"Number of blocks in the state pruning window",
+				)?,
+				registry,
+			)?,
+			state_db_pruning_window_age: register(
+				Gauge::new(
+					"substrate_state_db_pruning_window_age_seconds",
+					"Time span of the state pruning window according to the block timestamps",
+				)?,
+				registry,
+			)?,
+			storage_item_ops: register(
+				GaugeVec::new(
+					Opts::new(
+						"substrate_state_storage_item_ops",
+						"Accesses of the storage items since the node started",
+					),
+					&["prefix", "op"],
+				)?,
+				registry,
+			)?,
+			storage_item_bytes: register(
+				GaugeVec::new(
+					Opts::new(
+						"substrate_state_storage_item_bytes",
+						"Bytes accessed in the storage items since the node started",
+					),
+					&["prefix", "op"],
+				)?,
+				registry,
+			)?,
 		})
 	}
 }
@@ -264,6 +303,24 @@ impl MetricsService {
 					.state_db
 					.with_label_values(&["pinned"])
 					.set(info.memory.state_db.pinned.as_bytes() as u64);
+				if let Some(blocks) = info.memory.state_db.pruning_window_blocks {
+					metrics.state_db_pruning_window_blocks.set(blocks);
+				}
+				if let Some(age) = info.memory.state_db.pruning_window_age {
+					metrics.state_db_pruning_window_age.set(age.as_secs());
+				}
+
+				for usage in &info.io.storage_items {
+					// Names of the items are resolved by the `dev_storageStats` RPC.
+					let prefix = format!("0x{}", HexDisplay::from(&usage.prefix));
+					let ops = &metrics.storage_item_ops;
+					ops.with_label_values(&[&prefix, "read"]).set(usage.reads);
+					ops.with_label_values(&[&prefix, "cache_read"]).set(usage.cache_reads);
+					ops.with_label_values(&[&prefix, "write"]).set(usage.writes);
+					let bytes = &metrics.storage_item_bytes;
+					bytes.with_label_values(&[&prefix, "read"]).set(usage.bytes_read);
+					bytes.with_label_values(&[&prefix, "write"]).set(usage.bytes_written);
+				}
 			}
 		}
 
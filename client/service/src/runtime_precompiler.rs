@@ -0,0 +1,502 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Background compilation of upcoming runtimes.
+//!
+//! The executor compiles a runtime the first time it is called, which makes the first block after
+//! a runtime upgrade slow to author and to import. This task compiles upcoming runtimes into the
+//! runtime cache ahead of time. It looks for them in:
+//!
+//! - the `:code` of every imported block, forks included, which is the runtime of its children;
+//! - the requested preimages of the preimage pallet, where scheduled `set_code` calls wait for
+//!   their dispatch.
+//!
+//! The preimage pallet and the calls setting the code are found through the runtime metadata, so
+//! only runtimes exposing metadata V14 have their preimages looked at.
+
+use codec::Decode;
+use frame_metadata::{
+	PalletStorageMetadata, RuntimeMetadata, RuntimeMetadataPrefixed, StorageEntryType,
+	StorageHasher,
+};
+use futures::StreamExt;
+use log::{debug, info, warn};
+use prometheus_endpoint::{register, HistogramOpts, HistogramVec, PrometheusError, Registry};
+use sc_client_api::{backend, BlockchainEvents, ExecutorProvider};
+use sc_executor::PrepareRuntime;
+use scale_info::{form::PortableForm, PortableRegistry, TypeDef, TypeDefPrimitive, Variant};
+use sp_api::{Metadata, ProvideRuntimeApi};
+use sp_core::{
+	hexdisplay::HexDisplay,
+	storage::well_known_keys,
+	traits::{RuntimeCode, WrappedRuntimeCode},
+	twox_128, Hasher,
+};
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, HashFor},
+};
+use sp_state_machine::{backend::BackendRuntimeCode, Backend as _, BasicExternalities};
+use std::{collections::HashSet, sync::Arc};
+
+const LOG_TARGET: &str = "runtime-precompiler";
+
+struct Metrics {
+	compile_time: HistogramVec,
+}
+
+impl Metrics {
+	fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+		Ok(Self {
+			compile_time: register(
+				HistogramVec::new(
+					HistogramOpts::new(
+						"substrate_runtime_precompile_time_seconds",
+						"Time spent compiling upcoming runtimes in the background",
+					)
+					.buckets(vec![0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 25.0, 60.0]),
+					&["source"],
+				)?,
+				registry,
+			)?,
+		})
+	}
+}
+
+/// Where an upcoming runtime was found.
+#[derive(Clone, Copy)]
+enum Source {
+	Block,
+	Preimage,
+}
+
+impl Source {
+	fn as_str(&self) -> &'static str {
+		match self {
+			Source::Block => "block",
+			Source::Preimage => "preimage",
+		}
+	}
+}
+
+/// Compiles the upcoming runtimes of the imported blocks until the import notifications end.
+///
+/// Compilation blocks, so this must be spawned as a blocking task.
+pub(crate) async fn run<Block, BE, C>(client: Arc<C>, backend: Arc<BE>, registry: Option<Registry>)
+where
+	Block: BlockT,
+	BE: backend::Backend<Block>,
+	C: BlockchainEvents<Block> + ExecutorProvider<Block> + ProvideRuntimeApi<Block>,
+	C::Api: Metadata<Block>,
+	C::Executor: PrepareRuntime,
+{
+	let metrics = match registry.as_ref().map(Metrics::register).transpose() {
+		Ok(metrics) => metrics,
+		Err(err) => {
+			warn!(
+				target: LOG_TARGET,
+				"Failed to register the runtime precompiler metrics: {}", err,
+			);
+			None
+		},
+	};
+	let mut precompiler = Precompiler { metrics, layout: None, examined_preimages: HashSet::new() };
+
+	let mut import_notifications = client.import_notification_stream();
+	while let Some(notification) = import_notifications.next().await {
+		precompiler.prepare_upcoming(
+			&*client,
+			&*backend,
+			notification.hash,
+			notification.is_new_best,
+		);
+	}
+}
+
+struct Precompiler {
+	metrics: Option<Metrics>,
+	/// Hash of the runtime code of the last best block, with the layout read from its metadata.
+	layout: Option<(Vec<u8>, Option<RuntimeLayout>)>,
+	/// Hashes of the requested preimages examined already.
+	examined_preimages: HashSet<Vec<u8>>,
+}
+
+impl Precompiler {
+	fn prepare<E: PrepareRuntime>(&self, executor: &E, runtime_code: &RuntimeCode, source: Source) {
+		let mut ext = BasicExternalities::default();
+		match executor.prepare_runtime(&mut ext, runtime_code) {
+			Ok(Some(elapsed)) => {
+				info!(
+					target: LOG_TARGET,
+					"⚙️  Precompiled the upcoming runtime 0x{} from a {} in {} ms",
+					HexDisplay::from(&runtime_code.hash),
+					source.as_str(),
+					elapsed.as_millis(),
+				);
+				if let Some(metrics) = &self.metrics {
+					metrics
+						.compile_time
+						.with_label_values(&[source.as_str()])
+						.observe(elapsed.as_secs_f64());
+				}
+			},
+			Ok(None) => (),
+			Err(err) => warn!(
+				target: LOG_TARGET,
+				"Failed to precompile the runtime 0x{}: {}",
+				HexDisplay::from(&runtime_code.hash),
+				err,
+			),
+		}
+	}
+
+	/// Prepare the runtimes that may follow the imported block `hash`: the runtime of its children
+	/// and, if it is the new best block, those of its requested preimages.
+	fn prepare_upcoming<Block, BE, C>(
+		&mut self,
+		client: &C,
+		backend: &BE,
+		hash: Block::Hash,
+		is_new_best: bool,
+	) where
+		Block: BlockT,
+		BE: backend::Backend<Block>,
+		C: ExecutorProvider<Block> + ProvideRuntimeApi<Block>,
+		C::Api: Metadata<Block>,
+		C::Executor: PrepareRuntime,
+	{
+		let state = match backend.state_at(BlockId::Hash(hash)) {
+			Ok(state) => state,
+			Err(err) => {
+				debug!(target: LOG_TARGET, "No state for block {}: {}", hash, err);
+				return
+			},
+		};
+		let runtime_code = BackendRuntimeCode::new(&state);
+		let runtime_code = match runtime_code.runtime_code() {
+			Ok(runtime_code) => runtime_code,
+			Err(err) => {
+				debug!(target: LOG_TARGET, "No runtime at {}: {}", hash, err);
+				return
+			},
+		};
+		self.prepare(client.executor(), &runtime_code, Source::Block);
+
+		if !is_new_best {
+			return
+		}
+		// The layout only changes with the runtime.
+		if self.layout.as_ref().map(|(code_hash, _)| code_hash) != Some(&runtime_code.hash) {
+			let layout = client
+				.runtime_api()
+				.metadata(&BlockId::Hash(hash))
+				.ok()
+				.and_then(|metadata| RuntimeLayout::from_metadata(&metadata));
+			if layout.is_none() {
+				debug!(target: LOG_TARGET, "No preimage pallet in the metadata at {}", hash);
+			}
+			self.layout = Some((runtime_code.hash.clone(), layout));
+		}
+		if let Some((_, Some(layout))) = self.layout.clone() {
+			self.prepare_requested_preimages::<Block, _, _>(
+				client.executor(),
+				&state,
+				&layout,
+				runtime_code.heap_pages,
+			);
+		}
+	}
+
+	/// Prepare the runtimes found in the preimages that the chain has requested, which includes
+	/// those of the scheduled calls.
+	fn prepare_requested_preimages<Block, S, E>(
+		&mut self,
+		executor: &E,
+		state: &S,
+		layout: &RuntimeLayout,
+		heap_pages: Option<u64>,
+	) where
+		Block: BlockT,
+		S: sp_state_machine::Backend<HashFor<Block>>,
+		E: PrepareRuntime,
+	{
+		let status_prefix = layout.storage_prefix("StatusFor");
+		let preimage_prefix = layout.storage_prefix("PreimageFor");
+
+		let mut requested = HashSet::new();
+		for status_key in state.keys(&status_prefix) {
+			let preimage_hash = &status_key[status_prefix.len()..];
+			let is_requested = state
+				.storage(&status_key)
+				.ok()
+				.flatten()
+				.map_or(false, |status| status.first() == Some(&layout.requested_status));
+			if !is_requested {
+				continue
+			}
+			requested.insert(preimage_hash.to_vec());
+			if self.examined_preimages.contains(preimage_hash) {
+				continue
+			}
+			// The preimage may not have been noted yet, look at it again later.
+			let preimage = match state.storage(&[&preimage_prefix[..], preimage_hash].concat()) {
+				Ok(Some(preimage)) => preimage,
+				_ => continue,
+			};
+			self.examined_preimages.insert(preimage_hash.to_vec());
+
+			let preimage = match Vec::<u8>::decode(&mut &preimage[..]) {
+				Ok(preimage) => preimage,
+				Err(_) => continue,
+			};
+			if let Some(code) = layout.runtime_code(&preimage) {
+				let code_fetcher = WrappedRuntimeCode(code.into());
+				let runtime_code = RuntimeCode {
+					code_fetcher: &code_fetcher,
+					hash: HashFor::<Block>::hash(&code).as_ref().to_vec(),
+					heap_pages,
+				};
+				self.prepare(executor, &runtime_code, Source::Preimage);
+			}
+		}
+		// Forget the preimages that are no longer requested, they can't come back with the same
+		// status.
+		self.examined_preimages
+			.retain(|preimage_hash| requested.contains(preimage_hash));
+	}
+}
+
+/// Where a runtime keeps what the precompiler looks at, read from its metadata.
+#[derive(Clone, Debug, PartialEq)]
+struct RuntimeLayout {
+	/// Storage prefix of the preimage pallet.
+	preimage_prefix: String,
+	/// Index of the `Requested` variant of the preimage request statuses.
+	requested_status: u8,
+	/// Pallet and call indices of the calls taking a runtime code as their only argument, like
+	/// `set_code`.
+	set_code_calls: Vec<[u8; 2]>,
+}
+
+impl RuntimeLayout {
+	/// Read the layout from the SCALE encoded `metadata` of a runtime.
+	///
+	/// Returns `None` for metadata older than V14 and for runtimes without a preimage pallet.
+	fn from_metadata(metadata: &[u8]) -> Option<Self> {
+		let metadata = match RuntimeMetadataPrefixed::decode(&mut &metadata[..]) {
+			Ok(RuntimeMetadataPrefixed(_, RuntimeMetadata::V14(metadata))) => metadata,
+			_ => return None,
+		};
+
+		let mut preimage = None;
+		let mut set_code_calls = Vec::new();
+		for pallet in &metadata.pallets {
+			if let Some(storage) = &pallet.storage {
+				let status = identity_map_value(storage, "StatusFor");
+				let preimages = identity_map_value(storage, "PreimageFor");
+				if let (Some(status), Some(_)) = (status, preimages) {
+					let requested = variants(&metadata.types, status)
+						.iter()
+						.find(|status| status.name() == "Requested")?;
+					preimage = Some((storage.prefix.clone(), requested.index()));
+				}
+			}
+			if let Some(calls) = &pallet.calls {
+				set_code_calls.extend(
+					variants(&metadata.types, calls.ty.id())
+						.iter()
+						.filter(|call| takes_only_code(&metadata.types, call))
+						.map(|call| [pallet.index, call.index()]),
+				);
+			}
+		}
+
+		let (preimage_prefix, requested_status) = preimage?;
+		Some(Self { preimage_prefix, requested_status, set_code_calls })
+	}
+
+	fn storage_prefix(&self, item: &str) -> Vec<u8> {
+		[twox_128(self.preimage_prefix.as_bytes()), twox_128(item.as_bytes())].concat()
+	}
+
+	/// Returns the runtime code passed to the encoded `call`, if it is one of the calls setting
+	/// the code.
+	fn runtime_code(&self, call: &[u8]) -> Option<Vec<u8>> {
+		if call.len() < 2 || !self.set_code_calls.iter().any(|indices| call.starts_with(indices)) {
+			return None
+		}
+		let mut input = &call[2..];
+		let code = Vec::<u8>::decode(&mut input).ok()?;
+		if !input.is_empty() || !is_runtime_code(&code) {
+			return None
+		}
+		Some(code)
+	}
+}
+
+/// Returns the value type of the storage map `name`, if its keys are stored as they are.
+fn identity_map_value(storage: &PalletStorageMetadata<PortableForm>, name: &str) -> Option<u32> {
+	match &storage.entries.iter().find(|entry| entry.name == name)?.ty {
+		StorageEntryType::Map { hashers, value, .. }
+			if hashers[..] == [StorageHasher::Identity] =>
+			Some(value.id()),
+		_ => None,
+	}
+}
+
+/// Returns the variants of the enum `ty`, none if it isn't an enum.
+fn variants(types: &PortableRegistry, ty: u32) -> &[Variant<PortableForm>] {
+	match types.resolve(ty).map(|ty| ty.type_def()) {
+		Some(TypeDef::Variant(def)) => def.variants(),
+		_ => &[],
+	}
+}
+
+/// Whether the `call` takes a single `code: Vec<u8>` argument.
+fn takes_only_code(types: &PortableRegistry, call: &Variant<PortableForm>) -> bool {
+	let is_bytes = |ty: u32| match types.resolve(ty).map(|ty| ty.type_def()) {
+		Some(TypeDef::Sequence(seq)) => matches!(
+			types.resolve(seq.type_param().id()).map(|ty| ty.type_def()),
+			Some(TypeDef::Primitive(TypeDefPrimitive::U8))
+		),
+		_ => false,
+	};
+	match call.fields() {
+		[field] => field.name().map_or(false, |name| name == "code") && is_bytes(field.ty().id()),
+		_ => false,
+	}
+}
+
+fn is_runtime_code(code: &[u8]) -> bool {
+	sp_maybe_compressed_blob::decompress(code, sp_maybe_compressed_blob::CODE_BLOB_BOMB_LIMIT)
+		.map_or(false, |code| code.starts_with(b"\0asm"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use codec::Encode;
+	use frame_metadata::{
+		ExtrinsicMetadata, PalletCallMetadata, PalletMetadata, RuntimeMetadataV14,
+		StorageEntryMetadata, StorageEntryModifier,
+	};
+	use scale_info::{meta_type, TypeInfo};
+
+	#[allow(dead_code)]
+	#[derive(TypeInfo)]
+	enum RequestStatus {
+		Unrequested(Option<u64>),
+		Requested(u32),
+	}
+
+	#[allow(dead_code, non_camel_case_types)]
+	#[derive(TypeInfo)]
+	enum SystemCall {
+		remark { remark: Vec<u8> },
+		set_heap_pages { pages: u64 },
+		set_code { code: Vec<u8> },
+		set_code_without_checks { code: Vec<u8> },
+	}
+
+	fn storage_map<V: TypeInfo + 'static>(name: &'static str) -> StorageEntryMetadata {
+		StorageEntryMetadata {
+			name,
+			modifier: StorageEntryModifier::Optional,
+			ty: StorageEntryType::Map {
+				hashers: vec![StorageHasher::Identity],
+				key: meta_type::<[u8; 32]>(),
+				value: meta_type::<V>(),
+			},
+			default: vec![],
+			docs: vec![],
+		}
+	}
+
+	fn pallet(name: &'static str, index: u8) -> PalletMetadata {
+		PalletMetadata {
+			name,
+			storage: None,
+			calls: None,
+			event: None,
+			constants: vec![],
+			error: None,
+			index,
+		}
+	}
+
+	fn metadata(pallets: Vec<PalletMetadata>) -> Vec<u8> {
+		let extrinsic =
+			ExtrinsicMetadata { ty: meta_type::<()>(), version: 4, signed_extensions: vec![] };
+		RuntimeMetadataPrefixed::from(RuntimeMetadataV14::new(
+			pallets,
+			extrinsic,
+			meta_type::<()>(),
+		))
+		.encode()
+	}
+
+	#[test]
+	fn layout_is_read_from_metadata() {
+		let system = PalletMetadata {
+			calls: Some(PalletCallMetadata { ty: meta_type::<SystemCall>() }),
+			..pallet("System", 0)
+		};
+		let preimage = PalletMetadata {
+			storage: Some(PalletStorageMetadata {
+				prefix: "Preimages",
+				entries: vec![
+					storage_map::<RequestStatus>("StatusFor"),
+					storage_map::<Vec<u8>>("PreimageFor"),
+				],
+			}),
+			..pallet("Preimages", 7)
+		};
+
+		assert_eq!(
+			RuntimeLayout::from_metadata(&metadata(vec![system.clone(), preimage])),
+			Some(RuntimeLayout {
+				preimage_prefix: "Preimages".into(),
+				requested_status: 1,
+				set_code_calls: vec![[0, 2], [0, 3]],
+			}),
+		);
+		assert_eq!(RuntimeLayout::from_metadata(&metadata(vec![system])), None);
+	}
+
+	#[test]
+	fn runtime_code_is_found_in_calls() {
+		let code = substrate_test_runtime::wasm_binary_unwrap().to_vec();
+		let layout = RuntimeLayout {
+			preimage_prefix: "Preimage".into(),
+			requested_status: 1,
+			set_code_calls: vec![[0, 3]],
+		};
+
+		let set_code = [&[0u8, 3][..], &code.encode()].concat();
+		assert_eq!(layout.runtime_code(&set_code), Some(code.clone()));
+
+		let other_call = [&[0u8, 4][..], &code.encode()].concat();
+		assert_eq!(layout.runtime_code(&other_call), None);
+
+		let remark = [&[0u8, 3][..], &vec![1u8; 100].encode()].concat();
+		assert_eq!(layout.runtime_code(&remark), None);
+
+		let truncated = &set_code[..set_code.len() - 1];
+		assert_eq!(layout.runtime_code(truncated), None);
+	}
+}
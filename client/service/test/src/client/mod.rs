@@ -23,7 +23,7 @@ use sc_block_builder::BlockBuilderProvider;
 use sc_client_api::{
 	in_mem, BlockBackend, BlockchainEvents, FinalityNotifications, StorageProvider,
 };
-use sc_client_db::{Backend, DatabaseSettings, DatabaseSource, KeepBlocks, PruningMode};
+use sc_client_db::{Backend, BlocksPruning, DatabaseSettings, DatabaseSource, PruningMode};
 use sc_consensus::{
 	BlockCheckParams, BlockImport, BlockImportParams, ForkChoiceStrategy, ImportResult,
 };
@@ -1200,7 +1200,10 @@ fn doesnt_import_blocks_that_revert_finality() {
 				state_cache_size: 1 << 20,
 				state_cache_child_ratio: None,
 				state_pruning: Some(PruningMode::ArchiveAll),
-				keep_blocks: KeepBlocks::All,
+				blocks_pruning: BlocksPruning::keep_all(),
+				flat_state: false,
+				state_diff_index: false,
+				storage_item_stats: false,
 				source: DatabaseSource::RocksDb { path: tmp.path().into(), cache_size: 1024 },
 			},
 			u64::MAX,
@@ -1427,7 +1430,10 @@ fn returns_status_for_pruned_blocks() {
 				state_cache_size: 1 << 20,
 				state_cache_child_ratio: None,
 				state_pruning: Some(PruningMode::keep_blocks(1)),
-				keep_blocks: KeepBlocks::All,
+				blocks_pruning: BlocksPruning::keep_all(),
+				flat_state: false,
+				state_diff_index: false,
+				storage_item_stats: false,
 				source: DatabaseSource::RocksDb { path: tmp.path().into(), cache_size: 1024 },
 			},
 			u64::MAX,
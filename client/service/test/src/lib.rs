@@ -29,8 +29,8 @@ use sc_network::{
 use sc_service::{
 	client::Client,
 	config::{BasePath, DatabaseSource, KeystoreConfig},
-	ChainSpecExtension, Configuration, Error, GenericChainSpec, KeepBlocks, Role, RuntimeGenesis,
-	SpawnTaskHandle, TaskManager,
+	BlocksPruning, ChainSpecExtension, Configuration, Error, GenericChainSpec, Role,
+	RuntimeGenesis, SpawnTaskHandle, TaskManager,
 };
 use sc_transaction_pool_api::TransactionPool;
 use sp_api::BlockId;
@@ -218,8 +218,11 @@ fn node_config<
 			.collect(),
 	);
 
-	network_config.transport =
-		TransportConfig::Normal { enable_mdns: false, allow_private_ipv4: true };
+	network_config.transport = TransportConfig::Normal {
+		enable_mdns: false,
+		allow_private_ipv4: true,
+		websocket_tls: None,
+	};
 
 	Configuration {
 		impl_name: String::from("network-test-impl"),
@@ -234,11 +237,19 @@ fn node_config<
 		state_cache_size: 16777216,
 		state_cache_child_ratio: None,
 		state_pruning: Default::default(),
-		keep_blocks: KeepBlocks::All,
+		blocks_pruning: BlocksPruning::keep_all(),
+		flat_state: false,
+		state_diff_index: false,
+		storage_item_stats: false,
 		chain_spec: Box::new((*spec).clone()),
 		wasm_method: sc_service::config::WasmExecutionMethod::Interpreted,
 		wasm_runtime_overrides: Default::default(),
+		runtime_artifacts_path: Default::default(),
+		wasm_host_function_profiling: false,
+		wasm_heap_usage_warning: None,
+		wasm_sandbox_backend: None,
 		execution_strategies: Default::default(),
+		execution_budgets: Default::default(),
 		rpc_http: None,
 		rpc_ipc: None,
 		rpc_ws: None,
@@ -39,7 +39,9 @@
 //!
 //! # Pruning.
 //! See `RefWindow` for pruning algorithm details. `StateDb` prunes on each canonicalization until
-//! pruning constraints are satisfied.
+//! pruning constraints are satisfied. The window may be limited by a number of blocks, by the
+//! memory used by its journals or by the age of the blocks, as given by the timestamps passed to
+//! [`StateDb::canonicalize_block_with_timestamp`].
 
 mod noncanonical;
 mod pruning;
@@ -56,6 +58,7 @@ use sc_client_api::{MemorySize, StateDbMemoryInfo};
 use std::{
 	collections::{hash_map::Entry, HashMap},
 	fmt,
+	time::Duration,
 };
 
 const PRUNING_MODE: &[u8] = b"mode";
@@ -204,14 +207,19 @@ pub struct CommitSet<H: Hash> {
 	pub meta: ChangeSet<Vec<u8>>,
 }
 
-/// Pruning constraints. If none are specified pruning is
+/// Pruning constraints. Canonical blocks are pruned while any of the specified constraints is
+/// exceeded. If none are specified, only non-canonical states are kept.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Constraints {
 	/// Maximum blocks. Defaults to 0 when unspecified, effectively keeping only non-canonical
 	/// states.
 	pub max_blocks: Option<u32>,
-	/// Maximum memory in the pruning overlay.
+	/// Maximum memory in bytes used by the journals of the pruning window. The non-canonical
+	/// overlay isn't counted, it can't be reduced by pruning canonical blocks.
 	pub max_mem: Option<usize>,
+	/// Maximum age of the pruning window, measured between the timestamps of its oldest and most
+	/// recent blocks.
+	pub max_age: Option<Duration>,
 }
 
 /// Pruning mode.
@@ -228,7 +236,21 @@ pub enum PruningMode {
 impl PruningMode {
 	/// Create a mode that keeps given number of blocks.
 	pub fn keep_blocks(n: u32) -> PruningMode {
-		PruningMode::Constrained(Constraints { max_blocks: Some(n), max_mem: None })
+		PruningMode::Constrained(Constraints { max_blocks: Some(n), max_mem: None, max_age: None })
+	}
+
+	/// Create a mode that keeps the blocks of the given time span.
+	pub fn keep_age(age: Duration) -> PruningMode {
+		PruningMode::Constrained(Constraints {
+			max_blocks: None,
+			max_mem: None,
+			max_age: Some(age),
+		})
+	}
+
+	/// Does this mode need the timestamps of the canonicalized blocks?
+	pub fn uses_timestamps(&self) -> bool {
+		matches!(self, PruningMode::Constrained(Constraints { max_age: Some(_), .. }))
 	}
 
 	/// Is this an archive (either ArchiveAll or ArchiveCanonical) pruning mode?
@@ -266,7 +288,7 @@ impl Default for PruningMode {
 
 impl Default for Constraints {
 	fn default() -> Self {
-		Self { max_blocks: Some(256), max_mem: None }
+		Self { max_blocks: Some(256), max_mem: None, max_age: None }
 	}
 }
 
@@ -293,7 +315,6 @@ impl<BlockHash: Hash + MallocSizeOf, Key: Hash + MallocSizeOf> StateDbSync<Block
 
 		let non_canonical: NonCanonicalOverlay<BlockHash, Key> = NonCanonicalOverlay::new(db)?;
 		let pruning: Option<RefWindow<BlockHash, Key>> = match mode {
-			PruningMode::Constrained(Constraints { max_mem: Some(_), .. }) => unimplemented!(),
 			PruningMode::Constrained(_) => Some(RefWindow::new(db, ref_counting)?),
 			PruningMode::ArchiveAll | PruningMode::ArchiveCanonical => None,
 		};
@@ -324,6 +345,7 @@ impl<BlockHash: Hash + MallocSizeOf, Key: Hash + MallocSizeOf> StateDbSync<Block
 	fn canonicalize_block<E: fmt::Debug>(
 		&mut self,
 		hash: &BlockHash,
+		timestamp: Option<u64>,
 	) -> Result<CommitSet<Key>, Error<E>> {
 		let mut commit = CommitSet::default();
 		if self.mode == PruningMode::ArchiveAll {
@@ -337,7 +359,7 @@ impl<BlockHash: Hash + MallocSizeOf, Key: Hash + MallocSizeOf> StateDbSync<Block
 			Err(e) => return Err(e.into()),
 		};
 		if let Some(ref mut pruning) = self.pruning {
-			pruning.note_canonical(hash, &mut commit);
+			pruning.note_canonical(hash, timestamp, &mut commit);
 		}
 		self.prune(&mut commit);
 		Ok(commit)
@@ -366,12 +388,30 @@ impl<BlockHash: Hash + MallocSizeOf, Key: Hash + MallocSizeOf> StateDbSync<Block
 		if let (&mut Some(ref mut pruning), &PruningMode::Constrained(ref constraints)) =
 			(&mut self.pruning, &self.mode)
 		{
+			let unconstrained = constraints.max_blocks.is_none() &&
+				constraints.max_mem.is_none() &&
+				constraints.max_age.is_none();
+			let mut mem_used = match constraints.max_mem {
+				Some(_) => pruning.mem_used(),
+				None => 0,
+			};
 			loop {
-				if pruning.window_size() <= constraints.max_blocks.unwrap_or(0) as u64 {
+				if pruning.window_size() == 0 {
 					break
 				}
 
-				if constraints.max_mem.map_or(false, |m| pruning.mem_used() > m) {
+				let over_blocks = constraints
+					.max_blocks
+					.map_or(unconstrained, |m| pruning.window_size() > m as u64);
+				let over_mem = constraints.max_mem.map_or(false, |m| mem_used > m);
+				let over_age = constraints.max_age.map_or(false, |m| {
+					match (pruning.next_timestamp(), pruning.last_timestamp()) {
+						(Some(first), Some(last)) =>
+							last.saturating_sub(first) > m.as_millis() as u64,
+						_ => false,
+					}
+				});
+				if !over_blocks && !over_mem && !over_age {
 					break
 				}
 
@@ -379,6 +419,7 @@ impl<BlockHash: Hash + MallocSizeOf, Key: Hash + MallocSizeOf> StateDbSync<Block
 				if pruning.next_hash().map_or(false, |h| pinned.contains_key(&h)) {
 					break
 				}
+				mem_used = mem_used.saturating_sub(pruning.next_mem_used());
 				pruning.prune_one(commit);
 			}
 		}
@@ -484,6 +525,8 @@ impl<BlockHash: Hash + MallocSizeOf, Key: Hash + MallocSizeOf> StateDbSync<Block
 			non_canonical: MemorySize::from_bytes(malloc_size(&self.non_canonical)),
 			pruning: self.pruning.as_ref().map(|p| MemorySize::from_bytes(malloc_size(p))),
 			pinned: MemorySize::from_bytes(malloc_size(&self.pinned)),
+			pruning_window_blocks: self.pruning.as_ref().map(|p| p.window_size()),
+			pruning_window_age: self.pruning.as_ref().and_then(|p| p.window_age()),
 		}
 	}
 }
@@ -563,7 +606,18 @@ impl<BlockHash: Hash + MallocSizeOf, Key: Hash + MallocSizeOf> StateDb<BlockHash
 		&self,
 		hash: &BlockHash,
 	) -> Result<CommitSet<Key>, Error<E>> {
-		self.db.write().canonicalize_block(hash)
+		self.db.write().canonicalize_block(hash, None)
+	}
+
+	/// Finalize a previously inserted block, noting its timestamp in milliseconds.
+	///
+	/// The timestamp is used to limit the pruning window by age, see [`Constraints::max_age`].
+	pub fn canonicalize_block_with_timestamp<E: fmt::Debug>(
+		&self,
+		hash: &BlockHash,
+		timestamp: Option<u64>,
+	) -> Result<CommitSet<Key>, Error<E>> {
+		self.db.write().canonicalize_block(hash, timestamp)
 	}
 
 	/// Prevents pruning of specified block and its descendants.
@@ -629,7 +683,10 @@ impl<BlockHash: Hash + MallocSizeOf, Key: Hash + MallocSizeOf> StateDb<BlockHash
 	}
 }
 
-fn fetch_stored_pruning_mode<D: MetaDb>(db: &D) -> Result<Option<PruningMode>, Error<D::Error>> {
+/// Returns the pruning mode the state database stored in `db` has been created with, if any.
+pub fn fetch_stored_pruning_mode<D: MetaDb>(
+	db: &D,
+) -> Result<Option<PruningMode>, Error<D::Error>> {
 	let meta_key_mode = to_meta_key(PRUNING_MODE, &());
 	if let Some(stored_mode) = db.get_meta(&meta_key_mode).map_err(Error::Db)? {
 		if let Some(mode) = PruningMode::from_id(&stored_mode) {
@@ -646,6 +703,21 @@ fn fetch_stored_pruning_mode<D: MetaDb>(db: &D) -> Result<Option<PruningMode>, E
 	}
 }
 
+/// Reads the journals of the blocks that haven't been canonicalized yet, and of the blocks in the
+/// pruning window, and applies `f` to the keys of the state nodes they reference.
+///
+/// Returns the rewritten journal records along with the meta keys they must be stored at. This is
+/// used when moving the state database from a backend without reference counting to a backend
+/// counting the references to the nodes, and keying them differently.
+pub fn rewrite_journal_keys<BlockHash: Hash, D: MetaDb>(
+	db: &D,
+	f: impl Fn(Vec<u8>) -> Vec<u8>,
+) -> Result<Vec<(Vec<u8>, DBValue)>, Error<D::Error>> {
+	let mut records = noncanonical::rewrite_journal_keys::<BlockHash, D>(db, &f)?;
+	records.extend(pruning::rewrite_journal_keys::<BlockHash, D>(db, &f)?);
+	Ok(records)
+}
+
 fn choose_pruning_mode(
 	stored: PruningMode,
 	requested: PruningMode,
@@ -667,7 +739,7 @@ mod tests {
 		Constraints, Error, PruningMode, StateDb, StateDbError,
 	};
 	use sp_core::H256;
-	use std::io;
+	use std::{io, time::Duration};
 
 	fn make_test_db(settings: PruningMode) -> (TestDb, StateDb<H256, H256>) {
 		let mut db = make_db(&[91, 921, 922, 93, 94]);
@@ -755,6 +827,7 @@ mod tests {
 		let (db, _) = make_test_db(PruningMode::Constrained(Constraints {
 			max_blocks: Some(0),
 			max_mem: None,
+			max_age: None,
 		}));
 		assert!(db.data_eq(&make_db(&[21, 3, 922, 94])));
 	}
@@ -764,6 +837,7 @@ mod tests {
 		let (db, sdb) = make_test_db(PruningMode::Constrained(Constraints {
 			max_blocks: Some(1),
 			max_mem: None,
+			max_age: None,
 		}));
 		assert!(sdb.is_pruned(&H256::from_low_u64_be(0), 0));
 		assert!(sdb.is_pruned(&H256::from_low_u64_be(1), 1));
@@ -777,6 +851,7 @@ mod tests {
 		let (db, sdb) = make_test_db(PruningMode::Constrained(Constraints {
 			max_blocks: Some(2),
 			max_mem: None,
+			max_age: None,
 		}));
 		assert!(sdb.is_pruned(&H256::from_low_u64_be(0), 0));
 		assert!(sdb.is_pruned(&H256::from_low_u64_be(1), 1));
@@ -785,6 +860,61 @@ mod tests {
 		assert!(db.data_eq(&make_db(&[1, 21, 3, 921, 922, 93, 94])));
 	}
 
+	#[test]
+	fn prune_by_mem() {
+		let (db, _) = make_test_db(PruningMode::Constrained(Constraints {
+			max_blocks: None,
+			max_mem: Some(0),
+			max_age: None,
+		}));
+		assert!(db.data_eq(&make_db(&[21, 3, 922, 94])));
+
+		let (db, _) = make_test_db(PruningMode::Constrained(Constraints {
+			max_blocks: None,
+			max_mem: Some(usize::MAX),
+			max_age: None,
+		}));
+		assert!(db.data_eq(&make_db(&[1, 21, 3, 91, 921, 922, 93, 94])));
+	}
+
+	#[test]
+	fn prune_by_age() {
+		let mut db = make_db(&[]);
+		let (state_db_init, sdb) = StateDb::open(
+			&mut db,
+			Some(PruningMode::keep_age(Duration::from_secs(12))),
+			false,
+			true,
+		)
+		.unwrap();
+		db.commit(&state_db_init);
+		for n in 1..=4u64 {
+			db.commit(
+				&sdb.insert_block::<io::Error>(
+					&H256::from_low_u64_be(n),
+					n,
+					&H256::from_low_u64_be(n - 1),
+					make_changeset(&[n], &[]),
+				)
+				.unwrap(),
+			);
+			sdb.apply_pending();
+			db.commit(
+				&sdb.canonicalize_block_with_timestamp::<io::Error>(
+					&H256::from_low_u64_be(n),
+					Some((n - 1) * 6_000),
+				)
+				.unwrap(),
+			);
+			sdb.apply_pending();
+		}
+		assert!(sdb.is_pruned(&H256::from_low_u64_be(1), 1));
+		assert!(!sdb.is_pruned(&H256::from_low_u64_be(2), 2));
+		let info = sdb.memory_info();
+		assert_eq!(info.pruning_window_blocks, Some(3));
+		assert_eq!(info.pruning_window_age, Some(Duration::from_secs(12)));
+	}
+
 	#[test]
 	fn detects_incompatible_mode() {
 		let mut db = make_db(&[]);
@@ -801,7 +931,11 @@ mod tests {
 				)
 				.unwrap(),
 		);
-		let new_mode = PruningMode::Constrained(Constraints { max_blocks: Some(2), max_mem: None });
+		let new_mode = PruningMode::Constrained(Constraints {
+			max_blocks: Some(2),
+			max_mem: None,
+			max_age: None,
+		});
 		let state_db_open_result: Result<(_, StateDb<H256, H256>), _> =
 			StateDb::open(&mut db, Some(new_mode), false, false);
 		assert!(state_db_open_result.is_err());
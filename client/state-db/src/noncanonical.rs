@@ -84,6 +84,44 @@ fn to_journal_key(block: u64, index: u64) -> Vec<u8> {
 	to_meta_key(NON_CANONICAL_JOURNAL, &(block, index))
 }
 
+/// Reads the journals of the non-canonicalized blocks and applies `f` to the node keys they
+/// reference, returning the rewritten journal records along with their meta keys.
+pub(crate) fn rewrite_journal_keys<BlockHash: Hash, D: MetaDb>(
+	db: &D,
+	f: impl Fn(Vec<u8>) -> Vec<u8>,
+) -> Result<Vec<(Vec<u8>, DBValue)>, Error<D::Error>> {
+	let last_canonicalized = db.get_meta(&to_meta_key(LAST_CANONICAL, &())).map_err(Error::Db)?;
+	let mut block = match last_canonicalized {
+		Some(buffer) => <(BlockHash, u64)>::decode(&mut buffer.as_slice())?.1 + 1,
+		None => return Ok(Vec::new()),
+	};
+
+	let mut records = Vec::new();
+	loop {
+		let mut level_empty = true;
+		for index in 0..MAX_BLOCKS_PER_LEVEL {
+			let journal_key = to_journal_key(block, index);
+			if let Some(record) = db.get_meta(&journal_key).map_err(Error::Db)? {
+				let record: JournalRecord<BlockHash, Vec<u8>> =
+					Decode::decode(&mut record.as_slice())?;
+				let record = JournalRecord {
+					hash: record.hash,
+					parent_hash: record.parent_hash,
+					inserted: record.inserted.into_iter().map(|(k, v)| (f(k), v)).collect(),
+					deleted: record.deleted.into_iter().map(&f).collect(),
+				};
+				records.push((journal_key, record.encode()));
+				level_empty = false;
+			}
+		}
+		if level_empty {
+			break
+		}
+		block += 1;
+	}
+	Ok(records)
+}
+
 #[cfg_attr(test, derive(PartialEq, Debug))]
 #[derive(parity_util_mem_derive::MallocSizeOf)]
 struct BlockOverlay<BlockHash: Hash, Key: Hash> {
@@ -634,9 +672,9 @@ impl<BlockHash: Hash, Key: Hash> NonCanonicalOverlay<BlockHash, Key> {
 
 #[cfg(test)]
 mod tests {
-	use super::{to_journal_key, NonCanonicalOverlay};
+	use super::{rewrite_journal_keys, to_journal_key, NonCanonicalOverlay};
 	use crate::{
-		test::{make_changeset, make_db},
+		test::{make_changeset, make_db, TestDb},
 		ChangeSet, CommitSet, MetaDb, StateDbError,
 	};
 	use sp_core::H256;
@@ -743,6 +781,46 @@ mod tests {
 		assert!(db.data_eq(&make_db(&[1, 3, 4])));
 	}
 
+	#[test]
+	fn rewrite_journal_keys_works() {
+		fn commit_meta(db: &mut TestDb, commit: CommitSet<Vec<u8>>) {
+			db.meta.extend(commit.meta.inserted);
+			for key in commit.meta.deleted {
+				db.meta.remove(&key);
+			}
+		}
+
+		let (h1, h2, h3) = (H256::random(), H256::random(), H256::random());
+		let mut db = make_db(&[]);
+		let mut overlay = NonCanonicalOverlay::<H256, Vec<u8>>::new(&db).unwrap();
+		let changeset = ChangeSet { inserted: vec![(vec![0, 1], vec![1])], deleted: vec![] };
+		commit_meta(&mut db, overlay.insert(&h1, 1, &H256::default(), changeset).unwrap());
+		let mut commit = CommitSet::default();
+		overlay.canonicalize(&h1, &mut commit).unwrap();
+		overlay.apply_pending();
+		commit_meta(&mut db, commit);
+
+		let changeset = ChangeSet { inserted: vec![(vec![0, 2], vec![2])], deleted: vec![] };
+		commit_meta(&mut db, overlay.insert(&h2, 2, &h1, changeset).unwrap());
+		let changeset =
+			ChangeSet { inserted: vec![(vec![0, 3], vec![3])], deleted: vec![vec![0, 2]] };
+		commit_meta(&mut db, overlay.insert(&h3, 3, &h2, changeset).unwrap());
+		overlay.apply_pending();
+
+		let records = rewrite_journal_keys::<H256, _>(&db, |key| key[1..].to_vec()).unwrap();
+		assert_eq!(
+			records.iter().map(|(key, _)| key.clone()).collect::<Vec<_>>(),
+			vec![to_journal_key(2, 0), to_journal_key(3, 0)],
+		);
+		db.meta.extend(records);
+
+		let overlay = NonCanonicalOverlay::<H256, Vec<u8>>::new(&db).unwrap();
+		assert_eq!(overlay.get(&vec![2u8][..]), Some(vec![2]));
+		assert_eq!(overlay.get(&vec![3u8][..]), Some(vec![3]));
+		assert_eq!(overlay.get(&vec![0u8, 3][..]), None);
+		assert_eq!(overlay.levels[1].blocks[0].deleted, vec![vec![2]]);
+	}
+
 	#[test]
 	fn restore_from_journal() {
 		let h1 = H256::random();
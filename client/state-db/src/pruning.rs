@@ -23,14 +23,22 @@
 //! If a node is re-inserted into the window it gets removed from
 //! the death list.
 //! The changes are journaled in the DB.
+//!
+//! Blocks may be noted with a timestamp, which is stored next to the journal record and used to
+//! limit the window by age.
 
-use crate::{to_meta_key, CommitSet, Error, Hash, MetaDb};
+use crate::{to_meta_key, CommitSet, DBValue, Error, Hash, MetaDb};
 use codec::{Decode, Encode};
 use log::{trace, warn};
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::{
+	collections::{HashMap, HashSet, VecDeque},
+	mem,
+	time::Duration,
+};
 
 const LAST_PRUNED: &[u8] = b"last_pruned";
 const PRUNING_JOURNAL: &[u8] = b"pruning_journal";
+const PRUNING_TIMESTAMP: &[u8] = b"pruning_timestamp";
 
 /// See module documentation.
 #[derive(parity_util_mem_derive::MallocSizeOf)]
@@ -58,6 +66,8 @@ struct DeathRow<BlockHash: Hash, Key: Hash> {
 	hash: BlockHash,
 	journal_key: Vec<u8>,
 	deleted: HashSet<Key>,
+	/// Timestamp of the block in milliseconds, if known.
+	timestamp: Option<u64>,
 }
 
 #[derive(Encode, Decode)]
@@ -71,6 +81,33 @@ fn to_journal_key(block: u64) -> Vec<u8> {
 	to_meta_key(PRUNING_JOURNAL, &block)
 }
 
+fn to_timestamp_key(block: u64) -> Vec<u8> {
+	to_meta_key(PRUNING_TIMESTAMP, &block)
+}
+
+/// Reads the pruning journal and applies `f` to the keys of the nodes pending deletion, returning
+/// the journal records of a backend counting the references to the nodes along with their meta
+/// keys.
+///
+/// The journal must have been written with `count_insertions` set. The nodes re-inserted after
+/// being deleted are left out of the records, as the backend only counted them once.
+pub(crate) fn rewrite_journal_keys<BlockHash: Hash, D: MetaDb>(
+	db: &D,
+	f: impl Fn(Vec<u8>) -> Vec<u8>,
+) -> Result<Vec<(Vec<u8>, DBValue)>, Error<D::Error>> {
+	let window = RefWindow::<BlockHash, Vec<u8>>::new(db, true)?;
+	Ok(window
+		.death_rows
+		.into_iter()
+		.map(|row| {
+			let mut deleted: Vec<_> = row.deleted.into_iter().map(&f).collect();
+			deleted.sort();
+			let record = JournalRecord { hash: row.hash, inserted: Vec::new(), deleted };
+			(row.journal_key, record.encode())
+		})
+		.collect())
+}
+
 impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 	pub fn new<D: MetaDb>(
 		db: &D,
@@ -98,10 +135,16 @@ impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 				Some(record) => {
 					let record: JournalRecord<BlockHash, Key> =
 						Decode::decode(&mut record.as_slice())?;
+					let timestamp =
+						match db.get_meta(&to_timestamp_key(block)).map_err(Error::Db)? {
+							Some(buffer) => Some(u64::decode(&mut buffer.as_slice())?),
+							None => None,
+						};
 					trace!(target: "state-db", "Pruning journal entry {} ({} inserted, {} deleted)", block, record.inserted.len(), record.deleted.len());
 					pruning.import(
 						&record.hash,
 						journal_key,
+						timestamp,
 						record.inserted.into_iter(),
 						record.deleted,
 					);
@@ -117,6 +160,7 @@ impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 		&mut self,
 		hash: &BlockHash,
 		journal_key: Vec<u8>,
+		timestamp: Option<u64>,
 		inserted: I,
 		deleted: Vec<Key>,
 	) {
@@ -138,6 +182,7 @@ impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 			hash: hash.clone(),
 			deleted: deleted.into_iter().collect(),
 			journal_key,
+			timestamp,
 		});
 	}
 
@@ -149,8 +194,56 @@ impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 		self.death_rows.get(self.pending_prunings).map(|r| r.hash.clone())
 	}
 
+	/// Estimated memory used by the blocks in the window, in bytes.
 	pub fn mem_used(&self) -> usize {
-		0
+		self.death_rows
+			.iter()
+			.skip(self.pending_prunings)
+			.map(|r| self.row_mem_used(r))
+			.sum()
+	}
+
+	/// Estimated memory that pruning the next block would free, in bytes.
+	pub fn next_mem_used(&self) -> usize {
+		self.death_rows.get(self.pending_prunings).map_or(0, |r| self.row_mem_used(r))
+	}
+
+	fn row_mem_used(&self, row: &DeathRow<BlockHash, Key>) -> usize {
+		let key_size = if self.count_insertions {
+			// The key is also stored in `death_index`.
+			2 * mem::size_of::<Key>() + mem::size_of::<u64>()
+		} else {
+			mem::size_of::<Key>()
+		};
+		mem::size_of::<DeathRow<BlockHash, Key>>() +
+			row.journal_key.len() +
+			row.deleted.len() * key_size
+	}
+
+	/// Timestamp of the next block to be pruned.
+	///
+	/// A block that was noted without a timestamp is considered as old as the first block after it
+	/// that has one. Returns `None` if there is no such block.
+	pub fn next_timestamp(&self) -> Option<u64> {
+		self.death_rows.iter().skip(self.pending_prunings).find_map(|r| r.timestamp)
+	}
+
+	/// Timestamp of the most recent block in the window that has one.
+	pub fn last_timestamp(&self) -> Option<u64> {
+		self.death_rows
+			.iter()
+			.skip(self.pending_prunings)
+			.rev()
+			.find_map(|r| r.timestamp)
+	}
+
+	/// Time span between the oldest and the most recent block in the window, according to their
+	/// timestamps.
+	pub fn window_age(&self) -> Option<Duration> {
+		match (self.next_timestamp(), self.last_timestamp()) {
+			(Some(first), Some(last)) => Some(Duration::from_millis(last.saturating_sub(first))),
+			_ => None,
+		}
 	}
 
 	pub fn pending(&self) -> u64 {
@@ -169,14 +262,23 @@ impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 			commit.data.deleted.extend(pruned.deleted.iter().cloned());
 			commit.meta.inserted.push((to_meta_key(LAST_PRUNED, &()), index.encode()));
 			commit.meta.deleted.push(pruned.journal_key.clone());
+			if pruned.timestamp.is_some() {
+				commit.meta.deleted.push(to_timestamp_key(index));
+			}
 			self.pending_prunings += 1;
 		} else {
 			warn!(target: "state-db", "Trying to prune when there's nothing to prune");
 		}
 	}
 
-	/// Add a change set to the window. Creates a journal record and pushes it to `commit`
-	pub fn note_canonical(&mut self, hash: &BlockHash, commit: &mut CommitSet<Key>) {
+	/// Add a change set to the window. Creates a journal record and pushes it to `commit`.
+	/// `timestamp` is the time of the block in milliseconds, if known.
+	pub fn note_canonical(
+		&mut self,
+		hash: &BlockHash,
+		timestamp: Option<u64>,
+		commit: &mut CommitSet<Key>,
+	) {
 		trace!(target: "state-db", "Adding to pruning window: {:?} ({} inserted, {} deleted)", hash, commit.data.inserted.len(), commit.data.deleted.len());
 		let inserted = if self.count_insertions {
 			commit.data.inserted.iter().map(|(k, _)| k.clone()).collect()
@@ -188,9 +290,13 @@ impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 		let block = self.pending_number + self.death_rows.len() as u64;
 		let journal_key = to_journal_key(block);
 		commit.meta.inserted.push((journal_key.clone(), journal_record.encode()));
+		if let Some(timestamp) = timestamp {
+			commit.meta.inserted.push((to_timestamp_key(block), timestamp.encode()));
+		}
 		self.import(
 			&journal_record.hash,
 			journal_key,
+			timestamp,
 			journal_record.inserted.into_iter(),
 			journal_record.deleted,
 		);
@@ -235,12 +341,13 @@ impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 
 #[cfg(test)]
 mod tests {
-	use super::RefWindow;
+	use super::{rewrite_journal_keys, to_journal_key, RefWindow};
 	use crate::{
 		test::{make_commit, make_db, TestDb},
-		CommitSet,
+		ChangeSet, CommitSet,
 	};
 	use sp_core::H256;
+	use std::{collections::HashSet, time::Duration};
 
 	fn check_journal(pruning: &RefWindow<H256, H256>, db: &TestDb) {
 		let restored: RefWindow<H256, H256> = RefWindow::new(db, pruning.count_insertions).unwrap();
@@ -277,7 +384,7 @@ mod tests {
 		let mut pruning: RefWindow<H256, H256> = RefWindow::new(&db, true).unwrap();
 		let mut commit = make_commit(&[4, 5], &[1, 3]);
 		let h = H256::random();
-		pruning.note_canonical(&h, &mut commit);
+		pruning.note_canonical(&h, None, &mut commit);
 		db.commit(&commit);
 		assert!(pruning.have_block(&h));
 		pruning.apply_pending();
@@ -305,10 +412,10 @@ mod tests {
 		let mut db = make_db(&[1, 2, 3]);
 		let mut pruning: RefWindow<H256, H256> = RefWindow::new(&db, true).unwrap();
 		let mut commit = make_commit(&[4], &[1]);
-		pruning.note_canonical(&H256::random(), &mut commit);
+		pruning.note_canonical(&H256::random(), None, &mut commit);
 		db.commit(&commit);
 		let mut commit = make_commit(&[5], &[2]);
-		pruning.note_canonical(&H256::random(), &mut commit);
+		pruning.note_canonical(&H256::random(), None, &mut commit);
 		db.commit(&commit);
 		pruning.apply_pending();
 		assert!(db.data_eq(&make_db(&[1, 2, 3, 4, 5])));
@@ -333,10 +440,10 @@ mod tests {
 		let mut db = make_db(&[1, 2, 3]);
 		let mut pruning: RefWindow<H256, H256> = RefWindow::new(&db, true).unwrap();
 		let mut commit = make_commit(&[4], &[1]);
-		pruning.note_canonical(&H256::random(), &mut commit);
+		pruning.note_canonical(&H256::random(), None, &mut commit);
 		db.commit(&commit);
 		let mut commit = make_commit(&[5], &[2]);
-		pruning.note_canonical(&H256::random(), &mut commit);
+		pruning.note_canonical(&H256::random(), None, &mut commit);
 		db.commit(&commit);
 		assert!(db.data_eq(&make_db(&[1, 2, 3, 4, 5])));
 		let mut commit = CommitSet::default();
@@ -356,13 +463,13 @@ mod tests {
 		let mut db = make_db(&[1, 2, 3]);
 		let mut pruning: RefWindow<H256, H256> = RefWindow::new(&db, true).unwrap();
 		let mut commit = make_commit(&[], &[2]);
-		pruning.note_canonical(&H256::random(), &mut commit);
+		pruning.note_canonical(&H256::random(), None, &mut commit);
 		db.commit(&commit);
 		let mut commit = make_commit(&[2], &[]);
-		pruning.note_canonical(&H256::random(), &mut commit);
+		pruning.note_canonical(&H256::random(), None, &mut commit);
 		db.commit(&commit);
 		let mut commit = make_commit(&[], &[2]);
-		pruning.note_canonical(&H256::random(), &mut commit);
+		pruning.note_canonical(&H256::random(), None, &mut commit);
 		db.commit(&commit);
 		assert!(db.data_eq(&make_db(&[1, 2, 3])));
 		pruning.apply_pending();
@@ -389,13 +496,13 @@ mod tests {
 		let mut db = make_db(&[1, 2, 3]);
 		let mut pruning: RefWindow<H256, H256> = RefWindow::new(&db, true).unwrap();
 		let mut commit = make_commit(&[], &[2]);
-		pruning.note_canonical(&H256::random(), &mut commit);
+		pruning.note_canonical(&H256::random(), None, &mut commit);
 		db.commit(&commit);
 		let mut commit = make_commit(&[2], &[]);
-		pruning.note_canonical(&H256::random(), &mut commit);
+		pruning.note_canonical(&H256::random(), None, &mut commit);
 		db.commit(&commit);
 		let mut commit = make_commit(&[], &[2]);
-		pruning.note_canonical(&H256::random(), &mut commit);
+		pruning.note_canonical(&H256::random(), None, &mut commit);
 		db.commit(&commit);
 		assert!(db.data_eq(&make_db(&[1, 2, 3])));
 
@@ -419,13 +526,13 @@ mod tests {
 		let mut db = make_db(&[1, 2, 3]);
 		let mut pruning: RefWindow<H256, H256> = RefWindow::new(&db, false).unwrap();
 		let mut commit = make_commit(&[], &[2]);
-		pruning.note_canonical(&H256::random(), &mut commit);
+		pruning.note_canonical(&H256::random(), None, &mut commit);
 		db.commit(&commit);
 		let mut commit = make_commit(&[2], &[]);
-		pruning.note_canonical(&H256::random(), &mut commit);
+		pruning.note_canonical(&H256::random(), None, &mut commit);
 		db.commit(&commit);
 		let mut commit = make_commit(&[], &[2]);
-		pruning.note_canonical(&H256::random(), &mut commit);
+		pruning.note_canonical(&H256::random(), None, &mut commit);
 		db.commit(&commit);
 		assert!(db.data_eq(&make_db(&[1, 2, 3])));
 		pruning.apply_pending();
@@ -438,4 +545,98 @@ mod tests {
 		assert!(db.data_eq(&make_db(&[1, 3])));
 		assert!(pruning.death_index.is_empty());
 	}
+
+	#[test]
+	fn timestamps_are_journaled() {
+		let mut db = make_db(&[1, 2, 3]);
+		let mut pruning: RefWindow<H256, H256> = RefWindow::new(&db, true).unwrap();
+		let mut commit = make_commit(&[4], &[1]);
+		pruning.note_canonical(&H256::random(), None, &mut commit);
+		db.commit(&commit);
+		let mut commit = make_commit(&[5], &[2]);
+		pruning.note_canonical(&H256::random(), Some(6_000), &mut commit);
+		db.commit(&commit);
+		let mut commit = make_commit(&[6], &[3]);
+		pruning.note_canonical(&H256::random(), Some(18_000), &mut commit);
+		db.commit(&commit);
+		pruning.apply_pending();
+
+		check_journal(&pruning, &db);
+		// The first block has no timestamp and is as old as the second one.
+		assert_eq!(pruning.next_timestamp(), Some(6_000));
+		assert_eq!(pruning.last_timestamp(), Some(18_000));
+		assert_eq!(pruning.window_age(), Some(Duration::from_secs(12)));
+
+		let mut commit = CommitSet::default();
+		pruning.prune_one(&mut commit);
+		pruning.prune_one(&mut commit);
+		db.commit(&commit);
+		pruning.apply_pending();
+		check_journal(&pruning, &db);
+		assert_eq!(pruning.window_age(), Some(Duration::ZERO));
+		assert_eq!(db.meta.len(), 3);
+	}
+
+	#[test]
+	fn mem_used_follows_window() {
+		let mut db = make_db(&[1, 2, 3]);
+		let mut pruning: RefWindow<H256, H256> = RefWindow::new(&db, true).unwrap();
+		assert_eq!(pruning.mem_used(), 0);
+		let mut commit = make_commit(&[4], &[1, 2]);
+		pruning.note_canonical(&H256::random(), None, &mut commit);
+		db.commit(&commit);
+		let mut commit = make_commit(&[5], &[3]);
+		pruning.note_canonical(&H256::random(), None, &mut commit);
+		db.commit(&commit);
+		pruning.apply_pending();
+
+		let used = pruning.mem_used();
+		let first = pruning.next_mem_used();
+		assert!(first > 0 && first < used);
+
+		let mut commit = CommitSet::default();
+		pruning.prune_one(&mut commit);
+		assert_eq!(pruning.mem_used(), used - first);
+		pruning.prune_one(&mut commit);
+		assert_eq!(pruning.mem_used(), 0);
+		db.commit(&commit);
+		pruning.apply_pending();
+		assert_eq!(pruning.mem_used(), 0);
+	}
+
+	#[test]
+	fn rewrite_journal_keys_works() {
+		fn commit_set(inserted: &[u8], deleted: &[u8]) -> CommitSet<Vec<u8>> {
+			CommitSet {
+				data: ChangeSet {
+					inserted: inserted.iter().map(|v| (vec![0, *v], vec![*v])).collect(),
+					deleted: deleted.iter().map(|v| vec![0, *v]).collect(),
+				},
+				meta: ChangeSet::default(),
+			}
+		}
+
+		let mut db = make_db(&[]);
+		let mut pruning: RefWindow<H256, Vec<u8>> = RefWindow::new(&db, true).unwrap();
+		let mut commit = commit_set(&[3], &[1, 2]);
+		pruning.note_canonical(&H256::random(), Some(10), &mut commit);
+		db.meta.extend(commit.meta.inserted);
+		let mut commit = commit_set(&[1], &[3]);
+		pruning.note_canonical(&H256::random(), None, &mut commit);
+		db.meta.extend(commit.meta.inserted);
+
+		let records = rewrite_journal_keys::<H256, _>(&db, |key| key[1..].to_vec()).unwrap();
+		assert_eq!(
+			records.iter().map(|(key, _)| key.clone()).collect::<Vec<_>>(),
+			vec![to_journal_key(0), to_journal_key(1)],
+		);
+		db.meta.extend(records);
+
+		let restored: RefWindow<H256, Vec<u8>> = RefWindow::new(&db, false).unwrap();
+		// Node 1 is re-inserted by the second block, it isn't pending deletion anymore.
+		let deleted: Vec<HashSet<Vec<u8>>> =
+			restored.death_rows.iter().map(|row| row.deleted.clone()).collect();
+		assert_eq!(deleted, vec![HashSet::from([vec![2]]), HashSet::from([vec![3]])]);
+		assert_eq!(restored.death_rows[0].timestamp, Some(10));
+	}
 }
@@ -16,6 +16,10 @@
 
 //! Utilities for tracing block execution
 
+mod profile;
+
+pub use profile::BlockProfiler;
+
 use std::{
 	collections::HashMap,
 	sync::{
@@ -216,25 +220,9 @@ where
 	/// prefixes in `Self::storage_keys`.
 	pub fn trace_block(&self) -> TraceBlockResult<TraceBlockResponse> {
 		tracing::debug!(target: "state_tracing", "Tracing block: {}", self.block);
-		// Prepare the block
 		let id = BlockId::Hash(self.block);
-		let mut header = self
-			.client
-			.header(id)
-			.map_err(Error::InvalidBlockId)?
-			.ok_or_else(|| Error::MissingBlockComponent("Header not found".to_string()))?;
-		let extrinsics = self
-			.client
-			.block_body(&id)
-			.map_err(Error::InvalidBlockId)?
-			.ok_or_else(|| Error::MissingBlockComponent("Extrinsics not found".to_string()))?;
-		tracing::debug!(target: "state_tracing", "Found {} extrinsics", extrinsics.len());
-		let parent_hash = *header.parent_hash();
-		let parent_id = BlockId::Hash(parent_hash);
-		// Remove all `Seal`s as they are added by the consensus engines after building the block.
-		// On import they are normally removed by the consensus engine.
-		header.digest_mut().logs.retain(|d| d.as_seal().is_none());
-		let block = Block::new(header, extrinsics);
+		let block = prepare_block(&*self.client, self.block)?;
+		let parent_id = BlockId::Hash(*block.header().parent_hash());
 
 		let targets = if let Some(t) = &self.targets { t } else { DEFAULT_TARGETS };
 		let block_subscriber = BlockSubscriber::new(targets);
@@ -312,6 +300,28 @@ where
 	}
 }
 
+/// Fetch the block `hash` for re-execution on top of its parent.
+fn prepare_block<Block, Client>(client: &Client, hash: Block::Hash) -> TraceBlockResult<Block>
+where
+	Block: BlockT,
+	Client: HeaderBackend<Block> + BlockBackend<Block>,
+{
+	let id = BlockId::Hash(hash);
+	let mut header = client
+		.header(id)
+		.map_err(Error::InvalidBlockId)?
+		.ok_or_else(|| Error::MissingBlockComponent("Header not found".to_string()))?;
+	let extrinsics = client
+		.block_body(&id)
+		.map_err(Error::InvalidBlockId)?
+		.ok_or_else(|| Error::MissingBlockComponent("Extrinsics not found".to_string()))?;
+	tracing::debug!(target: "state_tracing", "Found {} extrinsics", extrinsics.len());
+	// Remove all `Seal`s as they are added by the consensus engines after building the block.
+	// On import they are normally removed by the consensus engine.
+	header.digest_mut().logs.retain(|d| d.as_seal().is_none());
+	Ok(Block::new(header, extrinsics))
+}
+
 fn event_values_filter(event: &TraceEvent, filter_kind: &str, values: &str) -> bool {
 	event
 		.values
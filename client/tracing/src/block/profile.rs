@@ -0,0 +1,162 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Host function profiling of block execution
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use parking_lot::Mutex;
+
+use super::{block_id_as_string, prepare_block, Error, TraceBlockResult};
+use sc_client_api::{BlockBackend, CallExecutor, ExecutionStrategy, ExecutorProvider};
+use sp_api::Encode;
+use sp_blockchain::HeaderBackend;
+use sp_core::traits::{HostFunctionProfiler, HostFunctionProfilerExt};
+use sp_externalities::Extensions;
+use sp_rpc::profiling::{BlockProfile, HostFunctionProfile};
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, Header},
+};
+
+/// Holds a reference to the client in order to re-execute the given block in wasm and record
+/// the host functions called by the runtime.
+///
+/// Note: requires the executor to be created with host function profiling enabled.
+pub struct BlockProfiler<Block: BlockT, Client> {
+	client: Arc<Client>,
+	block: Block::Hash,
+}
+
+impl<Block, Client> BlockProfiler<Block, Client>
+where
+	Block: BlockT + 'static,
+	Client: HeaderBackend<Block>
+		+ BlockBackend<Block>
+		+ ExecutorProvider<Block>
+		+ Send
+		+ Sync
+		+ 'static,
+{
+	/// Create a new `BlockProfiler`
+	pub fn new(client: Arc<Client>, block: Block::Hash) -> Self {
+		Self { client, block }
+	}
+
+	/// Execute the block and collect the statistics of the host functions it called.
+	pub fn profile_block(&self) -> TraceBlockResult<BlockProfile> {
+		tracing::debug!(target: "state_tracing", "Profiling block: {}", self.block);
+		let block = prepare_block(&*self.client, self.block)?;
+		let parent_id = BlockId::Hash(*block.header().parent_hash());
+
+		let recorder = Arc::new(Recorder::default());
+		let mut extensions = Extensions::new();
+		extensions.register(HostFunctionProfilerExt(recorder.clone()));
+		self.client
+			.executor()
+			.call(
+				&parent_id,
+				"Core_execute_block",
+				&block.encode(),
+				ExecutionStrategy::AlwaysWasm,
+				Some(extensions),
+			)
+			.map_err(|e| Error::Dispatch(format!("Failed to profile block: {}", e)))?;
+
+		let recorded = std::mem::take(&mut *recorder.recorded.lock());
+		if recorded.unprofiled_calls > 0 {
+			return Err(Error::Dispatch(
+				"Host function profiling is disabled, see `--wasm-host-function-profiling`"
+					.to_string(),
+			))
+		}
+
+		let host_time = recorded.host_functions.values().map(|stats| stats.time).sum();
+		let mut host_functions: Vec<_> = recorded
+			.host_functions
+			.into_iter()
+			.map(|(name, stats)| HostFunctionProfile {
+				name,
+				calls: stats.calls,
+				time_ns: as_nanos(stats.time),
+				bytes_read: stats.bytes_read,
+				bytes_written: stats.bytes_written,
+			})
+			.collect();
+		host_functions.sort_by(|a, b| b.time_ns.cmp(&a.time_ns).then_with(|| a.name.cmp(&b.name)));
+
+		Ok(BlockProfile {
+			block_hash: block_id_as_string(BlockId::<Block>::Hash(self.block)),
+			parent_hash: block_id_as_string(parent_id),
+			execution_time_ns: as_nanos(recorded.execution_time),
+			wasm_time_ns: as_nanos(recorded.execution_time.saturating_sub(host_time)),
+			host_functions,
+		})
+	}
+}
+
+fn as_nanos(duration: Duration) -> u64 {
+	duration.as_nanos().try_into().unwrap_or(u64::MAX)
+}
+
+#[derive(Default)]
+struct HostFunctionStats {
+	calls: u64,
+	time: Duration,
+	bytes_read: u64,
+	bytes_written: u64,
+}
+
+#[derive(Default)]
+struct Recorded {
+	execution_time: Duration,
+	unprofiled_calls: usize,
+	host_functions: HashMap<String, HostFunctionStats>,
+}
+
+/// Accumulates the statistics of all the runtime calls made while executing a block.
+#[derive(Default)]
+struct Recorder {
+	recorded: Mutex<Recorded>,
+}
+
+impl HostFunctionProfiler for Recorder {
+	fn record_runtime_call(&self, time: Duration, profiled: bool) {
+		let mut recorded = self.recorded.lock();
+		recorded.execution_time += time;
+		if !profiled {
+			recorded.unprofiled_calls += 1;
+		}
+	}
+
+	fn record_host_function(
+		&self,
+		name: &str,
+		calls: u64,
+		time: Duration,
+		bytes_read: u64,
+		bytes_written: u64,
+	) {
+		let mut recorded = self.recorded.lock();
+		let stats = recorded.host_functions.entry(name.to_string()).or_default();
+		stats.calls += calls;
+		stats.time += time;
+		stats.bytes_read += bytes_read;
+		stats.bytes_written += bytes_written;
+	}
+}
@@ -30,7 +30,7 @@ use std::{marker::PhantomData, pin::Pin, sync::Arc};
 use prometheus_endpoint::Registry as PrometheusRegistry;
 use sc_client_api::{blockchain::HeaderBackend, BlockBackend};
 use sp_api::{ApiExt, ProvideRuntimeApi};
-use sp_core::traits::SpawnEssentialNamed;
+use sp_core::{traits::SpawnEssentialNamed, ExecutionContext};
 use sp_runtime::{
 	generic::BlockId,
 	traits::{self, Block as BlockT, BlockIdTo},
@@ -229,8 +229,13 @@ where
 			sp_tracing::Level::TRACE, "runtime::validate_transaction";
 		{
 			if api_version >= 3 {
-				runtime_api.validate_transaction(at, source, uxt, block_hash)
-					.map_err(|e| Error::RuntimeApi(e.to_string()))
+				runtime_api.validate_transaction_with_context(
+					at,
+					ExecutionContext::TransactionValidation,
+					source,
+					uxt,
+					block_hash,
+				).map_err(|e| Error::RuntimeApi(e.to_string()))
 			} else {
 				let block_number = client.to_number(at)
 					.map_err(|e| Error::RuntimeApi(e.to_string()))?
@@ -397,6 +397,8 @@ pub mod pallet {
 
 		/// Set the new runtime code.
 		///
+		/// The code may be a delta against the current runtime, see [`Pallet::resolve_code`].
+		///
 		/// # <weight>
 		/// - `O(C + S)` where `C` length of `code` and `S` complexity of `can_set_code`
 		/// - 1 call to `can_set_code`: `O(S)` (calls `sp_io::misc::runtime_version` which is
@@ -410,6 +412,7 @@ pub mod pallet {
 		#[pallet::weight((T::BlockWeights::get().max_block, DispatchClass::Operational))]
 		pub fn set_code(origin: OriginFor<T>, code: Vec<u8>) -> DispatchResultWithPostInfo {
 			ensure_root(origin)?;
+			let code = Self::resolve_code(code)?;
 			Self::can_set_code(&code)?;
 			T::OnSetCode::set_code(code)?;
 			Ok(().into())
@@ -417,6 +420,9 @@ pub mod pallet {
 
 		/// Set the new runtime code without doing any checks of the given `code`.
 		///
+		/// A delta against the current runtime is still decoded and checked against its hash, see
+		/// [`Pallet::resolve_code`].
+		///
 		/// # <weight>
 		/// - `O(C)` where `C` length of `code`
 		/// - 1 storage write (codec `O(C)`).
@@ -430,6 +436,7 @@ pub mod pallet {
 			code: Vec<u8>,
 		) -> DispatchResultWithPostInfo {
 			ensure_root(origin)?;
+			let code = Self::resolve_code(code)?;
 			T::OnSetCode::set_code(code)?;
 			Ok(().into())
 		}
@@ -530,6 +537,8 @@ pub mod pallet {
 		NonZeroRefCount,
 		/// The origin filter prevent the call to be dispatched.
 		CallFiltered,
+		/// The new runtime code is a delta that could not be decoded against the current runtime.
+		InvalidCodeDelta,
 	}
 
 	/// Exposed trait-generic origin type.
@@ -1537,6 +1546,23 @@ impl<T: Config> Pallet<T> {
 		Self::deposit_event(Event::KilledAccount { account: who });
 	}
 
+	/// Resolve the `code` given to `set_code` into the code to store.
+	///
+	/// To save on the size of upgrades, `code` may be a delta against the current runtime (see
+	/// `sp_maybe_compressed_blob::compress_delta`), which is decoded by the host and checked
+	/// against the hash it embeds. Any other code is returned as is.
+	///
+	/// Decoding deltas requires the `decompress_runtime_code_delta` host function, so nodes must
+	/// be upgraded before a delta is enacted.
+	pub fn resolve_code(code: Vec<u8>) -> Result<Vec<u8>, sp_runtime::DispatchError> {
+		if !sp_maybe_compressed_blob::is_delta(&code) {
+			return Ok(code)
+		}
+
+		sp_io::misc::decompress_runtime_code_delta(&code)
+			.ok_or_else(|| Error::<T>::InvalidCodeDelta.into())
+	}
+
 	/// Determine whether or not it is possible to update the code.
 	///
 	/// Checks the given code if it is a valid runtime wasm blob by instantianting
@@ -398,6 +398,48 @@ fn set_code_checks_works() {
 	}
 }
 
+#[test]
+fn set_code_accepts_delta_against_current_code() {
+	use sp_maybe_compressed_blob::{compress_delta, decompress, CODE_BLOB_BOMB_LIMIT};
+
+	struct ReadRuntimeVersion(Vec<u8>);
+
+	impl sp_core::traits::ReadRuntimeVersion for ReadRuntimeVersion {
+		fn read_runtime_version(
+			&self,
+			wasm_code: &[u8],
+			_ext: &mut dyn sp_externalities::Externalities,
+		) -> Result<Vec<u8>, String> {
+			assert!(!sp_maybe_compressed_blob::is_delta(wasm_code));
+			Ok(self.0.clone())
+		}
+	}
+
+	let version =
+		RuntimeVersion { spec_name: "test".into(), spec_version: 2, ..Default::default() };
+	let base = vec![1u8; 1024];
+	let code = [&base[..512], &[2u8; 512][..]].concat();
+	let delta = compress_delta(&code, &base, CODE_BLOB_BOMB_LIMIT).unwrap();
+
+	let mut ext = new_test_ext();
+	ext.register_extension(sp_core::traits::ReadRuntimeVersionExt::new(ReadRuntimeVersion(
+		version.encode(),
+	)));
+	ext.execute_with(|| {
+		storage::unhashed::put_raw(well_known_keys::CODE, &code);
+		assert_eq!(
+			System::set_code(RawOrigin::Root.into(), delta.clone()),
+			Err(Error::<Test>::InvalidCodeDelta.into()),
+		);
+
+		storage::unhashed::put_raw(well_known_keys::CODE, &base);
+		assert_ok!(System::set_code(RawOrigin::Root.into(), delta));
+
+		let stored = storage::unhashed::get_raw(well_known_keys::CODE).unwrap();
+		assert_eq!(decompress(&stored, CODE_BLOB_BOMB_LIMIT).unwrap(), &code[..]);
+	});
+}
+
 fn assert_runtime_updated_digest(num: usize) {
 	assert_eq!(
 		System::digest()
@@ -19,6 +19,8 @@
 
 #![cfg_attr(not(feature = "std"), no_std)]
 
+#[cfg(feature = "std")]
+use sp_externalities::ExternalitiesExt;
 use sp_std::vec::Vec;
 
 mod app {
@@ -37,6 +39,48 @@ pub type AuthorityId = app::Public;
 /// An authority discovery authority signature.
 pub type AuthoritySignature = app::Signature;
 
+/// Key in the persistent offchain local storage under which the node collects messages received
+/// from other authorities.
+///
+/// The value is a SCALE encoded `Vec<(AuthorityId, Vec<u8>)>` of senders and payloads, oldest
+/// first. Offchain workers take messages out of the inbox by replacing the value through
+/// compare-and-set.
+pub const OFFCHAIN_INBOX_KEY: &[u8] = b"authority-discovery::validator-messaging::inbox";
+
+/// Queue of messages an offchain worker wants to send to other authorities.
+#[cfg(feature = "std")]
+pub trait MessageQueue: Send + Sync {
+	/// Queue `payload` to be sent to `recipient`.
+	///
+	/// Returns `false` if the message was dropped because the queue is full.
+	fn queue(&self, recipient: AuthorityId, payload: Vec<u8>) -> bool;
+}
+
+#[cfg(feature = "std")]
+sp_externalities::decl_extension! {
+	/// The validator messaging extension to register/retrieve from the externalities.
+	pub struct ValidatorMessagingExt(std::sync::Arc<dyn MessageQueue>);
+}
+
+/// Interface that allows offchain workers to send messages to other authorities.
+#[sp_runtime_interface::runtime_interface]
+pub trait ValidatorMessaging {
+	/// Queue `payload` to be sent to the authority `recipient`.
+	///
+	/// The message is signed with the local authority discovery key and delivered
+	/// asynchronously. Returns `false` if the message could not be queued, e.g. because the node
+	/// does not run validator messaging.
+	fn send(
+		&mut self,
+		recipient: sp_application_crypto::sr25519::Public,
+		payload: Vec<u8>,
+	) -> bool {
+		self.extension::<ValidatorMessagingExt>()
+			.map(|ext| ext.0.queue(recipient.into(), payload))
+			.unwrap_or(false)
+	}
+}
+
 sp_api::decl_runtime_apis! {
 	/// The authority discovery api.
 	///
@@ -239,6 +239,30 @@ pub trait Backend<Block: BlockT>:
 	}
 
 	fn block_indexed_body(&self, id: BlockId<Block>) -> Result<Option<Vec<Vec<u8>>>>;
+
+	/// Returns the number of the oldest finalized block whose data of the given kind is kept, if
+	/// the data of older blocks is being pruned.
+	fn pruned_below(&self, _data: PrunableBlockData) -> Option<NumberFor<Block>> {
+		None
+	}
+}
+
+/// Data of finalized blocks that can be pruned independently of the rest of the block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrunableBlockData {
+	/// Block headers.
+	Header,
+	/// Block bodies.
+	Body,
+}
+
+impl std::fmt::Display for PrunableBlockData {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Header => write!(f, "header"),
+			Self::Body => write!(f, "body"),
+		}
+	}
 }
 
 /// Blockchain info
@@ -97,6 +97,35 @@ fn bench_ed25519(c: &mut Criterion) {
 		});
 	}
 
+	for &batch_size in &[1, 16, 128] {
+		let signed = (0..batch_size)
+			.map(|_| {
+				let msg = (0..32).map(|_| rand::random::<u8>()).collect::<Vec<_>>();
+				let key = sp_core::ed25519::Pair::generate().0;
+				(key.sign(&msg), msg, key.public())
+			})
+			.collect::<Vec<_>>();
+		group.bench_function(
+			BenchmarkId::new("verifying one by one", format!("{}", batch_size)),
+			|b| {
+				b.iter(|| {
+					signed
+						.iter()
+						.all(|(sig, msg, public)| sp_core::ed25519::Pair::verify(sig, msg, public))
+				})
+			},
+		);
+		group.bench_function(BenchmarkId::new("batch verifying", format!("{}", batch_size)), |b| {
+			b.iter(|| {
+				sp_core::ed25519::verify_batch(
+					signed.iter().map(|(_, msg, _)| &msg[..]).collect(),
+					signed.iter().map(|(sig, _, _)| sig).collect(),
+					signed.iter().map(|(_, _, public)| public).collect(),
+				)
+			})
+		});
+	}
+
 	group.finish();
 }
 
@@ -121,6 +150,35 @@ fn bench_sr25519(c: &mut Criterion) {
 		});
 	}
 
+	for &batch_size in &[1, 16, 128] {
+		let signed = (0..batch_size)
+			.map(|_| {
+				let msg = (0..32).map(|_| rand::random::<u8>()).collect::<Vec<_>>();
+				let key = sp_core::sr25519::Pair::generate().0;
+				(key.sign(&msg), msg, key.public())
+			})
+			.collect::<Vec<_>>();
+		group.bench_function(
+			BenchmarkId::new("verifying one by one", format!("{}", batch_size)),
+			|b| {
+				b.iter(|| {
+					signed
+						.iter()
+						.all(|(sig, msg, public)| sp_core::sr25519::Pair::verify(sig, msg, public))
+				})
+			},
+		);
+		group.bench_function(BenchmarkId::new("batch verifying", format!("{}", batch_size)), |b| {
+			b.iter(|| {
+				sp_core::sr25519::verify_batch(
+					signed.iter().map(|(_, msg, _)| &msg[..]).collect(),
+					signed.iter().map(|(sig, _, _)| sig).collect(),
+					signed.iter().map(|(_, _, public)| public).collect(),
+				)
+			})
+		});
+	}
+
 	group.finish();
 }
 
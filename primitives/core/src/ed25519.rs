@@ -556,6 +556,54 @@ impl CryptoType for Pair {
 	type Pair = Pair;
 }
 
+/// Batch verification.
+///
+/// `messages`, `signatures` and `pub_keys` should all have equal length.
+///
+/// Follows the [ZIP 215] validation rules, which give the same result for a signature whether
+/// it is verified alone or in a batch. Public keys and signature `R` points that are not
+/// canonically encoded or of small order are rejected beforehand, as [`Pair::verify`] rejects
+/// most of the signatures with them that the rules accept.
+///
+/// Returns `true` if all signatures are correct, `false` otherwise.
+///
+/// [ZIP 215]: https://zips.z.cash/zip-0215
+#[cfg(feature = "std")]
+pub fn verify_batch(
+	messages: Vec<&[u8]>,
+	signatures: Vec<&Signature>,
+	pub_keys: Vec<&Public>,
+) -> bool {
+	if messages.len() != signatures.len() || signatures.len() != pub_keys.len() {
+		return false
+	}
+
+	let mut verifier = ed25519_zebra::batch::Verifier::new();
+	for ((message, signature), pub_key) in messages.into_iter().zip(signatures).zip(pub_keys) {
+		if !is_canonical_and_not_small_order(&pub_key.0) ||
+			!is_canonical_and_not_small_order(&signature.0[..32])
+		{
+			return false
+		}
+		verifier.queue((
+			ed25519_zebra::VerificationKeyBytes::from(pub_key.0),
+			ed25519_zebra::Signature::from(signature.0),
+			message,
+		));
+	}
+
+	verifier.verify(rand_core::OsRng).is_ok()
+}
+
+/// Whether `point` is the canonical encoding of a curve point that is not of small order.
+#[cfg(feature = "std")]
+fn is_canonical_and_not_small_order(point: &[u8]) -> bool {
+	let compressed = curve25519_dalek::edwards::CompressedEdwardsY::from_slice(point);
+	compressed
+		.decompress()
+		.map_or(false, |point| !point.is_small_order() && point.compress() == compressed)
+}
+
 #[cfg(test)]
 mod test {
 	use super::*;
@@ -709,4 +757,45 @@ mod test {
 		// Poorly-sized
 		assert!(deserialize_signature("\"abc123\"").is_err());
 	}
+
+	#[test]
+	fn verify_batch_works() {
+		let pairs: Vec<_> = (0..4u8).map(|i| Pair::from_seed(&[i; 32])).collect();
+		let messages: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i; 10]).collect();
+		let mut signatures: Vec<_> =
+			pairs.iter().zip(&messages).map(|(pair, message)| pair.sign(message)).collect();
+		let publics: Vec<_> = pairs.iter().map(|pair| pair.public()).collect();
+
+		let verify = |signatures: &[Signature]| {
+			verify_batch(
+				messages.iter().map(|message| &message[..]).collect(),
+				signatures.iter().collect(),
+				publics.iter().collect(),
+			)
+		};
+		assert!(verify(&signatures));
+
+		signatures.swap(0, 1);
+		assert!(!verify(&signatures));
+	}
+
+	#[test]
+	fn verify_batch_rejects_small_order_and_non_canonical_points() {
+		// The point with `y = 3`, encoded canonically and as `y + p`.
+		let mut canonical = [0u8; 32];
+		canonical[0] = 3;
+		let mut non_canonical = [0xffu8; 32];
+		non_canonical[0] = 0xf0;
+		non_canonical[31] = 0x7f;
+		assert!(is_canonical_and_not_small_order(&canonical));
+		assert!(!is_canonical_and_not_small_order(&non_canonical));
+
+		// The point with `y = 0` is of order 4.
+		assert!(!is_canonical_and_not_small_order(&[0u8; 32]));
+		assert!(!verify_batch(
+			vec![&[][..]],
+			vec![&Signature::from_raw([0; 64])],
+			vec![&Public::from_raw([0; 32])],
+		));
+	}
 }
@@ -101,6 +101,12 @@ pub enum ExecutionContext {
 	Syncing,
 	/// Context used for block construction.
 	BlockConstruction,
+	/// Context used for validating transactions before they enter the transaction pool.
+	///
+	/// The validation used to run as `OffchainCall(None)` and keeps its capabilities. Its own
+	/// context lets the node tell the calls of the pool from the RPC calls, e.g. to give them a
+	/// different execution budget.
+	TransactionValidation,
 	/// Context used for offchain calls.
 	///
 	/// This allows passing offchain extension and customizing available capabilities.
@@ -116,7 +122,7 @@ impl ExecutionContext {
 			Importing | Syncing | BlockConstruction => offchain::Capabilities::empty(),
 			// Enable keystore, transaction pool and Offchain DB reads by default for offchain
 			// calls.
-			OffchainCall(None) =>
+			TransactionValidation | OffchainCall(None) =>
 				offchain::Capabilities::KEYSTORE |
 					offchain::Capabilities::OFFCHAIN_DB_READ |
 					offchain::Capabilities::TRANSACTION_POOL,
@@ -21,6 +21,8 @@ use std::{
 	borrow::Cow,
 	fmt::{Debug, Display},
 	panic::UnwindSafe,
+	sync::Arc,
+	time::Duration,
 };
 
 pub use sp_externalities::{Externalities, ExternalitiesExt};
@@ -171,8 +173,42 @@ impl TaskExecutorExt {
 	}
 }
 
+sp_externalities::decl_extension! {
+	/// The fuel a runtime call may consume, fuel being roughly a count of executed wasm
+	/// instructions.
+	///
+	/// An executor with fuel metering enabled aborts the call once it runs out of fuel. Without
+	/// this extension a call is not limited.
+	pub struct FuelBudgetExt(u64);
+}
+
+/// Receives the host function calls of runtime calls, for profiling.
+pub trait HostFunctionProfiler: Send + Sync {
+	/// Record a runtime call that took `time`, its host function calls included.
+	///
+	/// `profiled` tells whether the host function calls of the runtime call were recorded. This
+	/// requires an executor with host function profiling enabled.
+	fn record_runtime_call(&self, time: Duration, profiled: bool);
+
+	/// Record `calls` calls to the host function `name` that took `time` and read and wrote
+	/// `bytes_read` and `bytes_written` bytes of the wasm memory.
+	fn record_host_function(
+		&self,
+		name: &str,
+		calls: u64,
+		time: Duration,
+		bytes_read: u64,
+		bytes_written: u64,
+	);
+}
+
+sp_externalities::decl_extension! {
+	/// Records the host function calls of the runtime calls executed in wasm.
+	pub struct HostFunctionProfilerExt(Arc<dyn HostFunctionProfiler>);
+}
+
 /// Runtime spawn extension.
-pub trait RuntimeSpawn: Send {
+pub trait RuntimeSpawn: Send + Sync {
 	/// Create new runtime instance and use dynamic dispatch to invoke with specified payload.
 	///
 	/// Returns handle of the spawned task.
@@ -181,13 +217,16 @@ pub trait RuntimeSpawn: Send {
 	fn spawn_call(&self, dispatcher_ref: u32, func: u32, payload: Vec<u8>) -> u64;
 
 	/// Join the result of previously created runtime instance invocation.
-	fn join(&self, handle: u64) -> Vec<u8>;
+	///
+	/// `ext` are the externalities of the caller, used to answer the storage reads of the task
+	/// while it is joined.
+	fn join(&self, handle: u64, ext: &dyn Externalities) -> Vec<u8>;
 }
 
 #[cfg(feature = "std")]
 sp_externalities::decl_extension! {
 	/// Extension that supports spawning extra runtime instances in externalities.
-	pub struct RuntimeSpawnExt(Box<dyn RuntimeSpawn>);
+	pub struct RuntimeSpawnExt(Arc<dyn RuntimeSpawn>);
 }
 
 /// Something that can spawn tasks (blocking and non-blocking) with an assigned name
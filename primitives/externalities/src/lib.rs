@@ -76,6 +76,40 @@ impl MultiRemovalResults {
 	}
 }
 
+/// A read of the storage a runtime call is executed on, without the changes made by the call.
+///
+/// See [`Externalities::read_backend`].
+#[derive(Debug, Clone)]
+pub enum BackendRead {
+	/// Read runtime storage.
+	Storage(Vec<u8>),
+	/// Get storage value hash.
+	StorageHash(Vec<u8>),
+	/// Read child runtime storage.
+	ChildStorage(ChildInfo, Vec<u8>),
+	/// Get child storage value hash.
+	ChildStorageHash(ChildInfo, Vec<u8>),
+	/// Returns the key immediately following the given key, if it exists.
+	NextStorageKey(Vec<u8>),
+	/// Returns the key immediately following the given key, if it exists, in child storage.
+	NextChildStorageKey(ChildInfo, Vec<u8>),
+}
+
+impl BackendRead {
+	/// Answers the read from the storage of `ext`, the changes made through `ext` included.
+	pub fn read_from<E: Externalities + ?Sized>(&self, ext: &E) -> Option<Vec<u8>> {
+		match self {
+			Self::Storage(key) => ext.storage(key),
+			Self::StorageHash(key) => ext.storage_hash(key),
+			Self::ChildStorage(child_info, key) => ext.child_storage(child_info, key),
+			Self::ChildStorageHash(child_info, key) => ext.child_storage_hash(child_info, key),
+			Self::NextStorageKey(key) => ext.next_storage_key(key),
+			Self::NextChildStorageKey(child_info, key) =>
+				ext.next_child_storage_key(child_info, key),
+		}
+	}
+}
+
 /// The Substrate externalities.
 ///
 /// Provides access to the storage and to other registered extensions.
@@ -140,6 +174,13 @@ pub trait Externalities: ExtensionStore {
 	/// Returns the key immediately following the given key, if it exists, in child storage.
 	fn next_child_storage_key(&self, child_info: &ChildInfo, key: &[u8]) -> Option<Vec<u8>>;
 
+	/// Answers `read` from the storage the externalities were created on, without the changes
+	/// made through them.
+	///
+	/// Used to give the tasks spawned by a runtime call a view of the storage which does not
+	/// depend on when they run. Hash values are returned SCALE encoded.
+	fn read_backend(&self, read: &BackendRead) -> Option<Vec<u8>>;
+
 	/// Clear an entire child storage.
 	///
 	/// Deletes all keys from the overlay and up to `maybe_limit` keys from the backend. No
@@ -24,6 +24,9 @@ use std::sync::{
 	Arc,
 };
 
+/// Number of signatures verified together by a single verification task.
+const BATCH_SIZE: usize = 128;
+
 #[derive(Debug, Clone)]
 struct Sr25519BatchItem {
 	signature: sr25519::Signature,
@@ -31,15 +34,29 @@ struct Sr25519BatchItem {
 	message: Vec<u8>,
 }
 
+#[derive(Debug, Clone)]
+struct Ed25519BatchItem {
+	signature: ed25519::Signature,
+	pub_key: ed25519::Public,
+	message: Vec<u8>,
+}
+
 /// Batch verifier.
 ///
 /// Used to parallel-verify signatures for runtime host. Provide task executor and
 /// just push (`push_ed25519`, `push_sr25519`) as many signature as you need. At the end,
 /// call `verify_and_clear to get a result. After that, batch verifier is ready for the
 /// next batching job.
+///
+/// The sr25519 signatures, and the ed25519 signatures pushed with `push_ed25519_batched`, are
+/// collected into batches of [`BATCH_SIZE`] signatures, which are verified in parallel with the
+/// batch verification algorithms.
 pub struct BatchVerifier {
 	scheduler: Box<dyn SpawnNamed>,
 	sr25519_items: Vec<Sr25519BatchItem>,
+	ed25519_items: Vec<Ed25519BatchItem>,
+	/// Whether `push_ed25519_batched` uses the batch verification algorithm.
+	ed25519_batching: bool,
 	invalid: Arc<AtomicBool>,
 	pending_tasks: Vec<oneshot::Receiver<()>>,
 }
@@ -49,11 +66,20 @@ impl BatchVerifier {
 		BatchVerifier {
 			scheduler,
 			sr25519_items: Default::default(),
+			ed25519_items: Default::default(),
+			ed25519_batching: true,
 			invalid: Arc::new(false.into()),
 			pending_tasks: vec![],
 		}
 	}
 
+	/// Verify the signatures pushed with `push_ed25519_batched` one by one, like
+	/// `push_ed25519`, unless `ed25519_batching` is set.
+	pub fn with_ed25519_batching(mut self, ed25519_batching: bool) -> Self {
+		self.ed25519_batching = ed25519_batching;
+		self
+	}
+
 	/// Spawn a verification task.
 	///
 	/// Returns `false` if there was already an invalid verification or if
@@ -107,6 +133,38 @@ impl BatchVerifier {
 		)
 	}
 
+	/// Push ed25519 signature to verify with the batch verification equation.
+	///
+	/// See [`ed25519::verify_batch`] for how it differs from the single signature verification
+	/// used by [`Self::push_ed25519`].
+	///
+	/// Returns false if some of the pushed signatures before already failed the check.
+	/// (in this case it won't verify anything else)
+	pub fn push_ed25519_batched(
+		&mut self,
+		signature: ed25519::Signature,
+		pub_key: ed25519::Public,
+		message: Vec<u8>,
+	) -> bool {
+		if !self.ed25519_batching {
+			return self.push_ed25519(signature, pub_key, message)
+		}
+		if self.invalid.load(AtomicOrdering::Relaxed) {
+			return false
+		}
+		self.ed25519_items.push(Ed25519BatchItem { signature, pub_key, message });
+
+		if self.ed25519_items.len() >= BATCH_SIZE {
+			let items = std::mem::take(&mut self.ed25519_items);
+			self.spawn_verification_task(
+				move || Self::verify_ed25519_batch(items),
+				"substrate_ed25519_verify",
+			)
+		} else {
+			true
+		}
+	}
+
 	/// Push sr25519 signature to verify.
 	///
 	/// Returns false if some of the pushed signatures before already failed the check.
@@ -122,7 +180,7 @@ impl BatchVerifier {
 		}
 		self.sr25519_items.push(Sr25519BatchItem { signature, pub_key, message });
 
-		if self.sr25519_items.len() >= 128 {
+		if self.sr25519_items.len() >= BATCH_SIZE {
 			let items = std::mem::take(&mut self.sr25519_items);
 			self.spawn_verification_task(
 				move || Self::verify_sr25519_batch(items),
@@ -157,20 +215,39 @@ impl BatchVerifier {
 		sr25519::verify_batch(messages, signatures, pub_keys)
 	}
 
+	fn verify_ed25519_batch(items: Vec<Ed25519BatchItem>) -> bool {
+		let messages = items.iter().map(|item| &item.message[..]).collect();
+		let signatures = items.iter().map(|item| &item.signature).collect();
+		let pub_keys = items.iter().map(|item| &item.pub_key).collect();
+
+		ed25519::verify_batch(messages, signatures, pub_keys)
+	}
+
 	/// Verify all previously pushed signatures since last call and return
 	/// aggregated result.
 	#[must_use]
 	pub fn verify_and_clear(&mut self) -> bool {
-		let pending = std::mem::take(&mut self.pending_tasks);
 		let started = std::time::Instant::now();
 
 		log::trace!(
 			target: "runtime",
-			"Batch-verification: {} pending tasks, {} sr25519 signatures",
-			pending.len(),
+			"Batch-verification: {} pending tasks, {} sr25519 signatures, {} ed25519 signatures",
+			self.pending_tasks.len(),
 			self.sr25519_items.len(),
+			self.ed25519_items.len(),
 		);
 
+		// Verify the last ed25519 batch in parallel to the last sr25519 batch.
+		let ed25519_items = std::mem::take(&mut self.ed25519_items);
+		if !ed25519_items.is_empty() {
+			self.spawn_verification_task(
+				move || Self::verify_ed25519_batch(ed25519_items),
+				"substrate_ed25519_verify",
+			);
+		}
+
+		let pending = std::mem::take(&mut self.pending_tasks);
+
 		if !Self::verify_sr25519_batch(std::mem::take(&mut self.sr25519_items)) {
 			return false
 		}
@@ -696,6 +696,34 @@ pub trait Misc {
 			},
 		}
 	}
+
+	/// Decode the runtime code `delta`, encoded against the runtime at `:code`.
+	///
+	/// Returns `None` if `delta` is not a delta against the current runtime, decodes to more than
+	/// `sp_maybe_compressed_blob::CODE_BLOB_BOMB_LIMIT` bytes or does not match its embedded
+	/// hash. Otherwise returns the decoded code, compressed on its own so that it can be stored
+	/// at `:code`.
+	fn decompress_runtime_code_delta(&mut self, delta: &[u8]) -> Option<Vec<u8>> {
+		use sp_maybe_compressed_blob::{
+			compress, decompress, decompress_delta, CODE_BLOB_BOMB_LIMIT,
+		};
+
+		let base = self.storage(sp_core::storage::well_known_keys::CODE)?;
+		let code = decompress(&base, CODE_BLOB_BOMB_LIMIT)
+			.and_then(|base| decompress_delta(delta, &base, CODE_BLOB_BOMB_LIMIT));
+
+		match code {
+			Ok(code) => compress(&code, CODE_BLOB_BOMB_LIMIT).or(Some(code)),
+			Err(err) => {
+				log::debug!(
+					target: LOG_TARGET,
+					"cannot decompress the runtime code delta: {}",
+					err,
+				);
+				None
+			},
+		}
+	}
 }
 
 /// Interfaces for working with crypto related types from within the runtime.
@@ -769,6 +797,30 @@ pub trait Crypto {
 			.unwrap_or_else(|| ed25519_verify(sig, msg, pub_key))
 	}
 
+	/// Register a `ed25519` signature for batch verification.
+	///
+	/// Batch verification must be enabled by calling [`start_batch_verify`].
+	/// If batch verification is not enabled, the signature will be verified immediately.
+	/// To get the result of the batch verification, [`finish_batch_verify`]
+	/// needs to be called.
+	///
+	/// Unlike the first version, the signatures of a batch verification are verified with the
+	/// ed25519 batch verification algorithm, see [`ed25519::verify_batch`]. If batch
+	/// verification is not enabled, the signature is verified like [`ed25519_verify`] does.
+	///
+	/// Returns `true` when the verification is either successful or batched.
+	#[version(2)]
+	fn ed25519_batch_verify(
+		&mut self,
+		sig: &ed25519::Signature,
+		msg: &[u8],
+		pub_key: &ed25519::Public,
+	) -> bool {
+		self.extension::<VerificationExt>()
+			.map(|extension| extension.push_ed25519_batched(sig.clone(), *pub_key, msg.to_vec()))
+			.unwrap_or_else(|| ed25519_verify(sig, msg, pub_key))
+	}
+
 	/// Verify `sr25519` signature.
 	///
 	/// Returns `true` when the verification was successful.
@@ -803,8 +855,13 @@ pub trait Crypto {
 			.expect("No task executor associated with the current context!")
 			.clone();
 
-		self.register_extension(VerificationExt(BatchVerifier::new(scheduler)))
-			.expect("Failed to register required extension: `VerificationExt`");
+		let ed25519_batching =
+			self.extension::<Ed25519BatchingExt>().map_or(true, |batching| batching.0);
+
+		self.register_extension(VerificationExt(
+			BatchVerifier::new(scheduler).with_ed25519_batching(ed25519_batching),
+		))
+		.expect("Failed to register required extension: `VerificationExt`");
 	}
 
 	/// Finish batch-verification of signatures.
@@ -1153,6 +1210,17 @@ sp_externalities::decl_extension! {
 	pub struct VerificationExt(BatchVerifier);
 }
 
+#[cfg(feature = "std")]
+sp_externalities::decl_extension! {
+	/// Whether the batch verification uses the ed25519 batch verification algorithm for the
+	/// signatures registered with the second version of `ed25519_batch_verify`, which it does
+	/// without this extension.
+	///
+	/// Disabling it verifies them one by one like the first version does, which allows comparing
+	/// both versions on the same runtime.
+	pub struct Ed25519BatchingExt(bool);
+}
+
 /// Interface that provides functions to access the offchain functionality.
 ///
 /// These functions are being made available to the runtime and are called by the runtime.
@@ -1682,8 +1750,10 @@ pub trait RuntimeTasks {
 		sp_externalities::with_externalities(|mut ext| {
 			let runtime_spawn = ext
 				.extension::<RuntimeSpawnExt>()
-				.expect("Cannot join without dynamic runtime dispatcher (RuntimeSpawnExt)");
-			runtime_spawn.join(handle)
+				.expect("Cannot join without dynamic runtime dispatcher (RuntimeSpawnExt)")
+				.clone();
+			// The task reads the storage through `ext` while it is joined.
+			runtime_spawn.join(handle, &*ext)
 		})
 		.expect("`RuntimeTasks::join`: called outside of externalities context")
 	}
@@ -1871,6 +1941,36 @@ mod tests {
 		});
 	}
 
+	#[test]
+	fn decompress_runtime_code_delta_works() {
+		use sp_maybe_compressed_blob::{
+			compress, compress_delta, decompress, CODE_BLOB_BOMB_LIMIT,
+		};
+
+		let base = (0..1000u32).flat_map(|i| i.to_le_bytes()).collect::<Vec<_>>();
+		let mut code = base.clone();
+		code[100..120].copy_from_slice(&[7; 20]);
+		let delta = compress_delta(&code, &base, CODE_BLOB_BOMB_LIMIT).unwrap();
+
+		let mut t = BasicExternalities::new(Storage {
+			top: map![
+				sp_core::storage::well_known_keys::CODE.to_vec() =>
+					compress(&base, CODE_BLOB_BOMB_LIMIT).unwrap()
+			],
+			children_default: map![],
+		});
+
+		t.execute_with(|| {
+			let decompressed = misc::decompress_runtime_code_delta(&delta).unwrap();
+			assert_eq!(decompress(&decompressed, CODE_BLOB_BOMB_LIMIT).unwrap(), &code[..]);
+
+			// Not a delta against the current code.
+			let delta = compress_delta(&code, &code, CODE_BLOB_BOMB_LIMIT).unwrap();
+			assert_eq!(misc::decompress_runtime_code_delta(&delta), None);
+			assert_eq!(misc::decompress_runtime_code_delta(&code), None);
+		});
+	}
+
 	#[test]
 	fn batch_verify_start_finish_works() {
 		let mut ext = BasicExternalities::default();
@@ -1924,6 +2024,11 @@ mod tests {
 		ed25519::Signature::from_raw([0u8; 64])
 	}
 
+	fn invalid_ed_sig() -> (ed25519::Signature, ed25519::Public) {
+		let pair = ed25519::Pair::from_seed(&[1; 32]);
+		(pair.sign(b"Another message"), pair.public())
+	}
+
 	fn zero_sr_pub() -> sr25519::Public {
 		[0u8; 32].unchecked_into()
 	}
@@ -1932,6 +2037,55 @@ mod tests {
 		sr25519::Signature::from_raw([0u8; 64])
 	}
 
+	#[test]
+	fn ed25519_batching_can_be_disabled() {
+		let mut ext = BasicExternalities::default();
+		ext.register_extension(TaskExecutorExt::new(TaskExecutor::new()));
+		ext.register_extension(Ed25519BatchingExt(false));
+
+		ext.execute_with(|| {
+			let pair = ed25519::Pair::generate_with_phrase(None).0;
+			crypto::start_batch_verify();
+			for it in 0..10 {
+				let msg = format!("Ed25519 {}!", it);
+				let signature = pair.sign(msg.as_bytes());
+				crypto::ed25519_batch_verify(&signature, msg.as_bytes(), &pair.public());
+			}
+			assert!(crypto::finish_batch_verify());
+
+			crypto::start_batch_verify();
+			crypto::ed25519_batch_verify(&zero_ed_sig(), &Vec::new(), &zero_ed_pub());
+			assert!(!crypto::finish_batch_verify());
+		});
+	}
+
+	#[test]
+	fn long_ed25519_batching() {
+		let mut ext = BasicExternalities::default();
+		ext.register_extension(TaskExecutorExt::new(TaskExecutor::new()));
+		ext.execute_with(|| {
+			let pair = ed25519::Pair::generate_with_phrase(None).0;
+			crypto::start_batch_verify();
+			for it in 0..300 {
+				let msg = format!("Ed25519 {}!", it);
+				let signature = pair.sign(msg.as_bytes());
+				assert!(crypto::ed25519_batch_verify(&signature, msg.as_bytes(), &pair.public()));
+			}
+
+			let (signature, public) = invalid_ed_sig();
+			crypto::ed25519_batch_verify(&signature, &Vec::new(), &public);
+			assert!(!crypto::finish_batch_verify());
+
+			crypto::start_batch_verify();
+			for it in 0..300 {
+				let msg = format!("Ed25519 {}!", it);
+				let signature = pair.sign(msg.as_bytes());
+				crypto::ed25519_batch_verify(&signature, msg.as_bytes(), &pair.public());
+			}
+			assert!(crypto::finish_batch_verify());
+		});
+	}
+
 	#[test]
 	fn batching_works() {
 		let mut ext = BasicExternalities::default();
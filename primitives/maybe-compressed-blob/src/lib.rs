@@ -17,7 +17,18 @@
 
 //! Handling of blobs that may be compressed, based on an 8-byte magic identifier
 //! at the head.
+//!
+//! Besides plain Zstd compression, a blob may be a *delta*: it is compressed with another blob,
+//! its *base*, as Zstd raw content dictionary, which makes it a binary diff against the base.
+//! Runtime upgrades use this to ship only what changed since the current runtime. A delta embeds
+//! the hashes of its base and of its decompressed content, and decompression checks both.
+//!
+//! Without the `std` feature only [`is_delta`] is available, so that runtimes can tell deltas
+//! apart before handing them to the host.
 
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "std")]
 use std::{
 	borrow::Cow,
 	io::{Read, Write},
@@ -27,8 +38,21 @@ use std::{
 // Zstd compression.
 //
 // This differs from the WASM magic bytes, so real WASM blobs will not have this prefix.
+#[cfg(feature = "std")]
 const ZSTD_PREFIX: [u8; 8] = [82, 188, 83, 118, 70, 219, 142, 5];
 
+// Like `ZSTD_PREFIX`, but indicates a blob that was compressed with a base blob as dictionary.
+//
+// The prefix is followed by the blake2-256 hashes of the base and of the decompressed blob.
+const ZSTD_DELTA_PREFIX: [u8; 8] = [82, 188, 83, 118, 70, 219, 142, 6];
+
+#[cfg(feature = "std")]
+const DELTA_HEADER_LEN: usize = ZSTD_DELTA_PREFIX.len() + 64;
+
+/// Largest window of a delta, in bits. Decoders reject larger windows by default.
+#[cfg(feature = "std")]
+const DELTA_MAX_WINDOW_LOG: u32 = 27;
+
 /// A recommendation for the bomb limit for code blobs.
 ///
 /// This may be adjusted upwards in the future, but is set much higher than the
@@ -37,6 +61,7 @@ const ZSTD_PREFIX: [u8; 8] = [82, 188, 83, 118, 70, 219, 142, 5];
 pub const CODE_BLOB_BOMB_LIMIT: usize = 50 * 1024 * 1024;
 
 /// A possible bomb was encountered.
+#[cfg(feature = "std")]
 #[derive(Debug, Clone, PartialEq, thiserror::Error)]
 pub enum Error {
 	/// Decoded size was too large, and the code payload may be a bomb.
@@ -45,8 +70,18 @@ pub enum Error {
 	/// The compressed value had an invalid format.
 	#[error("Blob had invalid format")]
 	Invalid,
+	/// The blob is a delta, which can only be decompressed with its base.
+	#[error("Blob is a delta and its base is required")]
+	MissingBase,
+	/// The blob is a delta against another base than the given one.
+	#[error("Blob is a delta against another base")]
+	BaseMismatch,
+	/// The decompressed blob does not match the hash embedded in the delta.
+	#[error("Decompressed blob does not match its hash")]
+	HashMismatch,
 }
 
+#[cfg(feature = "std")]
 fn read_from_decoder(
 	decoder: impl Read,
 	blob_len: usize,
@@ -64,25 +99,75 @@ fn read_from_decoder(
 	}
 }
 
+#[cfg(feature = "std")]
 fn decompress_zstd(blob: &[u8], bomb_limit: usize) -> Result<Vec<u8>, Error> {
 	let decoder = zstd::Decoder::new(blob).map_err(|_| Error::Invalid)?;
 
 	read_from_decoder(decoder, blob.len(), bomb_limit)
 }
 
+/// Whether the blob is a delta, which needs its base to be decompressed.
+pub fn is_delta(blob: &[u8]) -> bool {
+	blob.starts_with(&ZSTD_DELTA_PREFIX)
+}
+
 /// Decode a blob, if it indicates that it is compressed. Provide a `bomb_limit`, which
 /// is the limit of bytes which should be decompressed from the blob.
+///
+/// Deltas are refused with [`Error::MissingBase`], see [`decompress_delta`].
+#[cfg(feature = "std")]
 pub fn decompress(blob: &[u8], bomb_limit: usize) -> Result<Cow<[u8]>, Error> {
 	if blob.starts_with(&ZSTD_PREFIX) {
 		decompress_zstd(&blob[ZSTD_PREFIX.len()..], bomb_limit).map(Into::into)
+	} else if is_delta(blob) {
+		Err(Error::MissingBase)
 	} else {
 		Ok(blob.into())
 	}
 }
 
+/// Returns the hash of the base the delta `blob` was encoded against, or `None` if `blob` is
+/// not a delta.
+#[cfg(feature = "std")]
+pub fn delta_base_hash(blob: &[u8]) -> Option<[u8; 32]> {
+	if !is_delta(blob) || blob.len() < DELTA_HEADER_LEN {
+		return None
+	}
+	let mut hash = [0; 32];
+	hash.copy_from_slice(&blob[ZSTD_DELTA_PREFIX.len()..ZSTD_DELTA_PREFIX.len() + 32]);
+	Some(hash)
+}
+
+/// Decode the delta `blob` against its (decompressed) `base`, with the given `bomb_limit`.
+///
+/// Fails if `base` is not the one the delta was encoded against, or if the result does not match
+/// the hash embedded in the delta.
+#[cfg(feature = "std")]
+pub fn decompress_delta(blob: &[u8], base: &[u8], bomb_limit: usize) -> Result<Vec<u8>, Error> {
+	if !is_delta(blob) || blob.len() < DELTA_HEADER_LEN {
+		return Err(Error::Invalid)
+	}
+	let (base_hash, rest) = blob[ZSTD_DELTA_PREFIX.len()..].split_at(32);
+	let (hash, frame) = rest.split_at(32);
+	if base_hash != sp_core_hashing::blake2_256(base) {
+		return Err(Error::BaseMismatch)
+	}
+
+	let mut decoder =
+		zstd::stream::read::Decoder::with_dictionary(frame, base).map_err(|_| Error::Invalid)?;
+	decoder.window_log_max(DELTA_MAX_WINDOW_LOG).map_err(|_| Error::Invalid)?;
+	let decompressed = read_from_decoder(decoder, frame.len(), bomb_limit)?;
+
+	if hash != sp_core_hashing::blake2_256(&decompressed) {
+		return Err(Error::HashMismatch)
+	}
+	Ok(decompressed)
+}
+
 /// Encode a blob as compressed. If the blob's size is over the bomb limit,
 /// this will not compress the blob, as the decoder will not be able to be
 /// able to differentiate it from a compression bomb.
+#[cfg(feature = "std")]
 pub fn compress(blob: &[u8], bomb_limit: usize) -> Option<Vec<u8>> {
 	if blob.len() > bomb_limit {
 		return None
@@ -98,6 +183,37 @@ pub fn compress(blob: &[u8], bomb_limit: usize) -> Option<Vec<u8>> {
 	Some(buf)
 }
 
+/// Encode a blob as a delta against its (decompressed) `base`. Like [`compress`], this returns
+/// `None` if the blob's size is over the bomb limit.
+///
+/// The window covers both the base and the blob, so that any part of the base can be referenced.
+#[cfg(feature = "std")]
+pub fn compress_delta(blob: &[u8], base: &[u8], bomb_limit: usize) -> Option<Vec<u8>> {
+	if blob.len() > bomb_limit {
+		return None
+	}
+
+	let window = (base.len() + blob.len()).max(1);
+	let window_log = (usize::BITS - (window - 1).leading_zeros()).clamp(10, DELTA_MAX_WINDOW_LOG);
+	if window > 1 << window_log {
+		return None
+	}
+
+	let mut buf = ZSTD_DELTA_PREFIX.to_vec();
+	buf.extend_from_slice(&sp_core_hashing::blake2_256(base));
+	buf.extend_from_slice(&sp_core_hashing::blake2_256(blob));
+
+	{
+		let mut encoder = zstd::stream::write::Encoder::with_dictionary(&mut buf, 3, base).ok()?;
+		encoder.window_log(window_log).ok()?;
+		encoder.long_distance_matching(true).ok()?;
+		let mut v = encoder.auto_finish();
+		v.write_all(blob).ok()?;
+	}
+
+	Some(buf)
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -142,4 +258,35 @@ mod tests {
 
 		assert_eq!(decompress(&buf[..], BOMB_LIMIT).err(), Some(Error::PossibleBomb));
 	}
+
+	#[test]
+	fn compress_and_decompress_delta() {
+		let base = (0..1000u32).flat_map(|i| i.to_le_bytes()).collect::<Vec<_>>();
+		let mut v = base.clone();
+		v[100..120].copy_from_slice(&[7; 20]);
+
+		let delta = compress_delta(&v, &base, v.len()).unwrap();
+
+		assert!(is_delta(&delta));
+		assert!(delta.len() < compress(&v, v.len()).unwrap().len());
+		assert_eq!(delta_base_hash(&delta), Some(sp_core_hashing::blake2_256(&base)));
+		assert_eq!(decompress_delta(&delta, &base, v.len()).unwrap(), v);
+		assert_eq!(decompress(&delta, v.len()).err(), Some(Error::MissingBase));
+	}
+
+	#[test]
+	fn delta_checks_base_and_hash() {
+		let base = vec![1; BOMB_LIMIT];
+		let v = vec![2; BOMB_LIMIT];
+		let mut delta = compress_delta(&v, &base, BOMB_LIMIT).unwrap();
+
+		assert_eq!(decompress_delta(&delta, &v, BOMB_LIMIT).err(), Some(Error::BaseMismatch));
+		assert_eq!(
+			decompress_delta(&delta, &base, BOMB_LIMIT - 1).err(),
+			Some(Error::PossibleBomb),
+		);
+
+		delta[ZSTD_DELTA_PREFIX.len() + 32] ^= 1;
+		assert_eq!(decompress_delta(&delta, &base, BOMB_LIMIT).err(), Some(Error::HashMismatch));
+	}
 }
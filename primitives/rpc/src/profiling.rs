@@ -0,0 +1,139 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Types for working with host function profiles
+
+use serde::{Deserialize, Serialize};
+
+/// Host function profile of the execution of a block.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockProfile {
+	/// Hash of the profiled block
+	pub block_hash: String,
+	/// Parent hash
+	pub parent_hash: String,
+	/// Total time of the block execution, in nanoseconds
+	pub execution_time_ns: u64,
+	/// Time spent executing wasm code, excluding host functions, in nanoseconds
+	pub wasm_time_ns: u64,
+	/// Called host functions, the most time consuming first
+	pub host_functions: Vec<HostFunctionProfile>,
+}
+
+impl BlockProfile {
+	/// Render the profile in the folded stacks format, as consumed by flamegraph tools
+	/// (e.g. `inferno-flamegraph` or `flamegraph.pl`).
+	///
+	/// Every line is a stack of frames separated by `;` followed by the time spent in it, in
+	/// nanoseconds.
+	pub fn folded_stacks(&self) -> String {
+		let mut folded = format!("execute_block;wasm {}\n", self.wasm_time_ns);
+		for host_function in &self.host_functions {
+			folded.push_str(&format!(
+				"execute_block;host;{} {}\n",
+				host_function.name, host_function.time_ns
+			));
+		}
+		folded
+	}
+}
+
+/// Statistics of the calls to a single host function.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct HostFunctionProfile {
+	/// Name of the host function
+	pub name: String,
+	/// Number of calls
+	pub calls: u64,
+	/// Time spent in the host function, in nanoseconds
+	pub time_ns: u64,
+	/// Number of bytes read from the wasm memory
+	pub bytes_read: u64,
+	/// Number of bytes written to the wasm memory
+	pub bytes_written: u64,
+}
+
+/// Response for the `state_profileBlock` RPC.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileBlockResponse {
+	/// The profile of the block execution
+	pub profile: BlockProfile,
+	/// The profile in the folded stacks format, see [`BlockProfile::folded_stacks`]
+	pub folded_stacks: String,
+}
+
+impl From<BlockProfile> for ProfileBlockResponse {
+	fn from(profile: BlockProfile) -> Self {
+		let folded_stacks = profile.folded_stacks();
+		Self { profile, folded_stacks }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_fold_stacks() {
+		let profile = BlockProfile {
+			block_hash: "0x01".into(),
+			parent_hash: "0x00".into(),
+			execution_time_ns: 100,
+			wasm_time_ns: 60,
+			host_functions: vec![
+				HostFunctionProfile {
+					name: "ext_storage_get_version_1".into(),
+					calls: 3,
+					time_ns: 30,
+					bytes_read: 96,
+					bytes_written: 12,
+				},
+				HostFunctionProfile {
+					name: "ext_hashing_blake2_256_version_1".into(),
+					calls: 1,
+					time_ns: 10,
+					bytes_read: 32,
+					bytes_written: 32,
+				},
+			],
+		};
+
+		assert_eq!(
+			profile.folded_stacks(),
+			"execute_block;wasm 60\n\
+			execute_block;host;ext_storage_get_version_1 30\n\
+			execute_block;host;ext_hashing_blake2_256_version_1 10\n",
+		);
+	}
+
+	#[test]
+	fn should_serialize_host_function_profile() {
+		crate::assert_deser(
+			r#"{"name":"ext_storage_get_version_1","calls":1,"timeNs":2,"bytesRead":3,"bytesWritten":4}"#,
+			HostFunctionProfile {
+				name: "ext_storage_get_version_1".into(),
+				calls: 1,
+				time_ns: 2,
+				bytes_read: 3,
+				bytes_written: 4,
+			},
+		);
+	}
+}
@@ -416,6 +416,20 @@ impl Verify for MultiSignature {
 			},
 		}
 	}
+
+	fn batch_verify<L: Lazy<[u8]>>(&self, msg: L, signer: &AccountId32) -> bool {
+		match (self, signer) {
+			(Self::Ed25519(ref sig), who) => match ed25519::Public::from_slice(who.as_ref()) {
+				Ok(signer) => sig.batch_verify(msg, &signer),
+				Err(()) => false,
+			},
+			(Self::Sr25519(ref sig), who) => match sr25519::Public::from_slice(who.as_ref()) {
+				Ok(signer) => sig.batch_verify(msg, &signer),
+				Err(()) => false,
+			},
+			(Self::Ecdsa(_), _) => self.verify(msg, signer),
+		}
+	}
 }
 
 /// Signature verify that can work with any known signature types..
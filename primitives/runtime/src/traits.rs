@@ -99,6 +99,27 @@ pub trait Verify {
 		msg: L,
 		signer: &<Self::Signer as IdentifyAccount>::AccountId,
 	) -> bool;
+
+	/// Verify a signature, as part of the running [`SignatureBatching`] session, if any.
+	///
+	/// While a batching session runs, the verification can be deferred to
+	/// [`SignatureBatching::verify`], returning `true`. It should only be used where an invalid
+	/// signature invalidates the whole batching session, e.g. the signatures of the extrinsics of
+	/// an imported block.
+	///
+	/// The signatures of a session can be verified with the batch verification algorithms, which
+	/// may accept signatures rejected by [`Self::verify`]. Outside of a session the signature is
+	/// verified like [`Self::verify`] does.
+	///
+	/// [`SignatureBatching`]: crate::SignatureBatching
+	/// [`SignatureBatching::verify`]: crate::SignatureBatching::verify
+	fn batch_verify<L: Lazy<[u8]>>(
+		&self,
+		msg: L,
+		signer: &<Self::Signer as IdentifyAccount>::AccountId,
+	) -> bool {
+		self.verify(msg, signer)
+	}
 }
 
 impl Verify for sp_core::ed25519::Signature {
@@ -107,6 +128,10 @@ impl Verify for sp_core::ed25519::Signature {
 	fn verify<L: Lazy<[u8]>>(&self, mut msg: L, signer: &sp_core::ed25519::Public) -> bool {
 		sp_io::crypto::ed25519_verify(self, msg.get(), signer)
 	}
+
+	fn batch_verify<L: Lazy<[u8]>>(&self, mut msg: L, signer: &sp_core::ed25519::Public) -> bool {
+		sp_io::crypto::ed25519_batch_verify(self, msg.get(), signer)
+	}
 }
 
 impl Verify for sp_core::sr25519::Signature {
@@ -115,6 +140,10 @@ impl Verify for sp_core::sr25519::Signature {
 	fn verify<L: Lazy<[u8]>>(&self, mut msg: L, signer: &sp_core::sr25519::Public) -> bool {
 		sp_io::crypto::sr25519_verify(self, msg.get(), signer)
 	}
+
+	fn batch_verify<L: Lazy<[u8]>>(&self, mut msg: L, signer: &sp_core::sr25519::Public) -> bool {
+		sp_io::crypto::sr25519_batch_verify(self, msg.get(), signer)
+	}
 }
 
 impl Verify for sp_core::ecdsa::Signature {
@@ -23,7 +23,9 @@ use crate::{
 };
 use codec::Encode;
 use hash_db::Hasher;
-use sp_core::storage::{ChildInfo, StateVersion, TrackedStorageKey};
+use sp_core::storage::{
+	well_known_keys::DEFAULT_CHILD_STORAGE_KEY_PREFIX, ChildInfo, StateVersion, TrackedStorageKey,
+};
 #[cfg(feature = "std")]
 use sp_core::traits::RuntimeCode;
 use sp_std::vec::Vec;
@@ -116,6 +118,28 @@ pub trait Backend<H: Hasher>: sp_std::fmt::Debug {
 		f: F,
 	);
 
+	/// Retrieve the default child tries of the state and call `f` with the info and the encoded
+	/// root of each of them, in order of their storage keys. If `start_at` is given, the
+	/// iteration starts at the child trie with this storage key (without the default child
+	/// storage prefix). Aborts as soon as `f` returns false.
+	///
+	/// Returns `true` if all the child tries have been visited.
+	fn apply_to_default_child_tries_while<F: FnMut(ChildInfo, Vec<u8>) -> bool>(
+		&self,
+		start_at: Option<&[u8]>,
+		mut f: F,
+	) -> Result<bool, Self::Error> {
+		let prefix = DEFAULT_CHILD_STORAGE_KEY_PREFIX;
+		let start_at = start_at.map(|storage_key| [prefix, storage_key].concat());
+		self.apply_to_key_values_while(
+			None,
+			Some(prefix),
+			start_at.as_deref(),
+			|key, root| f(ChildInfo::new_default(&key[prefix.len()..]), root),
+			false,
+		)
+	}
+
 	/// Retrieve all entries keys which start with the given prefix and
 	/// call `f` for each of those keys.
 	fn for_keys_with_prefix<F: FnMut(&[u8])>(&self, prefix: &[u8], mut f: F) {
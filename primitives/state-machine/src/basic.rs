@@ -29,7 +29,7 @@ use sp_core::{
 	traits::Externalities,
 	Blake2Hasher,
 };
-use sp_externalities::{Extension, Extensions, MultiRemovalResults};
+use sp_externalities::{BackendRead, Extension, Extensions, MultiRemovalResults};
 use sp_trie::{empty_child_trie_root, HashKey, LayoutV0, LayoutV1, TrieConfiguration};
 use std::{
 	any::{Any, TypeId},
@@ -172,6 +172,11 @@ impl Externalities for BasicExternalities {
 			.and_then(|child| child.data.range::<[u8], _>(range).next().map(|(k, _)| k).cloned())
 	}
 
+	/// The basic externalities have no backend, `read` is answered from their storage.
+	fn read_backend(&self, read: &BackendRead) -> Option<Vec<u8>> {
+		read.read_from(self)
+	}
+
 	fn place_storage(&mut self, key: StorageKey, maybe_value: Option<StorageValue>) {
 		if is_child_storage_key(&key) {
 			warn!(target: "trie", "Refuse to set child storage key via main storage");
@@ -27,7 +27,9 @@ use sp_core::hexdisplay::HexDisplay;
 use sp_core::storage::{
 	well_known_keys::is_child_storage_key, ChildInfo, StateVersion, TrackedStorageKey,
 };
-use sp_externalities::{Extension, ExtensionStore, Externalities, MultiRemovalResults};
+use sp_externalities::{
+	BackendRead, Extension, ExtensionStore, Externalities, MultiRemovalResults,
+};
 use sp_trie::{empty_child_trie_root, LayoutV1};
 
 use crate::{log_error, trace, warn, StorageTransactionCache};
@@ -387,6 +389,26 @@ where
 		}
 	}
 
+	fn read_backend(&self, read: &BackendRead) -> Option<Vec<u8>> {
+		let _guard = guard();
+		let result = match read {
+			BackendRead::Storage(key) => self.backend.storage(key),
+			BackendRead::StorageHash(key) =>
+				self.backend.storage_hash(key).map(|hash| hash.map(|hash| hash.encode())),
+			BackendRead::ChildStorage(child_info, key) =>
+				self.backend.child_storage(child_info, key),
+			BackendRead::ChildStorageHash(child_info, key) => self
+				.backend
+				.child_storage_hash(child_info, key)
+				.map(|hash| hash.map(|hash| hash.encode())),
+			BackendRead::NextStorageKey(key) => self.backend.next_storage_key(key),
+			BackendRead::NextChildStorageKey(child_info, key) =>
+				self.backend.next_child_storage_key(child_info, key),
+		};
+
+		result.expect(EXT_NOT_ALLOWED_TO_FAIL)
+	}
+
 	fn place_storage(&mut self, key: StorageKey, value: Option<StorageValue>) {
 		let _guard = guard();
 		if is_child_storage_key(&key) {
@@ -942,6 +964,36 @@ mod tests {
 		assert_eq!(ext.next_storage_key(&[40]), Some(vec![50]));
 	}
 
+	#[test]
+	fn read_backend_ignores_the_overlay() {
+		let mut cache = StorageTransactionCache::default();
+		let mut overlay = OverlayedChanges::default();
+		overlay.set_storage(vec![10], Some(vec![11]));
+		overlay.set_storage(vec![15], Some(vec![15]));
+		let backend = (
+			Storage {
+				top: map![
+					vec![10] => vec![10],
+					vec![20] => vec![20]
+				],
+				children_default: map![],
+			},
+			StateVersion::default(),
+		)
+			.into();
+
+		let ext = TestExt::new(&mut overlay, &mut cache, &backend, None);
+
+		assert_eq!(ext.storage(&[10]), Some(vec![11]));
+		assert_eq!(ext.read_backend(&BackendRead::Storage(vec![10])), Some(vec![10]));
+		assert_eq!(
+			ext.read_backend(&BackendRead::StorageHash(vec![10])),
+			Some(Blake2Hasher::hash(&[10]).encode()),
+		);
+		assert_eq!(ext.next_storage_key(&[10]), Some(vec![15]));
+		assert_eq!(ext.read_backend(&BackendRead::NextStorageKey(vec![10])), Some(vec![20]));
+	}
+
 	#[test]
 	fn next_storage_key_works_with_a_lot_empty_values_in_overlay() {
 		let mut cache = StorageTransactionCache::default();
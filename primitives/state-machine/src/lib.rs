@@ -1010,6 +1010,136 @@ mod execution {
 		Ok((proving_backend.extract_proof(), count))
 	}
 
+	/// Generate range storage read proof of up to `count` entries starting with `prefix`,
+	/// following `start_at` exclusively.
+	///
+	/// Returns the read entries along with the proof.
+	pub fn prove_range_read_with_count<B, H>(
+		backend: B,
+		child_info: Option<&ChildInfo>,
+		prefix: Option<&[u8]>,
+		count: u32,
+		start_at: Option<&[u8]>,
+	) -> Result<(Vec<(Vec<u8>, Vec<u8>)>, StorageProof), Box<dyn Error>>
+	where
+		B: Backend<H>,
+		H: Hasher,
+		H::Out: Ord + Codec,
+	{
+		let trie_backend = backend
+			.as_trie_backend()
+			.ok_or_else(|| Box::new(ExecutionError::UnableToGenerateProof) as Box<dyn Error>)?;
+		prove_range_read_with_count_on_trie_backend(
+			trie_backend,
+			child_info,
+			prefix,
+			count,
+			start_at,
+		)
+	}
+
+	/// Generate range storage read proof of up to `count` entries on an existing trie backend.
+	pub fn prove_range_read_with_count_on_trie_backend<S, H>(
+		trie_backend: &TrieBackend<S, H>,
+		child_info: Option<&ChildInfo>,
+		prefix: Option<&[u8]>,
+		count: u32,
+		start_at: Option<&[u8]>,
+	) -> Result<(Vec<(Vec<u8>, Vec<u8>)>, StorageProof), Box<dyn Error>>
+	where
+		S: trie_backend_essence::TrieBackendStorage<H>,
+		H: Hasher,
+		H::Out: Ord + Codec,
+	{
+		let proving_backend = proving_backend::ProvingBackend::<S, H>::new(trie_backend);
+		let mut entries = Vec::new();
+		if count > 0 {
+			proving_backend
+				.apply_to_key_values_while(
+					child_info,
+					prefix,
+					start_at,
+					|key, value| {
+						if Some(key.as_slice()) != start_at {
+							entries.push((key, value));
+						}
+						(entries.len() as u32) < count
+					},
+					false,
+				)
+				.map_err(|e| Box::new(e) as Box<dyn Error>)?;
+		}
+		Ok((entries, proving_backend.extract_proof()))
+	}
+
+	/// Generate a proof of the storage entries with keys starting with `prefix`, from `start_at`
+	/// inclusively to `end_at` exclusively, that `read_complete_range_proof_check` can check to be
+	/// the whole content of the range.
+	///
+	/// The proof stops after `max_entries` entries, and then only covers the start of the range.
+	pub fn prove_complete_range_read<B, H>(
+		backend: B,
+		child_info: Option<&ChildInfo>,
+		prefix: Option<&[u8]>,
+		start_at: Option<&[u8]>,
+		end_at: Option<&[u8]>,
+		max_entries: u32,
+	) -> Result<StorageProof, Box<dyn Error>>
+	where
+		B: Backend<H>,
+		H: Hasher,
+		H::Out: Ord + Codec,
+	{
+		let trie_backend = backend
+			.as_trie_backend()
+			.ok_or_else(|| Box::new(ExecutionError::UnableToGenerateProof) as Box<dyn Error>)?;
+		prove_complete_range_read_on_trie_backend(
+			trie_backend,
+			child_info,
+			prefix,
+			start_at,
+			end_at,
+			max_entries,
+		)
+	}
+
+	/// Generate a complete range storage read proof on an existing trie backend.
+	pub fn prove_complete_range_read_on_trie_backend<S, H>(
+		trie_backend: &TrieBackend<S, H>,
+		child_info: Option<&ChildInfo>,
+		prefix: Option<&[u8]>,
+		start_at: Option<&[u8]>,
+		end_at: Option<&[u8]>,
+		max_entries: u32,
+	) -> Result<StorageProof, Box<dyn Error>>
+	where
+		S: trie_backend_essence::TrieBackendStorage<H>,
+		H: Hasher,
+		H::Out: Ord + Codec,
+	{
+		let proving_backend = proving_backend::ProvingBackend::<S, H>::new(trie_backend);
+		let mut count = 0;
+		proving_backend
+			.apply_to_key_values_while(
+				child_info,
+				prefix,
+				start_at,
+				|key, _value| {
+					// Reading the first key out of the range proves its end.
+					if end_at.map_or(false, |end_at| key.as_slice() >= end_at) ||
+						count == max_entries
+					{
+						return false
+					}
+					count += 1;
+					true
+				},
+				false,
+			)
+			.map_err(|e| Box::new(e) as Box<dyn Error>)?;
+		Ok(proving_backend.extract_proof())
+	}
+
 	/// Generate child storage read proof.
 	pub fn prove_child_read<B, H, I>(
 		backend: B,
@@ -1093,6 +1223,92 @@ mod execution {
 		Ok(result)
 	}
 
+	/// Check storage range proof, generated by `prove_complete_range_read` call.
+	///
+	/// Returns the entries of the range and a `bool` that is set to `true` when the proof shows
+	/// that they are the whole content of the range. When `false`, they are the whole content of
+	/// the range up to the last returned key.
+	pub fn read_complete_range_proof_check<H>(
+		root: H::Out,
+		proof: StorageProof,
+		child_info: Option<&ChildInfo>,
+		prefix: Option<&[u8]>,
+		start_at: Option<&[u8]>,
+		end_at: Option<&[u8]>,
+	) -> Result<(Vec<(Vec<u8>, Vec<u8>)>, bool), Box<dyn Error>>
+	where
+		H: Hasher,
+		H::Out: Ord + Codec,
+	{
+		let db = proof.into_memory_db::<H>();
+		let prefix = prefix.unwrap_or_default();
+		let result = match child_info {
+			None =>
+				sp_trie::read_trie_range::<LayoutV1<H>, _>(&db, &root, prefix, start_at, end_at),
+			Some(child_info) => {
+				let child_root = sp_trie::read_trie_value::<LayoutV1<H>, _>(
+					&db,
+					&root,
+					&child_info.prefixed_storage_key(),
+				)
+				.map_err(|e| Box::new(e) as Box<dyn Error>)?;
+				match child_root {
+					Some(child_root) => {
+						let child_root = Decode::decode(&mut &child_root[..])
+							.map_err(|e| Box::new(e) as Box<dyn Error>)?;
+						sp_trie::read_child_trie_range::<LayoutV1<H>, _>(
+							child_info.keyspace(),
+							&db,
+							&child_root,
+							prefix,
+							start_at,
+							end_at,
+						)
+					},
+					// The child trie doesn't exist, so the range is empty.
+					None => Ok((Vec::new(), true)),
+				}
+			},
+		};
+		result.map_err(|e| Box::new(e) as Box<dyn Error>)
+	}
+
+	/// Check compact storage read proofs of several states, generated by
+	/// `sp_trie::encode_compact_multi` from `prove_read` calls.
+	///
+	/// Returns the values of `keys` in each of the states, in the order of `roots`.
+	pub fn read_compact_multi_proof_check<H, I>(
+		roots: &[H::Out],
+		proofs: &[CompactProof],
+		keys: I,
+	) -> Result<Vec<HashMap<Vec<u8>, Option<Vec<u8>>>>, Box<dyn Error>>
+	where
+		H: Hasher,
+		H::Out: Ord + Codec,
+		I: IntoIterator + Clone,
+		I::Item: AsRef<[u8]>,
+	{
+		if roots.len() != proofs.len() {
+			return Err(Box::new(ExecutionError::InvalidProof))
+		}
+		let mut db = MemoryDB::<H>::default();
+		sp_trie::decode_compact_multi::<LayoutV0<H>, _, _>(&mut db, proofs.iter().zip(roots))
+			.map_err(|e| Box::new(e) as Box<dyn Error>)?;
+
+		let mut results = Vec::with_capacity(roots.len());
+		for root in roots {
+			let proving_backend = TrieBackend::new(db, *root);
+			let mut result = HashMap::new();
+			for key in keys.clone() {
+				let value = read_proof_check_on_proving_backend(&proving_backend, key.as_ref())?;
+				result.insert(key.as_ref().to_vec(), value);
+			}
+			results.push(result);
+			db = proving_backend.into_storage();
+		}
+		Ok(results)
+	}
+
 	/// Check storage range proof with child trie included, generated by
 	/// `prove_range_read_with_child_with_size` call.
 	///
@@ -2030,6 +2246,186 @@ mod tests {
 		assert_eq!(completed, true);
 	}
 
+	#[test]
+	fn prove_complete_range_read_works() {
+		let state_version = StateVersion::V1;
+		let remote_backend = trie_backend::tests::test_trie(state_version);
+		let remote_root = remote_backend.storage_root(::std::iter::empty(), state_version).0;
+		let child_info = ChildInfo::new_default(b"sub1");
+		let check = |child_info: Option<&ChildInfo>,
+		             prefix: Option<&[u8]>,
+		             start_at: Option<&[u8]>,
+		             end_at: Option<&[u8]>,
+		             max_entries| {
+			let proof = prove_complete_range_read_on_trie_backend(
+				&remote_backend,
+				child_info,
+				prefix,
+				start_at,
+				end_at,
+				max_entries,
+			)
+			.unwrap();
+			read_complete_range_proof_check::<BlakeTwo256>(
+				remote_root,
+				proof,
+				child_info,
+				prefix,
+				start_at,
+				end_at,
+			)
+			.unwrap()
+		};
+
+		assert_eq!(
+			check(None, Some(b"value"), None, None, 10),
+			(vec![(b"value1".to_vec(), vec![42]), (b"value2".to_vec(), vec![24])], true),
+		);
+		assert_eq!(
+			check(None, None, Some(b"key"), Some(b"value2"), 10),
+			(vec![(b"key".to_vec(), b"value".to_vec()), (b"value1".to_vec(), vec![42])], true),
+		);
+		assert_eq!(
+			check(Some(&child_info), None, None, None, 10),
+			(vec![(b"value3".to_vec(), vec![142; 33]), (b"value4".to_vec(), vec![124; 33])], true),
+		);
+		assert_eq!(
+			check(Some(&ChildInfo::new_default(b"sub2")), None, None, None, 10),
+			(Vec::new(), true),
+		);
+
+		// Only the start of the range is proven.
+		let (entries, complete) = check(None, None, None, None, 2);
+		assert_eq!(complete, false);
+		assert!(entries.len() >= 2);
+
+		// Without the proof of the end of the range.
+		let proof = prove_complete_range_read_on_trie_backend(
+			&remote_backend,
+			None,
+			Some(b"value"),
+			None,
+			None,
+			10,
+		)
+		.unwrap();
+		let (_, complete) = read_complete_range_proof_check::<BlakeTwo256>(
+			remote_root,
+			proof,
+			None,
+			None,
+			None,
+			None,
+		)
+		.unwrap();
+		assert_eq!(complete, false);
+	}
+
+	#[test]
+	fn compact_multi_proof_shares_nodes() {
+		let state_version = StateVersion::V1;
+		let (mut mdb, root1) = trie_backend::tests::test_db(state_version);
+		let mut root2 = root1;
+		{
+			let mut trie = TrieDBMutV1::from_existing(&mut mdb, &mut root2).unwrap();
+			trie.insert(b"value2", &[25]).expect("insert failed");
+		}
+		let keys: &[&[u8]] = &[b"value1", b"value2"];
+		let proof_at = |root| {
+			let remote_backend = TrieBackend::new(mdb.clone(), root);
+			prove_read(remote_backend, keys).unwrap()
+		};
+		let proofs =
+			vec![(proof_at(root1), root1), (proof_at(root2), root2), (proof_at(root2), root2)];
+		let single_size: usize = proofs
+			.iter()
+			.map(|(proof, root)| proof.clone().into_compact_proof::<BlakeTwo256>(*root).unwrap())
+			.map(|proof| proof.encoded_size())
+			.sum();
+
+		let compact_proofs =
+			sp_trie::encode_compact_multi::<LayoutV1<BlakeTwo256>>(proofs).unwrap();
+		assert!(compact_proofs[1].encoded_nodes.len() > 0);
+		// The root of the third state was already proven.
+		assert!(compact_proofs[2].encoded_nodes.is_empty());
+		assert!(compact_proofs.encoded_size() < single_size);
+
+		let results = read_compact_multi_proof_check::<BlakeTwo256, _>(
+			&[root1, root2, root2],
+			&compact_proofs,
+			keys,
+		)
+		.unwrap();
+		assert_eq!(results[0][&b"value2"[..]], Some(vec![24]));
+		assert_eq!(results[1][&b"value1"[..]], Some(vec![42]));
+		assert_eq!(results[1][&b"value2"[..]], Some(vec![25]));
+		assert_eq!(results[2], results[1]);
+
+		// Proofs can't be verified out of order.
+		assert!(read_compact_multi_proof_check::<BlakeTwo256, _>(
+			&[root2, root1],
+			&compact_proofs[1..],
+			keys,
+		)
+		.is_err());
+	}
+
+	#[test]
+	fn prove_read_with_count_works() {
+		let state_version = StateVersion::V0;
+		let remote_backend = trie_backend::tests::test_trie(state_version);
+		let remote_root = remote_backend.storage_root(::std::iter::empty(), state_version).0;
+		let (entries, proof) =
+			prove_range_read_with_count(remote_backend, None, Some(b"value"), 1, Some(b"value1"))
+				.unwrap();
+		// The start key is excluded.
+		assert_eq!(entries, vec![(b"value2".to_vec(), vec![24])]);
+		let (results, completed) = read_range_proof_check::<BlakeTwo256>(
+			remote_root,
+			proof,
+			None,
+			Some(b"value"),
+			Some(2),
+			Some(b"value1"),
+		)
+		.unwrap();
+		assert_eq!(results, vec![(b"value1".to_vec(), vec![42]), (b"value2".to_vec(), vec![24])]);
+		assert_eq!(completed, false);
+
+		let remote_backend = trie_backend::tests::test_trie(state_version);
+		let child_info = ChildInfo::new_default(b"sub1");
+		let (entries, _) =
+			prove_range_read_with_count(remote_backend, Some(&child_info), None, 10, None).unwrap();
+		assert_eq!(
+			entries,
+			vec![(b"value3".to_vec(), vec![142; 33]), (b"value4".to_vec(), vec![124; 33])],
+		);
+	}
+
+	#[test]
+	fn default_child_tries_are_iterated() {
+		let remote_backend = trie_backend::tests::test_trie(StateVersion::V1);
+		let mut child_tries = Vec::new();
+		let completed = remote_backend
+			.apply_to_default_child_tries_while(None, |child_info, root| {
+				child_tries.push((child_info.storage_key().to_vec(), root.len()));
+				true
+			})
+			.unwrap();
+		assert!(completed);
+		assert_eq!(child_tries, vec![(b"sub1".to_vec(), 32)]);
+
+		let mut visited = false;
+		let completed = remote_backend
+			.apply_to_default_child_tries_while(Some(b"sub2"), |_, _| {
+				visited = true;
+				true
+			})
+			.unwrap();
+		assert!(completed);
+		assert!(!visited);
+	}
+
 	#[test]
 	fn inner_state_versioning_switch_proofs() {
 		let mut state_version = StateVersion::V0;
@@ -25,7 +25,7 @@ use sp_core::{
 	traits::Externalities,
 	Blake2Hasher,
 };
-use sp_externalities::MultiRemovalResults;
+use sp_externalities::{BackendRead, MultiRemovalResults};
 use std::{
 	any::{Any, TypeId},
 	marker::PhantomData,
@@ -112,6 +112,10 @@ impl<'a, H: Hasher, B: 'a + Backend<H>> Externalities for ReadOnlyExternalities<
 			.expect("Backed failed for next_child_storage_key in ReadOnlyExternalities")
 	}
 
+	fn read_backend(&self, read: &BackendRead) -> Option<Vec<u8>> {
+		read.read_from(self)
+	}
+
 	fn place_storage(&mut self, _key: StorageKey, _maybe_value: Option<StorageValue>) {
 		unimplemented!("place_storage not supported in ReadOnlyExternalities")
 	}
@@ -22,22 +22,61 @@ use sp_core::{
 	storage::{ChildInfo, StateVersion, TrackedStorageKey},
 	traits::{Externalities, RuntimeSpawn, RuntimeSpawnExt, SpawnNamed, TaskExecutorExt},
 };
-use sp_externalities::{Extensions, ExternalitiesExt as _, MultiRemovalResults};
-use std::any::{Any, TypeId};
+use sp_externalities::{BackendRead, Extensions, ExternalitiesExt as _, MultiRemovalResults};
+use std::{
+	any::{Any, TypeId},
+	sync::{mpsc, Arc},
+};
+
+/// A message sent by a task to the thread joining it.
+pub enum TaskMessage {
+	/// A storage read of the task, answered through the sender.
+	Read(BackendRead, mpsc::Sender<Option<Vec<u8>>>),
+	/// The result of the task.
+	Result(Vec<u8>),
+}
+
+/// Waits for the result of the task sending to `receiver`, answering its storage reads from the
+/// backend of `ext` meanwhile.
+///
+/// Returns `None` if the task stopped without sending its result, e.g. because it panicked.
+pub fn wait_for_task(
+	receiver: &mpsc::Receiver<TaskMessage>,
+	ext: &dyn Externalities,
+) -> Option<Vec<u8>> {
+	loop {
+		match receiver.recv().ok()? {
+			TaskMessage::Read(read, reply) => {
+				let _ = reply.send(ext.read_backend(&read));
+			},
+			TaskMessage::Result(result) => return Some(result),
+		}
+	}
+}
 
 /// Simple state-less externalities for use in async context.
 ///
-/// Will panic if anything is accessing the storage.
-#[derive(Debug)]
+/// Will panic if anything is writing to the storage, or reading it without
+/// [`AsyncExternalities::with_joiner`].
 pub struct AsyncExternalities {
 	extensions: Extensions,
+	joiner: Option<mpsc::Sender<TaskMessage>>,
+}
+
+impl std::fmt::Debug for AsyncExternalities {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("AsyncExternalities")
+			.field("extensions", &self.extensions)
+			.field("joiner", &self.joiner.is_some())
+			.finish()
+	}
 }
 
 /// New Async externalities.
 pub fn new_async_externalities(
 	scheduler: Box<dyn SpawnNamed>,
 ) -> Result<AsyncExternalities, &'static str> {
-	let mut res = AsyncExternalities { extensions: Default::default() };
+	let mut res = AsyncExternalities { extensions: Default::default(), joiner: None };
 	let mut ext = &mut res as &mut dyn Externalities;
 	ext.register_extension::<TaskExecutorExt>(TaskExecutorExt(scheduler.clone()))
 		.map_err(|_| "Failed to register task executor extension.")?;
@@ -52,11 +91,34 @@ impl AsyncExternalities {
 		runtime_ext: Box<dyn RuntimeSpawn>,
 	) -> Result<Self, &'static str> {
 		let mut ext = &mut self as &mut dyn Externalities;
-		ext.register_extension::<RuntimeSpawnExt>(RuntimeSpawnExt(runtime_ext))
+		ext.register_extension::<RuntimeSpawnExt>(RuntimeSpawnExt(Arc::from(runtime_ext)))
 			.map_err(|_| "Failed to register task executor extension.")?;
 
 		Ok(self)
 	}
+
+	/// Extend async externalities with read only access to the storage of the runtime call,
+	/// read by the thread joining the task through `joiner`, see [`wait_for_task`].
+	///
+	/// A read panics once the task can't be joined anymore.
+	pub fn with_joiner(mut self, joiner: mpsc::Sender<TaskMessage>) -> Self {
+		self.joiner = Some(joiner);
+		self
+	}
+
+	fn read(&self, method: &str, read: BackendRead) -> Option<Vec<u8>> {
+		let joiner = match &self.joiner {
+			Some(joiner) => joiner,
+			None => panic!("`{}`: should not be used in async externalities!", method),
+		};
+
+		let (sender, receiver) = mpsc::channel();
+		joiner
+			.send(TaskMessage::Read(read, sender))
+			.ok()
+			.and_then(|_| receiver.recv().ok())
+			.unwrap_or_else(|| panic!("`{}`: the task can't be joined anymore!", method))
+	}
 }
 
 type StorageKey = Vec<u8>;
@@ -68,28 +130,34 @@ impl Externalities for AsyncExternalities {
 		panic!("`set_offchain_storage`: should not be used in async externalities!")
 	}
 
-	fn storage(&self, _key: &[u8]) -> Option<StorageValue> {
-		panic!("`storage`: should not be used in async externalities!")
+	fn storage(&self, key: &[u8]) -> Option<StorageValue> {
+		self.read("storage", BackendRead::Storage(key.to_vec()))
+	}
+
+	fn storage_hash(&self, key: &[u8]) -> Option<Vec<u8>> {
+		self.read("storage_hash", BackendRead::StorageHash(key.to_vec()))
 	}
 
-	fn storage_hash(&self, _key: &[u8]) -> Option<Vec<u8>> {
-		panic!("`storage_hash`: should not be used in async externalities!")
+	fn child_storage(&self, child_info: &ChildInfo, key: &[u8]) -> Option<StorageValue> {
+		self.read("child_storage", BackendRead::ChildStorage(child_info.clone(), key.to_vec()))
 	}
 
-	fn child_storage(&self, _child_info: &ChildInfo, _key: &[u8]) -> Option<StorageValue> {
-		panic!("`child_storage`: should not be used in async externalities!")
+	fn child_storage_hash(&self, child_info: &ChildInfo, key: &[u8]) -> Option<Vec<u8>> {
+		let read = BackendRead::ChildStorageHash(child_info.clone(), key.to_vec());
+		self.read("child_storage_hash", read)
 	}
 
-	fn child_storage_hash(&self, _child_info: &ChildInfo, _key: &[u8]) -> Option<Vec<u8>> {
-		panic!("`child_storage_hash`: should not be used in async externalities!")
+	fn next_storage_key(&self, key: &[u8]) -> Option<StorageKey> {
+		self.read("next_storage_key", BackendRead::NextStorageKey(key.to_vec()))
 	}
 
-	fn next_storage_key(&self, _key: &[u8]) -> Option<StorageKey> {
-		panic!("`next_storage_key`: should not be used in async externalities!")
+	fn next_child_storage_key(&self, child_info: &ChildInfo, key: &[u8]) -> Option<StorageKey> {
+		let read = BackendRead::NextChildStorageKey(child_info.clone(), key.to_vec());
+		self.read("next_child_storage_key", read)
 	}
 
-	fn next_child_storage_key(&self, _child_info: &ChildInfo, _key: &[u8]) -> Option<StorageKey> {
-		panic!("`next_child_storage_key`: should not be used in async externalities!")
+	fn read_backend(&self, read: &BackendRead) -> Option<Vec<u8>> {
+		self.read("read_backend", read.clone())
 	}
 
 	fn place_storage(&mut self, _key: StorageKey, _maybe_value: Option<StorageValue>) {
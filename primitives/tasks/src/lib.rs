@@ -49,6 +49,25 @@
 //!
 //! When allowing unbounded parallelism, malicious transactions can exploit it and partition
 //! network consensus based on how much resources nodes have.
+//!
+//! # Parallel execution
+//!
+//! Runtimes implementing the [`ParallelTasks`] runtime api have their tasks executed by the
+//! wasm executor on worker threads, each task in a fresh runtime instance. To keep the
+//! execution deterministic, the tasks follow these rules:
+//!
+//! - A task can read the storage of the block the runtime call is executed on, but not the changes
+//!   made by the call itself, as they depend on when the task runs. Writing to the storage panics.
+//! - The storage reads of a task are answered by its caller while joining it, so they are
+//!   recorded in the storage proof of the runtime call. A task which is never joined blocks on
+//!   its first read.
+//! - A runtime call, its tasks included, can spawn at most [`MAX_TASKS_PER_CALL`] tasks. Spawning
+//!   more panics.
+//! - A task does not have access to the extensions of the runtime call, e.g. the keystore or the
+//!   offchain database.
+//! - A panic in a task panics the `join` of its handle.
+//!
+//! The tasks of other runtimes keep running without access to the storage and without a limit.
 
 #![cfg_attr(not(feature = "std"), no_std)]
 
@@ -56,13 +75,42 @@
 mod async_externalities;
 
 #[cfg(feature = "std")]
-pub use async_externalities::{new_async_externalities, AsyncExternalities};
+pub use async_externalities::{
+	new_async_externalities, wait_for_task, AsyncExternalities, TaskMessage,
+};
+
+/// The maximum number of tasks a runtime call implementing [`ParallelTasks`] can spawn, the
+/// tasks spawned by its tasks included.
+pub const MAX_TASKS_PER_CALL: u32 = 16;
+
+sp_api::decl_runtime_apis! {
+	/// Marks a runtime whose tasks follow the rules of parallel execution.
+	///
+	/// See the [crate documentation](crate#parallel-execution) for the rules.
+	pub trait ParallelTasks {}
+}
+
+/// Returns whether the runtime of `version` implements [`ParallelTasks`].
+#[cfg(feature = "std")]
+pub fn runs_tasks_in_parallel(version: &sp_version::RuntimeVersion) -> bool {
+	version.has_api_with(&runtime_decl_for_ParallelTasks::ID, |_| true)
+}
 
 #[cfg(feature = "std")]
 mod inner {
 	use sp_core::traits::TaskExecutorExt;
 	use sp_externalities::ExternalitiesExt as _;
-	use std::{panic::AssertUnwindSafe, sync::mpsc};
+	use std::{
+		panic::AssertUnwindSafe,
+		sync::{mpsc, Arc, Mutex},
+	};
+
+	type Job = Box<dyn FnOnce() + Send>;
+
+	/// Takes the job of a task, unless it started already.
+	fn take_job(job: &Mutex<Option<Job>>) -> Option<Job> {
+		job.lock().ok().and_then(|mut job| job.take())
+	}
 
 	/// Task handle (wasm).
 	///
@@ -70,19 +118,33 @@ mod inner {
 	/// the spawned task execution.
 	#[must_use]
 	pub struct DataJoinHandle {
-		receiver: mpsc::Receiver<Vec<u8>>,
+		job: Arc<Mutex<Option<Job>>>,
+		receiver: mpsc::Receiver<crate::TaskMessage>,
 	}
 
 	impl DataJoinHandle {
 		/// Join handle returned by `spawn` function
+		///
+		/// The storage reads of the task are answered from the current context while waiting.
 		pub fn join(self) -> Vec<u8> {
-			self.receiver
-				.recv()
+			// A task which did not start yet gets a thread of its own, the scheduler may be busy
+			// with tasks waiting for their storage reads.
+			if let Some(job) = take_job(&self.job) {
+				if let Err(e) = std::thread::Builder::new().spawn(job) {
+					log::error!(target: "runtime", "Unable to start joined task: {}", e);
+				}
+			}
+
+			sp_externalities::with_externalities(|ext| crate::wait_for_task(&self.receiver, ext))
+				.expect("Join called outside of externalities context!")
 				.expect("Spawned runtime task terminated before sending result.")
 		}
 	}
 
 	/// Spawn new runtime task (native).
+	///
+	/// The task can read the storage of the current context, without its changes, while it is
+	/// joined.
 	pub fn spawn(entry_point: fn(Vec<u8>) -> Vec<u8>, data: Vec<u8>) -> DataJoinHandle {
 		let scheduler = sp_externalities::with_externalities(|mut ext| {
 			ext.extension::<TaskExecutorExt>()
@@ -93,48 +155,58 @@ mod inner {
 
 		let (sender, receiver) = mpsc::channel();
 		let extra_scheduler = scheduler.clone();
+		let job: Job = Box::new(move || {
+			let ext = crate::new_async_externalities(extra_scheduler)
+				.map(|ext| ext.with_joiner(sender.clone()));
+			let result = match ext {
+				Ok(mut ext) => {
+					let mut ext = AssertUnwindSafe(&mut ext);
+					match std::panic::catch_unwind(move || {
+						sp_externalities::set_and_run_with_externalities(
+							&mut **ext,
+							move || entry_point(data),
+						)
+					}) {
+						Ok(result) => result,
+						Err(panic) => {
+							log::error!(
+								target: "runtime",
+								"Spawned task panicked: {:?}",
+								panic,
+							);
+
+							// This will drop sender without sending anything.
+							return
+						},
+					}
+				},
+				Err(e) => {
+					log::error!(
+						target: "runtime",
+						"Unable to run async task: {}",
+						e,
+					);
+
+					return
+				},
+			};
+
+			let _ = sender.send(crate::TaskMessage::Result(result));
+		});
+
+		let job = Arc::new(Mutex::new(Some(job)));
+		let scheduled_job = job.clone();
 		scheduler.spawn(
 			"parallel-runtime-spawn",
 			Some("substrate-runtime"),
 			Box::pin(async move {
-				let result = match crate::new_async_externalities(extra_scheduler) {
-					Ok(mut ext) => {
-						let mut ext = AssertUnwindSafe(&mut ext);
-						match std::panic::catch_unwind(move || {
-							sp_externalities::set_and_run_with_externalities(
-								&mut **ext,
-								move || entry_point(data),
-							)
-						}) {
-							Ok(result) => result,
-							Err(panic) => {
-								log::error!(
-									target: "runtime",
-									"Spawned task panicked: {:?}",
-									panic,
-								);
-
-								// This will drop sender without sending anything.
-								return
-							},
-						}
-					},
-					Err(e) => {
-						log::error!(
-							target: "runtime",
-							"Unable to run async task: {}",
-							e,
-						);
-
-						return
-					},
-				};
-
-				let _ = sender.send(result);
+				if let Some(job) = take_job(&scheduled_job) {
+					job()
+				}
 			}),
 		);
 
-		DataJoinHandle { receiver }
+		DataJoinHandle { job, receiver }
 	}
 }
 
@@ -217,6 +289,21 @@ mod tests {
 		})
 	}
 
+	fn async_reader(key: Vec<u8>) -> Vec<u8> {
+		sp_io::storage::get(&key).unwrap_or_default()
+	}
+
+	#[test]
+	fn reading_storage() {
+		let mut ext = sp_io::TestExternalities::default();
+		ext.insert(b"key".to_vec(), b"value".to_vec());
+		ext.execute_with(|| {
+			sp_io::storage::set(b"key", b"changed");
+			let handle = spawn(async_reader, b"key".to_vec());
+			assert_eq!(handle.join(), b"value".to_vec());
+		})
+	}
+
 	#[test]
 	fn panicking() {
 		let res = sp_io::TestExternalities::default().execute_with_safe(|| {
@@ -39,7 +39,10 @@ use sp_std::{borrow::Borrow, boxed::Box, marker::PhantomData, vec::Vec};
 pub use storage_proof::{CompactProof, StorageProof};
 /// Trie codec reexport, mainly child trie support
 /// for trie compact proof.
-pub use trie_codec::{decode_compact, encode_compact, Error as CompactProofError};
+pub use trie_codec::{
+	decode_compact, decode_compact_multi, encode_compact, encode_compact_multi,
+	Error as CompactProofError,
+};
 pub use trie_db::proof::VerifyError;
 use trie_db::proof::{generate_proof, verify_proof};
 /// Various re-exports from the `trie-db` crate.
@@ -321,6 +324,22 @@ where
 	L::trie_root(input)
 }
 
+/// Build a trie from its contents ordered by key, inserting its nodes into `db` as soon as they
+/// are encoded, and return its root.
+///
+/// Unlike with [`TrieMut`], the nodes of the trie are never all held in memory.
+pub fn build_trie<L: TrieConfiguration, I, A, B, DB>(input: I, db: &mut DB) -> TrieHash<L>
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsRef<[u8]>,
+	DB: hash_db::HashDB<L::Hash, trie_db::DBValue>,
+{
+	let mut builder = trie_db::TrieBuilder::<L, DB>::new(db);
+	trie_db::trie_visit::<L, _, _, _, _>(input, &mut builder);
+	builder.root.unwrap_or_else(empty_trie_root::<L>)
+}
+
 /// Determine a child trie root given a hash DB and delta values. H is the default hasher,
 /// but a generic implementation may ignore this type parameter and use other hashers.
 pub fn child_delta_trie_root<L: TrieConfiguration, I, A, B, DB, RD, V>(
@@ -406,6 +425,67 @@ where
 		.map(|x| x.map(|val| val.to_vec()))
 }
 
+/// Read the key-value pairs of the trie with keys starting with `prefix`, from `start_at`
+/// inclusively to `end_at` exclusively.
+///
+/// The iteration stops at the first missing node. The returned `bool` is `true` if the end
+/// of the range was reached, otherwise the returned pairs are only the start of the range.
+/// Reading from the nodes of a range proof this way checks that it is complete.
+pub fn read_trie_range<L, DB>(
+	db: &DB,
+	root: &TrieHash<L>,
+	prefix: &[u8],
+	start_at: Option<&[u8]>,
+	end_at: Option<&[u8]>,
+) -> Result<(Vec<(Vec<u8>, Vec<u8>)>, bool), Box<TrieError<L>>>
+where
+	L: TrieConfiguration,
+	DB: hash_db::HashDBRef<L::Hash, trie_db::DBValue>,
+{
+	let trie = TrieDB::<L>::new(&*db, root)?;
+	let iter = match start_at {
+		Some(start_at) => TrieDBIterator::new_prefixed_then_seek(&trie, prefix, start_at),
+		None => TrieDBIterator::new_prefixed(&trie, prefix),
+	};
+
+	let mut entries = Vec::new();
+	let result = iter.and_then(|iter| {
+		for item in iter {
+			let (key, value) = item?;
+			if end_at.map_or(false, |end_at| key.as_slice() >= end_at) {
+				break
+			}
+			entries.push((key, value));
+		}
+		Ok(())
+	});
+	match result {
+		Ok(()) => Ok((entries, true)),
+		Err(e) if matches!(*e, trie_db::TrieError::IncompleteDatabase(_)) => Ok((entries, false)),
+		Err(e) => Err(e),
+	}
+}
+
+/// Read the key-value pairs of the child trie with keys starting with `prefix`, from
+/// `start_at` inclusively to `end_at` exclusively.
+///
+/// See [`read_trie_range`].
+pub fn read_child_trie_range<L, DB>(
+	keyspace: &[u8],
+	db: &DB,
+	root: &TrieHash<L>,
+	prefix: &[u8],
+	start_at: Option<&[u8]>,
+	end_at: Option<&[u8]>,
+) -> Result<(Vec<(Vec<u8>, Vec<u8>)>, bool), Box<TrieError<L>>>
+where
+	L: TrieConfiguration,
+	DB: hash_db::HashDBRef<L::Hash, trie_db::DBValue>,
+{
+	let db = KeySpacedDB::new(&*db, keyspace);
+	read_trie_range::<L, _>(&db, root, prefix, start_at, end_at)
+}
+
 /// `HashDB` implementation that append a encoded prefix (unique id bytes) in addition to the
 /// prefix of every key value.
 pub struct KeySpacedDB<'a, DB, H>(&'a DB, &'a [u8], PhantomData<H>);
@@ -853,6 +933,62 @@ mod tests {
 		assert_eq!(pairs, iter_pairs);
 	}
 
+	#[test]
+	fn build_trie_works() {
+		build_trie_works_inner::<LayoutV1>();
+		build_trie_works_inner::<LayoutV0>();
+	}
+	fn build_trie_works_inner<Layout: TrieConfiguration>() {
+		let pairs = vec![
+			(hex!("0102").to_vec(), vec![1; 40]),
+			(hex!("0103").to_vec(), vec![2; 40]),
+			(hex!("0201").to_vec(), vec![3; 2]),
+		];
+
+		let mut mdb = MemoryDB::default();
+		let root = build_trie::<Layout, _, _, _, _>(pairs.clone(), &mut mdb);
+		assert_eq!(root, Layout::trie_root(pairs.clone()));
+
+		let trie = TrieDB::<Layout>::new(&mdb, &root).unwrap();
+		let iter_pairs = trie.iter().unwrap().map(|pair| pair.unwrap()).collect::<Vec<_>>();
+		assert_eq!(pairs, iter_pairs);
+
+		let mut mdb = MemoryDB::default();
+		let root = build_trie::<Layout, _, Vec<u8>, Vec<u8>, _>(Vec::new(), &mut mdb);
+		assert_eq!(root, empty_trie_root::<Layout>());
+	}
+
+	#[test]
+	fn read_trie_range_works() {
+		let pairs = vec![
+			(hex!("0102").to_vec(), vec![1; 40]),
+			(hex!("0103").to_vec(), vec![2; 40]),
+			(hex!("0104").to_vec(), vec![3; 40]),
+			(hex!("0203").to_vec(), vec![4; 40]),
+		];
+
+		let mut memdb = MemoryDB::default();
+		let mut root = Default::default();
+		populate_trie::<LayoutV1>(&mut memdb, &mut root, &pairs);
+
+		let range = read_trie_range::<LayoutV1, _>(&memdb, &root, &hex!("01"), None, None);
+		assert_eq!(range.unwrap(), (pairs[..3].to_vec(), true));
+		let range = read_trie_range::<LayoutV1, _>(
+			&memdb,
+			&root,
+			&[],
+			Some(&hex!("0103")),
+			Some(&hex!("0104")),
+		);
+		assert_eq!(range.unwrap(), (pairs[1..2].to_vec(), true));
+
+		// Only the root node is known, so the range can't be proven.
+		let mut partial_db = MemoryDB::<Blake2Hasher>::default();
+		partial_db.insert(EMPTY_PREFIX, &memdb.get(&root, EMPTY_PREFIX).unwrap());
+		let range = read_trie_range::<LayoutV1, _>(&partial_db, &root, &hex!("01"), None, None);
+		assert_eq!(range.unwrap(), (Vec::new(), false));
+	}
+
 	#[test]
 	fn proof_non_inclusion_works() {
 		let pairs = vec![
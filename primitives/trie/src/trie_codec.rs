@@ -21,6 +21,7 @@
 //! it to substrate specific layout and child trie system.
 
 use crate::{CompactProof, HashDBT, StorageProof, TrieConfiguration, TrieHash, EMPTY_PREFIX};
+use hash_db::Hasher;
 use sp_std::{boxed::Box, vec::Vec};
 use trie_db::{CError, Trie};
 
@@ -205,3 +206,81 @@ where
 
 	Ok(CompactProof { encoded_nodes: compact_proof })
 }
+
+/// Encode proofs of the same top trie content at several states as compact proofs sharing
+/// their nodes.
+///
+/// Takes as input the full encoded nodes of each proof along with its root, in order.
+/// The compact proof of a state only contains the nodes not already decodable from the
+/// compact proofs of the previous states, and references the others by hash. It is empty
+/// if the root of the state was already proven.
+/// Child trie content is not encoded.
+pub fn encode_compact_multi<L>(
+	proofs: impl IntoIterator<Item = (StorageProof, TrieHash<L>)>,
+) -> Result<Vec<CompactProof>, Error<TrieHash<L>, CError<L>>>
+where
+	L: TrieConfiguration,
+{
+	let mut known_db = crate::MemoryDB::<L::Hash>::default();
+	let mut compact_proofs = Vec::new();
+	for (proof, root) in proofs {
+		if HashDBT::<L::Hash, _>::contains(&known_db, &root, EMPTY_PREFIX) {
+			compact_proofs.push(CompactProof { encoded_nodes: Vec::new() });
+			continue
+		}
+
+		let mut partial_db = crate::MemoryDB::<L::Hash>::default();
+		for node in proof.iter_nodes() {
+			if !HashDBT::<L::Hash, _>::contains(&known_db, &L::Hash::hash(&node), EMPTY_PREFIX) {
+				HashDBT::<L::Hash, _>::insert(&mut partial_db, EMPTY_PREFIX, &node);
+			}
+		}
+		let encoded_nodes = {
+			let trie = crate::TrieDB::<L>::new(&partial_db, &root)?;
+			trie_db::encode_compact::<L>(&trie)?
+		};
+
+		// Only keep track of what the verifier gets from decoding the proof.
+		trie_db::decode_compact_from_iter::<L, _, _>(
+			&mut known_db,
+			encoded_nodes.iter().map(Vec::as_slice),
+		)?;
+		compact_proofs.push(CompactProof { encoded_nodes });
+	}
+
+	Ok(compact_proofs)
+}
+
+/// Decode compact proofs encoded with [`encode_compact_multi`].
+///
+/// Takes as input a destination `db` for decoded nodes and the compact proofs along with
+/// their expected roots, in order.
+pub fn decode_compact_multi<'a, L, DB, I>(
+	db: &mut DB,
+	proofs: I,
+) -> Result<(), Error<TrieHash<L>, CError<L>>>
+where
+	L: TrieConfiguration,
+	DB: HashDBT<L::Hash, trie_db::DBValue> + hash_db::HashDBRef<L::Hash, trie_db::DBValue>,
+	I: IntoIterator<Item = (&'a CompactProof, &'a TrieHash<L>)>,
+{
+	for (proof, expected_root) in proofs {
+		if proof.encoded_nodes.is_empty() {
+			if !HashDBT::<L::Hash, _>::contains(db, expected_root, EMPTY_PREFIX) {
+				return Err(Error::IncompleteProof)
+			}
+			continue
+		}
+
+		let mut nodes_iter = proof.iter_compact_encoded_nodes();
+		let (root, _nb_used) = trie_db::decode_compact_from_iter::<L, _, _>(db, &mut nodes_iter)?;
+		if expected_root != &root {
+			return Err(Error::RootMismatch(root, *expected_root))
+		}
+		if nodes_iter.next().is_some() {
+			return Err(Error::ExtraneousChildNode)
+		}
+	}
+
+	Ok(())
+}
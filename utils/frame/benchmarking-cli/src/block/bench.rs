@@ -22,16 +22,30 @@ use frame_support::weights::constants::WEIGHT_PER_NANOS;
 use frame_system::ConsumedWeight;
 use sc_block_builder::{BlockBuilderApi, BlockBuilderProvider};
 use sc_cli::{Error, Result};
-use sc_client_api::{Backend as ClientBackend, BlockBackend, StorageProvider, UsageProvider};
+use sc_client_api::{
+	execution_extensions::ExtensionsFactory, Backend as ClientBackend, BlockBackend,
+	ExecutorProvider, StorageProvider, UsageProvider,
+};
 use sp_api::{ApiExt, Core, HeaderT, ProvideRuntimeApi};
 use sp_blockchain::Error::RuntimeApiError;
+use sp_core::offchain::Capabilities;
+use sp_externalities::Extensions;
+use sp_io::Ed25519BatchingExt;
 use sp_runtime::{generic::BlockId, traits::Block as BlockT, DigestItem, OpaqueExtrinsic};
 use sp_storage::StorageKey;
 
 use clap::Args;
 use log::{info, warn};
 use serde::Serialize;
-use std::{fmt::Debug, marker::PhantomData, sync::Arc, time::Instant};
+use std::{
+	fmt::Debug,
+	marker::PhantomData,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+	time::Instant,
+};
 use thousands::Separable;
 
 use crate::shared::{StatSelect, Stats};
@@ -53,6 +67,13 @@ pub struct BenchmarkParams {
 	/// Number of times that the benchmark should be repeated for each block.
 	#[clap(long, default_value = "10")]
 	pub repeat: u32,
+
+	/// Also measure each block verifying its ed25519 signatures one by one instead of with the
+	/// batch verification algorithm.
+	///
+	/// Only affects runtimes using the second version of `ed25519_batch_verify`.
+	#[clap(long)]
+	pub compare_ed25519_batching: bool,
 }
 
 /// Convenience closure for the [`Benchmark::run()`] function.
@@ -73,7 +94,8 @@ where
 		+ ProvideRuntimeApi<Block>
 		+ StorageProvider<Block, BA>
 		+ UsageProvider<Block>
-		+ BlockBackend<Block>,
+		+ BlockBackend<Block>
+		+ ExecutorProvider<Block>,
 	C::Api: ApiExt<Block, StateBackend = BA::State> + BlockBuilderApi<Block>,
 {
 	/// Returns a new [`Self`] from the arguments.
@@ -87,6 +109,14 @@ where
 			return Err("Cannot benchmark the genesis block".into())
 		}
 
+		let ed25519_batching = self.params.compare_ed25519_batching.then(|| {
+			let batching = Arc::new(AtomicBool::new(true));
+			self.client
+				.execution_extensions()
+				.set_extensions_factory(Box::new(Ed25519Batching(batching.clone())));
+			batching
+		});
+
 		for i in self.params.from..=self.params.to {
 			let block_num = BlockId::Number(i.into());
 			let parent_num = BlockId::Number(((i - 1) as u32).into());
@@ -98,6 +128,13 @@ where
 			let took = self.measure_block(&block, &parent_num)?;
 
 			self.log_weight(i, block.extrinsics().len(), consumed, took);
+
+			if let Some(batching) = &ed25519_batching {
+				batching.store(false, Ordering::Relaxed);
+				let took_unbatched = self.measure_block(&block, &parent_num);
+				batching.store(true, Ordering::Relaxed);
+				self.log_ed25519_batching(i, took, took_unbatched?);
+			}
 		}
 
 		Ok(())
@@ -167,6 +204,17 @@ where
 		}
 	}
 
+	/// Prints the execution times of a block with and without the ed25519 batch verification.
+	fn log_ed25519_batching(&self, num: u32, batched: NanoSeconds, unbatched: NanoSeconds) {
+		info!(
+			target: LOG_TARGET,
+			"Block {} took {: >14} ns with and {: >14} ns without ed25519 batch verification",
+			num,
+			batched.separate_with_commas(),
+			unbatched.separate_with_commas(),
+		);
+	}
+
 	/// Removes the consensus seal from the block.
 	fn unsealed(&self, block: Block) -> Block {
 		let (mut header, exts) = block.deconstruct();
@@ -174,3 +222,14 @@ where
 		Block::new(header, exts)
 	}
 }
+
+/// Enables or disables the ed25519 batch verification of the runtime calls.
+struct Ed25519Batching(Arc<AtomicBool>);
+
+impl ExtensionsFactory for Ed25519Batching {
+	fn extensions_for(&self, _: Capabilities) -> Extensions {
+		let mut extensions = Extensions::new();
+		extensions.register(Ed25519BatchingExt(self.0.load(Ordering::Relaxed)));
+		extensions
+	}
+}
@@ -20,7 +20,9 @@
 
 use sc_block_builder::{BlockBuilderApi, BlockBuilderProvider};
 use sc_cli::{CliConfiguration, ImportParams, Result, SharedParams};
-use sc_client_api::{Backend as ClientBackend, BlockBackend, StorageProvider, UsageProvider};
+use sc_client_api::{
+	Backend as ClientBackend, BlockBackend, ExecutorProvider, StorageProvider, UsageProvider,
+};
 use sp_api::{ApiExt, ProvideRuntimeApi};
 use sp_runtime::{traits::Block as BlockT, OpaqueExtrinsic};
 
@@ -81,7 +83,8 @@ impl BlockCmd {
 			+ BlockBackend<Block>
 			+ ProvideRuntimeApi<Block>
 			+ StorageProvider<Block, BA>
-			+ UsageProvider<Block>,
+			+ UsageProvider<Block>
+			+ ExecutorProvider<Block>,
 		C::Api: ApiExt<Block, StateBackend = BA::State> + BlockBuilderApi<Block>,
 	{
 		// Put everything in the benchmark type to have the generic types handy.
@@ -100,12 +100,17 @@ pub struct StorageParams {
 impl StorageCmd {
 	/// Calls into the Read and Write benchmarking functions.
 	/// Processes the output and writes it into files and stdout.
+	///
+	/// `flat_state` is the block whose state is stored in the flat state, as returned by
+	/// `sc_client_db::Backend::wait_for_flat_state`. With `--flat-state`, the values are read at
+	/// that block, so that all the reads are served by the flat state.
 	pub fn run<Block, BA, C>(
 		&self,
 		cfg: Configuration,
 		client: Arc<C>,
 		db: (Arc<dyn Database<DbHash>>, ColumnId),
 		storage: Arc<dyn Storage<HashFor<Block>>>,
+		flat_state: Option<Block::Hash>,
 	) -> Result<()>
 	where
 		BA: ClientBackend<Block>,
@@ -118,8 +123,17 @@ impl StorageCmd {
 		template.set_block_number(block_id.to_string());
 
 		if !self.params.skip_read {
-			self.bench_warmup(&client)?;
-			let record = self.bench_read(client.clone())?;
+			let read_block = match (cfg.flat_state, flat_state) {
+				(false, _) => block_id,
+				(true, Some(hash)) => {
+					let number = client.number(hash)?.ok_or("Unknown flat state block")?;
+					template.set_block_number(format!("{} (flat state)", number));
+					BlockId::Hash(hash)
+				},
+				(true, None) => return Err("The flat state couldn't be built".into()),
+			};
+			self.bench_warmup(&client, read_block)?;
+			let record = self.bench_read(client.clone(), read_block)?;
 			if let Some(path) = &self.params.json_read_path {
 				record.save_json(&cfg, path, "read")?;
 			}
@@ -129,7 +143,7 @@ impl StorageCmd {
 		}
 
 		if !self.params.skip_write {
-			self.bench_warmup(&client)?;
+			self.bench_warmup(&client, block_id)?;
 			let record = self.bench_write(client, db, storage)?;
 			if let Some(path) = &self.params.json_write_path {
 				record.save_json(&cfg, path, "write")?;
@@ -151,15 +165,14 @@ impl StorageCmd {
 		}
 	}
 
-	/// Run some rounds of the (read) benchmark as warmup.
+	/// Run some rounds of the (read) benchmark at `block` as warmup.
 	/// See `frame_benchmarking_cli::storage::read::bench_read` for detailed comments.
-	fn bench_warmup<B, BA, C>(&self, client: &Arc<C>) -> Result<()>
+	fn bench_warmup<B, BA, C>(&self, client: &Arc<C>, block: BlockId<B>) -> Result<()>
 	where
-		C: UsageProvider<B> + StorageProvider<B, BA>,
+		C: StorageProvider<B, BA>,
 		B: BlockT + Debug,
 		BA: ClientBackend<B>,
 	{
-		let block = BlockId::Number(client.usage_info().chain.best_number);
 		let empty_prefix = StorageKey(Vec::new());
 		let mut keys = client.storage_keys(&block, &empty_prefix)?;
 		let (mut rng, _) = new_rng(None);
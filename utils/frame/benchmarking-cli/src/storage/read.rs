@@ -16,7 +16,7 @@
 // limitations under the License.
 
 use sc_cli::Result;
-use sc_client_api::{Backend as ClientBackend, StorageProvider, UsageProvider};
+use sc_client_api::{Backend as ClientBackend, StorageProvider};
 use sp_core::storage::StorageKey;
 use sp_runtime::{
 	generic::BlockId,
@@ -32,16 +32,19 @@ use crate::shared::{new_rng, BenchRecord};
 
 impl StorageCmd {
 	/// Benchmarks the time it takes to read a single Storage item.
-	/// Uses the latest state that is available for the given client.
-	pub(crate) fn bench_read<B, BA, C>(&self, client: Arc<C>) -> Result<BenchRecord>
+	/// Uses the state of `block`.
+	pub(crate) fn bench_read<B, BA, C>(
+		&self,
+		client: Arc<C>,
+		block: BlockId<B>,
+	) -> Result<BenchRecord>
 	where
-		C: UsageProvider<B> + StorageProvider<B, BA>,
+		C: StorageProvider<B, BA>,
 		B: BlockT + Debug,
 		BA: ClientBackend<B>,
 		<<B as BlockT>::Header as HeaderT>::Number: From<u32>,
 	{
 		let mut record = BenchRecord::default();
-		let block = BlockId::Number(client.usage_info().chain.best_number);
 
 		info!("Preparing keys from block {}", block);
 		// Load all keys and randomly shuffle them.
@@ -0,0 +1,524 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{build_executor, full_extensions, hash_of, parse, SharedParams, State, LOG_TARGET};
+use parity_scale_codec::{Decode, Encode};
+use remote_externalities::rpc_api;
+use sc_cli::WasmExecutionMethod;
+use sc_executor::NativeElseWasmExecutor;
+use sc_service::{Configuration, NativeExecutionDispatch};
+use sp_core::{
+	hexdisplay::HexDisplay,
+	storage::{well_known_keys, ChildInfo, StateVersion},
+	Blake2Hasher,
+};
+use sp_runtime::traits::{Block as BlockT, Header as HeaderT, NumberFor};
+use sp_state_machine::{
+	backend::{Backend, BackendRuntimeCode},
+	InMemoryBackend, OverlayedChanges, StateMachine,
+};
+use std::{collections::BTreeMap, fmt::Debug, path::PathBuf, str::FromStr};
+
+/// Configurations of the [`Command::DiffExecute`].
+#[derive(Debug, Clone, clap::Parser)]
+pub struct DiffExecuteCmd {
+	/// The hash of the first block of the range to execute.
+	#[clap(long, multiple_values = false, parse(try_from_str = parse::hash))]
+	from: String,
+
+	/// The hash of the last block of the range to execute.
+	///
+	/// Must be a descendant of (or equal to) `--from`.
+	#[clap(long, multiple_values = false, parse(try_from_str = parse::hash))]
+	to: String,
+
+	/// The ws uri from which to fetch the blocks.
+	///
+	/// If the `live` state type is being used, then this can be omitted, and is equal to whatever
+	/// the `state::uri` is.
+	#[clap(long, multiple_values = false, parse(try_from_str = parse::url))]
+	blocks_ws_uri: Option<String>,
+
+	/// A file holding the SCALE encoded blocks of the range.
+	///
+	/// If the file exists, the blocks are read from it and no connection is made. Otherwise the
+	/// fetched blocks are written to it, so that subsequent runs on a state snapshot are offline.
+	#[clap(long)]
+	blocks_path: Option<PathBuf>,
+
+	/// The wasm code to use on the left side. If not provided, the code in state is used.
+	#[clap(long)]
+	left_code: Option<PathBuf>,
+
+	/// The wasm code to use on the right side. If not provided, the code in state is used.
+	#[clap(long)]
+	right_code: Option<PathBuf>,
+
+	/// Type of wasm execution used on the left side. Defaults to `--wasm-execution`.
+	#[clap(
+		long,
+		value_name = "METHOD",
+		possible_values = WasmExecutionMethod::variants(),
+		ignore_case = true,
+	)]
+	left_wasm_execution: Option<WasmExecutionMethod>,
+
+	/// Type of wasm execution used on the right side. Defaults to `--wasm-execution`.
+	#[clap(
+		long,
+		value_name = "METHOD",
+		possible_values = WasmExecutionMethod::variants(),
+		ignore_case = true,
+	)]
+	right_wasm_execution: Option<WasmExecutionMethod>,
+
+	/// Stop at the first block whose storage changes differ.
+	#[clap(long)]
+	stop_on_mismatch: bool,
+
+	/// Leave the writes of `:code` out of the comparison, e.g. when only one side upgrades its
+	/// runtime.
+	#[clap(long)]
+	ignore_code: bool,
+
+	/// The state type to use.
+	///
+	/// This must be the state of the parent of `--from`. If the `live` state type is used, the
+	/// state of the parent block is fetched, and `State::Live::at` is ignored.
+	#[clap(subcommand)]
+	state: State,
+}
+
+impl DiffExecuteCmd {
+	fn blocks_ws_uri(&self) -> sc_cli::Result<String> {
+		match (&self.blocks_ws_uri, &self.state) {
+			(Some(uri), _) => Ok(uri.clone()),
+			(None, State::Live { uri, .. }) => Ok(uri.clone()),
+			(None, State::Snap { .. }) =>
+				Err("either `--blocks-ws-uri` or an existing `--blocks-path` must be provided, \
+					or state must be `live`"
+					.into()),
+		}
+	}
+}
+
+/// The values written by a block, by child trie and key. `None` values are deletions.
+type BlockChanges = BTreeMap<(Option<ChildInfo>, Vec<u8>), Option<Vec<u8>>>;
+
+/// A key written differently by the two sides: `None` if a side didn't write it at all.
+#[derive(Debug, PartialEq)]
+struct Mismatch<'a> {
+	child_info: &'a Option<ChildInfo>,
+	key: &'a [u8],
+	left: Option<&'a Option<Vec<u8>>>,
+	right: Option<&'a Option<Vec<u8>>>,
+}
+
+/// Compare the changes of a block on both sides, leaving out `:code` if `ignore_code` is set.
+fn diff_changes<'a>(
+	left: &'a BlockChanges,
+	right: &'a BlockChanges,
+	ignore_code: bool,
+) -> Vec<Mismatch<'a>> {
+	left.keys()
+		.chain(right.keys().filter(|key| !left.contains_key(*key)))
+		.filter(|(child_info, key)| {
+			!(ignore_code && child_info.is_none() && key.as_slice() == well_known_keys::CODE)
+		})
+		.map(|(child_info, key)| Mismatch {
+			child_info,
+			key,
+			left: left.get(&(child_info.clone(), key.clone())),
+			right: right.get(&(child_info.clone(), key.clone())),
+		})
+		.filter(|mismatch| mismatch.left != mismatch.right)
+		.collect()
+}
+
+/// One of the two executions being compared.
+struct Side<D: NativeExecutionDispatch + 'static> {
+	name: &'static str,
+	executor: NativeElseWasmExecutor<D>,
+	backend: InMemoryBackend<Blake2Hasher>,
+}
+
+impl<D: NativeExecutionDispatch + 'static> Side<D> {
+	fn new(
+		name: &'static str,
+		shared: &SharedParams,
+		config: &Configuration,
+		wasm_method: Option<WasmExecutionMethod>,
+		code: &Option<PathBuf>,
+		mut backend: InMemoryBackend<Blake2Hasher>,
+		state_version: StateVersion,
+	) -> sc_cli::Result<Self> {
+		let mut shared = shared.clone();
+		if let Some(wasm_method) = wasm_method {
+			shared.wasm_method = wasm_method;
+		}
+
+		if let Some(path) = code {
+			let code = std::fs::read(path)
+				.map_err(|e| format!("failed to read {} code from {:?}: {}", name, path, e))?;
+			backend.insert(
+				vec![(None, vec![(well_known_keys::CODE.to_vec(), Some(code))])],
+				state_version,
+			);
+		}
+
+		log::info!(
+			target: LOG_TARGET,
+			"{} side: wasm execution {:?}, code {}",
+			name,
+			shared.wasm_method,
+			code.as_ref().map_or("from state".into(), |p| format!("{:?}", p)),
+		);
+
+		Ok(Self { name, executor: build_executor::<D>(&shared, config), backend })
+	}
+
+	fn call(
+		&self,
+		execution: sc_cli::ExecutionStrategy,
+		method: &'static str,
+		data: &[u8],
+	) -> sc_cli::Result<(OverlayedChanges, Vec<u8>)> {
+		let mut changes = Default::default();
+		let encoded_results = StateMachine::new(
+			&self.backend,
+			&mut changes,
+			&self.executor,
+			method,
+			data,
+			full_extensions(),
+			&BackendRuntimeCode::new(&self.backend).runtime_code()?,
+			sp_core::testing::TaskExecutor::new(),
+		)
+		.execute(execution.into())
+		.map_err(|e| format!("{} side failed to execute '{}': {}", self.name, method, e))
+		.map_err::<sc_cli::Error, _>(Into::into)?;
+
+		Ok((changes, encoded_results))
+	}
+
+	fn state_version(&self, execution: sc_cli::ExecutionStrategy) -> sc_cli::Result<StateVersion> {
+		let (_, encoded) = self.call(execution, "Core_version", &[])?;
+		<sp_version::RuntimeVersion as Decode>::decode(&mut &*encoded)
+			.map(|v| v.state_version())
+			.map_err(|e| format!("failed to decode {} runtime version: {:?}", self.name, e).into())
+	}
+
+	/// Execute `block` and commit its changes, returning the consumed weight and the changes.
+	fn execute_block(
+		&mut self,
+		execution: sc_cli::ExecutionStrategy,
+		block: &[u8],
+	) -> sc_cli::Result<(u64, BlockChanges)> {
+		let state_version = self.state_version(execution)?;
+		let (mut changes, encoded_result) =
+			self.call(execution, "TryRuntime_execute_block_no_check", block)?;
+		let consumed_weight = <u64 as Decode>::decode(&mut &*encoded_result)
+			.map_err(|e| format!("failed to decode output: {:?}", e))?;
+
+		let mut written: BlockChanges = changes
+			.changes()
+			.map(|(k, v)| ((None, k.clone()), v.value().cloned()))
+			.collect();
+		for (child_changes, child_info) in changes.children() {
+			written.extend(
+				child_changes
+					.map(|(k, v)| ((Some(child_info.clone()), k.clone()), v.value().cloned())),
+			);
+		}
+
+		let storage_changes = changes
+			.drain_storage_changes(&self.backend, &mut Default::default(), state_version)
+			.map_err(|e| format!("failed to drain {} storage changes: {}", self.name, e))?;
+		self.backend.apply_transaction(
+			storage_changes.transaction_storage_root,
+			storage_changes.transaction,
+		);
+
+		Ok((consumed_weight, written))
+	}
+}
+
+/// Fetch the blocks `from..=to` from `uri`, in ascending order.
+async fn fetch_blocks<Block>(
+	uri: &str,
+	from: Block::Hash,
+	to: Block::Hash,
+) -> sc_cli::Result<Vec<Block>>
+where
+	Block: BlockT + serde::de::DeserializeOwned,
+	Block::Header: serde::de::DeserializeOwned,
+{
+	let from_number = *rpc_api::get_header::<Block, _>(uri, from).await?.number();
+
+	let mut blocks = Vec::new();
+	let mut hash = to;
+	loop {
+		let block: Block = rpc_api::get_block::<Block, _>(uri, hash).await?;
+		let number = *block.header().number();
+		if number < from_number {
+			return Err(format!("{:?} is not an ancestor of {:?}", from, to).into())
+		}
+		hash = *block.header().parent_hash();
+		let done = number == from_number;
+		blocks.push(block);
+		if done {
+			break
+		}
+	}
+
+	if blocks.last().map(|b| b.header().hash()) != Some(from) {
+		return Err(format!("{:?} is not an ancestor of {:?}", from, to).into())
+	}
+
+	blocks.reverse();
+	Ok(blocks)
+}
+
+pub(crate) async fn diff_execute<Block, ExecDispatch>(
+	shared: SharedParams,
+	command: DiffExecuteCmd,
+	config: Configuration,
+) -> sc_cli::Result<()>
+where
+	Block: BlockT + serde::de::DeserializeOwned,
+	Block::Header: serde::de::DeserializeOwned,
+	Block::Hash: FromStr,
+	<Block::Hash as FromStr>::Err: Debug,
+	NumberFor<Block>: FromStr,
+	<NumberFor<Block> as FromStr>::Err: Debug,
+	ExecDispatch: NativeExecutionDispatch + 'static,
+{
+	let execution = shared.execution;
+	if matches!(execution, sc_cli::ExecutionStrategy::Native) {
+		log::warn!(
+			target: LOG_TARGET,
+			"native execution ignores the code and wasm execution method of both sides",
+		);
+	}
+
+	let from = hash_of::<Block>(&command.from)?;
+	let to = hash_of::<Block>(&command.to)?;
+
+	let blocks: Vec<Block> = match command.blocks_path {
+		Some(ref path) if path.exists() => {
+			log::info!(target: LOG_TARGET, "reading blocks from {:?}", path);
+			let encoded = std::fs::read(path)
+				.map_err(|e| format!("failed to read blocks from {:?}: {}", path, e))?;
+			Decode::decode(&mut &*encoded)
+				.map_err(|e| format!("failed to decode blocks from {:?}: {:?}", path, e))?
+		},
+		_ => {
+			let uri = command.blocks_ws_uri()?;
+			let blocks = fetch_blocks::<Block>(&uri, from, to).await?;
+			log::info!(target: LOG_TARGET, "fetched {} blocks from {:?}", blocks.len(), uri);
+			if let Some(ref path) = command.blocks_path {
+				std::fs::write(path, blocks.encode())
+					.map_err(|e| format!("failed to write blocks to {:?}: {}", path, e))?;
+			}
+			blocks
+		},
+	};
+
+	let first = blocks.first().ok_or("the block range is empty")?;
+	if first.header().hash() != from || blocks.last().map(|b| b.header().hash()) != Some(to) {
+		return Err("the blocks do not match the `--from` and `--to` range".into())
+	}
+	let parent_hash = *first.header().parent_hash();
+
+	let ext = command
+		.state
+		.builder::<Block>()?
+		// make sure the state is being build with the parent hash, if it is online.
+		.overwrite_online_at(parent_hash)
+		.inject_hashed_key(well_known_keys::CODE)
+		.build()
+		.await?;
+	log::info!(
+		target: LOG_TARGET,
+		"initialized state externalities at {:?}, storage root {:?}",
+		parent_hash,
+		ext.backend.root(),
+	);
+
+	let mut left = Side::<ExecDispatch>::new(
+		"left",
+		&shared,
+		&config,
+		command.left_wasm_execution,
+		&command.left_code,
+		ext.backend.clone(),
+		ext.state_version,
+	)?;
+	let mut right = Side::<ExecDispatch>::new(
+		"right",
+		&shared,
+		&config,
+		command.right_wasm_execution,
+		&command.right_code,
+		ext.backend.clone(),
+		ext.state_version,
+	)?;
+
+	let mut mismatches = 0;
+	for block in blocks {
+		let number = *block.header().number();
+
+		// A digest item gets added when the runtime is processing the block, so we need to pop
+		// the last one to be consistent with what a gossiped block would contain.
+		let (mut header, extrinsics) = block.deconstruct();
+		header.digest_mut().pop();
+		let encoded_block = Block::new(header, extrinsics).encode();
+
+		let (left_weight, left_written) = left.execute_block(execution, &encoded_block)?;
+		let (right_weight, right_written) = right.execute_block(execution, &encoded_block)?;
+
+		if left_weight != right_weight {
+			log::warn!(
+				target: LOG_TARGET,
+				"block {}: consumed weight differs, left {} != right {}",
+				number,
+				left_weight,
+				right_weight,
+			);
+		}
+
+		let diff = diff_changes(&left_written, &right_written, command.ignore_code);
+		if diff.is_empty() {
+			log::info!(
+				target: LOG_TARGET,
+				"block {}: {} storage changes match, consumed weight {}",
+				number,
+				left_written.len(),
+				left_weight,
+			);
+			continue
+		}
+
+		mismatches += 1;
+		log::error!(target: LOG_TARGET, "block {}: {} storage changes differ", number, diff.len());
+		let show = |value: Option<&Option<Vec<u8>>>| match value {
+			None => "not written".to_string(),
+			Some(None) => "deleted".to_string(),
+			Some(Some(value)) => format!("0x{}", HexDisplay::from(value)),
+		};
+		for Mismatch { child_info, key, left, right } in diff {
+			log::error!(
+				target: LOG_TARGET,
+				"  {}0x{}: left {} != right {}",
+				child_info
+					.as_ref()
+					.map(|c| format!("child 0x{} / ", HexDisplay::from(&c.storage_key())))
+					.unwrap_or_default(),
+				HexDisplay::from(&key),
+				show(left),
+				show(right),
+			);
+		}
+
+		if command.stop_on_mismatch {
+			break
+		}
+	}
+
+	if mismatches > 0 {
+		return Err(format!("{} blocks with differing storage changes", mismatches).into())
+	}
+
+	log::info!(target: LOG_TARGET, "all blocks executed with matching storage changes.");
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn changes(written: &[(Option<&str>, &str, Option<&str>)]) -> BlockChanges {
+		written
+			.iter()
+			.map(|(child, key, value)| {
+				let child_info = child.map(|child| ChildInfo::new_default(child.as_bytes()));
+				((child_info, key.as_bytes().to_vec()), value.map(|v| v.as_bytes().to_vec()))
+			})
+			.collect()
+	}
+
+	#[test]
+	fn identical_changes_do_not_differ() {
+		let written = changes(&[
+			(None, "key", Some("value")),
+			(None, "deleted", None),
+			(Some("child"), "key", Some("value")),
+		]);
+		assert_eq!(diff_changes(&written, &written.clone(), false), vec![]);
+	}
+
+	#[test]
+	fn differing_changes_are_reported() {
+		let left = changes(&[
+			(None, "same", Some("value")),
+			(None, "changed", Some("left")),
+			(None, "left only", Some("value")),
+			(Some("child"), "key", Some("value")),
+		]);
+		let right = changes(&[
+			(None, "same", Some("value")),
+			(None, "changed", Some("right")),
+			(None, "right only", None),
+			(Some("child"), "key", None),
+		]);
+
+		let child = Some(ChildInfo::new_default(b"child"));
+		assert_eq!(
+			diff_changes(&left, &right, false),
+			vec![
+				Mismatch {
+					child_info: &None,
+					key: b"changed",
+					left: Some(&Some(b"left".to_vec())),
+					right: Some(&Some(b"right".to_vec())),
+				},
+				Mismatch {
+					child_info: &None,
+					key: b"left only",
+					left: Some(&Some(b"value".to_vec())),
+					right: None,
+				},
+				Mismatch {
+					child_info: &child,
+					key: b"key",
+					left: Some(&Some(b"value".to_vec())),
+					right: Some(&None),
+				},
+				Mismatch { child_info: &None, key: b"right only", left: None, right: Some(&None) },
+			],
+		);
+	}
+
+	#[test]
+	fn code_can_be_ignored() {
+		let left = changes(&[(None, ":code", Some("left"))]);
+		let right = changes(&[(None, ":code", Some("right"))]);
+
+		assert_eq!(diff_changes(&left, &right, false).len(), 1);
+		assert_eq!(diff_changes(&left, &right, true), vec![]);
+	}
+}
@@ -15,6 +15,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub(crate) mod diff_execute;
 pub(crate) mod execute_block;
 pub(crate) mod follow_chain;
 pub(crate) mod offchain_worker;
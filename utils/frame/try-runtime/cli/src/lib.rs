@@ -264,6 +264,27 @@
 //!     snap \
 //!     -s snap \
 //! ```
+//!
+//! To compare a new runtime against the live one over a range of blocks, offline, first scrape
+//! the state of the parent of the first block and the blocks themselves:
+//!
+//! ```sh
+//! RUST_LOG=try-runtime::cli=info \
+//!     cargo run try-runtime \
+//!     --chain polkadot-dev \
+//!     diff-execute \
+//!     --from <first-block-hash> \
+//!     --to <last-block-hash> \
+//!     --blocks-path blocks \
+//!     --right-code new_runtime.compact.compressed.wasm \
+//!     live \
+//!     --uri wss://rpc.polkadot.io \
+//!     --snapshot-path snap
+//! ```
+//!
+//! Subsequent runs can then use `--blocks-path blocks` together with `snap -s snap`. Likewise,
+//! `--left-wasm-execution interpreted-i-know-what-i-do --right-wasm-execution compiled` compares
+//! the two execution methods on the same runtime.
 
 use parity_scale_codec::Decode;
 use remote_externalities::{
@@ -378,6 +399,28 @@ pub enum Command {
 	/// initializes the state from the remote node, and starts applying that block, plus all the
 	/// blocks that follow, to the same growing state.
 	FollowChain(commands::follow_chain::FollowChainCmd),
+
+	/// Executes a range of blocks under two runtimes, or two wasm execution methods, and reports
+	/// where their results differ.
+	///
+	/// Both sides start from the same state, which must be the state of the parent of
+	/// `--from`, and each keeps applying its own changes for the rest of the range. The code of
+	/// either side can be replaced with `--left-code` and `--right-code`, and its execution method
+	/// with `--left-wasm-execution` and `--right-wasm-execution`.
+	///
+	/// Blocks are executed with `TryRuntime_execute_block_no_check`, since two different runtimes
+	/// will rarely agree with the state root of the original header. Instead, the storage changes
+	/// of each block are compared, and the keys the two sides wrote differently are reported.
+	/// Comparing the changes rather than the storage roots keeps a single difference, such as a
+	/// different `:code`, from failing every following block. Differences in events and weights
+	/// show up as differences of the corresponding `System` storage items. Differences in the
+	/// consumed weight are reported as well.
+	///
+	/// Writes of `:code` are left out with `DiffExecuteCmd::ignore_code`, and
+	/// `DiffExecuteCmd::stop_on_mismatch` stops at the first mismatch.
+	///
+	/// Combined with [`State::Snap`] and `DiffExecuteCmd::blocks_path`, this runs offline.
+	DiffExecute(commands::diff_execute::DiffExecuteCmd),
 }
 
 /// Shared parameters of the `try-runtime` commands
@@ -571,6 +614,13 @@ impl TryRuntimeCmd {
 					config,
 				)
 				.await,
+			Command::DiffExecute(cmd) =>
+				commands::diff_execute::diff_execute::<Block, ExecDispatch>(
+					self.shared.clone(),
+					cmd.clone(),
+					config,
+				)
+				.await,
 		}
 	}
 }
@@ -87,6 +87,10 @@
 //!   required as we walk up from the target directory until we find a `Cargo.toml`. If the target
 //!   directory is changed for the build, this environment variable can be used to point to the
 //!   actual workspace.
+//! - `WASM_BUILD_DELTA_BASE` - Path to the runtime code currently on chain, compressed or not. When
+//!   set, the compact Wasm binary is also written as a delta against it, to
+//!   `PROJECT_NAME.compact.delta.wasm`. `frame_system::set_code` accepts such deltas, which are
+//!   usually much smaller than the compressed Wasm binary.
 //!
 //! Each project can be skipped individually by using the environment variable
 //! `SKIP_PROJECT_NAME_WASM_BUILD`. Where `PROJECT_NAME` needs to be replaced by the name of the
@@ -147,6 +151,10 @@ const FORCE_WASM_BUILD_ENV: &str = "FORCE_WASM_BUILD";
 /// Environment variable that hints the workspace we are building.
 const WASM_BUILD_WORKSPACE_HINT: &str = "WASM_BUILD_WORKSPACE_HINT";
 
+/// Environment variable to set the runtime code the compact wasm binary is encoded as a delta
+/// against.
+const WASM_BUILD_DELTA_BASE: &str = "WASM_BUILD_DELTA_BASE";
+
 /// Write to the given `file` if the `content` is different.
 fn write_file_if_changed(file: impl AsRef<Path>, content: impl AsRef<str>) {
 	if fs::read_to_string(file.as_ref()).ok().as_deref() != Some(content.as_ref()) {
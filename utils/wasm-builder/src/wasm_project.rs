@@ -636,6 +636,13 @@ fn compact_wasm_file(
 		}
 	});
 
+	if let Some(compact_binary) = wasm_compact_file.as_ref() {
+		let file_name =
+			wasm_binary_name.clone().unwrap_or_else(|| default_wasm_binary_name.clone());
+
+		delta_wasm(&compact_binary.0, &project.join(format!("{}.compact.delta.wasm", file_name)));
+	}
+
 	let bloaty_file_name = if let Some(name) = wasm_binary_name {
 		format!("{}.wasm", name)
 	} else {
@@ -667,6 +674,31 @@ fn compress_wasm(wasm_binary_path: &Path, compressed_binary_out_path: &Path) ->
 	}
 }
 
+/// Encode the WASM binary as a delta against the runtime code at `WASM_BUILD_DELTA_BASE`, if set.
+fn delta_wasm(wasm_binary_path: &Path, delta_out_path: &Path) {
+	use sp_maybe_compressed_blob::CODE_BLOB_BOMB_LIMIT;
+
+	let base_path = match env::var(crate::WASM_BUILD_DELTA_BASE) {
+		Ok(path) => PathBuf::from(path),
+		Err(_) => return,
+	};
+
+	let base = fs::read(&base_path).expect("Failed to read the delta base");
+	let base = sp_maybe_compressed_blob::decompress(&base, CODE_BLOB_BOMB_LIMIT)
+		.expect("Failed to decompress the delta base");
+	let data = fs::read(wasm_binary_path).expect("Failed to read WASM binary");
+	if let Some(delta) =
+		sp_maybe_compressed_blob::compress_delta(&data, &base, CODE_BLOB_BOMB_LIMIT)
+	{
+		fs::write(delta_out_path, &delta[..]).expect("Failed to write WASM binary delta");
+	} else {
+		build_helper::warning!(
+			"Not writing a wasm delta. Exceeded maximum size {}",
+			CODE_BLOB_BOMB_LIMIT,
+		);
+	}
+}
+
 /// Custom wrapper for a [`cargo_metadata::Package`] to store it in
 /// a `HashSet`.
 #[derive(Debug)]
@@ -767,6 +799,11 @@ fn generate_rerun_if_changed_instructions(
 	println!("cargo:rerun-if-env-changed={}", crate::WASM_BUILD_RUSTFLAGS_ENV);
 	println!("cargo:rerun-if-env-changed={}", crate::WASM_TARGET_DIRECTORY);
 	println!("cargo:rerun-if-env-changed={}", crate::WASM_BUILD_TOOLCHAIN);
+	println!("cargo:rerun-if-env-changed={}", crate::WASM_BUILD_DELTA_BASE);
+
+	if let Ok(path) = env::var(crate::WASM_BUILD_DELTA_BASE) {
+		rerun_if_changed(PathBuf::from(path));
+	}
 }
 
 /// Track files and paths related to the given package to rerun `build.rs` on any relevant change.